@@ -0,0 +1,72 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo::rustc-check-cfg=cfg(progpow_reference)");
+    #[cfg(feature = "node")]
+    napi_build::setup();
+    #[cfg(feature = "verifyd")]
+    compile_verifier_proto();
+    #[cfg(feature = "reference-cpp")]
+    compile_reference();
+}
+
+/// Generates the gRPC service code for `progpow-verifyd`.
+///
+/// The proto is parsed with `protox`, so building does not need `protoc`.
+#[cfg(feature = "verifyd")]
+fn compile_verifier_proto() {
+    println!("cargo:rerun-if-changed=proto/progpow.proto");
+    let descriptors =
+        protox::compile(["progpow.proto"], ["proto"]).expect("proto/progpow.proto is invalid");
+    tonic_prost_build::configure()
+        .compile_fds(descriptors)
+        .expect("failed to generate the gRPC service");
+}
+
+/// Builds the reference C++ ProgPoW implementation and `reference/shim.cpp`
+/// for the differential tests in `progpow::reference`.
+///
+/// `PROGPOW_REFERENCE_DIR` names a checkout of the reference laid out like
+/// chfast/ethash, with headers in `include/` and sources under `lib/`.
+/// Without it the reference is left out and `progpow_reference` is not set.
+#[cfg(feature = "reference-cpp")]
+fn compile_reference() {
+    use std::path::{Path, PathBuf};
+
+    /// Collects the C and C++ sources under `dir`.
+    fn sources(dir: &Path, c: &mut Vec<PathBuf>, cpp: &mut Vec<PathBuf>) {
+        let entries = std::fs::read_dir(dir).expect("cannot read the reference sources");
+        for path in entries.map(|entry| entry.unwrap().path()) {
+            match path.extension().and_then(|extension| extension.to_str()) {
+                _ if path.is_dir() => sources(&path, c, cpp),
+                Some("c") => c.push(path),
+                Some("cpp") => cpp.push(path),
+                _ => {}
+            }
+        }
+    }
+
+    println!("cargo:rerun-if-env-changed=PROGPOW_REFERENCE_DIR");
+    println!("cargo:rerun-if-changed=reference/shim.cpp");
+    let Some(dir) = std::env::var_os("PROGPOW_REFERENCE_DIR").map(PathBuf::from) else {
+        return;
+    };
+    let (mut c, mut cpp) = (Vec::new(), Vec::new());
+    sources(&dir.join("lib"), &mut c, &mut cpp);
+    // The C++ library is linked first since it calls into the C one.
+    cc::Build::new()
+        .cpp(true)
+        .std("c++17")
+        .include(dir.join("include"))
+        .include(dir.join("lib"))
+        .files(cpp)
+        .file("reference/shim.cpp")
+        .warnings(false)
+        .compile("progpow_reference");
+    cc::Build::new()
+        .include(dir.join("include"))
+        .include(dir.join("lib"))
+        .files(c)
+        .warnings(false)
+        .compile("progpow_reference_c");
+    println!("cargo:rustc-cfg=progpow_reference");
+}
@@ -0,0 +1,55 @@
+// Serves the differential-testing protocol of progpow_verifier's
+// `progpow::oracle` module on top of go-ethereum's ProgPoW.
+//
+// Copy this file into consensus/ethash of a go-ethereum checkout with
+// ProgPoW (the upstream `progpow` branch), then build the test binary:
+//
+//	go test -c -o progpow-oracle ./consensus/ethash
+//
+// and run it as `progpow-oracle -test.run=TestProgpowOracle`.
+package ethash
+
+import (
+	"bufio"
+	"encoding/json"
+	"os"
+	"testing"
+
+	"github.com/ethereum/go-ethereum/common/hexutil"
+)
+
+type oracleInput struct {
+	HeaderHash  hexutil.Bytes  `json:"header_hash"`
+	Nonce       hexutil.Uint64 `json:"nonce"`
+	BlockNumber uint64         `json:"block_number"`
+	CacheSize   uint64         `json:"cache_size"`
+	DatasetSize uint64         `json:"dataset_size"`
+	SeedHash    hexutil.Bytes  `json:"seed_hash"`
+}
+
+type oracleOutput struct {
+	MixHash   hexutil.Bytes `json:"mix_hash"`
+	FinalHash hexutil.Bytes `json:"final_hash"`
+}
+
+func TestProgpowOracle(t *testing.T) {
+	in := bufio.NewScanner(os.Stdin)
+	in.Buffer(nil, 1<<20)
+	out := json.NewEncoder(os.Stdout)
+	for in.Scan() {
+		var input oracleInput
+		if err := json.Unmarshal(in.Bytes(), &input); err != nil {
+			t.Fatal(err)
+		}
+		epoch := input.BlockNumber / epochLength
+		cache := make([]uint32, input.CacheSize/4)
+		generateCache(cache, epoch, input.SeedHash)
+		cDag := make([]uint32, progpowCacheWords)
+		generateCDag(cDag, cache, epoch)
+		mix, final := progpowLight(input.DatasetSize, cache, input.HeaderHash,
+			uint64(input.Nonce), input.BlockNumber, cDag)
+		if err := out.Encode(oracleOutput{mix, final}); err != nil {
+			t.Fatal(err)
+		}
+	}
+}
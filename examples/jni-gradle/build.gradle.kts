@@ -0,0 +1,26 @@
+plugins {
+    application
+}
+
+val crateDir = rootDir.resolve("../..")
+
+// Builds the native library the `ProgPow` class loads.
+val cargoBuild by tasks.registering(Exec::class) {
+    workingDir = crateDir
+    commandLine("cargo", "build", "--release", "--features", "java")
+}
+
+java {
+    toolchain {
+        languageVersion = JavaLanguageVersion.of(17)
+    }
+}
+
+application {
+    mainClass = "org.progpow.example.ValidateShare"
+    applicationDefaultJvmArgs = listOf("-Djava.library.path=${crateDir.resolve("target/release")}")
+}
+
+tasks.named("run") {
+    dependsOn(cargoBuild)
+}
@@ -0,0 +1 @@
+rootProject.name = "progpow-jni-example"
@@ -0,0 +1,69 @@
+package org.progpow;
+
+/**
+ * ProgPoW hashing and share validation over one epoch's light cache.
+ *
+ * <p>Creating an instance generates the cache, which takes about a second;
+ * pools should keep one per active epoch and share it between threads.
+ * Call {@link #close()} to release the cache.
+ */
+public final class ProgPow implements AutoCloseable {
+    /** The share is valid. */
+    public static final int VALID = 0;
+    /** The share's mix hash differs from the recomputed one. */
+    public static final int MIX_MISMATCH = 2;
+    /** The share's final hash exceeds its boundary. */
+    public static final int BOUNDARY_NOT_MET = 3;
+
+    static {
+        System.loadLibrary("progpow_verifier");
+    }
+
+    private final long epoch;
+    private long handle;
+
+    /** Generates the light cache of {@code epoch}. */
+    public ProgPow(long epoch) {
+        this.epoch = epoch;
+        this.handle = newCache(epoch);
+    }
+
+    /** Returns the epoch this instance validates. */
+    public long epoch() {
+        return epoch;
+    }
+
+    /**
+     * Computes the ProgPoW hash of a header.
+     *
+     * @return the 32-byte mix hash followed by the 32-byte final hash
+     */
+    public byte[] hash(byte[] headerHash, long blockNumber, long nonce) {
+        return hash(handle, headerHash, blockNumber, nonce);
+    }
+
+    /**
+     * Validates a share.
+     *
+     * @return {@link #VALID}, {@link #MIX_MISMATCH} or {@link #BOUNDARY_NOT_MET}
+     */
+    public int validateShare(
+            byte[] headerHash, long blockNumber, long nonce, byte[] mixHash, byte[] boundary) {
+        return validateShare(handle, headerHash, blockNumber, nonce, mixHash, boundary);
+    }
+
+    @Override
+    public synchronized void close() {
+        freeCache(handle);
+        handle = 0;
+    }
+
+    private static native long newCache(long epoch);
+
+    private static native void freeCache(long handle);
+
+    private static native byte[] hash(long handle, byte[] headerHash, long blockNumber, long nonce);
+
+    private static native int validateShare(
+            long handle, byte[] headerHash, long blockNumber, long nonce, byte[] mixHash, byte[] boundary);
+}
@@ -0,0 +1,33 @@
+package org.progpow.example;
+
+import java.util.Arrays;
+import java.util.HexFormat;
+import org.progpow.ProgPow;
+
+/** Hashes a header, then validates the resulting share and a forged one. */
+public final class ValidateShare {
+    public static void main(String[] args) {
+        long blockNumber = 100;
+        long nonce = 0x123456789abcdef0L;
+        byte[] headerHash = new byte[32];
+        for (int i = 0; i < headerHash.length; i++) {
+            headerHash[i] = (byte) i;
+        }
+        byte[] boundary = new byte[32];
+        Arrays.fill(boundary, (byte) 0xff);
+
+        try (ProgPow progpow = new ProgPow(blockNumber / 30000)) {
+            byte[] hashes = progpow.hash(headerHash, blockNumber, nonce);
+            byte[] mixHash = Arrays.copyOfRange(hashes, 0, 32);
+            HexFormat hex = HexFormat.of();
+            System.out.println("mix hash:   " + hex.formatHex(mixHash));
+            System.out.println("final hash: " + hex.formatHex(hashes, 32, 64));
+
+            System.out.println("valid share:  "
+                    + progpow.validateShare(headerHash, blockNumber, nonce, mixHash, boundary));
+            mixHash[0] ^= 1;
+            System.out.println("forged share: "
+                    + progpow.validateShare(headerHash, blockNumber, nonce, mixHash, boundary));
+        }
+    }
+}
@@ -0,0 +1,24 @@
+//! Loads arbitrary bytes as a DAG file and, if they load, hashes over them,
+//! checking that a file `MmapDag::open` accepts is never read out of
+//! bounds. Files that pass the length checks are at least 16 KiB, so run
+//! with `-max_len=65536`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use progpow_verifier::ethash::buffer::{DagBuffer, MmapDag};
+use progpow_verifier::progpow::progpow::progpow;
+
+fuzz_target!(|data: &[u8]| {
+    let path = std::env::temp_dir().join(format!("progpow-fuzz-dag-{}", std::process::id()));
+    std::fs::write(&path, data).unwrap();
+    if let Ok(dag) = MmapDag::open(&path) {
+        let c_dag = dag.c_dag();
+        for nonce in 0..4 {
+            progpow(&[7; 32], nonce, dag.size(), 0, &c_dag, &|index| {
+                dag.lookup(index)
+            });
+        }
+    }
+    let _ = std::fs::remove_file(&path);
+});
@@ -0,0 +1,22 @@
+//! Decodes arbitrary bytes as an RLP header, checking that decoding never
+//! panics and that whatever decodes re-encodes to the same header.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use progpow_verifier::header::{seal_header, split_seal, Header};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(header) = Header::decode(data) else {
+        return;
+    };
+    // Canonical RLP has one encoding, so a decoded header re-encodes to the
+    // input.
+    assert_eq!(header.encode(true), data);
+    assert_eq!(Header::decode(&header.encode(true)).unwrap(), header);
+    let _ = header.pre_hash();
+
+    let (unsealed, nonce, mix_hash) = split_seal(data).unwrap();
+    assert_eq!((nonce, mix_hash), (header.nonce, header.mix_hash));
+    assert_eq!(seal_header(&unsealed, nonce, mix_hash).unwrap(), data);
+});
@@ -0,0 +1,50 @@
+//! Hashes arbitrary header hashes, nonces, dataset sizes and block numbers
+//! over a deterministic DAG, checking that `progpow()` never panics or
+//! reads outside the dataset for any size it accepts.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use progpow_verifier::basic_algorithm::{PROGPOW_CACHE_WORDS, PROGPOW_MIX_BYTES};
+use progpow_verifier::progpow::progpow::progpow;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((header_hash, rest)) = data.split_first_chunk::<32>() else {
+        return;
+    };
+    let Some((nonce, rest)) = rest.split_first_chunk::<8>() else {
+        return;
+    };
+    let Some((size, rest)) = rest.split_first_chunk::<8>() else {
+        return;
+    };
+    let Some((block_number, _)) = rest.split_first_chunk::<8>() else {
+        return;
+    };
+    let size = u64::from_le_bytes(*size);
+    // `progpow()` documents a panic for sizes that leave no items to load.
+    let items = (size / PROGPOW_MIX_BYTES as u64) as u32;
+    if items.wrapping_mul(64) == 0 {
+        return;
+    }
+
+    let c_dag: Vec<u32> = (0..PROGPOW_CACHE_WORDS as u32)
+        .map(|i| i.wrapping_mul(0x9e37_79b9))
+        .collect();
+    // Words past the dataset must never be asked for.
+    let words = items as u64 * PROGPOW_MIX_BYTES as u64 / 4;
+    let lookup = |index: u32| {
+        assert!((index as u64) < words, "word {index} is past the dataset");
+        (0..64u32).map(|b| index.wrapping_add(b) as u8).collect()
+    };
+    let (mix_hash, final_hash) = progpow(
+        header_hash,
+        u64::from_le_bytes(*nonce),
+        size,
+        u64::from_le_bytes(*block_number),
+        &c_dag,
+        &lookup,
+    );
+    assert_eq!(mix_hash.len(), 32);
+    assert_eq!(final_hash.len(), 32);
+});
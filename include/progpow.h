@@ -0,0 +1,289 @@
+#ifndef PROGPOW_H
+#define PROGPOW_H
+
+/* Generated by cbindgen from src/ffi.rs; do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The result of an FFI call.
+typedef enum ProgpowStatus {
+  // The call succeeded.
+  PROGPOW_STATUS_OK = 0,
+  // A required pointer argument was null.
+  PROGPOW_STATUS_NULL_POINTER = 1,
+  // The seal's mix hash differs from the recomputed one.
+  PROGPOW_STATUS_MIX_MISMATCH = 2,
+  // The mix hash matches, but the final hash exceeds the boundary.
+  PROGPOW_STATUS_BOUNDARY_NOT_MET = 3,
+  // A file could not be read or written.
+  PROGPOW_STATUS_IO = 4,
+} ProgpowStatus;
+
+// An epoch's ethash light cache.
+typedef struct ProgpowCache ProgpowCache;
+
+// A dataset description that hashes and verifications run against.
+typedef struct ProgpowContext ProgpowContext;
+
+// An epoch's full dataset (the DAG), held in memory.
+typedef struct ProgpowDataset ProgpowDataset;
+
+// The ProgPoW parameters this library was built with.
+//
+// C callers can compare these against the variant they expect before
+// trusting any hash.
+typedef struct ProgpowParams {
+  // Blocks per period, after which the random program changes.
+  uint64_t period_length;
+  // Epochs below this one are accepted by the cache and dataset functions.
+  uint64_t max_epoch;
+  // Parallel lanes per hash.
+  uint32_t lanes;
+  // Registers per lane.
+  uint32_t regs;
+  // DAG words loaded per lane and loop.
+  uint32_t dag_loads;
+  // Bytes of the cached DAG prefix (`c_dag`).
+  uint32_t cache_bytes;
+  // Loop iterations, each doing one DAG access.
+  uint32_t cnt_dag;
+  // Cache accesses per loop.
+  uint32_t cnt_cache;
+  // Math operations per loop.
+  uint32_t cnt_math;
+} ProgpowParams;
+
+// Reads the 64-byte DAG item `item_index` into `out`.
+//
+// `user_data` is the pointer given to [`progpow_context_new`]. The callback
+// may be called from any thread using the context.
+typedef void (*ProgpowLookupFn)(void *user_data, uint32_t item_index, uint8_t *out);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Returns the ProgPoW parameters this library was built with.
+struct ProgpowParams progpow_params(void);
+
+// Creates a context over a dataset served by a callback.
+//
+// # Arguments
+//
+// * `dataset_size` - The size of the dataset in bytes.
+// * `c_dag` - The cached DAG prefix; the first `PROGPOW_CACHE_WORDS` words
+//   are copied.
+// * `c_dag_len` - The number of words at `c_dag`.
+// * `lookup` - The callback reading DAG items.
+// * `user_data` - An opaque pointer passed to every `lookup` call.
+//
+// # Returns
+//
+// A context to release with [`progpow_context_free`], or null if a pointer
+// is null or `c_dag_len` is too short.
+//
+// # Safety
+//
+// `c_dag` must point to `c_dag_len` readable words. `lookup` must write 64
+// bytes to `out` and stay callable with `user_data` until the context is
+// freed.
+struct ProgpowContext *progpow_context_new(uint64_t dataset_size,
+                                           const uint32_t *c_dag,
+                                           size_t c_dag_len,
+                                           ProgpowLookupFn lookup,
+                                           void *user_data);
+
+// Releases a context. Passing null does nothing.
+//
+// # Safety
+//
+// `context` must come from [`progpow_context_new`] and not be used again.
+void progpow_context_free(struct ProgpowContext *context);
+
+// Creates a context verifying against an epoch's light cache.
+//
+// DAG items are computed from the cache on demand, so no dataset is needed;
+// this suits verifiers checking a few seals. The context keeps its own
+// reference to the cache, which may be freed independently.
+//
+// # Returns
+//
+// A context to release with [`progpow_context_free`], or null if `cache`
+// is null.
+//
+// # Safety
+//
+// `cache` must be null or a live cache.
+struct ProgpowContext *progpow_context_from_cache(const struct ProgpowCache *cache);
+
+// Creates a context hashing against an epoch's full dataset.
+//
+// The context keeps its own reference to the dataset, which may be freed
+// independently.
+//
+// # Returns
+//
+// A context to release with [`progpow_context_free`], or null if `dataset`
+// is null.
+//
+// # Safety
+//
+// `dataset` must be null or a live dataset.
+struct ProgpowContext *progpow_context_from_dataset(const struct ProgpowDataset *dataset);
+
+// Generates the light cache of `epoch`.
+//
+// This takes about a second for early epochs in an optimized build.
+//
+// # Returns
+//
+// A cache to release with [`progpow_cache_free`], or null if `epoch` is not
+// below `max_epoch` of [`progpow_params`].
+struct ProgpowCache *progpow_cache_new(uint64_t epoch);
+
+// Loads the light cache of `epoch` saved by [`progpow_cache_save`].
+//
+// # Returns
+//
+// A cache to release with [`progpow_cache_free`], or null if `path` is null
+// or the file cannot be read or does not hold a cache of `epoch`'s size.
+//
+// # Safety
+//
+// `path` must be null or a NUL-terminated string.
+struct ProgpowCache *progpow_cache_load(uint64_t epoch, const char *path);
+
+// Saves a light cache to `path` as raw little-endian words.
+//
+// # Safety
+//
+// `cache` must be a live cache and `path` a NUL-terminated string.
+enum ProgpowStatus progpow_cache_save(const struct ProgpowCache *cache, const char *path);
+
+// Returns the epoch of a cache, or `u64::MAX` if `cache` is null.
+//
+// # Safety
+//
+// `cache` must be null or a live cache.
+uint64_t progpow_cache_epoch(const struct ProgpowCache *cache);
+
+// Releases a cache. Passing null does nothing.
+//
+// # Safety
+//
+// `cache` must come from this library and not be used again.
+void progpow_cache_free(struct ProgpowCache *cache);
+
+// Generates the full dataset of a cache's epoch, on every available core.
+//
+// The dataset is over a gigabyte and takes minutes to compute on a CPU;
+// save it with [`progpow_dataset_save`] to generate it once per epoch.
+//
+// # Returns
+//
+// A dataset to release with [`progpow_dataset_free`], or null if `cache` is
+// null.
+//
+// # Safety
+//
+// `cache` must be null or a live cache.
+struct ProgpowDataset *progpow_dataset_new(const struct ProgpowCache *cache);
+
+// Loads the full dataset of `epoch` saved by [`progpow_dataset_save`].
+//
+// Files written by go-ethereum, which start with an 8-byte magic number,
+// are accepted too.
+//
+// # Returns
+//
+// A dataset to release with [`progpow_dataset_free`], or null if `path` is
+// null or the file cannot be read or does not hold `epoch`'s dataset size.
+//
+// # Safety
+//
+// `path` must be null or a NUL-terminated string.
+struct ProgpowDataset *progpow_dataset_load(uint64_t epoch, const char *path);
+
+// Saves a full dataset to `path` in go-ethereum's DAG file format.
+//
+// # Safety
+//
+// `dataset` must be a live dataset and `path` a NUL-terminated string.
+enum ProgpowStatus progpow_dataset_save(const struct ProgpowDataset *dataset, const char *path);
+
+// Returns the epoch of a dataset, or `u64::MAX` if `dataset` is null.
+//
+// # Safety
+//
+// `dataset` must be null or a live dataset.
+uint64_t progpow_dataset_epoch(const struct ProgpowDataset *dataset);
+
+// Releases a dataset. Passing null does nothing.
+//
+// # Safety
+//
+// `dataset` must come from this library and not be used again.
+void progpow_dataset_free(struct ProgpowDataset *dataset);
+
+// Computes the ProgPoW mix hash and final hash of a header and nonce.
+//
+// # Arguments
+//
+// * `context` - The dataset to hash against.
+// * `header_hash` - The 32-byte header hash.
+// * `block_number` - The block number, which selects the period.
+// * `nonce` - The nonce.
+// * `mix_hash_out` - Receives the 32-byte mix hash.
+// * `final_hash_out` - Receives the 32-byte final hash.
+//
+// # Safety
+//
+// `context` must be a live context, and every other pointer must be valid
+// for 32 bytes.
+enum ProgpowStatus progpow_hash(const struct ProgpowContext *context,
+                                const uint8_t *header_hash,
+                                uint64_t block_number,
+                                uint64_t nonce,
+                                uint8_t *mix_hash_out,
+                                uint8_t *final_hash_out);
+
+// Verifies a seal: the mix hash must match and the final hash must not
+// exceed the big-endian boundary.
+//
+// # Arguments
+//
+// * `context` - The dataset to verify against.
+// * `header_hash` - The 32-byte header hash.
+// * `block_number` - The block number, which selects the period.
+// * `nonce` - The sealed nonce.
+// * `mix_hash` - The 32-byte mix hash claimed by the seal.
+// * `boundary` - The 32-byte big-endian target.
+// * `final_hash_out` - Receives the 32-byte final hash when the mix hash
+//   matches; may be null.
+//
+// # Returns
+//
+// [`ProgpowStatus::Ok`] for a valid seal, or the status of the first check
+// that failed.
+//
+// # Safety
+//
+// `context` must be a live context, and every other non-null pointer must
+// be valid for 32 bytes.
+enum ProgpowStatus progpow_verify_seal(const struct ProgpowContext *context,
+                                       const uint8_t *header_hash,
+                                       uint64_t block_number,
+                                       uint64_t nonce,
+                                       const uint8_t *mix_hash,
+                                       const uint8_t *boundary,
+                                       uint8_t *final_hash_out);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* PROGPOW_H */
@@ -0,0 +1,26 @@
+// A C ABI over the reference C++ ProgPoW implementation (chfast/ethash's
+// `progpow::hash`), called by progpow_verifier's `progpow::reference` module.
+#include <ethash/progpow.hpp>
+
+#include <cstdint>
+#include <cstring>
+#include <mutex>
+
+extern "C" void progpow_reference_hash(int epoch, uint64_t block_number,
+    const uint8_t* header_hash, uint64_t nonce, uint8_t* mix_hash, uint8_t* final_hash)
+{
+    // Contexts take seconds to build, so keep the last one; inputs arrive
+    // grouped by epoch.
+    static std::mutex mutex;
+    static ethash::epoch_context_ptr context{nullptr, nullptr};
+    const std::lock_guard<std::mutex> lock{mutex};
+    if (!context || context->epoch_number != epoch)
+        context = ethash::create_epoch_context(epoch);
+
+    ethash::hash256 header;
+    std::memcpy(header.bytes, header_hash, sizeof(header.bytes));
+    const auto result =
+        progpow::hash(*context, static_cast<int>(block_number), header, nonce);
+    std::memcpy(mix_hash, result.mix_hash.bytes, sizeof(result.mix_hash.bytes));
+    std::memcpy(final_hash, result.final_hash.bytes, sizeof(result.final_hash.bytes));
+}
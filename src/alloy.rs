@@ -0,0 +1,155 @@
+//! [`SealableHeader`] for `alloy_consensus::Header`, the header type of
+//! alloy and reth.
+//!
+//! The seal hash is the keccak256 of the header's RLP encoding without the
+//! mix hash and nonce, as go-ethereum's `SealHash` computes it; fork-specific
+//! fields such as the base fee are kept when present. The boundary is
+//! `2^256 / difficulty`. A header converts to a [`BlockInfo`] for the
+//! difficulty rules of [`difficulty`](crate::difficulty).
+
+use alloy_consensus::{Header, EMPTY_OMMER_ROOT_HASH};
+use alloy_primitives::{B256, B64};
+use alloy_rlp::Encodable;
+
+use crate::difficulty::{BlockInfo, Work};
+use crate::engine::SealableHeader;
+use crate::keccak::keccak256;
+use crate::target::{self, boundary_from_difficulty};
+
+/// Returns the RLP encoding of `header`, without its seal unless `seal`.
+fn encode(header: &Header, seal: bool) -> Vec<u8> {
+    let mut payload = Vec::new();
+    header.parent_hash.encode(&mut payload);
+    header.ommers_hash.encode(&mut payload);
+    header.beneficiary.encode(&mut payload);
+    header.state_root.encode(&mut payload);
+    header.transactions_root.encode(&mut payload);
+    header.receipts_root.encode(&mut payload);
+    header.logs_bloom.encode(&mut payload);
+    header.difficulty.encode(&mut payload);
+    header.number.encode(&mut payload);
+    header.gas_limit.encode(&mut payload);
+    header.gas_used.encode(&mut payload);
+    header.timestamp.encode(&mut payload);
+    header.extra_data.encode(&mut payload);
+    if seal {
+        header.mix_hash.encode(&mut payload);
+        header.nonce.encode(&mut payload);
+    }
+    if let Some(base_fee) = header.base_fee_per_gas {
+        base_fee.encode(&mut payload);
+    }
+    if let Some(root) = header.withdrawals_root {
+        root.encode(&mut payload);
+    }
+    if let Some(blob_gas_used) = header.blob_gas_used {
+        blob_gas_used.encode(&mut payload);
+    }
+    if let Some(excess_blob_gas) = header.excess_blob_gas {
+        excess_blob_gas.encode(&mut payload);
+    }
+    if let Some(root) = header.parent_beacon_block_root {
+        root.encode(&mut payload);
+    }
+    if let Some(requests_hash) = header.requests_hash {
+        requests_hash.encode(&mut payload);
+    }
+
+    let mut out = Vec::with_capacity(payload.len() + 9);
+    alloy_rlp::Header {
+        list: true,
+        payload_length: payload.len(),
+    }
+    .encode(&mut out);
+    out.extend(payload);
+    out
+}
+
+/// Returns the difficulty of `header`.
+fn difficulty(header: &Header) -> target::U256 {
+    target::U256::from_be_bytes(header.difficulty.to_be_bytes())
+}
+
+impl From<&Header> for BlockInfo {
+    fn from(header: &Header) -> Self {
+        BlockInfo {
+            number: header.number,
+            timestamp: header.timestamp,
+            work: Work::Difficulty(difficulty(header)),
+            has_ommers: header.ommers_hash != EMPTY_OMMER_ROOT_HASH,
+        }
+    }
+}
+
+impl SealableHeader for Header {
+    fn number(&self) -> u64 {
+        self.number
+    }
+
+    fn seal_hash(&self) -> [u8; 32] {
+        keccak256(&encode(self, false))
+    }
+
+    fn boundary(&self) -> [u8; 32] {
+        boundary_from_difficulty(difficulty(self))
+    }
+
+    fn nonce(&self) -> u64 {
+        u64::from_be_bytes(self.nonce.0)
+    }
+
+    fn mix_hash(&self) -> [u8; 32] {
+        self.mix_hash.0
+    }
+
+    fn set_seal(&mut self, nonce: u64, mix_hash: [u8; 32]) {
+        self.nonce = B64::from(nonce.to_be_bytes());
+        self.mix_hash = B256::from(mix_hash);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{PowEngine, ProgpowEngine};
+    use crate::testutil::tiny_cache_manager;
+    use alloy_primitives::U256;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_alloy_header_seals_and_verifies() {
+        let mut header = Header {
+            number: 100,
+            difficulty: U256::from(8),
+            gas_limit: 8_000_000,
+            extra_data: vec![0x70, 0x70].into(),
+            base_fee_per_gas: Some(7),
+            ..Header::default()
+        };
+        assert_eq!(keccak256(&encode(&header, true)), header.hash_slow().0);
+        assert_eq!(header.boundary()[0], 0x20);
+        let decoded = crate::header::Header::decode(&encode(&header, true)).unwrap();
+        assert_eq!(decoded.pre_hash(), header.seal_hash());
+
+        let caches = tiny_cache_manager(1);
+        let engine = ProgpowEngine::new(Arc::new(caches));
+        let seal_hash = header.seal_hash();
+        assert!(engine.seal(&mut header, 0..256).unwrap());
+        assert_eq!(header.seal_hash(), seal_hash);
+        assert_eq!(keccak256(&encode(&header, true)), header.hash_slow().0);
+        assert_eq!(engine.verify_header_seal(&header), Ok(()));
+
+        header.timestamp += 1;
+        assert!(engine.verify_header_seal(&header).is_err());
+
+        let info = BlockInfo::from(&header);
+        assert_eq!(info.work, Work::Difficulty(target::U256::from(8u64)));
+        assert_eq!((info.number, info.has_ommers), (100, false));
+        let decoded = BlockInfo::from(&decoded);
+        assert_eq!(info.timestamp, decoded.timestamp + 1);
+        assert_eq!(
+            (info.work, info.has_ommers),
+            (decoded.work, decoded.has_ommers)
+        );
+    }
+}
@@ -26,11 +26,14 @@ pub const PROGPOW_CNT_DAG: usize = 64;
 pub const PROGPOW_MIX_BYTES: usize = 256;
 
 /// Length of the period for block processing.
-pub const PROGPOW_PERIOD_LENGTH: u64 = u64::max_value();
+pub const PROGPOW_PERIOD_LENGTH: u64 = u64::MAX;
 
-use byteorder::{ByteOrder, LittleEndian};
+use crate::progpow::generic::{dag_load_base_core, fill_mix_core, init_core, loop_core};
 
-#[derive(Default)]
+/// The state of the KISS99 generator ProgPoW draws its random program and
+/// initial registers from.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Kiss99State {
     z: u32,
     w: u32,
@@ -38,6 +41,56 @@ pub struct Kiss99State {
     jcong: u32,
 }
 
+impl Kiss99State {
+    /// Creates a state from its four words: the two multiply-with-carry
+    /// words `z` and `w`, the xorshift word `jsr` and the congruential word
+    /// `jcong`.
+    pub fn new(z: u32, w: u32, jsr: u32, jcong: u32) -> Self {
+        Kiss99State { z, w, jsr, jcong }
+    }
+
+    /// Returns the four words `[z, w, jsr, jcong]`.
+    pub fn words(&self) -> [u32; 4] {
+        [self.z, self.w, self.jsr, self.jcong]
+    }
+
+    /// Creates the state Marsaglia's KISS99 reference starts from, which the
+    /// ProgPoW spec's KISS99 test sequence is computed from: `z = 362436069`,
+    /// `w = 521288629`, `jsr = 123456789` and `jcong = 380116160`.
+    pub fn canonical() -> Self {
+        Kiss99State::new(362436069, 521288629, 123456789, 380116160)
+    }
+}
+
+/// KISS99 as a `rand_core` generator, yielding the words [`kiss99`] does;
+/// 64-bit values are two words, the first one low.
+#[cfg(feature = "rand_core")]
+impl rand_core::RngCore for Kiss99State {
+    fn next_u32(&mut self) -> u32 {
+        kiss99(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand_core::impls::next_u64_via_u32(self)
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        rand_core::impls::fill_bytes_via_next(self, dst)
+    }
+}
+
+/// Seeds KISS99 with its four words `z`, `w`, `jsr` and `jcong`, in that
+/// order, as little-endian bytes.
+#[cfg(feature = "rand_core")]
+impl rand_core::SeedableRng for Kiss99State {
+    type Seed = [u8; 16];
+
+    fn from_seed(seed: [u8; 16]) -> Self {
+        let word = |i: usize| u32::from_le_bytes(seed[i * 4..i * 4 + 4].try_into().unwrap());
+        Kiss99State::new(word(0), word(1), word(2), word(3))
+    }
+}
+
 /// Computes the FNV-1a hash.
 ///
 /// This is used for hashing small inputs in ProgPoW, such as seeds and indices.
@@ -150,16 +203,31 @@ pub fn fill_mix(seed: u64, lane_id: u32) -> [u32; PROGPOW_REGS] {
     st.w = fnv1a(&mut fnv_hash, higher32(seed));
     st.jsr = fnv1a(&mut fnv_hash, lane_id);
     st.jcong = fnv1a(&mut fnv_hash, lane_id);
-    println!(
-        "state: z: {}, w: {}, jsr: {}, jcong: {}",
-        st.z, st.w, st.jsr, st.jcong
-    );
 
-    for i in 0..PROGPOW_REGS {
-        mix[i] = kiss99(&mut st);
+    for reg in mix.iter_mut() {
+        *reg = kiss99(&mut st);
     }
     mix
 }
+
+/// Fills the mix registers of every lane at once, as [`fill_mix`] does for
+/// each lane.
+///
+/// The lanes' KISS99 states differ only in `jsr` and `jcong`, so the
+/// multiply-with-carry half is computed once per register and the other
+/// half for all lanes side by side, a loop the compiler turns into vector
+/// instructions.
+///
+/// # Arguments
+///
+/// * `seed` - The seed for random number generation.
+///
+/// # Returns
+///
+/// The `PROGPOW_REGS` registers of each of the `PROGPOW_LANES` lanes.
+pub fn fill_mix_lanes(seed: u64) -> [[u32; PROGPOW_REGS]; PROGPOW_LANES] {
+    fill_mix_core(seed)
+}
 /// Performs a mathematical operation based on a given opcode.
 ///
 /// This function implements various mathematical and bitwise operations.
@@ -173,7 +241,7 @@ pub fn fill_mix(seed: u64, lane_id: u32) -> [u32; PROGPOW_REGS] {
 /// # Returns
 ///
 /// The result of the operation.
-fn progpow_math(a: u32, b: u32, r: u32) -> u32 {
+pub(crate) fn progpow_math(a: u32, b: u32, r: u32) -> u32 {
     match r % 11 {
         0 => a.wrapping_add(b),
         1 => a.wrapping_mul(b),
@@ -190,8 +258,8 @@ fn progpow_math(a: u32, b: u32, r: u32) -> u32 {
         6 => a & b,
         7 => a | b,
         8 => a ^ b,
-        9 => (a.leading_zeros() + b.leading_zeros()) as u32,
-        10 => (a.count_ones() + b.count_ones()) as u32,
+        9 => a.leading_zeros() + b.leading_zeros(),
+        10 => a.count_ones() + b.count_ones(),
         _ => 0,
     }
 }
@@ -202,7 +270,7 @@ fn progpow_math(a: u32, b: u32, r: u32) -> u32 {
 /// * `a` - A mutable reference to the destination register.
 /// * `b` - The value to merge.
 /// * `r` - A random value that determines the operation.
-fn merge(a: &mut u32, b: u32, r: u32) {
+pub(crate) fn merge(a: &mut u32, b: u32, r: u32) {
     match r % 4 {
         0 => *a = (*a).wrapping_mul(33).wrapping_add(b),
         1 => *a = (*a ^ b).wrapping_mul(33),
@@ -225,33 +293,55 @@ fn merge(a: &mut u32, b: u32, r: u32) {
 /// 2. The destination register sequence.
 /// 3. The source register sequence.
 pub fn progpow_init(seed: u64) -> (Kiss99State, [u32; PROGPOW_REGS], [u32; PROGPOW_REGS]) {
-    let mut rand_state = Kiss99State::default();
-    let fnv_hash = &mut 0x811c9dc5u32;
-
-    rand_state.z = fnv1a(fnv_hash, lower32(seed));
-    rand_state.w = fnv1a(fnv_hash, higher32(seed));
-    rand_state.jsr = fnv1a(fnv_hash, lower32(seed));
-    rand_state.jcong = fnv1a(fnv_hash, higher32(seed));
-
-    let mut dst_seq: [u32; PROGPOW_REGS] = (0..PROGPOW_REGS as u32)
-        .collect::<Vec<u32>>()
-        .try_into()
-        .unwrap();
-    let mut src_seq: [u32; PROGPOW_REGS] = (0..PROGPOW_REGS as u32)
-        .collect::<Vec<u32>>()
-        .try_into()
-        .unwrap();
-
-    for i in (1..PROGPOW_REGS).rev() {
-        let j = kiss99(&mut rand_state) % (i as u32 + 1);
-        dst_seq.swap(i, j as usize);
-
-        let j = kiss99(&mut rand_state) % (i as u32 + 1);
-        src_seq.swap(i, j as usize);
+    init_core(seed)
+}
+/// Loads the 256 bytes of DAG data shared by all lanes in one loop iteration.
+///
+/// All lanes share a base address for the global load, taken from `mix[0]` of
+/// lane `loop_index % PROGPOW_LANES` so that it depends on the previous load.
+///
+/// # Arguments
+///
+/// * `loop_index` - The index of the current loop iteration.
+/// * `mix` - The mix data at the start of the iteration.
+/// * `lookup` - A function to retrieve DAG items based on an index.
+/// * `dataset_size` - The size of the dataset.
+///
+/// # Returns
+///
+/// The `PROGPOW_LANES * PROGPOW_DAG_LOADS` little-endian words as bytes.
+///
+/// # Panics
+///
+/// Panics if `64 * dataset_size` wraps to zero in 32 bits, as the division
+/// does in go-ethereum.
+pub(crate) fn load_dag_item(
+    loop_index: u32,
+    mix: &[[u32; PROGPOW_REGS]; PROGPOW_LANES],
+    lookup: &dyn Fn(u32) -> Vec<u8>,
+    dataset_size: u32,
+) -> Vec<u8> {
+    let mut dag_item = vec![0u8; 256];
+    let base = dag_load_base(loop_index, mix, dataset_size);
+    #[cfg(feature = "tracing")]
+    tracing::trace!(loop_index, item = base, "DAG load");
+    // The lookup returns 64 bytes, so fetch 4 times.
+    for (i, chunk) in dag_item.chunks_mut(64).enumerate() {
+        chunk.copy_from_slice(&lookup(base + 16 * i as u32)[..]);
     }
+    dag_item
+}
 
-    (rand_state, dst_seq, src_seq)
+/// Returns the dataset word index where the global DAG load of loop
+/// `loop_index` starts; see [`load_dag_item`].
+pub(crate) fn dag_load_base(
+    loop_index: u32,
+    mix: &[[u32; PROGPOW_REGS]; PROGPOW_LANES],
+    dataset_size: u32,
+) -> u32 {
+    dag_load_base_core(loop_index, mix, dataset_size)
 }
+
 /// Executes a single loop of the ProgPoW computation.
 ///
 /// This function performs memory accesses, random math operations, and merges results into the mix.
@@ -276,104 +366,215 @@ pub fn progpow_loop(
     c_dag: &[u32],
     dataset_size: u32,
 ) {
-    let g_offset = mix[loop_index as usize % PROGPOW_LANES][0]
-        % (64 * dataset_size / (PROGPOW_LANES as u32 * PROGPOW_DAG_LOADS as u32));
+    let dag_item = load_dag_item(loop_index, mix, lookup, dataset_size);
+    progpow_loop_with_item(seed, loop_index, mix, &dag_item, c_dag);
+}
 
-    let mut dst_counter: u32 = 0;
-    let mut rand_state = Kiss99State {
-        z: 0,
-        w: 0,
-        jsr: 0,
-        jcong: 0,
-    };
+/// Runs loop `loop_index` of [`progpow_loop`] on its already loaded
+/// 256-byte DAG item, for callers that load items into their own buffers.
+pub(crate) fn progpow_loop_with_item(
+    seed: u64,
+    loop_index: u32,
+    mix: &mut [[u32; PROGPOW_REGS]; PROGPOW_LANES],
+    dag_item: &[u8],
+    c_dag: &[u32],
+) {
+    loop_core(seed, loop_index, mix, dag_item, c_dag)
+}
 
-    //检查数据
-    println!("g_offset: {}", g_offset);
+// The spec helpers spell out their rotations rather than call the ones
+// under test.
+#[cfg(test)]
+#[allow(clippy::manual_rotate)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// `ROTL32` from the spec, with the shift by 32 of `n % 32 == 0` made
+    /// explicit.
+    fn spec_rotl32(x: u32, n: u32) -> u32 {
+        let n = n % 32;
+        if n == 0 {
+            x
+        } else {
+            (x << n) | (x >> (32 - n))
+        }
+    }
 
-    let mut src_seq = [0u32; PROGPOW_REGS];
-    let mut dst_seq = [0u32; PROGPOW_REGS];
-    let mut data_g = [0u32; PROGPOW_DAG_LOADS];
-    let mut dag_item = vec![0u8; 256];
+    /// `ROTR32` from the spec.
+    fn spec_rotr32(x: u32, n: u32) -> u32 {
+        let n = n % 32;
+        if n == 0 {
+            x
+        } else {
+            (x >> n) | (x << (32 - n))
+        }
+    }
 
-    dag_item[0..64]
-        .copy_from_slice(&lookup((g_offset * PROGPOW_LANES as u32) * PROGPOW_DAG_LOADS as u32)[..]);
-    dag_item[64..128].copy_from_slice(
-        &lookup((g_offset * PROGPOW_LANES as u32) * PROGPOW_DAG_LOADS as u32 + 16)[..],
-    );
-    dag_item[128..192].copy_from_slice(
-        &lookup((g_offset * PROGPOW_LANES as u32) * PROGPOW_DAG_LOADS as u32 + 32)[..],
-    );
-    dag_item[192..].copy_from_slice(
-        &lookup((g_offset * PROGPOW_LANES as u32) * PROGPOW_DAG_LOADS as u32 + 48)[..],
-    );
-
-    for l in 0..PROGPOW_LANES as u32 {
-        // Initialize the seed and mix destination sequence
-        let mut src_counter: u32 = 0;
-        let (mut rand_state, dst_seq, src_seq) = progpow_init(seed);
-        // println!("dst_seq: {:?}, src_seq: {:?}", dst_seq, src_seq);
-        for i in 0..PROGPOW_CNT_MATH {
-            if i < PROGPOW_CNT_CACHE {
-                // Cached memory access
-                let src = src_seq[(src_counter % PROGPOW_REGS as u32) as usize];
-                // println!("{}", (src_counter % PROGPOW_REGS as u32) as usize);
-                src_counter += 1;
-                // println!("Lane {} Cache Access: src={}", l, src);
-
-                let offset = mix[l as usize][src as usize] % PROGPOW_CACHE_WORDS as u32;
-                let data32 = c_dag[offset as usize];
-
-                let dst = dst_seq[(dst_counter % PROGPOW_REGS as u32) as usize];
-                dst_counter += 1;
-
-                let r = kiss99(&mut rand_state);
-                merge(&mut mix[l as usize][dst as usize], data32, r);
-                // println!(
-                //     "Lane {} Cache Access: offset={}, data32={}, dst={}, mix[dst]={}",
-                //     l, offset, data32, dst, mix[l as usize][dst as usize]
-                // );
-            }
+    /// `clz` from the spec, counting from the top bit down.
+    fn spec_clz(mut a: u32) -> u32 {
+        let mut count = 0;
+        while count < 32 && a & 0x8000_0000 == 0 {
+            count += 1;
+            a <<= 1;
+        }
+        count
+    }
 
-            // Random Math
-            let src_rnd = kiss99(&mut rand_state) % (PROGPOW_REGS * (PROGPOW_REGS - 1)) as u32;
-            let src1 = src_rnd % PROGPOW_REGS as u32;
-            let mut src2 = src_rnd / PROGPOW_REGS as u32;
-            if src2 >= src1 {
-                src2 += 1;
+    /// `popcount` from the spec, one bit at a time.
+    fn spec_popcount(a: u32) -> u32 {
+        (0..32).map(|bit| (a >> bit) & 1).sum()
+    }
+
+    /// `progpow_math` as the spec's pseudocode writes it.
+    fn spec_math(a: u32, b: u32, r: u32) -> u32 {
+        match r % 11 {
+            0 => a.wrapping_add(b),
+            1 => a.wrapping_mul(b),
+            2 => ((a as u64 * b as u64) >> 32) as u32,
+            3 => a.min(b),
+            4 => spec_rotl32(a, b),
+            5 => spec_rotr32(a, b),
+            6 => a & b,
+            7 => a | b,
+            8 => a ^ b,
+            9 => spec_clz(a) + spec_clz(b),
+            _ => spec_popcount(a) + spec_popcount(b),
+        }
+    }
+
+    /// `merge` as the spec's pseudocode writes it; the rotation amount is
+    /// never 0, so the rotations need no special case.
+    fn spec_merge(a: u32, b: u32, r: u32) -> u32 {
+        let x = ((r >> 16) % 31) + 1;
+        match r % 4 {
+            0 => a.wrapping_mul(33).wrapping_add(b),
+            1 => (a ^ b).wrapping_mul(33),
+            2 => ((a << x) | (a >> (32 - x))) ^ b,
+            _ => ((a >> x) | (a << (32 - x))) ^ b,
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn prop_math_matches_spec(a: u32, b: u32, r: u32) {
+            prop_assert_eq!(progpow_math(a, b, r), spec_math(a, b, r));
+        }
+
+        #[test]
+        fn prop_merge_matches_spec(a: u32, b: u32, r: u32) {
+            let mut merged = a;
+            merge(&mut merged, b, r);
+            prop_assert_eq!(merged, spec_merge(a, b, r));
+        }
+
+        #[test]
+        fn prop_fill_mix_lanes_matches_fill_mix(seed: u64) {
+            let lanes = fill_mix_lanes(seed);
+            for (lane, mix) in lanes.iter().enumerate() {
+                prop_assert_eq!(*mix, fill_mix(seed, lane as u32));
             }
-            let data32 = progpow_math(
-                mix[l as usize][src1 as usize],
-                mix[l as usize][src2 as usize],
-                kiss99(&mut rand_state),
-            );
-
-            let dst = dst_seq[(dst_counter % PROGPOW_REGS as u32) as usize];
-            dst_counter += 1;
-
-            merge(
-                &mut mix[l as usize][dst as usize],
-                data32,
-                kiss99(&mut rand_state),
-            );
         }
 
-        let index = ((l ^ loop_index) % PROGPOW_LANES as u32) * PROGPOW_DAG_LOADS as u32;
+        #[test]
+        fn prop_rotations_match_spec(x: u32, n: u32) {
+            prop_assert_eq!(rotl32(x, n), spec_rotl32(x, n));
+            prop_assert_eq!(rotr32(x, n), spec_rotr32(x, n));
+            prop_assert_eq!(rotr32(rotl32(x, n), n), x);
+        }
+    }
+
+    #[test]
+    fn test_kiss99_canonical_sequence() {
+        // The test sequence of the ProgPoW spec.
+        let mut st = Kiss99State::canonical();
+        let first: Vec<u32> = (0..4).map(|_| kiss99(&mut st)).collect();
+        assert_eq!(first, [769445856, 742012328, 2121196314, 2805620942]);
+        let hundred_thousandth = (4..100_000).map(|_| kiss99(&mut st)).last();
+        assert_eq!(hundred_thousandth, Some(941074834));
+    }
 
-        data_g[0] = LittleEndian::read_u32(&dag_item[(4 * index) as usize..]);
-        data_g[1] = LittleEndian::read_u32(&dag_item[(4 * (index + 1)) as usize..]);
-        data_g[2] = LittleEndian::read_u32(&dag_item[(4 * (index + 2)) as usize..]);
-        data_g[3] = LittleEndian::read_u32(&dag_item[(4 * (index + 3)) as usize..]);
+    #[test]
+    fn test_kiss99_state_is_a_plain_value() {
+        let start = Kiss99State::new(1, 2, 3, 4);
+        let mut st = start;
+        kiss99(&mut st);
+        assert_eq!(start.words(), [1, 2, 3, 4]);
+        assert_ne!(st, start);
+        assert_eq!(Kiss99State::canonical().words()[3], 380116160);
+        assert_eq!(
+            format!("{start:?}"),
+            "Kiss99State { z: 1, w: 2, jsr: 3, jcong: 4 }"
+        );
+    }
 
-        merge(&mut mix[l as usize][0], data_g[0], kiss99(&mut rand_state));
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_core_types_are_serializable() {
+        fn serializable<T: serde::Serialize + serde::de::DeserializeOwned>() {}
+        serializable::<Kiss99State>();
+        serializable::<crate::progpow::verify::Seal>();
+        serializable::<crate::progpow::verify::SealError>();
+        serializable::<crate::progpow::program::Program>();
+        serializable::<crate::progpow::search::Solution>();
+        serializable::<crate::miner::backend::Work>();
+        serializable::<crate::ethash::manager::Retention>();
+        serializable::<crate::ethash::background::Throttle>();
+        serializable::<crate::warmup::WarmUpStatus>();
+    }
 
-        for i in 1..PROGPOW_DAG_LOADS {
-            let dst = dst_seq[(dst_counter % PROGPOW_REGS as u32) as usize];
-            dst_counter += 1;
-            merge(
-                &mut mix[l as usize][dst as usize],
-                data_g[i],
-                kiss99(&mut rand_state),
-            );
+    #[cfg(feature = "rand_core")]
+    #[test]
+    fn test_kiss99_as_rand_core_rng() {
+        use rand_core::{RngCore, SeedableRng};
+
+        let mut seed = [0u8; 16];
+        for (bytes, word) in
+            seed.chunks_exact_mut(4)
+                .zip([362436069u32, 521288629, 123456789, 380116160])
+        {
+            bytes.copy_from_slice(&word.to_le_bytes());
         }
+        let mut rng = Kiss99State::from_seed(seed);
+        assert_eq!(rng.next_u32(), 769445856);
+        assert_eq!(rng.next_u64(), 2121196314 << 32 | 742012328);
+        let mut bytes = [0u8; 5];
+        rng.fill_bytes(&mut bytes);
+        assert_eq!(bytes[..4], 2805620942u32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_rotation_edge_cases() {
+        let x = 0x8000_0001;
+        for n in [0, 32, 64] {
+            assert_eq!(rotl32(x, n), x);
+            assert_eq!(rotr32(x, n), x);
+        }
+        assert_eq!(rotl32(x, 1), 0x0000_0003);
+        assert_eq!(rotr32(x, 1), 0xc000_0000);
+        assert_eq!(rotl32(x, 31), rotr32(x, 1));
+        assert_eq!(rotr32(x, 33), rotr32(x, 1));
+
+        // The math ops that rotate by register values hit n = 0 and 32.
+        assert_eq!(progpow_math(x, 0, 4), x);
+        assert_eq!(progpow_math(x, 32, 5), x);
+        assert_eq!(progpow_math(0, 0, 9), 64);
+        assert_eq!(progpow_math(u32::MAX, u32::MAX, 10), 64);
+    }
+
+    #[test]
+    fn test_load_dag_item_wraps_the_item_count_like_geth() {
+        // The dataset of epoch 2047 has more than 2^26 items of 256 bytes.
+        let items = (crate::ethash::cache::dataset_size(2047) / 256) as u32;
+        assert!(items > 1 << 26);
+        let mix = [[u32::MAX; PROGPOW_REGS]; PROGPOW_LANES];
+        let largest = std::cell::Cell::new(0);
+        let lookup = |index: u32| {
+            largest.set(largest.get().max(index));
+            vec![0; 64]
+        };
+        load_dag_item(0, &mix, &lookup, items);
+        let g_offset = u32::MAX % (items % (1 << 26));
+        assert_eq!(largest.get(), g_offset * 64 + 48);
     }
 }
@@ -0,0 +1,59 @@
+//! `progpow-httpd`: serves ProgPoW seal verification over HTTP with JSON.
+//!
+//! ```text
+//! progpow-httpd [--listen ADDR] [--caches N]
+//! ```
+//!
+//! `--listen` defaults to `127.0.0.1:8545` and `--caches`, the number of
+//! epochs whose light caches are kept, to 3.
+
+use std::net::SocketAddr;
+use std::process;
+use std::sync::Arc;
+
+use progpow_verifier::ethash::manager::CacheManager;
+use progpow_verifier::http::router;
+
+/// The command-line options.
+struct Options {
+    listen: SocketAddr,
+    caches: usize,
+}
+
+/// Parses the command line, exiting with a usage message on errors.
+fn parse_options() -> Options {
+    let usage = || -> ! {
+        eprintln!("usage: progpow-httpd [--listen ADDR] [--caches N]");
+        process::exit(2)
+    };
+    let mut options = Options {
+        listen: "127.0.0.1:8545".parse().unwrap(),
+        caches: 3,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match flag.as_str() {
+            "--listen" => options.listen = value.parse().unwrap_or_else(|_| usage()),
+            "--caches" => match value.parse() {
+                Ok(caches) if caches > 0 => options.caches = caches,
+                _ => usage(),
+            },
+            _ => usage(),
+        }
+    }
+    options
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let options = parse_options();
+    let listener = tokio::net::TcpListener::bind(options.listen).await?;
+    eprintln!("progpow-httpd listening on {}", listener.local_addr()?);
+    axum::serve(
+        listener,
+        router(Arc::new(CacheManager::new(options.caches))),
+    )
+    .await?;
+    Ok(())
+}
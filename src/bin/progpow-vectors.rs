@@ -0,0 +1,78 @@
+//! `progpow-vectors`: exports and checks machine-readable test vectors.
+//!
+//! ```text
+//! progpow-vectors generate [--count N] [--seed S] [--epoch E | --size BYTES]
+//! progpow-vectors check FILE
+//! ```
+//!
+//! `generate` writes a JSON corpus to standard output: `--count` vectors
+//! (default 8) with inputs derived from `--seed` (default 0), over the
+//! synthetic dataset of `--size` bytes (default 1 MiB) or the real dataset of
+//! `--epoch`. `check` recomputes a corpus and exits with status 1 if any
+//! vector differs.
+
+use std::process;
+
+use progpow_verifier::ethash::cache::MAX_EPOCH;
+use progpow_verifier::progpow::vectors::{Corpus, Dataset};
+
+/// Prints the usage message and exits.
+fn usage() -> ! {
+    eprintln!(
+        "usage: progpow-vectors generate [--count N] [--seed S] [--epoch E | --size BYTES]\n\
+         \x20      progpow-vectors check FILE"
+    );
+    process::exit(2)
+}
+
+/// Runs `generate` with the arguments after the subcommand.
+fn generate(mut args: impl Iterator<Item = String>) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut count, mut seed) = (8, 0);
+    let mut dataset = Dataset::Synthetic { size: 1 << 20 };
+    while let Some(flag) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        let number: u64 = value.parse().unwrap_or_else(|_| usage());
+        match flag.as_str() {
+            "--count" => count = number as usize,
+            "--seed" => seed = number,
+            "--epoch" if number < MAX_EPOCH => dataset = Dataset::Ethash { epoch: number },
+            "--size" if number > 0 && number.is_multiple_of(256) => {
+                dataset = Dataset::Synthetic { size: number }
+            }
+            _ => usage(),
+        }
+    }
+    let corpus = Corpus::generate(dataset, count, seed);
+    println!("{}", serde_json::to_string_pretty(&corpus)?);
+    Ok(())
+}
+
+/// Runs `check` on `path`.
+fn check(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let corpus: Corpus = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    let mismatches = corpus.check()?;
+    for mismatch in &mismatches {
+        eprintln!("vector {}: {} differs", mismatch.index, mismatch.field);
+    }
+    eprintln!(
+        "{} of {} vectors match",
+        corpus.vectors.len() - mismatches.len(),
+        corpus.vectors.len()
+    );
+    if !mismatches.is_empty() {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        Some("generate") => generate(args),
+        Some("check") => match (args.next(), args.next()) {
+            (Some(path), None) => check(&path),
+            _ => usage(),
+        },
+        _ => usage(),
+    }
+}
@@ -0,0 +1,70 @@
+//! `progpow-verifyd`: serves ProgPoW seal verification over gRPC.
+//!
+//! ```text
+//! progpow-verifyd [--listen ADDR] [--caches N] [--metrics ADDR]
+//! ```
+//!
+//! `--listen` defaults to `127.0.0.1:50051` and `--caches`, the number of
+//! epochs whose light caches are kept, to 3. With the `metrics` feature,
+//! `--metrics` serves Prometheus metrics over HTTP on another address.
+
+use std::net::SocketAddr;
+use std::process;
+use std::sync::Arc;
+
+use progpow_verifier::ethash::manager::CacheManager;
+use progpow_verifier::grpc::VerifierService;
+
+/// The command-line options.
+struct Options {
+    listen: SocketAddr,
+    caches: usize,
+    #[cfg(feature = "metrics")]
+    metrics: Option<SocketAddr>,
+}
+
+/// Parses the command line, exiting with a usage message on errors.
+fn parse_options() -> Options {
+    let usage = || -> ! {
+        eprintln!("usage: progpow-verifyd [--listen ADDR] [--caches N] [--metrics ADDR]");
+        process::exit(2)
+    };
+    let mut options = Options {
+        listen: "127.0.0.1:50051".parse().unwrap(),
+        caches: 3,
+        #[cfg(feature = "metrics")]
+        metrics: None,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().unwrap_or_else(|| usage());
+        match flag.as_str() {
+            "--listen" => options.listen = value.parse().unwrap_or_else(|_| usage()),
+            "--caches" => match value.parse() {
+                Ok(caches) if caches > 0 => options.caches = caches,
+                _ => usage(),
+            },
+            #[cfg(feature = "metrics")]
+            "--metrics" => options.metrics = Some(value.parse().unwrap_or_else(|_| usage())),
+            _ => usage(),
+        }
+    }
+    options
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let options = parse_options();
+    #[cfg(feature = "metrics")]
+    if let Some(address) = options.metrics {
+        let address = progpow_verifier::metrics::serve(address)?;
+        eprintln!("progpow-verifyd serving metrics on {address}");
+    }
+    let service = VerifierService::new(Arc::new(CacheManager::new(options.caches)));
+    eprintln!("progpow-verifyd listening on {}", options.listen);
+    tonic::transport::Server::builder()
+        .add_service(service.into_server())
+        .serve(options.listen)
+        .await?;
+    Ok(())
+}
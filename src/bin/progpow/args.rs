@@ -0,0 +1,315 @@
+//! Command-line flags and the hex values they carry.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::str::FromStr;
+use std::time::Duration;
+
+use progpow_verifier::chain::{Chain, ChainRegistry};
+use progpow_verifier::target::U256;
+
+/// A usage error, printed with the usage message before exiting with
+/// status 2.
+#[derive(Debug)]
+pub struct UsageError(pub String);
+
+impl std::fmt::Display for UsageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for UsageError {}
+
+/// Returns a [`UsageError`] as a boxed error.
+pub fn usage_error(message: impl Into<String>) -> Box<dyn std::error::Error> {
+    Box::new(UsageError(message.into()))
+}
+
+/// The `--name value` flags and bare `--switch`es after a subcommand.
+///
+/// Each subcommand takes its flags out by name and then calls
+/// [`finish`](Self::finish), so unknown or repeated flags are reported
+/// instead of ignored.
+pub struct Flags {
+    values: HashMap<String, String>,
+    switches: Vec<String>,
+}
+
+impl Flags {
+    /// Splits `args` into flags, treating the names in `switches` as taking
+    /// no value.
+    pub fn parse(
+        args: impl IntoIterator<Item = String>,
+        switches: &[&str],
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut flags = Flags {
+            values: HashMap::new(),
+            switches: Vec::new(),
+        };
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                return Err(usage_error(format!("unexpected argument {arg:?}")));
+            };
+            if switches.contains(&name) {
+                flags.switches.push(name.to_string());
+                continue;
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| usage_error(format!("--{name} needs a value")))?;
+            if flags.values.insert(name.to_string(), value).is_some() {
+                return Err(usage_error(format!("--{name} is given twice")));
+            }
+        }
+        Ok(flags)
+    }
+
+    /// Takes the value of `--name` as text, if given.
+    pub fn take(&mut self, name: &str) -> Option<String> {
+        self.values.remove(name)
+    }
+
+    /// Takes and parses the value of `--name`, if given.
+    pub fn optional<T: FromStr>(
+        &mut self,
+        name: &str,
+    ) -> Result<Option<T>, Box<dyn std::error::Error>> {
+        self.take(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| usage_error(format!("invalid --{name} {value:?}")))
+            })
+            .transpose()
+    }
+
+    /// Takes and parses the value of `--name`, which must be given.
+    pub fn required<T: FromStr>(&mut self, name: &str) -> Result<T, Box<dyn std::error::Error>> {
+        self.optional(name)?
+            .ok_or_else(|| usage_error(format!("--{name} is required")))
+    }
+
+    /// Takes the value of `--name` through `parse`, which must succeed.
+    pub fn required_with<T>(
+        &mut self,
+        name: &str,
+        parse: impl FnOnce(&str) -> Option<T>,
+    ) -> Result<T, Box<dyn std::error::Error>> {
+        let value = self
+            .take(name)
+            .ok_or_else(|| usage_error(format!("--{name} is required")))?;
+        parse(&value).ok_or_else(|| usage_error(format!("invalid --{name} {value:?}")))
+    }
+
+    /// Returns `true` if the switch `--name` was given.
+    pub fn switch(&mut self, name: &str) -> bool {
+        let given = self.switches.iter().any(|switch| switch == name);
+        self.switches.retain(|switch| switch != name);
+        given
+    }
+
+    /// Takes `--chain`: the name of a built-in chain, or with the
+    /// `chain-config` feature the path of a chain's config file. Defaults
+    /// to `ethereum`.
+    pub fn chain(&mut self) -> Result<Chain, Box<dyn std::error::Error>> {
+        let name = self.take("chain").unwrap_or_else(|| "ethereum".to_string());
+        let registry = ChainRegistry::with_builtins();
+        if let Some(chain) = registry.get(&name) {
+            return Ok(chain.clone());
+        }
+        #[cfg(feature = "chain-config")]
+        if std::path::Path::new(&name).is_file() {
+            return Ok(Chain::from_file(&name)?);
+        }
+        let names: Vec<_> = registry.names().collect();
+        Err(usage_error(format!(
+            "unknown chain {name:?}, expected one of {}",
+            names.join(", ")
+        )))
+    }
+
+    /// Fails if a flag was given that the subcommand did not take.
+    pub fn finish(self) -> Result<(), Box<dyn std::error::Error>> {
+        match self.values.keys().chain(&self.switches).next() {
+            Some(name) => Err(usage_error(format!("unknown flag --{name}"))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Decodes hex, with or without a `0x` prefix.
+pub fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    let digits = hex.strip_prefix("0x").unwrap_or(hex);
+    if !digits.len().is_multiple_of(2) || !digits.is_ascii() {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Decodes 32 bytes of hex.
+pub fn parse_hash(hex: &str) -> Option<[u8; 32]> {
+    parse_hex(hex)?.try_into().ok()
+}
+
+/// Parses a hex nonce of at most 16 digits.
+pub fn parse_nonce(hex: &str) -> Option<u64> {
+    let digits = hex.strip_prefix("0x").unwrap_or(hex);
+    (!digits.is_empty() && digits.len() <= 16)
+        .then(|| u64::from_str_radix(digits, 16).ok())
+        .flatten()
+}
+
+/// Parses a hex 256-bit integer of at most 64 digits.
+pub fn parse_u256(hex: &str) -> Option<U256> {
+    let digits = hex.strip_prefix("0x").unwrap_or(hex);
+    if digits.is_empty() || digits.len() > 64 {
+        return None;
+    }
+    let padded = format!("{digits:0>64}");
+    parse_hash(&padded).map(U256::from_be_bytes)
+}
+
+/// Parses a duration such as `30s`, `500ms` or `2m`; a bare number is in
+/// seconds. Durations too long for a [`Duration`] are rejected.
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let (number, unit) = match text.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(split) => text.split_at(split),
+        None => (text, "s"),
+    };
+    let number: f64 = number
+        .parse()
+        .ok()
+        .filter(|n: &f64| n.is_finite() && *n >= 0.0)?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// Formats `bytes` as `0x`-prefixed hex.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::from("0x"), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flags(args: &[&str], switches: &[&str]) -> Result<Flags, Box<dyn std::error::Error>> {
+        Flags::parse(args.iter().map(|arg| arg.to_string()), switches)
+    }
+
+    fn message(result: Result<impl Sized, Box<dyn std::error::Error>>) -> String {
+        let error = result.err().expect("a usage error");
+        assert!(error.is::<UsageError>(), "{error}");
+        error.to_string()
+    }
+
+    #[test]
+    fn test_flags_parse() {
+        let mut parsed = flags(&["--block", "7", "--json", "--nonce", "0x1"], &["json"]).unwrap();
+        assert_eq!(parsed.required::<u64>("block").unwrap(), 7);
+        assert!(parsed.switch("json"));
+        assert!(!parsed.switch("json"));
+        assert_eq!(parsed.take("nonce").as_deref(), Some("0x1"));
+        assert_eq!(parsed.optional::<u64>("epoch").unwrap(), None);
+        parsed.finish().unwrap();
+
+        assert_eq!(
+            message(flags(&["--block", "1", "--block", "2"], &[])),
+            "--block is given twice"
+        );
+        assert_eq!(message(flags(&["--block"], &[])), "--block needs a value");
+        assert_eq!(
+            message(flags(&["block", "1"], &[])),
+            "unexpected argument \"block\""
+        );
+
+        let mut parsed = flags(&["--block", "x"], &[]).unwrap();
+        assert_eq!(
+            message(parsed.required::<u64>("block")),
+            "invalid --block \"x\""
+        );
+        assert_eq!(
+            message(parsed.required::<u64>("block")),
+            "--block is required"
+        );
+        assert_eq!(
+            message(flags(&["--bogus", "1"], &[]).unwrap().finish()),
+            "unknown flag --bogus"
+        );
+        assert_eq!(
+            message(flags(&["--json"], &["json"]).unwrap().finish()),
+            "unknown flag --json"
+        );
+    }
+
+    #[test]
+    fn test_flags_chain() {
+        let mut parsed = flags(&["--chain", "ravencoin"], &[]).unwrap();
+        assert_eq!(parsed.chain().unwrap().name, "ravencoin");
+        assert_eq!(flags(&[], &[]).unwrap().chain().unwrap().name, "ethereum");
+        let mut parsed = flags(&["--chain", "dogecoin"], &[]).unwrap();
+        assert!(message(parsed.chain()).starts_with("unknown chain \"dogecoin\""));
+    }
+
+    #[test]
+    fn test_parse_hex_values() {
+        assert_eq!(parse_hex("0x00ff10"), Some(vec![0, 0xff, 0x10]));
+        assert_eq!(parse_hex("ABcd"), Some(vec![0xab, 0xcd]));
+        assert_eq!(parse_hex(""), Some(vec![]));
+        assert_eq!(parse_hex("abc"), None);
+        assert_eq!(parse_hex("zz"), None);
+        assert_eq!(parse_hex("é0"), None);
+
+        assert_eq!(parse_hash(&"11".repeat(32)), Some([0x11; 32]));
+        assert_eq!(parse_hash(&"11".repeat(31)), None);
+        assert_eq!(parse_hash(&"11".repeat(33)), None);
+
+        assert_eq!(
+            parse_nonce("0x123456789abcdef0"),
+            Some(0x1234_5678_9abc_def0)
+        );
+        assert_eq!(parse_nonce("7"), Some(7));
+        assert_eq!(parse_nonce("ffffffffffffffff"), Some(u64::MAX));
+        assert_eq!(parse_nonce("10000000000000000"), None);
+        assert_eq!(parse_nonce("0x"), None);
+        assert_eq!(parse_nonce("-1"), None);
+
+        assert_eq!(parse_u256("0x10"), Some(U256::from(16u64)));
+        assert_eq!(parse_u256(&"f".repeat(64)), Some(U256::MAX));
+        assert_eq!(parse_u256(&"f".repeat(65)), None);
+        assert_eq!(parse_u256(""), None);
+        assert_eq!(parse_u256("0x"), None);
+        assert_eq!(parse_u256("0xg"), None);
+
+        assert_eq!(to_hex(&[0, 0xab]), "0x00ab");
+        assert_eq!(to_hex(&[]), "0x");
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("30"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("2m"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_duration("1.5h"), Some(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("-1s"), None);
+        assert_eq!(parse_duration("3d"), None);
+
+        // Too long for a Duration: rejected rather than panicking.
+        assert_eq!(parse_duration("99999999999999999999"), None);
+        assert_eq!(parse_duration("9999999999999999h"), None);
+    }
+}
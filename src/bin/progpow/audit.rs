@@ -0,0 +1,159 @@
+//! `progpow audit`: verifies a range of blocks from a node and reports on
+//! each.
+//!
+//! Every block's header is fetched with `eth_getBlockByNumber`, checked
+//! against its block hash, and its seal verified with the chain's caches,
+//! two epochs of which are kept so a range crossing an epoch boundary
+//! generates each cache once. The report has one row per block: its number
+//! and epoch, whether it passed, the time taken to fetch and to verify it,
+//! and why it failed. It is CSV, or JSON when `--format json` is given or
+//! `--out` ends in `.json`. The command exits with status 1 if any block
+//! failed, so it can run from cron.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::time::Instant;
+
+use progpow_verifier::ethash::cache::MAX_EPOCH;
+use progpow_verifier::header::Header;
+use progpow_verifier::progpow::verify::Seal;
+use progpow_verifier::rpc::fetch_header;
+use progpow_verifier::target::boundary_from_difficulty;
+use serde_json::json;
+
+use crate::args::{usage_error, Flags};
+
+/// The outcome of auditing one block.
+struct Row {
+    block_number: u64,
+    epoch: u64,
+    fetch_ms: f64,
+    verify_ms: f64,
+    /// Why the block failed, or `None` if it passed.
+    error: Option<String>,
+}
+
+/// Audits the blocks in `flags` and writes the report.
+pub fn run(mut flags: Flags) -> Result<(), Box<dyn std::error::Error>> {
+    let from: u64 = flags.required("from")?;
+    let to: u64 = flags.required("to")?;
+    let rpc: String = flags.required("rpc")?;
+    let out: Option<PathBuf> = flags.optional("out")?;
+    let format = flags.take("format");
+    let chain = flags.chain()?;
+    flags.finish()?;
+    if from > to {
+        return Err(usage_error("--from is after --to"));
+    }
+    let json = match format.as_deref() {
+        Some("json") => true,
+        Some("csv") => false,
+        Some(format) => {
+            return Err(usage_error(format!(
+                "unknown --format {format:?}, expected csv or json"
+            )))
+        }
+        None => out
+            .as_ref()
+            .is_some_and(|path| path.extension().is_some_and(|ext| ext == "json")),
+    };
+
+    let caches = chain.cache_manager(2);
+    let mut rows = Vec::new();
+    for block_number in from..=to {
+        let epoch = chain.epoch(block_number);
+        let started = Instant::now();
+        let fetched = fetch_header(&rpc, block_number);
+        let fetch_ms = elapsed_ms(started);
+        let started = Instant::now();
+        let result = fetched
+            .map_err(|error| error.to_string())
+            .and_then(|rlp| Header::decode(&rlp).map_err(|error| error.to_string()))
+            .and_then(|header| {
+                if epoch >= MAX_EPOCH {
+                    return Err(format!("epoch {epoch} is not supported"));
+                }
+                let seal = Seal {
+                    header_hash: header.pre_hash(),
+                    block_number,
+                    nonce: header.nonce,
+                    mix_hash: header.mix_hash,
+                    boundary: boundary_from_difficulty(header.difficulty),
+                };
+                chain
+                    .verify_seal(&caches, &seal)
+                    .map_err(|error| error.to_string())
+            });
+        let row = Row {
+            block_number,
+            epoch,
+            fetch_ms,
+            verify_ms: elapsed_ms(started),
+            error: result.err(),
+        };
+        if let Some(error) = &row.error {
+            eprintln!("block {block_number}: {error}");
+        }
+        rows.push(row);
+    }
+
+    let report = if json {
+        json_report(&rows)
+    } else {
+        csv_report(&rows)
+    };
+    match &out {
+        Some(path) => fs::write(path, report)?,
+        None => print!("{report}"),
+    }
+    let failed = rows.iter().filter(|row| row.error.is_some()).count();
+    eprintln!("{} of {} blocks passed", rows.len() - failed, rows.len());
+    if failed > 0 {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+/// Returns the milliseconds since `started`.
+fn elapsed_ms(started: Instant) -> f64 {
+    started.elapsed().as_secs_f64() * 1e3
+}
+
+/// Renders `rows` as CSV with a header line.
+fn csv_report(rows: &[Row]) -> String {
+    let mut out = String::from("block,epoch,result,fetch_ms,verify_ms,error\n");
+    for row in rows {
+        out.push_str(&format!(
+            "{},{},{},{:.3},{:.3},{}\n",
+            row.block_number,
+            row.epoch,
+            if row.error.is_none() { "pass" } else { "fail" },
+            row.fetch_ms,
+            row.verify_ms,
+            row.error.as_deref().map_or(String::new(), |error| format!(
+                "\"{}\"",
+                error.replace('"', "\"\"")
+            ))
+        ));
+    }
+    out
+}
+
+/// Renders `rows` as a JSON array of objects with the CSV's columns.
+fn json_report(rows: &[Row]) -> String {
+    let rows: Vec<_> = rows
+        .iter()
+        .map(|row| {
+            json!({
+                "block": row.block_number,
+                "epoch": row.epoch,
+                "result": if row.error.is_none() { "pass" } else { "fail" },
+                "fetch_ms": (row.fetch_ms * 1e3).round() / 1e3,
+                "verify_ms": (row.verify_ms * 1e3).round() / 1e3,
+                "error": row.error,
+            })
+        })
+        .collect();
+    format!("{}\n", serde_json::Value::Array(rows))
+}
@@ -0,0 +1,169 @@
+//! `progpow bench`: times each stage of light verification on the CPU.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use progpow_verifier::ethash::cache::{make_cache, seed_hash, MAX_EPOCH};
+use progpow_verifier::ethash::manager::EpochCache;
+use progpow_verifier::progpow::progpow::progpow_seed;
+
+use crate::args::{parse_duration, usage_error, Flags};
+
+/// Nonces a thread hashes between checks of the clock.
+const NONCES_PER_CHECK: u64 = 16;
+
+/// Seed passes timed for the Keccak stage.
+const SEED_PASSES: u64 = 100_000;
+
+/// The timings of one run.
+struct Report {
+    chain: String,
+    epoch: u64,
+    threads: usize,
+    seed_hash: Duration,
+    cache: Duration,
+    c_dag: Duration,
+    seed_pass: Duration,
+    hashes: u64,
+    hashing: Duration,
+}
+
+impl Report {
+    fn hashes_per_second(&self) -> f64 {
+        self.hashes as f64 / self.hashing.as_secs_f64()
+    }
+
+    fn seed_passes_per_second(&self) -> f64 {
+        SEED_PASSES as f64 / self.seed_pass.as_secs_f64()
+    }
+
+    /// Prints the report for people.
+    fn print(&self) {
+        println!(
+            "chain {}, epoch {}, {} threads",
+            self.chain, self.epoch, self.threads
+        );
+        println!("seed hash   {:>12.3} ms", millis(self.seed_hash));
+        println!("light cache {:>12.3} ms", millis(self.cache));
+        println!("cached DAG  {:>12.3} ms", millis(self.c_dag));
+        println!(
+            "seed pass   {:>12.0} /s on one thread",
+            self.seed_passes_per_second()
+        );
+        println!(
+            "hashing     {:>12.1} H/s ({} hashes in {:.1} s, {:.1} H/s per thread)",
+            self.hashes_per_second(),
+            self.hashes,
+            self.hashing.as_secs_f64(),
+            self.hashes_per_second() / self.threads as f64
+        );
+    }
+
+    /// Prints the report as one JSON object.
+    fn print_json(&self) {
+        println!(
+            "{{\"chain\":{:?},\"epoch\":{},\"threads\":{},\"stages\":{{\
+             \"seed_hash_ms\":{:.3},\"cache_ms\":{:.3},\"c_dag_ms\":{:.3},\
+             \"seed_passes_per_second\":{:.0}}},\"hashes\":{},\"hashing_seconds\":{:.3},\
+             \"hashes_per_second\":{:.1}}}",
+            self.chain,
+            self.epoch,
+            self.threads,
+            millis(self.seed_hash),
+            millis(self.cache),
+            millis(self.c_dag),
+            self.seed_passes_per_second(),
+            self.hashes,
+            self.hashing.as_secs_f64(),
+            self.hashes_per_second()
+        );
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Runs the benchmark described by `flags`.
+pub fn run(mut flags: Flags) -> Result<(), Box<dyn std::error::Error>> {
+    let threads = match flags.optional::<usize>("threads")? {
+        Some(threads) => threads.max(1),
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+    let epoch = flags.optional("epoch")?.unwrap_or(0);
+    let duration = match flags.take("duration") {
+        Some(text) => parse_duration(&text)
+            .ok_or_else(|| usage_error(format!("invalid --duration {text:?}")))?,
+        None => Duration::from_secs(10),
+    };
+    let json = flags.switch("json");
+    let chain = flags.chain()?;
+    flags.finish()?;
+    if epoch >= MAX_EPOCH {
+        return Err(usage_error(format!(
+            "epoch {epoch} is past the last supported epoch {}",
+            MAX_EPOCH - 1
+        )));
+    }
+
+    let started = Instant::now();
+    let seed = seed_hash(epoch);
+    let seed_hash_time = started.elapsed();
+
+    let started = Instant::now();
+    let cache = make_cache(chain.cache_size(epoch), &seed);
+    let cache_time = started.elapsed();
+
+    // Wrapping the cache computes the DAG words ProgPoW keeps cached.
+    let started = Instant::now();
+    let cache = EpochCache::new(epoch, cache, chain.dataset_size(epoch));
+    let c_dag_time = started.elapsed();
+
+    let header_hash = [0x5a; 32];
+    let started = Instant::now();
+    let mut sink = 0;
+    for nonce in 0..SEED_PASSES {
+        sink ^= progpow_seed(&header_hash, nonce);
+    }
+    std::hint::black_box(sink);
+    let seed_pass_time = started.elapsed();
+
+    // Verification reads DAG items from the light cache, as here.
+    let block_number = epoch * chain.epoch_length;
+    let next_nonce = AtomicU64::new(0);
+    let started = Instant::now();
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let first = next_nonce.fetch_add(NONCES_PER_CHECK, Ordering::Relaxed);
+                for nonce in first..first + NONCES_PER_CHECK {
+                    std::hint::black_box(chain.hash(&cache, &header_hash, block_number, nonce));
+                }
+                if started.elapsed() >= duration {
+                    return;
+                }
+            });
+        }
+    });
+    let hashing = started.elapsed();
+    let hashes = next_nonce.into_inner();
+
+    let report = Report {
+        chain: chain.name,
+        epoch,
+        threads,
+        seed_hash: seed_hash_time,
+        cache: cache_time,
+        c_dag: c_dag_time,
+        seed_pass: seed_pass_time,
+        hashes,
+        hashing,
+    };
+    if json {
+        report.print_json();
+    } else {
+        report.print();
+    }
+    Ok(())
+}
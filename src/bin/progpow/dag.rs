@@ -0,0 +1,140 @@
+//! `progpow dag`: writes an epoch's light cache, and optionally its full
+//! dataset, to disk.
+//!
+//! Files are named as go-ethereum names them, `cache-R23-` or `full-R23-`
+//! and the first 8 bytes of the epoch's seed hash in hex. The cache is raw
+//! little-endian words and the dataset starts with go-ethereum's dump magic,
+//! as the C API saves them. Files already present are checked, the cache
+//! in full and the dataset by a sample of its items, and kept if they
+//! match, so rerunning the command on a warm directory is cheap.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use progpow_verifier::ethash::buffer::GETH_DUMP_MAGIC;
+use progpow_verifier::ethash::cache::{
+    cache_file_name, dataset_file_name, seed_hash, CacheBuilder, MAX_EPOCH,
+};
+use progpow_verifier::ethash::dataset::{calc_dataset_item, generate_dataset_chunks};
+
+use crate::args::{usage_error, Flags};
+use crate::progress::Progress;
+
+/// Cache rows hashed between progress updates.
+const CACHE_ROWS_PER_STEP: usize = 1 << 12;
+
+/// Dataset items generated between progress updates.
+const DATASET_ITEMS_PER_CHUNK: usize = 1 << 16;
+
+/// Dataset items compared with the cache when checking a dataset file.
+const DATASET_SAMPLES: u64 = 4096;
+
+/// Writes the files for the epoch in `flags`.
+pub fn run(mut flags: Flags) -> Result<(), Box<dyn std::error::Error>> {
+    let epoch: u64 = flags.required("epoch")?;
+    let dir: PathBuf = flags.required("dir")?;
+    let full = flags.switch("full");
+    let chain = flags.chain()?;
+    flags.finish()?;
+    if epoch >= MAX_EPOCH {
+        return Err(usage_error(format!(
+            "epoch {epoch} is past the last supported epoch {}",
+            MAX_EPOCH - 1
+        )));
+    }
+
+    fs::create_dir_all(&dir)?;
+    let seed = seed_hash(epoch);
+
+    let mut builder = CacheBuilder::from_seed(chain.cache_size(epoch), &seed);
+    let mut progress = Progress::new(format!("cache   epoch {epoch}"));
+    while !builder.step(CACHE_ROWS_PER_STEP) {
+        progress.set(builder.progress());
+    }
+    progress.finish();
+    let cache = builder.finish();
+    let cache_bytes: Vec<u8> = cache.iter().flat_map(|word| word.to_le_bytes()).collect();
+
+    let cache_path = dir.join(cache_file_name(&seed));
+    if fs::read(&cache_path).is_ok_and(|bytes| bytes == cache_bytes) {
+        println!("{}: verified", cache_path.display());
+    } else {
+        write_atomically(&cache_path, |out| out.write_all(&cache_bytes))?;
+        if fs::read(&cache_path)? != cache_bytes {
+            return Err(format!("{} does not read back as written", cache_path.display()).into());
+        }
+        println!("{}: generated", cache_path.display());
+    }
+
+    if !full {
+        return Ok(());
+    }
+    let size = chain.dataset_size(epoch);
+    let full_path = dir.join(dataset_file_name(&seed));
+    if check_dataset(&full_path, &cache, size).is_ok() {
+        println!("{}: verified", full_path.display());
+        return Ok(());
+    }
+    let mut progress = Progress::new(format!("dataset epoch {epoch}"));
+    write_atomically(&full_path, |out| {
+        out.write_all(&GETH_DUMP_MAGIC)?;
+        let mut written = 0;
+        generate_dataset_chunks(&cache, size, DATASET_ITEMS_PER_CHUNK, |chunk| {
+            let bytes: Vec<u8> = chunk.iter().flat_map(|word| word.to_le_bytes()).collect();
+            out.write_all(&bytes)?;
+            written += bytes.len() as u64;
+            progress.set(written as f64 / size as f64);
+            Ok(())
+        })
+    })?;
+    progress.finish();
+    check_dataset(&full_path, &cache, size)
+        .map_err(|error| format!("{}: {error}", full_path.display()))?;
+    println!("{}: generated", full_path.display());
+    Ok(())
+}
+
+/// Writes a file through `write` to a temporary path next to `path` and
+/// renames it into place, so an interrupted run leaves no partial file.
+fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let partial = path.with_extension("partial");
+    let mut out = BufWriter::new(File::create(&partial)?);
+    write(&mut out)?;
+    out.into_inner()
+        .map_err(|error| error.into_error())?
+        .sync_all()?;
+    fs::rename(&partial, path)
+}
+
+/// Checks that the dataset file at `path` has the magic and size of a
+/// dataset of `size` bytes, and that evenly spread items and the last one
+/// match the ones computed from `cache`.
+fn check_dataset(path: &Path, cache: &[u32], size: u64) -> io::Result<()> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let mut file = File::open(path)?;
+    if file.metadata()?.len() != GETH_DUMP_MAGIC.len() as u64 + size {
+        return Err(invalid("the dataset file has the wrong size"));
+    }
+    let mut magic = [0; 8];
+    file.read_exact(&mut magic)?;
+    if magic != GETH_DUMP_MAGIC {
+        return Err(invalid(
+            "the dataset file does not start with the dump magic",
+        ));
+    }
+    let items = size / 64;
+    let step = (items / DATASET_SAMPLES).max(1);
+    let mut item = [0; 64];
+    for index in (0..items).step_by(step as usize).chain([items - 1]) {
+        file.seek(SeekFrom::Start(GETH_DUMP_MAGIC.len() as u64 + index * 64))?;
+        file.read_exact(&mut item)?;
+        if item != calc_dataset_item(cache, index as u32) {
+            return Err(invalid(&format!("dataset item {index} is wrong")));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,38 @@
+//! `progpow epoch`: prints the epoch parameters of a block.
+
+use progpow_verifier::ethash::cache::seed_hash;
+use progpow_verifier::progpow::program::program_digest;
+
+use crate::args::{to_hex, Flags};
+
+/// Prints the epoch, seed hash, sizes, program period and program digest of
+/// the block in `flags`.
+pub fn run(mut flags: Flags) -> Result<(), Box<dyn std::error::Error>> {
+    let block_number: u64 = flags.required("block")?;
+    let chain = flags.chain()?;
+    flags.finish()?;
+
+    let epoch = chain.epoch(block_number);
+    let period = block_number / chain.period_length;
+    println!("chain:        {}", chain.name);
+    println!("block:        {block_number}");
+    println!("epoch:        {epoch}");
+    println!("seed hash:    {}", to_hex(&seed_hash(epoch)));
+    println!("cache size:   {} bytes", chain.cache_size(epoch));
+    println!("dataset size: {} bytes", chain.dataset_size(epoch));
+    // go-ethereum's ProgPoW never changes programs: its period is u64::MAX.
+    match (period + 1).checked_mul(chain.period_length) {
+        Some(end) if chain.period_length != u64::MAX => println!(
+            "period:       {period} (blocks {} to {})",
+            period * chain.period_length,
+            end - 1
+        ),
+        _ => println!("period:       {period} (one program for every block)"),
+    }
+    println!("program:      {}", to_hex(&program_digest(period)));
+    println!(
+        "next epoch:   block {}",
+        (epoch + 1).saturating_mul(chain.epoch_length)
+    );
+    Ok(())
+}
@@ -0,0 +1,44 @@
+//! `progpow export-trace`: writes every step of one hash as columnar JSON.
+//!
+//! Hashes `--header-hash` and `--nonce` at `--block` keeping each cache
+//! load, math operation and DAG merge with the registers around it, and
+//! writes the trace to `--out` or standard output in the format documented
+//! in `progpow::execution`.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use crate::args::{parse_hash, parse_nonce, to_hex, Flags};
+
+/// Writes the execution trace of the hash in `flags`.
+pub fn run(mut flags: Flags) -> Result<(), Box<dyn std::error::Error>> {
+    let header_hash = flags.required_with("header-hash", parse_hash)?;
+    let nonce = flags.required_with("nonce", parse_nonce)?;
+    let block_number: u64 = flags.required("block")?;
+    let out: Option<PathBuf> = flags.optional("out")?;
+    let chain = flags.chain()?;
+    flags.finish()?;
+
+    let cache = chain.cache_manager(1).get(chain.epoch(block_number));
+    let trace = chain.execution_trace(&cache, &header_hash, block_number, nonce);
+    match out {
+        Some(path) => {
+            let mut file = BufWriter::new(File::create(&path)?);
+            trace.write_json(&mut file)?;
+            file.flush()?;
+            eprintln!(
+                "{}: {} steps, mix hash {}",
+                path.display(),
+                trace.steps.len(),
+                to_hex(&trace.mix_hash)
+            );
+        }
+        None => {
+            let mut stdout = BufWriter::new(io::stdout().lock());
+            trace.write_json(&mut stdout)?;
+            stdout.flush()?;
+        }
+    }
+    Ok(())
+}
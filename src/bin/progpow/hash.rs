@@ -0,0 +1,24 @@
+//! `progpow hash`: prints the hashes of a header hash and nonce, with
+//! `--trace` after the intermediate state they come from.
+
+use crate::args::{parse_hash, parse_nonce, to_hex, Flags};
+use crate::trace;
+
+/// Hashes the header hash and nonce in `flags` on the chosen chain.
+pub fn run(mut flags: Flags) -> Result<(), Box<dyn std::error::Error>> {
+    let header_hash = flags.required_with("header-hash", parse_hash)?;
+    let nonce = flags.required_with("nonce", parse_nonce)?;
+    let block_number: u64 = flags.required("block")?;
+    let show_trace = flags.switch("trace");
+    let chain = flags.chain()?;
+    flags.finish()?;
+
+    let cache = chain.cache_manager(1).get(chain.epoch(block_number));
+    if show_trace {
+        trace::print(&chain, &cache, &header_hash, block_number, nonce);
+    }
+    let (mix_hash, final_hash) = chain.hash(&cache, &header_hash, block_number, nonce);
+    println!("mix hash:   {}", to_hex(&mix_hash));
+    println!("final hash: {}", to_hex(&final_hash));
+    Ok(())
+}
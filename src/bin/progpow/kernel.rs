@@ -0,0 +1,62 @@
+//! `progpow kernel`: prints the generated GPU source of one period's program,
+//! or the program as pseudocode.
+
+use progpow_verifier::basic_algorithm::PROGPOW_LANES;
+use progpow_verifier::kernelgen::cuda::cuda_kernel;
+use progpow_verifier::kernelgen::opencl::opencl_kernel;
+use progpow_verifier::kernelgen::source::KernelConfig;
+use progpow_verifier::kernelgen::wgsl::wgsl_kernel;
+use progpow_verifier::progpow::describe::{describe_program, DescribeConfig};
+
+use crate::args::{usage_error, Flags};
+
+/// Prints the kernel for the period, or the block's period, in `flags`.
+///
+/// With `--block` the dataset size of the block's epoch is written into the
+/// source as `PROGPOW_DAG_ELEMENTS`; with `--period` it is left to the host's
+/// compiler options, as the reference miners do.
+pub fn run(mut flags: Flags) -> Result<(), Box<dyn std::error::Error>> {
+    let period: Option<u64> = flags.optional("period")?;
+    let block_number: Option<u64> = flags.optional("block")?;
+    let target = flags.take("target").unwrap_or_else(|| "opencl".to_string());
+    let group_size: Option<u32> = flags.optional("group-size")?;
+    let chain = flags.chain()?;
+    flags.finish()?;
+
+    let (period, mut config) = match (period, block_number) {
+        (Some(period), None) => (period, KernelConfig::default()),
+        (None, Some(block_number)) => (
+            block_number / chain.period_length,
+            KernelConfig::for_dataset_size(chain.dataset_size(chain.epoch(block_number))),
+        ),
+        _ => return Err(usage_error("give one of --period and --block")),
+    };
+    if let Some(group_size) = group_size {
+        if group_size == 0 || group_size % PROGPOW_LANES as u32 != 0 {
+            return Err(usage_error(format!(
+                "--group-size must be a multiple of {PROGPOW_LANES}"
+            )));
+        }
+        config.group_size = group_size;
+    }
+
+    let source = match target.as_str() {
+        "opencl" => opencl_kernel(period, &config),
+        "cuda" => cuda_kernel(period, &config),
+        "wgsl" => wgsl_kernel(period, &config),
+        "pseudocode" => describe_program(
+            period,
+            &DescribeConfig {
+                selectors: true,
+                line_numbers: false,
+            },
+        ),
+        _ => {
+            return Err(usage_error(format!(
+                "unknown --target {target:?}, expected opencl, cuda, wgsl or pseudocode"
+            )))
+        }
+    };
+    print!("{source}");
+    Ok(())
+}
@@ -0,0 +1,233 @@
+//! `progpow`: verifies and inspects ProgPoW seals from the command line.
+//!
+//! ```text
+//! progpow verify --header-hash HEX --nonce HEX --mix-hash HEX --block N --difficulty HEX [--trace] [--chain C]
+//! progpow hash --header-hash HEX --nonce HEX --block N [--trace] [--chain C]
+//! progpow dag --epoch N --dir PATH [--full] [--chain C]
+//! progpow epoch --block N [--chain C]
+//! progpow bench [--threads N] [--epoch E] [--duration 30s] [--json] [--chain C]
+//! progpow kernel (--period P | --block N) [--target opencl|cuda|wgsl|pseudocode] [--group-size N] [--chain C]
+//! progpow mine (--rpc URL | --stratum URL --login L) [--threads N] [--report 10s]
+//! progpow audit --from A --to B --rpc URL [--out FILE] [--format csv|json] [--chain C]
+//! progpow vectors [--count N] [--seed S] [--epoch E | --size BYTES] [--out FILE]
+//! progpow export-trace --header-hash HEX --nonce HEX --block N [--out FILE] [--chain C]
+//! progpow trace-diff --log FILE --header-hash HEX --nonce HEX --block N [--chain C]
+//! progpow stats --from P --to Q [--json]
+//! progpow sweep [--lanes L,…] [--regs R,…] [--dag-loads D,…] [--cnt-cache C,…] [--cnt-math M,…] [--cnt-dag N,…] [--hashes N] [--epoch E] [--json] [--chain C]
+//! ```
+//!
+//! `verify` recomputes a seal with the light cache of its block's epoch,
+//! generated on demand, and exits with status 1 if the mix hash differs or
+//! the final hash does not meet the difficulty. `hash` prints the mix hash
+//! and final hash, for comparing other implementations against this one.
+//! `dag` writes an epoch's light cache, and with `--full` its dataset, to
+//! `--dir`, verifying files already there instead of regenerating them.
+//! `epoch` prints the epoch, seed hash, cache and dataset sizes, program
+//! period and program digest of a block. `bench` times cache generation, the seed pass and
+//! light hashing on `--threads` threads (default: every core) for
+//! `--duration` (default 10s), for people or with `--json` as one object.
+//! `kernel` prints the generated OpenCL, CUDA or WGSL source of a period's
+//! random program, by default OpenCL, or with `--target pseudocode` the
+//! program as readable pseudocode with its raw selectors.
+//! `mine`, built with the `net` feature, mines on the CPU for work from a
+//! node's `eth_getWork` or an eth-proxy stratum pool, printing the hashrate
+//! every `--report`. `audit`, also built with `net`, verifies the blocks
+//! `--from` to `--to` of a node and writes a CSV or JSON row for each,
+//! exiting with status 1 if any failed. `vectors`, built with the
+//! `vectors` feature, writes a reproducible test-vector corpus with every
+//! intermediate value. `--trace` makes `hash` and `verify` print the seed,
+//! the lanes' initial mixes, each lane's digest after every loop, the lane
+//! results and the reduced result first. `export-trace` writes every cache
+//! load, math operation and DAG merge of one hash, with the registers before
+//! and after it, as columnar JSON to `--out` or standard output. `trace-diff` reads another
+//! implementation's trace from `--log`, in that format or as JSON, and
+//! prints the first stage, lane and register where it differs from this
+//! one's, exiting with status 1. `stats` counts the math and merge
+//! operations, registers and rotations the programs of periods `--from` to
+//! `--to` choose, as text histograms or with `--json` as one object.
+//! `sweep` times light hashing with every combination of the listed loop
+//! parameters, each by default the built-in one, against the built-in shape.
+//! `--chain` is `ethereum` (the default) or `ravencoin`, or with the
+//! `chain-config` feature a chain config file. Hex values may start with
+//! `0x`. Usage errors exit with status 2.
+
+mod args;
+#[cfg(feature = "net")]
+mod audit;
+mod bench;
+mod dag;
+mod epoch;
+mod export_trace;
+mod hash;
+mod kernel;
+#[cfg(feature = "net")]
+mod mine;
+mod progress;
+mod stats;
+mod sweep;
+mod trace;
+mod trace_diff;
+#[cfg(feature = "vectors")]
+mod vectors;
+mod verify;
+
+use std::process;
+
+use args::{Flags, UsageError};
+
+/// The usage message.
+const USAGE: &str = "\
+usage: progpow verify --header-hash HEX --nonce HEX --mix-hash HEX --block N --difficulty HEX [--trace] [--chain C]
+       progpow hash --header-hash HEX --nonce HEX --block N [--trace] [--chain C]
+       progpow dag --epoch N --dir PATH [--full] [--chain C]
+       progpow epoch --block N [--chain C]
+       progpow bench [--threads N] [--epoch E] [--duration 30s] [--json] [--chain C]
+       progpow kernel (--period P | --block N) [--target opencl|cuda|wgsl|pseudocode] [--group-size N] [--chain C]
+       progpow mine (--rpc URL | --stratum URL --login L) [--threads N] [--report 10s]
+       progpow audit --from A --to B --rpc URL [--out FILE] [--format csv|json] [--chain C]
+       progpow vectors [--count N] [--seed S] [--epoch E | --size BYTES] [--out FILE]
+       progpow export-trace --header-hash HEX --nonce HEX --block N [--out FILE] [--chain C]
+       progpow trace-diff --log FILE --header-hash HEX --nonce HEX --block N [--chain C]
+       progpow stats --from P --to Q [--json]
+       progpow sweep [--lanes L,…] [--regs R,…] [--dag-loads D,…] [--cnt-cache C,…] [--cnt-math M,…] [--cnt-dag N,…] [--hashes N] [--epoch E] [--json] [--chain C]";
+
+/// Runs `subcommand` with the arguments after it.
+fn run(
+    subcommand: &str,
+    args: impl Iterator<Item = String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match subcommand {
+        #[cfg(feature = "net")]
+        "audit" => audit::run(Flags::parse(args, &[])?),
+        #[cfg(not(feature = "net"))]
+        "audit" => Err("audit needs progpow built with the net feature".into()),
+        "bench" => bench::run(Flags::parse(args, &["json"])?),
+        "dag" => dag::run(Flags::parse(args, &["full"])?),
+        "epoch" => epoch::run(Flags::parse(args, &[])?),
+        "export-trace" => export_trace::run(Flags::parse(args, &[])?),
+        "hash" => hash::run(Flags::parse(args, &["trace"])?),
+        "kernel" => kernel::run(Flags::parse(args, &[])?),
+        #[cfg(feature = "net")]
+        "mine" => mine::run(Flags::parse(args, &[])?),
+        #[cfg(not(feature = "net"))]
+        "mine" => Err("mine needs progpow built with the net feature".into()),
+        "stats" => stats::run(Flags::parse(args, &["json"])?),
+        "sweep" => sweep::run(Flags::parse(args, &["json"])?),
+        "trace-diff" => trace_diff::run(Flags::parse(args, &[])?),
+        #[cfg(feature = "vectors")]
+        "vectors" => vectors::run(Flags::parse(args, &[])?),
+        #[cfg(not(feature = "vectors"))]
+        "vectors" => Err("vectors needs progpow built with the vectors feature".into()),
+        "verify" => verify::run(Flags::parse(args, &["trace"])?),
+        _ => Err(args::usage_error(format!("unknown command {subcommand:?}"))),
+    }
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let Some(subcommand) = args.next() else {
+        eprintln!("{USAGE}");
+        process::exit(2)
+    };
+    if let Err(error) = run(&subcommand, args) {
+        eprintln!("progpow: {error}");
+        if error.is::<UsageError>() {
+            eprintln!("{USAGE}");
+            process::exit(2)
+        }
+        process::exit(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `subcommand` with `args` and returns its usage error message.
+    fn rejection(subcommand: &str, args: &[&str]) -> String {
+        let error =
+            run(subcommand, args.iter().map(|arg| arg.to_string())).expect_err("a usage error");
+        assert!(error.is::<UsageError>(), "{subcommand}: {error}");
+        error.to_string()
+    }
+
+    #[test]
+    fn test_subcommands_reject_bad_flags() {
+        let hash = "11".repeat(32);
+        let cases: &[(&str, &[&str], &str)] = &[
+            (
+                "bench",
+                &["--duration", "99999999999999999999"],
+                "invalid --duration",
+            ),
+            ("bench", &["--threads", "many"], "invalid --threads"),
+            ("dag", &["--dir", "/tmp"], "--epoch is required"),
+            (
+                "epoch",
+                &["--block", "1", "--bogus", "2"],
+                "unknown flag --bogus",
+            ),
+            (
+                "export-trace",
+                &["--header-hash", "00", "--nonce", "1", "--block", "1"],
+                "invalid --header-hash",
+            ),
+            (
+                "hash",
+                &[
+                    "--header-hash",
+                    &hash,
+                    "--nonce",
+                    "10000000000000000",
+                    "--block",
+                    "1",
+                ],
+                "invalid --nonce",
+            ),
+            (
+                "kernel",
+                &["--period", "1", "--chain", "dogecoin"],
+                "unknown chain",
+            ),
+            ("stats", &["--from", "1"], "--to is required"),
+            ("sweep", &["--lanes", "x"], "invalid --lanes"),
+            ("trace-diff", &["--header-hash", &hash], "--log is required"),
+            (
+                "verify",
+                &[
+                    "--header-hash",
+                    &hash,
+                    "--nonce",
+                    "1",
+                    "--mix-hash",
+                    &hash,
+                    "--block",
+                    "1",
+                    "--difficulty",
+                    "",
+                ],
+                "invalid --difficulty",
+            ),
+            (
+                "verify",
+                &["--header-hash", &hash, "--nonce", "1", "--trace"],
+                "--mix-hash is required",
+            ),
+            ("nonsense", &[], "unknown command"),
+            #[cfg(feature = "net")]
+            ("audit", &["--from", "1", "--to", "2"], "--rpc is required"),
+            #[cfg(feature = "net")]
+            (
+                "mine",
+                &["--report", "9999999999999999h"],
+                "invalid --report",
+            ),
+            #[cfg(feature = "vectors")]
+            ("vectors", &["--count", "-1"], "invalid --count"),
+        ];
+        for (subcommand, args, expected) in cases {
+            let message = rejection(subcommand, args);
+            assert!(message.starts_with(expected), "{subcommand}: {message}");
+        }
+    }
+}
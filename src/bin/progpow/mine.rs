@@ -0,0 +1,201 @@
+//! `progpow mine`: a reference CPU miner for development networks.
+//!
+//! Work comes from a node's `eth_getWork`, polled every second, or from an
+//! eth-proxy stratum pool, which pushes it. The light cache of the work's
+//! epoch stands in for the dataset, so the miner starts in seconds but
+//! hashes slowly: it is meant for networks of low difficulty.
+
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crossbeam_channel::RecvTimeoutError;
+use progpow_verifier::engine::Work;
+use progpow_verifier::ethash::buffer::DagBuffer;
+use progpow_verifier::ethash::cache::epoch;
+use progpow_verifier::ethash::manager::{CacheManager, EpochCache};
+use progpow_verifier::hashrate::HashrateMeter;
+use progpow_verifier::miner::backend::{self, Miner};
+use progpow_verifier::miner::cpu::CpuMiner;
+use progpow_verifier::miner::pipeline::{Found, MinerPipeline, PipelineConfig};
+use progpow_verifier::rpc::{get_work, submit_work};
+use progpow_verifier::stratum::StratumClient;
+
+use crate::args::{parse_duration, to_hex, usage_error, Flags};
+
+/// How often a node is asked for new work.
+const RPC_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Nonces in each batch a hashing worker takes.
+const NONCES_PER_BATCH: u64 = 32;
+
+/// How long the miner waits for a solution before polling for new work.
+const SOLUTION_WAIT: Duration = Duration::from_millis(100);
+
+/// Where work comes from and seals go.
+enum Source {
+    Rpc {
+        url: String,
+        polled: Option<Instant>,
+    },
+    Stratum(StratumClient),
+}
+
+impl Source {
+    /// Returns new work if the source has any.
+    fn poll(&mut self) -> Result<Option<Work>, Box<dyn std::error::Error>> {
+        match self {
+            Source::Rpc { url, polled } => {
+                if polled.is_some_and(|polled| polled.elapsed() < RPC_POLL_INTERVAL) {
+                    return Ok(None);
+                }
+                *polled = Some(Instant::now());
+                Ok(Some(get_work(url)?))
+            }
+            Source::Stratum(client) => Ok(client.poll_work()?),
+        }
+    }
+
+    /// Submits a seal, returning whether it was accepted.
+    fn submit(
+        &mut self,
+        nonce: u64,
+        header_hash: &[u8; 32],
+        mix_hash: &[u8; 32],
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        Ok(match self {
+            Source::Rpc { url, .. } => submit_work(url, nonce, header_hash, mix_hash)?,
+            Source::Stratum(client) => client.submit_work(nonce, header_hash, mix_hash)?,
+        })
+    }
+}
+
+/// A CPU miner over the light cache of one epoch.
+type LightMiner = CpuMiner<Box<dyn Fn(u32) -> Vec<u8> + Send + Sync>>;
+
+/// Builds a miner over `cache`.
+fn light_miner(cache: Arc<EpochCache>, threads: usize) -> LightMiner {
+    let (size, c_dag) = (cache.size(), cache.c_dag());
+    CpuMiner::new(
+        size,
+        c_dag,
+        Box::new(move |index| cache.lookup(index)),
+        threads,
+    )
+}
+
+/// Mines until interrupted or the work source fails.
+pub fn run(mut flags: Flags) -> Result<(), Box<dyn std::error::Error>> {
+    let rpc = flags.take("rpc");
+    let stratum = flags.take("stratum");
+    let login = flags.take("login");
+    let threads = match flags.optional::<usize>("threads")? {
+        Some(threads) => threads.max(1),
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+    let report_every = match flags.take("report") {
+        Some(text) => parse_duration(&text)
+            .ok_or_else(|| usage_error(format!("invalid --report {text:?}")))?,
+        None => Duration::from_secs(10),
+    };
+    flags.finish()?;
+
+    let mut source = match (rpc, stratum) {
+        (Some(url), None) => Source::Rpc { url, polled: None },
+        (None, Some(url)) => {
+            let login = login.ok_or_else(|| usage_error("--stratum needs --login"))?;
+            let mut client = StratumClient::connect(&url, &login)?;
+            let work = client.get_work()?;
+            let mut source = Source::Stratum(client);
+            return mine(&mut source, work, threads, report_every);
+        }
+        _ => return Err(usage_error("give one of --rpc and --stratum")),
+    };
+    let work = loop {
+        if let Some(work) = source.poll()? {
+            break work;
+        }
+        thread::sleep(RPC_POLL_INTERVAL / 4);
+    };
+    mine(&mut source, work, threads, report_every)
+}
+
+/// Searches nonces for `work` and the work that follows it.
+fn mine(
+    source: &mut Source,
+    mut work: Work,
+    threads: usize,
+    report_every: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let caches = CacheManager::new(2);
+    // Start from a different nonce on every run, so two instances mining
+    // the same work do not repeat each other.
+    let first_nonce = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+        >> 1;
+    let config = PipelineConfig {
+        workers: threads,
+        batch_nonces: NONCES_PER_BATCH,
+        first_nonce,
+    };
+    // The pipeline runs one hashing worker per thread, so each miner hashes
+    // on the worker's thread alone; it is rebuilt when the epoch changes.
+    let mut held: Option<(u64, Arc<dyn Miner>)> = None;
+    let pipeline = MinerPipeline::start(
+        config,
+        Arc::new(HashrateMeter::new(Duration::from_secs(60))),
+        move |job| match &held {
+            Some((epoch_held, miner)) if *epoch_held == epoch(job.block_number) => miner.clone(),
+            _ => {
+                let cache = caches.for_block(job.block_number);
+                let miner: Arc<dyn Miner> = Arc::new(light_miner(cache, 1));
+                held = Some((epoch(job.block_number), miner.clone()));
+                miner
+            }
+        },
+    );
+    let mut reported = Instant::now();
+    println!("mining block {} with {threads} threads", work.block_number);
+    pipeline.push_work(job(&work));
+
+    loop {
+        if let Some(next) = source.poll()? {
+            if next.header_hash != work.header_hash {
+                println!("mining block {}", next.block_number);
+                pipeline.push_work(job(&next));
+                work = next;
+            }
+        }
+
+        match pipeline.solutions().recv_timeout(SOLUTION_WAIT) {
+            Ok(Found { job, solution }) => {
+                let mix_hash: [u8; 32] = solution.mix_hash.as_slice().try_into()?;
+                let accepted = source.submit(solution.nonce, &job.work.header_hash, &mix_hash)?;
+                println!(
+                    "{} nonce {:#018x} for block {} (final hash {})",
+                    if accepted { "accepted" } else { "rejected" },
+                    solution.nonce,
+                    job.work.block_number,
+                    to_hex(&solution.final_hash)
+                );
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Err("the miner stopped".into()),
+        }
+        if reported.elapsed() >= report_every {
+            reported = Instant::now();
+            let meter = pipeline.meter();
+            println!("{:.1} H/s, {} hashes in total", meter.rate(), meter.total());
+        }
+    }
+}
+
+/// Returns the pipeline's work for `work`.
+fn job(work: &Work) -> backend::Work {
+    backend::Work {
+        header_hash: work.header_hash,
+        block_number: work.block_number,
+        boundary: work.boundary,
+    }
+}
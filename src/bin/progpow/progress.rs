@@ -0,0 +1,52 @@
+//! A progress bar on standard error.
+
+use std::io::{IsTerminal, Write};
+
+/// The width of the bar in characters.
+const WIDTH: usize = 40;
+
+/// Draws the progress of one long task, redrawing only when the shown
+/// percentage changes. When standard error is not a terminal only the
+/// finished line is printed, so logs are not filled with redraws.
+pub struct Progress {
+    label: String,
+    shown: Option<usize>,
+    terminal: bool,
+}
+
+impl Progress {
+    /// Starts a bar described by `label`.
+    pub fn new(label: impl Into<String>) -> Self {
+        Progress {
+            label: label.into(),
+            shown: None,
+            terminal: std::io::stderr().is_terminal(),
+        }
+    }
+
+    /// Shows `fraction` of the task, from 0 to 1, as done.
+    pub fn set(&mut self, fraction: f64) {
+        let percent = (fraction.clamp(0.0, 1.0) * 100.0) as usize;
+        if self.terminal && self.shown != Some(percent) {
+            self.shown = Some(percent);
+            let filled = percent * WIDTH / 100;
+            eprint!(
+                "\r{} [{}{}] {percent:3}%",
+                self.label,
+                "#".repeat(filled),
+                " ".repeat(WIDTH - filled)
+            );
+            let _ = std::io::stderr().flush();
+        }
+    }
+
+    /// Shows the task as done and ends the line.
+    pub fn finish(mut self) {
+        if self.terminal {
+            self.set(1.0);
+            eprintln!();
+        } else {
+            eprintln!("{} done", self.label);
+        }
+    }
+}
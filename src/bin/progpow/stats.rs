@@ -0,0 +1,29 @@
+//! `progpow stats`: histograms of the random programs of a range of
+//! periods.
+//!
+//! Prints how often the programs of periods `--from` to `--to` choose each
+//! math and merge operation, register and rotation, as text or with
+//! `--json` as one object; see `progpow::stats`.
+
+use progpow_verifier::progpow::stats::ProgramStats;
+
+use crate::args::{usage_error, Flags};
+
+/// Prints the statistics of the periods in `flags`.
+pub fn run(mut flags: Flags) -> Result<(), Box<dyn std::error::Error>> {
+    let from: u64 = flags.required("from")?;
+    let to: u64 = flags.required("to")?;
+    let json = flags.switch("json");
+    flags.finish()?;
+    if from > to {
+        return Err(usage_error("--from is after --to"));
+    }
+
+    let stats = ProgramStats::for_periods(from..to.saturating_add(1));
+    if json {
+        println!("{}", stats.to_json());
+    } else {
+        print!("{}", stats.render());
+    }
+    Ok(())
+}
@@ -0,0 +1,161 @@
+//! `progpow sweep`: light verification cost under other loop parameters.
+//!
+//! Each of `--lanes`, `--regs`, `--dag-loads`, `--cnt-cache`, `--cnt-math`
+//! and `--cnt-dag` takes a comma-separated list, by default the built-in
+//! value; every combination is timed over `--hashes` hashes with the light
+//! cache of `--epoch` and printed with its cost relative to the built-in
+//! shape, as a table or with `--json` as an array; see `progpow::sweep`.
+
+use progpow_verifier::ethash::cache::{make_cache, seed_hash, MAX_EPOCH};
+use progpow_verifier::ethash::manager::EpochCache;
+use progpow_verifier::progpow::sweep::{measure, Cost, LoopParams};
+
+use crate::args::{usage_error, Flags};
+
+/// Takes `--name` as a comma-separated list, or `default` alone.
+fn list(
+    flags: &mut Flags,
+    name: &str,
+    default: usize,
+) -> Result<Vec<usize>, Box<dyn std::error::Error>> {
+    match flags.take(name) {
+        Some(text) => text
+            .split(',')
+            .map(|value| {
+                value
+                    .trim()
+                    .parse()
+                    .map_err(|_| usage_error(format!("invalid --{name} {value:?}")))
+            })
+            .collect(),
+        None => Ok(vec![default]),
+    }
+}
+
+/// Times every shape described by `flags`.
+pub fn run(mut flags: Flags) -> Result<(), Box<dyn std::error::Error>> {
+    let current = LoopParams::current();
+    let lanes = list(&mut flags, "lanes", current.lanes)?;
+    let regs = list(&mut flags, "regs", current.regs)?;
+    let dag_loads = list(&mut flags, "dag-loads", current.dag_loads)?;
+    let cnt_cache = list(&mut flags, "cnt-cache", current.cnt_cache)?;
+    let cnt_math = list(&mut flags, "cnt-math", current.cnt_math)?;
+    let cnt_dag = list(&mut flags, "cnt-dag", current.cnt_dag)?;
+    let hashes: u32 = flags.optional("hashes")?.unwrap_or(20).max(1);
+    let epoch = flags.optional("epoch")?.unwrap_or(0);
+    let json = flags.switch("json");
+    let chain = flags.chain()?;
+    flags.finish()?;
+    if epoch >= MAX_EPOCH {
+        return Err(usage_error(format!(
+            "epoch {epoch} is past the last supported epoch {}",
+            MAX_EPOCH - 1
+        )));
+    }
+
+    let mut configs = Vec::new();
+    for &lanes in &lanes {
+        for &regs in &regs {
+            for &dag_loads in &dag_loads {
+                for &cnt_cache in &cnt_cache {
+                    for &cnt_math in &cnt_math {
+                        for &cnt_dag in &cnt_dag {
+                            let params = LoopParams {
+                                lanes,
+                                regs,
+                                dag_loads,
+                                cnt_cache,
+                                cnt_math,
+                                cnt_dag,
+                            };
+                            params.validate().map_err(|reason| {
+                                usage_error(format!("{}: {reason}", describe(&params)))
+                            })?;
+                            configs.push(params);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let cache = EpochCache::new(
+        epoch,
+        make_cache(chain.cache_size(epoch), &seed_hash(epoch)),
+        chain.dataset_size(epoch),
+    );
+    // One untimed hash first, so the first shape is not charged for a cold
+    // start; the built-in shape is timed again only if it is not listed.
+    measure(&current, &cache, 1);
+    let costs: Vec<Cost> = configs
+        .iter()
+        .map(|params| measure(params, &cache, hashes))
+        .collect();
+    let baseline = match costs.iter().find(|cost| cost.params == current) {
+        Some(cost) => cost.clone(),
+        None => measure(&current, &cache, hashes),
+    };
+
+    let relative =
+        |cost: &Cost| cost.time_per_hash.as_secs_f64() / baseline.time_per_hash.as_secs_f64();
+    if json {
+        let rows: Vec<String> = costs
+            .iter()
+            .map(|cost| {
+                let p = &cost.params;
+                format!(
+                    "{{\"lanes\":{},\"regs\":{},\"dag_loads\":{},\"cnt_cache\":{},\
+                     \"cnt_math\":{},\"cnt_dag\":{},\"ms_per_hash\":{:.3},\"relative\":{:.3},\
+                     \"dag_items_per_hash\":{},\"cache_loads_per_hash\":{},\
+                     \"math_ops_per_hash\":{}}}",
+                    p.lanes,
+                    p.regs,
+                    p.dag_loads,
+                    p.cnt_cache,
+                    p.cnt_math,
+                    p.cnt_dag,
+                    cost.time_per_hash.as_secs_f64() * 1000.0,
+                    relative(cost),
+                    cost.dag_items_per_hash,
+                    cost.cache_loads_per_hash,
+                    cost.math_ops_per_hash
+                )
+            })
+            .collect();
+        println!("[{}]", rows.join(","));
+    } else {
+        println!(
+            "chain {}, epoch {epoch}, {hashes} hashes per shape",
+            chain.name
+        );
+        println!(
+            "{:<48} {:>10} {:>8} {:>10} {:>11} {:>10}",
+            "shape", "ms/hash", "relative", "DAG items", "cache loads", "math ops"
+        );
+        for cost in &costs {
+            println!(
+                "{:<48} {:>10.3} {:>7.2}x {:>10} {:>11} {:>10}",
+                describe(&cost.params),
+                cost.time_per_hash.as_secs_f64() * 1000.0,
+                relative(cost),
+                cost.dag_items_per_hash,
+                cost.cache_loads_per_hash,
+                cost.math_ops_per_hash
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Names a shape by its parameters.
+fn describe(params: &LoopParams) -> String {
+    format!(
+        "lanes {} regs {} loads {} cache {} math {} dag {}",
+        params.lanes,
+        params.regs,
+        params.dag_loads,
+        params.cnt_cache,
+        params.cnt_math,
+        params.cnt_dag
+    )
+}
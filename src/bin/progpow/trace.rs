@@ -0,0 +1,49 @@
+//! `--trace`: prints the intermediate state of one hash.
+//!
+//! The stages are printed in computation order, each word as eight hex
+//! digits the way go-ethereum's `%08x` debug prints show them, one line per
+//! lane, so the output diffs line by line against another implementation's:
+//!
+//! ```text
+//! seed 0x...
+//! fill_mix lane  0: 32 words
+//! loop  0 lane  0: FNV-1a digest of the lane's registers
+//! lane_results: 16 words
+//! result: 8 words, the mix hash
+//! ```
+
+use progpow_verifier::chain::Chain;
+use progpow_verifier::ethash::manager::EpochCache;
+use progpow_verifier::progpow::trace::digest;
+
+/// Prints the trace of hashing `header_hash` with `nonce` at `block_number`
+/// on `chain`, over `cache`.
+pub fn print(
+    chain: &Chain,
+    cache: &EpochCache,
+    header_hash: &[u8; 32],
+    block_number: u64,
+    nonce: u64,
+) {
+    let trace = chain.trace(cache, header_hash, block_number, nonce);
+    println!("seed {:#018x}", trace.seed);
+    for (lane, lane_mix) in trace.fill_mix.iter().enumerate() {
+        println!("fill_mix lane {lane:2}: {}", words(lane_mix));
+    }
+    for (l, mix) in trace.loops.iter().enumerate() {
+        for (lane, lane_mix) in mix.iter().enumerate() {
+            println!("loop {l:2} lane {lane:2}: {:08x}", digest(lane_mix));
+        }
+    }
+    println!("lane_results: {}", words(&trace.lane_results));
+    println!("result: {}", words(&trace.result));
+}
+
+/// Formats `words` as space-separated `%08x` words.
+fn words(words: &[u32]) -> String {
+    words
+        .iter()
+        .map(|word| format!("{word:08x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
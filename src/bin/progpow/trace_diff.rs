@@ -0,0 +1,68 @@
+//! `progpow trace-diff`: compares another implementation's trace of a hash
+//! with this one's.
+//!
+//! `--log` is the other implementation's debug output, holding the trace
+//! lines `--trace` prints (as `progpow::trace` documents them), or with the
+//! `vectors` feature the JSON of a whole `Trace`. The command recomputes the
+//! hash of the same inputs and prints the first stage, lane and register
+//! where the traces differ, exiting with status 1, or how many values
+//! agreed.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use progpow_verifier::progpow::trace::parse_log;
+
+use crate::args::{parse_hash, parse_nonce, usage_error, Flags};
+
+/// Compares the trace in `--log` with this crate's trace of the same hash.
+pub fn run(mut flags: Flags) -> Result<(), Box<dyn std::error::Error>> {
+    let log: PathBuf = flags.required("log")?;
+    let header_hash = flags.required_with("header-hash", parse_hash)?;
+    let nonce = flags.required_with("nonce", parse_nonce)?;
+    let block_number: u64 = flags.required("block")?;
+    let chain = flags.chain()?;
+    flags.finish()?;
+
+    let text = fs::read_to_string(&log)?;
+    let expected = if text.trim_start().starts_with('{') {
+        json_entries(&text)?
+    } else {
+        parse_log(&text)?
+    };
+    if expected.is_empty() {
+        return Err(usage_error(format!(
+            "{} holds no trace lines",
+            log.display()
+        )));
+    }
+
+    let cache = chain.cache_manager(1).get(chain.epoch(block_number));
+    let trace = chain.trace(&cache, &header_hash, block_number, nonce);
+    match trace.first_divergence(&expected) {
+        Some(divergence) => {
+            println!("first divergence: {divergence}");
+            process::exit(1);
+        }
+        None => println!("{} trace entries agree", expected.len()),
+    }
+    Ok(())
+}
+
+/// Reads the entries of a JSON trace.
+#[cfg(feature = "vectors")]
+fn json_entries(
+    text: &str,
+) -> Result<Vec<progpow_verifier::progpow::trace::Entry>, Box<dyn std::error::Error>> {
+    let trace: progpow_verifier::progpow::trace::Trace = serde_json::from_str(text)?;
+    Ok(trace.entries())
+}
+
+/// Rejects a JSON trace, which needs serde.
+#[cfg(not(feature = "vectors"))]
+fn json_entries(
+    _text: &str,
+) -> Result<Vec<progpow_verifier::progpow::trace::Entry>, Box<dyn std::error::Error>> {
+    Err("JSON traces need progpow built with the vectors feature".into())
+}
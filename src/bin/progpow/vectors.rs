@@ -0,0 +1,48 @@
+//! `progpow vectors`: writes a reproducible corpus of test vectors.
+//!
+//! The corpus is the JSON of [`Corpus`]: this build's parameters, the dataset
+//! and, for each input, every intermediate value from the seed to the final
+//! hash. The same `--count`, `--seed` and dataset always give the same file,
+//! so other implementations can pin it in their test suites.
+
+use std::fs;
+use std::path::PathBuf;
+
+use progpow_verifier::ethash::cache::MAX_EPOCH;
+use progpow_verifier::progpow::vectors::{Corpus, Dataset};
+
+use crate::args::{usage_error, Flags};
+
+/// Writes the corpus described by `flags` to `--out`, or to standard output.
+pub fn run(mut flags: Flags) -> Result<(), Box<dyn std::error::Error>> {
+    let count: usize = flags.optional("count")?.unwrap_or(8);
+    let seed: u64 = flags.optional("seed")?.unwrap_or(0);
+    let epoch: Option<u64> = flags.optional("epoch")?;
+    let size: Option<u64> = flags.optional("size")?;
+    let out: Option<PathBuf> = flags.optional("out")?;
+    flags.finish()?;
+
+    let dataset = match (epoch, size) {
+        (Some(epoch), None) if epoch < MAX_EPOCH => Dataset::Ethash { epoch },
+        (Some(epoch), None) => {
+            return Err(usage_error(format!(
+                "epoch {epoch} is past the last supported epoch {}",
+                MAX_EPOCH - 1
+            )))
+        }
+        (None, Some(size)) if size > 0 && size.is_multiple_of(256) => Dataset::Synthetic { size },
+        (None, Some(_)) => return Err(usage_error("--size must be a multiple of 256")),
+        (None, None) => Dataset::Synthetic { size: 1 << 20 },
+        (Some(_), Some(_)) => return Err(usage_error("give at most one of --epoch and --size")),
+    };
+
+    let json = serde_json::to_string_pretty(&Corpus::generate(dataset, count, seed))?;
+    match out {
+        Some(path) => {
+            fs::write(&path, format!("{json}\n"))?;
+            eprintln!("{}: {count} vectors", path.display());
+        }
+        None => println!("{json}"),
+    }
+    Ok(())
+}
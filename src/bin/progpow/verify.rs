@@ -0,0 +1,47 @@
+//! `progpow verify`: checks one seal, with `--trace` printing the
+//! intermediate state of its hash first.
+
+use std::process;
+
+use progpow_verifier::progpow::verify::Seal;
+use progpow_verifier::target::boundary_from_difficulty;
+
+use crate::args::{parse_hash, parse_nonce, parse_u256, to_hex, Flags};
+use crate::trace;
+
+/// Verifies the seal described by `flags`, exiting with status 1 if it is
+/// invalid.
+pub fn run(mut flags: Flags) -> Result<(), Box<dyn std::error::Error>> {
+    let header_hash = flags.required_with("header-hash", parse_hash)?;
+    let nonce = flags.required_with("nonce", parse_nonce)?;
+    let mix_hash = flags.required_with("mix-hash", parse_hash)?;
+    let block_number: u64 = flags.required("block")?;
+    let difficulty = flags.required_with("difficulty", parse_u256)?;
+    let show_trace = flags.switch("trace");
+    let chain = flags.chain()?;
+    flags.finish()?;
+
+    let seal = Seal {
+        header_hash,
+        block_number,
+        nonce,
+        mix_hash,
+        boundary: boundary_from_difficulty(difficulty),
+    };
+    // One seal needs one epoch's cache, generated here and dropped after.
+    let caches = chain.cache_manager(1);
+    if show_trace {
+        let cache = caches.get(chain.epoch(block_number));
+        trace::print(&chain, &cache, &header_hash, block_number, nonce);
+    }
+    match chain.verify_seal(&caches, &seal) {
+        Ok(final_hash) => {
+            println!("valid: final hash {}", to_hex(&final_hash));
+            Ok(())
+        }
+        Err(error) => {
+            println!("invalid: {error}");
+            process::exit(1)
+        }
+    }
+}
@@ -0,0 +1,457 @@
+//! A verification front-end with per-peer and global budgets.
+//!
+//! Every seal a peer sends costs a node one full ProgPoW hash, and a seal from
+//! a far-future epoch costs a cache generation too, so an unguarded node can
+//! be kept busy by anyone. [`BudgetedVerifier`] sits in front of a
+//! [`Verifier`] and settles each seal in order of cost:
+//!
+//! 1. free pre-checks: the seal's epoch is at most
+//!    [`Budget::max_epochs_ahead`] past the head, and its boundary is at least
+//!    [`Budget::min_difficulty`];
+//! 2. a seal the verifier's [`SealMemo`](crate::memo::SealMemo) already holds
+//!    is verified at once, since it costs no hash;
+//! 3. a token from the peer's bucket and one from the global bucket, refilled
+//!    at [`Budget::per_peer`] and [`Budget::global`];
+//! 4. a place among the [`Budget::max_in_flight`] verifications running.
+//!
+//! A seal rejected before step 4 costs no hash, and one rejected by a
+//! pre-check costs the peer no token.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::progpow::verify::{Seal, SealError};
+use crate::target::{difficulty_from_boundary, U256};
+use crate::verifier::Verifier;
+
+/// Peers tracked before the buckets of idle peers, refilled to their burst,
+/// are dropped.
+const PEER_SWEEP: usize = 4096;
+
+/// A token bucket's rate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rate {
+    /// Verifications allowed per second, on average.
+    pub per_second: f64,
+    /// Verifications allowed at once after an idle spell.
+    pub burst: u32,
+}
+
+/// The limits a [`BudgetedVerifier`] enforces.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Budget {
+    /// The rate over every peer, or `None` for no global limit.
+    pub global: Option<Rate>,
+    /// The rate for each peer, or `None` for no per-peer limit.
+    pub per_peer: Option<Rate>,
+    /// Verifications running at once; more are rejected, not queued.
+    pub max_in_flight: usize,
+    /// Epochs past the cache manager's head a seal may be from, or `None`
+    /// for any epoch. A seal before the first head is always let through.
+    pub max_epochs_ahead: Option<u64>,
+    /// The lowest difficulty a seal's boundary may stand for, or `None`.
+    pub min_difficulty: Option<U256>,
+}
+
+impl Default for Budget {
+    /// 500 verifications a second overall and 20 per peer, each with a
+    /// burst of twice that, 64 at once, and seals up to one epoch ahead.
+    fn default() -> Self {
+        Budget {
+            global: Some(Rate {
+                per_second: 500.0,
+                burst: 1000,
+            }),
+            per_peer: Some(Rate {
+                per_second: 20.0,
+                burst: 40,
+            }),
+            max_in_flight: 64,
+            max_epochs_ahead: Some(1),
+            min_difficulty: None,
+        }
+    }
+}
+
+/// Why a [`BudgetedVerifier`] did not accept a seal.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Rejection {
+    /// The seal's epoch is too far past the head.
+    TooFarAhead {
+        /// The seal's epoch.
+        epoch: u64,
+        /// The cache manager's head epoch.
+        head: u64,
+    },
+    /// The seal's boundary is easier than the minimum difficulty.
+    TooEasy,
+    /// The peer has used its budget.
+    PeerBudget,
+    /// The node has used its global budget.
+    GlobalBudget,
+    /// [`Budget::max_in_flight`] verifications are already running.
+    QueueFull,
+    /// The seal was verified and failed.
+    Seal(SealError),
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::TooFarAhead { epoch, head } => {
+                write!(f, "epoch {epoch} is too far past the head epoch {head}")
+            }
+            Rejection::TooEasy => write!(f, "seal boundary is below the minimum difficulty"),
+            Rejection::PeerBudget => write!(f, "peer verification budget exhausted"),
+            Rejection::GlobalBudget => write!(f, "global verification budget exhausted"),
+            Rejection::QueueFull => write!(f, "too many verifications in flight"),
+            Rejection::Seal(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+impl From<SealError> for Rejection {
+    fn from(err: SealError) -> Self {
+        Rejection::Seal(err)
+    }
+}
+
+/// A token bucket.
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Creates a bucket holding its burst.
+    fn full(rate: &Rate, now: Instant) -> Self {
+        Bucket {
+            tokens: rate.burst as f64,
+            updated: now,
+        }
+    }
+
+    /// Adds the tokens earned since the last refill, up to the burst.
+    fn refill(&mut self, rate: &Rate, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate.per_second).min(rate.burst as f64);
+        self.updated = self.updated.max(now);
+    }
+}
+
+/// The buckets of a [`BudgetedVerifier`].
+struct Buckets<P> {
+    global: Option<Bucket>,
+    peers: HashMap<P, Bucket>,
+}
+
+/// What the clones of a [`BudgetedVerifier`] share.
+struct Shared<P> {
+    budget: Budget,
+    buckets: Mutex<Buckets<P>>,
+    in_flight: AtomicUsize,
+}
+
+/// A [`Verifier`] front-end enforcing a [`Budget`] per peer of type `P`.
+///
+/// Like a [`Verifier`], it is a cheap handle to clone into every thread;
+/// clones share the budget.
+pub struct BudgetedVerifier<P> {
+    verifier: Verifier,
+    shared: Arc<Shared<P>>,
+}
+
+impl<P> Clone for BudgetedVerifier<P> {
+    fn clone(&self) -> Self {
+        BudgetedVerifier {
+            verifier: self.verifier.clone(),
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+/// Gives back a place among the verifications in flight when dropped.
+struct InFlight<'a>(&'a AtomicUsize);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl<P: Hash + Eq + Clone> BudgetedVerifier<P> {
+    /// Creates a front-end verifying with `verifier` within `budget`.
+    pub fn new(verifier: Verifier, budget: Budget) -> Self {
+        let now = Instant::now();
+        BudgetedVerifier {
+            verifier,
+            shared: Arc::new(Shared {
+                buckets: Mutex::new(Buckets {
+                    global: budget.global.as_ref().map(|rate| Bucket::full(rate, now)),
+                    peers: HashMap::new(),
+                }),
+                budget,
+                in_flight: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Returns the verifier seals are checked with.
+    pub fn verifier(&self) -> &Verifier {
+        &self.verifier
+    }
+
+    /// Returns the budget enforced.
+    pub fn budget(&self) -> &Budget {
+        &self.shared.budget
+    }
+
+    /// Returns the number of verifications running.
+    pub fn in_flight(&self) -> usize {
+        self.shared.in_flight.load(Ordering::Acquire)
+    }
+
+    /// Drops a peer's bucket, as when it disconnects.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the peer had one.
+    pub fn forget_peer(&self, peer: &P) -> bool {
+        let mut buckets = self.shared.buckets.lock().unwrap();
+        buckets.peers.remove(peer).is_some()
+    }
+
+    /// Runs the free checks of a seal: its epoch and its boundary.
+    pub fn pre_check(&self, seal: &Seal) -> Result<(), Rejection> {
+        let budget = &self.shared.budget;
+        if let Some(ahead) = budget.max_epochs_ahead {
+            let epoch = self.verifier.chain().epoch(seal.block_number);
+            if let Some(head) = self.verifier.caches().head_epoch() {
+                if epoch > head.saturating_add(ahead) {
+                    return Err(Rejection::TooFarAhead { epoch, head });
+                }
+            }
+        }
+        if let Some(minimum) = budget.min_difficulty {
+            if difficulty_from_boundary(&seal.boundary) < minimum {
+                return Err(Rejection::TooEasy);
+            }
+        }
+        Ok(())
+    }
+
+    /// Verifies a seal sent by `peer`, if the budget allows it now.
+    ///
+    /// # Returns
+    ///
+    /// The final hash, or the [`Rejection`] saying which check stopped the
+    /// seal.
+    pub fn verify(&self, peer: &P, seal: &Seal) -> Result<Vec<u8>, Rejection> {
+        self.verify_at(peer, seal, Instant::now())
+    }
+
+    /// Verifies a seal sent by `peer`, refilling the buckets up to `now`.
+    pub fn verify_at(&self, peer: &P, seal: &Seal, now: Instant) -> Result<Vec<u8>, Rejection> {
+        self.pre_check(seal)?;
+        let remembered = self
+            .verifier
+            .memo()
+            .is_some_and(|memo| memo.contains(&seal.header_hash, seal.nonce, seal.block_number));
+        if remembered {
+            return Ok(self.verifier.verify_seal(seal)?);
+        }
+        self.take_tokens(peer, now)?;
+        let in_flight = &self.shared.in_flight;
+        in_flight
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |running| {
+                (running < self.shared.budget.max_in_flight).then_some(running + 1)
+            })
+            .map_err(|_| Rejection::QueueFull)?;
+        let _place = InFlight(in_flight);
+        Ok(self.verifier.verify_seal(seal)?)
+    }
+
+    /// Takes a token from `peer`'s bucket and one from the global bucket,
+    /// or neither.
+    fn take_tokens(&self, peer: &P, now: Instant) -> Result<(), Rejection> {
+        let budget = &self.shared.budget;
+        let mut buckets = self.shared.buckets.lock().unwrap();
+        let buckets = &mut *buckets;
+        if let (Some(rate), Some(global)) = (&budget.global, &mut buckets.global) {
+            global.refill(rate, now);
+            if global.tokens < 1.0 {
+                return Err(Rejection::GlobalBudget);
+            }
+        }
+        if let Some(rate) = &budget.per_peer {
+            if buckets.peers.len() >= PEER_SWEEP && !buckets.peers.contains_key(peer) {
+                buckets.peers.retain(|_, bucket| {
+                    bucket.refill(rate, now);
+                    bucket.tokens < rate.burst as f64
+                });
+            }
+            let bucket = buckets
+                .peers
+                .entry(peer.clone())
+                .or_insert_with(|| Bucket::full(rate, now));
+            bucket.refill(rate, now);
+            if bucket.tokens < 1.0 {
+                return Err(Rejection::PeerBudget);
+            }
+            bucket.tokens -= 1.0;
+        }
+        if let Some(global) = &mut buckets.global {
+            global.tokens -= 1.0;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::chain::Chain;
+    use crate::ethash::cache::EPOCH_LENGTH;
+    use crate::target::boundary_from_difficulty;
+    use crate::testutil::{header_hash, tiny_cache, tiny_cache_manager, valid_seal};
+
+    fn unlimited() -> Budget {
+        Budget {
+            global: None,
+            per_peer: None,
+            max_in_flight: usize::MAX,
+            max_epochs_ahead: None,
+            min_difficulty: None,
+        }
+    }
+
+    #[test]
+    fn test_pre_checks_cost_no_token() {
+        let verifier = Verifier::with_caches(Chain::ethereum(), tiny_cache_manager(2));
+        let budget = Budget {
+            per_peer: Some(Rate {
+                per_second: 1.0,
+                burst: 1,
+            }),
+            max_epochs_ahead: Some(1),
+            min_difficulty: Some(U256::from(2u64)),
+            ..unlimited()
+        };
+        let front = BudgetedVerifier::new(verifier, budget);
+        let seal = valid_seal(&tiny_cache(0), header_hash(0), 5, 9);
+        let mut hard = seal.clone();
+        hard.boundary = boundary_from_difficulty(U256::from(2u64));
+        let mut future = hard.clone();
+        future.block_number = 3 * EPOCH_LENGTH;
+        // Before the first head, any epoch passes.
+        assert!(front.pre_check(&future).is_ok());
+        front.verifier().cache_for_block(5);
+        assert_eq!(
+            front.verify(&"peer", &future),
+            Err(Rejection::TooFarAhead { epoch: 3, head: 0 })
+        );
+        // The test seal's boundary stands for difficulty 1.
+        assert_eq!(front.verify(&"peer", &seal), Err(Rejection::TooEasy));
+
+        // The peer's one token is still there.
+        assert!(matches!(
+            front.verify(&"peer", &hard),
+            Ok(_) | Err(Rejection::Seal(SealError::BoundaryNotMet { .. }))
+        ));
+        assert_eq!(front.verify(&"peer", &hard), Err(Rejection::PeerBudget));
+    }
+
+    #[test]
+    fn test_buckets_refill() {
+        let verifier = Verifier::with_caches(Chain::ethereum(), tiny_cache_manager(1));
+        let budget = Budget {
+            global: Some(Rate {
+                per_second: 1.0,
+                burst: 3,
+            }),
+            per_peer: Some(Rate {
+                per_second: 1.0,
+                burst: 2,
+            }),
+            ..unlimited()
+        };
+        let front = BudgetedVerifier::new(verifier, budget);
+        let seal = valid_seal(&tiny_cache(0), header_hash(0), 5, 9);
+        let start = Instant::now();
+        assert!(front.verify_at(&1, &seal, start).is_ok());
+        assert!(front.verify_at(&1, &seal, start).is_ok());
+        assert_eq!(
+            front.verify_at(&1, &seal, start),
+            Err(Rejection::PeerBudget)
+        );
+        // Another peer spends from the global bucket too.
+        assert!(front.verify_at(&2, &seal, start).is_ok());
+        assert_eq!(
+            front.verify_at(&2, &seal, start),
+            Err(Rejection::GlobalBudget)
+        );
+        let later = start + Duration::from_secs(1);
+        assert!(front.verify_at(&1, &seal, later).is_ok());
+        assert_eq!(
+            front.verify_at(&2, &seal, later),
+            Err(Rejection::GlobalBudget)
+        );
+        assert!(front.forget_peer(&1));
+        assert!(!front.forget_peer(&3));
+        assert_eq!(front.in_flight(), 0);
+
+        // A memoized seal costs no hash, so no token either.
+        let memoized = BudgetedVerifier::new(
+            front.verifier().with_memo(4),
+            Budget {
+                per_peer: Some(Rate {
+                    per_second: 0.0,
+                    burst: 1,
+                }),
+                ..unlimited()
+            },
+        );
+        for _ in 0..3 {
+            assert!(memoized.verify_at(&1, &seal, start).is_ok());
+        }
+        let mut other = seal.clone();
+        other.nonce += 1;
+        assert_eq!(
+            memoized.verify_at(&1, &other, start),
+            Err(Rejection::PeerBudget)
+        );
+    }
+
+    #[test]
+    fn test_in_flight_limit() {
+        let verifier = Verifier::with_caches(Chain::ethereum(), tiny_cache_manager(1));
+        let front = BudgetedVerifier::new(
+            verifier,
+            Budget {
+                max_in_flight: 0,
+                ..unlimited()
+            },
+        );
+        let seal = valid_seal(&tiny_cache(0), header_hash(0), 5, 9);
+        assert_eq!(front.verify(&(), &seal), Err(Rejection::QueueFull));
+        let front = BudgetedVerifier::new(
+            front.verifier().clone(),
+            Budget {
+                max_in_flight: 1,
+                ..unlimited()
+            },
+        );
+        assert!(front.verify(&(), &seal).is_ok());
+        assert!(front.verify(&(), &seal).is_ok());
+        assert_eq!(front.in_flight(), 0);
+    }
+}
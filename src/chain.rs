@@ -0,0 +1,702 @@
+//! ProgPoW-derivative chains described by their parameters.
+//!
+//! ProgPoW, KawPoW and the chains forked from them run the same mix loop
+//! and differ only in a handful of constants: how many blocks a program
+//! lasts, how many blocks an epoch lasts, how fast the cache and DAG grow
+//! and which Keccak-f800 passes surround the loop. A [`Chain`] holds those
+//! constants, so a new derivative is verified without code of its own.
+//! With the `chain-config` feature a chain is loaded from a JSON or TOML
+//! file, and a [`ChainRegistry`] looks chains up by name.
+
+use std::collections::BTreeMap;
+use std::fmt;
+#[cfg(feature = "chain-config")]
+use std::io;
+#[cfg(feature = "chain-config")]
+use std::path::Path;
+
+use crate::basic_algorithm::PROGPOW_PERIOD_LENGTH;
+use crate::ethash::cache::{
+    make_cache, prime_sized, seed_hash, CACHE_BYTES_GROWTH, CACHE_BYTES_INIT, DATASET_BYTES_GROWTH,
+    DATASET_BYTES_INIT, EPOCH_LENGTH, HASH_BYTES, MIX_BYTES,
+};
+use crate::ethash::manager::{CacheManager, EpochCache};
+use crate::keccak::f800long::keccak_f800_long;
+use crate::keccak::f800state::{Padding, RAVENCOIN_KAWPOW};
+use crate::keccak::kawpow::{final_pass, seed_pass};
+use crate::progpow::execution::{execute_at_period, ExecutionTrace};
+use crate::progpow::kawpow::{padded_progpow, KAWPOW_EPOCH_LENGTH, KAWPOW_PERIOD_LENGTH};
+use crate::progpow::progpow::{progpow_from_seed_at_period, progpow_seed};
+use crate::progpow::trace::{trace_at_period, Trace};
+use crate::progpow::verify::{check_seal, Seal, SealError};
+
+/// The Keccak-f800 passes a chain runs around the mix loop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Variant {
+    /// The passes of go-ethereum's ProgPoW 0.9.2: a 64-bit seed from the
+    /// header hash and nonce, and a final pass over the header hash, seed
+    /// and mix hash. The chain's padding is not used.
+    Progpow,
+    /// The passes of ProgPoW 0.9.4, as in KawPoW and FiroPoW: both are
+    /// padded with the chain's words, and the final pass absorbs the whole
+    /// seed state.
+    Padded,
+}
+
+/// The reason a chain definition was rejected.
+#[derive(Debug)]
+pub enum ChainError {
+    /// The configuration file could not be read.
+    #[cfg(feature = "chain-config")]
+    Read(io::Error),
+    /// The configuration is not valid JSON or TOML, or misses a field.
+    Parse(String),
+    /// A parameter is out of range.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "chain-config")]
+            ChainError::Read(error) => write!(f, "failed to read chain config: {error}"),
+            ChainError::Parse(message) => write!(f, "invalid chain config: {message}"),
+            ChainError::Invalid(reason) => write!(f, "invalid chain parameters: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ChainError {}
+
+#[cfg(feature = "chain-config")]
+impl From<io::Error> for ChainError {
+    fn from(error: io::Error) -> Self {
+        ChainError::Read(error)
+    }
+}
+
+/// The parameters of a ProgPoW-derivative chain.
+///
+/// Cache and dataset sizes follow ethash's rule: the size of an epoch is
+/// the largest prime number of rows (64 bytes for the cache, 128 for the
+/// dataset) below `init + growth * epoch`. Seed hashes are ethash's too.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chain {
+    /// The name the chain is registered under.
+    pub name: String,
+    /// The Keccak-f800 passes around the mix loop.
+    pub variant: Variant,
+    /// The number of blocks each program is used for.
+    pub period_length: u64,
+    /// The number of blocks in an epoch.
+    pub epoch_length: u64,
+    /// Bytes in the light cache at epoch 0.
+    pub cache_bytes_init: u64,
+    /// Bytes the light cache grows by per epoch.
+    pub cache_bytes_growth: u64,
+    /// Bytes in the dataset at epoch 0.
+    pub dataset_bytes_init: u64,
+    /// Bytes the dataset grows by per epoch.
+    pub dataset_bytes_growth: u64,
+    /// The words padding both Keccak-f800 passes of a
+    /// [`Variant::Padded`] chain.
+    pub padding: [u32; 15],
+}
+
+impl Chain {
+    /// Returns go-ethereum's ProgPoW 0.9.2 on ethash's epochs.
+    pub fn ethereum() -> Self {
+        Chain {
+            name: "ethereum".to_string(),
+            variant: Variant::Progpow,
+            period_length: PROGPOW_PERIOD_LENGTH,
+            epoch_length: EPOCH_LENGTH,
+            cache_bytes_init: CACHE_BYTES_INIT,
+            cache_bytes_growth: CACHE_BYTES_GROWTH,
+            dataset_bytes_init: DATASET_BYTES_INIT,
+            dataset_bytes_growth: DATASET_BYTES_GROWTH,
+            padding: [0; 15],
+        }
+    }
+
+    /// Returns Ravencoin's KawPoW.
+    pub fn ravencoin() -> Self {
+        Chain {
+            name: "ravencoin".to_string(),
+            variant: Variant::Padded,
+            period_length: KAWPOW_PERIOD_LENGTH,
+            epoch_length: KAWPOW_EPOCH_LENGTH,
+            padding: RAVENCOIN_KAWPOW,
+            ..Self::ethereum()
+        }
+    }
+
+    /// Checks that the parameters describe a chain that can be hashed.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())`, or [`ChainError::Invalid`] if a length is zero or a size
+    /// is not a whole number of rows or too small to hold a prime number of
+    /// them.
+    pub fn validate(&self) -> Result<(), ChainError> {
+        if self.name.is_empty() {
+            return Err(ChainError::Invalid("the name is empty"));
+        }
+        if self.period_length == 0 {
+            return Err(ChainError::Invalid("the period length is zero"));
+        }
+        if self.epoch_length == 0 {
+            return Err(ChainError::Invalid("the epoch length is zero"));
+        }
+        let rows = |init: u64, growth: u64, unit: u64| {
+            init >= 3 * unit && init.is_multiple_of(unit) && growth.is_multiple_of(unit)
+        };
+        if !rows(self.cache_bytes_init, self.cache_bytes_growth, HASH_BYTES) {
+            return Err(ChainError::Invalid(
+                "cache sizes must be whole 64-byte rows, at least 3",
+            ));
+        }
+        if !rows(
+            self.dataset_bytes_init,
+            self.dataset_bytes_growth,
+            MIX_BYTES,
+        ) {
+            return Err(ChainError::Invalid(
+                "dataset sizes must be whole 128-byte mixes, at least 3",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the epoch `block_number` belongs to.
+    pub fn epoch(&self, block_number: u64) -> u64 {
+        block_number / self.epoch_length
+    }
+
+    /// Returns the size in bytes of the light cache for `epoch`.
+    pub fn cache_size(&self, epoch: u64) -> u64 {
+        prime_sized(
+            self.cache_bytes_init + self.cache_bytes_growth * epoch,
+            HASH_BYTES,
+        )
+    }
+
+    /// Returns the size in bytes of the dataset for `epoch`.
+    pub fn dataset_size(&self, epoch: u64) -> u64 {
+        prime_sized(
+            self.dataset_bytes_init + self.dataset_bytes_growth * epoch,
+            MIX_BYTES,
+        )
+    }
+
+    /// Returns a [`CacheManager`] holding up to `capacity` of this chain's
+    /// epoch caches.
+    pub fn cache_manager(&self, capacity: usize) -> CacheManager {
+        let (chain, sizes) = (self.clone(), self.clone());
+        CacheManager::with_generator(capacity, move |epoch| {
+            let cache = make_cache(chain.cache_size(epoch), &seed_hash(epoch));
+            EpochCache::new(epoch, cache, chain.dataset_size(epoch))
+        })
+        .with_sizes(move |epoch| (sizes.cache_size(epoch), sizes.dataset_size(epoch)))
+    }
+
+    /// Computes the `(mix_hash, final_hash)` of a header hash.
+    ///
+    /// # Arguments
+    ///
+    /// * `cache` - The cache of the block's epoch, as
+    ///   [`cache_manager`](Self::cache_manager) generates it.
+    /// * `header_hash` - The 32-byte header hash that is sealed.
+    /// * `block_number` - The block height, which selects the program.
+    /// * `nonce` - The 64-bit nonce.
+    pub fn hash(
+        &self,
+        cache: &EpochCache,
+        header_hash: &[u8; 32],
+        block_number: u64,
+        nonce: u64,
+    ) -> (Vec<u8>, Vec<u8>) {
+        cache.with_dag(|size, c_dag, lookup| {
+            self.hash_with_dag(size, c_dag, lookup, header_hash, block_number, nonce)
+        })
+    }
+
+    /// Computes [`hash`](Self::hash) over any dataset: its size in bytes,
+    /// its cached words and a lookup of its items.
+    pub(crate) fn hash_with_dag(
+        &self,
+        size: u64,
+        c_dag: &[u32],
+        lookup: &dyn Fn(u32) -> Vec<u8>,
+        header_hash: &[u8; 32],
+        block_number: u64,
+        nonce: u64,
+    ) -> (Vec<u8>, Vec<u8>) {
+        match self.variant {
+            Variant::Progpow => progpow_from_seed_at_period(
+                header_hash,
+                progpow_seed(header_hash, nonce),
+                size,
+                block_number / self.period_length,
+                c_dag,
+                lookup,
+            ),
+            Variant::Padded => padded_progpow(
+                Padding::Words(self.padding),
+                self.period_length,
+                header_hash,
+                nonce,
+                size,
+                block_number,
+                c_dag,
+                lookup,
+            ),
+        }
+    }
+
+    /// Computes the hash [`hash`](Self::hash) does, keeping its
+    /// intermediate state; see [`crate::progpow::trace`].
+    pub fn trace(
+        &self,
+        cache: &EpochCache,
+        header_hash: &[u8; 32],
+        block_number: u64,
+        nonce: u64,
+    ) -> Trace {
+        self.run_at_period(
+            cache,
+            header_hash,
+            block_number,
+            nonce,
+            |seed, size, period, c_dag, lookup, final_hash| {
+                trace_at_period(seed, size, period, c_dag, lookup, final_hash)
+            },
+        )
+    }
+
+    /// Computes the hash [`hash`](Self::hash) does, keeping every step;
+    /// see [`crate::progpow::execution`].
+    pub fn execution_trace(
+        &self,
+        cache: &EpochCache,
+        header_hash: &[u8; 32],
+        block_number: u64,
+        nonce: u64,
+    ) -> ExecutionTrace {
+        self.run_at_period(
+            cache,
+            header_hash,
+            block_number,
+            nonce,
+            |seed, size, period, c_dag, lookup, final_hash| {
+                execute_at_period(seed, size, period, c_dag, lookup, final_hash)
+            },
+        )
+    }
+
+    /// Calls `run` with this chain's seed, dataset size, period, cached DAG
+    /// words, lookup and final pass for the hash of `header_hash` and
+    /// `nonce` at `block_number`.
+    fn run_at_period<T>(
+        &self,
+        cache: &EpochCache,
+        header_hash: &[u8; 32],
+        block_number: u64,
+        nonce: u64,
+        run: impl FnOnce(
+            u64,
+            u64,
+            u64,
+            &[u32],
+            &dyn Fn(u32) -> Vec<u8>,
+            &dyn Fn(&[u32; 8]) -> [u8; 32],
+        ) -> T,
+    ) -> T {
+        let period = block_number / self.period_length;
+        cache.with_dag(|size, c_dag, lookup| match self.variant {
+            Variant::Progpow => {
+                let seed = progpow_seed(header_hash, nonce);
+                run(seed, size, period, c_dag, lookup, &|result| {
+                    keccak_f800_long(header_hash, seed, result)
+                        .try_into()
+                        .unwrap()
+                })
+            }
+            Variant::Padded => {
+                let padding = Padding::Words(self.padding);
+                let seed_words = seed_pass(header_hash, nonce, padding);
+                let seed = (seed_words[1] as u64) << 32 | seed_words[0] as u64;
+                run(seed, size, period, c_dag, lookup, &|result| {
+                    let mut mix_hash = [0u8; 32];
+                    for (bytes, word) in mix_hash.chunks_exact_mut(4).zip(result) {
+                        bytes.copy_from_slice(&word.to_le_bytes());
+                    }
+                    final_pass(&seed_words, &mix_hash, padding)
+                })
+            }
+        })
+    }
+
+    /// Verifies a seal with the cache of its block's epoch.
+    ///
+    /// `caches` must hold this chain's caches, as
+    /// [`cache_manager`](Self::cache_manager) makes them; it is indexed by
+    /// this chain's epochs, not ethash's.
+    ///
+    /// # Returns
+    ///
+    /// The final hash if the mix hash matches and the final hash meets the
+    /// boundary, or the [`SealError`] describing the first check that failed.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(
+                chain = %self.name,
+                block_number = seal.block_number,
+                epoch = self.epoch(seal.block_number),
+                period = seal.block_number / self.period_length,
+                nonce = seal.nonce,
+            ),
+            err(level = "debug"),
+        )
+    )]
+    pub fn verify_seal(&self, caches: &CacheManager, seal: &Seal) -> Result<Vec<u8>, SealError> {
+        let cache = caches.get(self.epoch(seal.block_number));
+        let (mix_hash, final_hash) =
+            self.hash(&cache, &seal.header_hash, seal.block_number, seal.nonce);
+        check_seal(seal, &mix_hash, &final_hash)?;
+        Ok(final_hash)
+    }
+}
+
+#[cfg(feature = "chain-config")]
+impl Chain {
+    /// Parses and validates a chain from JSON; see [`ChainConfig`] for the
+    /// fields.
+    pub fn from_json(json: &str) -> Result<Self, ChainError> {
+        let config: config::ChainConfig =
+            serde_json::from_str(json).map_err(|error| ChainError::Parse(error.to_string()))?;
+        config.into_chain()
+    }
+
+    /// Parses and validates a chain from TOML; see [`ChainConfig`] for the
+    /// fields.
+    pub fn from_toml(toml: &str) -> Result<Self, ChainError> {
+        let config: config::ChainConfig =
+            toml::from_str(toml).map_err(|error| ChainError::Parse(error.to_string()))?;
+        config.into_chain()
+    }
+
+    /// Loads a chain from a file, parsed as TOML if its extension is
+    /// `toml` and as JSON otherwise.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ChainError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "toml")
+        {
+            Self::from_toml(&text)
+        } else {
+            Self::from_json(&text)
+        }
+    }
+}
+
+#[cfg(feature = "chain-config")]
+pub use config::ChainConfig;
+
+#[cfg(feature = "chain-config")]
+mod config {
+    use serde::Deserialize;
+
+    use super::{Chain, ChainError, Variant};
+
+    /// A chain as written in a configuration file.
+    ///
+    /// ```toml
+    /// name = "examplecoin"
+    /// variant = "padded"
+    /// period_length = 3
+    /// epoch_length = 7500
+    /// padding = "eXAMPLECOINPOW!"
+    /// ```
+    ///
+    /// `variant` is `progpow` or `padded`. The four size fields, named as
+    /// in [`Chain`], default to ethash's. `padding` is a string of 15 ASCII
+    /// characters or a list of 15 words, and defaults to zeros.
+    #[derive(Clone, Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    pub struct ChainConfig {
+        name: String,
+        variant: VariantConfig,
+        period_length: u64,
+        epoch_length: u64,
+        cache_bytes_init: Option<u64>,
+        cache_bytes_growth: Option<u64>,
+        dataset_bytes_init: Option<u64>,
+        dataset_bytes_growth: Option<u64>,
+        padding: Option<PaddingConfig>,
+    }
+
+    #[derive(Clone, Copy, Debug, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    enum VariantConfig {
+        Progpow,
+        Padded,
+    }
+
+    #[derive(Clone, Debug, Deserialize)]
+    #[serde(untagged)]
+    enum PaddingConfig {
+        Text(String),
+        Words(Vec<u32>),
+    }
+
+    impl ChainConfig {
+        /// Converts the configuration into a validated [`Chain`].
+        pub fn into_chain(self) -> Result<Chain, ChainError> {
+            let ethash = Chain::ethereum();
+            let words: Vec<u32> = match self.padding {
+                None => vec![0; 15],
+                Some(PaddingConfig::Text(text)) if text.is_ascii() => {
+                    text.bytes().map(u32::from).collect()
+                }
+                Some(PaddingConfig::Text(_)) => {
+                    return Err(ChainError::Invalid("the padding is not ASCII"))
+                }
+                Some(PaddingConfig::Words(words)) => words,
+            };
+            let padding = words
+                .try_into()
+                .map_err(|_| ChainError::Invalid("the padding is not 15 words"))?;
+            let chain = Chain {
+                name: self.name,
+                variant: match self.variant {
+                    VariantConfig::Progpow => Variant::Progpow,
+                    VariantConfig::Padded => Variant::Padded,
+                },
+                period_length: self.period_length,
+                epoch_length: self.epoch_length,
+                cache_bytes_init: self.cache_bytes_init.unwrap_or(ethash.cache_bytes_init),
+                cache_bytes_growth: self.cache_bytes_growth.unwrap_or(ethash.cache_bytes_growth),
+                dataset_bytes_init: self.dataset_bytes_init.unwrap_or(ethash.dataset_bytes_init),
+                dataset_bytes_growth: self
+                    .dataset_bytes_growth
+                    .unwrap_or(ethash.dataset_bytes_growth),
+                padding,
+            };
+            chain.validate()?;
+            Ok(chain)
+        }
+    }
+}
+
+/// Chains looked up by name.
+#[derive(Clone, Debug, Default)]
+pub struct ChainRegistry {
+    chains: BTreeMap<String, Chain>,
+}
+
+impl ChainRegistry {
+    /// Returns a registry without chains.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a registry holding [`Chain::ethereum`] and
+    /// [`Chain::ravencoin`].
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::new();
+        for chain in [Chain::ethereum(), Chain::ravencoin()] {
+            registry.chains.insert(chain.name.clone(), chain);
+        }
+        registry
+    }
+
+    /// Validates and adds `chain` under its name.
+    ///
+    /// # Returns
+    ///
+    /// The chain previously registered under the name, or the
+    /// [`ChainError`] that rejected `chain`.
+    pub fn register(&mut self, chain: Chain) -> Result<Option<Chain>, ChainError> {
+        chain.validate()?;
+        Ok(self.chains.insert(chain.name.clone(), chain))
+    }
+
+    /// Returns the chain registered as `name`.
+    pub fn get(&self, name: &str) -> Option<&Chain> {
+        self.chains.get(name)
+    }
+
+    /// Returns the registered names in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.chains.keys().map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ethash::cache::{cache_size, dataset_size};
+    use crate::testutil::{header_hash, tiny_cache};
+
+    fn small(chain: Chain) -> Chain {
+        Chain {
+            cache_bytes_init: 1024,
+            cache_bytes_growth: 64,
+            dataset_bytes_init: 1 << 16,
+            dataset_bytes_growth: 1 << 10,
+            ..chain
+        }
+    }
+
+    #[test]
+    fn test_builtins_match_their_algorithms() {
+        let ethereum = Chain::ethereum();
+        ethereum.validate().unwrap();
+        for epoch in [0, 1, 100] {
+            assert_eq!(ethereum.cache_size(epoch), cache_size(epoch));
+            assert_eq!(ethereum.dataset_size(epoch), dataset_size(epoch));
+        }
+
+        let header_hash = header_hash(1);
+        let cache = tiny_cache(0);
+        assert_eq!(
+            ethereum.hash(&cache, &header_hash, 25, 7),
+            cache.hash(&header_hash, 25, 7)
+        );
+        assert_eq!(
+            Chain::ravencoin().hash(&cache, &header_hash, 25, 7),
+            cache.hash_kawpow(&header_hash, 25, 7)
+        );
+    }
+
+    #[test]
+    fn test_custom_chain_verifies_its_seals() {
+        let chain = small(Chain {
+            name: "examplecoin".to_string(),
+            period_length: 2,
+            epoch_length: 100,
+            padding: (*b"eXAMPLECOINPOW!").map(u32::from),
+            ..Chain::ravencoin()
+        });
+        chain.validate().unwrap();
+        assert_eq!(chain.epoch(250), 2);
+        assert_eq!(chain.cache_size(2), prime_sized(1024 + 128, 64));
+
+        let caches = chain.cache_manager(2);
+        let header_hash = [3; 32];
+        let (mix_hash, final_hash) = chain.hash(&caches.get(2), &header_hash, 250, 9);
+        assert_ne!(
+            (mix_hash.clone(), final_hash.clone()),
+            Chain::ravencoin().hash(&caches.get(2), &header_hash, 250, 9)
+        );
+        let seal = Seal {
+            header_hash,
+            block_number: 250,
+            nonce: 9,
+            mix_hash: mix_hash.try_into().unwrap(),
+            boundary: [0xff; 32],
+        };
+        assert_eq!(chain.verify_seal(&caches, &seal), Ok(final_hash));
+        // The next period runs another program.
+        let later = Seal {
+            block_number: 252,
+            ..seal
+        };
+        assert!(matches!(
+            chain.verify_seal(&caches, &later),
+            Err(SealError::MixMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_registry_rejects_invalid_chains() {
+        let mut registry = ChainRegistry::with_builtins();
+        assert_eq!(
+            registry.names().collect::<Vec<_>>(),
+            ["ethereum", "ravencoin"]
+        );
+        assert_eq!(registry.get("ravencoin"), Some(&Chain::ravencoin()));
+
+        let zero_period = Chain {
+            period_length: 0,
+            ..Chain::ethereum()
+        };
+        assert!(matches!(
+            registry.register(zero_period),
+            Err(ChainError::Invalid(_))
+        ));
+        let ragged = Chain {
+            dataset_bytes_growth: 1000,
+            ..Chain::ethereum()
+        };
+        assert!(registry.register(ragged).is_err());
+
+        let renamed = Chain {
+            name: "ethereum".to_string(),
+            ..Chain::ravencoin()
+        };
+        assert_eq!(
+            registry.register(renamed.clone()).unwrap(),
+            Some(Chain::ethereum())
+        );
+        assert_eq!(registry.get("ethereum"), Some(&renamed));
+        assert_eq!(registry.get("examplecoin"), None);
+    }
+
+    #[cfg(feature = "chain-config")]
+    #[test]
+    fn test_chain_loads_from_json_and_toml() {
+        let toml = r#"
+            name = "examplecoin"
+            variant = "padded"
+            period_length = 3
+            epoch_length = 7500
+            padding = "rAVENCOINKAWPOW"
+        "#;
+        let mut ravencoin = Chain::from_toml(toml).unwrap();
+        ravencoin.name = "ravencoin".to_string();
+        assert_eq!(ravencoin, Chain::ravencoin());
+
+        let json = r#"{
+            "name": "tiny",
+            "variant": "progpow",
+            "period_length": 5,
+            "epoch_length": 100,
+            "cache_bytes_init": 1024,
+            "cache_bytes_growth": 64,
+            "dataset_bytes_init": 65536,
+            "dataset_bytes_growth": 1024,
+            "padding": [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]
+        }"#;
+        let tiny = Chain::from_json(json).unwrap();
+        assert_eq!(tiny.variant, Variant::Progpow);
+        assert_eq!(tiny.padding[14], 15);
+        assert_eq!(tiny.cache_size(0), prime_sized(1024, 64));
+
+        let dir = std::env::temp_dir().join(format!("progpow-chain-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("tiny.json"), json).unwrap();
+        assert_eq!(Chain::from_file(dir.join("tiny.json")).unwrap(), tiny);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        for (bad, expected) in [
+            (toml.replace("rAVENCOINKAWPOW", "short"), "15 words"),
+            (toml.replace("7500", "0"), "epoch length"),
+            (toml.replace("padded", "ethash"), "invalid chain config"),
+            (format!("{toml}\nextra = 1"), "unknown field"),
+        ] {
+            let error = Chain::from_toml(&bad).unwrap_err().to_string();
+            assert!(error.contains(expected), "{error}");
+        }
+        assert!(matches!(
+            Chain::from_file(dir.join("missing.toml")),
+            Err(ChainError::Read(_))
+        ));
+    }
+}
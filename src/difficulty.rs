@@ -0,0 +1,659 @@
+//! Difficulty adjustment rules, to check a header's declared difficulty
+//! against its parents.
+//!
+//! A seal proves work against the difficulty its header declares. Whether
+//! that is the difficulty the chain calls for at that height is a separate
+//! check, and every chain computes it differently. A [`DifficultyRule`]
+//! computes the work a block must declare from its parents: [`EthashRule`]
+//! for Ethereum-style difficulties, [`DarkGravityWave`] for Ravencoin's
+//! compact targets and [`KimotoGravityWell`] for Firo's. [`check_difficulty`]
+//! compares, and the `verify_header_with_parents` functions of
+//! [`engine`](crate::engine), [`ravencoin`](crate::ravencoin) and
+//! [`firo`](crate::firo) check it before the seal.
+
+use std::fmt;
+
+use crate::header::Header;
+use crate::ravencoin::{bits_from_target, target_from_bits, RavencoinHeader};
+use crate::target::U256;
+
+/// The work a block declares.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Work {
+    /// An Ethereum-style difficulty, the expected number of hashes.
+    Difficulty(U256),
+    /// A Bitcoin-style target in compact form; see
+    /// [`target_from_bits`].
+    Bits(u32),
+}
+
+/// What a [`DifficultyRule`] reads of a block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockInfo {
+    /// The block number or height.
+    pub number: u64,
+    /// The block time in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The work the block declares.
+    pub work: Work,
+    /// Whether the block has ommers, which Byzantium's rule rewards.
+    pub has_ommers: bool,
+}
+
+impl From<&Header> for BlockInfo {
+    fn from(header: &Header) -> Self {
+        BlockInfo {
+            number: header.number,
+            timestamp: header.timestamp,
+            work: Work::Difficulty(header.difficulty),
+            has_ommers: header.has_ommers(),
+        }
+    }
+}
+
+impl From<&RavencoinHeader> for BlockInfo {
+    fn from(header: &RavencoinHeader) -> Self {
+        BlockInfo {
+            number: header.height as u64,
+            timestamp: header.time as u64,
+            work: Work::Bits(header.bits),
+            has_ommers: false,
+        }
+    }
+}
+
+/// The reason a header's declared work was rejected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DifficultyError {
+    /// Fewer parents were given than the rule reads at this height.
+    MissingParents {
+        /// The parents the rule reads.
+        needed: usize,
+        /// The parents given.
+        found: usize,
+    },
+    /// A block does not follow the one before it.
+    NotConsecutive {
+        /// The number of the block out of place.
+        number: u64,
+    },
+    /// A parent declares work of another kind than the rule computes.
+    WrongKind {
+        /// The number of the parent.
+        number: u64,
+    },
+    /// A parent's compact target bits are negative or overflow 256 bits.
+    InvalidBits {
+        /// The bits of the parent.
+        bits: u32,
+    },
+    /// The header declares other work than its parents call for.
+    Mismatch {
+        /// The work the header declares.
+        declared: Work,
+        /// The work the rule computes.
+        expected: Work,
+    },
+}
+
+impl fmt::Display for DifficultyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DifficultyError::MissingParents { needed, found } => {
+                write!(f, "the rule reads {needed} parents, {found} were given")
+            }
+            DifficultyError::NotConsecutive { number } => {
+                write!(f, "block {number} does not follow the block before it")
+            }
+            DifficultyError::WrongKind { number } => {
+                write!(f, "block {number} declares work of another kind")
+            }
+            DifficultyError::InvalidBits { bits } => {
+                write!(f, "invalid compact target bits {bits:#010x}")
+            }
+            DifficultyError::Mismatch { declared, expected } => {
+                write!(f, "declared work {declared:?}, expected {expected:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DifficultyError {}
+
+/// A chain's rule for the work a block must declare.
+pub trait DifficultyRule: Send + Sync {
+    /// Returns the most parents the rule reads.
+    fn parents_needed(&self) -> usize;
+
+    /// Returns the work a block with time `timestamp` must declare.
+    ///
+    /// # Arguments
+    ///
+    /// * `parents` - Consecutive parents, oldest first, ending with the
+    ///   block's parent; [`parents_needed`](Self::parents_needed) of them
+    ///   suffice.
+    /// * `timestamp` - The time of the block.
+    fn next_work(&self, parents: &[BlockInfo], timestamp: u64) -> Result<Work, DifficultyError>;
+}
+
+/// Checks that `header` declares the work `rule` computes from its parents.
+///
+/// # Arguments
+///
+/// * `rule` - The chain's difficulty rule.
+/// * `header` - The block to check.
+/// * `parents` - Consecutive parents of `header`, oldest first, ending
+///   with its parent.
+///
+/// # Returns
+///
+/// `Ok(())`, or the [`DifficultyError`] describing why the work is not the
+/// one called for.
+pub fn check_difficulty(
+    rule: &dyn DifficultyRule,
+    header: &BlockInfo,
+    parents: &[BlockInfo],
+) -> Result<(), DifficultyError> {
+    let parent = parents.last().ok_or(DifficultyError::MissingParents {
+        needed: rule.parents_needed(),
+        found: 0,
+    })?;
+    for pair in parents.windows(2).chain([[*parent, *header].as_slice()]) {
+        if pair[0].number.checked_add(1) != Some(pair[1].number) {
+            return Err(DifficultyError::NotConsecutive {
+                number: pair[1].number,
+            });
+        }
+    }
+    let expected = rule.next_work(parents, header.timestamp)?;
+    if header.work != expected {
+        return Err(DifficultyError::Mismatch {
+            declared: header.work,
+            expected,
+        });
+    }
+    Ok(())
+}
+
+/// Returns the target of a block declaring compact bits.
+fn target_of(block: &BlockInfo) -> Result<U256, DifficultyError> {
+    match block.work {
+        Work::Bits(bits) => target_from_bits(bits)
+            .map(U256::from_be_bytes)
+            .ok_or(DifficultyError::InvalidBits { bits }),
+        Work::Difficulty(_) => Err(DifficultyError::WrongKind {
+            number: block.number,
+        }),
+    }
+}
+
+/// Returns `2^exponent`, or `None` if it does not fit in 256 bits.
+fn two_pow(exponent: u64) -> Option<U256> {
+    let mut bytes = [0; 32];
+    let byte = 31usize.checked_sub(usize::try_from(exponent / 8).ok()?)?;
+    bytes[byte] = 1 << (exponent % 8);
+    Some(U256::from_be_bytes(bytes))
+}
+
+/// Ethereum's difficulty adjustment since Byzantium (EIP-100): the
+/// parent's difficulty moves by a 2048th of itself per nine seconds the
+/// block is early or late, to at most 99 steps down, one step more if the
+/// parent has ommers, plus the difficulty bomb.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EthashRule {
+    /// The lowest difficulty the rule computes.
+    pub minimum: U256,
+    /// The blocks the difficulty bomb is delayed by, or `None` for chains
+    /// without one.
+    pub bomb_delay: Option<u64>,
+}
+
+impl EthashRule {
+    /// Returns the rule of Byzantium, its bomb delayed by 3,000,000 blocks.
+    pub fn byzantium() -> Self {
+        EthashRule {
+            minimum: U256::from(131_072),
+            bomb_delay: Some(3_000_000),
+        }
+    }
+
+    /// Returns the rule of Constantinople, its bomb delayed by 5,000,000
+    /// blocks.
+    pub fn constantinople() -> Self {
+        EthashRule {
+            bomb_delay: Some(5_000_000),
+            ..Self::byzantium()
+        }
+    }
+
+    /// Returns Byzantium's adjustment without the bomb, for testnets such
+    /// as the original ProgPoW ones, which ran without it.
+    pub fn progpow_testnet() -> Self {
+        EthashRule {
+            bomb_delay: None,
+            ..Self::byzantium()
+        }
+    }
+}
+
+impl DifficultyRule for EthashRule {
+    fn parents_needed(&self) -> usize {
+        1
+    }
+
+    fn next_work(&self, parents: &[BlockInfo], timestamp: u64) -> Result<Work, DifficultyError> {
+        let parent = parents.last().ok_or(DifficultyError::MissingParents {
+            needed: 1,
+            found: 0,
+        })?;
+        let Work::Difficulty(parent_difficulty) = parent.work else {
+            return Err(DifficultyError::WrongKind {
+                number: parent.number,
+            });
+        };
+        let step = parent_difficulty.div_rem(U256::from(2048)).0;
+        let late = timestamp.saturating_sub(parent.timestamp) / 9;
+        let steps = (1 + parent.has_ommers as i64 - late.min(100) as i64).max(-99);
+        let adjusted = if steps >= 0 {
+            step.checked_mul_u64(steps as u64)
+                .and_then(|change| parent_difficulty.checked_add(change))
+                .unwrap_or(U256::MAX)
+        } else {
+            // 99 steps are less than the parent's difficulty.
+            let change = step.checked_mul_u64(steps.unsigned_abs()).unwrap();
+            parent_difficulty.checked_sub(change).unwrap()
+        };
+        let mut difficulty = adjusted.max(self.minimum);
+        if let Some(delay) = self.bomb_delay {
+            let periods = (parent.number + 1).saturating_sub(delay) / 100_000;
+            if periods > 1 {
+                difficulty = two_pow(periods - 2)
+                    .and_then(|bomb| difficulty.checked_add(bomb))
+                    .unwrap_or(U256::MAX);
+            }
+        }
+        Ok(Work::Difficulty(difficulty))
+    }
+}
+
+/// Dash's Dark Gravity Wave v3, as Ravencoin retargets every block: the
+/// running average of the last `past_blocks` targets, scaled by how long
+/// they took against `past_blocks * target_spacing` seconds, at most three
+/// times either way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DarkGravityWave {
+    /// The blocks averaged over.
+    pub past_blocks: u64,
+    /// The seconds between blocks to aim for.
+    pub target_spacing: u64,
+    /// The easiest target, 32 bytes big-endian, which the first blocks
+    /// declare.
+    pub pow_limit: [u8; 32],
+}
+
+impl DarkGravityWave {
+    /// Returns Ravencoin's rule since KawPoW: 180 blocks of a minute, with
+    /// its KawPoW target limit.
+    pub fn ravencoin() -> Self {
+        let mut pow_limit = [0xff; 32];
+        pow_limit[..5].fill(0);
+        DarkGravityWave {
+            past_blocks: 180,
+            target_spacing: 60,
+            pow_limit,
+        }
+    }
+}
+
+impl DifficultyRule for DarkGravityWave {
+    fn parents_needed(&self) -> usize {
+        self.past_blocks as usize
+    }
+
+    fn next_work(&self, parents: &[BlockInfo], _: u64) -> Result<Work, DifficultyError> {
+        let needed = self.parents_needed();
+        let pow_limit = U256::from_be_bytes(self.pow_limit);
+        let parent = parents.last().ok_or(DifficultyError::MissingParents {
+            needed: 1,
+            found: 0,
+        })?;
+        if parent.number < self.past_blocks {
+            return Ok(Work::Bits(bits_from_target(&self.pow_limit)));
+        }
+        if parents.len() < needed {
+            return Err(DifficultyError::MissingParents {
+                needed,
+                found: parents.len(),
+            });
+        }
+        let window = &parents[parents.len() - needed..];
+        let mut average = U256::ZERO;
+        for (count, block) in (1u64..).zip(window.iter().rev()) {
+            let target = target_of(block)?;
+            // Dash's weighting, which is not quite the mean.
+            average = if count == 1 {
+                target
+            } else {
+                average
+                    .checked_mul_u64(count)
+                    .and_then(|sum| sum.checked_add(target))
+                    .map_or(U256::MAX, |sum| sum.div_rem(U256::from(count + 1)).0)
+            };
+        }
+        let timespan = (self.past_blocks * self.target_spacing) as i64;
+        let actual = (parent.timestamp as i64 - window[0].timestamp as i64)
+            .clamp(timespan / 3, timespan * 3) as u64;
+        let target = average
+            .checked_mul_u64(actual)
+            .map_or(pow_limit, |scaled| {
+                scaled.div_rem(U256::from(timespan as u64)).0
+            })
+            .min(pow_limit);
+        Ok(Work::Bits(bits_from_target(&target.to_be_bytes())))
+    }
+}
+
+/// The Kimoto Gravity Well, as Zcoin and now Firo inherited it from
+/// Megacoin: a running average of past targets over a window that grows
+/// from `past_blocks_min` to `past_blocks_max` blocks until their rate
+/// leaves the event horizon around the target rate, scaled by that rate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KimotoGravityWell {
+    /// The seconds between blocks to aim for.
+    pub target_spacing: u64,
+    /// The fewest blocks averaged over.
+    pub past_blocks_min: u64,
+    /// The most blocks averaged over.
+    pub past_blocks_max: u64,
+    /// The easiest target, 32 bytes big-endian, which the first blocks
+    /// declare.
+    pub pow_limit: [u8; 32],
+}
+
+impl KimotoGravityWell {
+    /// Returns Firo's window: five-minute blocks averaged over a quarter of
+    /// a day to a week. The target limit is left at the largest target; set
+    /// it to the chain's.
+    pub fn firo() -> Self {
+        KimotoGravityWell {
+            target_spacing: 300,
+            past_blocks_min: 6 * 3600 / 300,
+            past_blocks_max: 7 * 86400 / 300,
+            pow_limit: [0xff; 32],
+        }
+    }
+}
+
+impl DifficultyRule for KimotoGravityWell {
+    fn parents_needed(&self) -> usize {
+        self.past_blocks_max as usize
+    }
+
+    fn next_work(&self, parents: &[BlockInfo], _: u64) -> Result<Work, DifficultyError> {
+        let pow_limit = U256::from_be_bytes(self.pow_limit);
+        let limit = Work::Bits(bits_from_target(&self.pow_limit));
+        let parent = parents.last().ok_or(DifficultyError::MissingParents {
+            needed: 1,
+            found: 0,
+        })?;
+        if parent.number == 0 || parent.number < self.past_blocks_min {
+            return Ok(limit);
+        }
+        let (mut mass, mut average) = (0u64, U256::ZERO);
+        let (mut actual, mut expected) = (0u64, 0u64);
+        let mut done = false;
+        for (i, block) in (1u64..).zip(parents.iter().rev()) {
+            if block.number == 0 || i > self.past_blocks_max {
+                done = true;
+                break;
+            }
+            mass += 1;
+            let target = target_of(block)?;
+            average = if i == 1 {
+                target
+            } else if target >= average {
+                (target
+                    .checked_sub(average)
+                    .unwrap()
+                    .div_rem(U256::from(i))
+                    .0)
+                    .checked_add(average)
+                    .unwrap()
+            } else {
+                let fall = average
+                    .checked_sub(target)
+                    .unwrap()
+                    .div_rem(U256::from(i))
+                    .0;
+                average.checked_sub(fall).unwrap()
+            };
+            actual = parent.timestamp.saturating_sub(block.timestamp);
+            expected = self.target_spacing * mass;
+            let ratio = if actual != 0 && expected != 0 {
+                expected as f64 / actual as f64
+            } else {
+                1.0
+            };
+            let horizon = 1.0 + 0.7084 * (mass as f64 / 28.2).powf(-1.228);
+            if mass >= self.past_blocks_min && (ratio <= 1.0 / horizon || ratio >= horizon) {
+                done = true;
+                break;
+            }
+        }
+        // Short of the genesis block, the window went on past the parents.
+        if !done && parents[0].number > 1 {
+            return Err(DifficultyError::MissingParents {
+                needed: self.parents_needed(),
+                found: parents.len(),
+            });
+        }
+        let mut target = average;
+        if actual != 0 && expected != 0 {
+            target = target
+                .checked_mul_u64(actual)
+                .map_or(pow_limit, |scaled| scaled.div_rem(U256::from(expected)).0);
+        }
+        Ok(Work::Bits(bits_from_target(
+            &target.min(pow_limit).to_be_bytes(),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Consecutive blocks from `first` declaring `work`, `spacings` seconds
+    /// apart.
+    fn blocks(first: u64, work: Work, spacings: &[u64]) -> Vec<BlockInfo> {
+        let mut timestamp = 1_600_000_000;
+        let mut out = vec![BlockInfo {
+            number: first,
+            timestamp,
+            work,
+            has_ommers: false,
+        }];
+        for (number, spacing) in (first + 1..).zip(spacings) {
+            timestamp += spacing;
+            out.push(BlockInfo {
+                number,
+                timestamp,
+                work,
+                has_ommers: false,
+            });
+        }
+        out
+    }
+
+    #[test]
+    fn test_ethash_rule_follows_byzantium() {
+        let rule = EthashRule::progpow_testnet();
+        let parent = BlockInfo {
+            number: 100,
+            timestamp: 1000,
+            work: Work::Difficulty(U256::from(1 << 30)),
+            has_ommers: false,
+        };
+        let next = |parent: BlockInfo, timestamp, rule: &EthashRule| match rule
+            .next_work(&[parent], timestamp)
+            .unwrap()
+        {
+            Work::Difficulty(difficulty) => difficulty.to_u64().unwrap(),
+            Work::Bits(_) => unreachable!(),
+        };
+        let step = (1 << 30) / 2048;
+        assert_eq!(next(parent, 1005, &rule), (1 << 30) + step);
+        assert_eq!(next(parent, 1009, &rule), 1 << 30);
+        assert_eq!(next(parent, 1100, &rule), (1 << 30) - 10 * step);
+        assert_eq!(next(parent, 100_000, &rule), (1 << 30) - 99 * step);
+        let with_ommers = BlockInfo {
+            has_ommers: true,
+            ..parent
+        };
+        assert_eq!(next(with_ommers, 1005, &rule), (1 << 30) + 2 * step);
+        let easy = BlockInfo {
+            work: Work::Difficulty(U256::from(131_072)),
+            ..parent
+        };
+        assert_eq!(next(easy, 2000, &rule), 131_072);
+
+        // Byzantium's bomb doubles every 100,000 blocks past its delay.
+        let late = BlockInfo {
+            number: 4_299_999,
+            ..parent
+        };
+        assert_eq!(
+            next(late, 1009, &EthashRule::byzantium()),
+            (1 << 30) + (1 << 11)
+        );
+        assert_eq!(next(late, 1009, &EthashRule::constantinople()), 1 << 30);
+    }
+
+    #[test]
+    fn test_check_difficulty_compares_with_the_parents() {
+        let rule = EthashRule {
+            minimum: U256::ONE,
+            bomb_delay: None,
+        };
+        let parents = blocks(7, Work::Difficulty(U256::from(1 << 20)), &[13, 13]);
+        let mut header = BlockInfo {
+            number: 10,
+            timestamp: parents[2].timestamp + 5,
+            work: Work::Difficulty(U256::from((1 << 20) + 512)),
+            has_ommers: false,
+        };
+        assert_eq!(check_difficulty(&rule, &header, &parents), Ok(()));
+        header.work = Work::Difficulty(U256::from(1 << 20));
+        assert!(matches!(
+            check_difficulty(&rule, &header, &parents),
+            Err(DifficultyError::Mismatch { .. })
+        ));
+        header.number = 11;
+        assert_eq!(
+            check_difficulty(&rule, &header, &parents),
+            Err(DifficultyError::NotConsecutive { number: 11 })
+        );
+        assert_eq!(
+            check_difficulty(&rule, &header, &[]),
+            Err(DifficultyError::MissingParents {
+                needed: 1,
+                found: 0
+            })
+        );
+        let bits = blocks(9, Work::Bits(0x1b030000), &[]);
+        header.number = 10;
+        assert_eq!(
+            check_difficulty(&rule, &header, &bits),
+            Err(DifficultyError::WrongKind { number: 9 })
+        );
+    }
+
+    #[test]
+    fn test_dark_gravity_wave_retargets_on_the_timespan() {
+        let rule = DarkGravityWave {
+            past_blocks: 10,
+            target_spacing: 60,
+            ..DarkGravityWave::ravencoin()
+        };
+        assert_eq!(bits_from_target(&rule.pow_limit), 0x1c00ffff);
+        let work = Work::Bits(0x1b030000);
+        let next = |spacings: &[u64]| rule.next_work(&blocks(100, work, spacings), 0);
+
+        // Ten blocks spanning 600 seconds hold the target.
+        let mut on_time = [60; 9];
+        on_time[0] = 120;
+        assert_eq!(next(&on_time), Ok(work));
+        // Faster or slower than a third or three times scales by three.
+        assert_eq!(next(&[1; 9]), Ok(Work::Bits(0x1b010000)));
+        assert_eq!(next(&[1000; 9]), Ok(Work::Bits(0x1b090000)));
+        // Only the last ten blocks count.
+        assert_eq!(
+            next(&[1, 1, 1, 1, 1, 120, 60, 60, 60, 60, 60, 60, 60, 60]),
+            Ok(work)
+        );
+
+        assert_eq!(
+            rule.next_work(&blocks(5, work, &[60; 3]), 0),
+            Ok(Work::Bits(0x1c00ffff))
+        );
+        assert_eq!(
+            next(&[60; 3]),
+            Err(DifficultyError::MissingParents {
+                needed: 10,
+                found: 4
+            })
+        );
+        assert_eq!(
+            rule.next_work(&blocks(100, Work::Bits(0x1d812345), &[60; 9]), 0),
+            Err(DifficultyError::InvalidBits { bits: 0x1d812345 })
+        );
+    }
+
+    #[test]
+    fn test_kimoto_gravity_well_stops_at_the_event_horizon() {
+        let rule = KimotoGravityWell {
+            target_spacing: 10,
+            past_blocks_min: 4,
+            past_blocks_max: 10,
+            ..KimotoGravityWell::firo()
+        };
+        let work = Work::Bits(0x1b0a0000);
+
+        // On time, the window spans the maximum, nine intervals against ten
+        // blocks' worth of time.
+        assert_eq!(
+            rule.next_work(&blocks(100, work, &[10; 12]), 0),
+            Ok(Work::Bits(0x1b090000))
+        );
+        // Far too fast, it stops at the minimum: 3 seconds against 40.
+        assert_eq!(
+            rule.next_work(&blocks(100, work, &[1; 12]), 0),
+            Ok(Work::Bits(0x1b00c000))
+        );
+        // The average weighs the newest targets most.
+        let mut mixed = blocks(100, work, &[10; 12]);
+        mixed[12].work = Work::Bits(0x1b140000);
+        assert!(matches!(
+            rule.next_work(&mixed, 0),
+            Ok(Work::Bits(bits)) if bits > 0x1b090000
+        ));
+
+        assert_eq!(
+            rule.next_work(&blocks(100, work, &[10; 5]), 0),
+            Err(DifficultyError::MissingParents {
+                needed: 10,
+                found: 6
+            })
+        );
+        // From the genesis block, the window ends there: 50 seconds
+        // against 60.
+        assert_eq!(
+            rule.next_work(&blocks(0, work, &[10; 6]), 0),
+            Ok(Work::Bits(0x1b085555))
+        );
+        assert_eq!(
+            rule.next_work(&blocks(0, work, &[10; 2]), 0),
+            Ok(Work::Bits(bits_from_target(&[0xff; 32])))
+        );
+    }
+}
@@ -0,0 +1,103 @@
+//! Byte-order conversions between words and bytes.
+//!
+//! ethash and ProgPoW define their words as little-endian: the light cache,
+//! dataset items, the cached DAG words and the mix hash are read and written
+//! a little-endian `u32` at a time, while the 64-bit value Keccak-f800 takes
+//! from its digest and every boundary are big-endian. The helpers here name
+//! the order they use, so no conversion depends on the host's; CI tests the
+//! crate on big-endian and 32-bit targets as the README describes.
+//!
+//! A `[u32]` buffer handed to a device as it lies in memory is read in the
+//! device's order, which is little-endian on every GPU.
+//! [`to_le_layout`] and [`from_le_layout`] put such buffers in that layout
+//! and take them back, and cost nothing on a little-endian host.
+
+/// Reads `bytes` as little-endian words.
+///
+/// # Panics
+///
+/// Panics if the length of `bytes` is not a multiple of 4.
+pub fn le_words(bytes: &[u8]) -> Vec<u32> {
+    let mut words = vec![0; bytes.len() / 4];
+    read_le_words(bytes, &mut words);
+    words
+}
+
+/// Reads `bytes` as little-endian words into `words`.
+///
+/// # Panics
+///
+/// Panics if `bytes` does not hold exactly `4 * words.len()` bytes.
+pub fn read_le_words(bytes: &[u8], words: &mut [u32]) {
+    assert_eq!(bytes.len(), 4 * words.len(), "not a whole number of words");
+    for (word, chunk) in words.iter_mut().zip(bytes.chunks_exact(4)) {
+        *word = u32::from_le_bytes(chunk.try_into().unwrap());
+    }
+}
+
+/// Returns the little-endian bytes of `words`.
+pub fn le_bytes(words: &[u32]) -> Vec<u8> {
+    let mut bytes = vec![0; 4 * words.len()];
+    write_le_bytes(words, &mut bytes);
+    bytes
+}
+
+/// Writes the little-endian bytes of `words` into `bytes`.
+///
+/// # Panics
+///
+/// Panics if `bytes` does not hold exactly `4 * words.len()` bytes.
+pub fn write_le_bytes(words: &[u32], bytes: &mut [u8]) {
+    assert_eq!(bytes.len(), 4 * words.len(), "not a whole number of words");
+    for (chunk, word) in bytes.chunks_exact_mut(4).zip(words) {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+}
+
+/// Returns `words` laid out in memory as a little-endian device reads them.
+pub fn to_le_layout(words: &[u32]) -> Vec<u32> {
+    words.iter().map(|word| word.to_le()).collect()
+}
+
+/// Converts words a little-endian device wrote back to the host's order.
+pub fn from_le_layout(words: &mut [u32]) {
+    for word in words {
+        *word = u32::from_le(*word);
+    }
+}
+
+/// Returns `len` as a `usize`, for lengths of buffers held in memory.
+///
+/// # Panics
+///
+/// Panics if `len` exceeds the address space, as a full dataset does on a
+/// 32-bit target.
+pub fn in_memory(len: u64) -> usize {
+    usize::try_from(len)
+        .unwrap_or_else(|_| panic!("{len} bytes do not fit in this target's address space"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions_do_not_depend_on_the_host() {
+        let bytes = [1, 2, 3, 4, 0xff, 0, 0, 0x80];
+        let words = le_words(&bytes);
+        assert_eq!(words, [0x0403_0201, 0x8000_00ff]);
+        assert_eq!(le_bytes(&words), bytes);
+
+        // In memory, a device buffer holds the little-endian bytes.
+        let laid_out = to_le_layout(&words);
+        let in_memory_bytes: Vec<u8> = laid_out.iter().flat_map(|w| w.to_ne_bytes()).collect();
+        assert_eq!(in_memory_bytes, bytes);
+        let mut back = laid_out;
+        from_le_layout(&mut back);
+        assert_eq!(back, words);
+
+        assert_eq!(in_memory(1 << 20), 1 << 20);
+        #[cfg(target_pointer_width = "32")]
+        assert!(std::panic::catch_unwind(|| in_memory(1 << 32)).is_err());
+    }
+}
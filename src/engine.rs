@@ -0,0 +1,434 @@
+//! A consensus-engine surface for node integrations.
+//!
+//! Nodes hold headers, not header hashes. [`SealableHeader`] describes what
+//! ProgPoW needs from a header type, and [`PowEngine`] is the engine a node
+//! calls: verify a header's seal on import, hand work to external miners,
+//! and seal headers it mines itself. [`ProgpowEngine`] implements it for any
+//! sealable header on top of a shared [`CacheManager`]; the `alloy` feature
+//! makes `alloy_consensus::Header`, the header type of alloy and reth, one.
+
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::difficulty::{check_difficulty, BlockInfo, DifficultyError, DifficultyRule};
+use crate::ethash::buffer::DagBuffer;
+use crate::ethash::cache::{epoch, MAX_EPOCH};
+use crate::ethash::manager::CacheManager;
+use crate::header::{Header, HeaderError};
+use crate::progpow::search::{search, SearchStrategy};
+use crate::progpow::verify::{Seal, SealError};
+
+/// A block header that carries a ProgPoW seal: a nonce and a mix hash.
+pub trait SealableHeader {
+    /// Returns the block number, which selects the epoch and period.
+    fn number(&self) -> u64;
+
+    /// Returns the hash ProgPoW seals: the header's hash without its nonce
+    /// and mix hash.
+    fn seal_hash(&self) -> [u8; 32];
+
+    /// Returns the 32-byte big-endian target the final hash must not exceed,
+    /// derived from the header's difficulty.
+    fn boundary(&self) -> [u8; 32];
+
+    /// Returns the nonce in the seal.
+    fn nonce(&self) -> u64;
+
+    /// Returns the mix hash in the seal.
+    fn mix_hash(&self) -> [u8; 32];
+
+    /// Writes a seal into the header.
+    fn set_seal(&mut self, nonce: u64, mix_hash: [u8; 32]);
+}
+
+/// A unit of work for an external miner, as in `eth_getWork`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Work {
+    /// The header's seal hash.
+    pub header_hash: [u8; 32],
+    /// The seed hash of the header's epoch.
+    pub seed_hash: [u8; 32],
+    /// The target the final hash must not exceed.
+    pub boundary: [u8; 32],
+    /// The block number being mined.
+    pub block_number: u64,
+}
+
+/// The reason a [`PowEngine`] rejected a header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EngineError {
+    /// The header's epoch is past [`MAX_EPOCH`], so no cache is generated.
+    UnsupportedEpoch {
+        /// The epoch of the header.
+        epoch: u64,
+    },
+    /// The seal does not verify.
+    InvalidSeal(SealError),
+    /// The header's RLP encoding does not decode.
+    InvalidHeader(HeaderError),
+    /// The header's difficulty is not the one its parents call for.
+    InvalidDifficulty(DifficultyError),
+    /// One of the block's ommers was rejected.
+    InvalidOmmer {
+        /// The position of the ommer in the block.
+        index: usize,
+        /// Why it was rejected.
+        error: Box<EngineError>,
+    },
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineError::UnsupportedEpoch { epoch } => write!(
+                f,
+                "epoch {epoch} is past the last supported epoch {}",
+                MAX_EPOCH - 1
+            ),
+            EngineError::InvalidSeal(error) => write!(f, "invalid seal: {error}"),
+            EngineError::InvalidHeader(error) => write!(f, "invalid header: {error}"),
+            EngineError::InvalidDifficulty(error) => write!(f, "invalid difficulty: {error}"),
+            EngineError::InvalidOmmer { index, error } => write!(f, "ommer {index}: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+impl From<SealError> for EngineError {
+    fn from(error: SealError) -> Self {
+        EngineError::InvalidSeal(error)
+    }
+}
+
+impl From<HeaderError> for EngineError {
+    fn from(error: HeaderError) -> Self {
+        EngineError::InvalidHeader(error)
+    }
+}
+
+impl From<DifficultyError> for EngineError {
+    fn from(error: DifficultyError) -> Self {
+        EngineError::InvalidDifficulty(error)
+    }
+}
+
+/// A proof-of-work consensus engine for headers of type `H`.
+pub trait PowEngine<H> {
+    /// Verifies the seal of `header`.
+    fn verify_header_seal(&self, header: &H) -> Result<(), EngineError>;
+
+    /// Verifies the seals of `header` and of its `ommers`.
+    ///
+    /// Ommers are a few blocks older than the header, so they may fall in
+    /// an earlier epoch or period; each is verified at its own number.
+    /// The first rejected ommer is reported with its position.
+    fn verify_with_ommers(&self, header: &H, ommers: &[H]) -> Result<(), EngineError> {
+        self.verify_header_seal(header)?;
+        ommers.iter().enumerate().try_for_each(|(index, ommer)| {
+            self.verify_header_seal(ommer)
+                .map_err(|error| EngineError::InvalidOmmer {
+                    index,
+                    error: Box::new(error),
+                })
+        })
+    }
+
+    /// Returns the work an external miner needs to seal `header`.
+    fn prepare_work(&self, header: &H) -> Result<Work, EngineError>;
+
+    /// Searches `nonces` for a seal of `header` and writes the first one
+    /// found into it.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the header was sealed, `false` if the range was exhausted.
+    fn seal(&self, header: &mut H, nonces: Range<u64>) -> Result<bool, EngineError>;
+}
+
+/// The ProgPoW [`PowEngine`], hashing with caches from a [`CacheManager`].
+#[derive(Clone)]
+pub struct ProgpowEngine {
+    caches: Arc<CacheManager>,
+}
+
+impl ProgpowEngine {
+    /// Creates an engine sharing `caches`.
+    pub fn new(caches: Arc<CacheManager>) -> Self {
+        ProgpowEngine { caches }
+    }
+}
+
+/// Returns the epoch of `block_number`, rejecting unsupported ones.
+fn supported_epoch(block_number: u64) -> Result<u64, EngineError> {
+    let epoch = epoch(block_number);
+    if epoch >= MAX_EPOCH {
+        return Err(EngineError::UnsupportedEpoch { epoch });
+    }
+    Ok(epoch)
+}
+
+/// Verifies the seal of `header` with the cache of its epoch in `caches`.
+pub(crate) fn verify_with(
+    caches: &CacheManager,
+    header: &impl SealableHeader,
+) -> Result<(), EngineError> {
+    supported_epoch(header.number())?;
+    caches.verify_seal(&seal_of(header))?;
+    Ok(())
+}
+
+/// Returns the seal `header` carries.
+pub(crate) fn seal_of(header: &impl SealableHeader) -> Seal {
+    Seal {
+        header_hash: header.seal_hash(),
+        block_number: header.number(),
+        nonce: header.nonce(),
+        mix_hash: header.mix_hash(),
+        boundary: header.boundary(),
+    }
+}
+
+/// Verifies the seal of an RLP-encoded Ethereum-style header.
+///
+/// This decodes the header, takes its number, difficulty, nonce and mix
+/// hash, and checks the seal against its pre-hash with the cache of its
+/// epoch in `caches`, generating the cache if it is not held.
+pub fn verify_header(rlp: &[u8], caches: &CacheManager) -> Result<(), EngineError> {
+    verify_with(caches, &Header::decode(rlp)?)
+}
+
+/// Verifies the seal of an RLP-encoded header as [`verify_header`] does,
+/// after checking its difficulty against its RLP-encoded parents.
+///
+/// # Arguments
+///
+/// * `rlp` - The header.
+/// * `parents` - Consecutive parents of the header, oldest first, ending
+///   with its parent; [`DifficultyRule::parents_needed`] of them suffice.
+/// * `caches` - The caches to verify the seal with.
+/// * `rule` - The chain's difficulty rule.
+pub fn verify_header_with_parents(
+    rlp: &[u8],
+    parents: &[&[u8]],
+    caches: &CacheManager,
+    rule: &dyn DifficultyRule,
+) -> Result<(), EngineError> {
+    let header = Header::decode(rlp)?;
+    let parents = parents
+        .iter()
+        .map(|parent| Ok(BlockInfo::from(&Header::decode(parent)?)))
+        .collect::<Result<Vec<_>, HeaderError>>()?;
+    check_difficulty(rule, &BlockInfo::from(&header), &parents)?;
+    verify_with(caches, &header)
+}
+
+/// Verifies the seals of an RLP-encoded header and of its RLP-encoded
+/// ommers; see [`verify_header`] and [`PowEngine::verify_with_ommers`].
+pub fn verify_header_with_ommers(
+    rlp: &[u8],
+    ommers: &[&[u8]],
+    caches: &CacheManager,
+) -> Result<(), EngineError> {
+    verify_header(rlp, caches)?;
+    ommers.iter().enumerate().try_for_each(|(index, ommer)| {
+        verify_header(ommer, caches).map_err(|error| EngineError::InvalidOmmer {
+            index,
+            error: Box::new(error),
+        })
+    })
+}
+
+impl<H: SealableHeader> PowEngine<H> for ProgpowEngine {
+    fn verify_header_seal(&self, header: &H) -> Result<(), EngineError> {
+        verify_with(&self.caches, header)
+    }
+
+    fn prepare_work(&self, header: &H) -> Result<Work, EngineError> {
+        let epoch = supported_epoch(header.number())?;
+        Ok(Work {
+            header_hash: header.seal_hash(),
+            seed_hash: self
+                .caches
+                .seed_hash(epoch)
+                .expect("supported epochs have seed hashes"),
+            boundary: header.boundary(),
+            block_number: header.number(),
+        })
+    }
+
+    fn seal(&self, header: &mut H, nonces: Range<u64>) -> Result<bool, EngineError> {
+        supported_epoch(header.number())?;
+        let cache = self.caches.for_block(header.number());
+        let solution = search(
+            &header.seal_hash(),
+            cache.size(),
+            header.number(),
+            &cache.c_dag(),
+            &|index| cache.lookup(index),
+            nonces,
+            &header.boundary(),
+            SearchStrategy::Full,
+        );
+        Ok(match solution {
+            Some(solution) => {
+                header.set_seal(solution.nonce, solution.mix_hash.try_into().unwrap());
+                true
+            }
+            None => false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::difficulty::EthashRule;
+    use crate::ethash::cache::{seed_hash, EPOCH_LENGTH};
+    use crate::header::tests::encode_header;
+    use crate::keccak::keccak256;
+    use crate::target::U256;
+    use crate::testutil::tiny_cache_manager;
+
+    /// A header holding only what the engine reads.
+    struct TestHeader {
+        number: u64,
+        boundary: [u8; 32],
+        nonce: u64,
+        mix_hash: [u8; 32],
+    }
+
+    impl SealableHeader for TestHeader {
+        fn number(&self) -> u64 {
+            self.number
+        }
+
+        fn seal_hash(&self) -> [u8; 32] {
+            keccak256(&self.number.to_be_bytes())
+        }
+
+        fn boundary(&self) -> [u8; 32] {
+            self.boundary
+        }
+
+        fn nonce(&self) -> u64 {
+            self.nonce
+        }
+
+        fn mix_hash(&self) -> [u8; 32] {
+            self.mix_hash
+        }
+
+        fn set_seal(&mut self, nonce: u64, mix_hash: [u8; 32]) {
+            self.nonce = nonce;
+            self.mix_hash = mix_hash;
+        }
+    }
+
+    #[test]
+    fn test_progpow_engine_seals_and_verifies_headers() {
+        let caches = tiny_cache_manager(1);
+        let engine = ProgpowEngine::new(Arc::new(caches));
+        let mut boundary = [0xff; 32];
+        boundary[0] = 0x0f;
+        let mut header = TestHeader {
+            number: 30001,
+            boundary,
+            nonce: 0,
+            mix_hash: [0; 32],
+        };
+
+        let work = engine.prepare_work(&header).unwrap();
+        assert_eq!(work.seed_hash, seed_hash(1));
+        assert_eq!(work.header_hash, header.seal_hash());
+        assert!(matches!(
+            engine.verify_header_seal(&header),
+            Err(EngineError::InvalidSeal(_))
+        ));
+
+        assert!(engine.seal(&mut header, 0..256).unwrap());
+        assert_eq!(engine.verify_header_seal(&header), Ok(()));
+        header.nonce += 1;
+        assert!(engine.verify_header_seal(&header).is_err());
+
+        header.number = MAX_EPOCH * EPOCH_LENGTH;
+        assert_eq!(
+            engine.prepare_work(&header),
+            Err(EngineError::UnsupportedEpoch { epoch: MAX_EPOCH })
+        );
+    }
+
+    #[test]
+    fn test_verify_header_from_rlp() {
+        let caches = Arc::new(tiny_cache_manager(1));
+        let rlp = encode_header(30001, 16, 0, [0; 32]);
+        let mut header = Header::decode(&rlp).unwrap();
+        assert_eq!(header.boundary()[0], 0x10);
+        assert!(matches!(
+            verify_header(&rlp, &caches),
+            Err(EngineError::InvalidSeal(_))
+        ));
+
+        let engine = ProgpowEngine::new(caches.clone());
+        assert!(engine.seal(&mut header, 0..1024).unwrap());
+        let sealed = header.encode(true);
+        assert_eq!(verify_header(&sealed, &caches), Ok(()));
+
+        assert!(matches!(
+            verify_header(&sealed[1..], &caches),
+            Err(EngineError::InvalidHeader(_))
+        ));
+        // Ommers one block into the previous epoch use its cache.
+        let ommer = encode_header(29999, 1, 0, [0; 32]);
+        let mut ommer_header = Header::decode(&ommer).unwrap();
+        assert!(engine.seal(&mut ommer_header, 0..1).unwrap());
+        let ommer = ommer_header.encode(true);
+        assert_eq!(
+            verify_header_with_ommers(&sealed, &[&ommer, &ommer], &caches),
+            Ok(())
+        );
+        assert_eq!(
+            engine.verify_with_ommers(&header, &[ommer_header.clone()]),
+            Ok(())
+        );
+        assert!(matches!(
+            verify_header_with_ommers(&sealed, &[&ommer, &rlp], &caches),
+            Err(EngineError::InvalidOmmer { index: 1, error })
+                if matches!(*error, EngineError::InvalidSeal(_))
+        ));
+        ommer_header.nonce += 1;
+        assert!(matches!(
+            engine.verify_with_ommers(&header, &[ommer_header]),
+            Err(EngineError::InvalidOmmer { index: 0, .. })
+        ));
+
+        // The difficulty is checked against the parent's first.
+        let rule = EthashRule {
+            minimum: U256::ONE,
+            bomb_delay: None,
+        };
+        let parent = encode_header(30000, 16, 0, [0; 32]);
+        assert_eq!(
+            verify_header_with_parents(&sealed, &[&parent], &caches, &rule),
+            Ok(())
+        );
+        let harder = encode_header(30000, 1 << 20, 0, [0; 32]);
+        assert!(matches!(
+            verify_header_with_parents(&sealed, &[&harder], &caches, &rule),
+            Err(EngineError::InvalidDifficulty(
+                DifficultyError::Mismatch { .. }
+            ))
+        ));
+        assert!(matches!(
+            verify_header_with_parents(&rlp, &[&parent], &caches, &rule),
+            Err(EngineError::InvalidSeal(_))
+        ));
+
+        let far = encode_header(MAX_EPOCH * EPOCH_LENGTH, 1, 0, [0; 32]);
+        assert_eq!(
+            verify_header(&far, &caches),
+            Err(EngineError::UnsupportedEpoch { epoch: MAX_EPOCH })
+        );
+    }
+}
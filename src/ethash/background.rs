@@ -0,0 +1,460 @@
+//! Cache and dataset generation on a background thread.
+//!
+//! Generating a light cache takes a second or more and a full dataset
+//! minutes, so the first block of a new epoch either blocks the thread
+//! verifying it or is turned away. A [`BackgroundGenerator`] generates on a
+//! dedicated thread of its own, one request at a time in the order they were
+//! made, and hands back a [`Ready`] for each. A consumer waits on it, polls
+//! it, or awaits it as a [`Future`] on any executor, so blocks of the new
+//! epoch queue until their cache exists; [`subscribe`](BackgroundGenerator::subscribe)
+//! also reports every finished generation on a channel.
+//!
+//! On a host that also serves verification, a [`Throttle`] keeps the
+//! generation from starving it: a lower scheduling priority, fewer dataset
+//! threads, and pauses between dataset chunks.
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::endian::in_memory;
+use crate::ethash::buffer::DagBuffer;
+use crate::ethash::dataset::{fill_items_on, HASH_WORDS};
+use crate::ethash::manager::{CacheManager, EpochCache};
+
+/// A generation queued on the background thread.
+type Job = Box<dyn FnOnce() + Send>;
+
+/// What a [`BackgroundGenerator`] finished generating.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Generated {
+    /// The light cache of an epoch, now held by the cache manager.
+    Cache(u64),
+    /// The full dataset of an epoch.
+    Dataset(u64),
+}
+
+/// The reason a [`Ready`] never became ready: its generation panicked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GenerationFailed;
+
+impl fmt::Display for GenerationFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("background generation panicked")
+    }
+}
+
+impl std::error::Error for GenerationFailed {}
+
+/// How gently a [`BackgroundGenerator`] generates.
+///
+/// The default generates as fast as the machine allows.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Throttle {
+    /// The nice level of the generation thread and the dataset threads it
+    /// starts, from 0 (unchanged) to 19 (lowest priority). Only Linux sets a
+    /// nice level per thread, so elsewhere it is ignored.
+    pub nice: i32,
+    /// Threads generating dataset items, or 0 for one per core.
+    pub threads: usize,
+    /// Dataset items generated between pauses.
+    pub chunk_items: usize,
+    /// How long to pause after each chunk of dataset items.
+    pub pause: Duration,
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Throttle {
+            nice: 0,
+            threads: 0,
+            chunk_items: 1 << 16,
+            pause: Duration::ZERO,
+        }
+    }
+}
+
+/// Generates caches and datasets on a dedicated thread.
+///
+/// Caches are generated into a shared [`CacheManager`], with
+/// [`prefetch`](CacheManager::prefetch) so a coming epoch does not move its
+/// head, and requests for a cache already held are ready at once. Datasets
+/// are generated from the manager's cache and handed to the requester, which
+/// owns them from then on.
+///
+/// Dropping the generator finishes the requests already queued, then stops
+/// the thread.
+pub struct BackgroundGenerator {
+    caches: Arc<CacheManager>,
+    throttle: Arc<Throttle>,
+    jobs: Option<Sender<Job>>,
+    subscribers: Arc<Mutex<Vec<Sender<Generated>>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl BackgroundGenerator {
+    /// Starts a generator thread filling `caches`.
+    pub fn new(caches: Arc<CacheManager>) -> Self {
+        Self::with_throttle(caches, Throttle::default())
+    }
+
+    /// Starts a generator thread filling `caches`, generating as gently as
+    /// `throttle` says.
+    pub fn with_throttle(caches: Arc<CacheManager>, throttle: Throttle) -> Self {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let nice = throttle.nice;
+        let thread = thread::Builder::new()
+            .name("progpow-generate".into())
+            .spawn(move || {
+                set_nice(nice);
+                for job in queue {
+                    // A panicking job fails its own `Ready`, not the thread.
+                    let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(job));
+                }
+            })
+            .expect("cannot spawn the generation thread");
+        BackgroundGenerator {
+            caches,
+            throttle: Arc::new(throttle),
+            jobs: Some(jobs),
+            subscribers: Arc::default(),
+            thread: Some(thread),
+        }
+    }
+
+    /// Returns the cache manager the generator fills.
+    pub fn caches(&self) -> &Arc<CacheManager> {
+        &self.caches
+    }
+
+    /// Requests the cache of `epoch`.
+    ///
+    /// # Returns
+    ///
+    /// A handle that becomes ready with the cache once it is generated, at
+    /// once if the manager already holds it.
+    pub fn cache(&self, epoch: u64) -> Ready<Arc<EpochCache>> {
+        if self.caches.is_cached(epoch) {
+            return Ready::done(self.caches.prefetch(epoch));
+        }
+        let caches = self.caches.clone();
+        self.submit(Generated::Cache(epoch), move || caches.prefetch(epoch))
+    }
+
+    /// Requests the full dataset of `epoch`, generating its cache first if
+    /// the manager does not hold it.
+    ///
+    /// # Returns
+    ///
+    /// A handle that becomes ready with the dataset as little-endian words.
+    pub fn dataset(&self, epoch: u64) -> Ready<Arc<Vec<u32>>> {
+        let (caches, throttle) = (self.caches.clone(), self.throttle.clone());
+        self.submit(Generated::Dataset(epoch), move || {
+            let cache = caches.prefetch(epoch);
+            Arc::new(generate_throttled(
+                cache.cache_words(),
+                cache.size(),
+                &throttle,
+            ))
+        })
+    }
+
+    /// Returns a channel reporting every generation finished from now on.
+    ///
+    /// Dropping the receiver unsubscribes it.
+    pub fn subscribe(&self) -> Receiver<Generated> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Queues `generate` on the thread, reporting `generated` once done.
+    fn submit<T: Clone + Send + 'static>(
+        &self,
+        generated: Generated,
+        generate: impl FnOnce() -> T + Send + 'static,
+    ) -> Ready<T> {
+        let ready = Ready::pending();
+        let completer = Completer(Some(ready.shared.clone()));
+        let subscribers = self.subscribers.clone();
+        let job: Job = Box::new(move || {
+            completer.complete(generate());
+            subscribers
+                .lock()
+                .unwrap()
+                .retain(|subscriber| subscriber.send(generated).is_ok());
+        });
+        self.jobs
+            .as_ref()
+            .unwrap()
+            .send(job)
+            .expect("the generation thread stopped");
+        ready
+    }
+}
+
+impl Drop for BackgroundGenerator {
+    fn drop(&mut self) {
+        drop(self.jobs.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Generates the dataset of `size` bytes a chunk at a time, pausing between
+/// chunks as `throttle` says.
+fn generate_throttled(cache: &[u32], size: u64, throttle: &Throttle) -> Vec<u32> {
+    let threads = match throttle.threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        threads => threads,
+    };
+    let mut words = vec![0u32; in_memory(size) / 4];
+    let chunk_words = throttle.chunk_items.max(1) * HASH_WORDS;
+    for (index, chunk) in words.chunks_mut(chunk_words).enumerate() {
+        if index > 0 && !throttle.pause.is_zero() {
+            thread::sleep(throttle.pause);
+        }
+        let first = (index * throttle.chunk_items.max(1)) as u32;
+        fill_items_on(cache, first, chunk, threads);
+    }
+    words
+}
+
+/// Lowers the calling thread's priority to the nice level `nice`; threads
+/// it starts afterwards inherit it.
+#[cfg(target_os = "linux")]
+fn set_nice(nice: i32) {
+    extern "C" {
+        fn setpriority(which: i32, who: u32, prio: i32) -> i32;
+    }
+    const PRIO_PROCESS: i32 = 0;
+    if nice != 0 {
+        // SAFETY: setpriority takes no pointers. On Linux, `who` 0 with
+        // PRIO_PROCESS names the calling thread alone.
+        let _ = unsafe { setpriority(PRIO_PROCESS, 0, nice.clamp(0, 19)) };
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_nice(_nice: i32) {}
+
+/// A value being generated in the background.
+///
+/// Clones share the one generation. [`wait`](Self::wait) blocks until it is
+/// done, [`try_get`](Self::try_get) checks without blocking, and awaiting the
+/// handle suspends the task instead, waking it from the generation thread.
+pub struct Ready<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// The state of a [`Ready`], shared with the job completing it.
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    done: Condvar,
+}
+
+enum State<T> {
+    Pending(Vec<Waker>),
+    Done(T),
+    Failed,
+}
+
+impl<T: Clone> Ready<T> {
+    /// Creates a handle not yet ready.
+    fn pending() -> Self {
+        Ready {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State::Pending(Vec::new())),
+                done: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Creates a handle ready with `value`.
+    fn done(value: T) -> Self {
+        let ready = Self::pending();
+        *ready.shared.state.lock().unwrap() = State::Done(value);
+        ready
+    }
+
+    /// Returns `true` once the generation has finished or failed.
+    pub fn is_ready(&self) -> bool {
+        !matches!(*self.shared.state.lock().unwrap(), State::Pending(_))
+    }
+
+    /// Returns `true` if the generation panicked.
+    pub fn has_failed(&self) -> bool {
+        matches!(*self.shared.state.lock().unwrap(), State::Failed)
+    }
+
+    /// Returns the value if it has been generated, without blocking.
+    pub fn try_get(&self) -> Option<T> {
+        match &*self.shared.state.lock().unwrap() {
+            State::Done(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    /// Blocks until the value has been generated.
+    ///
+    /// # Returns
+    ///
+    /// The value, or [`GenerationFailed`] if its generation panicked.
+    pub fn wait(&self) -> Result<T, GenerationFailed> {
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            match &*state {
+                State::Pending(_) => state = self.shared.done.wait(state).unwrap(),
+                State::Done(value) => return Ok(value.clone()),
+                State::Failed => return Err(GenerationFailed),
+            }
+        }
+    }
+}
+
+impl<T> Clone for Ready<T> {
+    fn clone(&self) -> Self {
+        Ready {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T: Clone> Future for Ready<T> {
+    type Output = Result<T, GenerationFailed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match &mut *self.shared.state.lock().unwrap() {
+            State::Pending(wakers) => {
+                if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+                    wakers.push(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+            State::Done(value) => Poll::Ready(Ok(value.clone())),
+            State::Failed => Poll::Ready(Err(GenerationFailed)),
+        }
+    }
+}
+
+/// Completes a [`Ready`], or fails it if dropped first, as when its
+/// generation panics.
+struct Completer<T>(Option<Arc<Shared<T>>>);
+
+impl<T> Completer<T> {
+    fn complete(mut self, value: T) {
+        let shared = self.0.take().unwrap();
+        Self::finish(&shared, State::Done(value));
+    }
+
+    fn finish(shared: &Shared<T>, outcome: State<T>) {
+        let previous = std::mem::replace(&mut *shared.state.lock().unwrap(), outcome);
+        shared.done.notify_all();
+        if let State::Pending(wakers) = previous {
+            wakers.into_iter().for_each(Waker::wake);
+        }
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    fn drop(&mut self) {
+        if let Some(shared) = self.0.take() {
+            Self::finish(&shared, State::Failed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ethash::cache::{make_cache, seed_hash};
+    use crate::ethash::dataset::generate_dataset;
+    use std::sync::mpsc::TryRecvError;
+    use std::task::Wake;
+    use std::time::Instant;
+
+    /// Wakes the thread blocked in [`block_on`].
+    struct Unpark(thread::Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// Polls `future` to completion on this thread, parking between polls.
+    fn block_on<F: Future + Unpin>(mut future: F) -> F::Output {
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match Pin::new(&mut future).poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_background_generation_reports_readiness() {
+        let caches = Arc::new(CacheManager::with_generator(4, |epoch| {
+            if epoch == 9 {
+                panic!("no cache for epoch 9");
+            }
+            EpochCache::new(epoch, make_cache(1024, &seed_hash(epoch)), 1 << 12)
+        }));
+        let generator = BackgroundGenerator::new(caches.clone());
+        let events = generator.subscribe();
+
+        let cache = generator.cache(3);
+        let waiter = cache.clone();
+        let waiting = thread::spawn(move || waiter.wait().unwrap().epoch());
+        let dataset = generator.dataset(3);
+        assert_eq!(waiting.join().unwrap(), 3);
+        assert!(caches.is_cached(3));
+        assert_eq!(caches.head_epoch(), None);
+
+        // The future resolves on a bare executor woken by the thread.
+        let words = block_on(dataset).unwrap();
+        let expected = generate_dataset(&make_cache(1024, &seed_hash(3)), 1 << 12);
+        assert_eq!(*words, expected);
+        assert_eq!(events.recv().unwrap(), Generated::Cache(3));
+        assert_eq!(events.recv().unwrap(), Generated::Dataset(3));
+
+        // Held caches are ready at once, and a failed generation fails its
+        // handle without stopping the thread.
+        assert!(generator.cache(3).is_ready());
+        let failed = generator.cache(9);
+        assert_eq!(failed.wait().err(), Some(GenerationFailed));
+        assert!(failed.has_failed() && failed.try_get().is_none());
+        assert_eq!(generator.cache(4).wait().unwrap().epoch(), 4);
+        assert_eq!(events.recv().unwrap(), Generated::Cache(4));
+        assert_eq!(events.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn test_throttled_generation_pauses_between_chunks() {
+        let caches = Arc::new(crate::testutil::tiny_cache_manager(1));
+        let throttle = Throttle {
+            nice: 5,
+            threads: 1,
+            chunk_items: 256,
+            pause: Duration::from_millis(20),
+        };
+        let generator = BackgroundGenerator::with_throttle(caches, throttle);
+        generator.cache(0).wait().unwrap();
+
+        let started = Instant::now();
+        let words = generator.dataset(0).wait().unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(60));
+        let cache = generator.caches().get(0);
+        assert_eq!(*words, generate_dataset(cache.cache_words(), cache.size()));
+    }
+}
@@ -0,0 +1,225 @@
+use crate::basic_algorithm::PROGPOW_CACHE_WORDS;
+use crate::endian::le_words;
+use crate::ethash::dataset::{calc_dataset_item, HASH_WORDS};
+
+/// Bytes in one dataset item.
+const ITEM_BYTES: usize = HASH_WORDS * 4;
+
+/// A dataset (DAG) that ProgPoW can read items from, wherever it is stored.
+///
+/// Implementations exist for a DAG held in memory as words, a DAG computed on
+/// demand from the light cache ([`LightDag`]), a memory-mapped DAG file
+/// (`MmapDag`, behind the `mmap` feature), a DAG in a named shared-memory
+/// segment (`SharedDag`, behind the `shm` feature), a DAG in huge pages
+/// (`HugePageDag`, behind the `hugepages` feature), a DAG placed over NUMA
+/// nodes (`NumaDag`, behind the `numa` feature), a DAG file read through
+/// io_uring (`UringDag`, behind the `io-uring` feature), a DAG served by
+/// another machine ([`RemoteDag`](crate::ethash::remote::RemoteDag)), and a
+/// DAG resident on an OpenCL device.
+///
+/// The hashing, verification and mining code take a `size`, the cached DAG
+/// words and a lookup function; [`DagBuffer::size`], [`DagBuffer::c_dag`]
+/// and `|index| dag.lookup(index)` provide exactly those, so the same code
+/// runs against any of these buffers.
+pub trait DagBuffer: Send + Sync {
+    /// Returns the size of the dataset in bytes.
+    fn size(&self) -> u64;
+
+    /// Copies the 64-byte item `index` into `out`.
+    ///
+    /// # Panics
+    ///
+    /// May panic if `index` is past the end of the dataset.
+    fn read_item(&self, index: u32, out: &mut [u8; 64]);
+
+    /// Returns the item containing DAG word `word_index`, as `progpow()`'s
+    /// `lookup` argument expects.
+    fn lookup(&self, word_index: u32) -> Vec<u8> {
+        let mut item = [0u8; ITEM_BYTES];
+        self.read_item(word_index / HASH_WORDS as u32, &mut item);
+        item.to_vec()
+    }
+
+    /// Returns the cached DAG words: the first `PROGPOW_CACHE_WORDS` words.
+    fn c_dag(&self) -> Vec<u32> {
+        let mut words = Vec::with_capacity(PROGPOW_CACHE_WORDS);
+        let mut item = [0u8; ITEM_BYTES];
+        for index in 0..(PROGPOW_CACHE_WORDS / HASH_WORDS) as u32 {
+            self.read_item(index, &mut item);
+            words.extend(le_words(&item));
+        }
+        words
+    }
+}
+
+/// A full DAG held in memory as little-endian words.
+impl DagBuffer for Vec<u32> {
+    fn size(&self) -> u64 {
+        self.len() as u64 * 4
+    }
+
+    fn read_item(&self, index: u32, out: &mut [u8; 64]) {
+        let first = index as usize * HASH_WORDS;
+        for (chunk, word) in out
+            .chunks_exact_mut(4)
+            .zip(&self[first..first + HASH_WORDS])
+        {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+    }
+
+    fn c_dag(&self) -> Vec<u32> {
+        self[..PROGPOW_CACHE_WORDS].to_vec()
+    }
+}
+
+/// A DAG whose items are computed from the light cache on every read.
+///
+/// It needs only the cache's memory, at the cost of 256 cache reads per
+/// item, which suits verifiers checking a few seals.
+pub struct LightDag {
+    cache: Vec<u32>,
+    size: u64,
+}
+
+impl LightDag {
+    /// Creates a light DAG of `size` bytes over `cache`.
+    ///
+    /// # Arguments
+    ///
+    /// * `cache` - The ethash light cache as little-endian words.
+    /// * `size` - The size of the full dataset in bytes.
+    pub fn new(cache: Vec<u32>, size: u64) -> Self {
+        LightDag { cache, size }
+    }
+
+    /// Returns the light cache the items are computed from.
+    pub(crate) fn cache(&self) -> &[u32] {
+        &self.cache
+    }
+}
+
+impl DagBuffer for LightDag {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn read_item(&self, index: u32, out: &mut [u8; 64]) {
+        *out = calc_dataset_item(&self.cache, index);
+    }
+}
+
+/// A DAG file mapped into memory.
+///
+/// The file holds the dataset as raw little-endian items, optionally
+/// preceded by the 8-byte magic number go-ethereum writes to its DAG files.
+#[cfg(feature = "mmap")]
+pub struct MmapDag {
+    map: memmap2::Mmap,
+    offset: usize,
+}
+
+/// The first 8 bytes of a go-ethereum DAG dump.
+pub const GETH_DUMP_MAGIC: [u8; 8] = [0xfe, 0xca, 0xdd, 0xba, 0xad, 0xde, 0xe1, 0xfe];
+
+#[cfg(feature = "mmap")]
+impl MmapDag {
+    /// Maps the DAG file at `path` read-only.
+    ///
+    /// # Returns
+    ///
+    /// The mapped DAG, or an I/O error if the file cannot be opened or
+    /// mapped, does not hold whole 64-byte items, or is too short to hold
+    /// the `PROGPOW_CACHE_WORDS` cached words.
+    pub fn open(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        // SAFETY: the map is read-only; callers must not truncate the file
+        // while it is mapped, as for any file-backed DAG.
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        Self::from_map(map)
+    }
+
+    /// Wraps a map holding a DAG file's contents, checked as by
+    /// [`open`](Self::open).
+    pub(crate) fn from_map(map: memmap2::Mmap) -> std::io::Result<Self> {
+        let offset = if map.starts_with(&GETH_DUMP_MAGIC) {
+            GETH_DUMP_MAGIC.len()
+        } else {
+            0
+        };
+        let invalid = |message| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+        if !(map.len() - offset).is_multiple_of(ITEM_BYTES) {
+            return Err(invalid("DAG file does not hold whole 64-byte items"));
+        }
+        if map.len() - offset < PROGPOW_CACHE_WORDS * 4 {
+            return Err(invalid("DAG file is shorter than the cached DAG words"));
+        }
+        Ok(MmapDag { map, offset })
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl DagBuffer for MmapDag {
+    fn size(&self) -> u64 {
+        (self.map.len() - self.offset) as u64
+    }
+
+    fn read_item(&self, index: u32, out: &mut [u8; 64]) {
+        let first = self.offset + index as usize * ITEM_BYTES;
+        out.copy_from_slice(&self.map[first..first + ITEM_BYTES]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache() -> Vec<u32> {
+        (0..64 * HASH_WORDS as u32)
+            .map(|i| i.wrapping_mul(0x9e3779b9))
+            .collect()
+    }
+
+    /// The full DAG for `cache`, as stored in memory.
+    fn full_dag(cache: &[u32], items: u32) -> Vec<u32> {
+        (0..items)
+            .flat_map(|index| le_words(&calc_dataset_item(cache, index)))
+            .collect()
+    }
+
+    #[test]
+    fn test_dag_buffers_agree() {
+        let light = LightDag::new(cache(), 512 * 64);
+        let full = full_dag(&cache(), 512);
+
+        assert_eq!(full.size(), light.size());
+        assert_eq!(full.c_dag(), light.c_dag());
+        for word in [0, 17, 16 * 300 + 3, 16 * 511] {
+            assert_eq!(full.lookup(word), light.lookup(word));
+        }
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mmap_dag_skips_geth_magic() {
+        let full = full_dag(&cache(), 300);
+        let mut bytes = GETH_DUMP_MAGIC.to_vec();
+        bytes.extend(full.iter().flat_map(|word| word.to_le_bytes()));
+        let path = std::env::temp_dir().join(format!("progpow-dag-{}.bin", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+
+        let mapped = MmapDag::open(&path).unwrap();
+        assert_eq!(mapped.size(), full.size());
+        assert_eq!(mapped.c_dag(), full.c_dag());
+        assert_eq!(mapped.lookup(16 * 299), full.lookup(16 * 299));
+        // Windows refuses to truncate a mapped file.
+        drop(mapped);
+
+        std::fs::write(&path, &bytes[..100]).unwrap();
+        assert!(MmapDag::open(&path).is_err());
+        // Whole items, but fewer than the cached words need.
+        std::fs::write(&path, &bytes[..8 + 64 * 200]).unwrap();
+        assert!(MmapDag::open(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
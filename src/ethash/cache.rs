@@ -0,0 +1,264 @@
+use std::future::Future;
+
+use crate::endian::le_words;
+use crate::ethash::dataset::HASH_WORDS;
+use crate::hex;
+use crate::keccak::f1600::{keccak256, keccak512};
+
+/// Number of blocks per ethash epoch.
+pub const EPOCH_LENGTH: u64 = 30000;
+
+/// Epochs covered by go-ethereum's size tables; services and bindings
+/// reject later epochs rather than generate caches for them.
+pub const MAX_EPOCH: u64 = 2048;
+
+/// Bytes in the light cache at epoch 0.
+pub(crate) const CACHE_BYTES_INIT: u64 = 1 << 24;
+
+/// Bytes the light cache grows by per epoch.
+pub(crate) const CACHE_BYTES_GROWTH: u64 = 1 << 17;
+
+/// Bytes in the dataset at epoch 0.
+pub(crate) const DATASET_BYTES_INIT: u64 = 1 << 30;
+
+/// Bytes the dataset grows by per epoch.
+pub(crate) const DATASET_BYTES_GROWTH: u64 = 1 << 23;
+
+/// Bytes in one cache row.
+pub(crate) const HASH_BYTES: u64 = 64;
+
+/// Bytes in one ethash mix; the dataset is a whole number of mixes.
+pub(crate) const MIX_BYTES: u64 = 128;
+
+/// Number of RandMemoHash passes over the cache.
+const CACHE_ROUNDS: usize = 3;
+
+/// Returns the epoch `block_number` belongs to.
+pub fn epoch(block_number: u64) -> u64 {
+    block_number / EPOCH_LENGTH
+}
+
+/// Returns `true` if `n` is prime, by trial division.
+fn is_prime(n: u64) -> bool {
+    n >= 2
+        && (2..)
+            .take_while(|d| d * d <= n)
+            .all(|d| !n.is_multiple_of(d))
+}
+
+/// Returns the largest size below `upper` made of a prime number of `unit`s.
+pub(crate) fn prime_sized(upper: u64, unit: u64) -> u64 {
+    let mut size = upper - unit;
+    while !is_prime(size / unit) {
+        size -= 2 * unit;
+    }
+    size
+}
+
+/// Returns the size in bytes of the light cache for `epoch`.
+pub fn cache_size(epoch: u64) -> u64 {
+    prime_sized(CACHE_BYTES_INIT + CACHE_BYTES_GROWTH * epoch, HASH_BYTES)
+}
+
+/// Returns the size in bytes of the full dataset (the DAG) for `epoch`.
+pub fn dataset_size(epoch: u64) -> u64 {
+    prime_sized(DATASET_BYTES_INIT + DATASET_BYTES_GROWTH * epoch, MIX_BYTES)
+}
+
+/// Returns the seed hash of `epoch`: Keccak-256 applied `epoch` times to
+/// 32 zero bytes.
+pub fn seed_hash(epoch: u64) -> [u8; 32] {
+    (0..epoch).fold([0u8; 32], |seed, _| keccak256(&seed))
+}
+
+/// go-ethereum's ethash algorithm revision, part of its cache and DAG file
+/// names.
+pub const GETH_REVISION: u32 = 23;
+
+/// Returns go-ethereum's file name for the light cache of the epoch whose
+/// seed hash is `seed`: `cache-R23-` and the seed's first 8 bytes in hex.
+pub fn cache_file_name(seed: &[u8; 32]) -> String {
+    format!("cache-R{GETH_REVISION}-{}", seed_prefix(seed))
+}
+
+/// Returns go-ethereum's file name for the full dataset of the epoch whose
+/// seed hash is `seed`: `full-R23-` and the seed's first 8 bytes in hex.
+pub fn dataset_file_name(seed: &[u8; 32]) -> String {
+    format!("full-R{GETH_REVISION}-{}", seed_prefix(seed))
+}
+
+/// Formats the first 8 bytes of a seed hash as hex.
+fn seed_prefix(seed: &[u8; 32]) -> String {
+    hex::encode(&seed[..8])
+}
+
+/// Generates the ethash light cache from a seed hash.
+///
+/// # Arguments
+///
+/// * `size` - The cache size in bytes, a multiple of 64, as returned by
+///   [`cache_size`].
+/// * `seed` - The epoch's seed hash, as returned by [`seed_hash`].
+///
+/// # Returns
+///
+/// The cache as little-endian words, as `calc_dataset_item` expects.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(seed)))]
+pub fn make_cache(size: u64, seed: &[u8; 32]) -> Vec<u32> {
+    CacheBuilder::from_seed(size, seed).finish()
+}
+
+/// Generates a light cache incrementally, a bounded number of rows at a time.
+///
+/// Building a cache takes one Keccak-512 per row for the initial chain and
+/// for each of the memory-hard rounds. [`CacheBuilder::step`] does at most a
+/// given number of those hashes and returns, so single-threaded hosts such
+/// as a browser page can interleave generation with other work.
+pub struct CacheBuilder {
+    rows: Vec<[u8; 64]>,
+    /// 0 while chaining the initial rows, then the 1-based memory-hard round.
+    stage: usize,
+    next: usize,
+}
+
+impl CacheBuilder {
+    /// Starts building the cache of `epoch`.
+    pub fn new(epoch: u64) -> Self {
+        Self::from_seed(cache_size(epoch), &seed_hash(epoch))
+    }
+
+    /// Starts building a cache of `size` bytes from `seed`.
+    pub fn from_seed(size: u64, seed: &[u8; 32]) -> Self {
+        let mut rows = vec![[0u8; 64]; (size / HASH_BYTES) as usize];
+        rows[0] = keccak512(seed);
+        CacheBuilder {
+            rows,
+            stage: 0,
+            next: 1,
+        }
+    }
+
+    /// Computes up to `max_rows` more rows.
+    ///
+    /// # Returns
+    ///
+    /// `true` once the cache is complete.
+    pub fn step(&mut self, max_rows: usize) -> bool {
+        let n = self.rows.len();
+        for _ in 0..max_rows {
+            if self.next == n {
+                if self.stage == CACHE_ROUNDS {
+                    break;
+                }
+                self.stage += 1;
+                self.next = 0;
+            }
+            let i = self.next;
+            if self.stage == 0 {
+                // Sequentially chain Keccak-512 from the seed.
+                self.rows[i] = keccak512(&self.rows[i - 1]);
+            } else {
+                // Strict memory-hard hashing over the whole cache.
+                let other = u32::from_le_bytes(self.rows[i][..4].try_into().unwrap()) as usize % n;
+                let previous = &self.rows[(i + n - 1) % n];
+                let mut mixed = [0u8; 64];
+                for (byte, (a, b)) in mixed.iter_mut().zip(previous.iter().zip(&self.rows[other])) {
+                    *byte = a ^ b;
+                }
+                self.rows[i] = keccak512(&mixed);
+            }
+            self.next += 1;
+        }
+        self.is_done()
+    }
+
+    /// Returns `true` once every row of every round has been computed.
+    pub fn is_done(&self) -> bool {
+        self.stage == CACHE_ROUNDS && self.next == self.rows.len()
+    }
+
+    /// Returns the fraction of the work done so far, from 0 to 1.
+    pub fn progress(&self) -> f64 {
+        let total = (CACHE_ROUNDS + 1) * self.rows.len();
+        (self.stage * self.rows.len() + self.next) as f64 / total as f64
+    }
+
+    /// Runs the builder to completion, awaiting `yield_now()` after every
+    /// `rows_per_yield` rows.
+    ///
+    /// `yield_now` is how the host gives control back to its event loop,
+    /// for example a future resolving on the next browser timer tick.
+    pub async fn build<F, Y>(mut self, rows_per_yield: usize, mut yield_now: Y) -> Vec<u32>
+    where
+        F: Future<Output = ()>,
+        Y: FnMut() -> F,
+    {
+        while !self.step(rows_per_yield.max(1)) {
+            yield_now().await;
+        }
+        self.finish()
+    }
+
+    /// Completes any remaining work and returns the cache as little-endian
+    /// words, as `calc_dataset_item` expects.
+    pub fn finish(mut self) -> Vec<u32> {
+        while !self.step(usize::MAX) {}
+        let mut words = Vec::with_capacity(self.rows.len() * HASH_WORDS);
+        for row in &self.rows {
+            words.extend(le_words(row));
+        }
+        words
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ethash::dataset::calc_dataset_item;
+
+    #[test]
+    fn test_epoch_parameters() {
+        assert_eq!(epoch(29999), 0);
+        assert_eq!(epoch(30000), 1);
+        assert_eq!(cache_size(0), 16776896);
+        assert_eq!(dataset_size(0), 1073739904);
+        assert_eq!(cache_size(100), 29882816);
+        assert_eq!(dataset_size(100), 1912601216);
+        assert_eq!(seed_hash(0), [0; 32]);
+        assert_eq!(seed_hash(1), keccak256(&[0; 32]));
+        assert_eq!(cache_file_name(&seed_hash(1)), "cache-R23-290decd9548b62a8");
+        assert_eq!(dataset_file_name(&[0; 32]), "full-R23-0000000000000000");
+    }
+
+    #[test]
+    fn test_make_cache_small() {
+        // Cross-checked against an independent ethash implementation.
+        let cache = make_cache(1024, &seed_hash(1));
+        assert_eq!(cache.len(), 1024 / 4);
+        assert_eq!(cache[..2], [0x5d85561f, 0x085acc59]);
+        assert_eq!(calc_dataset_item(&cache, 3)[..4], [0xb3, 0x62, 0x35, 0x1f]);
+    }
+
+    #[test]
+    fn test_cache_builder_yields_between_chunks() {
+        let mut builder = CacheBuilder::from_seed(1024, &seed_hash(1));
+        assert!(!builder.step(10));
+        assert_eq!(builder.progress(), 11.0 / 64.0);
+
+        let mut yields = 0;
+        let cache = {
+            let build = builder.build(5, || {
+                yields += 1;
+                std::future::ready(())
+            });
+            let mut build = std::pin::pin!(build);
+            let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+            match build.as_mut().poll(&mut context) {
+                std::task::Poll::Ready(cache) => cache,
+                std::task::Poll::Pending => panic!("build did not complete"),
+            }
+        };
+        assert_eq!(cache, make_cache(1024, &seed_hash(1)));
+        assert_eq!(yields, (64 - 11usize).div_ceil(5) - 1);
+    }
+}
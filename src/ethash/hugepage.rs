@@ -0,0 +1,396 @@
+//! A full DAG in memory backed by huge pages.
+//!
+//! Full verification and mining read dataset items at random across
+//! gigabytes, so with 4 KB pages nearly every read misses the TLB. A
+//! [`HugePageDag`] holds the dataset in an anonymous map of 1 GB or 2 MB
+//! pages from the kernel's hugetlb pool, which covers the whole dataset with
+//! a handful of TLB entries. Pools are often empty or unconfigured, so each
+//! page size falls back to the next smaller one, then to transparent huge
+//! pages requested with `madvise`, then to ordinary pages;
+//! [`HugePageDag::page_size`] reports what was obtained.
+//!
+//! On Windows, 2 MB pages are the system's large pages from `VirtualAlloc`.
+//! They need the account to hold the "Lock pages in memory" right, which
+//! [`acquire_large_page_privilege`] enables for the process and reports on;
+//! without it, or for 1 GB pages, the DAG falls back to ordinary pages.
+
+use std::io;
+
+use crate::ethash::buffer::DagBuffer;
+use crate::ethash::dataset::generate_dataset_chunks;
+
+/// Dataset items generated at a time while filling the map.
+const ITEMS_PER_CHUNK: usize = 1 << 16;
+
+/// The pages backing a [`HugePageDag`], from the largest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PageSize {
+    /// 1 GB pages from the hugetlb pool.
+    Huge1G,
+    /// 2 MB pages from the hugetlb pool, or Windows large pages.
+    Huge2M,
+    /// Ordinary pages the kernel was asked to merge into transparent huge
+    /// pages.
+    Transparent,
+    /// Ordinary pages.
+    Normal,
+}
+
+impl PageSize {
+    /// Returns the pages to try, from `self` down to [`PageSize::Normal`].
+    fn fallbacks(self) -> impl Iterator<Item = PageSize> {
+        [
+            PageSize::Huge1G,
+            PageSize::Huge2M,
+            PageSize::Transparent,
+            PageSize::Normal,
+        ]
+        .into_iter()
+        .filter(move |size| *size >= self)
+    }
+}
+
+/// A dataset held in an anonymous map of the largest pages available.
+pub struct HugePageDag {
+    memory: Memory,
+    size: u64,
+    page_size: PageSize,
+}
+
+impl HugePageDag {
+    /// Generates the dataset into a map of the largest pages available, no
+    /// larger than `preferred`.
+    ///
+    /// # Arguments
+    ///
+    /// * `cache` - The ethash light cache as little-endian words.
+    /// * `size` - The size of the dataset in bytes, a multiple of 64.
+    /// * `preferred` - The largest pages to try.
+    ///
+    /// # Returns
+    ///
+    /// The DAG, or an I/O error if not even ordinary pages could be mapped.
+    pub fn generate(cache: &[u32], size: u64, preferred: PageSize) -> io::Result<Self> {
+        Self::fill(size, preferred, |bytes| {
+            let mut out = bytes.chunks_exact_mut(4);
+            generate_dataset_chunks(cache, size, ITEMS_PER_CHUNK, |chunk| {
+                for (word, out) in chunk.iter().zip(&mut out) {
+                    out.copy_from_slice(&word.to_le_bytes());
+                }
+                Ok::<_, io::Error>(())
+            })
+        })
+    }
+
+    /// Copies a dataset already in memory as little-endian words into a map of
+    /// the largest pages available, no larger than `preferred`.
+    pub fn from_words(words: &[u32], preferred: PageSize) -> io::Result<Self> {
+        Self::fill(words.len() as u64 * 4, preferred, |bytes| {
+            for (out, word) in bytes.chunks_exact_mut(4).zip(words) {
+                out.copy_from_slice(&word.to_le_bytes());
+            }
+            Ok(())
+        })
+    }
+
+    /// Returns the pages the dataset is held in.
+    pub fn page_size(&self) -> PageSize {
+        self.page_size
+    }
+
+    /// Maps `size` bytes and has `write` fill them.
+    fn fill(
+        size: u64,
+        preferred: PageSize,
+        write: impl FnOnce(&mut [u8]) -> io::Result<()>,
+    ) -> io::Result<Self> {
+        let (mut memory, page_size) = allocate(size as usize, preferred)?;
+        write(&mut memory.bytes_mut()[..size as usize])?;
+        Ok(HugePageDag {
+            memory,
+            size,
+            page_size,
+        })
+    }
+}
+
+impl DagBuffer for HugePageDag {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn read_item(&self, index: u32, out: &mut [u8; 64]) {
+        let first = index as usize * 64;
+        out.copy_from_slice(&self.memory.bytes()[first..first + 64]);
+    }
+}
+
+/// Memory holding a [`HugePageDag`].
+enum Memory {
+    /// An anonymous map.
+    Mapped(memmap2::MmapMut),
+    /// Windows large pages.
+    #[cfg(windows)]
+    Large(windows::LargePages),
+}
+
+impl Memory {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Memory::Mapped(map) => map,
+            #[cfg(windows)]
+            Memory::Large(pages) => pages.bytes(),
+        }
+    }
+
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        match self {
+            Memory::Mapped(map) => map,
+            #[cfg(windows)]
+            Memory::Large(pages) => pages.bytes_mut(),
+        }
+    }
+}
+
+/// Allocates at least `bytes` bytes in the largest pages available, no
+/// larger than `preferred`.
+fn allocate(bytes: usize, preferred: PageSize) -> io::Result<(Memory, PageSize)> {
+    let bytes = bytes.max(1);
+    for page_size in preferred.fallbacks() {
+        let memory = match page_size {
+            PageSize::Huge1G => hugetlb(bytes, 30).map(Memory::Mapped),
+            PageSize::Huge2M => large_pages(bytes),
+            PageSize::Transparent => transparent(bytes).map(Memory::Mapped),
+            PageSize::Normal => {
+                let map = memmap2::MmapMut::map_anon(bytes)?;
+                return Ok((Memory::Mapped(map), page_size));
+            }
+        };
+        if let Some(memory) = memory {
+            return Ok((memory, page_size));
+        }
+    }
+    unreachable!("the fallbacks end with ordinary pages")
+}
+
+/// Allocates `bytes` in 2 MB pages: from the hugetlb pool on Linux, as large
+/// pages on Windows.
+fn large_pages(bytes: usize) -> Option<Memory> {
+    #[cfg(windows)]
+    {
+        windows::LargePages::allocate(bytes).ok().map(Memory::Large)
+    }
+    #[cfg(not(windows))]
+    {
+        hugetlb(bytes, 21).map(Memory::Mapped)
+    }
+}
+
+/// Maps `bytes` rounded up to whole pages of `1 << page_bits` bytes from the
+/// hugetlb pool, or `None` if the pool cannot supply them.
+#[cfg(target_os = "linux")]
+fn hugetlb(bytes: usize, page_bits: u8) -> Option<memmap2::MmapMut> {
+    let len = bytes.div_ceil(1 << page_bits) << page_bits;
+    // Populated up front, so a pool too small for the map fails here rather
+    // than with SIGBUS on first touch.
+    memmap2::MmapOptions::new()
+        .len(len)
+        .huge(Some(page_bits))
+        .populate()
+        .map_anon()
+        .ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn hugetlb(_bytes: usize, _page_bits: u8) -> Option<memmap2::MmapMut> {
+    None
+}
+
+/// Maps `bytes` of ordinary pages the kernel is asked to back with
+/// transparent huge pages, or `None` if it does not support them.
+#[cfg(target_os = "linux")]
+fn transparent(bytes: usize) -> Option<memmap2::MmapMut> {
+    let map = memmap2::MmapMut::map_anon(bytes).ok()?;
+    map.advise(memmap2::Advice::HugePage).ok()?;
+    Some(map)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn transparent(_bytes: usize) -> Option<memmap2::MmapMut> {
+    None
+}
+
+/// Enables the "Lock pages in memory" privilege that Windows large pages
+/// need, for the whole process.
+///
+/// [`HugePageDag`] calls it before asking for large pages; call it first to
+/// tell users why they did not get them.
+///
+/// # Returns
+///
+/// `Ok(())`, or an error of kind [`io::ErrorKind::PermissionDenied`] if the
+/// account does not hold the right, which an administrator grants under
+/// Local Security Policy, User Rights Assignment.
+#[cfg(windows)]
+pub fn acquire_large_page_privilege() -> io::Result<()> {
+    windows::enable_lock_memory_privilege()
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::io;
+    use std::ptr;
+
+    use windows_sys::Win32::Foundation::{
+        CloseHandle, GetLastError, ERROR_NOT_ALL_ASSIGNED, HANDLE, LUID,
+    };
+    use windows_sys::Win32::Security::{
+        AdjustTokenPrivileges, LookupPrivilegeValueW, LUID_AND_ATTRIBUTES, SE_LOCK_MEMORY_NAME,
+        SE_PRIVILEGE_ENABLED, TOKEN_ADJUST_PRIVILEGES, TOKEN_PRIVILEGES, TOKEN_QUERY,
+    };
+    use windows_sys::Win32::System::Memory::{
+        GetLargePageMinimum, VirtualAlloc, VirtualFree, MEM_COMMIT, MEM_LARGE_PAGES, MEM_RELEASE,
+        MEM_RESERVE, PAGE_READWRITE,
+    };
+    use windows_sys::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+    /// Memory committed in large pages, released on drop.
+    pub struct LargePages {
+        ptr: *mut u8,
+        len: usize,
+    }
+
+    // SAFETY: the allocation is owned by this value alone, and shared
+    // access only reads it.
+    unsafe impl Send for LargePages {}
+    unsafe impl Sync for LargePages {}
+
+    impl LargePages {
+        /// Commits `bytes` rounded up to whole large pages.
+        pub fn allocate(bytes: usize) -> io::Result<Self> {
+            enable_lock_memory_privilege()?;
+            // SAFETY: GetLargePageMinimum takes no arguments.
+            let page = unsafe { GetLargePageMinimum() };
+            if page == 0 {
+                return Err(io::ErrorKind::Unsupported.into());
+            }
+            let len = bytes.div_ceil(page) * page;
+            // SAFETY: a fresh allocation at an address of the system's
+            // choosing, which touches no existing memory.
+            let ptr = unsafe {
+                VirtualAlloc(
+                    ptr::null(),
+                    len,
+                    MEM_RESERVE | MEM_COMMIT | MEM_LARGE_PAGES,
+                    PAGE_READWRITE,
+                )
+            };
+            if ptr.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(LargePages {
+                ptr: ptr.cast(),
+                len,
+            })
+        }
+
+        pub fn bytes(&self) -> &[u8] {
+            // SAFETY: `ptr` is a live, committed allocation of `len` bytes,
+            // zeroed by the system.
+            unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+        }
+
+        pub fn bytes_mut(&mut self) -> &mut [u8] {
+            // SAFETY: as in `bytes`, and `&mut self` makes this borrow unique.
+            unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+        }
+    }
+
+    impl Drop for LargePages {
+        fn drop(&mut self) {
+            // SAFETY: `ptr` came from VirtualAlloc and is released once.
+            unsafe { VirtualFree(self.ptr.cast(), 0, MEM_RELEASE) };
+        }
+    }
+
+    /// Enables SeLockMemoryPrivilege in the process token.
+    pub fn enable_lock_memory_privilege() -> io::Result<()> {
+        // SAFETY: every pointer passed refers to a local that outlives the
+        // call, and the token handle is closed once, after its last use.
+        unsafe {
+            let mut token: HANDLE = ptr::null_mut();
+            if OpenProcessToken(
+                GetCurrentProcess(),
+                TOKEN_ADJUST_PRIVILEGES | TOKEN_QUERY,
+                &mut token,
+            ) == 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+            let mut luid = LUID {
+                LowPart: 0,
+                HighPart: 0,
+            };
+            let result = if LookupPrivilegeValueW(ptr::null(), SE_LOCK_MEMORY_NAME, &mut luid) == 0
+            {
+                Err(io::Error::last_os_error())
+            } else {
+                let privileges = TOKEN_PRIVILEGES {
+                    PrivilegeCount: 1,
+                    Privileges: [LUID_AND_ATTRIBUTES {
+                        Luid: luid,
+                        Attributes: SE_PRIVILEGE_ENABLED,
+                    }],
+                };
+                // AdjustTokenPrivileges succeeds without granting a right
+                // the account lacks, and says so only in the last error.
+                if AdjustTokenPrivileges(token, 0, &privileges, 0, ptr::null_mut(), ptr::null_mut())
+                    == 0
+                {
+                    Err(io::Error::last_os_error())
+                } else if GetLastError() == ERROR_NOT_ALL_ASSIGNED {
+                    Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        "the account lacks the Lock pages in memory right",
+                    ))
+                } else {
+                    Ok(())
+                }
+            };
+            CloseHandle(token);
+            result
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ethash::dataset::generate_dataset;
+
+    #[test]
+    fn test_huge_page_dag_falls_back_and_agrees() {
+        let cache: Vec<u32> = (0..64 * 16u32)
+            .map(|i| i.wrapping_mul(0x9e3779b9))
+            .collect();
+        let size = 300 * 64;
+        let full = generate_dataset(&cache, size);
+
+        for preferred in [PageSize::Huge1G, PageSize::Huge2M, PageSize::Normal] {
+            let dag = HugePageDag::generate(&cache, size, preferred).unwrap();
+            assert!(dag.page_size() >= preferred);
+            assert_eq!(dag.size(), size);
+            assert_eq!(dag.c_dag(), full.c_dag());
+            assert_eq!(dag.lookup(16 * 299), full.lookup(16 * 299));
+        }
+        let copied = HugePageDag::from_words(&full, PageSize::Transparent).unwrap();
+        assert!(copied.page_size() >= PageSize::Transparent);
+        assert_eq!(copied.lookup(16 * 77), full.lookup(16 * 77));
+        assert_eq!(
+            HugePageDag::from_words(&full, PageSize::Normal)
+                .unwrap()
+                .page_size(),
+            PageSize::Normal
+        );
+    }
+}
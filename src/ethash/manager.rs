@@ -0,0 +1,641 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::endian::{le_bytes, le_words};
+use crate::ethash::buffer::{DagBuffer, LightDag};
+use crate::ethash::cache::{
+    cache_file_name, cache_size, dataset_file_name, dataset_size, epoch, make_cache, seed_hash,
+    GETH_REVISION, MAX_EPOCH,
+};
+use crate::ethash::dataset::generate_c_dag;
+use crate::ethash::merkle::dataset_root;
+use crate::ethash::seed::SeedHashChain;
+use crate::progpow::firopow::{firopow, verify_firopow_seal};
+use crate::progpow::kawpow::{kawpow, verify_kawpow_seal};
+use crate::progpow::progpow::progpow;
+use crate::progpow::verify::{verify_seal, Seal, SealError};
+
+/// One epoch's light cache, with the cached DAG words ProgPoW reads from it.
+pub struct EpochCache {
+    epoch: u64,
+    dag: LightDag,
+    c_dag: Vec<u32>,
+    /// The Merkle root of the dataset, once computed.
+    dataset_root: OnceLock<[u8; 32]>,
+}
+
+impl EpochCache {
+    /// Generates the light cache of `epoch`.
+    pub fn generate(epoch: u64) -> Self {
+        Self::generate_with_seed(epoch, &seed_hash(epoch))
+    }
+
+    /// Generates the light cache of `epoch` from its already known seed hash.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "info", skip(seed)))]
+    pub fn generate_with_seed(epoch: u64, seed: &[u8; 32]) -> Self {
+        let cache = make_cache(cache_size(epoch), seed);
+        Self::new(epoch, cache, dataset_size(epoch))
+    }
+
+    /// Wraps an existing light cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `epoch` - The epoch the cache belongs to.
+    /// * `cache` - The light cache as little-endian words.
+    /// * `size` - The size of the full dataset in bytes.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(cache)))]
+    pub fn new(epoch: u64, cache: Vec<u32>, size: u64) -> Self {
+        let c_dag = generate_c_dag(&cache);
+        EpochCache {
+            epoch,
+            dag: LightDag::new(cache, size),
+            c_dag,
+            dataset_root: OnceLock::new(),
+        }
+    }
+
+    /// Returns the epoch this cache belongs to.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Computes the `(mix_hash, final_hash)` of a header.
+    pub fn hash(
+        &self,
+        header_hash: &[u8; 32],
+        block_number: u64,
+        nonce: u64,
+    ) -> (Vec<u8>, Vec<u8>) {
+        progpow(
+            header_hash,
+            nonce,
+            self.size(),
+            block_number,
+            &self.c_dag,
+            &|index| self.dag.lookup(index),
+        )
+    }
+
+    /// Computes the KawPoW `(mix_hash, final_hash)` of a header hash.
+    pub fn hash_kawpow(
+        &self,
+        header_hash: &[u8; 32],
+        block_number: u64,
+        nonce: u64,
+    ) -> (Vec<u8>, Vec<u8>) {
+        kawpow(
+            header_hash,
+            nonce,
+            self.size(),
+            block_number,
+            &self.c_dag,
+            &|index| self.dag.lookup(index),
+        )
+    }
+
+    /// Computes the FiroPoW `(mix_hash, final_hash)` of a header hash.
+    pub fn hash_firopow(
+        &self,
+        header_hash: &[u8; 32],
+        block_number: u64,
+        nonce: u64,
+    ) -> (Vec<u8>, Vec<u8>) {
+        firopow(
+            header_hash,
+            nonce,
+            self.size(),
+            block_number,
+            &self.c_dag,
+            &|index| self.dag.lookup(index),
+        )
+    }
+
+    /// Calls `f` with the dataset size, the cached DAG words and a DAG
+    /// lookup, for hash functions without a method of their own.
+    pub(crate) fn with_dag<R>(
+        &self,
+        f: impl FnOnce(u64, &[u32], &dyn Fn(u32) -> Vec<u8>) -> R,
+    ) -> R {
+        f(self.size(), &self.c_dag, &|index| self.dag.lookup(index))
+    }
+
+    /// Returns the Merkle root of the epoch's dataset; see
+    /// [`merkle`](crate::ethash::merkle).
+    ///
+    /// The first call computes every dataset item, which takes as long as
+    /// generating the dataset; later calls return the kept root.
+    pub fn dataset_root(&self) -> [u8; 32] {
+        *self.dataset_root.get_or_init(|| dataset_root(&self.dag))
+    }
+
+    /// Returns the light cache as little-endian words.
+    pub(crate) fn cache_words(&self) -> &[u32] {
+        self.dag.cache()
+    }
+
+    /// Verifies a seal against this cache; see [`verify_seal`].
+    pub fn verify_seal(&self, seal: &Seal) -> Result<Vec<u8>, SealError> {
+        verify_seal(seal, self.size(), &self.c_dag, &|index| {
+            self.dag.lookup(index)
+        })
+    }
+
+    /// Verifies a KawPoW seal against this cache; see [`verify_kawpow_seal`].
+    pub fn verify_kawpow_seal(&self, seal: &Seal) -> Result<Vec<u8>, SealError> {
+        verify_kawpow_seal(seal, self.size(), &self.c_dag, &|index| {
+            self.dag.lookup(index)
+        })
+    }
+
+    /// Verifies a FiroPoW seal against this cache; see [`verify_firopow_seal`].
+    pub fn verify_firopow_seal(&self, seal: &Seal) -> Result<Vec<u8>, SealError> {
+        verify_firopow_seal(seal, self.size(), &self.c_dag, &|index| {
+            self.dag.lookup(index)
+        })
+    }
+}
+
+impl DagBuffer for EpochCache {
+    fn size(&self) -> u64 {
+        self.dag.size()
+    }
+
+    fn read_item(&self, index: u32, out: &mut [u8; 64]) {
+        self.dag.read_item(index, out);
+    }
+
+    fn c_dag(&self) -> Vec<u32> {
+        self.c_dag.clone()
+    }
+}
+
+/// A function producing the cache of an epoch.
+type Generator = dyn Fn(u64) -> EpochCache + Send + Sync;
+
+/// A function returning the cache and dataset sizes in bytes of an epoch.
+type Sizes = dyn Fn(u64) -> (u64, u64) + Send + Sync;
+
+/// Which epochs' caches a [`CacheManager`] keeps besides its most recently
+/// used ones.
+///
+/// Epochs are counted from the head, the newest epoch the manager has been
+/// asked for with [`get`](CacheManager::get). Keeping the current epoch and
+/// the next one in memory and the last three on disk is
+///
+/// ```
+/// # use progpow_verifier::ethash::manager::{DiskRetention, Retention};
+/// let retention = Retention {
+///     past: 0,
+///     future: 1,
+///     disk: Some(DiskRetention {
+///         dir: "/var/lib/progpow".into(),
+///         past: 2,
+///         future: 0,
+///     }),
+/// };
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Retention {
+    /// Epochs before the head whose caches stay in memory.
+    pub past: u64,
+    /// Epochs after the head whose caches stay in memory once generated
+    /// ahead of it with [`prefetch`](CacheManager::prefetch).
+    pub future: u64,
+    /// Where caches are saved and loaded, if anywhere.
+    pub disk: Option<DiskRetention>,
+}
+
+/// A directory of cache and dataset files, kept for epochs around the head.
+///
+/// Files are named as go-ethereum and `progpow dag` name them. The manager
+/// saves each cache it generates there and loads it back rather than
+/// generating it again, and whenever it produces a cache removes the cache
+/// and dataset files of epochs outside the window and not pinned, so the
+/// directory should hold one chain's files and nothing else of that name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DiskRetention {
+    /// The directory the files are in.
+    pub dir: PathBuf,
+    /// Epochs before the head whose files are kept.
+    pub past: u64,
+    /// Epochs after the head whose files are kept.
+    pub future: u64,
+}
+
+/// Keeps the light caches of the most recently used epochs.
+///
+/// Verifiers see headers from a few neighbouring epochs at once: the chain
+/// head, ommers, and blocks around an epoch boundary. The manager generates
+/// each epoch's cache once, on first use, shares it between threads, and
+/// drops the least recently used cache once more than `capacity` are held.
+///
+/// Concurrent requests for an epoch that is still being generated wait for
+/// that generation instead of starting another; requests for other epochs
+/// are not blocked by it.
+///
+/// Seed hashes come from a [`SeedHashChain`] the manager shares with its
+/// default generator, so moving to a new epoch hashes only from the last
+/// one seen.
+///
+/// A [`Retention`] policy keeps epochs around the head as well, whether
+/// used lately or not, and [`pin_epoch`](Self::pin_epoch) keeps a given
+/// epoch, for validators that may have to reorganise deep into the past.
+pub struct CacheManager {
+    capacity: usize,
+    seeds: Arc<SeedHashChain>,
+    generate: Box<Generator>,
+    sizes: Box<Sizes>,
+    retention: Option<Retention>,
+    state: Mutex<ManagerState>,
+}
+
+/// The cached epochs, from least to most recently used, and what keeps
+/// others held.
+struct ManagerState {
+    caches: HashMap<u64, Arc<OnceLock<Arc<EpochCache>>>>,
+    recent: VecDeque<u64>,
+    pinned: HashSet<u64>,
+    head: Option<u64>,
+}
+
+impl CacheManager {
+    /// Creates a manager holding at most `capacity` epochs' caches.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn new(capacity: usize) -> Self {
+        let seeds = Arc::new(SeedHashChain::new());
+        let chain = seeds.clone();
+        let mut manager =
+            Self::with_generator(capacity, move |epoch| match chain.seed_for_epoch(epoch) {
+                Some(seed) => EpochCache::generate_with_seed(epoch, &seed),
+                None => EpochCache::generate(epoch),
+            });
+        manager.seeds = seeds;
+        manager
+    }
+
+    /// Creates a manager producing caches with `generate` instead of
+    /// generating them, for example to load them from disk.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn with_generator(
+        capacity: usize,
+        generate: impl Fn(u64) -> EpochCache + Send + Sync + 'static,
+    ) -> Self {
+        assert!(capacity > 0, "a cache manager must hold at least one epoch");
+        CacheManager {
+            capacity,
+            seeds: Arc::new(SeedHashChain::new()),
+            generate: Box::new(generate),
+            sizes: Box::new(|epoch| (cache_size(epoch), dataset_size(epoch))),
+            retention: None,
+            state: Mutex::new(ManagerState {
+                caches: HashMap::new(),
+                recent: VecDeque::new(),
+                pinned: HashSet::new(),
+                head: None,
+            }),
+        }
+    }
+
+    /// Keeps epochs around the head, in memory and on disk, as `retention`
+    /// says, besides the `capacity` most recently used.
+    pub fn with_retention(mut self, retention: Retention) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+
+    /// Sets the cache and dataset sizes in bytes of each epoch, against
+    /// which caches loaded from disk are checked; ethash's by default.
+    pub fn with_sizes(mut self, sizes: impl Fn(u64) -> (u64, u64) + Send + Sync + 'static) -> Self {
+        self.sizes = Box::new(sizes);
+        self
+    }
+
+    /// Returns the cache of `epoch`, generating it if it is not held, and
+    /// makes `epoch` the head if it is newer than the head.
+    pub fn get(&self, epoch: u64) -> Arc<EpochCache> {
+        self.request(epoch, true)
+    }
+
+    /// Returns the cache of `epoch` as [`get`](Self::get) does without
+    /// moving the head, to generate a coming epoch's cache ahead of time.
+    pub fn prefetch(&self, epoch: u64) -> Arc<EpochCache> {
+        self.request(epoch, false)
+    }
+
+    /// Keeps the cache of `epoch` in memory, and its files on disk, until
+    /// [`unpin_epoch`](Self::unpin_epoch), whatever the retention policy.
+    ///
+    /// Pinning does not generate the cache; the next request for it does.
+    pub fn pin_epoch(&self, epoch: u64) {
+        self.state.lock().unwrap().pinned.insert(epoch);
+    }
+
+    /// Releases a pin taken with [`pin_epoch`](Self::pin_epoch), dropping
+    /// the cache unless something else keeps it.
+    ///
+    /// # Returns
+    ///
+    /// `true` if `epoch` was pinned.
+    pub fn unpin_epoch(&self, epoch: u64) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let unpinned = state.pinned.remove(&epoch);
+        self.evict(&mut state);
+        unpinned
+    }
+
+    /// Returns the head: the newest epoch asked for with [`get`](Self::get),
+    /// or `None` before the first request.
+    pub fn head_epoch(&self) -> Option<u64> {
+        self.state.lock().unwrap().head
+    }
+
+    /// Returns the cache of `epoch`, moving the head to it if `advance` is
+    /// set and it is newer.
+    fn request(&self, epoch: u64, advance: bool) -> Arc<EpochCache> {
+        let slot = {
+            let mut state = self.state.lock().unwrap();
+            if advance {
+                state.head = Some(state.head.map_or(epoch, |head| head.max(epoch)));
+            }
+            let slot = state.caches.entry(epoch).or_default().clone();
+            state.recent.retain(|&held| held != epoch);
+            state.recent.push_back(epoch);
+            self.evict(&mut state);
+            slot
+        };
+        #[cfg(feature = "tracing")]
+        tracing::debug!(epoch, cached = slot.get().is_some(), "cache requested");
+        #[cfg(feature = "metrics")]
+        crate::metrics::global().record_cache_request(slot.get().is_some());
+        // Generate outside the lock so other epochs stay available meanwhile.
+        slot.get_or_init(|| {
+            #[cfg(feature = "metrics")]
+            let started = std::time::Instant::now();
+            let cache = Arc::new(self.produce(epoch));
+            #[cfg(feature = "metrics")]
+            crate::metrics::global()
+                .cache_builds
+                .observe(started.elapsed());
+            cache
+        })
+        .clone()
+    }
+
+    /// Drops the caches that are neither among the `capacity` most recently
+    /// used, nor pinned, nor in the retention window around the head.
+    fn evict(&self, state: &mut ManagerState) {
+        let window = self
+            .retention
+            .as_ref()
+            .zip(state.head)
+            .map(|(retention, head)| window(head, retention.past, retention.future));
+        let ManagerState {
+            caches,
+            recent,
+            pinned,
+            ..
+        } = state;
+        let first_recent = recent.len().saturating_sub(self.capacity);
+        let mut position = 0;
+        recent.retain(|&held| {
+            let kept = position >= first_recent
+                || pinned.contains(&held)
+                || window.as_ref().is_some_and(|window| window.contains(&held));
+            position += 1;
+            if !kept {
+                caches.remove(&held);
+            }
+            kept
+        });
+    }
+
+    /// Produces the cache of `epoch`: loaded from the retention directory
+    /// if it holds it, and generated and saved there otherwise.
+    fn produce(&self, epoch: u64) -> EpochCache {
+        let Some(disk) = self.retention.as_ref().and_then(|r| r.disk.as_ref()) else {
+            return (self.generate)(epoch);
+        };
+        let Some(seed) = self.seeds.seed_for_epoch(epoch) else {
+            return (self.generate)(epoch);
+        };
+        let path = disk.dir.join(cache_file_name(&seed));
+        let (cache_size, dataset_size) = (self.sizes)(epoch);
+        let cache = match load_cache(&path, cache_size) {
+            Some(words) => EpochCache::new(epoch, words, dataset_size),
+            None => {
+                let cache = (self.generate)(epoch);
+                let _saved = save_cache(&path, cache.cache_words());
+                #[cfg(feature = "tracing")]
+                if let Err(error) = _saved {
+                    tracing::warn!(epoch, %error, "cannot save cache");
+                }
+                cache
+            }
+        };
+        self.prune(disk, epoch);
+        cache
+    }
+
+    /// Removes the files in the retention directory of epochs outside its
+    /// window and not pinned; the head is `epoch` if there is none yet.
+    fn prune(&self, disk: &DiskRetention, epoch: u64) {
+        let (head, pinned) = {
+            let state = self.state.lock().unwrap();
+            (state.head.unwrap_or(epoch), state.pinned.clone())
+        };
+        let kept: HashSet<String> = window(head, disk.past, disk.future)
+            .chain(pinned)
+            .filter_map(|epoch| self.seeds.seed_for_epoch(epoch))
+            .flat_map(|seed| [cache_file_name(&seed), dataset_file_name(&seed)])
+            .collect();
+        let Ok(entries) = fs::read_dir(&disk.dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if is_epoch_file(&name) && !kept.contains(&name) {
+                let _removed = fs::remove_file(entry.path());
+                #[cfg(feature = "tracing")]
+                if let Err(error) = _removed {
+                    tracing::warn!(file = name, %error, "cannot remove expired file");
+                }
+            }
+        }
+    }
+
+    /// Returns the seed hash of `epoch` from the manager's seed chain, or
+    /// `None` if `epoch` is not below [`MAX_EPOCH`].
+    pub fn seed_hash(&self, epoch: u64) -> Option<[u8; 32]> {
+        self.seeds.seed_for_epoch(epoch)
+    }
+
+    /// Returns the cache for the epoch `block_number` belongs to.
+    pub fn for_block(&self, block_number: u64) -> Arc<EpochCache> {
+        self.get(epoch(block_number))
+    }
+
+    /// Returns `true` if the cache of `epoch` is held and fully generated.
+    pub fn is_cached(&self, epoch: u64) -> bool {
+        let state = self.state.lock().unwrap();
+        state
+            .caches
+            .get(&epoch)
+            .is_some_and(|slot| slot.get().is_some())
+    }
+
+    /// Verifies a seal with the cache of its block's epoch.
+    pub fn verify_seal(&self, seal: &Seal) -> Result<Vec<u8>, SealError> {
+        self.for_block(seal.block_number).verify_seal(seal)
+    }
+}
+
+/// Returns the epochs from `past` before `head` to `future` after it, up to
+/// the last supported epoch.
+fn window(head: u64, past: u64, future: u64) -> std::ops::RangeInclusive<u64> {
+    head.saturating_sub(past)..=head.saturating_add(future).min(MAX_EPOCH - 1)
+}
+
+/// Returns `true` if `name` is a go-ethereum cache or dataset file name, and
+/// not, say, a partial file being written.
+fn is_epoch_file(name: &str) -> bool {
+    [
+        format!("cache-R{GETH_REVISION}-"),
+        format!("full-R{GETH_REVISION}-"),
+    ]
+    .iter()
+    .filter_map(|prefix| name.strip_prefix(prefix.as_str()))
+    .any(|seed| seed.len() == 16 && seed.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+/// Reads a cache of `size` bytes saved by [`save_cache`].
+fn load_cache(path: &Path, size: u64) -> Option<Vec<u32>> {
+    let bytes = fs::read(path).ok()?;
+    if bytes.len() as u64 != size {
+        return None;
+    }
+    Some(le_words(&bytes))
+}
+
+/// Writes a cache to `path` as raw little-endian words, through a partial
+/// file of this process's own so readers never see half of one.
+fn save_cache(path: &Path, words: &[u32]) -> io::Result<()> {
+    let partial = path.with_extension(format!("{}.partial", std::process::id()));
+    fs::write(&partial, le_bytes(words)).and_then(|()| fs::rename(&partial, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_cache_manager_evicts_least_recently_used() {
+        let generated = Arc::new(AtomicUsize::new(0));
+        let counter = generated.clone();
+        let manager = CacheManager::with_generator(2, move |epoch| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            EpochCache::new(epoch, make_cache(1024, &seed_hash(epoch)), 1 << 16)
+        });
+
+        assert_eq!(manager.get(0).epoch(), 0);
+        assert_eq!(manager.for_block(30000).epoch(), 1);
+        assert!(Arc::ptr_eq(&manager.get(0), &manager.get(0)));
+        assert_eq!(generated.load(Ordering::SeqCst), 2);
+
+        // Epoch 1 is now the least recently used.
+        manager.get(2);
+        assert!(manager.is_cached(0));
+        assert!(!manager.is_cached(1));
+        assert!(manager.is_cached(2));
+
+        let cache = manager.get(0);
+        let (mix_hash, final_hash) = cache.hash(&[1; 32], 100, 5);
+        let seal = Seal {
+            header_hash: [1; 32],
+            block_number: 100,
+            nonce: 5,
+            mix_hash: mix_hash.try_into().unwrap(),
+            boundary: [0xff; 32],
+        };
+        assert_eq!(manager.verify_seal(&seal), Ok(final_hash));
+        assert_eq!(generated.load(Ordering::SeqCst), 3);
+        assert_eq!(manager.seed_hash(3), Some(seed_hash(3)));
+    }
+
+    #[test]
+    fn test_retention_keeps_window_pins_and_files() {
+        let dir = std::env::temp_dir().join(format!("progpow-retention-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let generated = Arc::new(AtomicUsize::new(0));
+        let counter = generated.clone();
+        let retention = Retention {
+            past: 1,
+            future: 1,
+            disk: Some(DiskRetention {
+                dir: dir.clone(),
+                past: 1,
+                future: 1,
+            }),
+        };
+        let manager = CacheManager::with_generator(1, move |epoch| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            EpochCache::new(epoch, make_cache(1024, &seed_hash(epoch)), 1 << 16)
+        })
+        .with_sizes(|_| (1024, 1 << 16))
+        .with_retention(retention.clone());
+
+        manager.pin_epoch(1);
+        manager.get(1);
+        manager.get(3);
+        manager.prefetch(4);
+        manager.get(2);
+        assert_eq!(manager.head_epoch(), Some(3));
+        assert!((1..=4).all(|epoch| manager.is_cached(epoch)));
+
+        assert!(manager.unpin_epoch(1));
+        assert!(!manager.unpin_epoch(1));
+        assert!(!manager.is_cached(1));
+
+        // Moving the head drops what falls behind the window, in memory and
+        // on disk.
+        let (mix_hash, final_hash) = manager.get(5).hash(&[1; 32], 150_000, 5);
+        assert!(!manager.is_cached(2) && !manager.is_cached(3));
+        assert!(manager.is_cached(4));
+        let mut files: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        let mut expected = vec![
+            cache_file_name(&seed_hash(4)),
+            cache_file_name(&seed_hash(5)),
+        ];
+        expected.sort();
+        assert_eq!(files, expected);
+        assert_eq!(generated.load(Ordering::SeqCst), 5);
+
+        // Another manager over the directory loads instead of generating.
+        let loader = CacheManager::with_generator(1, |_| panic!("the cache is on disk"))
+            .with_sizes(|_| (1024, 1 << 16))
+            .with_retention(retention);
+        assert_eq!(
+            loader.get(5).hash(&[1; 32], 150_000, 5),
+            (mix_hash, final_hash)
+        );
+        assert!(is_epoch_file("full-R23-290decd9548b62a8"));
+        assert!(!is_epoch_file("full-R23-290decd9548b62a8.1.partial"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
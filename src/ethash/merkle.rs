@@ -0,0 +1,339 @@
+//! A Merkle tree over the 64-byte items of an epoch's dataset.
+//!
+//! Stateless verification protocols in the style of SmartPool and
+//! FlyClient commit to a dataset once, by its Merkle root, and later prove
+//! single DAG accesses against that root instead of handing the verifier
+//! the dataset or its cache. The tree here is binary over Keccak-256:
+//!
+//! - leaf `i` is `keccak256(0x00 || item_i)`;
+//! - an inner node is `keccak256(0x01 || left || right)`;
+//! - a level of odd length carries its last node up unchanged.
+//!
+//! The prefixes keep a 64-byte item from being read as a pair of child
+//! hashes, which are 64 bytes too. [`dataset_root`] computes the root while
+//! holding one path of the tree, for committing to a dataset too large to
+//! keep a tree of; [`DagMerkleTree`] holds every level, 64 bytes per item,
+//! to prove accesses from with [`progpow::proof`](crate::progpow::proof). [`EpochCache::dataset_root`](crate::ethash::manager::EpochCache::dataset_root)
+//! computes an epoch's root once and keeps it.
+
+use std::thread;
+
+use crate::ethash::buffer::DagBuffer;
+use crate::keccak::keccak256;
+
+/// Items whose leaves are hashed together, on every core, before they are
+/// folded into the tree.
+const LEAF_BATCH: usize = 1 << 14;
+
+/// Returns the leaf of a dataset item, `keccak256(0x00 || item)`.
+pub fn leaf_hash(item: &[u8; 64]) -> [u8; 32] {
+    let mut bytes = [0u8; 65];
+    bytes[1..].copy_from_slice(item);
+    keccak256(&bytes)
+}
+
+/// Returns the inner node over two children, `keccak256(0x01 || left || right)`.
+pub fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut bytes = [0u8; 65];
+    bytes[0] = 1;
+    bytes[1..33].copy_from_slice(left);
+    bytes[33..].copy_from_slice(right);
+    keccak256(&bytes)
+}
+
+/// Returns the number of 64-byte items in `dag`.
+///
+/// # Panics
+///
+/// Panics if `dag` holds no item, or more than `u32::MAX`.
+fn item_count(dag: &dyn DagBuffer) -> u32 {
+    let items = dag.size() / 64;
+    assert!(items > 0, "the dataset holds no item");
+    u32::try_from(items).expect("the dataset holds more than 2^32 items")
+}
+
+/// Calls `f` with the leaves of `dag`'s items, a batch at a time, in order,
+/// hashing each batch on every available core.
+fn for_each_leaf_batch(dag: &dyn DagBuffer, mut f: impl FnMut(&[[u8; 32]])) {
+    let items = item_count(dag);
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let mut leaves = Vec::with_capacity(LEAF_BATCH);
+    for first in (0..items).step_by(LEAF_BATCH) {
+        let count = LEAF_BATCH.min((items - first) as usize);
+        leaves.resize(count, [0; 32]);
+        let per_thread = count.div_ceil(threads);
+        thread::scope(|scope| {
+            for (chunk_index, chunk) in leaves.chunks_mut(per_thread).enumerate() {
+                let first = first + (chunk_index * per_thread) as u32;
+                scope.spawn(move || {
+                    let mut item = [0u8; 64];
+                    for (offset, leaf) in chunk.iter_mut().enumerate() {
+                        dag.read_item(first + offset as u32, &mut item);
+                        *leaf = leaf_hash(&item);
+                    }
+                });
+            }
+        });
+        f(&leaves);
+    }
+}
+
+/// Computes the Merkle root of `dag`'s items, holding one path of the tree.
+///
+/// Reading a [`LightDag`](crate::ethash::buffer::LightDag) or an
+/// [`EpochCache`](crate::ethash::manager::EpochCache) computes every item,
+/// which takes as long as generating the full dataset.
+///
+/// # Panics
+///
+/// Panics if `dag` holds no item, or more than `u32::MAX`.
+pub fn dataset_root(dag: &dyn DagBuffer) -> [u8; 32] {
+    // The roots of the complete subtrees so far, with their heights, from
+    // the left; heights strictly decrease, as the digits of a binary count.
+    let mut stack: Vec<(u32, [u8; 32])> = Vec::new();
+    for_each_leaf_batch(dag, |leaves| {
+        for leaf in leaves {
+            let mut node = (0, *leaf);
+            while let Some(&(height, left)) = stack.last() {
+                if height != node.0 {
+                    break;
+                }
+                stack.pop();
+                node = (height + 1, node_hash(&left, &node.1));
+            }
+            stack.push(node);
+        }
+    });
+    // Carrying an odd node up unchanged joins the leftover subtrees from the
+    // right.
+    let (_, mut root) = stack.pop().unwrap();
+    while let Some((_, left)) = stack.pop() {
+        root = node_hash(&left, &root);
+    }
+    root
+}
+
+/// Checks that `node`, node `index` of level `level` of the tree over
+/// `items` items, has branch `branch` to `root`; see
+/// [`DagMerkleTree::branch`].
+///
+/// # Returns
+///
+/// `true` if the branch leads to `root` and holds no extra node.
+pub fn verify_branch(
+    root: &[u8; 32],
+    items: u32,
+    level: usize,
+    index: u32,
+    node: &[u8; 32],
+    branch: &[[u8; 32]],
+) -> bool {
+    let mut len = items as u64;
+    for _ in 0..level {
+        len = len.div_ceil(2);
+    }
+    let (mut index, mut node) = (index as u64, *node);
+    if index >= len {
+        return false;
+    }
+    let mut siblings = branch.iter();
+    while len > 1 {
+        if index % 2 == 1 {
+            let Some(sibling) = siblings.next() else {
+                return false;
+            };
+            node = node_hash(sibling, &node);
+        } else if index + 1 < len {
+            let Some(sibling) = siblings.next() else {
+                return false;
+            };
+            node = node_hash(&node, sibling);
+        }
+        index /= 2;
+        len = len.div_ceil(2);
+    }
+    siblings.next().is_none() && node == *root
+}
+
+/// Every level of the Merkle tree over a dataset's items, leaves first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DagMerkleTree {
+    levels: Vec<Vec<[u8; 32]>>,
+}
+
+impl DagMerkleTree {
+    /// Builds the tree over `dag`'s items, hashing leaves on every core.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dag` holds no item, or more than `u32::MAX`.
+    pub fn build(dag: &dyn DagBuffer) -> Self {
+        let mut leaves = Vec::with_capacity(item_count(dag) as usize);
+        for_each_leaf_batch(dag, |batch| leaves.extend_from_slice(batch));
+        Self::from_leaves(leaves)
+    }
+
+    /// Builds the tree over already hashed leaves.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `leaves` is empty.
+    pub fn from_leaves(leaves: Vec<[u8; 32]>) -> Self {
+        assert!(!leaves.is_empty(), "a Merkle tree needs at least one leaf");
+        let mut levels = vec![leaves];
+        while levels.last().unwrap().len() > 1 {
+            let next = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => node_hash(left, right),
+                    [odd] => *odd,
+                    _ => unreachable!(),
+                })
+                .collect();
+            levels.push(next);
+        }
+        DagMerkleTree { levels }
+    }
+
+    /// Returns the root.
+    pub fn root(&self) -> [u8; 32] {
+        self.levels.last().unwrap()[0]
+    }
+
+    /// Returns the number of items, the leaves.
+    pub fn items(&self) -> u32 {
+        self.levels[0].len() as u32
+    }
+
+    /// Returns the number of levels above the leaves.
+    pub fn depth(&self) -> usize {
+        self.levels.len() - 1
+    }
+
+    /// Returns the branch of node `index` of level `level`: its sibling on
+    /// each level up to the root, skipping the levels where it is carried
+    /// up without one; see [`verify_branch`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the level has no node `index`.
+    pub fn branch(&self, level: usize, index: u32) -> Vec<[u8; 32]> {
+        assert!(
+            (index as usize) < self.levels[level].len(),
+            "level {level} has no node {index}"
+        );
+        let mut branch = Vec::new();
+        let mut index = index as usize;
+        for nodes in &self.levels[level..self.depth()] {
+            if let Some(sibling) = nodes.get(index ^ 1) {
+                branch.push(*sibling);
+            }
+            index /= 2;
+        }
+        branch
+    }
+
+    /// Returns the nodes of level `level`, 0 being the leaves.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `level` is greater than [`depth`](Self::depth).
+    pub fn level(&self, level: usize) -> &[[u8; 32]] {
+        &self.levels[level]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ethash::dataset::generate_dataset;
+    use crate::testutil::tiny_cache;
+
+    #[test]
+    fn test_dataset_root_matches_the_tree() {
+        let cache = tiny_cache(0);
+        let dataset = generate_dataset(cache.cache_words(), cache.size());
+        let tree = DagMerkleTree::build(&dataset);
+        assert_eq!(tree.root(), dataset_root(&dataset));
+        assert_eq!(tree.root(), dataset_root(&cache));
+        assert_eq!(tree.root(), cache.dataset_root());
+        assert_eq!(tree.items() as u64, cache.size() / 64);
+
+        let mut item = [0u8; 64];
+        dataset.read_item(3, &mut item);
+        assert_eq!(tree.level(0)[3], leaf_hash(&item));
+        assert_eq!(
+            tree.level(1)[1],
+            node_hash(&tree.level(0)[2], &tree.level(0)[3])
+        );
+
+        // Odd levels carry their last node up, whatever the item count.
+        for items in 1..=20usize {
+            let words = dataset[..items * 16].to_vec();
+            let tree = DagMerkleTree::build(&words);
+            assert_eq!(tree.root(), dataset_root(&words), "{items} items");
+            assert_eq!(
+                tree.depth(),
+                items.next_power_of_two().trailing_zeros() as usize
+            );
+        }
+        let three = DagMerkleTree::build(&dataset[..48].to_vec());
+        let leaves = three.level(0);
+        assert_eq!(
+            three.root(),
+            node_hash(&node_hash(&leaves[0], &leaves[1]), &leaves[2])
+        );
+
+        // Every node's branch leads to the root, and only from its place.
+        for (level, index) in [(0, 0), (0, 5), (0, 255), (3, 7), (8, 0)] {
+            let node = tree.level(level)[index as usize];
+            let branch = tree.branch(level, index);
+            assert!(verify_branch(
+                &tree.root(),
+                tree.items(),
+                level,
+                index,
+                &node,
+                &branch
+            ));
+            assert!(!verify_branch(
+                &tree.root(),
+                tree.items(),
+                level,
+                index ^ 1,
+                &node,
+                &branch
+            ));
+            assert!(!verify_branch(
+                &tree.root(),
+                tree.items(),
+                level,
+                index,
+                &node,
+                &branch[1..]
+            ));
+        }
+        for items in [5usize, 7, 12] {
+            let words = dataset[..items * 16].to_vec();
+            let tree = DagMerkleTree::build(&words);
+            for index in 0..items as u32 {
+                let leaf = tree.level(0)[index as usize];
+                let branch = tree.branch(0, index);
+                assert!(verify_branch(
+                    &tree.root(),
+                    items as u32,
+                    0,
+                    index,
+                    &leaf,
+                    &branch
+                ));
+            }
+        }
+
+        let mut changed = dataset.clone();
+        changed[100] ^= 1;
+        assert_ne!(dataset_root(&changed), tree.root());
+    }
+}
@@ -0,0 +1,409 @@
+//! Dataset placement across the NUMA nodes of a multi-socket machine.
+//!
+//! Full verification reads dataset items at random, so on a machine with
+//! several sockets most reads of a dataset in one node's memory cross the
+//! interconnect and their latency dominates throughput. A [`NumaDag`] places
+//! the dataset with one of two [`Placement`]s:
+//!
+//! - [`Placement::Interleaved`] spreads one copy's pages round-robin over
+//!   every node, so all sockets see the same average latency for the memory
+//!   of one dataset.
+//! - [`Placement::Replicated`] keeps a copy in each node's memory, and reads
+//!   from a thread go to the copy of the node the thread runs on, at the
+//!   cost of one dataset per node.
+//!
+//! Placement pairs with pinning worker threads to a node's CPUs with
+//! [`pin_to_node`]. Both are best effort: on kernels without NUMA support,
+//! or outside Linux, the topology is one node holding every CPU, memory is
+//! placed where the kernel puts it and [`NumaDag::is_placed`] says so.
+
+use std::io;
+
+use crate::ethash::buffer::DagBuffer;
+use crate::ethash::dataset::generate_dataset_chunks;
+
+/// Dataset items generated at a time while filling a copy.
+const ITEMS_PER_CHUNK: usize = 1 << 16;
+
+/// Where sysfs lists the NUMA nodes.
+const NODE_DIR: &str = "/sys/devices/system/node";
+
+/// One NUMA node: a socket's memory and the CPUs nearest it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Node {
+    /// The node's id, as the kernel numbers it.
+    pub id: u32,
+    /// The CPUs in the node.
+    pub cpus: Vec<usize>,
+}
+
+/// The NUMA nodes of the machine.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Topology {
+    nodes: Vec<Node>,
+}
+
+impl Topology {
+    /// Reads the nodes holding CPUs from sysfs, or returns a single node 0
+    /// with every CPU if the machine reports none.
+    pub fn detect() -> Self {
+        let mut nodes: Vec<Node> = std::fs::read_dir(NODE_DIR)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let id = entry
+                    .file_name()
+                    .to_str()?
+                    .strip_prefix("node")?
+                    .parse()
+                    .ok()?;
+                let list = std::fs::read_to_string(entry.path().join("cpulist")).ok()?;
+                let cpus = parse_cpu_list(&list)?;
+                (!cpus.is_empty()).then_some(Node { id, cpus })
+            })
+            .collect();
+        nodes.sort_by_key(|node| node.id);
+        Self::new(nodes)
+    }
+
+    /// Creates a topology of `nodes`, or of a single node 0 with every CPU
+    /// if `nodes` is empty.
+    pub fn new(nodes: Vec<Node>) -> Self {
+        if nodes.is_empty() {
+            let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+            return Topology {
+                nodes: vec![Node {
+                    id: 0,
+                    cpus: (0..cpus).collect(),
+                }],
+            };
+        }
+        Topology { nodes }
+    }
+
+    /// Returns the nodes, by id.
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// Returns the position in [`nodes`](Self::nodes) of the node holding
+    /// `cpu`, or of the first node if none lists it.
+    fn node_of(&self, cpu: usize) -> usize {
+        self.nodes
+            .iter()
+            .position(|node| node.cpus.contains(&cpu))
+            .unwrap_or(0)
+    }
+}
+
+/// Parses a kernel CPU list such as `0-3,8,10-11`.
+///
+/// # Returns
+///
+/// The CPUs in the list, or `None` if it is malformed.
+pub fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+        match range.split_once('-') {
+            Some((first, last)) => {
+                let (first, last): (usize, usize) = (first.parse().ok()?, last.parse().ok()?);
+                if last < first {
+                    return None;
+                }
+                cpus.extend(first..=last);
+            }
+            None => cpus.push(range.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+/// How a [`NumaDag`] spreads the dataset over the nodes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Placement {
+    /// One copy with its pages spread round-robin over every node.
+    Interleaved,
+    /// A copy in each node's memory, read by the threads on that node.
+    Replicated,
+}
+
+/// A dataset placed over the NUMA nodes of a [`Topology`].
+///
+/// As a [`DagBuffer`], it reads from the copy local to the CPU the calling
+/// thread runs on; pin threads with [`pin_to_node`] so they stay there.
+pub struct NumaDag {
+    topology: Topology,
+    placement: Placement,
+    /// One copy per node when replicated, else the interleaved copy.
+    copies: Vec<memmap2::Mmap>,
+    size: u64,
+    placed: bool,
+}
+
+impl NumaDag {
+    /// Generates the dataset and places it over `topology`'s nodes.
+    ///
+    /// # Arguments
+    ///
+    /// * `topology` - The nodes to place the dataset on, usually
+    ///   [`Topology::detect`].
+    /// * `cache` - The ethash light cache as little-endian words.
+    /// * `size` - The size of the dataset in bytes, a multiple of 64.
+    /// * `placement` - Whether to interleave one copy or replicate it.
+    ///
+    /// # Returns
+    ///
+    /// The placed DAG, or an I/O error if memory for a copy cannot be mapped.
+    pub fn generate(
+        topology: Topology,
+        cache: &[u32],
+        size: u64,
+        placement: Placement,
+    ) -> io::Result<Self> {
+        let len = (size as usize).max(1);
+        let all: Vec<u32> = topology.nodes.iter().map(|node| node.id).collect();
+        let targets: Vec<Vec<u32>> = match placement {
+            Placement::Interleaved => vec![all],
+            Placement::Replicated => all.iter().map(|&id| vec![id]).collect(),
+        };
+
+        let mut placed = true;
+        let mut copies: Vec<memmap2::MmapMut> = Vec::with_capacity(targets.len());
+        for nodes in &targets {
+            let map = memmap2::MmapMut::map_anon(len)?;
+            placed &= bind(&map, nodes, placement).is_ok();
+            copies.push(map);
+        }
+
+        // Pages land under their policy when first written, so the first
+        // copy is generated in place and the others are copied from it by a
+        // thread on their own node.
+        let (first, rest) = copies.split_first_mut().expect("a topology has a node");
+        let mut out = first[..size as usize].chunks_exact_mut(4);
+        generate_dataset_chunks(cache, size, ITEMS_PER_CHUNK, |chunk| {
+            for (word, out) in chunk.iter().zip(&mut out) {
+                out.copy_from_slice(&word.to_le_bytes());
+            }
+            Ok::<_, io::Error>(())
+        })?;
+        let source = &first[..];
+        let pinned = std::thread::scope(|scope| {
+            let copying: Vec<_> = rest
+                .iter_mut()
+                .zip(&topology.nodes[1..])
+                .map(|(copy, node)| {
+                    scope.spawn(move || {
+                        let pinned = pin_to_node(node).is_ok();
+                        copy.copy_from_slice(source);
+                        pinned
+                    })
+                })
+                .collect();
+            copying
+                .into_iter()
+                .all(|copy| copy.join().expect("copying a replica panicked"))
+        });
+
+        Ok(NumaDag {
+            topology,
+            placement,
+            copies: copies
+                .into_iter()
+                .map(|copy| copy.make_read_only())
+                .collect::<io::Result<_>>()?,
+            size,
+            placed: placed && pinned,
+        })
+    }
+
+    /// Returns the nodes the dataset is placed on.
+    pub fn topology(&self) -> &Topology {
+        &self.topology
+    }
+
+    /// Returns how the dataset is spread over the nodes.
+    pub fn placement(&self) -> Placement {
+        self.placement
+    }
+
+    /// Returns whether the kernel accepted the placement; if not, the copies
+    /// are wherever it put them.
+    pub fn is_placed(&self) -> bool {
+        self.placed
+    }
+
+    /// Returns the copy read by threads on node `id`, the interleaved copy
+    /// if there is one, or `None` if the topology has no such node.
+    pub fn copy_for_node(&self, id: u32) -> Option<&[u8]> {
+        let index = self.topology.nodes.iter().position(|node| node.id == id)?;
+        Some(&self.copies[index.min(self.copies.len() - 1)][..self.size as usize])
+    }
+
+    /// Returns the copy local to the CPU the calling thread runs on.
+    fn local_copy(&self) -> &[u8] {
+        let index = match (self.copies.len(), current_cpu()) {
+            (1, _) | (_, None) => 0,
+            (_, Some(cpu)) => self.topology.node_of(cpu),
+        };
+        &self.copies[index]
+    }
+}
+
+impl DagBuffer for NumaDag {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn read_item(&self, index: u32, out: &mut [u8; 64]) {
+        let first = index as usize * 64;
+        out.copy_from_slice(&self.local_copy()[first..first + 64]);
+    }
+}
+
+/// Restricts the calling thread to the CPUs of `node`.
+///
+/// # Returns
+///
+/// `Ok(())`, or the error the kernel returned; outside Linux, an error of
+/// kind [`io::ErrorKind::Unsupported`].
+pub fn pin_to_node(node: &Node) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        // SAFETY: `set` is a plain bit set, initialised by CPU_ZERO before
+        // use, and sched_setaffinity only reads it.
+        unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            libc::CPU_ZERO(&mut set);
+            for &cpu in &node.cpus {
+                libc::CPU_SET(cpu, &mut set);
+            }
+            if libc::sched_setaffinity(0, std::mem::size_of_val(&set), &set) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = node;
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// Returns the CPU the calling thread runs on, if the platform tells.
+fn current_cpu() -> Option<usize> {
+    #[cfg(target_os = "linux")]
+    {
+        // SAFETY: sched_getcpu takes no arguments and only returns a value.
+        usize::try_from(unsafe { libc::sched_getcpu() }).ok()
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        None
+    }
+}
+
+/// Sets the memory policy of `map` to the nodes `nodes`, bound to one or
+/// interleaved over several, before any of its pages are touched.
+fn bind(map: &memmap2::MmapMut, nodes: &[u32], placement: Placement) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        // From <numaif.h>; the libc crate does not export them.
+        const MPOL_BIND: libc::c_long = 2;
+        const MPOL_INTERLEAVE: libc::c_long = 3;
+        let bits = libc::c_ulong::BITS as usize;
+        let mut mask: Vec<libc::c_ulong> = vec![
+            0;
+            nodes
+                .iter()
+                .map(|&id| id as usize / bits + 1)
+                .max()
+                .unwrap_or(1)
+        ];
+        for &id in nodes {
+            mask[id as usize / bits] |= 1 << (id as usize % bits);
+        }
+        let mode = match placement {
+            Placement::Interleaved => MPOL_INTERLEAVE,
+            Placement::Replicated => MPOL_BIND,
+        };
+        // SAFETY: the range is exactly `map`, which this process owns, and
+        // `mask` holds the `mask.len() * bits` node bits passed as maxnode.
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_mbind,
+                map.as_ptr(),
+                map.len(),
+                mode,
+                mask.as_ptr(),
+                mask.len() * bits + 1,
+                0 as libc::c_ulong,
+            )
+        };
+        if result != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (map, nodes, placement);
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ethash::dataset::generate_dataset;
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(
+            parse_cpu_list("0-3,8,10-11\n"),
+            Some(vec![0, 1, 2, 3, 8, 10, 11])
+        );
+        assert_eq!(parse_cpu_list(""), Some(vec![]));
+        assert_eq!(parse_cpu_list("3-1"), None);
+        assert_eq!(parse_cpu_list("a"), None);
+    }
+
+    #[test]
+    fn test_numa_dag_reads_agree_with_every_placement() {
+        let cache: Vec<u32> = (0..64 * 16u32)
+            .map(|i| i.wrapping_mul(0x9e3779b9))
+            .collect();
+        let size = 300 * 64;
+        let full = generate_dataset(&cache, size);
+        let detected = Topology::detect();
+        assert!(!detected.nodes().is_empty());
+
+        // Two nodes splitting the CPUs, whatever the machine has: placement
+        // may be refused, but every copy must read the same.
+        let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let split = Topology::new(vec![
+            Node {
+                id: 0,
+                cpus: (0..cpus).step_by(2).collect(),
+            },
+            Node {
+                id: 1,
+                cpus: (1..cpus).step_by(2).collect(),
+            },
+        ]);
+        for topology in [detected, split] {
+            for placement in [Placement::Interleaved, Placement::Replicated] {
+                let dag = NumaDag::generate(topology.clone(), &cache, size, placement).unwrap();
+                assert_eq!(dag.size(), size);
+                assert_eq!(dag.c_dag(), full.c_dag());
+                assert_eq!(dag.lookup(16 * 299), full.lookup(16 * 299));
+                for node in topology.nodes() {
+                    let copy = dag.copy_for_node(node.id).unwrap();
+                    assert_eq!(copy[64 * 150..64 * 151], full.lookup(16 * 150)[..]);
+                }
+                assert!(dag.copy_for_node(7).is_none());
+            }
+        }
+    }
+}
@@ -0,0 +1,373 @@
+//! A DAG served over TCP by one machine to many verifiers.
+//!
+//! A fleet of light verifier workers each either holds a multi-gigabyte
+//! dataset or computes every item from the light cache, 256 cache reads
+//! apiece. A [`DagServer`] on one machine holding the datasets serves their
+//! items by index instead, and each worker reads them through a
+//! [`RemoteDag`], a [`DagBuffer`] like any other.
+//!
+//! The protocol is little-endian binary over one TCP connection per thread:
+//!
+//! - The client opens with the magic `PPDG`, the protocol version (1) and the
+//!   epoch as a `u64`; the server answers a status byte, then for status
+//!   [`STATUS_OK`] the dataset size in bytes as a `u64`.
+//! - Each request is a `u32` count of up to [`MAX_BATCH`] items and the
+//!   items' `u32` indices; the server answers a status byte, then for
+//!   [`STATUS_OK`] the 64 bytes of each item in order.
+//!
+//! A server closes a connection after a failed request. Like `UringDag`,
+//! the client reads a hash loop's whole load group of four items in one
+//! round trip, when asked for its first item.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+
+use crate::basic_algorithm::{PROGPOW_CACHE_WORDS, PROGPOW_MIX_BYTES};
+use crate::endian::le_words;
+use crate::ethash::buffer::DagBuffer;
+
+/// The bytes a client opens a connection with.
+pub const MAGIC: [u8; 4] = *b"PPDG";
+
+/// The protocol version a client asks for.
+pub const VERSION: u8 = 1;
+
+/// The most items one request may ask for.
+pub const MAX_BATCH: u32 = 4096;
+
+/// The status of a request that succeeded.
+pub const STATUS_OK: u8 = 0;
+
+/// The status of an opening for an epoch the server holds no dataset of.
+pub const STATUS_UNKNOWN_EPOCH: u8 = 1;
+
+/// The status of a request for an item past the dataset, or for more than
+/// [`MAX_BATCH`] items.
+pub const STATUS_BAD_REQUEST: u8 = 2;
+
+/// Bytes in one dataset item.
+const ITEM_BYTES: usize = 64;
+
+/// Items one loop iteration of a hash reads, starting at a multiple of it.
+const ITEMS_PER_LOAD: usize = PROGPOW_MIX_BYTES / ITEM_BYTES;
+
+/// Identifies each [`RemoteDag`], so a thread's buffered load group is
+/// never served from another DAG.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A load group a thread read: the DAG's id, the group's first item and its
+/// items.
+type LoadGroup = (u64, u32, [[u8; ITEM_BYTES]; ITEMS_PER_LOAD]);
+
+thread_local! {
+    /// The load group this thread read last.
+    static LOADED: RefCell<Option<LoadGroup>> = const { RefCell::new(None) };
+}
+
+/// Serves the items of the datasets it holds to [`RemoteDag`] clients.
+///
+/// The server is a cheap handle: clones share the datasets, so one clone
+/// can serve while another adds the next epoch's dataset and removes old
+/// ones.
+#[derive(Clone, Default)]
+pub struct DagServer {
+    datasets: Arc<RwLock<HashMap<u64, Arc<dyn DagBuffer>>>>,
+}
+
+impl DagServer {
+    /// Creates a server holding no datasets.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serves `dag` as the dataset of `epoch`, replacing any held before.
+    pub fn insert(&self, epoch: u64, dag: Arc<dyn DagBuffer>) {
+        self.datasets.write().unwrap().insert(epoch, dag);
+    }
+
+    /// Stops serving the dataset of `epoch` to new connections.
+    ///
+    /// # Returns
+    ///
+    /// The dataset, if one was held.
+    pub fn remove(&self, epoch: u64) -> Option<Arc<dyn DagBuffer>> {
+        self.datasets.write().unwrap().remove(&epoch)
+    }
+
+    /// Accepts connections on `listener` until accepting fails, serving each
+    /// on a thread of its own.
+    ///
+    /// # Returns
+    ///
+    /// The error accepting failed with.
+    pub fn serve(&self, listener: TcpListener) -> io::Error {
+        loop {
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(error) => return error,
+            };
+            let server = self.clone();
+            thread::spawn(move || {
+                // A client that goes away ends its connection; nothing else
+                // is to be done about it.
+                let _ = server.serve_connection(stream);
+            });
+        }
+    }
+
+    /// Answers the opening and requests of one connection.
+    fn serve_connection(&self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        let mut opening = [0u8; 13];
+        stream.read_exact(&mut opening)?;
+        let epoch = u64::from_le_bytes(opening[5..].try_into().unwrap());
+        let dag = self.datasets.read().unwrap().get(&epoch).cloned();
+        let dag = match dag {
+            Some(dag) if opening[..4] == MAGIC && opening[4] == VERSION => dag,
+            _ => return stream.write_all(&[STATUS_UNKNOWN_EPOCH]),
+        };
+        let mut reply = vec![STATUS_OK];
+        reply.extend_from_slice(&dag.size().to_le_bytes());
+        stream.write_all(&reply)?;
+
+        let items = dag.size() / ITEM_BYTES as u64;
+        let mut count = [0u8; 4];
+        loop {
+            match stream.read_exact(&mut count) {
+                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                read => read?,
+            }
+            let count = u32::from_le_bytes(count);
+            if count > MAX_BATCH {
+                return stream.write_all(&[STATUS_BAD_REQUEST]);
+            }
+            let mut indices = vec![0u8; count as usize * 4];
+            stream.read_exact(&mut indices)?;
+            let indices: Vec<u32> = indices
+                .chunks_exact(4)
+                .map(|index| u32::from_le_bytes(index.try_into().unwrap()))
+                .collect();
+            if indices.iter().any(|&index| index as u64 >= items) {
+                return stream.write_all(&[STATUS_BAD_REQUEST]);
+            }
+            reply.clear();
+            reply.push(STATUS_OK);
+            let mut item = [0u8; ITEM_BYTES];
+            for index in indices {
+                dag.read_item(index, &mut item);
+                reply.extend_from_slice(&item);
+            }
+            stream.write_all(&reply)?;
+        }
+    }
+}
+
+/// A dataset read item by item from a [`DagServer`].
+pub struct RemoteDag {
+    addr: SocketAddr,
+    epoch: u64,
+    size: u64,
+    c_dag: Vec<u32>,
+    id: u64,
+    connections: Mutex<Vec<TcpStream>>,
+}
+
+impl RemoteDag {
+    /// Connects to the server at `addr` for the dataset of `epoch` and
+    /// reads its cached DAG words.
+    ///
+    /// # Returns
+    ///
+    /// The DAG, or an I/O error if the server cannot be reached, does not
+    /// hold the epoch, or holds a dataset shorter than the cached words.
+    pub fn connect(addr: impl ToSocketAddrs, epoch: u64) -> io::Result<Self> {
+        let addr = addr.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no address to connect to")
+        })?;
+        let (stream, size) = open(addr, epoch)?;
+        if size < PROGPOW_CACHE_WORDS as u64 * 4 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the dataset is shorter than the cached DAG words",
+            ));
+        }
+        let mut dag = RemoteDag {
+            addr,
+            epoch,
+            size,
+            c_dag: Vec::new(),
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            connections: Mutex::new(vec![stream]),
+        };
+        let indices: Vec<u32> = (0..(PROGPOW_CACHE_WORDS * 4 / ITEM_BYTES) as u32).collect();
+        let mut items = vec![[0u8; ITEM_BYTES]; indices.len()];
+        dag.read_items(&indices, &mut items)?;
+        dag.c_dag = items.iter().flat_map(|item| le_words(item)).collect();
+        Ok(dag)
+    }
+
+    /// Returns the epoch whose dataset this is.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Reads the items `indices` into `out`, in round trips of up to
+    /// [`MAX_BATCH`] items.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `out` is shorter than `indices`.
+    pub fn read_items(&self, indices: &[u32], out: &mut [[u8; ITEM_BYTES]]) -> io::Result<()> {
+        assert!(
+            out.len() >= indices.len(),
+            "`out` is shorter than `indices`"
+        );
+        let stream = self.connections.lock().unwrap().pop();
+        let mut stream = match stream {
+            Some(stream) => stream,
+            None => open(self.addr, self.epoch)?.0,
+        };
+        let mut request = Vec::new();
+        for (indices, out) in indices
+            .chunks(MAX_BATCH as usize)
+            .zip(out.chunks_mut(MAX_BATCH as usize))
+        {
+            request.clear();
+            request.extend_from_slice(&(indices.len() as u32).to_le_bytes());
+            request.extend(indices.iter().flat_map(|index| index.to_le_bytes()));
+            stream.write_all(&request)?;
+            let mut status = [0u8];
+            stream.read_exact(&mut status)?;
+            if status[0] != STATUS_OK {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("the DAG server refused the read: status {}", status[0]),
+                ));
+            }
+            for item in out.iter_mut().take(indices.len()) {
+                stream.read_exact(item)?;
+            }
+        }
+        // A connection whose request failed is dropped; only clean ones go
+        // back to the pool.
+        self.connections.lock().unwrap().push(stream);
+        Ok(())
+    }
+}
+
+impl DagBuffer for RemoteDag {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the read fails, as mapped DAGs fault on I/O errors.
+    fn read_item(&self, index: u32, out: &mut [u8; 64]) {
+        if let Err(error) = self.read_items(&[index], std::slice::from_mut(out)) {
+            panic!("reading DAG item {index} failed: {error}");
+        }
+    }
+
+    fn lookup(&self, word_index: u32) -> Vec<u8> {
+        let index = word_index / (ITEM_BYTES / 4) as u32;
+        let first = index - index % ITEMS_PER_LOAD as u32;
+        LOADED.with(|loaded| {
+            let mut loaded = loaded.borrow_mut();
+            match &*loaded {
+                Some((id, at, _)) if *id == self.id && *at == first => {}
+                _ => {
+                    let items_in_dag = (self.size / ITEM_BYTES as u64) as u32;
+                    let indices: Vec<u32> =
+                        (first..items_in_dag.min(first + ITEMS_PER_LOAD as u32)).collect();
+                    let mut items = [[0u8; ITEM_BYTES]; ITEMS_PER_LOAD];
+                    if let Err(error) = self.read_items(&indices, &mut items) {
+                        panic!("reading DAG items from {first} failed: {error}");
+                    }
+                    *loaded = Some((self.id, first, items));
+                }
+            }
+            let (_, _, items) = loaded.as_ref().unwrap();
+            items[(index - first) as usize].to_vec()
+        })
+    }
+
+    fn c_dag(&self) -> Vec<u32> {
+        self.c_dag.clone()
+    }
+}
+
+/// Opens a connection to the server at `addr` for the dataset of `epoch`.
+///
+/// # Returns
+///
+/// The connection and the dataset size in bytes.
+fn open(addr: SocketAddr, epoch: u64) -> io::Result<(TcpStream, u64)> {
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_nodelay(true)?;
+    let mut opening = Vec::with_capacity(13);
+    opening.extend_from_slice(&MAGIC);
+    opening.push(VERSION);
+    opening.extend_from_slice(&epoch.to_le_bytes());
+    stream.write_all(&opening)?;
+    let mut status = [0u8];
+    stream.read_exact(&mut status)?;
+    if status[0] != STATUS_OK {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("the DAG server holds no dataset of epoch {epoch}"),
+        ));
+    }
+    let mut size = [0u8; 8];
+    stream.read_exact(&mut size)?;
+    Ok((stream, u64::from_le_bytes(size)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ethash::dataset::generate_dataset;
+    use crate::progpow::progpow::progpow;
+
+    #[test]
+    fn test_remote_dag_reads_served_items() {
+        let cache: Vec<u32> = (0..64 * 16u32)
+            .map(|i| i.wrapping_mul(0x9e3779b9))
+            .collect();
+        let full = Arc::new(generate_dataset(&cache, 1024 * 64));
+        let server = DagServer::new();
+        server.insert(7, full.clone());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let serving = server.clone();
+        thread::spawn(move || serving.serve(listener));
+
+        let remote = RemoteDag::connect(addr, 7).unwrap();
+        assert_eq!(remote.size(), full.size());
+        assert_eq!(remote.c_dag(), full.c_dag());
+        let mut items = [[0u8; 64]; 3];
+        remote.read_items(&[1023, 0, 512], &mut items).unwrap();
+        assert_eq!(items[0].to_vec(), full.lookup(16 * 1023));
+        assert!(remote.read_items(&[1024], &mut items).is_err());
+
+        let c_dag = full.c_dag();
+        let expected = progpow(&[3; 32], 9, full.size(), 30_000, &c_dag, &|word| {
+            full.lookup(word)
+        });
+        let served = progpow(&[3; 32], 9, remote.size(), 30_000, &c_dag, &|word| {
+            remote.lookup(word)
+        });
+        assert_eq!(served, expected);
+
+        assert_eq!(
+            RemoteDag::connect(addr, 8).err().map(|error| error.kind()),
+            Some(io::ErrorKind::NotFound)
+        );
+        assert!(server.remove(7).is_some());
+    }
+}
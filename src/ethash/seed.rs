@@ -0,0 +1,110 @@
+use std::sync::Mutex;
+
+use crate::ethash::cache::{seed_hash, MAX_EPOCH};
+use crate::keccak::keccak256;
+
+/// Memoized seed hashes of consecutive epochs.
+///
+/// The seed hash of epoch `n` is Keccak-256 applied `n` times, so computing
+/// it from scratch for a late epoch takes thousands of sequential hashes.
+/// The chain keeps every seed it has computed: [`SeedHashChain::seed_for_epoch`]
+/// only hashes from the latest one held up to the epoch asked for, and
+/// answers any earlier epoch by lookup. It grows no further than
+/// [`MAX_EPOCH`], so an absurd epoch can neither exhaust memory nor hold the
+/// lock for the hours its seed would take.
+pub struct SeedHashChain {
+    /// The seeds of epochs `0..seeds.len()`.
+    seeds: Mutex<Vec<[u8; 32]>>,
+}
+
+impl Default for SeedHashChain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SeedHashChain {
+    /// Creates a chain holding only the seed of epoch 0.
+    pub fn new() -> Self {
+        SeedHashChain {
+            seeds: Mutex::new(vec![seed_hash(0)]),
+        }
+    }
+
+    /// Returns the seed hash of `epoch`, extending the chain up to it first
+    /// if needed, or `None` if `epoch` is not below [`MAX_EPOCH`].
+    pub fn seed_for_epoch(&self, epoch: u64) -> Option<[u8; 32]> {
+        if epoch >= MAX_EPOCH {
+            return None;
+        }
+        let mut seeds = self.seeds.lock().unwrap();
+        while seeds.len() as u64 <= epoch {
+            let next = keccak256(seeds.last().unwrap());
+            seeds.push(next);
+        }
+        Some(seeds[epoch as usize])
+    }
+
+    /// Returns the epoch whose seed hash is `seed`, extending the chain up to
+    /// `max_epoch`, or [`MAX_EPOCH`] if lower, while looking, or `None` if no
+    /// epoch below it has that seed.
+    pub fn epoch_of(&self, seed: &[u8; 32], max_epoch: u64) -> Option<u64> {
+        let max_epoch = max_epoch.min(MAX_EPOCH);
+        let mut seeds = self.seeds.lock().unwrap();
+        if let Some(epoch) = seeds.iter().position(|held| held == seed) {
+            return Some(epoch as u64);
+        }
+        while (seeds.len() as u64) < max_epoch {
+            let next = keccak256(seeds.last().unwrap());
+            seeds.push(next);
+            if next == *seed {
+                return Some(seeds.len() as u64 - 1);
+            }
+        }
+        None
+    }
+
+    /// Returns the number of epochs whose seeds are held.
+    pub fn len(&self) -> u64 {
+        self.seeds.lock().unwrap().len() as u64
+    }
+
+    /// Returns `false`: the chain always holds the seed of epoch 0.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seed_hash_chain_matches_seed_hash() {
+        let chain = SeedHashChain::new();
+        assert_eq!(chain.len(), 1);
+        assert_eq!(chain.seed_for_epoch(5), Some(seed_hash(5)));
+        assert_eq!(chain.len(), 6);
+
+        // Earlier epochs are lookups; later ones extend from the last held.
+        assert_eq!(chain.seed_for_epoch(2), Some(seed_hash(2)));
+        assert_eq!(chain.len(), 6);
+        assert_eq!(chain.seed_for_epoch(9), Some(seed_hash(9)));
+        assert_eq!(chain.seed_for_epoch(0), Some([0; 32]));
+        assert_eq!(chain.len(), 10);
+
+        // Epochs past the last supported one are refused without growing.
+        assert_eq!(chain.seed_for_epoch(MAX_EPOCH), None);
+        assert_eq!(chain.seed_for_epoch(u64::MAX), None);
+        assert_eq!(chain.len(), 10);
+
+        assert_eq!(chain.epoch_of(&seed_hash(3), 100), Some(3));
+        assert_eq!(chain.epoch_of(&seed_hash(20), 100), Some(20));
+        assert_eq!(chain.len(), 21);
+        assert_eq!(chain.epoch_of(&seed_hash(20), 10), Some(20));
+        assert_eq!(chain.epoch_of(&[7; 32], 30), None);
+        assert_eq!(chain.len(), 30);
+        assert_eq!(chain.epoch_of(&[7; 32], u64::MAX), None);
+        assert_eq!(chain.len(), MAX_EPOCH);
+    }
+}
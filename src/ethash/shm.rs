@@ -0,0 +1,233 @@
+//! A full DAG in a named shared-memory segment.
+//!
+//! Each verifier process that maps or generates its own dataset spends
+//! several gigabytes on the same bytes. A [`SharedDag`] lives in a segment
+//! named after its chain and epoch under `/dev/shm` (the directory POSIX
+//! `shm_open` names live in on Linux; the temporary directory elsewhere), so
+//! every process on the machine maps the one copy the first of them wrote,
+//! and the kernel holds its pages once.
+//!
+//! A segment holds the dataset as a go-ethereum DAG dump does: the dump
+//! magic, then the raw little-endian items. It is written under a name of
+//! its own and linked into place once complete, so a process never maps a
+//! partial dataset; two processes that find no segment at the same time both
+//! generate it, and the later one maps the first one's.
+//!
+//! On Windows the segment is a file in the temporary directory marked
+//! temporary, which keeps its pages in the file cache rather than writing
+//! them back, and every process mapping it shares the same pages.
+
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::ethash::buffer::{DagBuffer, MmapDag, GETH_DUMP_MAGIC};
+use crate::ethash::dataset::generate_dataset_chunks;
+
+/// Dataset items generated at a time while filling a segment.
+const ITEMS_PER_CHUNK: usize = 1 << 16;
+
+/// A dataset mapped read-only from a named shared-memory segment.
+pub struct SharedDag {
+    dag: MmapDag,
+    path: PathBuf,
+}
+
+/// Returns the directory segments are created in: `/dev/shm` on Linux and
+/// the temporary directory elsewhere.
+pub fn segment_dir() -> PathBuf {
+    if cfg!(target_os = "linux") {
+        PathBuf::from("/dev/shm")
+    } else {
+        std::env::temp_dir()
+    }
+}
+
+/// Returns the segment name for the dataset of `epoch` on the chain named
+/// `chain`, e.g. `progpow-ethereum-412`.
+pub fn segment_name(chain: &str, epoch: u64) -> String {
+    format!("progpow-{chain}-{epoch}")
+}
+
+impl SharedDag {
+    /// Maps the existing segment `name` in [`segment_dir`].
+    ///
+    /// # Returns
+    ///
+    /// The mapped DAG, or an I/O error if there is no such segment or it
+    /// does not hold a DAG; see [`MmapDag::open`].
+    pub fn open(name: &str) -> io::Result<Self> {
+        Self::open_in(segment_dir(), name)
+    }
+
+    /// Maps the existing segment `name` in `dir`.
+    pub fn open_in(dir: impl AsRef<Path>, name: &str) -> io::Result<Self> {
+        let path = dir.as_ref().join(name);
+        let dag = MmapDag::open(&path)?;
+        Ok(SharedDag { dag, path })
+    }
+
+    /// Maps the segment `name` in [`segment_dir`], first generating it from
+    /// the light cache if no process has yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The segment's name, e.g. from [`segment_name`].
+    /// * `cache` - The epoch's ethash light cache as little-endian words.
+    /// * `size` - The size of the epoch's dataset in bytes.
+    ///
+    /// # Returns
+    ///
+    /// The mapped DAG, or an I/O error if the segment cannot be created or
+    /// mapped, or if the segment found holds a dataset of another size.
+    pub fn open_or_create(name: &str, cache: &[u32], size: u64) -> io::Result<Self> {
+        Self::open_or_create_in(segment_dir(), name, cache, size)
+    }
+
+    /// Maps the segment `name` in `dir`, first generating it from the light
+    /// cache if no process has yet; see [`open_or_create`](Self::open_or_create).
+    pub fn open_or_create_in(
+        dir: impl AsRef<Path>,
+        name: &str,
+        cache: &[u32],
+        size: u64,
+    ) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        let shared = match Self::open_in(dir, name) {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                create(dir, name, cache, size)?;
+                Self::open_in(dir, name)?
+            }
+            opened => opened?,
+        };
+        if shared.size() != size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "segment {name} holds {} bytes of dataset, not {size}",
+                    shared.size()
+                ),
+            ));
+        }
+        Ok(shared)
+    }
+
+    /// Returns the path of the segment.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Removes the segment's name, so the next process generates it anew.
+    ///
+    /// Processes that mapped it, this one included, keep their maps until
+    /// they drop them; the memory is freed after the last one does. Windows
+    /// may refuse to remove a segment that is still mapped, in which case
+    /// this returns its error and the segment stays.
+    pub fn unlink(&self) -> io::Result<()> {
+        fs::remove_file(&self.path)
+    }
+}
+
+impl DagBuffer for SharedDag {
+    fn size(&self) -> u64 {
+        self.dag.size()
+    }
+
+    fn read_item(&self, index: u32, out: &mut [u8; 64]) {
+        self.dag.read_item(index, out)
+    }
+
+    fn c_dag(&self) -> Vec<u32> {
+        self.dag.c_dag()
+    }
+}
+
+/// Generates the segment `name` in `dir` into a segment of this process's
+/// own and links it into place, unless another process linked one first.
+fn create(dir: &Path, name: &str, cache: &[u32], size: u64) -> io::Result<()> {
+    let partial = dir.join(format!("{name}.{}.partial", std::process::id()));
+    let written =
+        fill(&partial, cache, size).and_then(|()| fs::hard_link(&partial, dir.join(name)));
+    let removed = fs::remove_file(&partial);
+    match written {
+        Err(error) if error.kind() == io::ErrorKind::AlreadyExists => removed,
+        written => written.and(removed),
+    }
+}
+
+/// Writes the dataset of `size` bytes for `cache` to a new file at `path`.
+fn fill(path: &Path, cache: &[u32], size: u64) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.read(true).write(true).create_new(true);
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        // FILE_ATTRIBUTE_TEMPORARY: keep the pages in memory where possible.
+        options.attributes(0x100);
+    }
+    let file = options.open(path)?;
+    file.set_len(GETH_DUMP_MAGIC.len() as u64 + size)?;
+    // SAFETY: the file was created by this call and is not yet linked
+    // under its segment name, so no other map of it exists.
+    let mut map = unsafe { memmap2::MmapMut::map_mut(&file)? };
+    map[..GETH_DUMP_MAGIC.len()].copy_from_slice(&GETH_DUMP_MAGIC);
+    let mut bytes = map[GETH_DUMP_MAGIC.len()..].chunks_exact_mut(4);
+    generate_dataset_chunks(cache, size, ITEMS_PER_CHUNK, |chunk| {
+        for (word, out) in chunk.iter().zip(&mut bytes) {
+            out.copy_from_slice(&word.to_le_bytes());
+        }
+        Ok::<_, io::Error>(())
+    })?;
+    map.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ethash::dataset::generate_dataset;
+
+    #[test]
+    fn test_processes_share_one_segment() {
+        let cache: Vec<u32> = (0..64 * 16u32)
+            .map(|i| i.wrapping_mul(0x9e3779b9))
+            .collect();
+        let size = 300 * 64;
+        let dir = std::env::temp_dir();
+        let name = format!("progpow-shm-test-{}", std::process::id());
+
+        let created = SharedDag::open_or_create_in(&dir, &name, &cache, size).unwrap();
+        let full = generate_dataset(&cache, size);
+        assert_eq!(created.size(), size);
+        assert_eq!(created.c_dag(), full.c_dag());
+        assert_eq!(created.lookup(16 * 299), full.lookup(16 * 299));
+
+        // Another opener maps the same segment rather than generating one.
+        let opened = SharedDag::open_or_create_in(&dir, &name, &[], size).unwrap();
+        assert_eq!(opened.lookup(16 * 123), full.lookup(16 * 123));
+        assert!(SharedDag::open_or_create_in(&dir, &name, &cache, size + 64).is_err());
+        assert!(!dir
+            .join(format!("{name}.{}.partial", std::process::id()))
+            .exists());
+
+        // Unlinking leaves existing maps readable. Windows may refuse to
+        // remove a mapped file, so there the maps go first.
+        #[cfg(unix)]
+        {
+            created.unlink().unwrap();
+            assert_eq!(opened.lookup(16 * 7), full.lookup(16 * 7));
+        }
+        #[cfg(not(unix))]
+        {
+            let path = created.path().to_path_buf();
+            drop((created, opened));
+            fs::remove_file(path).unwrap();
+        }
+        assert_eq!(
+            SharedDag::open_in(&dir, &name)
+                .err()
+                .map(|error| error.kind()),
+            Some(io::ErrorKind::NotFound)
+        );
+        assert_eq!(segment_name("ethereum", 412), "progpow-ethereum-412");
+    }
+}
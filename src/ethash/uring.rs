@@ -0,0 +1,410 @@
+//! On-demand DAG reads from a file through Linux io_uring.
+//!
+//! A verification box that cannot hold the dataset in memory can still read
+//! it from a DAG file. Through a memory map, every read past the page cache
+//! is a page fault, served one at a time: each loop iteration of a hash
+//! reads four consecutive items, so it waits for up to four disk reads in
+//! turn. A [`UringDag`] submits the four reads of an iteration together in
+//! one `io_uring_enter`, so the device serves them in parallel and the
+//! iteration waits for the slowest one only.
+//!
+//! The hashing code asks for items one at a time through `lookup`, so the
+//! DAG reads a whole load group when asked for its first item and serves
+//! the other three from a per-thread buffer. Each thread takes a ring from a
+//! shared pool for the duration of a batch.
+
+use std::cell::RefCell;
+use std::ffi::c_void;
+use std::fs::File;
+use std::io::{self, Read};
+use std::mem::size_of;
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use rustix::io::Errno;
+use rustix::io_uring::{
+    io_uring_cqe, io_uring_enter, io_uring_params, io_uring_ptr, io_uring_setup, io_uring_sqe,
+    io_uring_user_data, IoringEnterFlags, IoringFeatureFlags, IoringOp, IORING_OFF_CQ_RING,
+    IORING_OFF_SQES, IORING_OFF_SQ_RING,
+};
+use rustix::mm::{mmap, munmap, MapFlags, ProtFlags};
+
+use crate::basic_algorithm::{PROGPOW_CACHE_WORDS, PROGPOW_MIX_BYTES};
+use crate::endian::le_words;
+use crate::ethash::buffer::{DagBuffer, GETH_DUMP_MAGIC};
+
+/// Bytes in one dataset item.
+const ITEM_BYTES: usize = 64;
+
+/// Items one loop iteration of a hash reads, starting at a multiple of it.
+const ITEMS_PER_LOAD: usize = PROGPOW_MIX_BYTES / ITEM_BYTES;
+
+/// Submission queue entries in each ring.
+const QUEUE_DEPTH: u32 = 64;
+
+/// Identifies each [`UringDag`], so a thread's buffered load group is never
+/// served from another DAG.
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A load group a thread read: the DAG's id, the group's first item and its
+/// items.
+type LoadGroup = (u64, u32, [[u8; ITEM_BYTES]; ITEMS_PER_LOAD]);
+
+thread_local! {
+    /// The load group this thread read last.
+    static LOADED: RefCell<Option<LoadGroup>> = const { RefCell::new(None) };
+}
+
+/// A DAG file read on demand through io_uring.
+pub struct UringDag {
+    file: File,
+    /// Bytes before the first item: the dump magic, if present.
+    offset: u64,
+    size: u64,
+    c_dag: Vec<u32>,
+    id: u64,
+    rings: Mutex<Vec<Ring>>,
+}
+
+impl UringDag {
+    /// Opens the DAG file at `path`, laid out as for `MmapDag`: raw items,
+    /// optionally after go-ethereum's dump magic.
+    ///
+    /// # Returns
+    ///
+    /// The DAG, or an I/O error if the file cannot be read, does not hold
+    /// whole 64-byte items, or is too short to hold the `PROGPOW_CACHE_WORDS`
+    /// cached words, or if the kernel does not offer io_uring.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut magic = [0u8; 8];
+        let offset = match file.read_exact(&mut magic) {
+            Ok(()) if magic == GETH_DUMP_MAGIC => GETH_DUMP_MAGIC.len() as u64,
+            _ => 0,
+        };
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+        let size = len - offset;
+        if !size.is_multiple_of(ITEM_BYTES as u64) {
+            return Err(invalid("DAG file does not hold whole 64-byte items"));
+        }
+        if size < PROGPOW_CACHE_WORDS as u64 * 4 {
+            return Err(invalid("DAG file is shorter than the cached DAG words"));
+        }
+
+        let mut dag = UringDag {
+            file,
+            offset,
+            size,
+            c_dag: Vec::new(),
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            rings: Mutex::new(vec![Ring::new(QUEUE_DEPTH)?]),
+        };
+        let indices: Vec<u32> = (0..(PROGPOW_CACHE_WORDS * 4 / ITEM_BYTES) as u32).collect();
+        let mut items = vec![[0u8; ITEM_BYTES]; indices.len()];
+        dag.read_items(&indices, &mut items)?;
+        dag.c_dag = items.iter().flat_map(|item| le_words(item)).collect();
+        Ok(dag)
+    }
+
+    /// Reads the items `indices` into `out` in batches of up to the ring
+    /// depth, each submitted with one system call.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `out` is shorter than `indices`.
+    pub fn read_items(&self, indices: &[u32], out: &mut [[u8; ITEM_BYTES]]) -> io::Result<()> {
+        assert!(
+            out.len() >= indices.len(),
+            "`out` is shorter than `indices`"
+        );
+        let ring = self.rings.lock().unwrap().pop();
+        let mut ring = match ring {
+            Some(ring) => ring,
+            None => Ring::new(QUEUE_DEPTH)?,
+        };
+        let fd = self.file.as_raw_fd();
+        for (indices, out) in indices
+            .chunks(QUEUE_DEPTH as usize)
+            .zip(out.chunks_mut(QUEUE_DEPTH as usize))
+        {
+            let reads: Vec<(u64, *mut u8)> = indices
+                .iter()
+                .zip(out.iter_mut())
+                .map(|(&index, item)| {
+                    let at = self.offset + index as u64 * ITEM_BYTES as u64;
+                    (at, item.as_mut_ptr())
+                })
+                .collect();
+            // SAFETY: every buffer is a distinct 64-byte item of `out`,
+            // borrowed mutably until the batch completes, and `fd` is this
+            // DAG's open file.
+            unsafe { ring.read(fd, &reads)? };
+        }
+        // A ring whose batch failed may hold queued entries; only clean ones
+        // go back to the pool.
+        self.rings.lock().unwrap().push(ring);
+        Ok(())
+    }
+}
+
+impl DagBuffer for UringDag {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the read fails, as mapped DAGs fault on I/O errors.
+    fn read_item(&self, index: u32, out: &mut [u8; 64]) {
+        if let Err(error) = self.read_items(&[index], std::slice::from_mut(out)) {
+            panic!("reading DAG item {index} failed: {error}");
+        }
+    }
+
+    fn lookup(&self, word_index: u32) -> Vec<u8> {
+        let index = word_index / (ITEM_BYTES / 4) as u32;
+        let first = index - index % ITEMS_PER_LOAD as u32;
+        LOADED.with(|loaded| {
+            let mut loaded = loaded.borrow_mut();
+            match &*loaded {
+                Some((id, at, _)) if *id == self.id && *at == first => {}
+                _ => {
+                    let items_in_dag = (self.size / ITEM_BYTES as u64) as u32;
+                    let indices: Vec<u32> =
+                        (first..items_in_dag.min(first + ITEMS_PER_LOAD as u32)).collect();
+                    let mut items = [[0u8; ITEM_BYTES]; ITEMS_PER_LOAD];
+                    if let Err(error) = self.read_items(&indices, &mut items) {
+                        panic!("reading DAG items from {first} failed: {error}");
+                    }
+                    *loaded = Some((self.id, first, items));
+                }
+            }
+            let (_, _, items) = loaded.as_ref().unwrap();
+            items[(index - first) as usize].to_vec()
+        })
+    }
+
+    fn c_dag(&self) -> Vec<u32> {
+        self.c_dag.clone()
+    }
+}
+
+/// A region of a ring mapped from the kernel.
+struct Mapping {
+    ptr: *mut c_void,
+    len: usize,
+}
+
+impl Mapping {
+    fn new(fd: &OwnedFd, len: usize, offset: u64) -> io::Result<Self> {
+        // SAFETY: a fresh shared map of the ring's own region, at an address
+        // of the kernel's choosing.
+        let ptr = unsafe {
+            mmap(
+                ptr::null_mut(),
+                len,
+                ProtFlags::READ | ProtFlags::WRITE,
+                MapFlags::SHARED | MapFlags::POPULATE,
+                fd,
+                offset,
+            )?
+        };
+        Ok(Mapping { ptr, len })
+    }
+
+    /// Returns the pointer `offset` bytes into the region.
+    fn at<T>(&self, offset: u32) -> *mut T {
+        // SAFETY: the kernel's offsets lie within the region it sized.
+        unsafe { self.ptr.cast::<u8>().add(offset as usize).cast() }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // SAFETY: the region was mapped by `new` and is unmapped once.
+        let _ = unsafe { munmap(self.ptr, self.len) };
+    }
+}
+
+/// One io_uring instance and its mapped queues.
+struct Ring {
+    /// The queues' maps; the submission ring's also holds the completion
+    /// ring when the kernel maps both at once.
+    _maps: Vec<Mapping>,
+    fd: OwnedFd,
+    entries: u32,
+    sq_tail: *const AtomicU32,
+    sq_mask: u32,
+    sq_array: *mut u32,
+    sqes: *mut io_uring_sqe,
+    cq_head: *const AtomicU32,
+    cq_tail: *const AtomicU32,
+    cq_mask: u32,
+    cqes: *const io_uring_cqe,
+}
+
+// SAFETY: the ring's pointers refer to its own maps, and it is used by one
+// thread at a time, taken from and returned to the pool under a mutex.
+unsafe impl Send for Ring {}
+
+impl Ring {
+    /// Sets up a ring with `entries` submission queue entries.
+    fn new(entries: u32) -> io::Result<Self> {
+        let mut params = io_uring_params::default();
+        // SAFETY: `params` is a valid, zeroed parameter block.
+        let fd = unsafe { io_uring_setup(entries, &mut params)? };
+        let sq_len = params.sq_off.array as usize + params.sq_entries as usize * 4;
+        let cq_len =
+            params.cq_off.cqes as usize + params.cq_entries as usize * size_of::<io_uring_cqe>();
+        let single = params.features.contains(IoringFeatureFlags::SINGLE_MMAP);
+
+        let sq = Mapping::new(
+            &fd,
+            if single { sq_len.max(cq_len) } else { sq_len },
+            IORING_OFF_SQ_RING,
+        )?;
+        let sqes = Mapping::new(
+            &fd,
+            params.sq_entries as usize * size_of::<io_uring_sqe>(),
+            IORING_OFF_SQES,
+        )?;
+        let cq = if single {
+            None
+        } else {
+            Some(Mapping::new(&fd, cq_len, IORING_OFF_CQ_RING)?)
+        };
+        let cq_map = cq.as_ref().unwrap_or(&sq);
+        // SAFETY: the masks are plain words the kernel set up and never
+        // changes.
+        let (sq_mask, cq_mask) = unsafe {
+            (
+                *sq.at::<u32>(params.sq_off.ring_mask),
+                *cq_map.at::<u32>(params.cq_off.ring_mask),
+            )
+        };
+        let ring = Ring {
+            entries: params.sq_entries,
+            sq_tail: sq.at(params.sq_off.tail),
+            sq_mask,
+            sq_array: sq.at(params.sq_off.array),
+            sqes: sqes.at(0),
+            cq_head: cq_map.at(params.cq_off.head),
+            cq_tail: cq_map.at(params.cq_off.tail),
+            cq_mask,
+            cqes: cq_map.at(params.cq_off.cqes),
+            fd,
+            _maps: [Some(sq), Some(sqes), cq].into_iter().flatten().collect(),
+        };
+        Ok(ring)
+    }
+
+    /// Reads 64 bytes at each `(offset, buffer)` of `reads` from `fd`,
+    /// submitting them together and waiting for all of them.
+    ///
+    /// # Safety
+    ///
+    /// Every buffer must be valid for 64 bytes of writes and not otherwise
+    /// accessed until this returns, and `fd` must be an open file.
+    unsafe fn read(&mut self, fd: i32, reads: &[(u64, *mut u8)]) -> io::Result<()> {
+        let count = reads.len() as u32;
+        assert!(count <= self.entries, "more reads than ring entries");
+        // This thread is the only producer, so the tail is its own.
+        let tail = (*self.sq_tail).load(Ordering::Relaxed);
+        for (i, &(offset, buffer)) in reads.iter().enumerate() {
+            let slot = tail.wrapping_add(i as u32) & self.sq_mask;
+            let mut sqe = io_uring_sqe {
+                opcode: IoringOp::Read,
+                fd,
+                user_data: io_uring_user_data::from_u64(i as u64),
+                ..Default::default()
+            };
+            sqe.off_or_addr2.off = offset;
+            sqe.addr_or_splice_off_in.addr = io_uring_ptr::new(buffer.cast());
+            sqe.len.len = ITEM_BYTES as u32;
+            self.sqes.add(slot as usize).write(sqe);
+            self.sq_array.add(slot as usize).write(slot);
+        }
+        (*self.sq_tail).store(tail.wrapping_add(count), Ordering::Release);
+
+        let mut to_submit = count;
+        while to_submit > 0 {
+            match io_uring_enter(&self.fd, to_submit, 0, IoringEnterFlags::empty()) {
+                Ok(submitted) => to_submit -= submitted,
+                Err(Errno::INTR) => {}
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        let mut failed = None;
+        let mut reaped = 0;
+        while reaped < count {
+            let head = (*self.cq_head).load(Ordering::Relaxed);
+            if head == (*self.cq_tail).load(Ordering::Acquire) {
+                match io_uring_enter(&self.fd, 0, 1, IoringEnterFlags::GETEVENTS) {
+                    Ok(_) | Err(Errno::INTR) => continue,
+                    Err(error) => return Err(error.into()),
+                }
+            }
+            let cqe = &*self.cqes.add((head & self.cq_mask) as usize);
+            let result = cqe.res;
+            (*self.cq_head).store(head.wrapping_add(1), Ordering::Release);
+            reaped += 1;
+            if result < 0 {
+                failed.get_or_insert(io::Error::from_raw_os_error(-result));
+            } else if result as usize != ITEM_BYTES {
+                failed.get_or_insert(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+        failed.map_or(Ok(()), Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ethash::dataset::generate_dataset;
+
+    #[test]
+    fn test_uring_dag_agrees_with_memory() {
+        let cache: Vec<u32> = (0..64 * 16u32)
+            .map(|i| i.wrapping_mul(0x9e3779b9))
+            .collect();
+        let full = generate_dataset(&cache, 302 * 64);
+        let mut bytes = GETH_DUMP_MAGIC.to_vec();
+        bytes.extend(full.iter().flat_map(|word| word.to_le_bytes()));
+        let path = std::env::temp_dir().join(format!("progpow-uring-{}.bin", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+
+        let dag = UringDag::open(&path).unwrap();
+        assert_eq!(dag.size(), full.size());
+        assert_eq!(dag.c_dag(), full.c_dag());
+        // Every word of a load group, groups in turn and the short last one.
+        for word in (16 * 296..16 * 302).chain([0, 17, 16 * 150 + 3]) {
+            assert_eq!(dag.lookup(word), full.lookup(word), "word {word}");
+        }
+        let mut item = [0u8; 64];
+        dag.read_item(77, &mut item);
+        assert_eq!(item.to_vec(), full.lookup(16 * 77));
+
+        // Threads take rings of their own.
+        std::thread::scope(|scope| {
+            for t in 0..3u32 {
+                let (dag, full) = (&dag, &full);
+                scope.spawn(move || {
+                    for word in (t * 16..16 * 302).step_by(61) {
+                        assert_eq!(dag.lookup(word), full.lookup(word));
+                    }
+                });
+            }
+        });
+        assert!(dag.read_items(&[302], &mut [[0; 64]]).is_err());
+
+        std::fs::write(&path, &bytes[..8 + 64 * 200]).unwrap();
+        assert!(UringDag::open(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,723 @@
+//! C bindings for hashing, seal verification, and epoch cache and dataset
+//! management.
+//!
+//! The functions here are exported unmangled so the crate can be linked as a
+//! shared or static library from C and C++; `include/progpow.h` declares
+//! them and is generated with `cbindgen`.
+//!
+//! Callers describe the dataset once, then hash and verify against the
+//! returned context. A context is created either from a callback with
+//! [`progpow_context_new`], or from an epoch's light cache or full dataset,
+//! which the library generates, saves and loads itself. Every pointer argument must be
+//! valid for the documented length; null pointers are reported as
+//! [`ProgpowStatus::NullPointer`] rather than dereferenced.
+
+use std::ffi::{c_char, c_void, CStr};
+use std::fs;
+use std::path::PathBuf;
+use std::slice;
+use std::sync::Arc;
+
+use crate::basic_algorithm::{
+    PROGPOW_CACHE_BYTES, PROGPOW_CACHE_WORDS, PROGPOW_CNT_CACHE, PROGPOW_CNT_DAG, PROGPOW_CNT_MATH,
+    PROGPOW_DAG_LOADS, PROGPOW_LANES, PROGPOW_PERIOD_LENGTH, PROGPOW_REGS,
+};
+use crate::endian::{le_bytes, le_words};
+use crate::ethash::buffer::GETH_DUMP_MAGIC;
+use crate::ethash::cache::{cache_size, dataset_size, make_cache, seed_hash, MAX_EPOCH};
+use crate::ethash::dataset::{calc_dataset_item, generate_c_dag, generate_dataset, HASH_WORDS};
+use crate::progpow::progpow::progpow;
+use crate::progpow::verify::{verify_seal, Seal, SealError};
+
+/// The result of an FFI call.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgpowStatus {
+    /// The call succeeded.
+    Ok = 0,
+    /// A required pointer argument was null.
+    NullPointer = 1,
+    /// The seal's mix hash differs from the recomputed one.
+    MixMismatch = 2,
+    /// The mix hash matches, but the final hash exceeds the boundary.
+    BoundaryNotMet = 3,
+    /// A file could not be read or written.
+    Io = 4,
+}
+
+/// The ProgPoW parameters this library was built with.
+///
+/// C callers can compare these against the variant they expect before
+/// trusting any hash.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProgpowParams {
+    /// Blocks per period, after which the random program changes.
+    pub period_length: u64,
+    /// Epochs below this one are accepted by the cache and dataset functions.
+    pub max_epoch: u64,
+    /// Parallel lanes per hash.
+    pub lanes: u32,
+    /// Registers per lane.
+    pub regs: u32,
+    /// DAG words loaded per lane and loop.
+    pub dag_loads: u32,
+    /// Bytes of the cached DAG prefix (`c_dag`).
+    pub cache_bytes: u32,
+    /// Loop iterations, each doing one DAG access.
+    pub cnt_dag: u32,
+    /// Cache accesses per loop.
+    pub cnt_cache: u32,
+    /// Math operations per loop.
+    pub cnt_math: u32,
+}
+
+/// Reads the 64-byte DAG item `item_index` into `out`.
+///
+/// `user_data` is the pointer given to [`progpow_context_new`]. The callback
+/// may be called from any thread using the context.
+pub type ProgpowLookupFn =
+    Option<unsafe extern "C" fn(user_data: *mut c_void, item_index: u32, out: *mut u8)>;
+
+/// An epoch's ethash light cache.
+pub struct ProgpowCache {
+    epoch: u64,
+    words: Arc<Vec<u32>>,
+}
+
+impl ProgpowCache {
+    /// Generates the cache of `epoch` with `size` bytes.
+    fn generate(epoch: u64, size: u64) -> Self {
+        ProgpowCache {
+            epoch,
+            words: Arc::new(make_cache(size, &seed_hash(epoch))),
+        }
+    }
+}
+
+/// An epoch's full dataset (the DAG), held in memory.
+pub struct ProgpowDataset {
+    epoch: u64,
+    words: Arc<Vec<u32>>,
+}
+
+/// A dataset description that hashes and verifications run against.
+pub struct ProgpowContext {
+    size: u64,
+    c_dag: Vec<u32>,
+    lookup: Box<dyn Fn(u32) -> Vec<u8>>,
+}
+
+impl ProgpowContext {
+    /// Computes `(mix_hash, final_hash)` for a header and nonce.
+    fn hash(&self, header_hash: &[u8; 32], block_number: u64, nonce: u64) -> (Vec<u8>, Vec<u8>) {
+        progpow(
+            header_hash,
+            nonce,
+            self.size,
+            block_number,
+            &self.c_dag,
+            &self.lookup,
+        )
+    }
+}
+
+/// Returns the ProgPoW parameters this library was built with.
+#[no_mangle]
+pub extern "C" fn progpow_params() -> ProgpowParams {
+    ProgpowParams {
+        period_length: PROGPOW_PERIOD_LENGTH,
+        max_epoch: MAX_EPOCH,
+        lanes: PROGPOW_LANES as u32,
+        regs: PROGPOW_REGS as u32,
+        dag_loads: PROGPOW_DAG_LOADS as u32,
+        cache_bytes: PROGPOW_CACHE_BYTES as u32,
+        cnt_dag: PROGPOW_CNT_DAG as u32,
+        cnt_cache: PROGPOW_CNT_CACHE as u32,
+        cnt_math: PROGPOW_CNT_MATH as u32,
+    }
+}
+
+/// Creates a context over a dataset served by a callback.
+///
+/// # Arguments
+///
+/// * `dataset_size` - The size of the dataset in bytes.
+/// * `c_dag` - The cached DAG prefix; the first `PROGPOW_CACHE_WORDS` words
+///   are copied.
+/// * `c_dag_len` - The number of words at `c_dag`.
+/// * `lookup` - The callback reading DAG items.
+/// * `user_data` - An opaque pointer passed to every `lookup` call.
+///
+/// # Returns
+///
+/// A context to release with [`progpow_context_free`], or null if a pointer
+/// is null or `c_dag_len` is too short.
+///
+/// # Safety
+///
+/// `c_dag` must point to `c_dag_len` readable words. `lookup` must write 64
+/// bytes to `out` and stay callable with `user_data` until the context is
+/// freed.
+#[no_mangle]
+pub unsafe extern "C" fn progpow_context_new(
+    dataset_size: u64,
+    c_dag: *const u32,
+    c_dag_len: usize,
+    lookup: ProgpowLookupFn,
+    user_data: *mut c_void,
+) -> *mut ProgpowContext {
+    let Some(lookup) = lookup else {
+        return std::ptr::null_mut();
+    };
+    if c_dag.is_null() || c_dag_len < PROGPOW_CACHE_WORDS {
+        return std::ptr::null_mut();
+    }
+    let c_dag = slice::from_raw_parts(c_dag, PROGPOW_CACHE_WORDS).to_vec();
+    let lookup = move |word_index: u32| {
+        let mut item = vec![0u8; 64];
+        // SAFETY: the caller guarantees the callback writes 64 bytes.
+        unsafe { lookup(user_data, word_index / 16, item.as_mut_ptr()) };
+        item
+    };
+    Box::into_raw(Box::new(ProgpowContext {
+        size: dataset_size,
+        c_dag,
+        lookup: Box::new(lookup),
+    }))
+}
+
+/// Releases a context. Passing null does nothing.
+///
+/// # Safety
+///
+/// `context` must come from [`progpow_context_new`] and not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn progpow_context_free(context: *mut ProgpowContext) {
+    if !context.is_null() {
+        drop(Box::from_raw(context));
+    }
+}
+
+/// Creates a context verifying against an epoch's light cache.
+///
+/// DAG items are computed from the cache on demand, so no dataset is needed;
+/// this suits verifiers checking a few seals. The context keeps its own
+/// reference to the cache, which may be freed independently.
+///
+/// # Returns
+///
+/// A context to release with [`progpow_context_free`], or null if `cache`
+/// is null.
+///
+/// # Safety
+///
+/// `cache` must be null or a live cache.
+#[no_mangle]
+pub unsafe extern "C" fn progpow_context_from_cache(
+    cache: *const ProgpowCache,
+) -> *mut ProgpowContext {
+    let Some(cache) = cache.as_ref() else {
+        return std::ptr::null_mut();
+    };
+    let words = cache.words.clone();
+    Box::into_raw(Box::new(ProgpowContext {
+        size: dataset_size(cache.epoch),
+        c_dag: generate_c_dag(&cache.words),
+        lookup: Box::new(move |word_index| {
+            calc_dataset_item(&words, word_index / HASH_WORDS as u32).to_vec()
+        }),
+    }))
+}
+
+/// Creates a context hashing against an epoch's full dataset.
+///
+/// The context keeps its own reference to the dataset, which may be freed
+/// independently.
+///
+/// # Returns
+///
+/// A context to release with [`progpow_context_free`], or null if `dataset`
+/// is null.
+///
+/// # Safety
+///
+/// `dataset` must be null or a live dataset.
+#[no_mangle]
+pub unsafe extern "C" fn progpow_context_from_dataset(
+    dataset: *const ProgpowDataset,
+) -> *mut ProgpowContext {
+    let Some(dataset) = dataset.as_ref() else {
+        return std::ptr::null_mut();
+    };
+    let words = dataset.words.clone();
+    Box::into_raw(Box::new(ProgpowContext {
+        size: words.len() as u64 * 4,
+        c_dag: words[..PROGPOW_CACHE_WORDS].to_vec(),
+        lookup: Box::new(move |word_index| {
+            let first = (word_index as usize / HASH_WORDS) * HASH_WORDS;
+            le_bytes(&words[first..first + HASH_WORDS])
+        }),
+    }))
+}
+
+/// Generates the light cache of `epoch`.
+///
+/// This takes about a second for early epochs in an optimized build.
+///
+/// # Returns
+///
+/// A cache to release with [`progpow_cache_free`], or null if `epoch` is not
+/// below `max_epoch` of [`progpow_params`].
+#[no_mangle]
+pub extern "C" fn progpow_cache_new(epoch: u64) -> *mut ProgpowCache {
+    if epoch >= MAX_EPOCH {
+        return std::ptr::null_mut();
+    }
+    Box::into_raw(Box::new(ProgpowCache::generate(epoch, cache_size(epoch))))
+}
+
+/// Loads the light cache of `epoch` saved by [`progpow_cache_save`].
+///
+/// # Returns
+///
+/// A cache to release with [`progpow_cache_free`], or null if `path` is null
+/// or the file cannot be read or does not hold a cache of `epoch`'s size.
+///
+/// # Safety
+///
+/// `path` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn progpow_cache_load(epoch: u64, path: *const c_char) -> *mut ProgpowCache {
+    if epoch >= MAX_EPOCH {
+        return std::ptr::null_mut();
+    }
+    match read_words(path, &[], cache_size(epoch)) {
+        Some(words) => Box::into_raw(Box::new(ProgpowCache {
+            epoch,
+            words: Arc::new(words),
+        })),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Saves a light cache to `path` as raw little-endian words.
+///
+/// # Safety
+///
+/// `cache` must be a live cache and `path` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn progpow_cache_save(
+    cache: *const ProgpowCache,
+    path: *const c_char,
+) -> ProgpowStatus {
+    match cache.as_ref() {
+        Some(cache) => write_words(path, &[], &cache.words),
+        None => ProgpowStatus::NullPointer,
+    }
+}
+
+/// Returns the epoch of a cache, or `u64::MAX` if `cache` is null.
+///
+/// # Safety
+///
+/// `cache` must be null or a live cache.
+#[no_mangle]
+pub unsafe extern "C" fn progpow_cache_epoch(cache: *const ProgpowCache) -> u64 {
+    cache.as_ref().map_or(u64::MAX, |cache| cache.epoch)
+}
+
+/// Releases a cache. Passing null does nothing.
+///
+/// # Safety
+///
+/// `cache` must come from this library and not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn progpow_cache_free(cache: *mut ProgpowCache) {
+    if !cache.is_null() {
+        drop(Box::from_raw(cache));
+    }
+}
+
+/// Generates the full dataset of a cache's epoch, on every available core.
+///
+/// The dataset is over a gigabyte and takes minutes to compute on a CPU;
+/// save it with [`progpow_dataset_save`] to generate it once per epoch.
+///
+/// # Returns
+///
+/// A dataset to release with [`progpow_dataset_free`], or null if `cache` is
+/// null.
+///
+/// # Safety
+///
+/// `cache` must be null or a live cache.
+#[no_mangle]
+pub unsafe extern "C" fn progpow_dataset_new(cache: *const ProgpowCache) -> *mut ProgpowDataset {
+    let Some(cache) = cache.as_ref() else {
+        return std::ptr::null_mut();
+    };
+    let words = generate_dataset(&cache.words, dataset_size(cache.epoch));
+    Box::into_raw(Box::new(ProgpowDataset {
+        epoch: cache.epoch,
+        words: Arc::new(words),
+    }))
+}
+
+/// Loads the full dataset of `epoch` saved by [`progpow_dataset_save`].
+///
+/// Files written by go-ethereum, which start with an 8-byte magic number,
+/// are accepted too.
+///
+/// # Returns
+///
+/// A dataset to release with [`progpow_dataset_free`], or null if `path` is
+/// null or the file cannot be read or does not hold `epoch`'s dataset size.
+///
+/// # Safety
+///
+/// `path` must be null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn progpow_dataset_load(
+    epoch: u64,
+    path: *const c_char,
+) -> *mut ProgpowDataset {
+    if epoch >= MAX_EPOCH {
+        return std::ptr::null_mut();
+    }
+    match read_words(path, &GETH_DUMP_MAGIC, dataset_size(epoch)) {
+        Some(words) => Box::into_raw(Box::new(ProgpowDataset {
+            epoch,
+            words: Arc::new(words),
+        })),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Saves a full dataset to `path` in go-ethereum's DAG file format.
+///
+/// # Safety
+///
+/// `dataset` must be a live dataset and `path` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn progpow_dataset_save(
+    dataset: *const ProgpowDataset,
+    path: *const c_char,
+) -> ProgpowStatus {
+    match dataset.as_ref() {
+        Some(dataset) => write_words(path, &GETH_DUMP_MAGIC, &dataset.words),
+        None => ProgpowStatus::NullPointer,
+    }
+}
+
+/// Returns the epoch of a dataset, or `u64::MAX` if `dataset` is null.
+///
+/// # Safety
+///
+/// `dataset` must be null or a live dataset.
+#[no_mangle]
+pub unsafe extern "C" fn progpow_dataset_epoch(dataset: *const ProgpowDataset) -> u64 {
+    dataset.as_ref().map_or(u64::MAX, |dataset| dataset.epoch)
+}
+
+/// Releases a dataset. Passing null does nothing.
+///
+/// # Safety
+///
+/// `dataset` must come from this library and not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn progpow_dataset_free(dataset: *mut ProgpowDataset) {
+    if !dataset.is_null() {
+        drop(Box::from_raw(dataset));
+    }
+}
+
+/// Converts a C path, or returns `None` for null.
+unsafe fn path(path: *const c_char) -> Option<PathBuf> {
+    if path.is_null() {
+        return None;
+    }
+    Some(PathBuf::from(
+        CStr::from_ptr(path).to_string_lossy().into_owned(),
+    ))
+}
+
+/// Reads `size` bytes of little-endian words from `path`, after an optional
+/// `magic` prefix.
+unsafe fn read_words(path_ptr: *const c_char, magic: &[u8], size: u64) -> Option<Vec<u32>> {
+    let bytes = fs::read(path(path_ptr)?).ok()?;
+    let body = bytes.strip_prefix(magic).unwrap_or(&bytes);
+    if body.len() as u64 != size {
+        return None;
+    }
+    Some(le_words(body))
+}
+
+/// Writes `magic` then `words` as little-endian bytes to `path`.
+unsafe fn write_words(path_ptr: *const c_char, magic: &[u8], words: &[u32]) -> ProgpowStatus {
+    let Some(path) = path(path_ptr) else {
+        return ProgpowStatus::NullPointer;
+    };
+    let mut bytes = Vec::with_capacity(magic.len() + words.len() * 4);
+    bytes.extend_from_slice(magic);
+    bytes.extend(le_bytes(words));
+    match fs::write(path, bytes) {
+        Ok(()) => ProgpowStatus::Ok,
+        Err(_) => ProgpowStatus::Io,
+    }
+}
+
+/// Computes the ProgPoW mix hash and final hash of a header and nonce.
+///
+/// # Arguments
+///
+/// * `context` - The dataset to hash against.
+/// * `header_hash` - The 32-byte header hash.
+/// * `block_number` - The block number, which selects the period.
+/// * `nonce` - The nonce.
+/// * `mix_hash_out` - Receives the 32-byte mix hash.
+/// * `final_hash_out` - Receives the 32-byte final hash.
+///
+/// # Safety
+///
+/// `context` must be a live context, and every other pointer must be valid
+/// for 32 bytes.
+#[no_mangle]
+pub unsafe extern "C" fn progpow_hash(
+    context: *const ProgpowContext,
+    header_hash: *const u8,
+    block_number: u64,
+    nonce: u64,
+    mix_hash_out: *mut u8,
+    final_hash_out: *mut u8,
+) -> ProgpowStatus {
+    if context.is_null()
+        || header_hash.is_null()
+        || mix_hash_out.is_null()
+        || final_hash_out.is_null()
+    {
+        return ProgpowStatus::NullPointer;
+    }
+    let (mix_hash, final_hash) = (*context).hash(&*header_hash.cast(), block_number, nonce);
+    mix_hash_out.copy_from_nonoverlapping(mix_hash.as_ptr(), 32);
+    final_hash_out.copy_from_nonoverlapping(final_hash.as_ptr(), 32);
+    ProgpowStatus::Ok
+}
+
+/// Verifies a seal: the mix hash must match and the final hash must not
+/// exceed the big-endian boundary.
+///
+/// # Arguments
+///
+/// * `context` - The dataset to verify against.
+/// * `header_hash` - The 32-byte header hash.
+/// * `block_number` - The block number, which selects the period.
+/// * `nonce` - The sealed nonce.
+/// * `mix_hash` - The 32-byte mix hash claimed by the seal.
+/// * `boundary` - The 32-byte big-endian target.
+/// * `final_hash_out` - Receives the 32-byte final hash when the mix hash
+///   matches; may be null.
+///
+/// # Returns
+///
+/// [`ProgpowStatus::Ok`] for a valid seal, or the status of the first check
+/// that failed.
+///
+/// # Safety
+///
+/// `context` must be a live context, and every other non-null pointer must
+/// be valid for 32 bytes.
+#[no_mangle]
+pub unsafe extern "C" fn progpow_verify_seal(
+    context: *const ProgpowContext,
+    header_hash: *const u8,
+    block_number: u64,
+    nonce: u64,
+    mix_hash: *const u8,
+    boundary: *const u8,
+    final_hash_out: *mut u8,
+) -> ProgpowStatus {
+    if context.is_null() || header_hash.is_null() || mix_hash.is_null() || boundary.is_null() {
+        return ProgpowStatus::NullPointer;
+    }
+    let context = &*context;
+    let seal = Seal {
+        header_hash: *header_hash.cast(),
+        block_number,
+        nonce,
+        mix_hash: *mix_hash.cast(),
+        boundary: *boundary.cast(),
+    };
+    let (status, final_hash) =
+        match verify_seal(&seal, context.size, &context.c_dag, &context.lookup) {
+            Ok(final_hash) => (ProgpowStatus::Ok, Some(final_hash)),
+            Err(SealError::BoundaryNotMet { final_hash }) => {
+                (ProgpowStatus::BoundaryNotMet, Some(final_hash))
+            }
+            Err(SealError::MixMismatch { .. }) => (ProgpowStatus::MixMismatch, None),
+        };
+    if let (Some(final_hash), false) = (final_hash, final_hash_out.is_null()) {
+        final_hash_out.copy_from_nonoverlapping(final_hash.as_ptr(), 32);
+    }
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The synthetic dataset used across the crate's tests, via the C callback.
+    unsafe extern "C" fn lookup(user_data: *mut c_void, item_index: u32, out: *mut u8) {
+        *user_data.cast::<u32>() += 1;
+        for i in 0..64u32 {
+            *out.add(i as usize) = (item_index * 16 + i) as u8;
+        }
+    }
+
+    #[test]
+    fn test_ffi_hash_and_verify() {
+        let c_dag: Vec<u32> = (0..4 * 1024).collect();
+        let header: Vec<u8> = (0..32).collect();
+        let mut calls = 0u32;
+        let user_data = (&mut calls as *mut u32).cast();
+        let (mut mix_hash, mut final_hash) = ([0u8; 32], [0u8; 32]);
+
+        unsafe {
+            assert!(
+                progpow_context_new(1024, c_dag.as_ptr(), 100, Some(lookup), user_data).is_null()
+            );
+            let context =
+                progpow_context_new(1024, c_dag.as_ptr(), c_dag.len(), Some(lookup), user_data);
+            assert!(!context.is_null());
+
+            let status = progpow_hash(
+                context,
+                header.as_ptr(),
+                100,
+                0x123456789ABCDEF0,
+                mix_hash.as_mut_ptr(),
+                final_hash.as_mut_ptr(),
+            );
+            assert_eq!(status, ProgpowStatus::Ok);
+            assert_eq!(mix_hash[..4], [0x64, 0x12, 0x7f, 0xab]);
+            assert_eq!(final_hash[..4], [0x4d, 0x02, 0x7c, 0x72]);
+            assert!(calls > 0);
+
+            let verify = |mix: &[u8; 32], boundary: &[u8; 32], out: *mut u8| {
+                progpow_verify_seal(
+                    context,
+                    header.as_ptr(),
+                    100,
+                    0x123456789ABCDEF0,
+                    mix.as_ptr(),
+                    boundary.as_ptr(),
+                    out,
+                )
+            };
+            let mut out = [0u8; 32];
+            assert_eq!(
+                verify(&mix_hash, &[0xff; 32], out.as_mut_ptr()),
+                ProgpowStatus::Ok
+            );
+            assert_eq!(out, final_hash);
+            assert_eq!(
+                verify(&mix_hash, &[0; 32], std::ptr::null_mut()),
+                ProgpowStatus::BoundaryNotMet
+            );
+            let mut wrong = mix_hash;
+            wrong[0] ^= 1;
+            assert_eq!(
+                verify(&wrong, &[0xff; 32], out.as_mut_ptr()),
+                ProgpowStatus::MixMismatch
+            );
+            assert_eq!(
+                progpow_verify_seal(
+                    context,
+                    std::ptr::null(),
+                    100,
+                    0,
+                    wrong.as_ptr(),
+                    wrong.as_ptr(),
+                    std::ptr::null_mut()
+                ),
+                ProgpowStatus::NullPointer
+            );
+
+            progpow_context_free(context);
+        }
+        assert_eq!(progpow_params().lanes, 16);
+    }
+
+    #[test]
+    fn test_ffi_cache_lifecycle() {
+        assert!(progpow_cache_new(MAX_EPOCH).is_null());
+
+        // A full-size cache is slow to build unoptimized, so use a small one.
+        let cache = Box::into_raw(Box::new(ProgpowCache::generate(3, 4096)));
+        let cache_words = unsafe { (*cache).words.clone() };
+        let path = std::env::temp_dir().join(format!("progpow-cache-{}.bin", std::process::id()));
+        let c_path = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+        let header = [7u8; 32];
+
+        unsafe {
+            assert_eq!(progpow_cache_epoch(cache), 3);
+            assert_eq!(
+                progpow_cache_save(cache, c_path.as_ptr()),
+                ProgpowStatus::Ok
+            );
+            assert_eq!(fs::read(&path).unwrap().len(), 4096);
+            // The file does not hold a real epoch 3 cache.
+            assert!(progpow_cache_load(3, c_path.as_ptr()).is_null());
+            assert!(progpow_cache_load(3, std::ptr::null()).is_null());
+
+            let context = progpow_context_from_cache(cache);
+            progpow_cache_free(cache);
+            let (mut mix_hash, mut final_hash) = ([0u8; 32], [0u8; 32]);
+            let status = progpow_hash(
+                context,
+                header.as_ptr(),
+                3 * 30000,
+                42,
+                mix_hash.as_mut_ptr(),
+                final_hash.as_mut_ptr(),
+            );
+            assert_eq!(status, ProgpowStatus::Ok);
+            assert_eq!(
+                progpow_verify_seal(
+                    context,
+                    header.as_ptr(),
+                    3 * 30000,
+                    42,
+                    mix_hash.as_ptr(),
+                    [0xff; 32].as_ptr(),
+                    std::ptr::null_mut(),
+                ),
+                ProgpowStatus::Ok
+            );
+            progpow_context_free(context);
+
+            let dataset = Box::into_raw(Box::new(ProgpowDataset {
+                epoch: 3,
+                words: Arc::new(generate_dataset(&cache_words, 256 * 64)),
+            }));
+            assert_eq!(
+                progpow_dataset_save(dataset, c_path.as_ptr()),
+                ProgpowStatus::Ok
+            );
+            let bytes = fs::read(&path).unwrap();
+            assert_eq!(bytes[..8], GETH_DUMP_MAGIC);
+            assert_eq!(bytes.len(), 8 + 256 * 64);
+            assert!(progpow_dataset_load(3, c_path.as_ptr()).is_null());
+            assert_eq!(progpow_dataset_epoch(dataset), 3);
+
+            let context = progpow_context_from_dataset(dataset);
+            progpow_dataset_free(dataset);
+            assert_eq!(
+                ((*context).lookup)(16 * 200 + 1),
+                calc_dataset_item(&cache_words, 200)
+            );
+            progpow_context_free(context);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
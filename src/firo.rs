@@ -0,0 +1,165 @@
+//! Firo headers and their FiroPoW seals.
+//!
+//! Firo's ProgPoW-era headers serialize field for field like Ravencoin's
+//! KawPoW headers: an 80-byte part holding the version, previous block
+//! hash, merkle root, time, compact target bits and height, followed by the
+//! 64-bit nonce and the 32-byte mix hash. FiroPoW seals the double SHA-256
+//! of the 80-byte part, so this module reuses [`crate::ravencoin`]'s
+//! decoding under Firo names and only changes the hash and its epochs.
+//! Firo also hands `progpow_hash_full` its header hash through
+//! `uint256::GetHex` and stores the mix hash through `uint256S`, so both
+//! are byte-reversed at the boundary as they are for Ravencoin.
+//!
+//! Firo's DAG grows differently from ethash's, so the caches must come from
+//! a [`CacheManager::with_generator`] producing Firo-sized ones;
+//! [`CacheManager::new`] generates ethash sizes.
+
+use crate::difficulty::DifficultyRule;
+use crate::ethash::manager::CacheManager;
+use crate::progpow::firopow::FIROPOW_EPOCH_LENGTH;
+use crate::ravencoin::{check_parents, supported_epoch};
+
+pub use crate::ravencoin::{
+    bits_from_target, header_hash, target_from_bits, RavencoinError as FiroError,
+    RavencoinHeader as FiroHeader, HEADER_HASH_INPUT_LENGTH, HEADER_LENGTH,
+};
+
+/// Returns the pre-hash FiroPoW seals for a serialized header: the
+/// [`header_hash`] of its first 80 bytes, in displayed byte order.
+///
+/// Both the 80-byte part and the full [`HEADER_LENGTH`]-byte header are
+/// accepted.
+pub fn pre_hash(bytes: &[u8]) -> Result<[u8; 32], FiroError> {
+    match bytes.len() {
+        HEADER_HASH_INPUT_LENGTH | HEADER_LENGTH => Ok(header_hash(
+            bytes[..HEADER_HASH_INPUT_LENGTH].try_into().unwrap(),
+        )),
+        found => Err(FiroError::Length { found }),
+    }
+}
+
+/// Verifies the FiroPoW seal of a serialized Firo header.
+///
+/// This decodes the header, expands its target bits, and checks the seal
+/// against its [`pre_hash`] with the cache of its FiroPoW epoch of
+/// [`FIROPOW_EPOCH_LENGTH`] blocks in `caches`.
+pub fn verify_header(bytes: &[u8], caches: &CacheManager) -> Result<(), FiroError> {
+    let header = FiroHeader::decode(bytes)?;
+    let epoch = supported_epoch(header.height, FIROPOW_EPOCH_LENGTH)?;
+    caches.get(epoch).verify_firopow_seal(&header.seal()?)?;
+    Ok(())
+}
+
+/// Verifies the FiroPoW seal of a serialized Firo header as
+/// [`verify_header`] does, after checking its target bits against its
+/// serialized parents, oldest first, under `rule`, such as
+/// [`KimotoGravityWell::firo`](crate::difficulty::KimotoGravityWell::firo).
+pub fn verify_header_with_parents(
+    bytes: &[u8],
+    parents: &[&[u8]],
+    caches: &CacheManager,
+    rule: &dyn DifficultyRule,
+) -> Result<(), FiroError> {
+    check_parents(bytes, parents, rule)?;
+    verify_header(bytes, caches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::difficulty::{DifficultyError, KimotoGravityWell};
+    use crate::ethash::cache::{make_cache, seed_hash};
+    use crate::ethash::manager::EpochCache;
+    use crate::hex;
+    use crate::progpow::verify::SealError;
+    use crate::testutil::tiny_cache_manager;
+
+    #[test]
+    fn test_verify_header_checks_the_firopow_seal() {
+        let caches = tiny_cache_manager(2);
+        let mut header = FiroHeader {
+            version: 0x2000_1000,
+            prev_block: [3; 32],
+            merkle_root: [5; 32],
+            time: 1_635_000_000,
+            bits: 0x2100ffff,
+            height: 2600,
+            nonce: 9,
+            mix_hash: [0; 32],
+        };
+        let bytes = header.encode();
+        assert_eq!(pre_hash(&bytes), Ok(header.header_hash()));
+        assert_eq!(pre_hash(&bytes[..80]), Ok(header.header_hash()));
+        assert_eq!(pre_hash(&bytes[..81]), Err(FiroError::Length { found: 81 }));
+
+        let (mix_hash, _) = caches.get(2).hash_firopow(&header.header_hash(), 2600, 9);
+        header.mix_hash = mix_hash.try_into().unwrap();
+        assert_eq!(verify_header(&header.encode(), &caches), Ok(()));
+        // Below the window's minimum, a Kimoto Gravity Well calls for the
+        // target limit.
+        let rule = KimotoGravityWell {
+            pow_limit: target_from_bits(0x2100ffff).unwrap(),
+            ..KimotoGravityWell::firo()
+        };
+        let parent = FiroHeader {
+            height: 2599,
+            ..header.clone()
+        };
+        assert_eq!(
+            verify_header_with_parents(&header.encode(), &[&parent.encode()], &caches, &rule),
+            Err(FiroError::InvalidDifficulty(
+                DifficultyError::MissingParents {
+                    needed: 2016,
+                    found: 1
+                }
+            ))
+        );
+        let rule = KimotoGravityWell {
+            past_blocks_min: 2600,
+            ..rule
+        };
+        assert_eq!(
+            verify_header_with_parents(&header.encode(), &[&parent.encode()], &caches, &rule),
+            Ok(())
+        );
+        // A KawPoW seal of the same header does not pass.
+        let (mix_hash, _) = caches.get(2).hash_kawpow(&header.header_hash(), 2600, 9);
+        header.mix_hash = mix_hash.try_into().unwrap();
+        assert!(matches!(
+            verify_header(&header.encode(), &caches),
+            Err(FiroError::InvalidSeal(SealError::MixMismatch { .. }))
+        ));
+    }
+
+    /// Verifies mainnet headers with real epoch caches. Each line of the
+    /// file `PROGPOW_FIRO_HEADERS` names holds the light cache size and the
+    /// dataset size of the header's epoch in bytes, then the serialized
+    /// 120-byte header in hex, separated by spaces.
+    #[test]
+    #[ignore = "needs PROGPOW_FIRO_HEADERS and full-size epoch caches"]
+    fn test_mainnet_headers() {
+        let path = std::env::var("PROGPOW_FIRO_HEADERS")
+            .expect("PROGPOW_FIRO_HEADERS must name a file of mainnet headers");
+        let fixture = std::fs::read_to_string(path).unwrap();
+        let mut checked = 0;
+        for line in fixture.lines().filter(|line| !line.trim().is_empty()) {
+            let [cache_size, dataset_size, hex] = line.split_whitespace().collect::<Vec<_>>()[..]
+            else {
+                panic!("malformed fixture line {line:?}");
+            };
+            let bytes = hex::decode(hex).unwrap();
+            let (cache_size, dataset_size): (u64, u64) =
+                (cache_size.parse().unwrap(), dataset_size.parse().unwrap());
+            let caches = CacheManager::with_generator(1, move |epoch| {
+                EpochCache::new(
+                    epoch,
+                    make_cache(cache_size, &seed_hash(epoch)),
+                    dataset_size,
+                )
+            });
+            assert_eq!(verify_header(&bytes, &caches), Ok(()), "{line}");
+            checked += 1;
+        }
+        assert!(checked > 0, "the fixture holds no headers");
+    }
+}
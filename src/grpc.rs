@@ -0,0 +1,213 @@
+//! The gRPC verification service served by `progpow-verifyd`.
+//!
+//! The service is defined in `proto/progpow.proto`. Every request is
+//! answered from a shared [`CacheManager`], so the light cache of each epoch
+//! is generated once per process; hashing and cache generation run on
+//! Tokio's blocking pool so they do not stall other requests.
+
+use std::sync::Arc;
+
+use tonic::{Request, Response, Status};
+
+use crate::basic_algorithm::PROGPOW_PERIOD_LENGTH;
+use crate::ethash::cache::{cache_size, dataset_size, epoch, MAX_EPOCH};
+use crate::ethash::manager::{CacheManager, EpochCache};
+use crate::progpow::verify::{Seal, SealError};
+
+/// Code generated from `proto/progpow.proto`.
+pub mod proto {
+    tonic::include_proto!("progpow.v1");
+}
+
+use proto::verifier_server::{Verifier, VerifierServer};
+use proto::{
+    ComputeHashRequest, ComputeHashResponse, GetEpochInfoRequest, GetEpochInfoResponse, SealStatus,
+    VerifySealRequest, VerifySealResponse,
+};
+
+/// Answers `Verifier` requests from a shared cache manager.
+pub struct VerifierService {
+    caches: Arc<CacheManager>,
+}
+
+impl VerifierService {
+    /// Creates a service answering from `caches`.
+    pub fn new(caches: Arc<CacheManager>) -> Self {
+        VerifierService { caches }
+    }
+
+    /// Wraps the service for `tonic::transport::Server::add_service`.
+    pub fn into_server(self) -> VerifierServer<Self> {
+        VerifierServer::new(self)
+    }
+
+    /// Runs `f` on the blocking pool with the cache for `block_number`.
+    async fn with_cache<T: Send + 'static>(
+        &self,
+        block_number: u64,
+        f: impl FnOnce(&EpochCache) -> T + Send + 'static,
+    ) -> Result<T, Status> {
+        check_epoch(block_number)?;
+        let caches = self.caches.clone();
+        tokio::task::spawn_blocking(move || f(&caches.for_block(block_number)))
+            .await
+            .map_err(|error| Status::internal(error.to_string()))
+    }
+}
+
+/// Rejects blocks past the epochs the service generates caches for.
+fn check_epoch(block_number: u64) -> Result<u64, Status> {
+    let epoch = epoch(block_number);
+    if epoch >= MAX_EPOCH {
+        return Err(Status::out_of_range(format!(
+            "block {block_number} is in epoch {epoch}, past the last supported epoch {}",
+            MAX_EPOCH - 1
+        )));
+    }
+    Ok(epoch)
+}
+
+/// Reads a 32-byte field, rejecting any other length.
+fn hash_field(bytes: &[u8], name: &str) -> Result<[u8; 32], Status> {
+    bytes.try_into().map_err(|_| {
+        Status::invalid_argument(format!("{name} must be 32 bytes, got {}", bytes.len()))
+    })
+}
+
+#[tonic::async_trait]
+impl Verifier for VerifierService {
+    async fn verify_seal(
+        &self,
+        request: Request<VerifySealRequest>,
+    ) -> Result<Response<VerifySealResponse>, Status> {
+        let request = request.into_inner();
+        let seal = Seal {
+            header_hash: hash_field(&request.header_hash, "header_hash")?,
+            block_number: request.block_number,
+            nonce: request.nonce,
+            mix_hash: hash_field(&request.mix_hash, "mix_hash")?,
+            boundary: hash_field(&request.boundary, "boundary")?,
+        };
+        let result = {
+            let seal = seal.clone();
+            self.with_cache(seal.block_number, move |cache| cache.verify_seal(&seal))
+                .await?
+        };
+        let response = match result {
+            Ok(final_hash) => VerifySealResponse {
+                status: SealStatus::Valid.into(),
+                mix_hash: seal.mix_hash.to_vec(),
+                final_hash,
+            },
+            Err(SealError::MixMismatch { computed }) => VerifySealResponse {
+                status: SealStatus::MixMismatch.into(),
+                mix_hash: computed,
+                final_hash: Vec::new(),
+            },
+            Err(SealError::BoundaryNotMet { final_hash }) => VerifySealResponse {
+                status: SealStatus::BoundaryNotMet.into(),
+                mix_hash: seal.mix_hash.to_vec(),
+                final_hash,
+            },
+        };
+        Ok(Response::new(response))
+    }
+
+    async fn compute_hash(
+        &self,
+        request: Request<ComputeHashRequest>,
+    ) -> Result<Response<ComputeHashResponse>, Status> {
+        let request = request.into_inner();
+        let header_hash = hash_field(&request.header_hash, "header_hash")?;
+        let (block_number, nonce) = (request.block_number, request.nonce);
+        let (mix_hash, final_hash) = self
+            .with_cache(block_number, move |cache| {
+                cache.hash(&header_hash, block_number, nonce)
+            })
+            .await?;
+        Ok(Response::new(ComputeHashResponse {
+            mix_hash,
+            final_hash,
+        }))
+    }
+
+    async fn get_epoch_info(
+        &self,
+        request: Request<GetEpochInfoRequest>,
+    ) -> Result<Response<GetEpochInfoResponse>, Status> {
+        let block_number = request.into_inner().block_number;
+        let epoch = check_epoch(block_number)?;
+        Ok(Response::new(GetEpochInfoResponse {
+            epoch,
+            period: block_number / PROGPOW_PERIOD_LENGTH,
+            cache_size: cache_size(epoch),
+            dataset_size: dataset_size(epoch),
+            seed_hash: self
+                .caches
+                .seed_hash(epoch)
+                .expect("supported epochs have seed hashes")
+                .to_vec(),
+            cached: self.caches.is_cached(epoch),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ethash::cache::seed_hash;
+    use crate::testutil::tiny_cache_manager;
+
+    #[tokio::test]
+    async fn test_verifier_service_answers_requests() {
+        let caches = tiny_cache_manager(2);
+        let service = VerifierService::new(Arc::new(caches));
+
+        let hash = service
+            .compute_hash(Request::new(ComputeHashRequest {
+                header_hash: vec![3; 32],
+                block_number: 100,
+                nonce: 11,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        let verify = |mix_hash: &[u8], boundary: u8| {
+            service.verify_seal(Request::new(VerifySealRequest {
+                header_hash: vec![3; 32],
+                block_number: 100,
+                nonce: 11,
+                mix_hash: mix_hash.to_vec(),
+                boundary: vec![boundary; 32],
+            }))
+        };
+
+        let valid = verify(&hash.mix_hash, 0xff).await.unwrap().into_inner();
+        assert_eq!(valid.status(), SealStatus::Valid);
+        assert_eq!(valid.final_hash, hash.final_hash);
+        let forged = verify(&[0; 32], 0xff).await.unwrap().into_inner();
+        assert_eq!(forged.status(), SealStatus::MixMismatch);
+        assert_eq!(forged.mix_hash, hash.mix_hash);
+        let too_hard = verify(&hash.mix_hash, 0).await.unwrap().into_inner();
+        assert_eq!(too_hard.status(), SealStatus::BoundaryNotMet);
+        let malformed = verify(&hash.mix_hash[..4], 0xff).await.unwrap_err();
+        assert_eq!(malformed.code(), tonic::Code::InvalidArgument);
+
+        let info = service
+            .get_epoch_info(Request::new(GetEpochInfoRequest {
+                block_number: 30001,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!((info.epoch, info.cached), (1, false));
+        assert_eq!(info.seed_hash, seed_hash(1));
+        let too_far = service
+            .get_epoch_info(Request::new(GetEpochInfoRequest {
+                block_number: u64::MAX,
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(too_far.code(), tonic::Code::OutOfRange);
+    }
+}
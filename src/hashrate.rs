@@ -0,0 +1,212 @@
+//! Conversions between difficulties, shares and hashrates.
+//!
+//! A difficulty of `d` means a hash meets the boundary with probability
+//! `1 / d`, so a block takes `d` hashes on average, and a pool share of
+//! difficulty `s` is worth `s` of them. The results are `f64` estimates for
+//! dashboards, not consensus values; see [`target`](crate::target) for the
+//! exact conversions. [`HashrateMeter`] measures a miner's own rate.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::header::Header;
+use crate::target::{boundary_from_difficulty, difficulty_from_boundary, U256};
+
+/// A header's timestamp and difficulty, the inputs to [`network_hashrate`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HeaderSample {
+    /// The block timestamp, in seconds.
+    pub timestamp: u64,
+    /// The block difficulty.
+    pub difficulty: U256,
+}
+
+impl From<&Header> for HeaderSample {
+    fn from(header: &Header) -> Self {
+        HeaderSample {
+            timestamp: header.timestamp,
+            difficulty: header.difficulty,
+        }
+    }
+}
+
+/// Returns the expected number of hashes to find a block at `difficulty`.
+pub fn expected_hashes(difficulty: U256) -> f64 {
+    difficulty.to_f64().max(1.0)
+}
+
+/// Returns the boundary of a pool share of `share_difficulty`, the target
+/// a miner submits shares against.
+pub fn share_boundary(share_difficulty: u64) -> [u8; 32] {
+    boundary_from_difficulty(U256::from(share_difficulty))
+}
+
+/// Returns the difficulty of a share found against `boundary`.
+pub fn share_difficulty(boundary: &[u8; 32]) -> U256 {
+    difficulty_from_boundary(boundary)
+}
+
+/// Returns the expected number of shares of `share_difficulty` per block
+/// found at `difficulty`.
+pub fn shares_per_block(difficulty: U256, share_difficulty: u64) -> f64 {
+    expected_hashes(difficulty) / share_difficulty.max(1) as f64
+}
+
+/// Returns the hashrate, in hashes per second, that finds blocks at
+/// `difficulty` every `block_time` seconds on average.
+pub fn hashrate(difficulty: U256, block_time: f64) -> f64 {
+    expected_hashes(difficulty) / block_time
+}
+
+/// Returns the expected seconds between blocks at `difficulty` for a
+/// `hashrate` in hashes per second.
+pub fn expected_block_time(difficulty: U256, hashrate: f64) -> f64 {
+    expected_hashes(difficulty) / hashrate
+}
+
+/// Estimates the network hashrate over a window of consecutive headers,
+/// oldest first.
+///
+/// Every header after the first took its difficulty's worth of hashes, done
+/// in the time between the first and last timestamps.
+///
+/// # Returns
+///
+/// The hashrate in hashes per second, or `None` if the window has fewer than
+/// two headers or spans no time.
+pub fn network_hashrate(window: &[HeaderSample]) -> Option<f64> {
+    let (first, rest) = window.split_first()?;
+    let last = rest.last()?;
+    let elapsed = last.timestamp.checked_sub(first.timestamp)?;
+    if elapsed == 0 {
+        return None;
+    }
+    let hashes: f64 = rest
+        .iter()
+        .map(|sample| expected_hashes(sample.difficulty))
+        .sum();
+    Some(hashes / elapsed as f64)
+}
+
+/// Measures the hashrate of a running miner over a sliding window.
+///
+/// Worker threads [`record`](Self::record) the hashes they finish; the rate
+/// is the hashes recorded in the last `window`, or since the meter started
+/// if that is shorter, per second.
+pub struct HashrateMeter {
+    window: Duration,
+    started: Instant,
+    state: Mutex<MeterState>,
+}
+
+/// The recorded batches still inside the window, and the running total.
+struct MeterState {
+    batches: VecDeque<(Instant, u64)>,
+    total: u64,
+}
+
+impl HashrateMeter {
+    /// Starts a meter averaging over `window`.
+    pub fn new(window: Duration) -> Self {
+        Self::starting_at(window, Instant::now())
+    }
+
+    /// Starts a meter averaging over `window`, as if started at `started`.
+    pub fn starting_at(window: Duration, started: Instant) -> Self {
+        HashrateMeter {
+            window,
+            started,
+            state: Mutex::new(MeterState {
+                batches: VecDeque::new(),
+                total: 0,
+            }),
+        }
+    }
+
+    /// Records `hashes` finished now.
+    pub fn record(&self, hashes: u64) {
+        self.record_at(hashes, Instant::now());
+    }
+
+    /// Records `hashes` finished at `at`.
+    pub fn record_at(&self, hashes: u64, at: Instant) {
+        let mut state = self.state.lock().unwrap();
+        state.batches.push_back((at, hashes));
+        state.total += hashes;
+    }
+
+    /// Returns the current rate in hashes per second.
+    pub fn rate(&self) -> f64 {
+        self.rate_at(Instant::now())
+    }
+
+    /// Returns the rate at `now` in hashes per second, 0 before any time
+    /// has passed.
+    pub fn rate_at(&self, now: Instant) -> f64 {
+        let mut state = self.state.lock().unwrap();
+        let from = now
+            .checked_sub(self.window)
+            .unwrap_or(self.started)
+            .max(self.started);
+        while state.batches.front().is_some_and(|&(at, _)| at <= from) {
+            state.batches.pop_front();
+        }
+        let span = now.saturating_duration_since(from).as_secs_f64();
+        if span == 0.0 {
+            return 0.0;
+        }
+        let hashes: u64 = state.batches.iter().map(|&(_, hashes)| hashes).sum();
+        hashes as f64 / span
+    }
+
+    /// Returns the hashes recorded since the meter started.
+    pub fn total(&self) -> u64 {
+        self.state.lock().unwrap().total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hashrate_conversions() {
+        let difficulty = U256::from(13_000_000_000_000);
+        assert_eq!(expected_hashes(difficulty), 13e12);
+        assert_eq!(hashrate(difficulty, 13.0), 1e12);
+        assert_eq!(expected_block_time(difficulty, 1e12), 13.0);
+        assert_eq!(shares_per_block(difficulty, 4_000_000_000), 3250.0);
+
+        let boundary = share_boundary(4_000_000_000);
+        assert_eq!(share_difficulty(&boundary), U256::from(4_000_000_000));
+
+        let sample = |timestamp, difficulty: u64| HeaderSample {
+            timestamp,
+            difficulty: U256::from(difficulty),
+        };
+        let window = [sample(100, 50), sample(110, 100), sample(130, 200)];
+        assert_eq!(network_hashrate(&window), Some(10.0));
+        assert_eq!(network_hashrate(&window[..1]), None);
+        assert_eq!(network_hashrate(&[sample(5, 1), sample(5, 1)]), None);
+    }
+
+    #[test]
+    fn test_hashrate_meter_averages_over_its_window() {
+        let started = Instant::now();
+        let at = |seconds| started + Duration::from_secs(seconds);
+        let meter = HashrateMeter::starting_at(Duration::from_secs(10), started);
+        assert_eq!(meter.rate_at(started), 0.0);
+
+        // Before a full window, the rate is over the time since the start.
+        meter.record_at(100, at(1));
+        meter.record_at(100, at(2));
+        assert_eq!(meter.rate_at(at(4)), 50.0);
+        // Later, batches older than the window drop out.
+        meter.record_at(300, at(11));
+        assert_eq!(meter.rate_at(at(11)), 40.0);
+        assert_eq!(meter.rate_at(at(12)), 30.0);
+        assert_eq!(meter.rate_at(at(30)), 0.0);
+        assert_eq!(meter.total(), 500);
+    }
+}
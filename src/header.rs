@@ -0,0 +1,504 @@
+//! Ethereum-style block headers from their RLP encoding.
+//!
+//! ProgPoW hashes a header's pre-hash: the Keccak-256 of its RLP encoding
+//! with the mix hash and nonce left out. [`Header::decode`] reads a header
+//! from its RLP bytes, keeping each field's original encoding, so the
+//! pre-hash is computed over exactly the bytes the header was sealed with,
+//! including any fork-specific fields after the nonce.
+
+use std::fmt;
+
+use crate::engine::SealableHeader;
+use crate::keccak::keccak256;
+use crate::target::{boundary_from_difficulty, U256};
+
+/// Index of the ommers hash among the header fields.
+const OMMERS_HASH: usize = 1;
+
+/// The ommers hash of a block without ommers, the Keccak-256 of the RLP
+/// encoding of an empty list.
+const EMPTY_OMMERS_HASH: [u8; 32] = [
+    0x1d, 0xcc, 0x4d, 0xe8, 0xde, 0xc7, 0x5d, 0x7a, 0xab, 0x85, 0xb5, 0x67, 0xb6, 0xcc, 0xd4, 0x1a,
+    0xd3, 0x12, 0x45, 0x1b, 0x94, 0x8a, 0x74, 0x13, 0xf0, 0xa1, 0x42, 0xfd, 0x40, 0xd4, 0x93, 0x47,
+];
+
+/// Index of the difficulty among the header fields.
+const DIFFICULTY: usize = 7;
+
+/// Index of the block number among the header fields.
+const NUMBER: usize = 8;
+
+/// Index of the timestamp among the header fields.
+const TIMESTAMP: usize = 11;
+
+/// Index of the mix hash among the header fields.
+const MIX_HASH: usize = 13;
+
+/// Index of the nonce among the header fields.
+const NONCE: usize = 14;
+
+/// Fields every header has: the 13 before the seal, the mix hash and the
+/// nonce. Later forks append more.
+const BASE_FIELDS: usize = 15;
+
+/// The reason a header could not be decoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HeaderError {
+    /// The bytes are not a single, canonically encoded RLP list.
+    Rlp(&'static str),
+    /// The list has fewer than the 15 base fields.
+    MissingFields {
+        /// The number of fields found.
+        found: usize,
+    },
+    /// A field is a list, or has the wrong length for its type.
+    InvalidField {
+        /// The index of the field in the header.
+        index: usize,
+    },
+}
+
+impl fmt::Display for HeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeaderError::Rlp(reason) => write!(f, "invalid RLP: {reason}"),
+            HeaderError::MissingFields { found } => write!(
+                f,
+                "a header has at least {BASE_FIELDS} fields, found {found}"
+            ),
+            HeaderError::InvalidField { index } => write!(f, "invalid header field {index}"),
+        }
+    }
+}
+
+impl std::error::Error for HeaderError {}
+
+/// A decoded header: its number, difficulty and seal, and the encoded
+/// fields the pre-hash is computed from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Header {
+    /// The RLP encoding of each field, in order.
+    fields: Vec<Vec<u8>>,
+    /// The block number.
+    pub number: u64,
+    /// The difficulty.
+    pub difficulty: U256,
+    /// The block timestamp, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// The mix hash in the seal.
+    pub mix_hash: [u8; 32],
+    /// The nonce in the seal.
+    pub nonce: u64,
+}
+
+impl Header {
+    /// Decodes a header from its RLP encoding.
+    pub fn decode(rlp: &[u8]) -> Result<Header, HeaderError> {
+        let fields = decode_fields(rlp)?;
+        if fields.len() < BASE_FIELDS {
+            return Err(HeaderError::MissingFields {
+                found: fields.len(),
+            });
+        }
+
+        let string = |index: usize| rlp::string(&fields[index]);
+        let fixed = |index: usize, len: usize| {
+            let bytes = string(index);
+            (bytes.len() == len).then_some(bytes)
+        };
+        let invalid = |index| HeaderError::InvalidField { index };
+        let u64_field = |index| {
+            rlp::uint(string(index))
+                .filter(|bytes| bytes.len() <= 8)
+                .map(|bytes| bytes.iter().fold(0, |n, &b| n << 8 | b as u64))
+                .ok_or(invalid(index))
+        };
+        let number = u64_field(NUMBER)?;
+        let timestamp = u64_field(TIMESTAMP)?;
+        let difficulty = rlp::uint(string(DIFFICULTY))
+            .filter(|bytes| bytes.len() <= 32)
+            .ok_or(invalid(DIFFICULTY))?;
+        let mix_hash = fixed(MIX_HASH, 32).ok_or(invalid(MIX_HASH))?;
+        let nonce = fixed(NONCE, 8).ok_or(invalid(NONCE))?;
+
+        let mut padded = [0; 32];
+        padded[32 - difficulty.len()..].copy_from_slice(difficulty);
+        Ok(Header {
+            number,
+            difficulty: U256::from_be_bytes(padded),
+            timestamp,
+            mix_hash: mix_hash.try_into().unwrap(),
+            nonce: u64::from_be_bytes(nonce.try_into().unwrap()),
+            fields,
+        })
+    }
+
+    /// Returns the header's RLP encoding, with its seal unless `seal` is
+    /// `false`.
+    pub fn encode(&self, seal: bool) -> Vec<u8> {
+        encode_fields(
+            self.fields
+                .iter()
+                .enumerate()
+                .filter(|&(index, _)| seal || (index != MIX_HASH && index != NONCE))
+                .map(|(_, field)| field),
+        )
+    }
+
+    /// Returns the pre-hash ProgPoW seals: the Keccak-256 of the header's
+    /// encoding without its mix hash and nonce.
+    pub fn pre_hash(&self) -> [u8; 32] {
+        keccak256(&self.encode(false))
+    }
+
+    /// Returns the block hash, the Keccak-256 of the full encoding.
+    pub fn hash(&self) -> [u8; 32] {
+        keccak256(&self.encode(true))
+    }
+
+    /// Returns `true` if the block has ommers, as its ommers hash tells;
+    /// Byzantium's difficulty adjustment depends on it.
+    pub fn has_ommers(&self) -> bool {
+        rlp::string(&self.fields[OMMERS_HASH]) != EMPTY_OMMERS_HASH
+    }
+
+    /// Returns the number of fields, 15 before the London fork.
+    pub fn field_count(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Replaces the seal, as a miner does once it has found a nonce.
+    pub fn set_seal(&mut self, nonce: u64, mix_hash: [u8; 32]) {
+        self.nonce = nonce;
+        self.mix_hash = mix_hash;
+        self.fields[MIX_HASH] = rlp::encode_string(&mix_hash);
+        self.fields[NONCE] = rlp::encode_string(&nonce.to_be_bytes());
+    }
+}
+
+impl SealableHeader for Header {
+    fn number(&self) -> u64 {
+        self.number
+    }
+
+    fn seal_hash(&self) -> [u8; 32] {
+        self.pre_hash()
+    }
+
+    fn boundary(&self) -> [u8; 32] {
+        boundary_from_difficulty(self.difficulty)
+    }
+
+    fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    fn mix_hash(&self) -> [u8; 32] {
+        self.mix_hash
+    }
+
+    fn set_seal(&mut self, nonce: u64, mix_hash: [u8; 32]) {
+        Header::set_seal(self, nonce, mix_hash);
+    }
+}
+
+/// Returns the pre-hash of an RLP-encoded header; see [`Header::pre_hash`].
+pub fn pre_hash(rlp: &[u8]) -> Result<[u8; 32], HeaderError> {
+    Ok(Header::decode(rlp)?.pre_hash())
+}
+
+/// Writes a seal into the RLP encoding of an unsealed header, the one
+/// [`Header::encode`] returns without its seal, giving the encoding of the
+/// sealed header a node accepts.
+///
+/// Fields after the seal are opaque, so an already sealed encoding is not
+/// rejected: its old seal ends up after the new one.
+///
+/// # Arguments
+///
+/// * `rlp_without_seal` - The header's RLP encoding without mix hash and nonce.
+/// * `nonce` - The nonce found by the miner.
+/// * `mix_hash` - The mix hash ProgPoW computed for that nonce.
+///
+/// # Returns
+///
+/// The sealed header's RLP encoding, or the [`HeaderError`] its fields fail
+/// to decode with.
+pub fn seal_header(
+    rlp_without_seal: &[u8],
+    nonce: u64,
+    mix_hash: [u8; 32],
+) -> Result<Vec<u8>, HeaderError> {
+    let mut fields = decode_fields(rlp_without_seal)?;
+    if fields.len() < MIX_HASH {
+        return Err(HeaderError::MissingFields {
+            found: fields.len() + 2,
+        });
+    }
+    fields.splice(
+        MIX_HASH..MIX_HASH,
+        [
+            rlp::encode_string(&mix_hash),
+            rlp::encode_string(&nonce.to_be_bytes()),
+        ],
+    );
+    let sealed = encode_fields(&fields);
+    Header::decode(&sealed)?;
+    Ok(sealed)
+}
+
+/// Splits a sealed header's RLP encoding into the encoding without its seal,
+/// its nonce and its mix hash; the inverse of [`seal_header`].
+pub fn split_seal(rlp: &[u8]) -> Result<(Vec<u8>, u64, [u8; 32]), HeaderError> {
+    let header = Header::decode(rlp)?;
+    Ok((header.encode(false), header.nonce, header.mix_hash))
+}
+
+/// Splits an RLP list of strings into the encodings of its items.
+fn decode_fields(rlp: &[u8]) -> Result<Vec<Vec<u8>>, HeaderError> {
+    let (item, rest) = rlp::item(rlp)?;
+    if !rest.is_empty() {
+        return Err(HeaderError::Rlp("trailing bytes after the header"));
+    }
+    let rlp::Item::List(mut payload) = item else {
+        return Err(HeaderError::Rlp("a header is a list"));
+    };
+    let mut fields = Vec::new();
+    while !payload.is_empty() {
+        let (field, rest) = rlp::item(payload)?;
+        if matches!(field, rlp::Item::List(_)) {
+            return Err(HeaderError::InvalidField {
+                index: fields.len(),
+            });
+        }
+        fields.push(payload[..payload.len() - rest.len()].to_vec());
+        payload = rest;
+    }
+    Ok(fields)
+}
+
+/// Encodes a list from the encodings of its items.
+fn encode_fields<'a>(fields: impl IntoIterator<Item = &'a Vec<u8>>) -> Vec<u8> {
+    let payload: Vec<u8> = fields.into_iter().flatten().copied().collect();
+    let mut out = rlp::list_header(payload.len());
+    out.extend(payload);
+    out
+}
+
+/// The subset of RLP headers need.
+pub(crate) mod rlp {
+    use super::HeaderError;
+
+    /// A decoded item's payload.
+    pub(crate) enum Item<'a> {
+        String(&'a [u8]),
+        List(&'a [u8]),
+    }
+
+    /// Splits the first item off `input`, requiring canonical length
+    /// prefixes.
+    pub(crate) fn item(input: &[u8]) -> Result<(Item<'_>, &[u8]), HeaderError> {
+        let err = HeaderError::Rlp;
+        let (&prefix, rest) = input.split_first().ok_or(err("unexpected end of input"))?;
+        let (is_list, offset, len) = match prefix {
+            0x00..=0x7f => return Ok((Item::String(&input[..1]), rest)),
+            0x80..=0xb7 => (false, 1, (prefix - 0x80) as usize),
+            0xc0..=0xf7 => (true, 1, (prefix - 0xc0) as usize),
+            _ => {
+                let is_list = prefix >= 0xf8;
+                let len_len = (prefix - if is_list { 0xf7 } else { 0xb7 }) as usize;
+                let len_bytes = rest.get(..len_len).ok_or(err("unexpected end of input"))?;
+                if len_bytes[0] == 0 || len_len > 8 {
+                    return Err(err("non-canonical length"));
+                }
+                let len = len_bytes.iter().fold(0usize, |n, &b| n << 8 | b as usize);
+                if len < 56 {
+                    return Err(err("non-canonical length"));
+                }
+                (is_list, 1 + len_len, len)
+            }
+        };
+        let end = offset
+            .checked_add(len)
+            .filter(|&end| end <= input.len())
+            .ok_or(err("unexpected end of input"))?;
+        let payload = &input[offset..end];
+        if !is_list && len == 1 && payload[0] < 0x80 {
+            return Err(err("non-canonical single byte"));
+        }
+        let item = if is_list {
+            Item::List(payload)
+        } else {
+            Item::String(payload)
+        };
+        Ok((item, &input[end..]))
+    }
+
+    /// Returns the payload of an encoded string, which [`item`] has already
+    /// validated.
+    pub(crate) fn string(encoded: &[u8]) -> &[u8] {
+        match item(encoded) {
+            Ok((Item::String(payload), _)) => payload,
+            _ => unreachable!("header fields are validated strings"),
+        }
+    }
+
+    /// Returns the bytes of a canonical big-endian integer, which has no
+    /// leading zero byte.
+    pub(crate) fn uint(bytes: &[u8]) -> Option<&[u8]> {
+        (bytes.first() != Some(&0)).then_some(bytes)
+    }
+
+    /// Returns the prefix of a list with a payload of `len` bytes.
+    pub(crate) fn list_header(len: usize) -> Vec<u8> {
+        length_prefix(0xc0, len)
+    }
+
+    /// Encodes a string.
+    pub(crate) fn encode_string(bytes: &[u8]) -> Vec<u8> {
+        if bytes.len() == 1 && bytes[0] < 0x80 {
+            return bytes.to_vec();
+        }
+        let mut out = length_prefix(0x80, bytes.len());
+        out.extend_from_slice(bytes);
+        out
+    }
+
+    fn length_prefix(offset: u8, len: usize) -> Vec<u8> {
+        if len < 56 {
+            return vec![offset + len as u8];
+        }
+        let be = len.to_be_bytes();
+        let skip = be.iter().take_while(|&&b| b == 0).count();
+        let mut out = vec![offset + 55 + (be.len() - skip) as u8];
+        out.extend_from_slice(&be[skip..]);
+        out
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Encodes a header with `number`, `difficulty` and a seal, plus a base
+    /// fee field after the nonce, as a post-London header has.
+    pub(crate) fn encode_header(
+        number: u64,
+        difficulty: u64,
+        nonce: u64,
+        mix: [u8; 32],
+    ) -> Vec<u8> {
+        let uint = |n: u64| {
+            let be = n.to_be_bytes();
+            let skip = be.iter().take_while(|&&b| b == 0).count();
+            rlp::encode_string(&be[skip..])
+        };
+        let mut fields = vec![
+            rlp::encode_string(&[1; 32]),
+            rlp::encode_string(&[2; 32]),
+            rlp::encode_string(&[3; 20]),
+            rlp::encode_string(&[4; 32]),
+            rlp::encode_string(&[5; 32]),
+            rlp::encode_string(&[6; 32]),
+            rlp::encode_string(&[0; 256]),
+            uint(difficulty),
+            uint(number),
+            uint(8_000_000),
+            uint(21_000),
+            uint(1_700_000_000),
+            rlp::encode_string(b"progpow"),
+            rlp::encode_string(&mix),
+            rlp::encode_string(&nonce.to_be_bytes()),
+            uint(7),
+        ];
+        let payload: Vec<u8> = fields.iter_mut().flat_map(|f| f.drain(..)).collect();
+        let mut out = rlp::list_header(payload.len());
+        out.extend(payload);
+        out
+    }
+
+    #[test]
+    fn test_header_decodes_and_strips_the_seal() {
+        let rlp = encode_header(100, 0x20000, 0x0102030405060708, [9; 32]);
+        let header = Header::decode(&rlp).unwrap();
+        assert_eq!(header.number, 100);
+        assert_eq!(header.difficulty, U256::from(0x20000));
+        assert_eq!(header.timestamp, 1_700_000_000);
+        assert_eq!(header.nonce, 0x0102030405060708);
+        assert_eq!(header.mix_hash, [9; 32]);
+        assert_eq!(header.field_count(), 16);
+        assert_eq!(header.encode(true), rlp);
+        assert_eq!(header.hash(), keccak256(&rlp));
+        assert!(header.has_ommers());
+        assert_eq!(EMPTY_OMMERS_HASH, keccak256(&[0xc0]));
+        let at = rlp.windows(32).position(|w| w == [2; 32]).unwrap();
+        let mut no_ommers = rlp.clone();
+        no_ommers[at..at + 32].copy_from_slice(&EMPTY_OMMERS_HASH);
+        assert!(!Header::decode(&no_ommers).unwrap().has_ommers());
+
+        // The pre-hash ignores the seal but covers the base fee.
+        let pre_hash = pre_hash(&rlp).unwrap();
+        let resealed = encode_header(100, 0x20000, 1, [0; 32]);
+        assert_eq!(Header::decode(&resealed).unwrap().pre_hash(), pre_hash);
+        let mut sealed = Header::decode(&resealed).unwrap();
+        sealed.set_seal(0x0102030405060708, [9; 32]);
+        assert_eq!(sealed, header);
+        let other = encode_header(101, 0x20000, 0x0102030405060708, [9; 32]);
+        assert_ne!(Header::decode(&other).unwrap().pre_hash(), pre_hash);
+    }
+
+    #[test]
+    fn test_seal_header_inverts_split_seal() {
+        let rlp = encode_header(100, 0x20000, 0x0102030405060708, [9; 32]);
+        let (unsealed, nonce, mix_hash) = split_seal(&rlp).unwrap();
+        assert_eq!(unsealed, Header::decode(&rlp).unwrap().encode(false));
+        assert_eq!((nonce, mix_hash), (0x0102030405060708, [9; 32]));
+        assert_eq!(keccak256(&unsealed), pre_hash(&rlp).unwrap());
+        assert_eq!(seal_header(&unsealed, nonce, mix_hash), Ok(rlp.clone()));
+
+        let resealed = seal_header(&unsealed, 1, [0; 32]).unwrap();
+        assert_eq!(resealed, encode_header(100, 0x20000, 1, [0; 32]));
+
+        assert_eq!(
+            seal_header(&[0xc1, 0x80], 1, [0; 32]),
+            Err(HeaderError::MissingFields { found: 3 })
+        );
+    }
+
+    #[test]
+    fn test_header_rejects_malformed_rlp() {
+        let rlp = encode_header(100, 1, 0, [0; 32]);
+        assert_eq!(
+            Header::decode(&rlp[..rlp.len() - 1]),
+            Err(HeaderError::Rlp("unexpected end of input"))
+        );
+        let mut trailing = rlp.clone();
+        trailing.push(0);
+        assert!(matches!(
+            Header::decode(&trailing),
+            Err(HeaderError::Rlp(_))
+        ));
+        assert_eq!(
+            Header::decode(&[0xc1, 0x80]),
+            Err(HeaderError::MissingFields { found: 1 })
+        );
+        // A single byte below 0x80 must encode as itself.
+        assert!(matches!(
+            Header::decode(&[0xc2, 0x81, 0x05]),
+            Err(HeaderError::Rlp(_))
+        ));
+
+        // A 7-byte nonce.
+        let mut short_nonce = rlp.clone();
+        let at = short_nonce.len() - 10;
+        assert_eq!(short_nonce[at], 0x88);
+        short_nonce[at] = 0x87;
+        short_nonce.remove(at + 1);
+        let len = short_nonce.len() - 3;
+        short_nonce[1..3].copy_from_slice(&(len as u16).to_be_bytes());
+        assert_eq!(
+            Header::decode(&short_nonce),
+            Err(HeaderError::InvalidField { index: 14 })
+        );
+    }
+}
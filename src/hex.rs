@@ -0,0 +1,75 @@
+//! Hex encoding and decoding, for error messages, services, bindings and
+//! test fixtures.
+
+use std::fmt::Write;
+
+/// Encodes `bytes` as lowercase hex digits, without a prefix.
+pub(crate) fn encode(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+/// Encodes `bytes` as lowercase hex with a `0x` prefix.
+#[cfg(any(
+    test,
+    feature = "http",
+    feature = "net",
+    feature = "vectors",
+    feature = "wasm"
+))]
+pub(crate) fn encode_prefixed(bytes: &[u8]) -> String {
+    format!("0x{}", encode(bytes))
+}
+
+/// Decodes hex digits, without a prefix.
+///
+/// # Returns
+///
+/// The bytes, or `None` if `digits` is not a whole number of hex-encoded
+/// bytes.
+#[cfg(any(
+    test,
+    feature = "http",
+    feature = "net",
+    feature = "vectors",
+    feature = "wasm"
+))]
+pub(crate) fn decode(digits: &str) -> Option<Vec<u8>> {
+    if !digits.len().is_multiple_of(2) || !digits.is_ascii() {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Decodes 32 bytes of hex digits, without a prefix.
+#[cfg(any(test, feature = "http", feature = "wasm"))]
+pub(crate) fn decode_hash(digits: &str) -> Option<[u8; 32]> {
+    decode(digits)?.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_round_trip() {
+        assert_eq!(encode(&[0, 0xab, 0x10]), "00ab10");
+        assert_eq!(encode_prefixed(&[0xff]), "0xff");
+        assert_eq!(encode(&[]), "");
+
+        assert_eq!(decode("00ab10"), Some(vec![0, 0xab, 0x10]));
+        assert_eq!(decode("FF"), Some(vec![0xff]));
+        assert_eq!(decode("0xff"), None);
+        assert_eq!(decode("abc"), None);
+        assert_eq!(decode("zz"), None);
+        assert_eq!(decode("é0"), None);
+
+        assert_eq!(decode_hash(&encode(&[7; 32])), Some([7; 32]));
+        assert_eq!(decode_hash(&encode(&[7; 31])), None);
+    }
+}
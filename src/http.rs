@@ -0,0 +1,368 @@
+//! The HTTP/JSON verification service served by `progpow-httpd`.
+//!
+//! - `POST /verify` takes `header_hash`, `block_number`, `nonce`, `mix_hash`
+//!   and `boundary`, and reports whether the seal is valid.
+//! - `POST /hash` takes `header_hash`, `block_number` and `nonce`, and returns
+//!   the mix and final hashes.
+//! - `GET /health` answers `{"status": "ok"}`.
+//! - `GET /metrics` reports request and seal counters in Prometheus format,
+//!   followed with the `metrics` feature by the crate's own
+//!   [`crate::metrics`].
+//!
+//! Hashes and nonces are `0x`-prefixed hex strings, so nonces above 2^53
+//! survive JavaScript clients; block numbers are JSON numbers. Malformed
+//! requests are answered with `400` and `{"error": "..."}`. Like the gRPC
+//! service, requests are answered from a shared [`CacheManager`] on Tokio's
+//! blocking pool.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use axum::extract::rejection::JsonRejection;
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::ethash::cache::{epoch, MAX_EPOCH};
+use crate::ethash::manager::{CacheManager, EpochCache};
+use crate::hex;
+use crate::progpow::verify::{Seal, SealError};
+
+/// The body of `POST /hash`.
+#[derive(Deserialize)]
+struct HashRequest {
+    header_hash: String,
+    block_number: u64,
+    nonce: String,
+}
+
+/// The body of `POST /verify`.
+#[derive(Deserialize)]
+struct VerifyRequest {
+    header_hash: String,
+    block_number: u64,
+    nonce: String,
+    mix_hash: String,
+    boundary: String,
+}
+
+/// The answer to `POST /hash`.
+#[derive(Serialize)]
+struct HashResponse {
+    mix_hash: String,
+    final_hash: String,
+}
+
+/// The answer to `POST /verify`.
+#[derive(Serialize)]
+struct VerifyResponse {
+    valid: bool,
+    /// `valid`, `mix_mismatch` or `boundary_not_met`.
+    status: &'static str,
+    /// The recomputed mix hash.
+    mix_hash: String,
+    /// The final hash; absent on a mix mismatch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    final_hash: Option<String>,
+}
+
+/// A failed request, answered with its status and `{"error": "..."}`.
+struct ApiError(StatusCode, String);
+
+impl ApiError {
+    fn bad_request(message: impl Into<String>) -> Self {
+        ApiError(StatusCode::BAD_REQUEST, message.into())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(json!({ "error": self.1 }))).into_response()
+    }
+}
+
+/// Request and seal counters reported by `GET /metrics`.
+#[derive(Default)]
+struct Metrics {
+    hash_requests: AtomicU64,
+    verify_requests: AtomicU64,
+    failed_requests: AtomicU64,
+    valid_seals: AtomicU64,
+    mix_mismatches: AtomicU64,
+    boundary_misses: AtomicU64,
+}
+
+/// The state shared by every route.
+struct Service {
+    caches: Arc<CacheManager>,
+    metrics: Metrics,
+}
+
+impl Service {
+    /// Runs `f` on the blocking pool with the cache for `block_number`.
+    async fn with_cache<T: Send + 'static>(
+        &self,
+        block_number: u64,
+        f: impl FnOnce(&EpochCache) -> T + Send + 'static,
+    ) -> Result<T, ApiError> {
+        let epoch = epoch(block_number);
+        if epoch >= MAX_EPOCH {
+            return Err(ApiError::bad_request(format!(
+                "block {block_number} is in epoch {epoch}, past the last supported epoch {}",
+                MAX_EPOCH - 1
+            )));
+        }
+        let caches = self.caches.clone();
+        tokio::task::spawn_blocking(move || f(&caches.for_block(block_number)))
+            .await
+            .map_err(|error| ApiError(StatusCode::INTERNAL_SERVER_ERROR, error.to_string()))
+    }
+
+    /// Counts a failed request and passes the error on.
+    fn reject<T>(&self, result: Result<T, ApiError>) -> Result<T, ApiError> {
+        if result.is_err() {
+            self.metrics.failed_requests.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+/// Returns the router serving the verification API from `caches`.
+pub fn router(caches: Arc<CacheManager>) -> Router {
+    let service = Arc::new(Service {
+        caches,
+        metrics: Metrics::default(),
+    });
+    Router::new()
+        .route("/hash", post(hash))
+        .route("/verify", post(verify))
+        .route("/health", get(health))
+        .route("/metrics", get(metrics))
+        .with_state(service)
+}
+
+/// Reports a body that is not the expected JSON as a bad request.
+fn malformed(rejection: JsonRejection) -> ApiError {
+    ApiError::bad_request(rejection.body_text())
+}
+
+/// Parses a `0x`-prefixed, 64-digit hex field.
+fn parse_hash(hex: &str, name: &str) -> Result<[u8; 32], ApiError> {
+    let invalid = || ApiError::bad_request(format!("{name} must be 32 bytes of 0x-prefixed hex"));
+    hex.strip_prefix("0x")
+        .and_then(hex::decode_hash)
+        .ok_or_else(invalid)
+}
+
+/// Parses a `0x`-prefixed hex nonce of at most 16 digits.
+fn parse_nonce(hex: &str) -> Result<u64, ApiError> {
+    hex.strip_prefix("0x")
+        .filter(|digits| !digits.is_empty() && digits.len() <= 16)
+        .and_then(|digits| u64::from_str_radix(digits, 16).ok())
+        .ok_or_else(|| ApiError::bad_request("nonce must be at most 8 bytes of 0x-prefixed hex"))
+}
+
+async fn hash(
+    State(service): State<Arc<Service>>,
+    body: Result<Json<HashRequest>, JsonRejection>,
+) -> Result<Json<HashResponse>, ApiError> {
+    service
+        .metrics
+        .hash_requests
+        .fetch_add(1, Ordering::Relaxed);
+    let Json(request) = service.reject(body.map_err(malformed))?;
+    let parsed = parse_hash(&request.header_hash, "header_hash")
+        .and_then(|header_hash| Ok((header_hash, parse_nonce(&request.nonce)?)));
+    let (header_hash, nonce) = service.reject(parsed)?;
+    let block_number = request.block_number;
+    let result = service
+        .with_cache(block_number, move |cache| {
+            cache.hash(&header_hash, block_number, nonce)
+        })
+        .await;
+    let (mix_hash, final_hash) = service.reject(result)?;
+    Ok(Json(HashResponse {
+        mix_hash: hex::encode_prefixed(&mix_hash),
+        final_hash: hex::encode_prefixed(&final_hash),
+    }))
+}
+
+async fn verify(
+    State(service): State<Arc<Service>>,
+    body: Result<Json<VerifyRequest>, JsonRejection>,
+) -> Result<Json<VerifyResponse>, ApiError> {
+    service
+        .metrics
+        .verify_requests
+        .fetch_add(1, Ordering::Relaxed);
+    let Json(request) = service.reject(body.map_err(malformed))?;
+    let parsed = (|| {
+        Ok(Seal {
+            header_hash: parse_hash(&request.header_hash, "header_hash")?,
+            block_number: request.block_number,
+            nonce: parse_nonce(&request.nonce)?,
+            mix_hash: parse_hash(&request.mix_hash, "mix_hash")?,
+            boundary: parse_hash(&request.boundary, "boundary")?,
+        })
+    })();
+    let seal = service.reject(parsed)?;
+    let result = {
+        let seal = seal.clone();
+        service
+            .with_cache(seal.block_number, move |cache| cache.verify_seal(&seal))
+            .await
+    };
+    let metrics = &service.metrics;
+    let response = match service.reject(result)? {
+        Ok(final_hash) => {
+            metrics.valid_seals.fetch_add(1, Ordering::Relaxed);
+            VerifyResponse {
+                valid: true,
+                status: "valid",
+                mix_hash: hex::encode_prefixed(&seal.mix_hash),
+                final_hash: Some(hex::encode_prefixed(&final_hash)),
+            }
+        }
+        Err(SealError::MixMismatch { computed }) => {
+            metrics.mix_mismatches.fetch_add(1, Ordering::Relaxed);
+            VerifyResponse {
+                valid: false,
+                status: "mix_mismatch",
+                mix_hash: hex::encode_prefixed(&computed),
+                final_hash: None,
+            }
+        }
+        Err(SealError::BoundaryNotMet { final_hash }) => {
+            metrics.boundary_misses.fetch_add(1, Ordering::Relaxed);
+            VerifyResponse {
+                valid: false,
+                status: "boundary_not_met",
+                mix_hash: hex::encode_prefixed(&seal.mix_hash),
+                final_hash: Some(hex::encode_prefixed(&final_hash)),
+            }
+        }
+    };
+    Ok(Json(response))
+}
+
+async fn health() -> Json<serde_json::Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+async fn metrics(State(service): State<Arc<Service>>) -> impl IntoResponse {
+    let metrics = &service.metrics;
+    let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+    let body = format!(
+        "# TYPE progpow_requests_total counter\n\
+         progpow_requests_total{{route=\"hash\"}} {}\n\
+         progpow_requests_total{{route=\"verify\"}} {}\n\
+         # TYPE progpow_failed_requests_total counter\n\
+         progpow_failed_requests_total {}\n\
+         # TYPE progpow_seals_total counter\n\
+         progpow_seals_total{{status=\"valid\"}} {}\n\
+         progpow_seals_total{{status=\"mix_mismatch\"}} {}\n\
+         progpow_seals_total{{status=\"boundary_not_met\"}} {}\n",
+        load(&metrics.hash_requests),
+        load(&metrics.verify_requests),
+        load(&metrics.failed_requests),
+        load(&metrics.valid_seals),
+        load(&metrics.mix_mismatches),
+        load(&metrics.boundary_misses),
+    );
+    #[cfg(feature = "metrics")]
+    let body = body + &crate::metrics::global().render();
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::tiny_cache_manager;
+    use std::future::IntoFuture;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Sends one HTTP/1.1 request and returns the status code and body.
+    async fn request(
+        address: std::net::SocketAddr,
+        method: &str,
+        path: &str,
+        body: &str,
+    ) -> (u16, String) {
+        let mut stream = tokio::net::TcpStream::connect(address).await.unwrap();
+        let request = format!(
+            "{method} {path} HTTP/1.1\r\nhost: test\r\ncontent-type: application/json\r\n\
+             content-length: {}\r\nconnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let status = response[9..12].parse().unwrap();
+        let body = response.split_once("\r\n\r\n").unwrap().1.to_string();
+        (status, body)
+    }
+
+    #[tokio::test]
+    async fn test_http_service_verifies_seals() {
+        let caches = tiny_cache_manager(1);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, router(Arc::new(caches))).into_future());
+
+        let header = hex::encode_prefixed(&[5; 32]);
+        let (status, body) = request(
+            address,
+            "POST",
+            "/hash",
+            &format!(
+                r#"{{"header_hash":"{header}","block_number":100,"nonce":"0xffffffffffffffff"}}"#
+            ),
+        )
+        .await;
+        assert_eq!(status, 200);
+        let hash: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let verify = |mix_hash: &str| {
+            format!(
+                r#"{{"header_hash":"{header}","block_number":100,"nonce":"0xffffffffffffffff",
+                    "mix_hash":"{mix_hash}","boundary":"0x{}"}}"#,
+                "f".repeat(64)
+            )
+        };
+
+        let (status, body) = request(
+            address,
+            "POST",
+            "/verify",
+            &verify(hash["mix_hash"].as_str().unwrap()),
+        )
+        .await;
+        assert_eq!(status, 200);
+        let verdict: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(verdict["status"], "valid");
+        assert_eq!(verdict["final_hash"], hash["final_hash"]);
+
+        let (_, body) = request(
+            address,
+            "POST",
+            "/verify",
+            &verify(&hex::encode_prefixed(&[0; 32])),
+        )
+        .await;
+        assert!(body.contains(r#""status":"mix_mismatch""#));
+        let (status, body) = request(address, "POST", "/verify", &verify("0x12")).await;
+        assert_eq!(status, 400);
+        assert!(body.contains("mix_hash must be 32 bytes"));
+        let (status, body) = request(address, "POST", "/hash", r#"{"block_number":1e30}"#).await;
+        assert_eq!(status, 400);
+        assert!(body.starts_with(r#"{"error":"#));
+
+        assert_eq!(request(address, "GET", "/health", "").await.0, 200);
+        let (_, metrics) = request(address, "GET", "/metrics", "").await;
+        assert!(metrics.contains("progpow_seals_total{status=\"mix_mismatch\"} 1"));
+        assert!(metrics.contains("progpow_failed_requests_total 2"));
+    }
+}
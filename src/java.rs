@@ -0,0 +1,193 @@
+//! JNI bindings for JVM pool backends, implementing the native methods of
+//! `org.progpow.ProgPow` (see `examples/jni-gradle`).
+//!
+//! A `ProgPow` object owns one epoch's light cache through an opaque `long`
+//! handle. Share validation returns the same status codes as the C API's
+//! `ProgpowStatus`; malformed arguments raise `IllegalArgumentException`, and
+//! using a closed object raises `IllegalStateException`.
+
+use jni::errors::{Error, Result, ThrowRuntimeExAndDefault};
+use jni::objects::{JByteArray, JClass};
+use jni::sys::{jint, jlong};
+use jni::{jni_str, Env, EnvUnowned};
+
+use crate::ethash::cache::{cache_size, dataset_size, make_cache, seed_hash};
+use crate::ethash::dataset::{dataset_lookup, generate_c_dag};
+use crate::ffi::ProgpowStatus;
+use crate::progpow::progpow::progpow;
+use crate::progpow::verify::{verify_seal, Seal, SealError};
+
+/// The light cache behind a Java `ProgPow` handle.
+struct JavaVerifier {
+    size: u64,
+    words: Vec<u32>,
+    c_dag: Vec<u32>,
+}
+
+impl JavaVerifier {
+    /// Wraps `words`, a light cache for a dataset of `size` bytes.
+    fn new(size: u64, words: Vec<u32>) -> Self {
+        let c_dag = generate_c_dag(&words);
+        JavaVerifier { size, words, c_dag }
+    }
+
+    /// Returns the mix hash followed by the final hash.
+    fn hash(&self, header_hash: &[u8; 32], block_number: u64, nonce: u64) -> Vec<u8> {
+        let lookup = dataset_lookup(&self.words);
+        let (mut mix_hash, final_hash) = progpow(
+            header_hash,
+            nonce,
+            self.size,
+            block_number,
+            &self.c_dag,
+            &lookup,
+        );
+        mix_hash.extend(final_hash);
+        mix_hash
+    }
+
+    /// Checks a share, returning its `ProgpowStatus` code.
+    fn validate_share(&self, seal: &Seal) -> ProgpowStatus {
+        let lookup = dataset_lookup(&self.words);
+        match verify_seal(seal, self.size, &self.c_dag, &lookup) {
+            Ok(_) => ProgpowStatus::Ok,
+            Err(SealError::MixMismatch { .. }) => ProgpowStatus::MixMismatch,
+            Err(SealError::BoundaryNotMet { .. }) => ProgpowStatus::BoundaryNotMet,
+        }
+    }
+}
+
+/// Returns the verifier behind `handle`, throwing if it was closed.
+fn verifier<'a>(env: &mut Env<'_>, handle: jlong) -> Result<&'a JavaVerifier> {
+    // SAFETY: non-zero handles come from `newCache` and stay valid until
+    // `freeCache`, which the Java side calls once, from `close`.
+    match unsafe { (handle as *const JavaVerifier).as_ref() } {
+        Some(verifier) => Ok(verifier),
+        None => {
+            env.throw_new(
+                jni_str!("java/lang/IllegalStateException"),
+                jni_str!("ProgPow is closed"),
+            )?;
+            Err(Error::JavaException)
+        }
+    }
+}
+
+/// Reads a 32-byte Java array, throwing if it has another length.
+fn hash_arg(env: &mut Env<'_>, array: &JByteArray<'_>) -> Result<[u8; 32]> {
+    let bytes = env.convert_byte_array(array)?;
+    match bytes.try_into() {
+        Ok(hash) => Ok(hash),
+        Err(_) => {
+            env.throw_new(
+                jni_str!("java/lang/IllegalArgumentException"),
+                jni_str!("hashes must be 32 bytes"),
+            )?;
+            Err(Error::JavaException)
+        }
+    }
+}
+
+/// `private static native long newCache(long epoch)`
+#[no_mangle]
+pub extern "system" fn Java_org_progpow_ProgPow_newCache<'caller>(
+    mut env: EnvUnowned<'caller>,
+    _class: JClass<'caller>,
+    epoch: jlong,
+) -> jlong {
+    env.with_env(|_| -> Result<_> {
+        let epoch = epoch as u64;
+        let words = make_cache(cache_size(epoch), &seed_hash(epoch));
+        let verifier = JavaVerifier::new(dataset_size(epoch), words);
+        Ok(Box::into_raw(Box::new(verifier)) as jlong)
+    })
+    .resolve::<ThrowRuntimeExAndDefault>()
+}
+
+/// `private static native void freeCache(long handle)`
+#[no_mangle]
+pub extern "system" fn Java_org_progpow_ProgPow_freeCache<'caller>(
+    _env: EnvUnowned<'caller>,
+    _class: JClass<'caller>,
+    handle: jlong,
+) {
+    if handle != 0 {
+        // SAFETY: see `verifier`; the Java side never reuses a freed handle.
+        drop(unsafe { Box::from_raw(handle as *mut JavaVerifier) });
+    }
+}
+
+/// `private static native byte[] hash(long handle, byte[] headerHash, long blockNumber, long nonce)`
+#[no_mangle]
+pub extern "system" fn Java_org_progpow_ProgPow_hash<'caller>(
+    mut env: EnvUnowned<'caller>,
+    _class: JClass<'caller>,
+    handle: jlong,
+    header_hash: JByteArray<'caller>,
+    block_number: jlong,
+    nonce: jlong,
+) -> JByteArray<'caller> {
+    env.with_env(|env| -> Result<_> {
+        let verifier = verifier(env, handle)?;
+        let header_hash = hash_arg(env, &header_hash)?;
+        let hashes = verifier.hash(&header_hash, block_number as u64, nonce as u64);
+        env.byte_array_from_slice(&hashes)
+    })
+    .resolve::<ThrowRuntimeExAndDefault>()
+}
+
+/// `private static native int validateShare(long handle, byte[] headerHash, long blockNumber,
+/// long nonce, byte[] mixHash, byte[] boundary)`
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub extern "system" fn Java_org_progpow_ProgPow_validateShare<'caller>(
+    mut env: EnvUnowned<'caller>,
+    _class: JClass<'caller>,
+    handle: jlong,
+    header_hash: JByteArray<'caller>,
+    block_number: jlong,
+    nonce: jlong,
+    mix_hash: JByteArray<'caller>,
+    boundary: JByteArray<'caller>,
+) -> jint {
+    env.with_env(|env| -> Result<_> {
+        let verifier = verifier(env, handle)?;
+        let seal = Seal {
+            header_hash: hash_arg(env, &header_hash)?,
+            block_number: block_number as u64,
+            nonce: nonce as u64,
+            mix_hash: hash_arg(env, &mix_hash)?,
+            boundary: hash_arg(env, &boundary)?,
+        };
+        Ok(verifier.validate_share(&seal) as jint)
+    })
+    .resolve::<ThrowRuntimeExAndDefault>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_java_verifier_validates_shares() {
+        let verifier = JavaVerifier::new(1 << 16, make_cache(1024, &seed_hash(1)));
+        let header_hash = [7u8; 32];
+        let hashes = verifier.hash(&header_hash, 100, 9);
+        let mut seal = Seal {
+            header_hash,
+            block_number: 100,
+            nonce: 9,
+            mix_hash: hashes[..32].try_into().unwrap(),
+            boundary: [0xff; 32],
+        };
+
+        assert_eq!(verifier.validate_share(&seal), ProgpowStatus::Ok);
+        seal.boundary = [0; 32];
+        assert_eq!(
+            verifier.validate_share(&seal),
+            ProgpowStatus::BoundaryNotMet
+        );
+        seal.mix_hash[0] ^= 1;
+        assert_eq!(verifier.validate_share(&seal), ProgpowStatus::MixMismatch);
+    }
+}
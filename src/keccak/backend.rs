@@ -0,0 +1,305 @@
+//! Keccak-f permutation backends.
+//!
+//! Every Keccak hash in the crate permutes through [`keccak_f`], which runs
+//! the backend chosen at build time:
+//!
+//! - `keccak-scalar`, the default: the portable round function in
+//!   [`permutation`](crate::keccak::permutation).
+//! - `keccak-simd`: Theta and Chi on whole rows with SSE2 vectors on
+//!   x86_64, which every x86_64 CPU has; other targets use the scalar
+//!   rounds.
+//! - `keccak-asm`: the RustCrypto `keccak` crate with its `asm` feature,
+//!   which uses the ARMv8 SHA-3 instructions for Keccak-f1600 on aarch64
+//!   CPUs that have them and portable code elsewhere.
+//!
+//! When several are enabled the last in this list wins; the others stay
+//! compiled so tests can check each against the scalar rounds.
+
+use crate::keccak::permutation::{keccak_f_round, KeccakLane};
+
+/// A Keccak-f permutation backend.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// The portable round function.
+    Scalar,
+    /// SSE2 row operations.
+    #[cfg(feature = "keccak-simd")]
+    Simd,
+    /// The `keccak` crate.
+    #[cfg(feature = "keccak-asm")]
+    Asm,
+}
+
+impl Backend {
+    /// The backend [`keccak_f`] runs.
+    pub const SELECTED: Backend = {
+        #[cfg(feature = "keccak-asm")]
+        let backend = Backend::Asm;
+        #[cfg(all(feature = "keccak-simd", not(feature = "keccak-asm")))]
+        let backend = Backend::Simd;
+        #[cfg(not(any(feature = "keccak-simd", feature = "keccak-asm")))]
+        let backend = Backend::Scalar;
+        backend
+    };
+
+    /// Returns every backend compiled in.
+    pub fn available() -> Vec<Backend> {
+        vec![
+            Backend::Scalar,
+            #[cfg(feature = "keccak-simd")]
+            Backend::Simd,
+            #[cfg(feature = "keccak-asm")]
+            Backend::Asm,
+        ]
+    }
+
+    /// Returns the backend's feature name.
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Scalar => "keccak-scalar",
+            #[cfg(feature = "keccak-simd")]
+            Backend::Simd => "keccak-simd",
+            #[cfg(feature = "keccak-asm")]
+            Backend::Asm => "keccak-asm",
+        }
+    }
+}
+
+/// A lane width the backends can permute.
+pub trait Permute: KeccakLane {
+    /// The rounds of the full permutation: 22 for Keccak-f800 and 24 for
+    /// Keccak-f1600.
+    const FULL_ROUNDS: usize;
+
+    /// Applies the first `rounds` rounds with `backend`.
+    fn permute(backend: Backend, st: &mut [Self; 25], rounds: usize);
+}
+
+impl Permute for u32 {
+    const FULL_ROUNDS: usize = 22;
+
+    fn permute(backend: Backend, st: &mut [u32; 25], rounds: usize) {
+        match backend {
+            Backend::Scalar => scalar(st, rounds),
+            #[cfg(feature = "keccak-simd")]
+            Backend::Simd => simd::f800(st, rounds),
+            #[cfg(feature = "keccak-asm")]
+            Backend::Asm if rounds == Self::FULL_ROUNDS => keccak::f800(st),
+            #[cfg(feature = "keccak-asm")]
+            Backend::Asm => scalar(st, rounds),
+        }
+    }
+}
+
+impl Permute for u64 {
+    const FULL_ROUNDS: usize = 24;
+
+    fn permute(backend: Backend, st: &mut [u64; 25], rounds: usize) {
+        match backend {
+            Backend::Scalar => scalar(st, rounds),
+            #[cfg(feature = "keccak-simd")]
+            Backend::Simd => simd::f1600(st, rounds),
+            #[cfg(feature = "keccak-asm")]
+            Backend::Asm if rounds == Self::FULL_ROUNDS => keccak::f1600(st),
+            #[cfg(feature = "keccak-asm")]
+            Backend::Asm => scalar(st, rounds),
+        }
+    }
+}
+
+/// Applies the first `rounds` rounds of the Keccak-f permutation with the
+/// selected backend.
+///
+/// Keccak-f1600 runs all 24 rounds; ProgPoW's Keccak-f800 runs 22. The
+/// `keccak` crate numbers reduced rounds from the end instead, so the asm
+/// backend only takes full permutations and runs others on the scalar
+/// rounds.
+///
+/// # Panics
+///
+/// Panics if `rounds` exceeds 24.
+pub fn keccak_f<L: Permute>(st: &mut [L; 25], rounds: usize) {
+    L::permute(Backend::SELECTED, st, rounds);
+}
+
+/// Applies `rounds` scalar rounds.
+fn scalar<L: KeccakLane>(st: &mut [L; 25], rounds: usize) {
+    for r in 0..rounds {
+        keccak_f_round(st, r);
+    }
+}
+
+/// Theta and Chi on SSE2 vectors; Rho, Pi and Iota stay scalar.
+///
+/// Both steps work on rows of five lanes, four (f800) or two (f1600) to a
+/// vector; Chi copies each row into a seven-lane buffer that repeats its
+/// first two lanes, so a lane's neighbours are one unaligned load away.
+#[cfg(feature = "keccak-simd")]
+mod simd {
+    #[cfg(target_arch = "x86_64")]
+    use crate::keccak::permutation::KeccakLane;
+
+    /// Pi lane mappings.
+    #[cfg(target_arch = "x86_64")]
+    const KECCAKF_PILN: [usize; 24] = [
+        10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4, 15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1,
+    ];
+
+    /// Applies Rho and Pi, the scalar steps between Theta and Chi.
+    #[cfg(target_arch = "x86_64")]
+    fn rho_pi<L: KeccakLane>(st: &mut [L; 25]) {
+        let mut t = st[1];
+        for (i, &j) in KECCAKF_PILN.iter().enumerate() {
+            let next = st[j];
+            st[j] = t.rotl(L::ROTATIONS[i]);
+            t = next;
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub(super) fn f800(st: &mut [u32; 25], rounds: usize) {
+        use std::arch::x86_64::*;
+
+        // SAFETY: SSE2 is part of the x86_64 baseline, and every load and
+        // store covers four lanes inside a buffer of at least five.
+        unsafe {
+            let rotl1 = |v: __m128i| _mm_or_si128(_mm_slli_epi32(v, 1), _mm_srli_epi32(v, 31));
+            let load = |lanes: &[u32]| _mm_loadu_si128(lanes.as_ptr() as *const __m128i);
+            for r in 0..rounds {
+                // Theta: the column parities, then each column's mask.
+                let mut c = [0u32; 5];
+                let mut c0 = _mm_setzero_si128();
+                for y in (0..25).step_by(5) {
+                    c0 = _mm_xor_si128(c0, load(&st[y..y + 4]));
+                    c[4] ^= st[y + 4];
+                }
+                _mm_storeu_si128(c.as_mut_ptr() as *mut __m128i, c0);
+                // d[x] = c[x + 4] ^ rotl(c[x + 1], 1), indices modulo 5.
+                let ext = [c[4], c[0], c[1], c[2], c[3]];
+                let d0 = _mm_xor_si128(load(&ext[..4]), rotl1(load(&c[1..5])));
+                let d4 = c[3] ^ c[0].rotate_left(1);
+                for y in (0..25).step_by(5) {
+                    let row = _mm_xor_si128(load(&st[y..y + 4]), d0);
+                    _mm_storeu_si128(st[y..].as_mut_ptr() as *mut __m128i, row);
+                    st[y + 4] ^= d4;
+                }
+
+                rho_pi(st);
+
+                // Chi: lane x ^= !lane (x + 1) & lane (x + 2).
+                for y in (0..25).step_by(5) {
+                    let mut e = [0u32; 7];
+                    e[..5].copy_from_slice(&st[y..y + 5]);
+                    e[5] = e[0];
+                    e[6] = e[1];
+                    let row = _mm_xor_si128(
+                        load(&e[..4]),
+                        _mm_andnot_si128(load(&e[1..5]), load(&e[2..6])),
+                    );
+                    _mm_storeu_si128(st[y..].as_mut_ptr() as *mut __m128i, row);
+                    st[y + 4] = e[4] ^ (!e[5] & e[6]);
+                }
+
+                st[0] ^= u32::ROUND_CONSTANTS[r];
+            }
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub(super) fn f1600(st: &mut [u64; 25], rounds: usize) {
+        use std::arch::x86_64::*;
+
+        // SAFETY: SSE2 is part of the x86_64 baseline, and every load and
+        // store covers two lanes inside a buffer of at least five.
+        unsafe {
+            let rotl1 = |v: __m128i| _mm_or_si128(_mm_slli_epi64(v, 1), _mm_srli_epi64(v, 63));
+            let load = |lanes: &[u64]| _mm_loadu_si128(lanes.as_ptr() as *const __m128i);
+            for r in 0..rounds {
+                // Theta: the column parities, then each column's mask.
+                let mut c = [0u64; 5];
+                let (mut c0, mut c2) = (_mm_setzero_si128(), _mm_setzero_si128());
+                for y in (0..25).step_by(5) {
+                    c0 = _mm_xor_si128(c0, load(&st[y..y + 2]));
+                    c2 = _mm_xor_si128(c2, load(&st[y + 2..y + 4]));
+                    c[4] ^= st[y + 4];
+                }
+                _mm_storeu_si128(c.as_mut_ptr() as *mut __m128i, c0);
+                _mm_storeu_si128(c[2..].as_mut_ptr() as *mut __m128i, c2);
+                // d[x] = c[x + 4] ^ rotl(c[x + 1], 1), indices modulo 5.
+                let ext = [c[4], c[0], c[1], c[2], c[3]];
+                let d0 = _mm_xor_si128(load(&ext[..2]), rotl1(load(&c[1..3])));
+                let d2 = _mm_xor_si128(load(&ext[2..4]), rotl1(load(&c[3..5])));
+                let d4 = c[3] ^ c[0].rotate_left(1);
+                for y in (0..25).step_by(5) {
+                    let lo = _mm_xor_si128(load(&st[y..y + 2]), d0);
+                    let hi = _mm_xor_si128(load(&st[y + 2..y + 4]), d2);
+                    _mm_storeu_si128(st[y..].as_mut_ptr() as *mut __m128i, lo);
+                    _mm_storeu_si128(st[y + 2..].as_mut_ptr() as *mut __m128i, hi);
+                    st[y + 4] ^= d4;
+                }
+
+                rho_pi(st);
+
+                // Chi: lane x ^= !lane (x + 1) & lane (x + 2).
+                for y in (0..25).step_by(5) {
+                    let mut e = [0u64; 7];
+                    e[..5].copy_from_slice(&st[y..y + 5]);
+                    e[5] = e[0];
+                    e[6] = e[1];
+                    let chi = |x: usize| {
+                        _mm_xor_si128(
+                            load(&e[x..x + 2]),
+                            _mm_andnot_si128(load(&e[x + 1..x + 3]), load(&e[x + 2..x + 4])),
+                        )
+                    };
+                    _mm_storeu_si128(st[y..].as_mut_ptr() as *mut __m128i, chi(0));
+                    _mm_storeu_si128(st[y + 2..].as_mut_ptr() as *mut __m128i, chi(2));
+                    st[y + 4] = e[4] ^ (!e[5] & e[6]);
+                }
+
+                st[0] ^= u64::ROUND_CONSTANTS[r];
+            }
+        }
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    pub(super) fn f800(st: &mut [u32; 25], rounds: usize) {
+        super::scalar(st, rounds);
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    pub(super) fn f1600(st: &mut [u64; 25], rounds: usize) {
+        super::scalar(st, rounds);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    /// Checks every compiled backend against the scalar rounds on `L`.
+    fn check_backends<L: Permute + PartialEq + std::fmt::Debug>(lane: impl Fn(u64) -> L) {
+        let mut seed = 7u64;
+        for rounds in [1, 7, L::FULL_ROUNDS] {
+            let start: [L; 25] = std::array::from_fn(|_| {
+                seed = seed
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                lane(seed)
+            });
+            let mut expected = start;
+            L::permute(Backend::Scalar, &mut expected, rounds);
+            for backend in Backend::available() {
+                let mut st = start;
+                L::permute(backend, &mut st, rounds);
+                assert_eq!(st, expected, "{} with {rounds} rounds", backend.name());
+            }
+        }
+    }
+
+    #[test]
+    fn test_backends_agree() {
+        check_backends::<u32>(|x| x as u32);
+        check_backends::<u64>(|x| x);
+        assert!(Backend::available().contains(&Backend::SELECTED));
+    }
+}
@@ -0,0 +1,87 @@
+use crate::keccak::backend::keccak_f;
+use crate::keccak::sponge::Sponge;
+
+/// Keccak-f1600 permutation.
+///
+/// Applies all 24 rounds of `Theta`, `Rho`, `Pi`, `Chi`, and `Iota` to the
+/// state, as used by ethash to derive the light cache and the dataset.
+///
+/// # Arguments
+///
+/// * `st` - A mutable reference to the 25-lane state array.
+pub fn keccak_f1600(st: &mut [u64; 25]) {
+    keccak_f(st, 24);
+}
+
+/// Computes the Keccak-256 hash of `data`.
+///
+/// This is the original Keccak padding used by Ethereum, not NIST SHA3-256.
+///
+/// # Arguments
+///
+/// * `data` - The bytes to hash.
+///
+/// # Returns
+///
+/// The 32-byte digest.
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    keccak(136, data, &mut out);
+    out
+}
+
+/// Computes the Keccak-512 hash of `data`.
+///
+/// This is the original Keccak padding used by Ethereum, not NIST SHA3-512.
+///
+/// # Arguments
+///
+/// * `data` - The bytes to hash.
+///
+/// # Returns
+///
+/// The 64-byte digest.
+pub fn keccak512(data: &[u8]) -> [u8; 64] {
+    let mut out = [0u8; 64];
+    keccak(72, data, &mut out);
+    out
+}
+
+/// Hashes `data` with a `rate`-byte block size into `out`.
+fn keccak(rate: usize, data: &[u8], out: &mut [u8]) {
+    let mut sponge = Sponge::<u64>::new(rate, 24);
+    sponge.update(data);
+    sponge.finalize(out);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+
+    #[test]
+    fn test_keccak256_vectors() {
+        assert_eq!(
+            hex::encode(&keccak256(b"")),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+        assert_eq!(
+            hex::encode(&keccak256(&[0; 32])),
+            "290decd9548b62a8d60345a988386fc84ba6bc95484008f6362f93160ef3e563"
+        );
+    }
+
+    #[test]
+    fn test_keccak512_vectors() {
+        assert_eq!(
+            hex::encode(&keccak512(b"")),
+            "0eab42de4c3ceb9235fc91acffe746b29c29a8c366b7c60e4e67c466f36a4304\
+             c00fa9caf9d87976ba469bcbe06713b435f091ef2769fb160cdab33d3670680e"
+        );
+        assert_eq!(
+            hex::encode(&keccak512(b"abc")),
+            "18587dc2ea106b9a1563e32b3312421ca164c7f1f07bc922a9c83d77cea3a1e5\
+             d0c69910739025372dc14ac9642629379540c17e2a65b19d77aa511a9d00bb96"
+        );
+    }
+}
@@ -1,13 +1,10 @@
-use crate::{
-    basic_algorithm::{higher32, lower32},
-    keccak::f800round::keccak_f800_round,
-};
+use crate::keccak::f800state::KeccakF800State;
 
 /// Computes a shortened Keccak-f800 hash.
 ///
-/// This function initializes a state array, combines the `header_hash`, `nonce`,
-/// and `result` values, performs 21 rounds of the Keccak-f800 permutation,
-/// and finally returns the result as a single 64-bit unsigned integer.
+/// This absorbs the `header_hash`, `nonce` and `result` into a fresh
+/// [`KeccakF800State`], permutes it once, and returns the first two words as
+/// a single 64-bit unsigned integer.
 ///
 /// # Arguments
 ///
@@ -18,37 +15,42 @@ use crate::{
 /// # Returns
 ///
 /// A `u64` representing the shortened Keccak-f800 hash result.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip_all, fields(nonce))
+)]
 pub fn keccak_f800_short(header_hash: &[u8], nonce: u64, result: &[u32]) -> u64 {
-    let mut st = [0u32; 25]; // Initialize the state array with 25 32-bit integers.
-
-    // Populate the first 8 words of the state array from `header_hash`.
-    for i in 0..8 {
-        st[i] = (header_hash[4 * i] as u32)
-            | ((header_hash[4 * i + 1] as u32) << 8)
-            | ((header_hash[4 * i + 2] as u32) << 16)
-            | ((header_hash[4 * i + 3] as u32) << 24);
-    }
-
-    // Add the lower 32 bits and higher 32 bits of the `nonce` to the state.
-    st[8] = lower32(nonce);
-    st[9] = higher32(nonce);
-
-    // Add the first 8 elements of the `result` array to the state.
-    for i in 0..8 {
-        st[10 + i] = result[i];
-    }
-
-    // Perform 21 rounds of the Keccak-f800 permutation.
-    for r in 0..21 {
-        keccak_f800_round(&mut st, r);
-    }
-    // Perform the 22nd round explicitly (round 21).
-    keccak_f800_round(&mut st, 21);
+    keccak_f800_short_with_digest(header_hash, nonce, result, &mut [0; 32])
+}
 
-    // Convert the first two words of the state into a single `u64`.
-    let mut ret = [0u8; 8];
-    ret[4..].copy_from_slice(&st[0].to_be_bytes()); // Use the first state word (big-endian).
-    ret[..4].copy_from_slice(&st[1].to_be_bytes()); // Use the second state word (big-endian).
+/// Computes the shortened Keccak-f800 hash and writes the full 32-byte
+/// digest it is taken from into `digest`.
+///
+/// The digest is what [`keccak_f800_long`](crate::keccak::f800long::keccak_f800_long)
+/// returns for the same input, so callers that need both forms permute once.
+///
+/// # Arguments
+///
+/// * `header_hash`, `nonce`, `result` - As for [`keccak_f800_short`].
+/// * `digest` - The buffer receiving the 32-byte digest.
+///
+/// # Returns
+///
+/// The first 8 bytes of `digest` as a big-endian `u64`.
+pub fn keccak_f800_short_with_digest(
+    header_hash: &[u8],
+    nonce: u64,
+    result: &[u32],
+    digest: &mut [u8; 32],
+) -> u64 {
+    let mut state = KeccakF800State::new();
+    state
+        .absorb_bytes(&header_hash[..32])
+        .absorb_u64(nonce)
+        .absorb_words(&result[..8])
+        .permute();
+    state.squeeze_into(digest);
 
-    u64::from_le_bytes(ret) // Return the 64-bit result.
+    // The first word is the high half, each read big-endian.
+    u64::from_be_bytes(digest[..8].try_into().unwrap())
 }
@@ -0,0 +1,262 @@
+use crate::{
+    basic_algorithm::{higher32, lower32},
+    keccak::backend::keccak_f,
+};
+
+/// Words in the Keccak-f800 state.
+const STATE_WORDS: usize = 25;
+
+/// Rounds in one Keccak-f800 permutation.
+const ROUNDS: usize = 22;
+
+/// The words that fill the state after a hash's input.
+///
+/// ProgPoW 0.9.2 leaves the rest of the state zero. ProgPoW 0.9.4 pads
+/// both of its passes the same way, relative to the end of their input:
+/// `0x00000001` in the first word after it and `0x80008081` eight words
+/// later. KawPoW fills the rest with the letters of "RAVENCOINKAWPOW", one
+/// per word, from the first letter on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Padding {
+    /// Zero words, as in ProgPoW 0.9.2.
+    #[default]
+    Zero,
+    /// The pad words of ProgPoW 0.9.4, as in FiroPoW: state words 10 and 18
+    /// in the seed pass, after the header hash and nonce, and state words 16
+    /// and 24 in the final pass, after the seed and mix hash.
+    Progpow094,
+    /// The "rAVENCOINKAWPOW" words, as in KawPoW.
+    Kawpow,
+    /// Any 15 words, for chains that pad with their own constants.
+    Words([u32; 15]),
+}
+
+/// ProgPoW 0.9.4's padding words, from the first word after the input.
+pub const PROGPOW_094_PADDING: [u32; 15] = [
+    0x00000001, 0, 0, 0, 0, 0, 0, 0, 0x80008081, 0, 0, 0, 0, 0, 0,
+];
+
+/// KawPoW's padding words, the ASCII codes of "rAVENCOINKAWPOW".
+pub const RAVENCOIN_KAWPOW: [u32; 15] = [
+    0x72, 0x41, 0x56, 0x45, 0x4e, 0x43, 0x4f, 0x49, 0x4e, 0x4b, 0x41, 0x57, 0x50, 0x4f, 0x57,
+];
+
+impl Padding {
+    /// Returns the padding word at `index` words past the end of the input.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the 15 words run out, which no input shorter than ten
+    /// words can cause.
+    pub fn word(self, index: usize) -> u32 {
+        match self {
+            Padding::Zero => 0,
+            Padding::Progpow094 => PROGPOW_094_PADDING[index],
+            Padding::Kawpow => RAVENCOIN_KAWPOW[index],
+            Padding::Words(words) => words[index],
+        }
+    }
+}
+
+/// A Keccak-f800 state with an absorb position.
+///
+/// ProgPoW's hashes all follow the same pattern: absorb a header hash, a
+/// 64-bit value and eight words into a zero state, permute once, and squeeze
+/// the first words. `keccak_f800_short` and `keccak_f800_long` are thin
+/// wrappers over this type; variants with other padding or more than one
+/// permutation compose the same three steps.
+///
+/// Absorbing XORs words into the state at the current position, so on a
+/// fresh state it loads them. [`KeccakF800State::permute`] makes the next
+/// absorb start again from the first word.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KeccakF800State {
+    words: [u32; STATE_WORDS],
+    position: usize,
+}
+
+impl KeccakF800State {
+    /// Creates a zero state.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the 25 state words.
+    pub fn words(&self) -> &[u32; STATE_WORDS] {
+        &self.words
+    }
+
+    /// XORs `words` into the state at the current position.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the words run past the end of the state.
+    pub fn absorb_words(&mut self, words: &[u32]) -> &mut Self {
+        let end = self.position + words.len();
+        assert!(end <= STATE_WORDS, "absorbing past the Keccak-f800 state");
+        for (word, &value) in self.words[self.position..end].iter_mut().zip(words) {
+            *word ^= value;
+        }
+        self.position = end;
+        self
+    }
+
+    /// Absorbs `bytes` as little-endian words.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length is not a multiple of 4 or the words run past the
+    /// end of the state.
+    pub fn absorb_bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        assert!(bytes.len().is_multiple_of(4), "absorbing a partial word");
+        let end = self.position + bytes.len() / 4;
+        assert!(end <= STATE_WORDS, "absorbing past the Keccak-f800 state");
+        for (word, value) in self.words[self.position..end]
+            .iter_mut()
+            .zip(bytes.chunks_exact(4))
+        {
+            *word ^= u32::from_le_bytes(value.try_into().unwrap());
+        }
+        self.position = end;
+        self
+    }
+
+    /// Absorbs a 64-bit value as its lower then its higher word.
+    pub fn absorb_u64(&mut self, value: u64) -> &mut Self {
+        self.absorb_words(&[lower32(value), higher32(value)])
+    }
+
+    /// Absorbs `padding` into every word from the current position to the end
+    /// of the state.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the padding has fewer words than remain in the state.
+    pub fn pad(&mut self, padding: Padding) -> &mut Self {
+        for (index, word) in self.words[self.position..].iter_mut().enumerate() {
+            *word ^= padding.word(index);
+        }
+        self.position = STATE_WORDS;
+        self
+    }
+
+    /// Applies the 22-round Keccak-f800 permutation.
+    pub fn permute(&mut self) -> &mut Self {
+        keccak_f(&mut self.words, ROUNDS);
+        self.position = 0;
+        self
+    }
+
+    /// Returns the first `count` state words.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` exceeds the 25 state words.
+    pub fn squeeze(&self, count: usize) -> &[u32] {
+        &self.words[..count]
+    }
+
+    /// Returns the first `count` state words as little-endian bytes.
+    pub fn squeeze_bytes(&self, count: usize) -> Vec<u8> {
+        let mut out = vec![0; count * 4];
+        self.squeeze_into(&mut out);
+        out
+    }
+
+    /// Writes the first `out.len() / 4` state words into `out` as
+    /// little-endian bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length is not a multiple of 4 or exceeds the state.
+    pub fn squeeze_into(&self, out: &mut [u8]) {
+        assert!(out.len().is_multiple_of(4), "squeezing a partial word");
+        let words = self.squeeze(out.len() / 4);
+        for (chunk, word) in out.chunks_exact_mut(4).zip(words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+    use crate::keccak::f800long::{keccak_f800_long, keccak_f800_long_into};
+    use crate::keccak::f800short::{keccak_f800_short, keccak_f800_short_with_digest};
+
+    #[test]
+    fn test_state_matches_short_and_long_hashes() {
+        let header_hash: Vec<u8> = (0..32).collect();
+        let result: Vec<u32> = (0..8).collect();
+        let nonce = 0x123456789abcdef0;
+
+        let mut state = KeccakF800State::new();
+        state
+            .absorb_bytes(&header_hash)
+            .absorb_u64(nonce)
+            .absorb_words(&result)
+            .permute();
+        let long = keccak_f800_long(&header_hash, nonce, &result);
+        assert_eq!(state.squeeze_bytes(8), long);
+        assert_eq!(
+            hex::encode(&long),
+            "5403d42d694d6e734380830ab064465897d5b4b078bb9ed05f4c57e69facf171"
+        );
+        assert_eq!(
+            keccak_f800_short(&header_hash, nonce, &result),
+            0x5403d42d694d6e73
+        );
+        assert_eq!(
+            keccak_f800_short(&header_hash, nonce, &[0; 8]),
+            0x03e410fba1aaa56f
+        );
+        let mut digest = [0; 32];
+        assert_eq!(
+            keccak_f800_short_with_digest(&header_hash, nonce, &result, &mut digest),
+            0x5403d42d694d6e73
+        );
+        assert_eq!(digest.to_vec(), long);
+        let mut out = [0xff; 32];
+        keccak_f800_long_into(&header_hash, nonce, &result, &mut out);
+        assert_eq!(out, digest);
+
+        // Absorbing XORs, and permuting rewinds the position.
+        let mut twice = KeccakF800State::new();
+        twice.absorb_words(&[5]).permute().absorb_words(&[5]);
+        let mut once = KeccakF800State::new();
+        once.absorb_words(&[5]).permute();
+        assert_eq!(twice.words()[0], once.words()[0] ^ 5);
+    }
+
+    #[test]
+    fn test_padding_fills_the_rest_of_the_state() {
+        let mut zero = KeccakF800State::new();
+        zero.absorb_words(&[1, 2]).pad(Padding::Zero);
+        let mut plain = KeccakF800State::new();
+        plain.absorb_words(&[1, 2]);
+        assert_eq!(zero.words(), plain.words());
+
+        // KawPoW's seed pass pads after ten words, its final pass after 16.
+        let mut seed = KeccakF800State::new();
+        seed.absorb_words(&[7; 10]).pad(Padding::Kawpow);
+        assert_eq!(seed.words()[10..], RAVENCOIN_KAWPOW);
+        let mut last = KeccakF800State::new();
+        last.absorb_words(&[7; 16]).pad(Padding::Kawpow);
+        assert_eq!(last.words()[16..], RAVENCOIN_KAWPOW[..9]);
+        assert_eq!(RAVENCOIN_KAWPOW.map(|c| c as u8), *b"rAVENCOINKAWPOW");
+
+        // ProgPoW 0.9.4 pads words 10 and 18 of its seed pass, and words 16
+        // and 24 of its final pass.
+        let mut seed = KeccakF800State::new();
+        seed.absorb_words(&[0; 10]).pad(Padding::Progpow094);
+        let mut expected = [0; 25];
+        (expected[10], expected[18]) = (0x00000001, 0x80008081);
+        assert_eq!(*seed.words(), expected);
+        let mut last = KeccakF800State::new();
+        last.absorb_words(&[0; 16]).pad(Padding::Progpow094);
+        let mut expected = [0; 25];
+        (expected[16], expected[24]) = (0x00000001, 0x80008081);
+        assert_eq!(*last.words(), expected);
+    }
+}
@@ -0,0 +1,115 @@
+use crate::keccak::f800state::{KeccakF800State, Padding};
+
+/// Computes KawPoW's seed pass over a header hash and nonce.
+///
+/// This absorbs the `header_hash` and `nonce` into a fresh
+/// [`KeccakF800State`], fills the remaining 15 words with the KawPoW padding,
+/// permutes it once, and returns the first 8 words. The first two seed the
+/// program's random state, and all 8 are absorbed again by
+/// [`kawpow_final`].
+///
+/// # Arguments
+///
+/// * `header_hash` - A byte slice representing the header hash (32 bytes expected).
+/// * `nonce` - A 64-bit nonce value.
+///
+/// # Returns
+///
+/// The first 8 words of the permuted state.
+pub fn kawpow_seed(header_hash: &[u8], nonce: u64) -> [u32; 8] {
+    seed_pass(header_hash, nonce, Padding::Kawpow)
+}
+
+/// Computes KawPoW's final hash from its seed pass and mix hash.
+///
+/// This absorbs the 8 `seed` words from [`kawpow_seed`] and the 32-byte
+/// `mix_hash`, fills the remaining 9 words with the KawPoW padding, permutes
+/// once, and squeezes the first 8 words.
+///
+/// # Arguments
+///
+/// * `seed` - The words returned by [`kawpow_seed`].
+/// * `mix_hash` - A byte slice representing the mix hash (32 bytes expected).
+///
+/// # Returns
+///
+/// The 32-byte final hash.
+pub fn kawpow_final(seed: &[u32; 8], mix_hash: &[u8]) -> [u8; 32] {
+    final_pass(seed, mix_hash, Padding::Kawpow)
+}
+
+/// Computes the seed pass of [`kawpow_seed`] with any `padding`.
+///
+/// With [`Padding::Progpow094`] this is the seed pass of ProgPoW 0.9.4,
+/// which FiroPoW uses.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip_all, fields(nonce))
+)]
+pub fn seed_pass(header_hash: &[u8], nonce: u64, padding: Padding) -> [u32; 8] {
+    let mut state = KeccakF800State::new();
+    state
+        .absorb_bytes(&header_hash[..32])
+        .absorb_u64(nonce)
+        .pad(padding)
+        .permute();
+    state.squeeze(8).try_into().unwrap()
+}
+
+/// Computes the final pass of [`kawpow_final`] with any `padding`.
+#[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all))]
+pub fn final_pass(seed: &[u32; 8], mix_hash: &[u8], padding: Padding) -> [u8; 32] {
+    let mut state = KeccakF800State::new();
+    state
+        .absorb_words(seed)
+        .absorb_bytes(&mix_hash[..32])
+        .pad(padding)
+        .permute();
+    state.squeeze_bytes(8).try_into().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+    use crate::keccak::f800short::keccak_f800_short;
+    use crate::keccak::f800state::RAVENCOIN_KAWPOW;
+
+    #[test]
+    fn test_kawpow_passes_pad_with_ravencoin_words() {
+        let header_hash: Vec<u8> = (0..32).collect();
+        let nonce = 0x123456789abcdef0;
+
+        // The seed pass is a plain permutation of the padded state.
+        let mut expected = KeccakF800State::new();
+        expected
+            .absorb_bytes(&header_hash)
+            .absorb_u64(nonce)
+            .absorb_words(&RAVENCOIN_KAWPOW)
+            .permute();
+        let seed = kawpow_seed(&header_hash, nonce);
+        assert_eq!(seed, expected.squeeze(8));
+        // The padding changes the hash: ProgPoW's seed differs.
+        let progpow = keccak_f800_short(&header_hash, nonce, &[0; 8]);
+        assert_ne!(
+            ((seed[0].swap_bytes() as u64) << 32) | seed[1].swap_bytes() as u64,
+            progpow
+        );
+
+        let final_hash = kawpow_final(&seed, &[0xab; 32]);
+        let mut expected = KeccakF800State::new();
+        expected
+            .absorb_words(&seed)
+            .absorb_bytes(&[0xab; 32])
+            .absorb_words(&RAVENCOIN_KAWPOW[..9])
+            .permute();
+        assert_eq!(final_hash.to_vec(), expected.squeeze_bytes(8));
+        // A regression value computed by this implementation, not a
+        // reference vector. Published vectors are checked over whole hashes
+        // by `test_kawpow_vectors` in `progpow::kawpow`.
+        assert_eq!(
+            hex::encode(&final_hash),
+            "2b1e38148124dc69df05d3bee8c64d356fdb283a4c1cce14dc492149acf47d31"
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use std::ops::{BitAnd, BitXor, BitXorAssign, Not};
+
+/// Rho offsets for rotation, before reduction modulo the lane width.
+const KECCAKF_ROTC: [u32; 24] = [
+    1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14, 27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44,
+];
+
+/// Pi lane mappings.
+const KECCAKF_PILN: [usize; 24] = [
+    10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4, 15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1,
+];
+
+/// A Keccak-f lane: the word type of one of the 25 state positions.
+///
+/// The lane width fixes the permutation: `u32` lanes give Keccak-f800 and
+/// `u64` lanes Keccak-f1600. Each width carries its own round constants and
+/// rotation offsets; the Pi mapping is the same for all of them.
+pub trait KeccakLane:
+    Copy + Default + BitAnd<Output = Self> + BitXor<Output = Self> + BitXorAssign + Not<Output = Self>
+{
+    /// The `Iota` round constants, truncated to the lane width.
+    const ROUND_CONSTANTS: [Self; 24];
+
+    /// The `Rho` rotation offsets, reduced modulo the lane width.
+    const ROTATIONS: [u32; 24];
+
+    /// The lane width in bytes.
+    const BYTES: usize;
+
+    /// Rotates the lane left by `n` bits.
+    fn rotl(self, n: u32) -> Self;
+
+    /// Reads a lane from its first [`Self::BYTES`] little-endian bytes.
+    fn from_le_slice(bytes: &[u8]) -> Self;
+
+    /// Writes the lane's little-endian bytes into `out`, truncated to its
+    /// length.
+    fn write_le(self, out: &mut [u8]);
+}
+
+impl KeccakLane for u32 {
+    const ROUND_CONSTANTS: [u32; 24] = truncate(u64::ROUND_CONSTANTS);
+    const ROTATIONS: [u32; 24] = reduce(32);
+    const BYTES: usize = 4;
+
+    fn rotl(self, n: u32) -> u32 {
+        self.rotate_left(n)
+    }
+
+    fn from_le_slice(bytes: &[u8]) -> u32 {
+        u32::from_le_bytes(bytes[..4].try_into().unwrap())
+    }
+
+    fn write_le(self, out: &mut [u8]) {
+        let len = out.len();
+        out.copy_from_slice(&self.to_le_bytes()[..len]);
+    }
+}
+
+impl KeccakLane for u64 {
+    const ROUND_CONSTANTS: [u64; 24] = [
+        0x0000000000000001,
+        0x0000000000008082,
+        0x800000000000808a,
+        0x8000000080008000,
+        0x000000000000808b,
+        0x0000000080000001,
+        0x8000000080008081,
+        0x8000000000008009,
+        0x000000000000008a,
+        0x0000000000000088,
+        0x0000000080008009,
+        0x000000008000000a,
+        0x000000008000808b,
+        0x800000000000008b,
+        0x8000000000008089,
+        0x8000000000008003,
+        0x8000000000008002,
+        0x8000000000000080,
+        0x000000000000800a,
+        0x800000008000000a,
+        0x8000000080008081,
+        0x8000000000008080,
+        0x0000000080000001,
+        0x8000000080008008,
+    ];
+    const ROTATIONS: [u32; 24] = reduce(64);
+    const BYTES: usize = 8;
+
+    fn rotl(self, n: u32) -> u64 {
+        self.rotate_left(n)
+    }
+
+    fn from_le_slice(bytes: &[u8]) -> u64 {
+        u64::from_le_bytes(bytes[..8].try_into().unwrap())
+    }
+
+    fn write_le(self, out: &mut [u8]) {
+        let len = out.len();
+        out.copy_from_slice(&self.to_le_bytes()[..len]);
+    }
+}
+
+/// Truncates the f1600 round constants to 32-bit lanes.
+const fn truncate(constants: [u64; 24]) -> [u32; 24] {
+    let mut out = [0; 24];
+    let mut i = 0;
+    while i < 24 {
+        out[i] = constants[i] as u32;
+        i += 1;
+    }
+    out
+}
+
+/// Reduces the rotation offsets modulo a lane width of `bits`.
+const fn reduce(bits: u32) -> [u32; 24] {
+    let mut out = [0; 24];
+    let mut i = 0;
+    while i < 24 {
+        out[i] = KECCAKF_ROTC[i] % bits;
+        i += 1;
+    }
+    out
+}
+
+/// Keccak-f permutation round function, for any lane width.
+///
+/// This function performs a single round of the permutation on the state
+/// array. It applies the `Theta`, `Rho`, `Pi`, and `Chi` transformations,
+/// followed by the round constant addition.
+///
+/// # Arguments
+///
+/// * `st` - A mutable reference to the 25-lane state array.
+/// * `r` - The round index (0-23), used to select the round constant.
+pub fn keccak_f_round<L: KeccakLane>(st: &mut [L; 25], r: usize) {
+    let mut bc = [L::default(); 5]; // Temporary array for column parity calculations.
+
+    // Theta step: Mix each column based on the XOR of all other columns.
+    for i in 0..5 {
+        bc[i] = st[i] ^ st[i + 5] ^ st[i + 10] ^ st[i + 15] ^ st[i + 20];
+    }
+    for i in 0..5 {
+        let t = bc[(i + 4) % 5] ^ bc[(i + 1) % 5].rotl(1);
+        for j in (0..25).step_by(5) {
+            st[j + i] ^= t;
+        }
+    }
+
+    // Rho and Pi steps: Rotate and rearrange lanes.
+    let mut t = st[1];
+    for (i, &j) in KECCAKF_PILN.iter().enumerate() {
+        bc[0] = st[j];
+        st[j] = t.rotl(L::ROTATIONS[i]);
+        t = bc[0];
+    }
+
+    // Chi step: Nonlinear mixing of rows.
+    for j in (0..25).step_by(5) {
+        bc.copy_from_slice(&st[j..j + 5]);
+        for i in 0..5 {
+            st[j + i] ^= !bc[(i + 1) % 5] & bc[(i + 2) % 5];
+        }
+    }
+
+    // Iota step: Add the round constant to the first lane.
+    st[0] ^= L::ROUND_CONSTANTS[r];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Rho offsets indexed `[x][y]`, as the Keccak reference lists them.
+    const RHO: [[u32; 5]; 5] = [
+        [0, 36, 3, 41, 18],
+        [1, 44, 10, 45, 2],
+        [62, 6, 43, 15, 61],
+        [28, 55, 25, 21, 56],
+        [27, 20, 39, 8, 14],
+    ];
+
+    /// One round as the Keccak reference specifies it, on `A[x + 5y]`.
+    fn reference_round<L: KeccakLane>(a: &[L; 25], r: usize, bits: u32) -> [L; 25] {
+        let c: [L; 5] =
+            std::array::from_fn(|x| a[x] ^ a[x + 5] ^ a[x + 10] ^ a[x + 15] ^ a[x + 20]);
+        let d: [L; 5] = std::array::from_fn(|x| c[(x + 4) % 5] ^ c[(x + 1) % 5].rotl(1));
+        let mut b = [L::default(); 25];
+        for x in 0..5 {
+            for y in 0..5 {
+                b[y + 5 * ((2 * x + 3 * y) % 5)] = (a[x + 5 * y] ^ d[x]).rotl(RHO[x][y] % bits);
+            }
+        }
+        let mut out: [L; 25] = std::array::from_fn(|i| {
+            b[i] ^ (!b[(i + 1) % 5 + i / 5 * 5] & b[(i + 2) % 5 + i / 5 * 5])
+        });
+        out[0] ^= L::ROUND_CONSTANTS[r];
+        out
+    }
+
+    proptest! {
+        #[test]
+        fn prop_f1600_round_matches_reference(st: [u64; 25], r in 0usize..24) {
+            let mut ours = st;
+            keccak_f_round(&mut ours, r);
+            prop_assert_eq!(ours, reference_round(&st, r, 64));
+        }
+
+        #[test]
+        fn prop_f800_round_matches_reference(st: [u32; 25], r in 0usize..22) {
+            let mut ours = st;
+            keccak_f_round(&mut ours, r);
+            prop_assert_eq!(ours, reference_round(&st, r, 32));
+        }
+
+        #[test]
+        fn prop_round_is_linear_in_iota(st: [u32; 25], r in 0usize..22) {
+            // Iota is the only step that depends on the round: two rounds
+            // of the same state differ only in the first lane's constant.
+            let (mut a, mut b) = (st, st);
+            keccak_f_round(&mut a, r);
+            keccak_f_round(&mut b, 0);
+            prop_assert_eq!(a[0] ^ b[0], u32::ROUND_CONSTANTS[r] ^ u32::ROUND_CONSTANTS[0]);
+            prop_assert_eq!(&a[1..], &b[1..]);
+        }
+    }
+
+    #[test]
+    fn test_zero_state_round_is_the_round_constant() {
+        for r in 0..24 {
+            let mut st = [0u64; 25];
+            keccak_f_round(&mut st, r);
+            assert_eq!(st[0], u64::ROUND_CONSTANTS[r]);
+            assert!(st[1..].iter().all(|&lane| lane == 0));
+        }
+    }
+
+    #[test]
+    fn test_narrow_lanes_use_truncated_tables() {
+        assert_eq!(u32::ROUND_CONSTANTS[2], 0x0000808a);
+        assert_eq!(u32::ROUND_CONSTANTS[23], 0x80008008);
+        assert_eq!(u32::ROTATIONS[7], 4);
+        assert_eq!(u64::ROTATIONS[7], 36);
+
+        // A 32-bit rotation by the unreduced offset is the same rotation,
+        // which is what the original f800 round relied on.
+        for (&offset, &reduced) in KECCAKF_ROTC.iter().zip(&u32::ROTATIONS) {
+            assert_eq!(
+                0x12345678u32.rotate_left(offset),
+                0x12345678u32.rotl(reduced)
+            );
+        }
+    }
+}
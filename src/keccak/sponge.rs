@@ -0,0 +1,149 @@
+use crate::keccak::backend::{keccak_f, Permute};
+
+/// The largest state, in bytes: 25 lanes of Keccak-f1600.
+const MAX_STATE_BYTES: usize = 200;
+
+/// A Keccak sponge over any Keccak-f permutation, absorbing input of any
+/// length.
+///
+/// The state splits into a `rate` of bytes that input is XORed into and a
+/// capacity that only the permutation touches. Input is absorbed a block of
+/// `rate` bytes at a time and may arrive in pieces of any size;
+/// [`Sponge::finalize`] pads the last block with Keccak's original `pad10*1`
+/// rule (a `0x01` byte after the input and `0x80` in the last rate byte) and
+/// squeezes as many bytes as asked, permuting again between blocks.
+///
+/// `keccak256` and `keccak512` are this sponge over Keccak-f1600 with rates
+/// of 136 and 72 bytes. Over Keccak-f800 it hashes inputs longer than the
+/// fixed single-permutation layouts ProgPoW uses.
+#[derive(Clone, Debug)]
+pub struct Sponge<L: Permute> {
+    state: [L; 25],
+    rate: usize,
+    rounds: usize,
+    block: [u8; MAX_STATE_BYTES],
+    filled: usize,
+}
+
+impl<L: Permute> Sponge<L> {
+    /// Creates an empty sponge absorbing `rate` bytes per block and
+    /// permuting with `rounds` rounds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` is zero, not a whole number of lanes, or leaves no
+    /// capacity, or if `rounds` exceeds 24.
+    pub fn new(rate: usize, rounds: usize) -> Self {
+        assert!(
+            rate > 0 && rate.is_multiple_of(L::BYTES) && rate < 25 * L::BYTES,
+            "invalid sponge rate {rate}"
+        );
+        assert!(rounds <= 24, "Keccak-f has at most 24 rounds");
+        Sponge {
+            state: [L::default(); 25],
+            rate,
+            rounds,
+            block: [0; MAX_STATE_BYTES],
+            filled: 0,
+        }
+    }
+
+    /// Returns the rate in bytes.
+    pub fn rate(&self) -> usize {
+        self.rate
+    }
+
+    /// Returns the capacity in bytes, the state bytes input never reaches.
+    pub fn capacity(&self) -> usize {
+        25 * L::BYTES - self.rate
+    }
+
+    /// Absorbs `data`, permuting after every full block.
+    pub fn update(&mut self, mut data: &[u8]) -> &mut Self {
+        while !data.is_empty() {
+            let take = data.len().min(self.rate - self.filled);
+            self.block[self.filled..self.filled + take].copy_from_slice(&data[..take]);
+            self.filled += take;
+            data = &data[take..];
+            if self.filled == self.rate {
+                self.absorb_block();
+            }
+        }
+        self
+    }
+
+    /// Pads the input and fills `out` with the squeezed output.
+    pub fn finalize(mut self, out: &mut [u8]) {
+        self.block[self.filled..self.rate].fill(0);
+        self.block[self.filled] ^= 0x01;
+        self.block[self.rate - 1] ^= 0x80;
+        self.absorb_block();
+
+        let mut chunks = out.chunks_mut(self.rate).peekable();
+        while let Some(chunk) = chunks.next() {
+            for (bytes, lane) in chunk.chunks_mut(L::BYTES).zip(self.state) {
+                lane.write_le(bytes);
+            }
+            if chunks.peek().is_some() {
+                keccak_f(&mut self.state, self.rounds);
+            }
+        }
+    }
+
+    /// XORs the rate-sized block into the state and permutes it.
+    fn absorb_block(&mut self) {
+        for (lane, bytes) in self
+            .state
+            .iter_mut()
+            .zip(self.block[..self.rate].chunks_exact(L::BYTES))
+        {
+            *lane ^= L::from_le_slice(bytes);
+        }
+        keccak_f(&mut self.state, self.rounds);
+        self.filled = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+    use crate::keccak::{keccak256, keccak512};
+
+    #[test]
+    fn test_streaming_matches_one_shot() {
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        for len in [0, 1, 135, 136, 137, 272, 1000] {
+            let mut out = [0; 32];
+            let mut sponge = Sponge::<u64>::new(136, 24);
+            for piece in data[..len].chunks(7) {
+                sponge.update(piece);
+            }
+            sponge.finalize(&mut out);
+            assert_eq!(out, keccak256(&data[..len]), "length {len}");
+        }
+        assert_eq!(Sponge::<u64>::new(72, 24).capacity(), 128);
+
+        // Squeezing past the rate permutes between blocks.
+        let mut long = [0; 100];
+        let mut sponge = Sponge::<u64>::new(72, 24);
+        sponge.update(b"abc");
+        sponge.finalize(&mut long);
+        assert_eq!(long[..64], keccak512(b"abc"));
+        assert_ne!(long[64..], [0; 36]);
+    }
+
+    #[test]
+    fn test_f800_sponge_hashes_long_inputs() {
+        let header: Vec<u8> = (0..100).collect();
+        let mut out = [0; 32];
+        let mut sponge = Sponge::<u32>::new(64, 22);
+        sponge.update(&header[..40]).update(&header[40..]);
+        assert_eq!(sponge.capacity(), 36);
+        sponge.finalize(&mut out);
+        assert_eq!(
+            hex::encode(&out),
+            "be0c24f6cc530ef0342bc37dbc412325a8080dc0285e4154ee1e409d8cc93bca"
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use crate::kernelgen::source::{parameters_source, program_body_source, KernelConfig};
+use crate::progpow::program::Program;
+
+/// Generates the CUDA C source of `progPowLoop` for a period.
+///
+/// Each group of `PROGPOW_LANES` consecutive threads forms one hash, with the
+/// lane taken from `threadIdx.x` and the global load address broadcast through
+/// a warp shuffle, matching the reference CUDA miner.
+///
+/// # Arguments
+///
+/// * `period` - The program seed, i.e. `block_number / PROGPOW_PERIOD_LENGTH`.
+/// * `config` - Kernel settings; only `dag_elements` applies to CUDA.
+///
+/// # Returns
+///
+/// The CUDA C source as a `String`.
+pub fn cuda_kernel(period: u64, config: &KernelConfig) -> String {
+    cuda_kernel_for_program(&Program::generate(period), config)
+}
+
+/// Generates the CUDA C source of `progPowLoop` for an already decoded program.
+pub fn cuda_kernel_for_program(program: &Program, config: &KernelConfig) -> String {
+    let mut out = String::new();
+
+    out.push_str("typedef unsigned int       uint32_t;\n");
+    out.push_str("typedef unsigned long long uint64_t;\n");
+    out.push_str("#if __CUDA_ARCH__ < 350\n");
+    out.push_str("#define ROTL32(x,n) (((x) << (n % 32)) | ((x) >> (32 - (n % 32))))\n");
+    out.push_str("#define ROTR32(x,n) (((x) >> (n % 32)) | ((x) << (32 - (n % 32))))\n");
+    out.push_str("#else\n");
+    out.push_str("#define ROTL32(x,n) __funnelshift_l((x), (x), (n))\n");
+    out.push_str("#define ROTR32(x,n) __funnelshift_r((x), (x), (n))\n");
+    out.push_str("#endif\n");
+    out.push_str("#define min(a,b) ((a<b) ? a : b)\n");
+    out.push_str("#define mul_hi(a, b) __umulhi(a, b)\n");
+    out.push_str("#define clz(a) __clz(a)\n");
+    out.push_str("#define popcount(a) __popc(a)\n");
+    out.push('\n');
+    out.push_str("#define DEV_INLINE __device__ __forceinline__\n");
+    out.push_str("#if (__CUDACC_VER_MAJOR__ > 8)\n");
+    out.push_str("#define SHFL(x, y, z) __shfl_sync(0xFFFFFFFF, (x), (y), (z))\n");
+    out.push_str("#else\n");
+    out.push_str("#define SHFL(x, y, z) __shfl((x), (y), (z))\n");
+    out.push_str("#endif\n");
+    out.push('\n');
+    out.push_str(&parameters_source(config));
+
+    out.push_str("typedef struct __align__(16) {uint32_t s[PROGPOW_DAG_LOADS];} dag_t;\n");
+    out.push('\n');
+    out.push_str(&format!("// Inner loop for prog_seed {}\n", program.period));
+    out.push_str("__device__ __forceinline__ void progPowLoop(const uint32_t loop,\n");
+    out.push_str("        uint32_t mix[PROGPOW_REGS],\n");
+    out.push_str("        const dag_t *g_dag,\n");
+    out.push_str("        const uint32_t c_dag[PROGPOW_CACHE_WORDS],\n");
+    out.push_str("        const bool hack_false)\n");
+    out.push_str("{\n");
+    out.push_str("dag_t data_dag;\n");
+    out.push_str("uint32_t offset, data;\n");
+    out.push_str("const uint32_t lane_id = threadIdx.x & (PROGPOW_LANES-1);\n");
+
+    // All lanes share the global load address taken from one lane's mix[0].
+    out.push_str("// global load to sequential locations\n");
+    out.push_str("offset = SHFL(mix[0], loop%PROGPOW_LANES, PROGPOW_LANES);\n");
+    out.push_str("offset %= PROGPOW_DAG_ELEMENTS;\n");
+    out.push_str("offset = offset * PROGPOW_LANES + (lane_id ^ loop) % PROGPOW_LANES;\n");
+    out.push_str("data_dag = g_dag[offset];\n");
+    out.push_str("// hack to prevent compiler from reordering LD and usage\n");
+    out.push_str("if (hack_false) __threadfence_block();\n");
+
+    out.push_str(&program_body_source(program));
+
+    out.push_str("}\n");
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernelgen::opencl::opencl_kernel;
+
+    #[test]
+    fn test_cuda_kernel_shares_program_with_opencl() {
+        let config = KernelConfig::for_dataset_size(1024);
+        let cuda = cuda_kernel(7, &config);
+        let opencl = opencl_kernel(7, &config);
+
+        assert!(cuda.contains("SHFL(mix[0], loop%PROGPOW_LANES, PROGPOW_LANES)"));
+        assert!(cuda.contains("threadIdx.x & (PROGPOW_LANES-1)"));
+        assert_eq!(cuda.matches('{').count(), cuda.matches('}').count());
+
+        // Both targets unroll the same random statements in the same order.
+        let body = |source: &str| -> Vec<String> {
+            let start = source.find("// cache load 0").unwrap();
+            source[start..]
+                .lines()
+                .take_while(|line| !line.starts_with("for(") && *line != "}")
+                .map(String::from)
+                .collect()
+        };
+        assert_eq!(body(&cuda), body(&opencl));
+    }
+}
@@ -0,0 +1,169 @@
+use crate::ethash::dataset::{DATASET_PARENTS, HASH_WORDS};
+
+/// The DAG generation kernel, written against a few macros so the same code
+/// compiles as OpenCL C and as CUDA C.
+///
+/// Every work-item computes one 64-byte dataset item: `g_dag` receives item
+/// `start + id` at the same position, and ids at or past `dag_items` exit.
+const DAG_KERNEL_BODY: &str = r#"CONSTANT uint64_t keccakf_rndc64[24] = {
+    0x0000000000000001UL, 0x0000000000008082UL, 0x800000000000808aUL, 0x8000000080008000UL,
+    0x000000000000808bUL, 0x0000000080000001UL, 0x8000000080008081UL, 0x8000000000008009UL,
+    0x000000000000008aUL, 0x0000000000000088UL, 0x0000000080008009UL, 0x000000008000000aUL,
+    0x000000008000808bUL, 0x800000000000008bUL, 0x8000000000008089UL, 0x8000000000008003UL,
+    0x8000000000008002UL, 0x8000000000000080UL, 0x000000000000800aUL, 0x800000008000000aUL,
+    0x8000000080008081UL, 0x8000000000008080UL, 0x0000000080000001UL, 0x8000000080008008UL
+};
+CONSTANT uint32_t keccakf_rotc64[24] = {
+    1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14, 27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44
+};
+CONSTANT uint32_t keccakf_piln64[24] = {
+    10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4, 15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1
+};
+
+#define ROTL64(x, n) (((x) << (n)) | ((x) >> (64 - (n))))
+#define FNV(a, b) (((a) * 0x01000193U) ^ (b))
+
+// Keccak-512 of 16 little-endian words, in place.
+DEVICE void keccak512_words(uint32_t words[HASH_WORDS])
+{
+    uint64_t st[25], bc[5], t;
+    for (int i = 0; i < 25; i++)
+        st[i] = 0;
+    for (int i = 0; i < 8; i++)
+        st[i] = (uint64_t)words[2 * i] | ((uint64_t)words[2 * i + 1] << 32);
+    // A 64-byte message pads to a single 72-byte block.
+    st[8] = 0x8000000000000001UL;
+
+    for (int r = 0; r < 24; r++) {
+        for (int i = 0; i < 5; i++)
+            bc[i] = st[i] ^ st[i + 5] ^ st[i + 10] ^ st[i + 15] ^ st[i + 20];
+        for (int i = 0; i < 5; i++) {
+            t = bc[(i + 4) % 5] ^ ROTL64(bc[(i + 1) % 5], 1);
+            for (int j = 0; j < 25; j += 5)
+                st[j + i] ^= t;
+        }
+        t = st[1];
+        for (int i = 0; i < 24; i++) {
+            int j = keccakf_piln64[i];
+            bc[0] = st[j];
+            st[j] = ROTL64(t, keccakf_rotc64[i]);
+            t = bc[0];
+        }
+        for (int j = 0; j < 25; j += 5) {
+            for (int i = 0; i < 5; i++)
+                bc[i] = st[j + i];
+            for (int i = 0; i < 5; i++)
+                st[j + i] ^= (~bc[(i + 1) % 5]) & bc[(i + 2) % 5];
+        }
+        st[0] ^= keccakf_rndc64[r];
+    }
+
+    for (int i = 0; i < 8; i++) {
+        words[2 * i] = (uint32_t)st[i];
+        words[2 * i + 1] = (uint32_t)(st[i] >> 32);
+    }
+}
+
+KERNEL void ethash_calculate_dag_item(const uint32_t start,
+        GLOBAL const uint32_t *g_cache,
+        const uint32_t cache_rows,
+        GLOBAL uint32_t *g_dag,
+        const uint32_t dag_items)
+{
+    const uint32_t index = start + GLOBAL_ID;
+    if (index >= dag_items)
+        return;
+
+    uint32_t mix[HASH_WORDS];
+    const uint32_t first = (index % cache_rows) * HASH_WORDS;
+    for (int i = 0; i < HASH_WORDS; i++)
+        mix[i] = g_cache[first + i];
+    mix[0] ^= index;
+    keccak512_words(mix);
+
+    for (uint32_t i = 0; i < DATASET_PARENTS; i++) {
+        const uint32_t parent = FNV(index ^ i, mix[i % HASH_WORDS]) % cache_rows;
+        for (int w = 0; w < HASH_WORDS; w++)
+            mix[w] = FNV(mix[w], g_cache[parent * HASH_WORDS + w]);
+    }
+    keccak512_words(mix);
+
+    for (int i = 0; i < HASH_WORDS; i++)
+        g_dag[(uint64_t)index * HASH_WORDS + i] = mix[i];
+}
+"#;
+
+/// Renders the `#define`s for the ethash parameters used by the DAG kernel.
+fn dag_parameters_source() -> String {
+    format!(
+        "#define HASH_WORDS              {HASH_WORDS}\n\
+         #define DATASET_PARENTS         {DATASET_PARENTS}\n\n"
+    )
+}
+
+/// Generates the OpenCL source of the `ethash_calculate_dag_item` kernel.
+///
+/// Each work-item computes one 64-byte dataset item from the light cache, so
+/// a whole DAG is a handful of dispatches instead of minutes of CPU work.
+///
+/// The kernel takes the first item index of the dispatch, the cache, its
+/// number of 64-byte rows, the output DAG buffer, and the total item count.
+///
+/// # Returns
+///
+/// The OpenCL C source as a `String`.
+pub fn opencl_dag_kernel() -> String {
+    let mut out = String::new();
+    out.push_str("typedef unsigned int       uint32_t;\n");
+    out.push_str("typedef unsigned long      uint64_t;\n");
+    out.push_str("#define CONSTANT __constant\n");
+    out.push_str("#define DEVICE\n");
+    out.push_str("#define KERNEL __kernel\n");
+    out.push_str("#define GLOBAL __global\n");
+    out.push_str("#define GLOBAL_ID ((uint32_t)get_global_id(0))\n");
+    out.push('\n');
+    out.push_str(&dag_parameters_source());
+    out.push_str(DAG_KERNEL_BODY);
+    out
+}
+
+/// Generates the CUDA source of the `ethash_calculate_dag_item` kernel.
+///
+/// The kernel is the same as [`opencl_dag_kernel`], with the item index taken
+/// from the block and thread indices.
+///
+/// # Returns
+///
+/// The CUDA C source as a `String`.
+pub fn cuda_dag_kernel() -> String {
+    let mut out = String::new();
+    out.push_str("typedef unsigned int       uint32_t;\n");
+    out.push_str("typedef unsigned long long uint64_t;\n");
+    out.push_str("#define CONSTANT __constant__ const\n");
+    out.push_str("#define DEVICE __device__ __forceinline__\n");
+    out.push_str("#define KERNEL extern \"C\" __global__\n");
+    out.push_str("#define GLOBAL\n");
+    out.push_str("#define GLOBAL_ID (blockIdx.x * blockDim.x + threadIdx.x)\n");
+    out.push('\n');
+    out.push_str(&dag_parameters_source());
+    out.push_str(DAG_KERNEL_BODY);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dag_kernels_share_body() {
+        let opencl = opencl_dag_kernel();
+        let cuda = cuda_dag_kernel();
+
+        let body = |source: &str| source[source.find("CONSTANT uint64_t").unwrap()..].to_string();
+        assert_eq!(body(&opencl), body(&cuda));
+        assert!(opencl.contains("#define DATASET_PARENTS         256\n"));
+        assert!(opencl.contains("get_global_id(0)"));
+        assert!(cuda.contains("blockIdx.x * blockDim.x + threadIdx.x"));
+        assert_eq!(opencl.matches('{').count(), opencl.matches('}').count());
+    }
+}
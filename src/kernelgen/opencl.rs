@@ -0,0 +1,359 @@
+use crate::kernelgen::source::{parameters_source, program_body_source, KernelConfig};
+use crate::progpow::program::Program;
+
+/// Generates the OpenCL source of `progPowLoop` for a period.
+///
+/// The output follows the layout of the reference generator: a preamble with
+/// the helper macros and parameters, then `progPowLoop` with the period's
+/// random cache loads, math, and merges fully unrolled. The host is expected to
+/// include it ahead of the search kernel.
+///
+/// # Arguments
+///
+/// * `period` - The program seed, i.e. `block_number / PROGPOW_PERIOD_LENGTH`.
+/// * `config` - Kernel settings such as the work-group size.
+///
+/// # Returns
+///
+/// The OpenCL C source as a `String`.
+pub fn opencl_kernel(period: u64, config: &KernelConfig) -> String {
+    opencl_kernel_for_program(&Program::generate(period), config)
+}
+
+/// Generates the OpenCL source of `progPowLoop` for an already decoded program.
+pub fn opencl_kernel_for_program(program: &Program, config: &KernelConfig) -> String {
+    let mut out = String::new();
+
+    out.push_str("#ifndef GROUP_SIZE\n");
+    out.push_str(&format!("#define GROUP_SIZE {}\n", config.group_size));
+    out.push_str("#endif\n");
+    out.push_str("#define GROUP_SHARE (GROUP_SIZE / PROGPOW_LANES)\n");
+    out.push('\n');
+    out.push_str("typedef unsigned int       uint32_t;\n");
+    out.push_str("typedef unsigned long      uint64_t;\n");
+    out.push_str("#define ROTL32(x, n) rotate((x), (uint32_t)(n))\n");
+    out.push_str("#define ROTR32(x, n) rotate((x), (uint32_t)(32-n))\n");
+    out.push('\n');
+    out.push_str(&parameters_source(config));
+
+    out.push_str(
+        "typedef struct __attribute__ ((aligned (16))) {uint32_t s[PROGPOW_DAG_LOADS];} dag_t;\n",
+    );
+    out.push('\n');
+    out.push_str(&format!("// Inner loop for prog_seed {}\n", program.period));
+    out.push_str("inline void progPowLoop(const uint32_t loop,\n");
+    out.push_str("        volatile uint32_t mix_arg[PROGPOW_REGS],\n");
+    out.push_str("        __global const dag_t *g_dag,\n");
+    out.push_str("        __local const uint32_t c_dag[PROGPOW_CACHE_WORDS],\n");
+    out.push_str("        __local uint64_t share[GROUP_SHARE],\n");
+    out.push_str("        const bool hack_false)\n");
+    out.push_str("{\n");
+    out.push_str("dag_t data_dag;\n");
+    out.push_str("uint32_t offset, data;\n");
+
+    // Copy out of the volatile argument to work around an AMD compiler bug.
+    out.push_str("uint32_t mix[PROGPOW_REGS];\n");
+    out.push_str("for(int i=0; i<PROGPOW_REGS; i++)\n");
+    out.push_str("    mix[i] = mix_arg[i];\n");
+    out.push_str("const uint32_t lane_id = get_local_id(0) & (PROGPOW_LANES-1);\n");
+    out.push_str("const uint32_t group_id = get_local_id(0) / PROGPOW_LANES;\n");
+
+    // All lanes share the global load address taken from one lane's mix[0].
+    out.push_str("// global load to sequential locations\n");
+    out.push_str("if(lane_id == (loop % PROGPOW_LANES))\n");
+    out.push_str("    share[group_id] = mix[0];\n");
+    out.push_str("barrier(CLK_LOCAL_MEM_FENCE);\n");
+    out.push_str("offset = share[group_id];\n");
+    // Without lockstep lanes, the next loop's writer could clobber the offset early.
+    out.push_str("barrier(CLK_LOCAL_MEM_FENCE);\n");
+    out.push_str("offset %= PROGPOW_DAG_ELEMENTS;\n");
+    out.push_str("offset = offset * PROGPOW_LANES + (lane_id ^ loop) % PROGPOW_LANES;\n");
+    out.push_str("data_dag = g_dag[offset];\n");
+    out.push_str("// hack to prevent compiler from reordering LD and usage\n");
+    out.push_str("if (hack_false) barrier(CLK_LOCAL_MEM_FENCE);\n");
+
+    out.push_str(&program_body_source(program));
+
+    out.push_str("for(int i=0; i<PROGPOW_REGS; i++)\n");
+    out.push_str("    mix_arg[i] = mix[i];\n");
+    out.push_str("}\n");
+    out.push('\n');
+    out
+}
+
+/// OpenCL helpers and the `progpow_hash` and `progpow_verify` entry points
+/// that drive `progPowLoop`.
+///
+/// Each group of `PROGPOW_LANES` work-items computes one hash. `progpow_hash`
+/// writes its 8-word mix hash followed by its 8-word final hash to `g_out`;
+/// `progpow_verify` checks it against a seal and flags failures in a bitmask.
+const OPENCL_HASH_KERNEL: &str = r#"#define FNV_PRIME 0x1000193
+#define FNV_OFFSET_BASIS 0x811c9dc5
+#define bswap32(x) as_uint(as_uchar4(x).s3210)
+
+__constant uint32_t keccakf_rndc[24] = {
+    0x00000001, 0x00008082, 0x0000808a, 0x80008000, 0x0000808b, 0x80000001, 0x80008081, 0x00008009,
+    0x0000008a, 0x00000088, 0x80008009, 0x8000000a, 0x8000808b, 0x0000008b, 0x00008089, 0x00008003,
+    0x00008002, 0x00000080, 0x0000800a, 0x8000000a, 0x80008081, 0x00008080, 0x80000001, 0x80008008
+};
+__constant uint32_t keccakf_rotc[24] = {
+    1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14, 27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44
+};
+__constant uint32_t keccakf_piln[24] = {
+    10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4, 15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1
+};
+
+void keccak_f800_round(uint32_t st[25], const int r)
+{
+    uint32_t t, bc[5];
+    for (int i = 0; i < 5; i++)
+        bc[i] = st[i] ^ st[i + 5] ^ st[i + 10] ^ st[i + 15] ^ st[i + 20];
+    for (int i = 0; i < 5; i++) {
+        t = bc[(i + 4) % 5] ^ ROTL32(bc[(i + 1) % 5], 1);
+        for (int j = 0; j < 25; j += 5)
+            st[j + i] ^= t;
+    }
+    t = st[1];
+    for (int i = 0; i < 24; i++) {
+        int j = keccakf_piln[i];
+        bc[0] = st[j];
+        st[j] = ROTL32(t, keccakf_rotc[i]);
+        t = bc[0];
+    }
+    for (int j = 0; j < 25; j += 5) {
+        for (int i = 0; i < 5; i++)
+            bc[i] = st[j + i];
+        for (int i = 0; i < 5; i++)
+            st[j + i] ^= (~bc[(i + 1) % 5]) & bc[(i + 2) % 5];
+    }
+    st[0] ^= keccakf_rndc[r];
+}
+
+void keccak_f800(uint32_t st[25], __global const uint32_t *header, const uint64_t value,
+        const uint32_t words[8])
+{
+    for (int i = 0; i < 25; i++)
+        st[i] = 0;
+    for (int i = 0; i < 8; i++)
+        st[i] = header[i];
+    st[8] = (uint32_t)value;
+    st[9] = (uint32_t)(value >> 32);
+    for (int i = 0; i < 8; i++)
+        st[10 + i] = words[i];
+    for (int r = 0; r < 22; r++)
+        keccak_f800_round(st, r);
+}
+
+uint32_t fnv1a(uint32_t *h, const uint32_t d)
+{
+    *h = (*h ^ d) * FNV_PRIME;
+    return *h;
+}
+
+typedef struct {
+    uint32_t z, w, jsr, jcong;
+} kiss99_t;
+
+uint32_t kiss99(kiss99_t *st)
+{
+    st->z = 36969 * (st->z & 65535) + (st->z >> 16);
+    st->w = 18000 * (st->w & 65535) + (st->w >> 16);
+    uint32_t mwc = (st->z << 16) + st->w;
+    st->jsr ^= (st->jsr << 17);
+    st->jsr ^= (st->jsr >> 13);
+    st->jsr ^= (st->jsr << 5);
+    st->jcong = 69069 * st->jcong + 1234567;
+    return (mwc ^ st->jcong) + st->jsr;
+}
+
+void fill_mix(const uint64_t seed, const uint32_t lane_id, uint32_t mix[PROGPOW_REGS])
+{
+    uint32_t fnv_hash = FNV_OFFSET_BASIS;
+    kiss99_t st;
+    st.z = fnv1a(&fnv_hash, (uint32_t)seed);
+    st.w = fnv1a(&fnv_hash, (uint32_t)(seed >> 32));
+    st.jsr = fnv1a(&fnv_hash, lane_id);
+    st.jcong = fnv1a(&fnv_hash, lane_id);
+    for (int i = 0; i < PROGPOW_REGS; i++)
+        mix[i] = kiss99(&st);
+}
+
+void load_c_dag(__local uint32_t c_dag[PROGPOW_CACHE_WORDS], __global const uint32_t *g_cdag)
+{
+    for (uint32_t word = get_local_id(0); word < PROGPOW_CACHE_WORDS; word += GROUP_SIZE)
+        c_dag[word] = g_cdag[word];
+    barrier(CLK_LOCAL_MEM_FENCE);
+}
+
+// Computes one hash per PROGPOW_LANES work-items; only lane 0 gets the result.
+void progpow_digest(__global const uint32_t *header,
+        const uint64_t nonce,
+        __global const dag_t *g_dag,
+        __local const uint32_t c_dag[PROGPOW_CACHE_WORDS],
+        __local uint64_t share[GROUP_SHARE],
+        __local uint32_t lane_hashes[GROUP_SIZE],
+        uint32_t digest[8],
+        uint32_t final_hash[8],
+        const bool hack_false)
+{
+    const uint32_t lid = get_local_id(0);
+    const uint32_t lane_id = lid & (PROGPOW_LANES - 1);
+    const uint32_t group_id = lid / PROGPOW_LANES;
+
+    uint32_t st[25];
+    const uint32_t zeros[8] = {0, 0, 0, 0, 0, 0, 0, 0};
+    keccak_f800(st, header, nonce, zeros);
+    const uint64_t seed = ((uint64_t)bswap32(st[0]) << 32) | bswap32(st[1]);
+
+    uint32_t mix[PROGPOW_REGS];
+    fill_mix(seed, lane_id, mix);
+    for (uint32_t l = 0; l < PROGPOW_CNT_DAG; l++)
+        progPowLoop(l, mix, g_dag, c_dag, share, hack_false);
+
+    uint32_t lane_hash = FNV_OFFSET_BASIS;
+    for (int i = 0; i < PROGPOW_REGS; i++)
+        fnv1a(&lane_hash, mix[i]);
+    lane_hashes[lid] = lane_hash;
+    barrier(CLK_LOCAL_MEM_FENCE);
+
+    if (lane_id == 0) {
+        for (int i = 0; i < 8; i++)
+            digest[i] = FNV_OFFSET_BASIS;
+        for (int l = 0; l < PROGPOW_LANES; l++)
+            fnv1a(&digest[l % 8], lane_hashes[group_id * PROGPOW_LANES + l]);
+        keccak_f800(st, header, seed, digest);
+        for (int i = 0; i < 8; i++)
+            final_hash[i] = st[i];
+    }
+}
+
+__kernel __attribute__((reqd_work_group_size(GROUP_SIZE, 1, 1)))
+void progpow_hash(__global const uint32_t *g_header,
+        const uint64_t start_nonce,
+        __global const dag_t *g_dag,
+        __global const uint32_t *g_cdag,
+        __global uint32_t *g_out,
+        const uint32_t hack_false)
+{
+    __local uint32_t c_dag[PROGPOW_CACHE_WORDS];
+    __local uint64_t share[GROUP_SHARE];
+    __local uint32_t lane_hashes[GROUP_SIZE];
+
+    const uint64_t hash_id = get_global_id(0) / PROGPOW_LANES;
+    load_c_dag(c_dag, g_cdag);
+
+    uint32_t digest[8], final_hash[8];
+    progpow_digest(g_header, start_nonce + hash_id, g_dag, c_dag, share, lane_hashes,
+            digest, final_hash, hack_false);
+
+    if ((get_local_id(0) & (PROGPOW_LANES - 1)) == 0) {
+        __global uint32_t *out = g_out + hash_id * 16;
+        for (int i = 0; i < 8; i++) {
+            out[i] = digest[i];
+            out[8 + i] = final_hash[i];
+        }
+    }
+}
+
+#define SEAL_WORDS 26
+
+// Each seal is the header hash, the nonce (low word first), the claimed mix
+// hash, and the boundary. Bit i of g_failed is set when seal i is invalid.
+__kernel __attribute__((reqd_work_group_size(GROUP_SIZE, 1, 1)))
+void progpow_verify(__global const uint32_t *g_seals,
+        const uint32_t seal_count,
+        __global const dag_t *g_dag,
+        __global const uint32_t *g_cdag,
+        __global volatile uint32_t *g_failed,
+        const uint32_t hack_false)
+{
+    __local uint32_t c_dag[PROGPOW_CACHE_WORDS];
+    __local uint64_t share[GROUP_SHARE];
+    __local uint32_t lane_hashes[GROUP_SIZE];
+
+    // Padding work-items re-check the last seal so every lane reaches the barriers.
+    const uint32_t hash_id = get_global_id(0) / PROGPOW_LANES;
+    const uint32_t seal_id = min(hash_id, seal_count - 1);
+    __global const uint32_t *seal = g_seals + seal_id * SEAL_WORDS;
+    load_c_dag(c_dag, g_cdag);
+
+    uint32_t digest[8], final_hash[8];
+    const uint64_t nonce = ((uint64_t)seal[9] << 32) | seal[8];
+    progpow_digest(seal, nonce, g_dag, c_dag, share, lane_hashes, digest, final_hash, hack_false);
+
+    if ((get_local_id(0) & (PROGPOW_LANES - 1)) == 0 && hash_id < seal_count) {
+        bool valid = true;
+        for (int i = 0; i < 8; i++)
+            valid = valid && digest[i] == seal[10 + i];
+        // Compare the final hash and boundary as big-endian 256-bit numbers.
+        int order = 0;
+        for (int i = 0; i < 8 && order == 0; i++) {
+            const uint32_t a = bswap32(final_hash[i]);
+            const uint32_t b = bswap32(seal[18 + i]);
+            order = (a > b) - (a < b);
+        }
+        if (!valid || order > 0)
+            atomic_or(&g_failed[seal_id / 32], 1u << (seal_id % 32));
+    }
+}
+"#;
+
+/// Generates a complete OpenCL program computing ProgPoW hashes for a period.
+///
+/// This is [`opencl_kernel`] followed by Keccak-f800, KISS99, `fill_mix`, and
+/// two kernels running all `PROGPOW_CNT_DAG` loops: `progpow_hash` writes the
+/// mix and final hash of every nonce, and `progpow_verify` checks a batch of
+/// seals. `config.dag_elements` must be set.
+///
+/// # Arguments
+///
+/// * `period` - The program seed, i.e. `block_number / PROGPOW_PERIOD_LENGTH`.
+/// * `config` - Kernel settings such as the work-group size.
+///
+/// # Returns
+///
+/// The OpenCL C source as a `String`.
+pub fn opencl_hash_kernel(period: u64, config: &KernelConfig) -> String {
+    let mut out = opencl_kernel(period, config);
+    out.push_str(OPENCL_HASH_KERNEL);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_algorithm::{PROGPOW_CNT_CACHE, PROGPOW_CNT_MATH, PROGPOW_DAG_LOADS};
+
+    #[test]
+    fn test_opencl_kernel_layout() {
+        let source = opencl_kernel(0, &KernelConfig::for_dataset_size(1024));
+
+        assert!(source.contains("#define PROGPOW_DAG_ELEMENTS    4\n"));
+        assert!(source.contains("// Inner loop for prog_seed 0\n"));
+        assert_eq!(source.matches("// cache load ").count(), PROGPOW_CNT_CACHE);
+        assert_eq!(source.matches("// random math ").count(), PROGPOW_CNT_MATH);
+        assert_eq!(source.matches("data_dag.s[").count(), PROGPOW_DAG_LOADS);
+        assert!(source.contains("mix[0] = "));
+        assert_eq!(source.matches('{').count(), source.matches('}').count());
+
+        // The program, and therefore the kernel, changes with the period.
+        assert_eq!(
+            source,
+            opencl_kernel(0, &KernelConfig::for_dataset_size(1024))
+        );
+        assert_ne!(
+            source,
+            opencl_kernel(1, &KernelConfig::for_dataset_size(1024))
+        );
+        assert!(!opencl_kernel(0, &KernelConfig::default()).contains("PROGPOW_DAG_ELEMENTS    "));
+    }
+
+    #[test]
+    fn test_opencl_hash_kernel_includes_loop() {
+        let source = opencl_hash_kernel(3, &KernelConfig::for_dataset_size(1024));
+        assert!(source.starts_with(&opencl_kernel(3, &KernelConfig::for_dataset_size(1024))));
+        assert!(source.contains("void progpow_hash("));
+        assert!(source.contains("void progpow_verify("));
+        assert_eq!(source.matches('{').count(), source.matches('}').count());
+    }
+}
@@ -0,0 +1,158 @@
+use crate::basic_algorithm::{
+    PROGPOW_CACHE_WORDS, PROGPOW_CNT_DAG, PROGPOW_CNT_MATH, PROGPOW_DAG_LOADS, PROGPOW_LANES,
+    PROGPOW_MIX_BYTES, PROGPOW_REGS,
+};
+use crate::progpow::program::{Program, ProgramOp};
+
+/// Settings for generated kernel sources.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KernelConfig {
+    /// Work-group size the kernel is written for (a multiple of `PROGPOW_LANES`).
+    pub group_size: u32,
+    /// Number of 256-byte DAG elements, emitted as `PROGPOW_DAG_ELEMENTS`.
+    ///
+    /// When `None` the define is left to the host compiler options, as the
+    /// reference miners do.
+    pub dag_elements: Option<u64>,
+}
+
+impl Default for KernelConfig {
+    fn default() -> Self {
+        KernelConfig {
+            group_size: 128,
+            dag_elements: None,
+        }
+    }
+}
+
+impl KernelConfig {
+    /// Returns a config with `PROGPOW_DAG_ELEMENTS` derived from a dataset size.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The size of the dataset in bytes, as passed to `progpow()`.
+    pub fn for_dataset_size(size: u64) -> Self {
+        KernelConfig {
+            dag_elements: Some(size / PROGPOW_MIX_BYTES as u64),
+            ..KernelConfig::default()
+        }
+    }
+}
+
+/// Renders the statement merging `b` into `a` for a merge selector.
+///
+/// # Arguments
+///
+/// * `a` - The destination expression.
+/// * `b` - The value expression to merge.
+/// * `r` - The raw random value selecting the merge operation.
+///
+/// # Returns
+///
+/// A single C statement, terminated by a newline.
+pub fn merge_source(a: &str, b: &str, r: u32) -> String {
+    match r % 4 {
+        0 => format!("{a} = ({a} * 33) + {b};\n"),
+        1 => format!("{a} = ({a} ^ {b}) * 33;\n"),
+        2 => format!("{a} = ROTL32({a}, {}) ^ {b};\n", ((r >> 16) % 31) + 1),
+        _ => format!("{a} = ROTR32({a}, {}) ^ {b};\n", ((r >> 16) % 31) + 1),
+    }
+}
+
+/// Renders the statement computing a random math operation into `d`.
+///
+/// # Arguments
+///
+/// * `d` - The destination expression.
+/// * `a` - The first operand expression.
+/// * `b` - The second operand expression.
+/// * `r` - The raw random value selecting the math operation.
+///
+/// # Returns
+///
+/// A single C statement, terminated by a newline.
+pub fn math_source(d: &str, a: &str, b: &str, r: u32) -> String {
+    match r % 11 {
+        0 => format!("{d} = {a} + {b};\n"),
+        1 => format!("{d} = {a} * {b};\n"),
+        2 => format!("{d} = mul_hi({a}, {b});\n"),
+        3 => format!("{d} = min({a}, {b});\n"),
+        4 => format!("{d} = ROTL32({a}, {b} % 32);\n"),
+        5 => format!("{d} = ROTR32({a}, {b} % 32);\n"),
+        6 => format!("{d} = {a} & {b};\n"),
+        7 => format!("{d} = {a} | {b};\n"),
+        8 => format!("{d} = {a} ^ {b};\n"),
+        9 => format!("{d} = clz({a}) + clz({b});\n"),
+        _ => format!("{d} = popcount({a}) + popcount({b});\n"),
+    }
+}
+
+/// Renders the `#define`s for the ProgPoW parameters shared by all kernels.
+pub(crate) fn parameters_source(config: &KernelConfig) -> String {
+    let mut out = format!(
+        "#define PROGPOW_LANES           {PROGPOW_LANES}\n\
+         #define PROGPOW_REGS            {PROGPOW_REGS}\n\
+         #define PROGPOW_DAG_LOADS       {PROGPOW_DAG_LOADS}\n\
+         #define PROGPOW_CACHE_WORDS     {PROGPOW_CACHE_WORDS}\n\
+         #define PROGPOW_CNT_DAG         {PROGPOW_CNT_DAG}\n\
+         #define PROGPOW_CNT_MATH        {PROGPOW_CNT_MATH}\n"
+    );
+    if let Some(dag_elements) = config.dag_elements {
+        out.push_str(&format!("#define PROGPOW_DAG_ELEMENTS    {dag_elements}\n"));
+    }
+    out.push('\n');
+    out
+}
+
+/// Renders the unrolled body of `progPowLoop` for a program.
+///
+/// The body expects `mix`, `data`, and a loaded `data_dag` in scope, which is
+/// how both the OpenCL and CUDA kernels declare them.
+pub(crate) fn program_body_source(program: &Program) -> String {
+    let mut out = String::new();
+    let (mut cache, mut math) = (0, 0);
+    for op in &program.ops {
+        match *op {
+            ProgramOp::Cache {
+                src,
+                dst,
+                merge_sel,
+            } => {
+                out.push_str(&format!("// cache load {cache}\n"));
+                out.push_str(&format!("offset = mix[{src}] % PROGPOW_CACHE_WORDS;\n"));
+                out.push_str("data = c_dag[offset];\n");
+                out.push_str(&merge_source(&format!("mix[{dst}]"), "data", merge_sel));
+                cache += 1;
+            }
+            ProgramOp::Math {
+                src1,
+                src2,
+                math_sel,
+                dst,
+                merge_sel,
+            } => {
+                out.push_str(&format!("// random math {math}\n"));
+                out.push_str(&math_source(
+                    "data",
+                    &format!("mix[{src1}]"),
+                    &format!("mix[{src2}]"),
+                    math_sel,
+                ));
+                out.push_str(&merge_source(&format!("mix[{dst}]"), "data", merge_sel));
+                math += 1;
+            }
+            ProgramOp::DagMerge {
+                word,
+                dst,
+                merge_sel,
+            } => {
+                out.push_str(&merge_source(
+                    &format!("mix[{dst}]"),
+                    &format!("data_dag.s[{word}]"),
+                    merge_sel,
+                ));
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,414 @@
+use crate::basic_algorithm::{
+    PROGPOW_CACHE_WORDS, PROGPOW_CNT_DAG, PROGPOW_CNT_MATH, PROGPOW_DAG_LOADS, PROGPOW_LANES,
+    PROGPOW_REGS,
+};
+use crate::kernelgen::source::{program_body_source, KernelConfig};
+use crate::progpow::program::Program;
+
+/// WGSL versions of the helpers the shared program body calls.
+///
+/// WGSL has neither macros nor `mul_hi`, so these are plain functions named
+/// after the OpenCL built-ins and `ROTL32`/`ROTR32` macros.
+const WGSL_HELPERS: &str = r#"fn ROTL32(x: u32, n: u32) -> u32 {
+    return (x << (n % 32u)) | (x >> ((32u - n % 32u) % 32u));
+}
+
+fn ROTR32(x: u32, n: u32) -> u32 {
+    return (x >> (n % 32u)) | (x << ((32u - n % 32u) % 32u));
+}
+
+fn mul_hi(a: u32, b: u32) -> u32 {
+    let a_lo = a & 0xffffu;
+    let a_hi = a >> 16u;
+    let b_lo = b & 0xffffu;
+    let b_hi = b >> 16u;
+    let lo_lo = a_lo * b_lo;
+    let hi_lo = a_hi * b_lo;
+    let lo_hi = a_lo * b_hi;
+    let cross = (lo_lo >> 16u) + (hi_lo & 0xffffu) + lo_hi;
+    return a_hi * b_hi + (hi_lo >> 16u) + (cross >> 16u);
+}
+
+fn clz(x: u32) -> u32 {
+    return countLeadingZeros(x);
+}
+
+fn popcount(x: u32) -> u32 {
+    return countOneBits(x);
+}
+
+"#;
+
+/// Keccak-f800, KISS99, `fill_mix`, and the `progpow_hash` and
+/// `progpow_verify` entry points.
+///
+/// WGSL has no 64-bit integers, so nonces and seeds are `vec2<u32>` holding
+/// the low word first.
+const WGSL_HASH_KERNEL: &str = r#"const FNV_PRIME: u32 = 0x1000193u;
+const FNV_OFFSET_BASIS: u32 = 0x811c9dc5u;
+
+const keccakf_rndc = array<u32, 24>(
+    0x00000001u, 0x00008082u, 0x0000808au, 0x80008000u, 0x0000808bu, 0x80000001u, 0x80008081u, 0x00008009u,
+    0x0000008au, 0x00000088u, 0x80008009u, 0x8000000au, 0x8000808bu, 0x0000008bu, 0x00008089u, 0x00008003u,
+    0x00008002u, 0x00000080u, 0x0000800au, 0x8000000au, 0x80008081u, 0x00008080u, 0x80000001u, 0x80008008u
+);
+const keccakf_rotc = array<u32, 24>(
+    1u, 3u, 6u, 10u, 15u, 21u, 28u, 36u, 45u, 55u, 2u, 14u, 27u, 41u, 56u, 8u, 25u, 43u, 62u, 18u, 39u, 61u, 20u, 44u
+);
+const keccakf_piln = array<u32, 24>(
+    10u, 7u, 11u, 17u, 18u, 3u, 5u, 16u, 8u, 21u, 24u, 4u, 15u, 23u, 19u, 13u, 12u, 2u, 20u, 14u, 22u, 9u, 6u, 1u
+);
+
+fn keccak_f800_round(st: ptr<function, array<u32, 25>>, r: u32) {
+    var bc: array<u32, 5>;
+    var t: u32;
+    for (var i = 0u; i < 5u; i++) {
+        bc[i] = (*st)[i] ^ (*st)[i + 5u] ^ (*st)[i + 10u] ^ (*st)[i + 15u] ^ (*st)[i + 20u];
+    }
+    for (var i = 0u; i < 5u; i++) {
+        t = bc[(i + 4u) % 5u] ^ ROTL32(bc[(i + 1u) % 5u], 1u);
+        for (var j = 0u; j < 25u; j += 5u) {
+            (*st)[j + i] ^= t;
+        }
+    }
+    t = (*st)[1];
+    for (var i = 0u; i < 24u; i++) {
+        let j = keccakf_piln[i];
+        bc[0] = (*st)[j];
+        (*st)[j] = ROTL32(t, keccakf_rotc[i]);
+        t = bc[0];
+    }
+    for (var j = 0u; j < 25u; j += 5u) {
+        for (var i = 0u; i < 5u; i++) {
+            bc[i] = (*st)[j + i];
+        }
+        for (var i = 0u; i < 5u; i++) {
+            (*st)[j + i] ^= (~bc[(i + 1u) % 5u]) & bc[(i + 2u) % 5u];
+        }
+    }
+    (*st)[0] ^= keccakf_rndc[r];
+}
+
+fn keccak_f800(st: ptr<function, array<u32, 25>>, header: array<u32, 8>, value: vec2<u32>,
+        words: array<u32, 8>) {
+    for (var i = 0u; i < 25u; i++) {
+        (*st)[i] = 0u;
+    }
+    for (var i = 0u; i < 8u; i++) {
+        (*st)[i] = header[i];
+    }
+    (*st)[8] = value.x;
+    (*st)[9] = value.y;
+    for (var i = 0u; i < 8u; i++) {
+        (*st)[10u + i] = words[i];
+    }
+    for (var r = 0u; r < 22u; r++) {
+        keccak_f800_round(st, r);
+    }
+}
+
+fn bswap32(x: u32) -> u32 {
+    return (x << 24u) | ((x << 8u) & 0x00ff0000u) | ((x >> 8u) & 0x0000ff00u) | (x >> 24u);
+}
+
+fn fnv1a(h: ptr<function, u32>, d: u32) -> u32 {
+    *h = (*h ^ d) * FNV_PRIME;
+    return *h;
+}
+
+struct kiss99_t {
+    z: u32,
+    w: u32,
+    jsr: u32,
+    jcong: u32,
+}
+
+fn kiss99(st: ptr<function, kiss99_t>) -> u32 {
+    (*st).z = 36969u * ((*st).z & 65535u) + ((*st).z >> 16u);
+    (*st).w = 18000u * ((*st).w & 65535u) + ((*st).w >> 16u);
+    let mwc = ((*st).z << 16u) + (*st).w;
+    (*st).jsr ^= ((*st).jsr << 17u);
+    (*st).jsr ^= ((*st).jsr >> 13u);
+    (*st).jsr ^= ((*st).jsr << 5u);
+    (*st).jcong = 69069u * (*st).jcong + 1234567u;
+    return (mwc ^ (*st).jcong) + (*st).jsr;
+}
+
+fn fill_mix(seed: vec2<u32>, lane_id: u32) {
+    var fnv_hash = FNV_OFFSET_BASIS;
+    var st: kiss99_t;
+    st.z = fnv1a(&fnv_hash, seed.x);
+    st.w = fnv1a(&fnv_hash, seed.y);
+    st.jsr = fnv1a(&fnv_hash, lane_id);
+    st.jcong = fnv1a(&fnv_hash, lane_id);
+    for (var i = 0u; i < PROGPOW_REGS; i++) {
+        mix[i] = kiss99(&st);
+    }
+}
+
+fn load_c_dag(lid: u32) {
+    for (var word = lid; word < PROGPOW_CACHE_WORDS; word += GROUP_SIZE) {
+        c_dag[word] = g_cdag[word];
+    }
+    workgroupBarrier();
+}
+
+struct progpow_hash_t {
+    digest: array<u32, 8>,
+    final_hash: array<u32, 8>,
+}
+
+// Computes one hash per PROGPOW_LANES invocations; only lane 0 gets the result.
+fn progpow_digest(header: array<u32, 8>, nonce: vec2<u32>, lid: u32) -> progpow_hash_t {
+    let lane_id = lid & (PROGPOW_LANES - 1u);
+    let group_id = lid / PROGPOW_LANES;
+
+    var st: array<u32, 25>;
+    var zeros: array<u32, 8>;
+    keccak_f800(&st, header, nonce, zeros);
+    let seed = vec2<u32>(bswap32(st[1]), bswap32(st[0]));
+
+    fill_mix(seed, lane_id);
+    for (var l = 0u; l < PROGPOW_CNT_DAG; l++) {
+        progPowLoop(l, lane_id, group_id);
+    }
+
+    var lane_hash = FNV_OFFSET_BASIS;
+    for (var i = 0u; i < PROGPOW_REGS; i++) {
+        fnv1a(&lane_hash, mix[i]);
+    }
+    lane_hashes[lid] = lane_hash;
+    workgroupBarrier();
+
+    var hash: progpow_hash_t;
+    if (lane_id == 0u) {
+        for (var i = 0u; i < 8u; i++) {
+            hash.digest[i] = FNV_OFFSET_BASIS;
+        }
+        for (var l = 0u; l < PROGPOW_LANES; l++) {
+            fnv1a(&hash.digest[l % 8u], lane_hashes[group_id * PROGPOW_LANES + l]);
+        }
+        keccak_f800(&st, header, seed, hash.digest);
+        for (var i = 0u; i < 8u; i++) {
+            hash.final_hash[i] = st[i];
+        }
+    }
+    return hash;
+}
+
+@compute @workgroup_size(GROUP_SIZE)
+fn progpow_hash(@builtin(local_invocation_index) lid: u32,
+        @builtin(global_invocation_id) gid: vec3<u32>) {
+    let hash_id = gid.x / PROGPOW_LANES;
+    let nonce_lo = params.start_nonce.x + hash_id;
+    let nonce = vec2<u32>(nonce_lo, params.start_nonce.y + select(0u, 1u, nonce_lo < hash_id));
+    var header: array<u32, 8>;
+    for (var i = 0u; i < 8u; i++) {
+        header[i] = params.header[i / 4u][i % 4u];
+    }
+    load_c_dag(lid);
+
+    let hash = progpow_digest(header, nonce, lid);
+
+    if ((lid & (PROGPOW_LANES - 1u)) == 0u) {
+        for (var i = 0u; i < 8u; i++) {
+            g_out[hash_id * 16u + i] = hash.digest[i];
+            g_out[hash_id * 16u + 8u + i] = hash.final_hash[i];
+        }
+    }
+}
+
+const SEAL_WORDS: u32 = 26u;
+
+// Each seal is the header hash, the nonce (low word first), the claimed mix
+// hash, and the boundary. Bit i of g_failed is set when seal i is invalid.
+@compute @workgroup_size(GROUP_SIZE)
+fn progpow_verify(@builtin(local_invocation_index) lid: u32,
+        @builtin(global_invocation_id) gid: vec3<u32>) {
+    // Padding invocations re-check the last seal so every lane reaches the barriers.
+    let seal_count = arrayLength(&g_seals) / SEAL_WORDS;
+    let hash_id = gid.x / PROGPOW_LANES;
+    let base = min(hash_id, seal_count - 1u) * SEAL_WORDS;
+    var header: array<u32, 8>;
+    for (var i = 0u; i < 8u; i++) {
+        header[i] = g_seals[base + i];
+    }
+    let nonce = vec2<u32>(g_seals[base + 8u], g_seals[base + 9u]);
+    load_c_dag(lid);
+
+    let hash = progpow_digest(header, nonce, lid);
+
+    if ((lid & (PROGPOW_LANES - 1u)) == 0u && hash_id < seal_count) {
+        var valid = true;
+        for (var i = 0u; i < 8u; i++) {
+            valid = valid && hash.digest[i] == g_seals[base + 10u + i];
+        }
+        // Compare the final hash and boundary as big-endian 256-bit numbers.
+        var order = 0;
+        for (var i = 0u; i < 8u && order == 0; i++) {
+            let a = bswap32(hash.final_hash[i]);
+            let b = bswap32(g_seals[base + 18u + i]);
+            order = select(select(0, -1, a < b), 1, a > b);
+        }
+        if (!valid || order > 0) {
+            atomicOr(&g_failed[hash_id / 32u], 1u << (hash_id % 32u));
+        }
+    }
+}
+"#;
+
+/// Generates a complete WGSL compute shader computing ProgPoW hashes for a period.
+///
+/// The shader is self-contained, since WGSL has no preprocessor: parameters
+/// become `const` declarations, and the period's random program is the same
+/// unrolled body the OpenCL and CUDA generators emit. When
+/// `config.dag_elements` is `None`, `PROGPOW_DAG_ELEMENTS` is declared as a
+/// pipeline-overridable constant for the host to set.
+///
+/// Both entry points read the DAG and cached DAG words from bindings `1` and
+/// `2` of group 0. `progpow_hash` additionally uses:
+///
+/// * `0` - A uniform holding the header hash words and the 64-bit start nonce.
+/// * `3` - The output: the 8-word mix hash then 8-word final hash of each nonce.
+///
+/// and `progpow_verify` uses:
+///
+/// * `4` - The seals, 26 words each: header hash, nonce, mix hash, boundary.
+/// * `5` - The failure bitmask, one bit per seal.
+///
+/// # Arguments
+///
+/// * `period` - The program seed, i.e. `block_number / PROGPOW_PERIOD_LENGTH`.
+/// * `config` - Kernel settings such as the work-group size.
+///
+/// # Returns
+///
+/// The WGSL source as a `String`.
+pub fn wgsl_kernel(period: u64, config: &KernelConfig) -> String {
+    wgsl_kernel_for_program(&Program::generate(period), config)
+}
+
+/// Generates a complete WGSL compute shader for an already decoded program.
+pub fn wgsl_kernel_for_program(program: &Program, config: &KernelConfig) -> String {
+    let mut out = String::new();
+
+    out.push_str(&format!(
+        "const GROUP_SIZE: u32 = {}u;\n",
+        config.group_size
+    ));
+    out.push_str("const GROUP_SHARE: u32 = GROUP_SIZE / PROGPOW_LANES;\n");
+    out.push('\n');
+    out.push_str(&format!(
+        "const PROGPOW_LANES: u32 = {PROGPOW_LANES}u;\n\
+         const PROGPOW_REGS: u32 = {PROGPOW_REGS}u;\n\
+         const PROGPOW_DAG_LOADS: u32 = {PROGPOW_DAG_LOADS}u;\n\
+         const PROGPOW_CACHE_WORDS: u32 = {PROGPOW_CACHE_WORDS}u;\n\
+         const PROGPOW_CNT_DAG: u32 = {PROGPOW_CNT_DAG}u;\n\
+         const PROGPOW_CNT_MATH: u32 = {PROGPOW_CNT_MATH}u;\n"
+    ));
+    match config.dag_elements {
+        Some(dag_elements) => out.push_str(&format!(
+            "const PROGPOW_DAG_ELEMENTS: u32 = {dag_elements}u;\n"
+        )),
+        None => out.push_str("override PROGPOW_DAG_ELEMENTS: u32;\n"),
+    }
+    out.push('\n');
+
+    out.push_str("struct dag_t {\n    s: array<u32, PROGPOW_DAG_LOADS>,\n}\n");
+    out.push('\n');
+    out.push_str("struct Params {\n");
+    out.push_str("    header: array<vec4<u32>, 2>,\n");
+    out.push_str("    start_nonce: vec2<u32>,\n");
+    out.push_str("}\n");
+    out.push('\n');
+    out.push_str("@group(0) @binding(0) var<uniform> params: Params;\n");
+    out.push_str("@group(0) @binding(1) var<storage, read> g_dag: array<dag_t>;\n");
+    out.push_str("@group(0) @binding(2) var<storage, read> g_cdag: array<u32>;\n");
+    out.push_str("@group(0) @binding(3) var<storage, read_write> g_out: array<u32>;\n");
+    out.push_str("@group(0) @binding(4) var<storage, read> g_seals: array<u32>;\n");
+    out.push_str("@group(0) @binding(5) var<storage, read_write> g_failed: array<atomic<u32>>;\n");
+    out.push('\n');
+    out.push_str("var<workgroup> c_dag: array<u32, PROGPOW_CACHE_WORDS>;\n");
+    out.push_str("var<workgroup> share: array<u32, GROUP_SHARE>;\n");
+    out.push_str("var<workgroup> lane_hashes: array<u32, GROUP_SIZE>;\n");
+    out.push_str("var<private> mix: array<u32, PROGPOW_REGS>;\n");
+    out.push('\n');
+    out.push_str(WGSL_HELPERS);
+
+    out.push_str(&format!("// Inner loop for prog_seed {}\n", program.period));
+    out.push_str("fn progPowLoop(loop_index: u32, lane_id: u32, group_id: u32) {\n");
+    out.push_str("var data_dag: dag_t;\n");
+    out.push_str("var offset: u32;\n");
+    out.push_str("var data: u32;\n");
+
+    // All lanes share the global load address taken from one lane's mix[0].
+    out.push_str("// global load to sequential locations\n");
+    out.push_str("if (lane_id == (loop_index % PROGPOW_LANES)) {\n");
+    out.push_str("    share[group_id] = mix[0];\n");
+    out.push_str("}\n");
+    out.push_str("workgroupBarrier();\n");
+    out.push_str("offset = share[group_id];\n");
+    out.push_str("workgroupBarrier();\n");
+    out.push_str("offset %= PROGPOW_DAG_ELEMENTS;\n");
+    out.push_str("offset = offset * PROGPOW_LANES + (lane_id ^ loop_index) % PROGPOW_LANES;\n");
+    out.push_str("data_dag = g_dag[offset];\n");
+
+    out.push_str(&program_body_source(program));
+
+    out.push_str("}\n");
+    out.push('\n');
+    out.push_str(WGSL_HASH_KERNEL);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_algorithm::{PROGPOW_CNT_CACHE, PROGPOW_DAG_LOADS};
+    use crate::kernelgen::opencl::opencl_kernel;
+
+    #[test]
+    fn test_wgsl_kernel_layout() {
+        let config = KernelConfig::for_dataset_size(1024);
+        let source = wgsl_kernel(5, &config);
+
+        assert!(source.contains("const PROGPOW_DAG_ELEMENTS: u32 = 4u;\n"));
+        assert!(source.contains("// Inner loop for prog_seed 5\n"));
+        assert_eq!(source.matches("// cache load ").count(), PROGPOW_CNT_CACHE);
+        assert_eq!(source.matches("data_dag.s[").count(), PROGPOW_DAG_LOADS);
+        assert!(!source.contains('#'));
+        assert_eq!(source.matches('{').count(), source.matches('}').count());
+        assert!(wgsl_kernel(5, &KernelConfig::default())
+            .contains("override PROGPOW_DAG_ELEMENTS: u32;\n"));
+
+        // The random program is shared with the other generators.
+        let opencl = opencl_kernel(5, &config);
+        let body = |source: &str| {
+            let start = source.find("// cache load 0").unwrap();
+            let end = start + source[start..].find("\n}\n").unwrap();
+            source[start..end].to_string()
+        };
+        assert!(body(&opencl).starts_with(&body(&source)));
+    }
+
+    #[cfg(feature = "gpu-wgpu")]
+    #[test]
+    fn test_wgsl_kernel_validates() {
+        use wgpu::naga;
+
+        for config in [
+            KernelConfig::for_dataset_size(1024),
+            KernelConfig::default(),
+        ] {
+            let source = wgsl_kernel(0, &config);
+            let module = naga::front::wgsl::parse_str(&source)
+                .unwrap_or_else(|err| panic!("{}", err.emit_to_string(&source)));
+            naga::valid::Validator::new(
+                naga::valid::ValidationFlags::all(),
+                naga::valid::Capabilities::all(),
+            )
+            .validate(&module)
+            .unwrap_or_else(|err| panic!("{}", err.emit_to_string(&source)));
+        }
+    }
+}
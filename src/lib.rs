@@ -7,7 +7,8 @@
 //! Specifically, it replicates and validates the behavior of ProgPoW as implemented in `go-ethereum`.
 //!
 //! ## Features
-//! - Keccak-f800 hashing functions
+//! - Keccak-f800 hashing functions, and the Keccak-f1600 `keccak256` and
+//!   `keccak512` hashes ethash needs
 //! - DAG access and caching
 //! - Math and memory mixing operations
 //!
@@ -15,21 +16,142 @@
 //! This library is intended for educational purposes or verification use cases. It may not be suitable
 //! for production mining.
 
+#[cfg(feature = "alloy")]
+pub mod alloy;
 pub mod basic_algorithm;
+pub mod budget;
+pub mod chain;
+pub mod difficulty;
+pub mod endian;
+pub mod engine;
+pub mod ffi;
+pub mod firo;
+#[cfg(feature = "verifyd")]
+pub mod grpc;
+pub mod hashrate;
+pub mod header;
+mod hex;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod kernelgen {
+    pub mod cuda;
+    pub mod dag;
+    pub mod opencl;
+    pub mod source;
+    pub mod wgsl;
+}
+pub mod ethash {
+    pub mod background;
+    pub mod buffer;
+    pub mod cache;
+    pub mod dataset;
+    #[cfg(feature = "hugepages")]
+    pub mod hugepage;
+    pub mod manager;
+    pub mod merkle;
+    #[cfg(feature = "numa")]
+    pub mod numa;
+    pub mod remote;
+    pub mod seed;
+    #[cfg(feature = "shm")]
+    pub mod shm;
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    pub mod uring;
+}
 pub mod keccak {
+    pub use f1600::{keccak256, keccak512};
+
+    pub mod backend;
+    pub mod f1600;
     pub mod f800long;
     pub mod f800round;
     pub mod f800short;
+    pub mod f800state;
+    pub mod kawpow;
+    pub mod permutation;
+    pub mod sponge;
 }
+#[cfg(feature = "java")]
+pub mod java;
+pub mod memo;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "uniffi")]
+pub mod mobile;
+pub mod miner {
+    pub mod backend;
+    pub mod cpu;
+    pub mod device;
+    pub mod kernel_cache;
+    #[cfg(feature = "gpu-opencl")]
+    pub mod opencl;
+    pub mod pipeline;
+    pub mod scheduler;
+    pub mod tune;
+    pub mod validate;
+    pub mod verify;
+    #[cfg(feature = "gpu-wgpu")]
+    pub mod wgpu;
+}
+#[cfg(feature = "node")]
+pub mod node;
+pub mod pipeline;
 pub mod progpow {
+    pub mod describe;
+    pub mod execution;
+    pub mod firopow;
+    pub mod generic;
+    pub mod kawpow;
+    #[cfg(feature = "differential")]
+    pub mod oracle;
+    #[allow(clippy::module_inception)]
     pub mod progpow;
+    pub mod program;
+    pub mod proof;
+    #[cfg(feature = "reference-cpp")]
+    pub mod reference;
+    pub mod scratch;
+    pub mod search;
+    pub mod stats;
+    pub mod sweep;
+    pub mod trace;
+    #[cfg(feature = "vectors")]
+    pub mod vectors;
+    pub mod verify;
 }
+#[cfg(feature = "python")]
+pub mod python;
+pub mod ravencoin;
+#[cfg(feature = "reth")]
+pub mod reth;
+#[cfg(feature = "net")]
+pub mod rpc;
+pub mod segment;
+pub mod share;
+pub mod stateless;
+#[cfg(feature = "net")]
+pub mod stratum;
+#[cfg(feature = "stream")]
+pub mod stream;
+#[cfg(feature = "substrate")]
+pub mod substrate;
+pub mod target;
+#[cfg(any(test, feature = "testutil"))]
+pub mod testutil;
+pub mod verifier;
+pub mod warmup;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "uniffi")]
+uniffi::setup_scaffolding!();
 
 #[cfg(test)]
 mod tests {
     use crate::progpow::progpow::progpow;
 
     #[test]
+    #[allow(clippy::needless_range_loop)]
     fn test_progpow_function() {
         println!("Test started!");
         let mut hash = vec![0u8; 32];
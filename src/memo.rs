@@ -0,0 +1,204 @@
+//! A bounded memo of computed hashes, for seals seen more than once.
+//!
+//! A gossip layer hears of a new block from many peers, each handing it the
+//! same seal. [`SealMemo`] keeps the `(mix_hash, final_hash)` pair of the
+//! latest header pre-hashes and nonces it was given, least recently used
+//! first out, so a repeated seal costs a lookup instead of the 64 loops over
+//! the DAG. [`Verifier::with_memo`](crate::verifier::Verifier::with_memo)
+//! puts one in front of a verifier.
+//!
+//! The memo holds hashes rather than verdicts: the mix hash and boundary of
+//! every seal are still checked against the remembered pair, so a peer
+//! replaying a valid header and nonce with a forged mix hash is still caught,
+//! and verifiers with different target rules can share one memo.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// The key of a memoized hash: the header pre-hash and the nonce.
+type Key = ([u8; 32], u64);
+
+/// A remembered hash, with the block number it was computed at and when it
+/// was last used.
+struct Entry {
+    block_number: u64,
+    mix_hash: [u8; 32],
+    final_hash: [u8; 32],
+    used: u64,
+}
+
+/// The memoized hashes, and their keys by last use.
+#[derive(Default)]
+struct State {
+    entries: HashMap<Key, Entry>,
+    by_use: BTreeMap<u64, Key>,
+    clock: u64,
+}
+
+/// A thread-safe LRU of `(mix_hash, final_hash)` pairs keyed by header
+/// pre-hash and nonce.
+///
+/// The block number selects the program and the epoch, so a pair is only
+/// returned for the block number it was computed at; another one misses.
+pub struct SealMemo {
+    capacity: usize,
+    state: Mutex<State>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl SealMemo {
+    /// Creates a memo holding up to `capacity` hashes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "a seal memo must hold at least one hash");
+        SealMemo {
+            capacity,
+            state: Mutex::new(State::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the most hashes the memo holds.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of hashes held.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    /// Returns `true` if no hash is held.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns how many lookups found a hash.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Returns how many lookups found none.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` if a pair is held for a header pre-hash and nonce at
+    /// `block_number`, without marking it used or counting a lookup.
+    pub fn contains(&self, header_hash: &[u8; 32], nonce: u64, block_number: u64) -> bool {
+        let state = self.state.lock().unwrap();
+        state
+            .entries
+            .get(&(*header_hash, nonce))
+            .is_some_and(|entry| entry.block_number == block_number)
+    }
+
+    /// Returns the `(mix_hash, final_hash)` pair remembered for a header
+    /// pre-hash and nonce at `block_number`, marking it most recently used.
+    pub fn get(
+        &self,
+        header_hash: &[u8; 32],
+        nonce: u64,
+        block_number: u64,
+    ) -> Option<([u8; 32], [u8; 32])> {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let key = (*header_hash, nonce);
+        let found = match state.entries.get_mut(&key) {
+            Some(entry) if entry.block_number == block_number => {
+                state.by_use.remove(&entry.used);
+                state.clock += 1;
+                entry.used = state.clock;
+                state.by_use.insert(entry.used, key);
+                Some((entry.mix_hash, entry.final_hash))
+            }
+            _ => None,
+        };
+        let counter = if found.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+
+    /// Remembers the pair computed for a header pre-hash and nonce at
+    /// `block_number`, replacing any pair held for them and dropping the
+    /// least recently used one if the memo is full.
+    pub fn insert(
+        &self,
+        header_hash: &[u8; 32],
+        nonce: u64,
+        block_number: u64,
+        mix_hash: [u8; 32],
+        final_hash: [u8; 32],
+    ) {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        state.clock += 1;
+        let key = (*header_hash, nonce);
+        let entry = Entry {
+            block_number,
+            mix_hash,
+            final_hash,
+            used: state.clock,
+        };
+        if let Some(old) = state.entries.insert(key, entry) {
+            state.by_use.remove(&old.used);
+        } else if state.entries.len() > self.capacity {
+            let (_, oldest) = state.by_use.pop_first().unwrap();
+            state.entries.remove(&oldest);
+        }
+        state.by_use.insert(state.clock, key);
+    }
+
+    /// Forgets every hash, keeping the hit and miss counts.
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.entries.clear();
+        state.by_use.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_least_recently_used_is_dropped() {
+        let memo = SealMemo::new(2);
+        memo.insert(&[1; 32], 7, 100, [1; 32], [2; 32]);
+        memo.insert(&[2; 32], 7, 100, [3; 32], [4; 32]);
+        assert_eq!(memo.get(&[1; 32], 7, 100), Some(([1; 32], [2; 32])));
+        // Header 2 is now the least recently used.
+        memo.insert(&[3; 32], 7, 100, [5; 32], [6; 32]);
+        assert_eq!(memo.len(), 2);
+        assert!(!memo.contains(&[2; 32], 7, 100));
+        assert!(memo.contains(&[1; 32], 7, 100));
+        assert_eq!(memo.get(&[2; 32], 7, 100), None);
+        assert!(memo.get(&[1; 32], 7, 100).is_some());
+        assert!(memo.get(&[3; 32], 7, 100).is_some());
+
+        // The nonce and the block number are part of the key.
+        assert_eq!(memo.get(&[1; 32], 8, 100), None);
+        assert_eq!(memo.get(&[1; 32], 7, 101), None);
+        assert_eq!((memo.hits(), memo.misses()), (3, 3));
+
+        // Replacing a pair keeps the other.
+        memo.insert(&[3; 32], 7, 101, [7; 32], [8; 32]);
+        assert_eq!(memo.len(), 2);
+        assert_eq!(memo.get(&[3; 32], 7, 101), Some(([7; 32], [8; 32])));
+        assert!(memo.get(&[1; 32], 7, 100).is_some());
+
+        memo.clear();
+        assert!(memo.is_empty());
+        assert_eq!(memo.capacity(), 2);
+    }
+}
@@ -0,0 +1,262 @@
+//! Process-wide counters and latency histograms in Prometheus format.
+//!
+//! With the `metrics` feature, the crate counts into [`global`] as it works:
+//! every seal checked and why it failed, every cache request and whether the
+//! epoch was already held, every cache generated and how long it took, and
+//! the time of every ProgPoW hash. [`Metrics::render`] writes them in the
+//! Prometheus text format; `progpow-httpd` appends them to its `/metrics`,
+//! and [`serve`] answers scrapes on an address of its own for services that
+//! have no HTTP server, such as `progpow-verifyd` with `--metrics`.
+//!
+//! The counters are plain atomics with no dependencies, so recording one is
+//! a relaxed atomic add.
+
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::progpow::verify::SealError;
+
+/// Upper bounds, in seconds, of the hash latency buckets.
+const HASH_BUCKETS: [f64; 10] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1,
+];
+
+/// Upper bounds, in seconds, of the cache generation buckets.
+const CACHE_BUCKETS: [f64; 10] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 25.0, 60.0, 120.0];
+
+/// A latency histogram with fixed bucket bounds.
+pub struct Histogram {
+    bounds: &'static [f64; 10],
+    /// Observations in each bucket, not cumulative.
+    buckets: [AtomicU64; 10],
+    count: AtomicU64,
+    sum_nanos: AtomicU64,
+}
+
+impl Histogram {
+    const fn new(bounds: &'static [f64; 10]) -> Self {
+        Histogram {
+            bounds,
+            buckets: [const { AtomicU64::new(0) }; 10],
+            count: AtomicU64::new(0),
+            sum_nanos: AtomicU64::new(0),
+        }
+    }
+
+    /// Records one observation of `elapsed`.
+    pub fn observe(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        if let Some(bucket) = self.bounds.iter().position(|&bound| seconds <= bound) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Returns the number of observations.
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Writes the histogram as the Prometheus metric `name`.
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        let mut cumulative = 0;
+        for (bound, bucket) in self.bounds.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {cumulative}");
+        }
+        let count = self.count();
+        let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {count}");
+        let sum = self.sum_nanos.load(Ordering::Relaxed) as f64 / 1e9;
+        let _ = writeln!(out, "{name}_sum {sum}");
+        let _ = writeln!(out, "{name}_count {count}");
+    }
+}
+
+/// The counters and histograms the crate records into.
+pub struct Metrics {
+    /// Seals that verified.
+    pub valid_seals: AtomicU64,
+    /// Seals whose recomputed mix hash differed.
+    pub mix_mismatches: AtomicU64,
+    /// Seals whose final hash exceeded the boundary.
+    pub boundary_misses: AtomicU64,
+    /// Cache requests answered with an epoch already held.
+    pub cache_hits: AtomicU64,
+    /// Cache requests for an epoch not held, which generate it.
+    pub cache_misses: AtomicU64,
+    /// The time taken to generate each cache.
+    pub cache_builds: Histogram,
+    /// The time taken by each ProgPoW hash.
+    pub hashes: Histogram,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    /// Creates a set of metrics with every counter at zero.
+    pub const fn new() -> Self {
+        Metrics {
+            valid_seals: AtomicU64::new(0),
+            mix_mismatches: AtomicU64::new(0),
+            boundary_misses: AtomicU64::new(0),
+            cache_hits: AtomicU64::new(0),
+            cache_misses: AtomicU64::new(0),
+            cache_builds: Histogram::new(&CACHE_BUCKETS),
+            hashes: Histogram::new(&HASH_BUCKETS),
+        }
+    }
+
+    /// Counts the outcome of checking one seal.
+    pub(crate) fn record_seal(&self, result: &Result<(), SealError>) {
+        let counter = match result {
+            Ok(()) => &self.valid_seals,
+            Err(SealError::MixMismatch { .. }) => &self.mix_mismatches,
+            Err(SealError::BoundaryNotMet { .. }) => &self.boundary_misses,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a cache request, a hit if the epoch was already held.
+    pub(crate) fn record_cache_request(&self, hit: bool) {
+        let counter = if hit {
+            &self.cache_hits
+        } else {
+            &self.cache_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let mut out = format!(
+            "# HELP progpow_verifications_total Seals checked, by outcome.\n\
+             # TYPE progpow_verifications_total counter\n\
+             progpow_verifications_total{{status=\"valid\"}} {}\n\
+             progpow_verifications_total{{status=\"mix_mismatch\"}} {}\n\
+             progpow_verifications_total{{status=\"boundary_not_met\"}} {}\n\
+             # HELP progpow_cache_requests_total Cache requests, by whether the epoch was held.\n\
+             # TYPE progpow_cache_requests_total counter\n\
+             progpow_cache_requests_total{{result=\"hit\"}} {}\n\
+             progpow_cache_requests_total{{result=\"miss\"}} {}\n",
+            load(&self.valid_seals),
+            load(&self.mix_mismatches),
+            load(&self.boundary_misses),
+            load(&self.cache_hits),
+            load(&self.cache_misses),
+        );
+        self.cache_builds.render(
+            &mut out,
+            "progpow_cache_build_seconds",
+            "Light cache generation time.",
+        );
+        self.hashes
+            .render(&mut out, "progpow_hash_seconds", "ProgPoW hash time.");
+        out
+    }
+}
+
+/// The metrics the crate records into.
+static GLOBAL: Metrics = Metrics::new();
+
+/// Returns the process-wide metrics.
+pub fn global() -> &'static Metrics {
+    &GLOBAL
+}
+
+/// Answers every HTTP request on `address` with [`global`]'s metrics, on a
+/// thread of its own.
+///
+/// # Returns
+///
+/// The address bound, which tells the port when `address` asked for any.
+pub fn serve(address: impl ToSocketAddrs) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(address)?;
+    let bound = listener.local_addr()?;
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            // The request is not parsed: every path is the metrics page.
+            let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request);
+            let body = global().render();
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\ncontent-type: text/plain; version=0.0.4\r\n\
+                 content-length: {}\r\nconnection: close\r\n\r\n{body}",
+                body.len()
+            );
+        }
+    });
+    Ok(bound)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progpow::verify::Seal;
+    use crate::testutil::tiny_cache_manager;
+    use std::net::TcpStream;
+
+    #[test]
+    fn test_histogram_renders_cumulative_buckets() {
+        let metrics = Metrics::new();
+        metrics.hashes.observe(Duration::from_micros(50));
+        metrics.hashes.observe(Duration::from_micros(700));
+        metrics.hashes.observe(Duration::from_secs(3));
+        metrics.record_seal(&Ok(()));
+        let text = metrics.render();
+        assert!(text.contains("progpow_hash_seconds_bucket{le=\"0.0001\"} 1\n"));
+        assert!(text.contains("progpow_hash_seconds_bucket{le=\"0.001\"} 2\n"));
+        assert!(text.contains("progpow_hash_seconds_bucket{le=\"0.1\"} 2\n"));
+        assert!(text.contains("progpow_hash_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(text.contains("progpow_hash_seconds_count 3\n"));
+        assert!(text.contains("progpow_verifications_total{status=\"valid\"} 1\n"));
+    }
+
+    #[test]
+    fn test_verification_records_into_the_global_metrics() {
+        let caches = tiny_cache_manager(1);
+        // Other tests record too, so only growth is checked.
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let (misses, mismatches, hashes) = (
+            load(&global().cache_misses),
+            load(&global().mix_mismatches),
+            global().hashes.count(),
+        );
+        let seal = Seal {
+            header_hash: [1; 32],
+            block_number: 10,
+            nonce: 2,
+            mix_hash: [0; 32],
+            boundary: [0xff; 32],
+        };
+        assert!(caches.verify_seal(&seal).is_err());
+        assert!(caches.verify_seal(&seal).is_err());
+        assert!(load(&global().cache_misses) > misses);
+        assert!(load(&global().cache_hits) >= 1);
+        assert!(load(&global().mix_mismatches) >= mismatches + 2);
+        assert!(global().hashes.count() >= hashes + 2);
+        assert!(global().cache_builds.count() >= 1);
+
+        let address = serve("127.0.0.1:0").unwrap();
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("progpow_verifications_total{status=\"mix_mismatch\"}"));
+    }
+}
@@ -0,0 +1,38 @@
+use std::ops::Range;
+
+use crate::progpow::search::Solution;
+
+/// A unit of mining work handed to a [`Miner`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Work {
+    /// The 32-byte header hash being sealed.
+    pub header_hash: [u8; 32],
+    /// The block number, which selects the ProgPoW period.
+    pub block_number: u64,
+    /// The 32-byte big-endian target a final hash must not exceed.
+    pub boundary: [u8; 32],
+}
+
+/// A mining backend able to hash nonces for a [`Work`].
+///
+/// Every backend (CPU workers, GPU devices, or a scheduler combining them)
+/// implements this trait, so callers can swap them without changing code.
+pub trait Miner: Send + Sync {
+    /// A short human-readable name for the backend.
+    fn name(&self) -> String;
+
+    /// Computes the `(mix_hash, final_hash)` pair for every nonce in `nonces`.
+    ///
+    /// # Returns
+    ///
+    /// One entry per nonce, in nonce order.
+    fn hash_batch(&self, work: &Work, nonces: Range<u64>) -> Vec<(Vec<u8>, Vec<u8>)>;
+
+    /// Searches `nonces` for a nonce meeting `work.boundary`.
+    ///
+    /// # Returns
+    ///
+    /// The lowest winning nonce in the range, or `None` if there is none.
+    fn search(&self, work: &Work, nonces: Range<u64>) -> Option<Solution>;
+}
@@ -0,0 +1,178 @@
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+
+use crate::miner::backend::{Miner, Work};
+use crate::miner::verify::{FailureMask, SealVerifier};
+use crate::progpow::progpow::progpow;
+use crate::progpow::search::{search, SearchStrategy, Solution};
+use crate::progpow::verify::{verify_seal, Seal};
+
+/// Number of nonces a worker thread claims at a time during a search.
+const CPU_SEARCH_CHUNK: u64 = 64;
+
+/// A multithreaded CPU mining backend.
+///
+/// The miner owns the cached DAG words and a lookup function for full DAG
+/// items, and spreads nonces across `threads` OS threads.
+pub struct CpuMiner<L> {
+    size: u64,
+    c_dag: Vec<u32>,
+    lookup: L,
+    threads: usize,
+    strategy: SearchStrategy,
+}
+
+impl<L> CpuMiner<L>
+where
+    L: Fn(u32) -> Vec<u8> + Send + Sync,
+{
+    /// Creates a CPU miner.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The size of the dataset.
+    /// * `c_dag` - The cached first words of the DAG.
+    /// * `lookup` - A function to retrieve memory segments based on an index.
+    /// * `threads` - The number of worker threads (at least one is used).
+    pub fn new(size: u64, c_dag: Vec<u32>, lookup: L, threads: usize) -> Self {
+        CpuMiner {
+            size,
+            c_dag,
+            lookup,
+            threads: threads.max(1),
+            strategy: SearchStrategy::Full,
+        }
+    }
+
+    /// Sets the strategy each worker uses when searching.
+    pub fn with_strategy(mut self, strategy: SearchStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+}
+
+impl<L> Miner for CpuMiner<L>
+where
+    L: Fn(u32) -> Vec<u8> + Send + Sync,
+{
+    fn name(&self) -> String {
+        format!("cpu x{}", self.threads)
+    }
+
+    fn hash_batch(&self, work: &Work, nonces: Range<u64>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let start = nonces.start;
+        let mut out = vec![(Vec::new(), Vec::new()); nonces.end.saturating_sub(start) as usize];
+        let per_thread = out.len().div_ceil(self.threads).max(1);
+
+        thread::scope(|scope| {
+            for (chunk_index, chunk) in out.chunks_mut(per_thread).enumerate() {
+                let first = start + (chunk_index * per_thread) as u64;
+                scope.spawn(move || {
+                    for (offset, slot) in chunk.iter_mut().enumerate() {
+                        *slot = progpow(
+                            &work.header_hash,
+                            first + offset as u64,
+                            self.size,
+                            work.block_number,
+                            &self.c_dag,
+                            &self.lookup,
+                        );
+                    }
+                });
+            }
+        });
+        out
+    }
+
+    fn search(&self, work: &Work, nonces: Range<u64>) -> Option<Solution> {
+        let next_chunk = AtomicU64::new(0);
+        let best = AtomicU64::new(u64::MAX);
+
+        let found: Vec<Solution> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..self.threads)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut found = Vec::new();
+                        loop {
+                            // Chunks are claimed in increasing order, so once a
+                            // solution is known no later chunk can beat it.
+                            let chunk = next_chunk.fetch_add(1, Ordering::Relaxed);
+                            let chunk_start = match chunk
+                                .checked_mul(CPU_SEARCH_CHUNK)
+                                .and_then(|offset| nonces.start.checked_add(offset))
+                            {
+                                Some(chunk_start) => chunk_start,
+                                None => break,
+                            };
+                            if chunk_start >= nonces.end
+                                || chunk_start > best.load(Ordering::Relaxed)
+                            {
+                                break;
+                            }
+                            let chunk_end =
+                                chunk_start.saturating_add(CPU_SEARCH_CHUNK).min(nonces.end);
+                            if let Some(solution) = search(
+                                &work.header_hash,
+                                self.size,
+                                work.block_number,
+                                &self.c_dag,
+                                &self.lookup,
+                                chunk_start..chunk_end,
+                                &work.boundary,
+                                self.strategy,
+                            ) {
+                                best.fetch_min(solution.nonce, Ordering::Relaxed);
+                                found.push(solution);
+                            }
+                        }
+                        found
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("cpu miner worker panicked"))
+                .collect()
+        });
+
+        found.into_iter().min_by_key(|solution| solution.nonce)
+    }
+}
+
+impl<L> SealVerifier for CpuMiner<L>
+where
+    L: Fn(u32) -> Vec<u8> + Send + Sync,
+{
+    fn failures(&self, seals: &[Seal]) -> FailureMask {
+        let per_thread = seals.len().div_ceil(self.threads).max(1);
+        let failed: Vec<usize> = thread::scope(|scope| {
+            let workers: Vec<_> = seals
+                .chunks(per_thread)
+                .enumerate()
+                .map(|(chunk_index, chunk)| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .enumerate()
+                            .filter(|(_, seal)| {
+                                verify_seal(seal, self.size, &self.c_dag, &self.lookup).is_err()
+                            })
+                            .map(|(offset, _)| chunk_index * per_thread + offset)
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("cpu verifier worker panicked"))
+                .collect()
+        });
+
+        let mut mask = FailureMask::new(seals.len());
+        for index in failed {
+            mask.set(index);
+        }
+        mask
+    }
+}
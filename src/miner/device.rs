@@ -0,0 +1,437 @@
+//! Enumerating the GPUs of a rig and opening the ones it mines on.
+//!
+//! [`Device::enumerate`] lists every GPU the compiled-in backends can see,
+//! with its vendor, memory and largest buffer, so a rig with mixed cards can
+//! check which of them hold an epoch's dataset before uploading it.
+//! [`open_devices`] opens the enabled ones with their [`DeviceSettings`] and
+//! splits the nonce space between them with a [`HybridScheduler`].
+
+use std::fmt;
+
+use crate::chain::Chain;
+use crate::miner::backend::Miner;
+#[cfg(feature = "gpu-opencl")]
+use crate::miner::opencl::{OpenClError, OpenClMiner};
+use crate::miner::scheduler::HybridScheduler;
+#[cfg(feature = "gpu-wgpu")]
+use crate::miner::wgpu::WgpuError;
+#[cfg(all(feature = "gpu-wgpu", not(target_arch = "wasm32")))]
+use crate::miner::wgpu::{adapters, WgpuMiner};
+
+/// Default number of hashes computed per dispatch, as the backends use.
+pub const DEFAULT_INTENSITY: u64 = 1 << 14;
+
+/// Bytes of device memory left free beside the dataset, for the driver, the
+/// cached DAG words, the kernels and their result buffers.
+pub const MEMORY_HEADROOM: u64 = 128 << 20;
+
+/// The API a [`Device`] is driven through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Backend {
+    /// OpenCL, with the `gpu-opencl` feature.
+    OpenCl,
+    /// wgpu, with the `gpu-wgpu` feature.
+    Wgpu,
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Backend::OpenCl => write!(f, "OpenCL"),
+            Backend::Wgpu => write!(f, "wgpu"),
+        }
+    }
+}
+
+/// A GPU visible through one backend.
+///
+/// A card reachable through both backends is listed once for each.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Device {
+    /// The backend the device is listed by.
+    pub backend: Backend,
+    /// The index of the device within its backend, as its miner takes it.
+    pub index: usize,
+    /// The name the driver reports.
+    pub name: String,
+    /// The vendor, by name where it is known.
+    pub vendor: String,
+    /// The device's global memory in bytes, where the backend reports it.
+    pub memory_bytes: Option<u64>,
+    /// The largest single buffer the device can allocate and bind.
+    pub max_buffer_bytes: u64,
+}
+
+/// Returns the vendor named by a PCI vendor id, or the id in hex.
+pub fn vendor_name(pci_id: u32) -> String {
+    match pci_id {
+        0x10de => "NVIDIA".to_string(),
+        0x1002 | 0x1022 => "AMD".to_string(),
+        0x8086 => "Intel".to_string(),
+        0x106b => "Apple".to_string(),
+        0x13b5 => "ARM".to_string(),
+        0x5143 => "Qualcomm".to_string(),
+        id => format!("{id:#06x}"),
+    }
+}
+
+impl Device {
+    /// Lists the GPUs of every compiled-in backend, OpenCL first, each by
+    /// its index.
+    ///
+    /// A backend whose platform fails to load lists no device.
+    pub fn enumerate() -> Vec<Device> {
+        #[allow(unused_mut)]
+        let mut devices = Vec::new();
+        #[cfg(feature = "gpu-opencl")]
+        devices.extend(opencl_devices());
+        #[cfg(all(feature = "gpu-wgpu", not(target_arch = "wasm32")))]
+        devices.extend(wgpu_devices());
+        devices
+    }
+
+    /// Returns `true` if a `dataset_size`-byte dataset fits in one buffer of
+    /// the device and, where its memory is known, leaves [`MEMORY_HEADROOM`]
+    /// free.
+    pub fn fits(&self, dataset_size: u64) -> bool {
+        dataset_size <= self.max_buffer_bytes
+            && self
+                .memory_bytes
+                .is_none_or(|memory| dataset_size.saturating_add(MEMORY_HEADROOM) <= memory)
+    }
+
+    /// Returns the last epoch of `chain` whose dataset [`fits`](Self::fits).
+    ///
+    /// # Returns
+    ///
+    /// The epoch, `u64::MAX` if the dataset never grows past the device, or
+    /// `None` if not even epoch 0's fits.
+    pub fn max_epoch(&self, chain: &Chain) -> Option<u64> {
+        if !self.fits(chain.dataset_size(0)) {
+            return None;
+        }
+        if chain.dataset_bytes_growth == 0 {
+            return Some(u64::MAX);
+        }
+        // Sizes are rounded down from `init + growth * epoch`, so the epoch
+        // that linear size fits up to is a lower bound.
+        let budget = self.max_buffer_bytes.min(
+            self.memory_bytes
+                .map_or(u64::MAX, |memory| memory.saturating_sub(MEMORY_HEADROOM)),
+        );
+        let mut epoch =
+            budget.saturating_sub(chain.dataset_bytes_init) / chain.dataset_bytes_growth;
+        while self.fits(chain.dataset_size(epoch + 1)) {
+            epoch += 1;
+        }
+        Some(epoch)
+    }
+
+    /// Opens a miner on the device and uploads the dataset.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The intensity and work-group size to mine with.
+    /// * `size` - The size of the dataset in bytes.
+    /// * `c_dag` - The cached first words of the DAG.
+    /// * `lookup` - A function to retrieve memory segments based on an index.
+    ///
+    /// # Returns
+    ///
+    /// The miner, or a [`DeviceError`] if the dataset does not fit, the
+    /// device's backend is not compiled in, or the device cannot be set up.
+    pub fn open(
+        &self,
+        settings: &DeviceSettings,
+        size: u64,
+        c_dag: &[u32],
+        lookup: &dyn Fn(u32) -> Vec<u8>,
+    ) -> Result<Box<dyn Miner>, DeviceError> {
+        if !self.fits(size) {
+            return Err(DeviceError::DatasetTooLarge {
+                device: self.name.clone(),
+                size,
+            });
+        }
+        match self.backend {
+            #[cfg(feature = "gpu-opencl")]
+            Backend::OpenCl => {
+                let mut miner = OpenClMiner::new(self.index, size, c_dag, lookup)?;
+                if let Some(group_size) = settings.group_size {
+                    miner = miner.with_group_size(group_size);
+                }
+                Ok(Box::new(miner.with_batch_hashes(settings.intensity)))
+            }
+            #[cfg(all(feature = "gpu-wgpu", not(target_arch = "wasm32")))]
+            Backend::Wgpu => {
+                let mut miner =
+                    pollster::block_on(WgpuMiner::new(self.index, size, c_dag, lookup))?;
+                // The intensity is clamped against the work-group size.
+                if let Some(group_size) = settings.group_size {
+                    miner = miner.with_group_size(group_size);
+                }
+                Ok(Box::new(miner.with_batch_hashes(settings.intensity)))
+            }
+            #[allow(unreachable_patterns)]
+            backend => {
+                let _ = (settings, c_dag, lookup);
+                Err(DeviceError::Unsupported(backend))
+            }
+        }
+    }
+}
+
+/// Lists the OpenCL GPUs, skipping any whose properties cannot be read.
+#[cfg(feature = "gpu-opencl")]
+fn opencl_devices() -> Vec<Device> {
+    use opencl3::device::{get_all_devices, Device as ClDevice, CL_DEVICE_TYPE_GPU};
+
+    let ids = get_all_devices(CL_DEVICE_TYPE_GPU).unwrap_or_default();
+    ids.into_iter()
+        .enumerate()
+        .filter_map(|(index, id)| {
+            let device = ClDevice::new(id);
+            Some(Device {
+                backend: Backend::OpenCl,
+                index,
+                name: device.name().ok()?,
+                vendor: device.vendor().ok()?,
+                memory_bytes: Some(device.global_mem_size().ok()?),
+                max_buffer_bytes: device.max_mem_alloc_size().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Lists the wgpu adapters, by the PCI vendor id they report.
+#[cfg(all(feature = "gpu-wgpu", not(target_arch = "wasm32")))]
+fn wgpu_devices() -> Vec<Device> {
+    pollster::block_on(adapters())
+        .iter()
+        .enumerate()
+        .map(|(index, adapter)| {
+            let info = adapter.get_info();
+            let limits = adapter.limits();
+            Device {
+                backend: Backend::Wgpu,
+                index,
+                name: info.name,
+                vendor: vendor_name(info.vendor),
+                // WebGPU does not expose the memory size.
+                memory_bytes: None,
+                max_buffer_bytes: limits
+                    .max_storage_buffer_binding_size
+                    .min(limits.max_buffer_size),
+            }
+        })
+        .collect()
+}
+
+/// How one device of a rig mines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceSettings {
+    /// Whether [`open_devices`] opens the device.
+    pub enabled: bool,
+    /// The number of hashes computed per dispatch; the backend clamps it to
+    /// what the device supports.
+    pub intensity: u64,
+    /// The work-group size, or `None` for the backend's default.
+    pub group_size: Option<u32>,
+}
+
+impl Default for DeviceSettings {
+    /// Enabled, at [`DEFAULT_INTENSITY`] and the default work-group size.
+    fn default() -> Self {
+        DeviceSettings {
+            enabled: true,
+            intensity: DEFAULT_INTENSITY,
+            group_size: None,
+        }
+    }
+}
+
+impl DeviceSettings {
+    /// Returns settings leaving the device idle.
+    pub fn disabled() -> Self {
+        DeviceSettings {
+            enabled: false,
+            ..Self::default()
+        }
+    }
+
+    /// Sets the number of hashes computed per dispatch.
+    pub fn with_intensity(mut self, intensity: u64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    /// Sets the work-group size.
+    pub fn with_group_size(mut self, group_size: u32) -> Self {
+        self.group_size = Some(group_size);
+        self
+    }
+}
+
+/// Errors raised while opening a rig's devices.
+#[derive(Debug)]
+pub enum DeviceError {
+    /// No device was enabled.
+    NoDevices,
+    /// The device's backend is not compiled in.
+    Unsupported(Backend),
+    /// The dataset does not fit on the named device.
+    DatasetTooLarge {
+        /// The device's name.
+        device: String,
+        /// The size of the dataset in bytes.
+        size: u64,
+    },
+    /// The OpenCL backend failed to set up the device.
+    #[cfg(feature = "gpu-opencl")]
+    OpenCl(OpenClError),
+    /// The wgpu backend failed to set up the device.
+    #[cfg(feature = "gpu-wgpu")]
+    Wgpu(WgpuError),
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceError::NoDevices => write!(f, "no device is enabled"),
+            DeviceError::Unsupported(backend) => {
+                write!(f, "the {backend} backend is not compiled in")
+            }
+            DeviceError::DatasetTooLarge { device, size } => {
+                write!(f, "a {size}-byte dataset does not fit on {device}")
+            }
+            #[cfg(feature = "gpu-opencl")]
+            DeviceError::OpenCl(err) => write!(f, "{err}"),
+            #[cfg(feature = "gpu-wgpu")]
+            DeviceError::Wgpu(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for DeviceError {}
+
+#[cfg(feature = "gpu-opencl")]
+impl From<OpenClError> for DeviceError {
+    fn from(err: OpenClError) -> Self {
+        DeviceError::OpenCl(err)
+    }
+}
+
+#[cfg(feature = "gpu-wgpu")]
+impl From<WgpuError> for DeviceError {
+    fn from(err: WgpuError) -> Self {
+        DeviceError::Wgpu(err)
+    }
+}
+
+/// Opens every enabled device of a rig and schedules them together.
+///
+/// # Arguments
+///
+/// * `devices` - The rig's devices, each with its settings.
+/// * `size` - The size of the dataset in bytes.
+/// * `c_dag` - The cached first words of the DAG.
+/// * `lookup` - A function to retrieve memory segments based on an index.
+///
+/// # Returns
+///
+/// A [`HybridScheduler`] over the enabled devices, or the first
+/// [`DeviceError`]; [`DeviceError::NoDevices`] if none is enabled.
+pub fn open_devices(
+    devices: &[(Device, DeviceSettings)],
+    size: u64,
+    c_dag: &[u32],
+    lookup: &dyn Fn(u32) -> Vec<u8>,
+) -> Result<HybridScheduler, DeviceError> {
+    let miners = devices
+        .iter()
+        .filter(|(_, settings)| settings.enabled)
+        .map(|(device, settings)| device.open(settings, size, c_dag, lookup))
+        .collect::<Result<Vec<_>, _>>()?;
+    if miners.is_empty() {
+        return Err(DeviceError::NoDevices);
+    }
+    Ok(HybridScheduler::new(miners))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(memory_bytes: Option<u64>, max_buffer_bytes: u64) -> Device {
+        Device {
+            backend: Backend::OpenCl,
+            index: 0,
+            name: "test card".to_string(),
+            vendor: vendor_name(0x1002),
+            memory_bytes,
+            max_buffer_bytes,
+        }
+    }
+
+    #[test]
+    fn test_dataset_fit() {
+        let eight_gib = card(Some(8 << 30), 8 << 30);
+        assert!(eight_gib.fits(4 << 30));
+        assert!(eight_gib.fits((8 << 30) - MEMORY_HEADROOM));
+        assert!(!eight_gib.fits((8 << 30) - MEMORY_HEADROOM + 1));
+        // A card allocating a quarter of its memory at once holds less.
+        assert!(!card(Some(8 << 30), 2 << 30).fits(3 << 30));
+        assert!(card(None, 2 << 30).fits(2 << 30));
+
+        let chain = Chain::ethereum();
+        let epoch = eight_gib.max_epoch(&chain).unwrap();
+        assert!(eight_gib.fits(chain.dataset_size(epoch)));
+        assert!(!eight_gib.fits(chain.dataset_size(epoch + 1)));
+        assert_eq!(card(None, 512 << 20).max_epoch(&chain), None);
+
+        let flat = Chain {
+            dataset_bytes_growth: 0,
+            ..Chain::ethereum()
+        };
+        assert_eq!(eight_gib.max_epoch(&flat), Some(u64::MAX));
+    }
+
+    #[test]
+    fn test_vendor_names_and_settings() {
+        assert_eq!(vendor_name(0x10de), "NVIDIA");
+        assert_eq!(vendor_name(0x8086), "Intel");
+        assert_eq!(vendor_name(0x1234), "0x1234");
+
+        let settings = DeviceSettings::default();
+        assert!(settings.enabled);
+        assert_eq!(settings.intensity, DEFAULT_INTENSITY);
+        let tuned = DeviceSettings::disabled()
+            .with_intensity(1 << 12)
+            .with_group_size(128);
+        assert!(!tuned.enabled);
+        assert_eq!((tuned.intensity, tuned.group_size), (1 << 12, Some(128)));
+    }
+
+    #[test]
+    fn test_open_devices_checks_before_opening() {
+        let lookup = |_: u32| vec![0u8; 64];
+        let rig = [(card(Some(4 << 30), 4 << 30), DeviceSettings::disabled())];
+        assert!(matches!(
+            open_devices(&rig, 1 << 30, &[], &lookup),
+            Err(DeviceError::NoDevices)
+        ));
+        let rig = [(card(Some(4 << 30), 1 << 30), DeviceSettings::default())];
+        assert!(matches!(
+            open_devices(&rig, 2 << 30, &[], &lookup),
+            Err(DeviceError::DatasetTooLarge { size, .. }) if size == 2 << 30
+        ));
+        #[cfg(not(feature = "gpu-opencl"))]
+        assert!(matches!(
+            open_devices(&rig, 1 << 20, &[], &lookup),
+            Err(DeviceError::Unsupported(Backend::OpenCl))
+        ));
+    }
+}
@@ -0,0 +1,145 @@
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::basic_algorithm::PROGPOW_PERIOD_LENGTH;
+
+/// The last period any 64-bit block number can fall in.
+const LAST_PERIOD: u64 = u64::MAX / PROGPOW_PERIOD_LENGTH;
+
+/// A function building the kernel for a period.
+type Compiler<K, E> = dyn Fn(u64) -> Result<K, E> + Send + Sync;
+
+/// Holds the kernel for the current ProgPoW period and prepares the next one.
+///
+/// Each period runs a different random program, so a GPU backend must
+/// regenerate and recompile its kernel at every period boundary. Doing that
+/// on demand stalls mining for the whole compile. The cache instead starts
+/// compiling period `p + 1` on a background thread as soon as period `p` is
+/// in use, and switches to it, under a lock, on the first request for it.
+pub struct KernelCache<K, E> {
+    compile: Arc<Compiler<K, E>>,
+    state: Mutex<CacheState<K, E>>,
+}
+
+/// The kernel in use and the background compile of its successor.
+struct CacheState<K, E> {
+    current: Option<(u64, Arc<K>)>,
+    next: Option<(u64, JoinHandle<Result<K, E>>)>,
+}
+
+impl<K, E> KernelCache<K, E>
+where
+    K: Send + Sync + 'static,
+    E: Send + 'static,
+{
+    /// Creates an empty cache building kernels with `compile`.
+    ///
+    /// `compile` runs on the calling thread for the first period requested,
+    /// and on a background thread for every precompiled period.
+    pub fn new(compile: impl Fn(u64) -> Result<K, E> + Send + Sync + 'static) -> Self {
+        KernelCache {
+            compile: Arc::new(compile),
+            state: Mutex::new(CacheState {
+                current: None,
+                next: None,
+            }),
+        }
+    }
+
+    /// Returns the kernel for `period`, compiling it if it is not ready.
+    ///
+    /// If `period` was being precompiled, this waits for that compile rather
+    /// than starting another. Once the kernel is current, the compile of the
+    /// following period starts in the background.
+    ///
+    /// # Returns
+    ///
+    /// The kernel, or the error raised while compiling it. A failed
+    /// compile leaves the previous kernel current.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a background compile panicked.
+    pub fn get(&self, period: u64) -> Result<Arc<K>, E> {
+        let mut state = self.state.lock().unwrap();
+        if let Some((current, kernel)) = &state.current {
+            if *current == period {
+                return Ok(kernel.clone());
+            }
+        }
+
+        // A precompile for another period is stale; dropping it detaches it.
+        let kernel = match state.next.take() {
+            Some((next, handle)) if next == period => {
+                handle.join().expect("kernel precompile panicked")?
+            }
+            _ => (self.compile)(period)?,
+        };
+        let kernel = Arc::new(kernel);
+        state.current = Some((period, kernel.clone()));
+
+        if period < LAST_PERIOD {
+            let compile = self.compile.clone();
+            let next = period + 1;
+            state.next = Some((next, thread::spawn(move || compile(next))));
+        }
+        Ok(kernel)
+    }
+
+    /// Returns the period of the kernel currently in use, if any.
+    pub fn current_period(&self) -> Option<u64> {
+        let state = self.state.lock().unwrap();
+        state.current.as_ref().map(|(period, _)| *period)
+    }
+
+    /// Returns the period being precompiled in the background, if any.
+    pub fn precompiling(&self) -> Option<u64> {
+        let state = self.state.lock().unwrap();
+        state.next.as_ref().map(|(period, _)| *period)
+    }
+
+    /// Drops every kernel, for example after the launch configuration changed.
+    pub fn clear(&mut self) {
+        let state = self.state.get_mut().unwrap();
+        state.current = None;
+        state.next = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_kernel_cache_precompiles_next_period() {
+        let compiled = Arc::new(AtomicUsize::new(0));
+        let counter = compiled.clone();
+        let mut cache = KernelCache::new(move |period: u64| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            if period > LAST_PERIOD {
+                Err(format!("period {period} does not exist"))
+            } else {
+                Ok(period)
+            }
+        });
+        let first = LAST_PERIOD - 1;
+
+        assert_eq!(*cache.get(first).unwrap(), first);
+        assert_eq!(cache.current_period(), Some(first));
+        assert_eq!(cache.precompiling(), Some(LAST_PERIOD));
+        assert_eq!(*cache.get(first).unwrap(), first);
+
+        // The boundary reuses the background compile instead of a new one.
+        assert_eq!(*cache.get(LAST_PERIOD).unwrap(), LAST_PERIOD);
+        assert_eq!(compiled.load(Ordering::SeqCst), 2);
+        assert_eq!(cache.precompiling(), None);
+
+        // Failures keep the old kernel current.
+        assert!(cache.get(u64::MAX).is_err());
+        assert_eq!(cache.current_period(), Some(LAST_PERIOD));
+
+        cache.clear();
+        assert_eq!(cache.current_period(), None);
+    }
+}
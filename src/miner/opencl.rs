@@ -0,0 +1,594 @@
+use std::fmt;
+use std::ops::Range;
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+use opencl3::command_queue::CommandQueue;
+use opencl3::context::Context;
+use opencl3::device::{get_all_devices, Device, CL_DEVICE_TYPE_GPU};
+use opencl3::error_codes::ClError;
+use opencl3::kernel::{ExecuteKernel, Kernel};
+use opencl3::memory::{Buffer, CL_MEM_READ_ONLY, CL_MEM_READ_WRITE, CL_MEM_WRITE_ONLY};
+use opencl3::program::Program;
+use opencl3::types::CL_BLOCKING;
+
+use crate::basic_algorithm::{PROGPOW_CACHE_WORDS, PROGPOW_LANES, PROGPOW_PERIOD_LENGTH};
+use crate::endian::{from_le_layout, in_memory, le_bytes, le_words, read_le_words, to_le_layout};
+use crate::ethash::buffer::DagBuffer;
+use crate::ethash::dataset::HASH_WORDS;
+use crate::kernelgen::dag::opencl_dag_kernel;
+use crate::kernelgen::opencl::opencl_hash_kernel;
+use crate::kernelgen::source::KernelConfig;
+use crate::miner::backend::{Miner, Work};
+use crate::miner::kernel_cache::KernelCache;
+use crate::miner::tune::{Tunable, TuningConfig};
+use crate::miner::verify::{by_period, seal_words, FailureMask, SealVerifier, SEAL_WORDS};
+use crate::progpow::search::{meets_boundary, Solution};
+use crate::progpow::verify::Seal;
+
+/// Default number of hashes computed per kernel dispatch.
+pub const DEFAULT_BATCH_HASHES: u64 = 1 << 14;
+
+/// The `(mix_hash, final_hash)` pairs produced by one dispatch.
+type Hashes = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// Bytes of DAG uploaded per write while initializing a device.
+const DAG_UPLOAD_CHUNK: usize = 16 << 20;
+
+/// DAG items generated per dispatch by [`OpenClMiner::from_cache`].
+const DAG_GENERATION_ITEMS: u32 = 1 << 18;
+
+/// Errors raised while setting up or running the OpenCL backend.
+#[derive(Debug)]
+pub enum OpenClError {
+    /// No GPU exists at the requested index.
+    NoDevice(usize),
+    /// An OpenCL API call failed.
+    Cl(ClError),
+    /// The generated kernel failed to compile; holds the build log.
+    Build(String),
+}
+
+impl fmt::Display for OpenClError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpenClError::NoDevice(index) => write!(f, "no OpenCL GPU at index {index}"),
+            OpenClError::Cl(err) => write!(f, "OpenCL error: {err}"),
+            OpenClError::Build(log) => write!(f, "OpenCL kernel build failed: {log}"),
+        }
+    }
+}
+
+impl std::error::Error for OpenClError {}
+
+impl From<ClError> for OpenClError {
+    fn from(err: ClError) -> Self {
+        OpenClError::Cl(err)
+    }
+}
+
+/// Opens the GPU at `device_index` with a context and an in-order queue.
+fn open_device(device_index: usize) -> Result<(Device, Context, CommandQueue), OpenClError> {
+    let id = *get_all_devices(CL_DEVICE_TYPE_GPU)?
+        .get(device_index)
+        .ok_or(OpenClError::NoDevice(device_index))?;
+    let device = Device::new(id);
+    let context = Context::from_device(&device)?;
+    let queue = CommandQueue::create_default(&context, 0)?;
+    Ok((device, context, queue))
+}
+
+/// Returns the names of the OpenCL GPUs visible to this process, by index.
+pub fn device_names() -> Result<Vec<String>, OpenClError> {
+    get_all_devices(CL_DEVICE_TYPE_GPU)?
+        .into_iter()
+        .map(|id| Ok(Device::new(id).name()?))
+        .collect()
+}
+
+/// A mining backend running ProgPoW on an OpenCL GPU.
+///
+/// The DAG and cached DAG words are uploaded once at construction. The
+/// per-period kernel is generated and compiled on first use, and the next
+/// period's kernel is compiled in the background by a [`KernelCache`].
+pub struct OpenClMiner {
+    device_name: String,
+    context: Arc<Context>,
+    queue: CommandQueue,
+    dag: Buffer<u8>,
+    size: u64,
+    c_dag: Buffer<u32>,
+    config: KernelConfig,
+    kernels: KernelCache<Mutex<Kernels>, OpenClError>,
+    batch_hashes: u64,
+}
+
+/// The kernels compiled for one period.
+struct Kernels {
+    hash: Kernel,
+    verify: Kernel,
+}
+
+/// Creates the cache compiling each period's kernels for `config`.
+fn kernel_cache(
+    context: &Arc<Context>,
+    config: KernelConfig,
+) -> KernelCache<Mutex<Kernels>, OpenClError> {
+    let context = context.clone();
+    KernelCache::new(move |period| {
+        let source = opencl_hash_kernel(period, &config);
+        let program = Program::create_and_build_from_source(&context, &source, "")
+            .map_err(OpenClError::Build)?;
+        Ok(Mutex::new(Kernels {
+            hash: Kernel::create(&program, "progpow_hash")?,
+            verify: Kernel::create(&program, "progpow_verify")?,
+        }))
+    })
+}
+
+impl OpenClMiner {
+    /// Creates a backend on a GPU and uploads the dataset.
+    ///
+    /// # Arguments
+    ///
+    /// * `device_index` - The index of the GPU, as listed by [`device_names`].
+    /// * `size` - The size of the dataset in bytes.
+    /// * `c_dag` - The cached first words of the DAG.
+    /// * `lookup` - A function to retrieve memory segments based on an index.
+    ///
+    /// # Returns
+    ///
+    /// The backend, or an [`OpenClError`] if the device cannot be set up.
+    pub fn new(
+        device_index: usize,
+        size: u64,
+        c_dag: &[u32],
+        lookup: &dyn Fn(u32) -> Vec<u8>,
+    ) -> Result<Self, OpenClError> {
+        let (device, context, queue) = open_device(device_index)?;
+
+        // SAFETY: the buffers are created without a host pointer and written
+        // with blocking transfers from slices that outlive each call.
+        let dag = unsafe {
+            let mut dag =
+                Buffer::<u8>::create(&context, CL_MEM_READ_ONLY, size as usize, ptr::null_mut())?;
+            let mut chunk = Vec::with_capacity(DAG_UPLOAD_CHUNK);
+            let mut offset = 0;
+            // Materialize the dataset from 64-byte lookups, one chunk at a time.
+            for item in 0..(size / 64) as u32 {
+                chunk.extend_from_slice(&lookup(item * 16));
+                if chunk.len() >= DAG_UPLOAD_CHUNK {
+                    queue.enqueue_write_buffer(&mut dag, CL_BLOCKING, offset, &chunk, &[])?;
+                    offset += chunk.len();
+                    chunk.clear();
+                }
+            }
+            if !chunk.is_empty() {
+                queue.enqueue_write_buffer(&mut dag, CL_BLOCKING, offset, &chunk, &[])?;
+            }
+            dag
+        };
+        Self::from_dag(
+            device,
+            context,
+            queue,
+            dag,
+            &c_dag[..PROGPOW_CACHE_WORDS],
+            size,
+        )
+    }
+
+    /// Creates a backend on a GPU and generates the dataset on the device.
+    ///
+    /// Only the light cache crosses the bus: every DAG item is computed by the
+    /// `ethash_calculate_dag_item` kernel, and the cached DAG words are read
+    /// back from the result.
+    ///
+    /// # Arguments
+    ///
+    /// * `device_index` - The index of the GPU, as listed by [`device_names`].
+    /// * `size` - The size of the dataset in bytes.
+    /// * `cache` - The ethash light cache as little-endian words.
+    ///
+    /// # Returns
+    ///
+    /// The backend, or an [`OpenClError`] if the device cannot be set up.
+    pub fn from_cache(device_index: usize, size: u64, cache: &[u32]) -> Result<Self, OpenClError> {
+        let (device, context, queue) = open_device(device_index)?;
+        let program = Program::create_and_build_from_source(&context, &opencl_dag_kernel(), "")
+            .map_err(OpenClError::Build)?;
+        let kernel = Kernel::create(&program, "ethash_calculate_dag_item")?;
+        let items = (size / 64) as u32;
+        let mut c_dag = vec![0u32; PROGPOW_CACHE_WORDS];
+
+        // SAFETY: argument types and order match `ethash_calculate_dag_item`,
+        // and all transfers are blocking so the host slices outlive them.
+        let dag = unsafe {
+            let mut cache_buffer =
+                Buffer::<u32>::create(&context, CL_MEM_READ_ONLY, cache.len(), ptr::null_mut())?;
+            let cache = to_le_layout(cache);
+            queue.enqueue_write_buffer(&mut cache_buffer, CL_BLOCKING, 0, &cache, &[])?;
+            let dag = Buffer::<u8>::create(
+                &context,
+                CL_MEM_READ_WRITE,
+                in_memory(size),
+                ptr::null_mut(),
+            )?;
+
+            // Split generation so no single dispatch trips a display watchdog.
+            for start in (0..items).step_by(DAG_GENERATION_ITEMS as usize) {
+                let count = DAG_GENERATION_ITEMS.min(items - start);
+                ExecuteKernel::new(&kernel)
+                    .set_arg(&start)
+                    .set_arg(&cache_buffer)
+                    .set_arg(&((cache.len() / HASH_WORDS) as u32))
+                    .set_arg(&dag)
+                    .set_arg(&items)
+                    .set_global_work_size(count.div_ceil(64) as usize * 64)
+                    .set_local_work_size(64)
+                    .enqueue_nd_range(&queue)?;
+            }
+            let mut bytes = vec![0u8; PROGPOW_CACHE_WORDS * 4];
+            queue.enqueue_read_buffer(&dag, CL_BLOCKING, 0, &mut bytes, &[])?;
+            read_le_words(&bytes, &mut c_dag);
+            dag
+        };
+        Self::from_dag(device, context, queue, dag, &c_dag, size)
+    }
+
+    /// Uploads the cached DAG words and assembles the backend around `dag`.
+    fn from_dag(
+        device: Device,
+        context: Context,
+        queue: CommandQueue,
+        dag: Buffer<u8>,
+        c_dag: &[u32],
+        size: u64,
+    ) -> Result<Self, OpenClError> {
+        // SAFETY: the buffer is created without a host pointer and written
+        // with a blocking transfer from a slice that outlives the call.
+        let c_dag_buffer = unsafe {
+            let mut c_dag_buffer =
+                Buffer::<u32>::create(&context, CL_MEM_READ_ONLY, c_dag.len(), ptr::null_mut())?;
+            let c_dag = to_le_layout(c_dag);
+            queue.enqueue_write_buffer(&mut c_dag_buffer, CL_BLOCKING, 0, &c_dag, &[])?;
+            c_dag_buffer
+        };
+
+        let context = Arc::new(context);
+        let config = KernelConfig::for_dataset_size(size);
+        Ok(OpenClMiner {
+            device_name: device.name()?,
+            kernels: kernel_cache(&context, config),
+            context,
+            queue,
+            dag,
+            size,
+            c_dag: c_dag_buffer,
+            config,
+            batch_hashes: DEFAULT_BATCH_HASHES,
+        })
+    }
+
+    /// Sets the work-group size the kernel is compiled for.
+    ///
+    /// The size is rounded down to a multiple of `PROGPOW_LANES`.
+    pub fn with_group_size(mut self, group_size: u32) -> Self {
+        self.set_group_size(group_size);
+        self
+    }
+
+    /// Sets the number of hashes computed per kernel dispatch (at least one).
+    pub fn with_batch_hashes(mut self, batch_hashes: u64) -> Self {
+        self.set_batch_hashes(batch_hashes);
+        self
+    }
+
+    /// Rounds `group_size` to whole hashes and drops kernels built for the old size.
+    fn set_group_size(&mut self, group_size: u32) {
+        let lanes = PROGPOW_LANES as u32;
+        self.config.group_size = (group_size / lanes).max(1) * lanes;
+        self.kernels = kernel_cache(&self.context, self.config);
+    }
+
+    /// Sets the hashes per dispatch within the bounds of [`Self::with_batch_hashes`].
+    fn set_batch_hashes(&mut self, batch_hashes: u64) {
+        self.batch_hashes = batch_hashes.max(1);
+    }
+
+    /// Runs `job` with the kernels for `period`, building them if not ready.
+    fn with_kernels<T>(
+        &self,
+        period: u64,
+        job: impl FnOnce(&Kernels) -> Result<T, OpenClError>,
+    ) -> Result<T, OpenClError> {
+        let kernels = self.kernels.get(period)?;
+        // Kernel arguments are per-kernel state, so launches are serialized.
+        let kernels = kernels.lock().unwrap();
+        job(&kernels)
+    }
+
+    /// Work-items to launch for `hashes` hashes, rounded up to whole work-groups.
+    fn global_work_size(&self, hashes: usize) -> usize {
+        let hashes_per_group = (self.config.group_size as usize) / PROGPOW_LANES;
+        hashes.div_ceil(hashes_per_group) * hashes_per_group * PROGPOW_LANES
+    }
+
+    /// Runs one dispatch for `count` nonces starting at `start`.
+    fn dispatch(&self, work: &Work, start: u64, count: u64) -> Result<Hashes, OpenClError> {
+        let period = work.block_number / PROGPOW_PERIOD_LENGTH;
+        self.with_kernels(period, |kernels| {
+            self.dispatch_hash(&kernels.hash, work, start, count)
+        })
+    }
+
+    /// Enqueues `progpow_hash` for `count` nonces and reads back the hashes.
+    fn dispatch_hash(
+        &self,
+        kernel: &Kernel,
+        work: &Work,
+        start: u64,
+        count: u64,
+    ) -> Result<Hashes, OpenClError> {
+        // Round up to whole work-groups; the extra hashes are discarded.
+        let global_work_size = self.global_work_size(count as usize);
+        let header = to_le_layout(&le_words(&work.header_hash));
+        let mut out = vec![0u32; global_work_size / PROGPOW_LANES * 16];
+
+        // SAFETY: argument types and order match `progpow_hash`, and all
+        // transfers are blocking so the host slices outlive them.
+        unsafe {
+            let mut header_buffer =
+                Buffer::<u32>::create(&self.context, CL_MEM_READ_ONLY, 8, ptr::null_mut())?;
+            self.queue
+                .enqueue_write_buffer(&mut header_buffer, CL_BLOCKING, 0, &header, &[])?;
+            let out_buffer = Buffer::<u32>::create(
+                &self.context,
+                CL_MEM_WRITE_ONLY,
+                out.len(),
+                ptr::null_mut(),
+            )?;
+
+            ExecuteKernel::new(kernel)
+                .set_arg(&header_buffer)
+                .set_arg(&start)
+                .set_arg(&self.dag)
+                .set_arg(&self.c_dag)
+                .set_arg(&out_buffer)
+                .set_arg(&0u32)
+                .set_global_work_size(global_work_size)
+                .set_local_work_size(self.config.group_size as usize)
+                .enqueue_nd_range(&self.queue)?;
+            self.queue
+                .enqueue_read_buffer(&out_buffer, CL_BLOCKING, 0, &mut out, &[])?;
+        }
+        from_le_layout(&mut out);
+
+        Ok(out
+            .chunks(16)
+            .take(count as usize)
+            .map(|hash| (le_bytes(&hash[..8]), le_bytes(&hash[8..])))
+            .collect())
+    }
+
+    /// Checks the seals at `indices` in one dispatch, marking failures in `mask`.
+    fn dispatch_verify(
+        &self,
+        kernel: &Kernel,
+        seals: &[Seal],
+        indices: &[usize],
+        mask: &mut FailureMask,
+    ) -> Result<(), OpenClError> {
+        let words: Vec<u32> = indices
+            .iter()
+            .flat_map(|&index| seal_words(&seals[index]))
+            .map(u32::to_le)
+            .collect();
+        let mut failed = vec![0u32; indices.len().div_ceil(32)];
+
+        // SAFETY: argument types and order match `progpow_verify`, and all
+        // transfers are blocking so the host slices outlive them.
+        unsafe {
+            let mut seals_buffer = Buffer::<u32>::create(
+                &self.context,
+                CL_MEM_READ_ONLY,
+                indices.len() * SEAL_WORDS,
+                ptr::null_mut(),
+            )?;
+            self.queue
+                .enqueue_write_buffer(&mut seals_buffer, CL_BLOCKING, 0, &words, &[])?;
+            let mut failed_buffer = Buffer::<u32>::create(
+                &self.context,
+                CL_MEM_READ_WRITE,
+                failed.len(),
+                ptr::null_mut(),
+            )?;
+            self.queue
+                .enqueue_write_buffer(&mut failed_buffer, CL_BLOCKING, 0, &failed, &[])?;
+
+            ExecuteKernel::new(kernel)
+                .set_arg(&seals_buffer)
+                .set_arg(&(indices.len() as u32))
+                .set_arg(&self.dag)
+                .set_arg(&self.c_dag)
+                .set_arg(&failed_buffer)
+                .set_arg(&0u32)
+                .set_global_work_size(self.global_work_size(indices.len()))
+                .set_local_work_size(self.config.group_size as usize)
+                .enqueue_nd_range(&self.queue)?;
+            self.queue
+                .enqueue_read_buffer(&failed_buffer, CL_BLOCKING, 0, &mut failed, &[])?;
+        }
+        from_le_layout(&mut failed);
+
+        for (bit, &index) in indices.iter().enumerate() {
+            if failed[bit / 32] & (1 << (bit % 32)) != 0 {
+                mask.set(index);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The dataset resident on the device, read back one item at a time.
+impl DagBuffer for OpenClMiner {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the read from the device fails.
+    fn read_item(&self, index: u32, out: &mut [u8; 64]) {
+        // SAFETY: the read is blocking, so `out` outlives the transfer.
+        unsafe {
+            self.queue
+                .enqueue_read_buffer(&self.dag, CL_BLOCKING, index as usize * 64, out, &[])
+                .expect("OpenCL DAG read failed");
+        }
+    }
+}
+
+impl Tunable for OpenClMiner {
+    fn device_name(&self) -> String {
+        self.device_name.clone()
+    }
+
+    fn apply_tuning(&mut self, config: TuningConfig) {
+        self.set_group_size(config.group_size);
+        self.set_batch_hashes(config.batch_hashes);
+    }
+}
+
+impl Miner for OpenClMiner {
+    fn name(&self) -> String {
+        format!("opencl {}", self.device_name)
+    }
+
+    /// # Panics
+    ///
+    /// Panics if kernel compilation or a device call fails.
+    fn hash_batch(&self, work: &Work, nonces: Range<u64>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let mut out = Vec::new();
+        let mut start = nonces.start;
+        while start < nonces.end {
+            let count = (nonces.end - start).min(self.batch_hashes);
+            out.extend(
+                self.dispatch(work, start, count)
+                    .unwrap_or_else(|err| panic!("{}: {err}", self.name())),
+            );
+            start += count;
+        }
+        out
+    }
+
+    /// # Panics
+    ///
+    /// Panics if kernel compilation or a device call fails.
+    fn search(&self, work: &Work, nonces: Range<u64>) -> Option<Solution> {
+        let mut start = nonces.start;
+        while start < nonces.end {
+            let count = (nonces.end - start).min(self.batch_hashes);
+            let hashes = self
+                .dispatch(work, start, count)
+                .unwrap_or_else(|err| panic!("{}: {err}", self.name()));
+            // Candidates are confirmed on the host, lowest nonce first.
+            for (offset, (mix_hash, final_hash)) in hashes.into_iter().enumerate() {
+                if meets_boundary(&final_hash, &work.boundary) {
+                    return Some(Solution {
+                        nonce: start + offset as u64,
+                        mix_hash,
+                        final_hash,
+                    });
+                }
+            }
+            start += count;
+        }
+        None
+    }
+}
+
+impl SealVerifier for OpenClMiner {
+    /// Checks all seals of a period in a single dispatch.
+    ///
+    /// # Panics
+    ///
+    /// Panics if kernel compilation or a device call fails.
+    fn failures(&self, seals: &[Seal]) -> FailureMask {
+        let mut mask = FailureMask::new(seals.len());
+        for (period, indices) in by_period(seals) {
+            self.with_kernels(period, |kernels| {
+                self.dispatch_verify(&kernels.verify, seals, &indices, &mut mask)
+            })
+            .unwrap_or_else(|err| panic!("{}: {err}", self.name()));
+        }
+        mask
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ethash::buffer::LightDag;
+    use crate::ethash::dataset::{dataset_lookup, generate_c_dag};
+    use crate::miner::cpu::CpuMiner;
+    use crate::testutil::{header_hash, mock_c_dag, mock_lookup};
+
+    #[test]
+    fn test_opencl_matches_cpu() {
+        let (c_dag, lookup) = (mock_c_dag(1), mock_lookup(1));
+        let gpu = match OpenClMiner::new(0, 1024, &c_dag, &lookup) {
+            Ok(gpu) => gpu,
+            // Nothing to compare against on machines without a GPU.
+            Err(OpenClError::NoDevice(_)) | Err(OpenClError::Cl(_)) => return,
+            Err(err) => panic!("{err}"),
+        };
+        let cpu = CpuMiner::new(1024, c_dag, lookup, 1);
+        let work = Work {
+            header_hash: header_hash(1),
+            block_number: 100,
+            boundary: [0xff; 32],
+        };
+        assert_eq!(gpu.hash_batch(&work, 0..40), cpu.hash_batch(&work, 0..40));
+
+        let mut seals: Vec<Seal> = cpu
+            .hash_batch(&work, 0..40)
+            .into_iter()
+            .enumerate()
+            .map(|(nonce, (mix_hash, _))| Seal {
+                header_hash: work.header_hash,
+                block_number: work.block_number,
+                nonce: nonce as u64,
+                mix_hash: mix_hash.try_into().unwrap(),
+                boundary: [0xff; 32],
+            })
+            .collect();
+        seals[5].mix_hash[0] ^= 1;
+        seals[36].boundary = [0; 32];
+        assert_eq!(gpu.failures(&seals), cpu.failures(&seals));
+    }
+
+    #[test]
+    fn test_opencl_generates_dataset() {
+        let cache: Vec<u32> = (0..64 * HASH_WORDS as u32)
+            .map(|i| i.wrapping_mul(0x9e3779b9))
+            .collect();
+        let size = 16 * 1024;
+        let gpu = match OpenClMiner::from_cache(0, size, &cache) {
+            Ok(gpu) => gpu,
+            Err(OpenClError::NoDevice(_)) | Err(OpenClError::Cl(_)) => return,
+            Err(err) => panic!("{err}"),
+        };
+        let cpu = CpuMiner::new(size, generate_c_dag(&cache), dataset_lookup(&cache), 1);
+        let work = Work {
+            header_hash: [7; 32],
+            block_number: 100,
+            boundary: [0xff; 32],
+        };
+        assert_eq!(gpu.hash_batch(&work, 0..20), cpu.hash_batch(&work, 0..20));
+
+        let light = LightDag::new(cache.clone(), size);
+        assert_eq!(DagBuffer::size(&gpu), light.size());
+        assert_eq!(gpu.lookup(16 * 255), light.lookup(16 * 255));
+    }
+}
@@ -0,0 +1,412 @@
+//! A staged mining pipeline over crossbeam channels.
+//!
+//! Mining runs as five stages, each a function from one channel to the
+//! next that returns once its input closes:
+//!
+//! 1. [`intake`] numbers incoming work and pairs it with the [`Miner`] that
+//!    hashes it, for example one over the work's epoch dataset.
+//! 2. [`generate_nonces`] cuts the newest job's nonce space into batches,
+//!    switching to a new job as soon as one arrives.
+//! 3. [`hash_batches`] workers hash batches with their job's miner, dropping
+//!    batches of superseded jobs.
+//! 4. [`check_targets`] finds the hashes that meet their job's boundary and
+//!    records the hashrate.
+//! 5. Submission is the caller's: it takes each [`Found`] from
+//!    [`MinerPipeline::solutions`].
+//!
+//! Channels between stages are bounded, so a fast stage waits for a slow one
+//! instead of queueing without limit. [`MinerPipeline`] wires the stages
+//! together on their own threads; tests and other arrangements, such as a
+//! GPU hashing stage beside the CPU workers, run the stage functions over
+//! channels of their own.
+
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use crossbeam_channel::{bounded, select, Receiver, Sender};
+
+use crate::hashrate::HashrateMeter;
+use crate::miner::backend::{Miner, Work};
+use crate::progpow::search::{meets_boundary, Solution};
+
+/// Batches a stage may queue for the next per hashing worker.
+const BATCHES_PER_WORKER: usize = 2;
+
+/// Work numbered by intake, with the miner that hashes it.
+pub struct Job {
+    /// The job's position in the order work arrived, from 0.
+    pub id: u64,
+    /// The work being mined.
+    pub work: Work,
+    /// The miner hashing the job's nonces.
+    pub miner: Arc<dyn Miner>,
+}
+
+/// A range of nonces of one job.
+pub struct Batch {
+    pub job: Arc<Job>,
+    pub nonces: Range<u64>,
+}
+
+/// The hashes of a [`Batch`], in nonce order.
+pub struct Hashed {
+    pub job: Arc<Job>,
+    pub nonces: Range<u64>,
+    /// The `(mix_hash, final_hash)` pair of each nonce.
+    pub hashes: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+/// A nonce meeting its job's boundary.
+pub struct Found {
+    pub job: Arc<Job>,
+    pub solution: Solution,
+}
+
+/// Numbers each work from `works`, pairs it with `miner_for(&work)` and
+/// sends it on to `jobs`, setting `current` to its id once it is sent.
+pub fn intake(
+    works: Receiver<Work>,
+    jobs: Sender<Arc<Job>>,
+    current: &AtomicU64,
+    mut miner_for: impl FnMut(&Work) -> Arc<dyn Miner>,
+) {
+    for (id, work) in works.iter().enumerate() {
+        let miner = miner_for(&work);
+        let job = Arc::new(Job {
+            id: id as u64,
+            work,
+            miner,
+        });
+        if jobs.send(job).is_err() {
+            return;
+        }
+        current.store(id as u64, Ordering::Relaxed);
+    }
+}
+
+/// Sends consecutive batches of `batch_nonces` nonces of the newest job
+/// from `jobs` to `batches`, starting at `first_nonce` and going on from
+/// where the previous job stopped, wrapping at the end of the nonce space.
+pub fn generate_nonces(
+    jobs: Receiver<Arc<Job>>,
+    batches: Sender<Batch>,
+    batch_nonces: u64,
+    first_nonce: u64,
+) {
+    let Ok(mut job) = jobs.recv() else {
+        return;
+    };
+    let mut next = first_nonce;
+    loop {
+        let end = next.saturating_add(batch_nonces.max(1));
+        let batch = Batch {
+            job: job.clone(),
+            nonces: next..end,
+        };
+        select! {
+            recv(jobs) -> newer => match newer {
+                Ok(newer) => job = newer,
+                Err(_) => return,
+            },
+            send(batches, batch) -> sent => {
+                if sent.is_err() {
+                    return;
+                }
+                next = if end == u64::MAX { 0 } else { end };
+            }
+        }
+    }
+}
+
+/// Hashes each batch from `batches` with its job's miner, sending the
+/// hashes to `hashed` unless a newer job than the batch's is `current`.
+pub fn hash_batches(batches: Receiver<Batch>, hashed: Sender<Hashed>, current: &AtomicU64) {
+    for Batch { job, nonces } in batches {
+        if job.id < current.load(Ordering::Relaxed) {
+            continue;
+        }
+        let hashes = job.miner.hash_batch(&job.work, nonces.clone());
+        if hashed
+            .send(Hashed {
+                job,
+                nonces,
+                hashes,
+            })
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+/// Records the hashes from `hashed` in `meter` and sends every nonce whose
+/// final hash meets its job's boundary to `found`.
+pub fn check_targets(hashed: Receiver<Hashed>, found: Sender<Found>, meter: &HashrateMeter) {
+    for Hashed {
+        job,
+        nonces,
+        hashes,
+    } in hashed
+    {
+        meter.record(hashes.len() as u64);
+        for (nonce, (mix_hash, final_hash)) in nonces.zip(hashes) {
+            if meets_boundary(&final_hash, &job.work.boundary) {
+                let solution = Solution {
+                    nonce,
+                    mix_hash,
+                    final_hash,
+                };
+                if found
+                    .send(Found {
+                        job: job.clone(),
+                        solution,
+                    })
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// How a [`MinerPipeline`] is laid out.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PipelineConfig {
+    /// Hashing worker threads (at least one is used).
+    pub workers: usize,
+    /// Nonces per batch.
+    pub batch_nonces: u64,
+    /// The first nonce searched.
+    pub first_nonce: u64,
+}
+
+/// The stages of the module docs, running on their own threads.
+///
+/// Dropping the pipeline closes its work channel and waits for every stage
+/// to finish its batch.
+pub struct MinerPipeline {
+    works: Option<Sender<Work>>,
+    solutions: Receiver<Found>,
+    meter: Arc<HashrateMeter>,
+    stages: Vec<JoinHandle<()>>,
+}
+
+impl MinerPipeline {
+    /// Starts the stages, hashing each work with `miner_for(&work)` and
+    /// recording hashes in `meter`.
+    pub fn start(
+        config: PipelineConfig,
+        meter: Arc<HashrateMeter>,
+        miner_for: impl FnMut(&Work) -> Arc<dyn Miner> + Send + 'static,
+    ) -> Self {
+        let workers = config.workers.max(1);
+        let queue = workers * BATCHES_PER_WORKER;
+        let current = Arc::new(AtomicU64::new(0));
+        let (works, work_queue) = bounded(1);
+        let (jobs, job_queue) = bounded(1);
+        let (batches, batch_queue) = bounded(queue);
+        let (hashed, hashed_queue) = bounded(queue);
+        let (found, solutions) = bounded(queue);
+
+        let mut stages = Vec::with_capacity(workers + 3);
+        {
+            let current = current.clone();
+            stages.push(thread::spawn(move || {
+                intake(work_queue, jobs, &current, miner_for)
+            }));
+        }
+        stages.push(thread::spawn(move || {
+            generate_nonces(job_queue, batches, config.batch_nonces, config.first_nonce)
+        }));
+        for _ in 0..workers {
+            let (batch_queue, hashed, current) =
+                (batch_queue.clone(), hashed.clone(), current.clone());
+            stages.push(thread::spawn(move || {
+                hash_batches(batch_queue, hashed, &current)
+            }));
+        }
+        drop(hashed);
+        {
+            let meter = meter.clone();
+            stages.push(thread::spawn(move || {
+                check_targets(hashed_queue, found, &meter)
+            }));
+        }
+        MinerPipeline {
+            works: Some(works),
+            solutions,
+            meter,
+            stages,
+        }
+    }
+
+    /// Queues `work`, which replaces the work being mined once intake has
+    /// paired it with its miner.
+    pub fn push_work(&self, work: Work) {
+        self.works
+            .as_ref()
+            .expect("the pipeline is running")
+            .send(work)
+            .expect("the intake stage stopped");
+    }
+
+    /// Returns the channel of found nonces, for the submission stage.
+    pub fn solutions(&self) -> &Receiver<Found> {
+        &self.solutions
+    }
+
+    /// Returns the meter the hashes are recorded in.
+    pub fn meter(&self) -> &HashrateMeter {
+        &self.meter
+    }
+}
+
+impl Drop for MinerPipeline {
+    fn drop(&mut self) {
+        self.works = None;
+        // Unblock a target check waiting to send a solution; the stages
+        // before it then finish as their outputs close.
+        let (_, closed) = bounded(0);
+        drop(std::mem::replace(&mut self.solutions, closed));
+        for stage in self.stages.drain(..) {
+            let _ = stage.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::miner::cpu::CpuMiner;
+    use crate::progpow::search::{search, SearchStrategy};
+    use crate::testutil::{mock_c_dag, mock_lookup};
+
+    const SIZE: u64 = 1024;
+
+    fn cpu() -> Arc<dyn Miner> {
+        Arc::new(CpuMiner::new(SIZE, mock_c_dag(1), mock_lookup(1), 1))
+    }
+
+    fn work(first_byte: u8) -> Work {
+        let mut boundary = [0xffu8; 32];
+        boundary[0] = 0x07;
+        Work {
+            header_hash: [first_byte; 32],
+            block_number: 100,
+            boundary,
+        }
+    }
+
+    fn job(id: u64, work: Work) -> Arc<Job> {
+        Arc::new(Job {
+            id,
+            work,
+            miner: cpu(),
+        })
+    }
+
+    #[test]
+    fn test_stages_in_isolation() {
+        // Nonce generation: consecutive batches, then the newer job.
+        let (jobs, job_queue) = bounded(1);
+        let (batches, batch_queue) = bounded(0);
+        jobs.send(job(0, work(1))).unwrap();
+        let generator = thread::spawn(move || generate_nonces(job_queue, batches, 8, 100));
+        let first = batch_queue.recv().unwrap();
+        let second = batch_queue.recv().unwrap();
+        assert_eq!((first.nonces, second.nonces), (100..108, 108..116));
+        jobs.send(job(1, work(2))).unwrap();
+        let switched = batch_queue.iter().find(|batch| batch.job.id == 1).unwrap();
+        assert!(switched.nonces.start >= 116);
+        drop(jobs);
+        while batch_queue.recv().is_ok() {}
+        generator.join().unwrap();
+
+        // Hashing drops batches of superseded jobs.
+        let (batches, batch_queue) = bounded(4);
+        let (hashed, hashed_queue) = bounded(4);
+        batches
+            .send(Batch {
+                job: job(0, work(1)),
+                nonces: 0..4,
+            })
+            .unwrap();
+        batches
+            .send(Batch {
+                job: job(1, work(1)),
+                nonces: 4..8,
+            })
+            .unwrap();
+        drop(batches);
+        hash_batches(batch_queue, hashed, &AtomicU64::new(1));
+        let out: Vec<Hashed> = hashed_queue.iter().collect();
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].hashes, cpu().hash_batch(&work(1), 4..8));
+
+        // Target checks find what a sequential search finds.
+        let (hashed, hashed_queue) = bounded(1);
+        let (found, found_queue) = bounded(64);
+        let hashes = cpu().hash_batch(&work(1), 0..256);
+        hashed
+            .send(Hashed {
+                job: job(0, work(1)),
+                nonces: 0..256,
+                hashes,
+            })
+            .unwrap();
+        drop(hashed);
+        let meter = HashrateMeter::new(Duration::from_secs(60));
+        check_targets(hashed_queue, found, &meter);
+        assert_eq!(meter.total(), 256);
+        let expected = search(
+            &work(1).header_hash,
+            SIZE,
+            100,
+            &mock_c_dag(1),
+            &mock_lookup(1),
+            0..256,
+            &work(1).boundary,
+            SearchStrategy::Full,
+        );
+        assert_eq!(
+            found_queue.iter().next().map(|found| found.solution),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_pipeline_finds_solutions_of_the_newest_work() {
+        let meter = Arc::new(HashrateMeter::new(Duration::from_secs(60)));
+        let config = PipelineConfig {
+            workers: 2,
+            batch_nonces: 16,
+            first_nonce: 0,
+        };
+        let pipeline = MinerPipeline::start(config, meter, |_| cpu());
+        pipeline.push_work(work(1));
+        let found = pipeline.solutions().recv().unwrap();
+        assert_eq!(found.job.id, 0);
+        assert!(meets_boundary(
+            &found.solution.final_hash,
+            &work(1).boundary
+        ));
+
+        pipeline.push_work(work(2));
+        let found = pipeline
+            .solutions()
+            .iter()
+            .find(|found| found.job.id == 1)
+            .unwrap();
+        let nonce = found.solution.nonce;
+        assert_eq!(
+            cpu().hash_batch(&work(2), nonce..nonce + 1)[0].1,
+            found.solution.final_hash
+        );
+        assert!(pipeline.meter().total() > 0);
+    }
+}
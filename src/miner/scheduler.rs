@@ -0,0 +1,234 @@
+use std::ops::Range;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+use crate::miner::backend::{Miner, Work};
+use crate::progpow::search::Solution;
+
+/// Default number of nonces dispatched across all devices per round.
+pub const DEFAULT_ROUND_NONCES: u64 = 4096;
+
+/// Weight given to the newest hashrate sample when rebalancing.
+const HASHRATE_SMOOTHING: f64 = 0.5;
+
+/// A scheduler splitting the nonce space across several [`Miner`] backends.
+///
+/// Nonces are handed out in rounds. Each round is divided between the devices
+/// in proportion to their measured hashrate, so CPU workers and GPU devices
+/// finish their shares at roughly the same time. The scheduler is itself a
+/// [`Miner`] and can be used wherever a single backend is expected.
+pub struct HybridScheduler {
+    devices: Vec<Box<dyn Miner>>,
+    hashrates: Mutex<Vec<f64>>,
+    round_nonces: u64,
+}
+
+impl HybridScheduler {
+    /// Creates a scheduler over `devices`.
+    ///
+    /// Every device starts with the same weight until its first round has
+    /// been measured.
+    pub fn new(devices: Vec<Box<dyn Miner>>) -> Self {
+        let hashrates = Mutex::new(vec![1.0; devices.len()]);
+        HybridScheduler {
+            devices,
+            hashrates,
+            round_nonces: DEFAULT_ROUND_NONCES,
+        }
+    }
+
+    /// Sets the number of nonces dispatched per round (at least one).
+    pub fn with_round_nonces(mut self, round_nonces: u64) -> Self {
+        self.round_nonces = round_nonces.max(1);
+        self
+    }
+
+    /// Returns the smoothed hashrate of each device, in hashes per second.
+    pub fn hashrates(&self) -> Vec<f64> {
+        self.hashrates.lock().unwrap().clone()
+    }
+
+    /// Runs `job` on every device with its share of `nonces`, then updates the
+    /// hashrate estimates from the measured durations.
+    fn dispatch<T, F>(&self, nonces: Range<u64>, job: F) -> Vec<T>
+    where
+        T: Send,
+        F: Fn(&dyn Miner, Range<u64>) -> T + Sync,
+    {
+        let shares = split_range(nonces, &self.hashrates());
+        let job = &job;
+
+        let results: Vec<(T, u64, f64)> = thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .devices
+                .iter()
+                .zip(shares)
+                .map(|(device, share)| {
+                    scope.spawn(move || {
+                        let count = share.end - share.start;
+                        let started = Instant::now();
+                        let result = job(device.as_ref(), share);
+                        (result, count, started.elapsed().as_secs_f64())
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("mining device panicked"))
+                .collect()
+        });
+
+        let mut hashrates = self.hashrates.lock().unwrap();
+        for (rate, &(_, count, elapsed)) in hashrates.iter_mut().zip(&results) {
+            // Devices that received no work keep their previous estimate.
+            if count > 0 && elapsed > 0.0 {
+                let sample = count as f64 / elapsed;
+                *rate = HASHRATE_SMOOTHING * sample + (1.0 - HASHRATE_SMOOTHING) * *rate;
+            }
+        }
+        results.into_iter().map(|(result, _, _)| result).collect()
+    }
+
+    /// Splits `nonces` into consecutive rounds of at most `round_nonces`.
+    fn rounds(&self, nonces: Range<u64>) -> impl Iterator<Item = Range<u64>> {
+        let step = self.round_nonces;
+        let end = nonces.end;
+        let mut next = nonces.start;
+        std::iter::from_fn(move || {
+            if next >= end {
+                return None;
+            }
+            let round = next..next.saturating_add(step).min(end);
+            next = round.end;
+            Some(round)
+        })
+    }
+}
+
+impl Miner for HybridScheduler {
+    fn name(&self) -> String {
+        let names: Vec<String> = self.devices.iter().map(|device| device.name()).collect();
+        format!("hybrid [{}]", names.join(", "))
+    }
+
+    fn hash_batch(&self, work: &Work, nonces: Range<u64>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let mut out = Vec::new();
+        for round in self.rounds(nonces) {
+            // Shares are consecutive, so concatenating keeps nonce order.
+            for hashes in self.dispatch(round, |device, share| device.hash_batch(work, share)) {
+                out.extend(hashes);
+            }
+        }
+        out
+    }
+
+    fn search(&self, work: &Work, nonces: Range<u64>) -> Option<Solution> {
+        for round in self.rounds(nonces) {
+            let found = self
+                .dispatch(round, |device, share| device.search(work, share))
+                .into_iter()
+                .flatten()
+                .min_by_key(|solution| solution.nonce);
+            if found.is_some() {
+                return found;
+            }
+        }
+        None
+    }
+}
+
+/// Splits `nonces` into consecutive sub-ranges sized in proportion to `weights`.
+///
+/// The returned ranges are in the same order as `weights`, cover `nonces`
+/// exactly, and may be empty for devices with a negligible weight.
+pub(crate) fn split_range(nonces: Range<u64>, weights: &[f64]) -> Vec<Range<u64>> {
+    let total_nonces = nonces.end.saturating_sub(nonces.start);
+    let total_weight: f64 = weights.iter().filter(|w| w.is_finite() && **w > 0.0).sum();
+
+    let mut shares = Vec::with_capacity(weights.len());
+    let mut next = nonces.start;
+    let mut cumulative = 0.0;
+    for (i, &weight) in weights.iter().enumerate() {
+        let end = if i + 1 == weights.len() {
+            nonces.end.max(next)
+        } else {
+            if weight.is_finite() && weight > 0.0 {
+                cumulative += weight;
+            }
+            let fraction = if total_weight > 0.0 {
+                cumulative / total_weight
+            } else {
+                (i + 1) as f64 / weights.len() as f64
+            };
+            nonces.start + (total_nonces as f64 * fraction) as u64
+        };
+        let end = end.clamp(next, nonces.end.max(next));
+        shares.push(next..end);
+        next = end;
+    }
+    shares
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::miner::cpu::CpuMiner;
+    use crate::progpow::search::{search, SearchStrategy};
+    use crate::testutil::{header_hash, mock_c_dag, mock_lookup};
+
+    const SIZE: u64 = 1024;
+
+    fn cpu(threads: usize) -> Box<dyn Miner> {
+        Box::new(CpuMiner::new(SIZE, mock_c_dag(1), mock_lookup(1), threads))
+    }
+
+    fn work() -> Work {
+        let mut boundary = [0xffu8; 32];
+        boundary[0] = 0x07;
+        Work {
+            header_hash: header_hash(1),
+            block_number: 100,
+            boundary,
+        }
+    }
+
+    #[test]
+    fn test_split_range_is_proportional_and_complete() {
+        let shares = split_range(10..110, &[1.0, 3.0, 0.0, 1.0]);
+        assert_eq!(shares, vec![10..30, 30..90, 90..90, 90..110]);
+
+        let even = split_range(0..10, &[0.0, 0.0]);
+        assert_eq!(even, vec![0..5, 5..10]);
+    }
+
+    #[test]
+    fn test_hybrid_search_matches_sequential_search() {
+        let work = work();
+        let expected = search(
+            &work.header_hash,
+            SIZE,
+            work.block_number,
+            &mock_c_dag(1),
+            &mock_lookup(1),
+            0..512,
+            &work.boundary,
+            SearchStrategy::Full,
+        );
+        assert!(expected.is_some());
+
+        let scheduler = HybridScheduler::new(vec![cpu(2), cpu(1)]).with_round_nonces(48);
+        assert_eq!(scheduler.search(&work, 0..512), expected);
+        assert!(scheduler.hashrates().iter().all(|&rate| rate > 0.0));
+    }
+
+    #[test]
+    fn test_hybrid_hash_batch_keeps_nonce_order() {
+        let work = work();
+        let scheduler = HybridScheduler::new(vec![cpu(1), cpu(2)]).with_round_nonces(5);
+        assert_eq!(
+            scheduler.hash_batch(&work, 3..15),
+            cpu(1).hash_batch(&work, 3..15)
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::basic_algorithm::PROGPOW_PERIOD_LENGTH;
+use crate::miner::backend::{Miner, Work};
+
+/// Work-group sizes tried by [`AutoTuner::new`].
+const DEFAULT_GROUP_SIZES: [u32; 3] = [64, 128, 256];
+
+/// Hashes per dispatch tried by [`AutoTuner::new`].
+const DEFAULT_BATCH_HASHES: [u64; 3] = [1 << 12, 1 << 14, 1 << 16];
+
+/// Dispatches timed per candidate, after one warm-up dispatch.
+const SAMPLE_DISPATCHES: u64 = 2;
+
+/// The launch settings a GPU backend can be tuned with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TuningConfig {
+    /// The number of work-items per work-group.
+    pub group_size: u32,
+    /// The number of hashes computed per dispatch (the intensity).
+    pub batch_hashes: u64,
+}
+
+/// A backend whose launch settings can be changed at run time.
+pub trait Tunable: Miner {
+    /// The name of the device, identifying it in the tuning cache.
+    fn device_name(&self) -> String;
+
+    /// Applies `config`, rebuilding kernels on next use if needed.
+    ///
+    /// A backend may round the values to what its device supports.
+    fn apply_tuning(&mut self, config: TuningConfig);
+}
+
+/// Benchmarks launch settings per device and remembers the fastest.
+///
+/// Results are cached by device name and ProgPoW period, since each period
+/// compiles a different program whose best settings may differ. Devices
+/// sharing a model share the cached result.
+pub struct AutoTuner {
+    candidates: Vec<TuningConfig>,
+    best: Mutex<HashMap<(String, u64), TuningConfig>>,
+}
+
+impl Default for AutoTuner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AutoTuner {
+    /// Creates a tuner trying every pairing of a few common work-group sizes
+    /// and intensities.
+    pub fn new() -> Self {
+        let candidates = DEFAULT_GROUP_SIZES
+            .iter()
+            .flat_map(|&group_size| {
+                DEFAULT_BATCH_HASHES
+                    .iter()
+                    .map(move |&batch_hashes| TuningConfig {
+                        group_size,
+                        batch_hashes,
+                    })
+            })
+            .collect();
+        Self::with_candidates(candidates)
+    }
+
+    /// Creates a tuner trying exactly `candidates`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `candidates` is empty.
+    pub fn with_candidates(candidates: Vec<TuningConfig>) -> Self {
+        assert!(!candidates.is_empty(), "no tuning candidates");
+        AutoTuner {
+            candidates,
+            best: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the cached configuration for a device and period, if tuned.
+    pub fn cached(&self, device_name: &str, period: u64) -> Option<TuningConfig> {
+        self.best
+            .lock()
+            .unwrap()
+            .get(&(device_name.to_string(), period))
+            .copied()
+    }
+
+    /// Tunes `miner` for the period of `work` and applies the best settings.
+    ///
+    /// A cached result is applied without benchmarking. Otherwise every
+    /// candidate is applied in turn, warmed up with one dispatch so kernel
+    /// compilation is not timed, and measured over a few full dispatches.
+    ///
+    /// # Returns
+    ///
+    /// The configuration left applied to `miner`.
+    pub fn tune<M: Tunable>(&self, miner: &mut M, work: &Work) -> TuningConfig {
+        let key = (
+            miner.device_name(),
+            work.block_number / PROGPOW_PERIOD_LENGTH,
+        );
+        if let Some(config) = self.cached(&key.0, key.1) {
+            miner.apply_tuning(config);
+            return config;
+        }
+
+        let mut best = (self.candidates[0], 0.0);
+        for &config in &self.candidates {
+            miner.apply_tuning(config);
+            miner.hash_batch(work, 0..1);
+
+            let count = config.batch_hashes * SAMPLE_DISPATCHES;
+            let started = Instant::now();
+            miner.hash_batch(work, 0..count);
+            let hashrate = count as f64 / started.elapsed().as_secs_f64();
+            if hashrate > best.1 {
+                best = (config, hashrate);
+            }
+        }
+
+        miner.apply_tuning(best.0);
+        self.best.lock().unwrap().insert(key, best.0);
+        best.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progpow::search::Solution;
+    use std::ops::Range;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    /// A device whose speed depends only on its work-group size.
+    struct FakeDevice {
+        config: TuningConfig,
+        hashed: AtomicU64,
+    }
+
+    impl Miner for FakeDevice {
+        fn name(&self) -> String {
+            "fake".to_string()
+        }
+
+        fn hash_batch(&self, _work: &Work, nonces: Range<u64>) -> Vec<(Vec<u8>, Vec<u8>)> {
+            let count = nonces.end - nonces.start;
+            let cost = if self.config.group_size == 128 { 1 } else { 4 };
+            thread::sleep(Duration::from_micros(count * cost));
+            self.hashed.fetch_add(count, Ordering::Relaxed);
+            vec![(vec![0; 32], vec![0; 32]); count as usize]
+        }
+
+        fn search(&self, _work: &Work, _nonces: Range<u64>) -> Option<Solution> {
+            None
+        }
+    }
+
+    impl Tunable for FakeDevice {
+        fn device_name(&self) -> String {
+            "fake gpu".to_string()
+        }
+
+        fn apply_tuning(&mut self, config: TuningConfig) {
+            self.config = config;
+        }
+    }
+
+    #[test]
+    fn test_tuner_picks_fastest_and_caches() {
+        let candidates: Vec<TuningConfig> = [64, 128, 256]
+            .map(|group_size| TuningConfig {
+                group_size,
+                batch_hashes: 500,
+            })
+            .to_vec();
+        let tuner = AutoTuner::with_candidates(candidates.clone());
+        let mut device = FakeDevice {
+            config: candidates[0],
+            hashed: AtomicU64::new(0),
+        };
+        let work = Work {
+            header_hash: [0; 32],
+            block_number: 100,
+            boundary: [0xff; 32],
+        };
+
+        assert_eq!(tuner.tune(&mut device, &work), candidates[1]);
+        assert_eq!(device.config, candidates[1]);
+        assert_eq!(tuner.cached("fake gpu", 0), Some(candidates[1]));
+        assert_eq!(tuner.cached("fake gpu", 1), None);
+
+        // The second run is served from the cache without benchmarking.
+        let hashed = device.hashed.load(Ordering::Relaxed);
+        device.config = candidates[2];
+        assert_eq!(tuner.tune(&mut device, &work), candidates[1]);
+        assert_eq!(device.config, candidates[1]);
+        assert_eq!(device.hashed.load(Ordering::Relaxed), hashed);
+    }
+}
@@ -0,0 +1,249 @@
+use std::fmt;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::basic_algorithm::PROGPOW_LANES;
+use crate::hex;
+use crate::miner::backend::{Miner, Work};
+use crate::progpow::progpow::{progpow_from_seed, progpow_lane_hashes, progpow_seed};
+use crate::progpow::search::Solution;
+
+/// A device result that disagrees with the CPU reference.
+///
+/// Besides both results, it carries the reference's intermediate state, so a
+/// miscompiled kernel can be narrowed down to the seed, the mix loop, or the
+/// final reduction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Discrepancy {
+    /// The header hash of the work.
+    pub header_hash: [u8; 32],
+    /// The block number of the work.
+    pub block_number: u64,
+    /// The nonce whose result differs.
+    pub nonce: u64,
+    /// The seed computed by the reference.
+    pub seed: u64,
+    /// The per-lane hashes computed by the reference, before the final reduction.
+    pub lane_hashes: [u32; PROGPOW_LANES],
+    /// The reference `(mix_hash, final_hash)`.
+    pub expected: (Vec<u8>, Vec<u8>),
+    /// The `(mix_hash, final_hash)` reported by the device.
+    pub actual: (Vec<u8>, Vec<u8>),
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "device result differs for block {} nonce {:#x}",
+            self.block_number, self.nonce
+        )?;
+        writeln!(f, "  header hash:   {}", hex::encode(&self.header_hash))?;
+        writeln!(f, "  seed:          {:016x}", self.seed)?;
+        let lanes: Vec<String> = self
+            .lane_hashes
+            .iter()
+            .map(|lane| format!("{lane:08x}"))
+            .collect();
+        writeln!(f, "  lane hashes:   {}", lanes.join(" "))?;
+        writeln!(f, "  expected mix:  {}", hex::encode(&self.expected.0))?;
+        writeln!(f, "  actual mix:    {}", hex::encode(&self.actual.0))?;
+        writeln!(f, "  expected hash: {}", hex::encode(&self.expected.1))?;
+        write!(f, "  actual hash:   {}", hex::encode(&self.actual.1))
+    }
+}
+
+/// A [`Miner`] wrapper re-checking a sample of a device's results on the CPU.
+///
+/// Every nonce divisible by `every` that passes through [`Miner::hash_batch`],
+/// and every solution returned by [`Miner::search`], is recomputed with the
+/// reference implementation. Results are passed through unchanged; mismatches
+/// are collected as [`Discrepancy`] reports for the caller to inspect.
+pub struct CrossValidator<L> {
+    device: Box<dyn Miner>,
+    size: u64,
+    c_dag: Vec<u32>,
+    lookup: L,
+    every: u64,
+    checked: AtomicU64,
+    discrepancies: Mutex<Vec<Discrepancy>>,
+}
+
+impl<L> CrossValidator<L>
+where
+    L: Fn(u32) -> Vec<u8> + Send + Sync,
+{
+    /// Wraps `device`, checking one result in `every` (at least one).
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The backend being validated.
+    /// * `size` - The size of the dataset.
+    /// * `c_dag` - The cached first words of the DAG.
+    /// * `lookup` - A function to retrieve memory segments based on an index.
+    /// * `every` - The sampling interval, in nonces.
+    pub fn new(device: Box<dyn Miner>, size: u64, c_dag: Vec<u32>, lookup: L, every: u64) -> Self {
+        CrossValidator {
+            device,
+            size,
+            c_dag,
+            lookup,
+            every: every.max(1),
+            checked: AtomicU64::new(0),
+            discrepancies: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the number of device results recomputed so far.
+    pub fn checked(&self) -> u64 {
+        self.checked.load(Ordering::Relaxed)
+    }
+
+    /// Returns and clears the discrepancies found so far, in detection order.
+    pub fn take_discrepancies(&self) -> Vec<Discrepancy> {
+        std::mem::take(&mut *self.discrepancies.lock().unwrap())
+    }
+
+    /// Recomputes `nonce` on the CPU and records a mismatch with `actual`.
+    fn check(&self, work: &Work, nonce: u64, actual: (&[u8], &[u8])) {
+        self.checked.fetch_add(1, Ordering::Relaxed);
+        let seed = progpow_seed(&work.header_hash, nonce);
+        let expected = progpow_from_seed(
+            &work.header_hash,
+            seed,
+            self.size,
+            work.block_number,
+            &self.c_dag,
+            &self.lookup,
+        );
+        if (expected.0.as_slice(), expected.1.as_slice()) == actual {
+            return;
+        }
+
+        // The lane hashes are only worth a second pass once something is wrong.
+        let lane_hashes = progpow_lane_hashes(
+            seed,
+            self.size,
+            work.block_number,
+            &self.c_dag,
+            &self.lookup,
+        );
+        self.discrepancies.lock().unwrap().push(Discrepancy {
+            header_hash: work.header_hash,
+            block_number: work.block_number,
+            nonce,
+            seed,
+            lane_hashes,
+            expected,
+            actual: (actual.0.to_vec(), actual.1.to_vec()),
+        });
+    }
+}
+
+impl<L> Miner for CrossValidator<L>
+where
+    L: Fn(u32) -> Vec<u8> + Send + Sync,
+{
+    fn name(&self) -> String {
+        format!("validated {}", self.device.name())
+    }
+
+    fn hash_batch(&self, work: &Work, nonces: Range<u64>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let hashes = self.device.hash_batch(work, nonces.clone());
+        for (nonce, (mix_hash, final_hash)) in nonces.zip(&hashes) {
+            if nonce % self.every == 0 {
+                self.check(work, nonce, (mix_hash, final_hash));
+            }
+        }
+        hashes
+    }
+
+    fn search(&self, work: &Work, nonces: Range<u64>) -> Option<Solution> {
+        let solution = self.device.search(work, nonces)?;
+        self.check(
+            work,
+            solution.nonce,
+            (&solution.mix_hash, &solution.final_hash),
+        );
+        Some(solution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::miner::cpu::CpuMiner;
+    use crate::testutil::{header_hash, mock_c_dag, mock_lookup};
+
+    const SIZE: u64 = 1024;
+
+    /// A device that corrupts the mix hash of one nonce.
+    struct Miscompiled<L> {
+        reference: CpuMiner<L>,
+        bad_nonce: u64,
+    }
+
+    impl<L> Miner for Miscompiled<L>
+    where
+        L: Fn(u32) -> Vec<u8> + Send + Sync,
+    {
+        fn name(&self) -> String {
+            "miscompiled".to_string()
+        }
+
+        fn hash_batch(&self, work: &Work, nonces: Range<u64>) -> Vec<(Vec<u8>, Vec<u8>)> {
+            let mut hashes = self.reference.hash_batch(work, nonces.clone());
+            if nonces.contains(&self.bad_nonce) {
+                hashes[(self.bad_nonce - nonces.start) as usize].0[0] ^= 1;
+            }
+            hashes
+        }
+
+        fn search(&self, work: &Work, nonces: Range<u64>) -> Option<Solution> {
+            self.reference.search(work, nonces)
+        }
+    }
+
+    fn validator(
+        bad_nonce: u64,
+        every: u64,
+    ) -> CrossValidator<impl Fn(u32) -> Vec<u8> + Send + Sync> {
+        let c_dag = mock_c_dag(1);
+        let device = Miscompiled {
+            reference: CpuMiner::new(SIZE, c_dag.clone(), mock_lookup(1), 2),
+            bad_nonce,
+        };
+        CrossValidator::new(Box::new(device), SIZE, c_dag, mock_lookup(1), every)
+    }
+
+    #[test]
+    fn test_cross_validator_reports_sampled_mismatches() {
+        let work = Work {
+            header_hash: header_hash(1),
+            block_number: 100,
+            boundary: [0xff; 32],
+        };
+
+        let sampled = validator(10, 5);
+        let hashes = sampled.hash_batch(&work, 0..20);
+        assert_eq!(sampled.checked(), 4);
+        let found = sampled.take_discrepancies();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].nonce, 10);
+        assert_eq!(found[0].actual, hashes[10]);
+        assert_ne!(found[0].expected.0, found[0].actual.0);
+        assert_eq!(found[0].expected.1, found[0].actual.1);
+        assert!(found[0].to_string().contains("nonce 0xa"));
+        assert!(sampled.take_discrepancies().is_empty());
+
+        // Nonces between samples go unchecked.
+        let skipped = validator(7, 5);
+        skipped.hash_batch(&work, 0..20);
+        assert!(skipped.take_discrepancies().is_empty());
+
+        let solution = skipped.search(&work, 3..4).unwrap();
+        assert_eq!(solution.nonce, 3);
+        assert_eq!(skipped.checked(), 5);
+    }
+}
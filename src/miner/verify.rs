@@ -0,0 +1,192 @@
+#[cfg(any(feature = "gpu-opencl", feature = "gpu-wgpu"))]
+use std::collections::BTreeMap;
+
+#[cfg(any(feature = "gpu-opencl", feature = "gpu-wgpu"))]
+use crate::basic_algorithm::PROGPOW_PERIOD_LENGTH;
+use crate::miner::backend::Miner;
+use crate::progpow::verify::{verify_seal, Seal, SealError};
+
+/// Words per seal in the buffers uploaded to GPU verification kernels: the
+/// header hash, the nonce (low word first), the mix hash, and the boundary.
+#[cfg(any(feature = "gpu-opencl", feature = "gpu-wgpu"))]
+pub(crate) const SEAL_WORDS: usize = 26;
+
+/// A bitmask marking which seals of a batch failed verification.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FailureMask {
+    words: Vec<u32>,
+    len: usize,
+}
+
+impl FailureMask {
+    /// Creates a mask for `len` seals with none marked as failed.
+    pub fn new(len: usize) -> Self {
+        FailureMask {
+            words: vec![0; len.div_ceil(32)],
+            len,
+        }
+    }
+
+    /// Marks seal `index` as failed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of range.
+    pub fn set(&mut self, index: usize) {
+        assert!(index < self.len, "seal {index} out of range");
+        self.words[index / 32] |= 1 << (index % 32);
+    }
+
+    /// Returns `true` if seal `index` failed.
+    pub fn is_failed(&self, index: usize) -> bool {
+        index < self.len && self.words[index / 32] & (1 << (index % 32)) != 0
+    }
+
+    /// Returns the number of seals the mask covers.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the mask covers no seals.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the indices of the failed seals, in increasing order.
+    pub fn failed(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.len).filter(|&index| self.is_failed(index))
+    }
+
+    /// Returns the mask as little-endian bit words: bit `i % 32` of word
+    /// `i / 32` is set when seal `i` failed.
+    pub fn words(&self) -> &[u32] {
+        &self.words
+    }
+}
+
+/// A backend able to check many seals at once.
+///
+/// GPU backends validate a whole batch in one dispatch and only report which
+/// seals failed; [`verify_batch`] turns that into precise errors on the CPU.
+pub trait SealVerifier: Miner {
+    /// Checks every seal's mix hash and boundary.
+    ///
+    /// # Returns
+    ///
+    /// A mask with a bit set for every seal that failed.
+    fn failures(&self, seals: &[Seal]) -> FailureMask;
+}
+
+/// Verifies a batch of seals on `verifier`, re-checking failures on the CPU.
+///
+/// The CPU result is authoritative: every seal the verifier flags is
+/// recomputed with [`verify_seal`] to obtain its exact [`SealError`], and one
+/// the CPU accepts is not reported.
+///
+/// # Arguments
+///
+/// * `verifier` - The backend running the bulk check.
+/// * `seals` - The seals to verify; all must belong to the dataset below.
+/// * `size` - The size of the dataset.
+/// * `c_dag` - The cached first words of the DAG.
+/// * `lookup` - A function to retrieve memory segments based on an index.
+///
+/// # Returns
+///
+/// The index and error of every invalid seal, in index order.
+pub fn verify_batch(
+    verifier: &dyn SealVerifier,
+    seals: &[Seal],
+    size: u64,
+    c_dag: &[u32],
+    lookup: &dyn Fn(u32) -> Vec<u8>,
+) -> Vec<(usize, SealError)> {
+    verifier
+        .failures(seals)
+        .failed()
+        .filter_map(|index| {
+            verify_seal(&seals[index], size, c_dag, lookup)
+                .err()
+                .map(|err| (index, err))
+        })
+        .collect()
+}
+
+/// Serializes a seal into the [`SEAL_WORDS`] little-endian words read by the
+/// GPU verification kernels.
+#[cfg(any(feature = "gpu-opencl", feature = "gpu-wgpu"))]
+pub(crate) fn seal_words(seal: &Seal) -> [u32; SEAL_WORDS] {
+    let word =
+        |bytes: &[u8], i: usize| u32::from_le_bytes(bytes[4 * i..4 * i + 4].try_into().unwrap());
+    let mut words = [0u32; SEAL_WORDS];
+    for i in 0..8 {
+        words[i] = word(&seal.header_hash, i);
+        words[10 + i] = word(&seal.mix_hash, i);
+        words[18 + i] = word(&seal.boundary, i);
+    }
+    words[8] = seal.nonce as u32;
+    words[9] = (seal.nonce >> 32) as u32;
+    words
+}
+
+/// Groups seal indices by ProgPoW period, since each period needs its own kernel.
+#[cfg(any(feature = "gpu-opencl", feature = "gpu-wgpu"))]
+pub(crate) fn by_period(seals: &[Seal]) -> BTreeMap<u64, Vec<usize>> {
+    let mut periods: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+    for (index, seal) in seals.iter().enumerate() {
+        periods
+            .entry(seal.block_number / PROGPOW_PERIOD_LENGTH)
+            .or_default()
+            .push(index);
+    }
+    periods
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::miner::cpu::CpuMiner;
+    use crate::progpow::progpow::progpow;
+    use crate::testutil::{header_hash, mock_c_dag, mock_lookup};
+
+    const SIZE: u64 = 1024;
+
+    #[test]
+    fn test_failure_mask_bits() {
+        let mut mask = FailureMask::new(40);
+        mask.set(0);
+        mask.set(33);
+        assert_eq!(mask.words(), &[1, 2]);
+        assert_eq!(mask.failed().collect::<Vec<_>>(), vec![0, 33]);
+        assert!(!mask.is_failed(40));
+    }
+
+    #[test]
+    fn test_verify_batch_reports_precise_errors() {
+        let (c_dag, lookup) = (mock_c_dag(1), mock_lookup(1));
+        let mut seals: Vec<Seal> = (0..20u64)
+            .map(|nonce| {
+                let header_hash = header_hash(nonce);
+                let (mix_hash, _) = progpow(&header_hash, nonce, SIZE, 100, &c_dag, &lookup);
+                Seal {
+                    header_hash,
+                    block_number: 100,
+                    nonce,
+                    mix_hash: mix_hash.try_into().unwrap(),
+                    boundary: [0xff; 32],
+                }
+            })
+            .collect();
+        seals[3].mix_hash[0] ^= 0x80;
+        seals[17].boundary = [0; 32];
+
+        let cpu = CpuMiner::new(SIZE, c_dag.clone(), mock_lookup(1), 3);
+        let mask = cpu.failures(&seals);
+        assert_eq!(mask.failed().collect::<Vec<_>>(), vec![3, 17]);
+
+        let errors = verify_batch(&cpu, &seals, SIZE, &c_dag, &lookup);
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0], (3, SealError::MixMismatch { .. })));
+        assert!(matches!(errors[1], (17, SealError::BoundaryNotMet { .. })));
+    }
+}
@@ -0,0 +1,598 @@
+use std::fmt;
+use std::future::Future;
+use std::ops::Range;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use wgpu::{
+    Adapter, BindGroupDescriptor, BindGroupEntry, Buffer, BufferAsyncError, BufferDescriptor,
+    BufferUsages, CommandEncoderDescriptor, ComputePassDescriptor, ComputePipeline,
+    ComputePipelineDescriptor, Device, DeviceDescriptor, ErrorFilter, Instance, InstanceDescriptor,
+    MapMode, PipelineCompilationOptions, PollType, Queue, RequestDeviceError,
+    ShaderModuleDescriptor, ShaderSource,
+};
+
+use crate::basic_algorithm::{PROGPOW_CACHE_WORDS, PROGPOW_LANES, PROGPOW_PERIOD_LENGTH};
+use crate::endian::le_bytes;
+use crate::kernelgen::source::KernelConfig;
+use crate::kernelgen::wgsl::wgsl_kernel;
+use crate::miner::backend::{Miner, Work};
+use crate::miner::tune::{Tunable, TuningConfig};
+use crate::miner::verify::{by_period, seal_words, FailureMask, SealVerifier};
+use crate::progpow::search::{meets_boundary, Solution};
+use crate::progpow::verify::Seal;
+
+/// Default number of hashes computed per dispatch.
+pub const DEFAULT_BATCH_HASHES: u64 = 1 << 14;
+
+/// The `(mix_hash, final_hash)` pairs produced by one dispatch.
+type Hashes = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// Bytes of DAG uploaded per write while initializing a device.
+const DAG_UPLOAD_CHUNK: usize = 16 << 20;
+
+/// Size of the shader's `Params` uniform: eight header words, the start
+/// nonce, and padding to a 16-byte multiple.
+const PARAMS_BYTES: usize = 48;
+
+/// The most work-groups a single dispatch may launch along one dimension.
+const MAX_WORKGROUPS: u64 = 65535;
+
+/// Errors raised while setting up or running the wgpu backend.
+#[derive(Debug)]
+pub enum WgpuError {
+    /// No adapter exists at the requested index.
+    NoAdapter(usize),
+    /// The adapter refused to create a device.
+    RequestDevice(RequestDeviceError),
+    /// The dataset exceeds the largest storage buffer the device can bind.
+    DatasetTooLarge(u64),
+    /// The generated shader or its pipeline failed validation.
+    Shader(String),
+    /// Reading the results back from the device failed.
+    Map(BufferAsyncError),
+}
+
+impl fmt::Display for WgpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WgpuError::NoAdapter(index) => write!(f, "no wgpu adapter at index {index}"),
+            WgpuError::RequestDevice(err) => write!(f, "wgpu device request failed: {err}"),
+            WgpuError::DatasetTooLarge(size) => {
+                write!(
+                    f,
+                    "a {size}-byte dataset does not fit in one storage buffer"
+                )
+            }
+            WgpuError::Shader(log) => write!(f, "wgpu shader validation failed: {log}"),
+            WgpuError::Map(err) => write!(f, "wgpu buffer read-back failed: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for WgpuError {}
+
+impl From<RequestDeviceError> for WgpuError {
+    fn from(err: RequestDeviceError) -> Self {
+        WgpuError::RequestDevice(err)
+    }
+}
+
+/// Lists the adapters visible to this process, honoring `WGPU_BACKEND`.
+pub(crate) async fn adapters() -> Vec<Adapter> {
+    let instance = Instance::new(InstanceDescriptor::new_without_display_handle_from_env());
+    instance.enumerate_adapters(wgpu::Backends::all()).await
+}
+
+/// Returns the names of the wgpu adapters visible to this process, by index.
+pub async fn adapter_names() -> Vec<String> {
+    adapters()
+        .await
+        .iter()
+        .map(|adapter| adapter.get_info().name)
+        .collect()
+}
+
+/// A mining backend running ProgPoW through wgpu on any WebGPU-capable GPU.
+///
+/// This backend is experimental. It needs no vendor SDK: the per-period
+/// program is generated as WGSL and wgpu translates it for Vulkan, Metal,
+/// DirectX 12, OpenGL, or the browser's WebGPU. Its methods are `async` so
+/// they also work on the web; the [`Miner`] implementation blocks on them and
+/// is only available natively.
+pub struct WgpuMiner {
+    adapter_name: String,
+    device: Device,
+    queue: Queue,
+    dag: Buffer,
+    c_dag: Buffer,
+    config: KernelConfig,
+    pipelines: Mutex<Option<Pipelines>>,
+    batch_hashes: u64,
+}
+
+/// The pipelines built for one period.
+#[derive(Clone)]
+struct Pipelines {
+    period: u64,
+    hash: ComputePipeline,
+    verify: ComputePipeline,
+}
+
+impl WgpuMiner {
+    /// Creates a backend on an adapter and uploads the dataset.
+    ///
+    /// # Arguments
+    ///
+    /// * `adapter_index` - The index of the adapter, as listed by [`adapter_names`].
+    /// * `size` - The size of the dataset in bytes.
+    /// * `c_dag` - The cached first words of the DAG.
+    /// * `lookup` - A function to retrieve memory segments based on an index.
+    ///
+    /// # Returns
+    ///
+    /// The backend, or a [`WgpuError`] if the device cannot be set up.
+    pub async fn new(
+        adapter_index: usize,
+        size: u64,
+        c_dag: &[u32],
+        lookup: &dyn Fn(u32) -> Vec<u8>,
+    ) -> Result<Self, WgpuError> {
+        let adapter = adapters()
+            .await
+            .into_iter()
+            .nth(adapter_index)
+            .ok_or(WgpuError::NoAdapter(adapter_index))?;
+        // The cached DAG alone fills the default 16 KiB of workgroup memory,
+        // so ask for everything the adapter offers.
+        let limits = adapter.limits();
+        if size > limits.max_storage_buffer_binding_size as u64 || size > limits.max_buffer_size {
+            return Err(WgpuError::DatasetTooLarge(size));
+        }
+        let (device, queue) = adapter
+            .request_device(&DeviceDescriptor {
+                label: Some("progpow"),
+                required_limits: limits,
+                ..Default::default()
+            })
+            .await?;
+
+        let dag = device.create_buffer(&BufferDescriptor {
+            label: Some("progpow dag"),
+            size,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut chunk = Vec::with_capacity(DAG_UPLOAD_CHUNK);
+        let mut offset = 0;
+        // Materialize the dataset from 64-byte lookups, one chunk at a time.
+        for item in 0..(size / 64) as u32 {
+            chunk.extend_from_slice(&lookup(item * 16));
+            if chunk.len() >= DAG_UPLOAD_CHUNK {
+                queue.write_buffer(&dag, offset, &chunk);
+                queue.submit([]);
+                offset += chunk.len() as u64;
+                chunk.clear();
+            }
+        }
+        if !chunk.is_empty() {
+            queue.write_buffer(&dag, offset, &chunk);
+        }
+
+        let c_dag_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("progpow c_dag"),
+            size: (PROGPOW_CACHE_WORDS * 4) as u64,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(&c_dag_buffer, 0, &le_bytes(&c_dag[..PROGPOW_CACHE_WORDS]));
+        queue.submit([]);
+
+        Ok(WgpuMiner {
+            adapter_name: adapter.get_info().name,
+            device,
+            queue,
+            dag,
+            c_dag: c_dag_buffer,
+            config: KernelConfig::for_dataset_size(size),
+            pipelines: Mutex::new(None),
+            batch_hashes: DEFAULT_BATCH_HASHES,
+        })
+    }
+
+    /// Sets the work-group size the shader is compiled for.
+    ///
+    /// The size is rounded down to a multiple of `PROGPOW_LANES`.
+    pub fn with_group_size(mut self, group_size: u32) -> Self {
+        self.set_group_size(group_size);
+        self
+    }
+
+    /// Sets the number of hashes computed per dispatch.
+    ///
+    /// The value is clamped so a dispatch stays within the work-group count
+    /// every WebGPU device supports.
+    pub fn with_batch_hashes(mut self, batch_hashes: u64) -> Self {
+        self.set_batch_hashes(batch_hashes);
+        self
+    }
+
+    /// Rounds `group_size` to whole hashes and drops kernels built for the old size.
+    fn set_group_size(&mut self, group_size: u32) {
+        let lanes = PROGPOW_LANES as u32;
+        self.config.group_size = (group_size / lanes).max(1) * lanes;
+        *self.pipelines.get_mut().unwrap() = None;
+    }
+
+    /// Sets the hashes per dispatch within the bounds of [`Self::with_batch_hashes`].
+    fn set_batch_hashes(&mut self, batch_hashes: u64) {
+        let hashes_per_group = self.config.group_size as u64 / PROGPOW_LANES as u64;
+        self.batch_hashes = batch_hashes.clamp(1, MAX_WORKGROUPS * hashes_per_group);
+    }
+
+    /// Returns the pipelines for `period`, building them on first use.
+    async fn pipelines(&self, period: u64) -> Result<Pipelines, WgpuError> {
+        if let Some(pipelines) = self.pipelines.lock().unwrap().as_ref() {
+            if pipelines.period == period {
+                return Ok(pipelines.clone());
+            }
+        }
+
+        let scope = self.device.push_error_scope(ErrorFilter::Validation);
+        let module = self.device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("progpow"),
+            source: ShaderSource::Wgsl(wgsl_kernel(period, &self.config).into()),
+        });
+        let pipeline = |entry_point| {
+            self.device
+                .create_compute_pipeline(&ComputePipelineDescriptor {
+                    label: Some(entry_point),
+                    layout: None,
+                    module: &module,
+                    entry_point: Some(entry_point),
+                    compilation_options: PipelineCompilationOptions {
+                        // The kernel writes all workgroup memory before reading it.
+                        zero_initialize_workgroup_memory: false,
+                        ..Default::default()
+                    },
+                    cache: None,
+                })
+        };
+        let pipelines = Pipelines {
+            period,
+            hash: pipeline("progpow_hash"),
+            verify: pipeline("progpow_verify"),
+        };
+        if let Some(err) = scope.pop().await {
+            return Err(WgpuError::Shader(err.to_string()));
+        }
+
+        *self.pipelines.lock().unwrap() = Some(pipelines.clone());
+        Ok(pipelines)
+    }
+
+    /// Creates a storage buffer holding `contents`.
+    fn storage_buffer(&self, label: &str, contents: &[u8], usage: BufferUsages) -> Buffer {
+        let buffer = self.device.create_buffer(&BufferDescriptor {
+            label: Some(label),
+            size: contents.len() as u64,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | usage,
+            mapped_at_creation: false,
+        });
+        self.queue.write_buffer(&buffer, 0, contents);
+        buffer
+    }
+
+    /// Dispatches `groups` work-groups of `pipeline` and reads `output` back.
+    ///
+    /// `bindings` are the entry point's bindings besides the DAG and cached
+    /// DAG words, which are always bound.
+    async fn run(
+        &self,
+        pipeline: &ComputePipeline,
+        bindings: &[(u32, &Buffer)],
+        groups: u64,
+        output: &Buffer,
+    ) -> Result<Vec<u8>, WgpuError> {
+        let mut entries = vec![
+            BindGroupEntry {
+                binding: 1,
+                resource: self.dag.as_entire_binding(),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: self.c_dag.as_entire_binding(),
+            },
+        ];
+        entries.extend(bindings.iter().map(|&(binding, buffer)| BindGroupEntry {
+            binding,
+            resource: buffer.as_entire_binding(),
+        }));
+        let bind_group = self.device.create_bind_group(&BindGroupDescriptor {
+            label: Some("progpow"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &entries,
+        });
+        let readback = self.device.create_buffer(&BufferDescriptor {
+            label: Some("progpow readback"),
+            size: output.size(),
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(groups as u32, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(output, 0, &readback, 0, output.size());
+        self.queue.submit([encoder.finish()]);
+
+        let mapped = MapFuture::default();
+        let state = Arc::clone(&mapped.0);
+        readback.map_async(MapMode::Read, .., move |result| {
+            let mut state = state.lock().unwrap();
+            state.0 = Some(result);
+            if let Some(waker) = state.1.take() {
+                waker.wake();
+            }
+        });
+        // Natively this drives the map callback; on the web it returns at once
+        // and the browser completes the mapping.
+        let _ = self.device.poll(PollType::wait_indefinitely());
+        mapped.await.map_err(WgpuError::Map)?;
+
+        let bytes = readback
+            .get_mapped_range(..)
+            .expect("readback buffer is mapped")
+            .to_vec();
+        readback.unmap();
+        Ok(bytes)
+    }
+
+    /// Hashes handled by one work-group.
+    fn hashes_per_group(&self) -> u64 {
+        self.config.group_size as u64 / PROGPOW_LANES as u64
+    }
+
+    /// Computes the `(mix_hash, final_hash)` pair of `count` nonces starting at `start`.
+    ///
+    /// `count` is capped at the batch size set by [`Self::with_batch_hashes`].
+    ///
+    /// # Returns
+    ///
+    /// One entry per nonce, in nonce order.
+    pub async fn hash(&self, work: &Work, start: u64, count: u64) -> Result<Hashes, WgpuError> {
+        let count = count.min(self.batch_hashes);
+        let pipelines = self
+            .pipelines(work.block_number / PROGPOW_PERIOD_LENGTH)
+            .await?;
+
+        // Round up to whole work-groups; the extra hashes are discarded.
+        let groups = count.div_ceil(self.hashes_per_group());
+
+        let mut params = [0u8; PARAMS_BYTES];
+        params[..32].copy_from_slice(&work.header_hash);
+        params[32..40].copy_from_slice(&start.to_le_bytes());
+        let params_buffer = self.device.create_buffer(&BufferDescriptor {
+            label: Some("progpow params"),
+            size: PARAMS_BYTES as u64,
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        self.queue.write_buffer(&params_buffer, 0, &params);
+        let out = self.device.create_buffer(&BufferDescriptor {
+            label: Some("progpow out"),
+            size: groups * self.hashes_per_group() * 64,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let bytes = self
+            .run(
+                &pipelines.hash,
+                &[(0, &params_buffer), (3, &out)],
+                groups,
+                &out,
+            )
+            .await?;
+        Ok(bytes
+            .chunks(64)
+            .take(count as usize)
+            .map(|hash| (hash[..32].to_vec(), hash[32..].to_vec()))
+            .collect())
+    }
+
+    /// Checks a batch of seals, one dispatch per period where the work-group
+    /// limit allows.
+    ///
+    /// # Returns
+    ///
+    /// A mask with a bit set for every seal whose mix hash or boundary check failed.
+    pub async fn verify(&self, seals: &[Seal]) -> Result<FailureMask, WgpuError> {
+        let mut mask = FailureMask::new(seals.len());
+        let per_dispatch = (MAX_WORKGROUPS * self.hashes_per_group()) as usize;
+        for (period, indices) in by_period(seals) {
+            let pipelines = self.pipelines(period).await?;
+            for chunk in indices.chunks(per_dispatch) {
+                let words: Vec<u8> = chunk
+                    .iter()
+                    .flat_map(|&index| seal_words(&seals[index]))
+                    .flat_map(|word| word.to_le_bytes())
+                    .collect();
+                let seals_buffer =
+                    self.storage_buffer("progpow seals", &words, BufferUsages::empty());
+                let failed = self.storage_buffer(
+                    "progpow failed",
+                    &vec![0; chunk.len().div_ceil(32) * 4],
+                    BufferUsages::COPY_SRC,
+                );
+
+                let groups = (chunk.len() as u64).div_ceil(self.hashes_per_group());
+                let bytes = self
+                    .run(
+                        &pipelines.verify,
+                        &[(4, &seals_buffer), (5, &failed)],
+                        groups,
+                        &failed,
+                    )
+                    .await?;
+                for (bit, &index) in chunk.iter().enumerate() {
+                    if bytes[bit / 8] & (1 << (bit % 8)) != 0 {
+                        mask.set(index);
+                    }
+                }
+            }
+        }
+        Ok(mask)
+    }
+}
+
+/// Resolves once a `map_async` callback has fired.
+#[derive(Default)]
+struct MapFuture(Arc<Mutex<MapState>>);
+
+type MapState = (Option<Result<(), BufferAsyncError>>, Option<Waker>);
+
+impl Future for MapFuture {
+    type Output = Result<(), BufferAsyncError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.0.lock().unwrap();
+        match state.0.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.1 = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Tunable for WgpuMiner {
+    fn device_name(&self) -> String {
+        self.adapter_name.clone()
+    }
+
+    fn apply_tuning(&mut self, config: TuningConfig) {
+        self.set_group_size(config.group_size);
+        self.set_batch_hashes(config.batch_hashes);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Miner for WgpuMiner {
+    fn name(&self) -> String {
+        format!("wgpu {}", self.adapter_name)
+    }
+
+    /// # Panics
+    ///
+    /// Panics if shader validation or a device call fails.
+    fn hash_batch(&self, work: &Work, nonces: Range<u64>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let mut out = Vec::new();
+        let mut start = nonces.start;
+        while start < nonces.end {
+            let count = (nonces.end - start).min(self.batch_hashes);
+            out.extend(
+                pollster::block_on(self.hash(work, start, count))
+                    .unwrap_or_else(|err| panic!("{}: {err}", self.name())),
+            );
+            start += count;
+        }
+        out
+    }
+
+    /// # Panics
+    ///
+    /// Panics if shader validation or a device call fails.
+    fn search(&self, work: &Work, nonces: Range<u64>) -> Option<Solution> {
+        let mut start = nonces.start;
+        while start < nonces.end {
+            let count = (nonces.end - start).min(self.batch_hashes);
+            let hashes = pollster::block_on(self.hash(work, start, count))
+                .unwrap_or_else(|err| panic!("{}: {err}", self.name()));
+            // Candidates are confirmed on the host, lowest nonce first.
+            for (offset, (mix_hash, final_hash)) in hashes.into_iter().enumerate() {
+                if meets_boundary(&final_hash, &work.boundary) {
+                    return Some(Solution {
+                        nonce: start + offset as u64,
+                        mix_hash,
+                        final_hash,
+                    });
+                }
+            }
+            start += count;
+        }
+        None
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SealVerifier for WgpuMiner {
+    /// # Panics
+    ///
+    /// Panics if shader validation or a device call fails.
+    fn failures(&self, seals: &[Seal]) -> FailureMask {
+        pollster::block_on(self.verify(seals))
+            .unwrap_or_else(|err| panic!("{}: {err}", self.name()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::miner::cpu::CpuMiner;
+    use crate::testutil::{header_hash, mock_c_dag, mock_lookup};
+
+    #[test]
+    fn test_wgpu_matches_cpu() {
+        let (c_dag, lookup) = (mock_c_dag(1), mock_lookup(1));
+        let gpu = match pollster::block_on(WgpuMiner::new(0, 1024, &c_dag, &lookup)) {
+            Ok(gpu) => gpu.with_group_size(64),
+            // Nothing to compare against on machines without an adapter.
+            Err(WgpuError::NoAdapter(_)) | Err(WgpuError::RequestDevice(_)) => return,
+            Err(err) => panic!("{err}"),
+        };
+        let cpu = CpuMiner::new(1024, c_dag, lookup, 1);
+        let work = Work {
+            header_hash: header_hash(1),
+            block_number: 100,
+            boundary: [0xff; 32],
+        };
+        assert_eq!(gpu.hash_batch(&work, 0..40), cpu.hash_batch(&work, 0..40));
+
+        // The 64-bit start nonce carries into its high word on the device.
+        let high = u32::MAX as u64 - 3;
+        assert_eq!(
+            gpu.hash_batch(&work, high..high + 8),
+            cpu.hash_batch(&work, high..high + 8)
+        );
+
+        let mut seals: Vec<Seal> = cpu
+            .hash_batch(&work, 0..40)
+            .into_iter()
+            .enumerate()
+            .map(|(nonce, (mix_hash, _))| Seal {
+                header_hash: work.header_hash,
+                block_number: work.block_number,
+                nonce: nonce as u64,
+                mix_hash: mix_hash.try_into().unwrap(),
+                boundary: [0xff; 32],
+            })
+            .collect();
+        seals[5].mix_hash[0] ^= 1;
+        seals[36].boundary = [0; 32];
+        assert_eq!(gpu.failures(&seals), cpu.failures(&seals));
+        assert_eq!(
+            gpu.failures(&seals).failed().collect::<Vec<_>>(),
+            vec![5, 36]
+        );
+    }
+}
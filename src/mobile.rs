@@ -0,0 +1,265 @@
+//! Light verification for mobile apps, exported to Kotlin and Swift with
+//! UniFFI.
+//!
+//! A [`LightVerifier`] holds one epoch's light cache, the ~16 MiB a light
+//! wallet needs to check headers without the full dataset. Generating it
+//! takes about a second of hashing, so apps should build it off the main
+//! thread once per epoch, save [`LightVerifier::cache_bytes`], and restore
+//! it with [`LightVerifier::from_cache_bytes`] on later launches.
+//!
+//! Bindings are generated from the compiled library:
+//!
+//! ```sh
+//! cargo build --release --features uniffi
+//! cargo run --features uniffi-bindgen --bin uniffi-bindgen -- generate \
+//!     --library target/release/libprogpow_verifier.so --language kotlin --out-dir out
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::endian::{le_bytes, le_words};
+use crate::ethash::cache::{self, make_cache};
+use crate::ethash::dataset::{dataset_lookup, generate_c_dag};
+use crate::hex;
+use crate::progpow::progpow::progpow;
+use crate::progpow::verify::{verify_seal, Seal, SealError};
+
+/// The reason a light-verification call failed.
+#[derive(Debug, PartialEq, Eq, uniffi::Error)]
+pub enum LightVerifyError {
+    /// An argument had the wrong length or described the wrong epoch.
+    InvalidInput {
+        /// What was wrong with the argument.
+        reason: String,
+    },
+    /// The recomputed mix hash differs from the one in the seal.
+    MixMismatch {
+        /// The mix hash ProgPoW produces for the header and nonce.
+        computed: Vec<u8>,
+    },
+    /// The mix hash matches, but the final hash exceeds the boundary.
+    BoundaryNotMet {
+        /// The final hash ProgPoW produces for the header and nonce.
+        final_hash: Vec<u8>,
+    },
+}
+
+impl fmt::Display for LightVerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LightVerifyError::InvalidInput { reason } => write!(f, "invalid input: {reason}"),
+            LightVerifyError::MixMismatch { computed } => {
+                write!(f, "mix hash mismatch: computed {}", hex::encode(computed))
+            }
+            LightVerifyError::BoundaryNotMet { final_hash } => {
+                write!(
+                    f,
+                    "final hash {} exceeds the boundary",
+                    hex::encode(final_hash)
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for LightVerifyError {}
+
+impl From<SealError> for LightVerifyError {
+    fn from(error: SealError) -> Self {
+        match error {
+            SealError::MixMismatch { computed } => LightVerifyError::MixMismatch { computed },
+            SealError::BoundaryNotMet { final_hash } => {
+                LightVerifyError::BoundaryNotMet { final_hash }
+            }
+        }
+    }
+}
+
+/// The two hashes ProgPoW produces for a header and nonce.
+#[derive(Clone, Debug, PartialEq, Eq, uniffi::Record)]
+pub struct ProgpowHash {
+    /// The 32-byte mix hash, stored in the block header.
+    pub mix_hash: Vec<u8>,
+    /// The 32-byte final hash, compared against the boundary.
+    pub final_hash: Vec<u8>,
+}
+
+/// Returns the epoch `block_number` belongs to.
+#[uniffi::export]
+pub fn epoch_of_block(block_number: u64) -> u64 {
+    cache::epoch(block_number)
+}
+
+/// Returns the 32-byte seed hash of `epoch`.
+#[uniffi::export]
+pub fn seed_hash(epoch: u64) -> Vec<u8> {
+    cache::seed_hash(epoch).to_vec()
+}
+
+/// Verifies headers of one epoch with its light cache.
+#[derive(uniffi::Object)]
+pub struct LightVerifier {
+    epoch: u64,
+    size: u64,
+    words: Vec<u32>,
+    c_dag: Vec<u32>,
+}
+
+impl LightVerifier {
+    /// Wraps `words`, a light cache for a dataset of `size` bytes.
+    fn from_words(epoch: u64, size: u64, words: Vec<u32>) -> Self {
+        let c_dag = generate_c_dag(&words);
+        LightVerifier {
+            epoch,
+            size,
+            words,
+            c_dag,
+        }
+    }
+
+    /// Returns `bytes` as a 32-byte array, or an error naming `what`.
+    fn hash_arg(bytes: &[u8], what: &str) -> Result<[u8; 32], LightVerifyError> {
+        bytes
+            .try_into()
+            .map_err(|_| LightVerifyError::InvalidInput {
+                reason: format!("{what} must be 32 bytes, got {}", bytes.len()),
+            })
+    }
+}
+
+#[uniffi::export]
+impl LightVerifier {
+    /// Generates the light cache of `epoch`.
+    #[uniffi::constructor]
+    pub fn new(epoch: u64) -> Arc<Self> {
+        let words = make_cache(cache::cache_size(epoch), &cache::seed_hash(epoch));
+        Arc::new(Self::from_words(epoch, cache::dataset_size(epoch), words))
+    }
+
+    /// Restores a verifier from the output of [`LightVerifier::cache_bytes`].
+    #[uniffi::constructor]
+    pub fn from_cache_bytes(epoch: u64, bytes: Vec<u8>) -> Result<Arc<Self>, LightVerifyError> {
+        let expected = cache::cache_size(epoch);
+        if bytes.len() as u64 != expected {
+            return Err(LightVerifyError::InvalidInput {
+                reason: format!(
+                    "epoch {epoch} cache is {expected} bytes, got {}",
+                    bytes.len()
+                ),
+            });
+        }
+        let words = le_words(&bytes);
+        Ok(Arc::new(Self::from_words(
+            epoch,
+            cache::dataset_size(epoch),
+            words,
+        )))
+    }
+
+    /// Returns the light cache as little-endian bytes, for storage.
+    pub fn cache_bytes(&self) -> Vec<u8> {
+        le_bytes(&self.words)
+    }
+
+    /// Returns the epoch this verifier checks.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Computes the ProgPoW hash of a header.
+    pub fn hash(
+        &self,
+        header_hash: Vec<u8>,
+        block_number: u64,
+        nonce: u64,
+    ) -> Result<ProgpowHash, LightVerifyError> {
+        let header_hash = Self::hash_arg(&header_hash, "header hash")?;
+        let lookup = dataset_lookup(&self.words);
+        let (mix_hash, final_hash) = progpow(
+            &header_hash,
+            nonce,
+            self.size,
+            block_number,
+            &self.c_dag,
+            &lookup,
+        );
+        Ok(ProgpowHash {
+            mix_hash,
+            final_hash,
+        })
+    }
+
+    /// Verifies a header's seal.
+    ///
+    /// # Returns
+    ///
+    /// The final hash if the seal is valid, or the reason it is not. A
+    /// block outside this verifier's epoch is reported as invalid input.
+    pub fn verify_seal(
+        &self,
+        header_hash: Vec<u8>,
+        block_number: u64,
+        nonce: u64,
+        mix_hash: Vec<u8>,
+        boundary: Vec<u8>,
+    ) -> Result<Vec<u8>, LightVerifyError> {
+        if cache::epoch(block_number) != self.epoch {
+            return Err(LightVerifyError::InvalidInput {
+                reason: format!("block {block_number} is not in epoch {}", self.epoch),
+            });
+        }
+        let seal = Seal {
+            header_hash: Self::hash_arg(&header_hash, "header hash")?,
+            block_number,
+            nonce,
+            mix_hash: Self::hash_arg(&mix_hash, "mix hash")?,
+            boundary: Self::hash_arg(&boundary, "boundary")?,
+        };
+        let lookup = dataset_lookup(&self.words);
+        Ok(verify_seal(&seal, self.size, &self.c_dag, &lookup)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_light_verifier_checks_seals() {
+        let verifier =
+            LightVerifier::from_words(0, 1 << 16, make_cache(1024, &cache::seed_hash(1)));
+        let header = (0..32).collect::<Vec<u8>>();
+        let hash = verifier.hash(header.clone(), 100, 7).unwrap();
+        let verify = |mix: &[u8], boundary: &[u8], block| {
+            verifier.verify_seal(header.clone(), block, 7, mix.to_vec(), boundary.to_vec())
+        };
+
+        assert_eq!(
+            verify(&hash.mix_hash, &[0xff; 32], 100),
+            Ok(hash.final_hash.clone())
+        );
+        assert_eq!(
+            verify(&[0; 32], &[0xff; 32], 100),
+            Err(LightVerifyError::MixMismatch {
+                computed: hash.mix_hash.clone()
+            })
+        );
+        assert_eq!(
+            verify(&hash.mix_hash, &[0; 32], 100),
+            Err(LightVerifyError::BoundaryNotMet {
+                final_hash: hash.final_hash
+            })
+        );
+        assert!(matches!(
+            verify(&hash.mix_hash[..31], &[0xff; 32], 100),
+            Err(LightVerifyError::InvalidInput { .. })
+        ));
+        assert!(matches!(
+            verify(&hash.mix_hash, &[0xff; 32], cache::EPOCH_LENGTH),
+            Err(LightVerifyError::InvalidInput { .. })
+        ));
+        // A cache of the wrong size for its epoch is rejected.
+        assert!(LightVerifier::from_cache_bytes(0, verifier.cache_bytes()).is_err());
+    }
+}
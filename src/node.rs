@@ -0,0 +1,311 @@
+//! Node.js bindings for pool backends and explorers, built with napi-rs.
+//!
+//! A `Verifier` holds the light caches of a chain's recent epochs. Hashing,
+//! seal verification and share validation run on libuv's thread pool and
+//! return promises, so the event loop keeps serving while a cache is
+//! generated or a seal hashed:
+//!
+//! ```js
+//! const { Verifier } = require('progpow')
+//!
+//! const verifier = new Verifier('ethereum', 3)
+//! const finalHash = await verifier.verifySeal(headerHash, blockNumber, nonce, mixHash, boundary)
+//! const kind = await verifier.validateShare(headerHash, blockNumber, nonce, mixHash,
+//!   shareBoundary, blockBoundary) // 'invalid', 'share' or 'block'
+//! ```
+//!
+//! Hashes and boundaries are 32-byte `Buffer`s, block numbers are numbers
+//! and nonces are `bigint`s. Malformed arguments throw; a seal that fails
+//! verification rejects its promise with the reason.
+
+use napi::bindgen_prelude::{AsyncTask, BigInt, Buffer};
+use napi::{Env, Error, Result, Status, Task};
+use napi_derive::napi;
+
+use crate::chain::ChainRegistry;
+use crate::progpow::verify::Seal;
+use crate::share::{classify_share, ShareKind};
+use crate::verifier;
+
+/// Reads a 32-byte hash or boundary argument.
+fn hash_arg(name: &str, buffer: &[u8]) -> Result<[u8; 32]> {
+    buffer.try_into().map_err(|_| {
+        Error::new(
+            Status::InvalidArg,
+            format!("{name} must be 32 bytes, got {}", buffer.len()),
+        )
+    })
+}
+
+/// Reads a block number, which JavaScript passes as a number.
+fn block_number_arg(block_number: i64) -> Result<u64> {
+    u64::try_from(block_number)
+        .map_err(|_| Error::new(Status::InvalidArg, "blockNumber must not be negative"))
+}
+
+/// Reads a nonce, which JavaScript passes as a `bigint`.
+fn nonce_arg(nonce: BigInt) -> Result<u64> {
+    match nonce.get_u64() {
+        (false, nonce, true) => Ok(nonce),
+        _ => Err(Error::new(
+            Status::InvalidArg,
+            "nonce must be a bigint between 0 and 2^64 - 1",
+        )),
+    }
+}
+
+/// Reads the arguments of a seal.
+fn seal_arg(
+    header_hash: &[u8],
+    block_number: i64,
+    nonce: BigInt,
+    mix_hash: &[u8],
+    boundary: &[u8],
+) -> Result<Seal> {
+    Ok(Seal {
+        header_hash: hash_arg("headerHash", header_hash)?,
+        block_number: block_number_arg(block_number)?,
+        nonce: nonce_arg(nonce)?,
+        mix_hash: hash_arg("mixHash", mix_hash)?,
+        boundary: hash_arg("boundary", boundary)?,
+    })
+}
+
+/// The mix hash and final hash of a header and nonce.
+#[napi(object)]
+pub struct Hashes {
+    /// The 32-byte mix hash.
+    pub mix_hash: Buffer,
+    /// The 32-byte final hash.
+    pub final_hash: Buffer,
+}
+
+/// Hashes a header and nonce off the event loop.
+pub struct HashTask {
+    verifier: verifier::Verifier,
+    header_hash: [u8; 32],
+    block_number: u64,
+    nonce: u64,
+}
+
+impl Task for HashTask {
+    type Output = (Vec<u8>, Vec<u8>);
+    type JsValue = Hashes;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        Ok(self
+            .verifier
+            .hash(&self.header_hash, self.block_number, self.nonce))
+    }
+
+    fn resolve(&mut self, _env: Env, (mix_hash, final_hash): Self::Output) -> Result<Hashes> {
+        Ok(Hashes {
+            mix_hash: mix_hash.into(),
+            final_hash: final_hash.into(),
+        })
+    }
+}
+
+/// Verifies a seal off the event loop.
+pub struct VerifySealTask {
+    verifier: verifier::Verifier,
+    seal: Seal,
+}
+
+impl Task for VerifySealTask {
+    type Output = Vec<u8>;
+    type JsValue = Buffer;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        self.verifier
+            .verify_seal(&self.seal)
+            .map_err(|error| Error::from_reason(error.to_string()))
+    }
+
+    fn resolve(&mut self, _env: Env, final_hash: Self::Output) -> Result<Buffer> {
+        Ok(final_hash.into())
+    }
+}
+
+/// Classifies a share off the event loop.
+pub struct ValidateShareTask {
+    verifier: verifier::Verifier,
+    seal: Seal,
+    block_boundary: [u8; 32],
+}
+
+impl ValidateShareTask {
+    /// Returns what the share is worth; a forged mix hash makes it invalid.
+    fn classify(&self) -> ShareKind {
+        let seal = &self.seal;
+        let (mix_hash, final_hash) =
+            self.verifier
+                .hash(&seal.header_hash, seal.block_number, seal.nonce);
+        if mix_hash != seal.mix_hash {
+            return ShareKind::Invalid;
+        }
+        let final_hash = final_hash[..].try_into().unwrap();
+        classify_share(&final_hash, &seal.boundary, &self.block_boundary)
+    }
+}
+
+impl Task for ValidateShareTask {
+    type Output = ShareKind;
+    type JsValue = String;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        Ok(self.classify())
+    }
+
+    fn resolve(&mut self, _env: Env, kind: Self::Output) -> Result<String> {
+        let kind = match kind {
+            ShareKind::Invalid => "invalid",
+            ShareKind::Share => "share",
+            ShareKind::Block => "block",
+        };
+        Ok(kind.to_owned())
+    }
+}
+
+/// A light verifier for one chain, keeping the caches of its most recently
+/// used epochs.
+#[napi(js_name = "Verifier")]
+pub struct NodeVerifier {
+    verifier: verifier::Verifier,
+}
+
+#[napi]
+impl NodeVerifier {
+    /// Creates a verifier for the built-in chain `chain`, `"ethereum"` or
+    /// `"ravencoin"`, holding up to `caches` light caches (3 by default).
+    #[napi(constructor)]
+    pub fn new(chain: String, caches: Option<u32>) -> Result<Self> {
+        let chain = ChainRegistry::with_builtins()
+            .get(&chain)
+            .cloned()
+            .ok_or_else(|| Error::new(Status::InvalidArg, format!("unknown chain {chain:?}")))?;
+        let capacity = caches.unwrap_or(3).max(1) as usize;
+        Ok(NodeVerifier {
+            verifier: verifier::Verifier::new(chain, capacity),
+        })
+    }
+
+    /// Returns the epoch `blockNumber` belongs to.
+    #[napi]
+    pub fn epoch(&self, block_number: i64) -> Result<i64> {
+        let epoch = self.verifier.chain().epoch(block_number_arg(block_number)?);
+        Ok(epoch as i64)
+    }
+
+    /// Computes the mix hash and final hash of a header and nonce.
+    #[napi(ts_return_type = "Promise<Hashes>")]
+    pub fn hash(
+        &self,
+        header_hash: Buffer,
+        block_number: i64,
+        nonce: BigInt,
+    ) -> Result<AsyncTask<HashTask>> {
+        Ok(AsyncTask::new(HashTask {
+            verifier: self.verifier.clone(),
+            header_hash: hash_arg("headerHash", &header_hash)?,
+            block_number: block_number_arg(block_number)?,
+            nonce: nonce_arg(nonce)?,
+        }))
+    }
+
+    /// Verifies a seal, resolving to its final hash, or rejecting if the mix
+    /// hash differs from the recomputed one or the final hash exceeds
+    /// `boundary`.
+    #[napi(ts_return_type = "Promise<Buffer>")]
+    pub fn verify_seal(
+        &self,
+        header_hash: Buffer,
+        block_number: i64,
+        nonce: BigInt,
+        mix_hash: Buffer,
+        boundary: Buffer,
+    ) -> Result<AsyncTask<VerifySealTask>> {
+        Ok(AsyncTask::new(VerifySealTask {
+            verifier: self.verifier.clone(),
+            seal: seal_arg(&header_hash, block_number, nonce, &mix_hash, &boundary)?,
+        }))
+    }
+
+    /// Validates a pool share against the miner's share boundary and the
+    /// block boundary, resolving to `"invalid"`, `"share"` or `"block"`.
+    #[napi(ts_return_type = "Promise<'invalid' | 'share' | 'block'>")]
+    #[allow(clippy::too_many_arguments)]
+    pub fn validate_share(
+        &self,
+        header_hash: Buffer,
+        block_number: i64,
+        nonce: BigInt,
+        mix_hash: Buffer,
+        share_boundary: Buffer,
+        block_boundary: Buffer,
+    ) -> Result<AsyncTask<ValidateShareTask>> {
+        Ok(AsyncTask::new(ValidateShareTask {
+            verifier: self.verifier.clone(),
+            seal: seal_arg(
+                &header_hash,
+                block_number,
+                nonce,
+                &mix_hash,
+                &share_boundary,
+            )?,
+            block_boundary: hash_arg("blockBoundary", &block_boundary)?,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::Chain;
+    use crate::testutil::{header_hash, tiny_cache_manager};
+
+    #[test]
+    fn test_node_tasks() {
+        let verifier = verifier::Verifier::with_caches(Chain::ethereum(), tiny_cache_manager(1));
+        let header_hash = header_hash(3);
+        let mut hash = HashTask {
+            verifier: verifier.clone(),
+            header_hash,
+            block_number: 100,
+            nonce: 7,
+        };
+        let (mix_hash, final_hash) = hash.compute().unwrap();
+        let seal = Seal {
+            header_hash,
+            block_number: 100,
+            nonce: 7,
+            mix_hash: mix_hash[..].try_into().unwrap(),
+            boundary: [0xff; 32],
+        };
+        let mut verify = VerifySealTask {
+            verifier: verifier.clone(),
+            seal: seal.clone(),
+        };
+        assert_eq!(verify.compute().unwrap(), final_hash);
+        verify.seal.boundary = [0; 32];
+        assert!(verify.compute().is_err());
+
+        let share = |mix_hash: [u8; 32], block_boundary: [u8; 32]| {
+            ValidateShareTask {
+                verifier: verifier.clone(),
+                seal: Seal {
+                    mix_hash,
+                    ..seal.clone()
+                },
+                block_boundary,
+            }
+            .classify()
+        };
+        assert_eq!(share(seal.mix_hash, [0xff; 32]), ShareKind::Block);
+        assert_eq!(share(seal.mix_hash, [0; 32]), ShareKind::Share);
+        assert_eq!(share([0; 32], [0xff; 32]), ShareKind::Invalid);
+
+        assert!(hash_arg("headerHash", &[0; 31]).is_err());
+        assert!(block_number_arg(-1).is_err());
+    }
+}
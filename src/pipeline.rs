@@ -0,0 +1,172 @@
+//! A parallel seal verification pipeline for block import.
+//!
+//! A node importing blocks receives headers faster than one thread verifies
+//! them, but must apply them in order. [`SealVerifierPool`] runs a fixed
+//! number of worker threads over a bounded queue: producers push headers,
+//! workers verify them with the caches of a shared [`CacheManager`], and
+//! results come back in the order the headers were pushed.
+
+use std::collections::BTreeMap;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::engine::{verify_with, EngineError, SealableHeader};
+use crate::ethash::manager::CacheManager;
+
+/// A verified header and its result.
+type Verified<H> = (H, Result<(), EngineError>);
+
+/// Verifies header seals on a pool of worker threads.
+///
+/// [`submit`](Self::submit) blocks while the queue is full, so a producer
+/// cannot run arbitrarily far ahead of the workers.
+/// [`recv`](Self::recv) returns headers in submission order, whichever
+/// worker finishes first.
+pub struct SealVerifierPool<H> {
+    jobs: Option<SyncSender<(u64, H)>>,
+    results: Receiver<(u64, Verified<H>)>,
+    workers: Vec<JoinHandle<()>>,
+    submitted: u64,
+    received: u64,
+    /// Results that arrived before an earlier one.
+    reorder: BTreeMap<u64, Verified<H>>,
+}
+
+impl<H: SealableHeader + Send + 'static> SealVerifierPool<H> {
+    /// Starts `workers` threads verifying with `caches`, with room for
+    /// `queue` headers waiting to be picked up.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `workers` is 0.
+    pub fn new(caches: Arc<CacheManager>, workers: usize, queue: usize) -> Self {
+        assert!(workers > 0, "a verifier pool needs at least one worker");
+        let (jobs, queued) = mpsc::sync_channel::<(u64, H)>(queue);
+        let (done, results) = mpsc::channel();
+        let queued = Arc::new(Mutex::new(queued));
+        let workers = (0..workers)
+            .map(|_| {
+                let queued = queued.clone();
+                let done = done.clone();
+                let caches = caches.clone();
+                thread::spawn(move || loop {
+                    // Hold the lock only to take a job, not while verifying it.
+                    let job = queued.lock().unwrap().recv();
+                    let Ok((sequence, header)) = job else {
+                        return;
+                    };
+                    let result = verify_with(&caches, &header);
+                    if done.send((sequence, (header, result))).is_err() {
+                        return;
+                    }
+                })
+            })
+            .collect();
+        SealVerifierPool {
+            jobs: Some(jobs),
+            results,
+            workers,
+            submitted: 0,
+            received: 0,
+            reorder: BTreeMap::new(),
+        }
+    }
+
+    /// Queues `header` for verification, waiting while the queue is full.
+    pub fn submit(&mut self, header: H) {
+        let jobs = self.jobs.as_ref().expect("the pool is running");
+        jobs.send((self.submitted, header))
+            .expect("verifier workers stopped");
+        self.submitted += 1;
+    }
+
+    /// Returns the next header in submission order and its result, waiting
+    /// for it to be verified, or `None` if every submitted header has been
+    /// returned.
+    pub fn recv(&mut self) -> Option<(H, Result<(), EngineError>)> {
+        if self.received == self.submitted {
+            return None;
+        }
+        let verified = loop {
+            if let Some(verified) = self.reorder.remove(&self.received) {
+                break verified;
+            }
+            let (sequence, verified) = self.results.recv().expect("verifier workers stopped");
+            self.reorder.insert(sequence, verified);
+        };
+        self.received += 1;
+        Some(verified)
+    }
+
+    /// Returns the number of headers submitted but not yet returned.
+    pub fn in_flight(&self) -> u64 {
+        self.submitted - self.received
+    }
+}
+
+impl<H> Drop for SealVerifierPool<H> {
+    fn drop(&mut self) {
+        // Closing the queue stops each worker once it is drained.
+        self.jobs.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::tests::encode_header;
+    use crate::header::Header;
+    use crate::progpow::verify::SealError;
+    use crate::testutil::tiny_cache_manager;
+
+    #[test]
+    fn test_pool_returns_results_in_submission_order() {
+        let caches = Arc::new(tiny_cache_manager(2));
+        // Headers from two epochs, every third one with a broken seal.
+        let headers: Vec<Header> = (0..12u64)
+            .map(|i| {
+                let number = 29_995 + i;
+                let mut header = Header::decode(&encode_header(number, 1, i, [0; 32])).unwrap();
+                let (mix_hash, _) = caches.for_block(number).hash(&header.pre_hash(), number, i);
+                let mut mix_hash: [u8; 32] = mix_hash.try_into().unwrap();
+                if i % 3 == 0 {
+                    mix_hash[0] ^= 1;
+                }
+                header.set_seal(i, mix_hash);
+                header
+            })
+            .collect();
+
+        let mut pool = SealVerifierPool::new(caches, 3, 2);
+        assert_eq!(pool.recv(), None);
+        let mut results = Vec::new();
+        for header in headers.iter().cloned() {
+            pool.submit(header);
+            // Take results back now and then, as an importer would.
+            if pool.in_flight() > 4 {
+                results.push(pool.recv().unwrap());
+            }
+        }
+        while let Some(result) = pool.recv() {
+            results.push(result);
+        }
+
+        assert_eq!(results.len(), headers.len());
+        for (i, ((header, result), expected)) in results.iter().zip(&headers).enumerate() {
+            assert_eq!(header, expected);
+            if i % 3 == 0 {
+                assert!(matches!(
+                    result,
+                    Err(EngineError::InvalidSeal(SealError::MixMismatch { .. }))
+                ));
+            } else {
+                assert_eq!(result, &Ok(()));
+            }
+        }
+        assert_eq!(pool.in_flight(), 0);
+    }
+}
@@ -0,0 +1,198 @@
+//! The random program of a period written out as readable pseudocode.
+//!
+//! Auditors checking a kernel and people learning the algorithm most often
+//! ask what a period's program actually does. [`describe_program`] renders
+//! each decoded [`ProgramOp`] as one assignment in the order a lane runs
+//! them, with the math and merge operations spelled out. Period 0 starts
+//!
+//! ```text
+//! r[18] = rotr(r[18], 6) ^ cache[r[29] % 4096]
+//! r[31] = (r[31] * 33) + min(r[21], r[14])
+//! r[13] = rotr(r[13], 20) ^ cache[r[8] % 4096]
+//! ```
+//!
+//! and ends `r[30] = rotr(r[30], 22) ^ dag[3]`.
+
+use std::fmt::Write;
+
+use crate::basic_algorithm::PROGPOW_CACHE_WORDS;
+use crate::progpow::program::{Program, ProgramOp};
+
+/// How [`describe_program`] writes a program.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DescribeConfig {
+    /// Follow each line with the raw KISS99 values that selected its math
+    /// and merge operations, as a comment.
+    pub selectors: bool,
+    /// Number the lines from 0, in execution order.
+    pub line_numbers: bool,
+}
+
+/// Writes the program of `period` as pseudocode, one operation per line,
+/// after a comment naming the period and what the names stand for.
+///
+/// # Arguments
+///
+/// * `period` - The program seed, i.e. `block_number / PROGPOW_PERIOD_LENGTH`.
+/// * `config` - How to write it.
+///
+/// # Returns
+///
+/// The pseudocode, each line ending in a newline.
+pub fn describe_program(period: u64, config: &DescribeConfig) -> String {
+    describe(&Program::generate(period), config)
+}
+
+/// Writes a decoded program as pseudocode; see [`describe_program`].
+pub fn describe(program: &Program, config: &DescribeConfig) -> String {
+    let count = |f: fn(&ProgramOp) -> bool| program.ops.iter().filter(|op| f(op)).count();
+    let mut out = format!(
+        "// ProgPoW program for period {}: {} cache loads, {} math operations and {} DAG merges per lane and loop\n",
+        program.period,
+        count(|op| matches!(op, ProgramOp::Cache { .. })),
+        count(|op| matches!(op, ProgramOp::Math { .. })),
+        count(|op| matches!(op, ProgramOp::DagMerge { .. })),
+    );
+    out.push_str(
+        "// r: the lane's registers, cache: the cached DAG words, dag: the lane's words of the loop's DAG item\n",
+    );
+    for (line, op) in program.ops.iter().enumerate() {
+        if config.line_numbers {
+            let _ = write!(out, "{line:2}: ");
+        }
+        let (statement, selectors) = match *op {
+            ProgramOp::Cache {
+                src,
+                dst,
+                merge_sel,
+            } => (
+                merge_expression(
+                    dst,
+                    &format!("cache[r[{src}] % {PROGPOW_CACHE_WORDS}]"),
+                    merge_sel,
+                ),
+                format!("merge {merge_sel:#010x}"),
+            ),
+            ProgramOp::Math {
+                src1,
+                src2,
+                math_sel,
+                dst,
+                merge_sel,
+            } => (
+                merge_expression(dst, &math_expression(src1, src2, math_sel), merge_sel),
+                format!("math {math_sel:#010x}, merge {merge_sel:#010x}"),
+            ),
+            ProgramOp::DagMerge {
+                word,
+                dst,
+                merge_sel,
+            } => (
+                merge_expression(dst, &format!("dag[{word}]"), merge_sel),
+                format!("merge {merge_sel:#010x}"),
+            ),
+        };
+        out.push_str(&statement);
+        if config.selectors {
+            let _ = write!(out, "  // {selectors}");
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Writes the assignment merging `value` into register `dst`.
+fn merge_expression(dst: u32, value: &str, r: u32) -> String {
+    let d = format!("r[{dst}]");
+    let x = ((r >> 16) % 31) + 1;
+    match r % 4 {
+        0 => format!("{d} = ({d} * 33) + {value}"),
+        1 => format!("{d} = ({d} ^ {value}) * 33"),
+        2 => format!("{d} = rotl({d}, {x}) ^ {value}"),
+        _ => format!("{d} = rotr({d}, {x}) ^ {value}"),
+    }
+}
+
+/// Writes the math operation on registers `src1` and `src2`.
+fn math_expression(src1: u32, src2: u32, r: u32) -> String {
+    let (a, b) = (format!("r[{src1}]"), format!("r[{src2}]"));
+    match r % 11 {
+        0 => format!("({a} + {b})"),
+        1 => format!("({a} * {b})"),
+        2 => format!("mul_hi({a}, {b})"),
+        3 => format!("min({a}, {b})"),
+        4 => format!("rotl({a}, {b})"),
+        5 => format!("rotr({a}, {b})"),
+        6 => format!("({a} & {b})"),
+        7 => format!("({a} | {b})"),
+        8 => format!("({a} ^ {b})"),
+        9 => format!("(clz({a}) + clz({b}))"),
+        _ => format!("(popcount({a}) + popcount({b}))"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_algorithm::{merge, progpow_math};
+
+    #[test]
+    fn test_describe_program_renders_each_op() {
+        let text = describe_program(0, &DescribeConfig::default());
+        let lines: Vec<&str> = text.lines().collect();
+        let program = Program::generate(0);
+        assert_eq!(lines.len(), program.ops.len() + 2);
+        assert!(lines[0].starts_with("// ProgPoW program for period 0: 11 cache loads"));
+
+        // Each selector renders as the operation it selects.
+        assert_eq!(
+            merge_expression(4, "v", 0x0007_0002),
+            "r[4] = rotl(r[4], 8) ^ v"
+        );
+        let mut merged = 0x8000_0001;
+        merge(&mut merged, 0, 0x0007_0002);
+        assert_eq!(merged, 0x8000_0001u32.rotate_left(8));
+        assert_eq!(math_expression(1, 2, 13), "mul_hi(r[1], r[2])");
+        assert_eq!(progpow_math(1 << 31, 4, 13), 2);
+
+        for (line, op) in lines[2..].iter().zip(&program.ops) {
+            match *op {
+                ProgramOp::Cache { src, dst, .. } => {
+                    assert!(line.starts_with(&format!("r[{dst}] = ")));
+                    assert!(line.contains(&format!("cache[r[{src}] % 4096]")));
+                }
+                ProgramOp::Math { src1, src2, .. } => {
+                    assert!(
+                        line.contains(&format!("r[{src1}]"))
+                            && line.contains(&format!("r[{src2}]"))
+                    );
+                }
+                ProgramOp::DagMerge { word, dst, .. } => {
+                    assert!(line.starts_with(&format!("r[{dst}] = ")));
+                    assert!(line.ends_with(&format!("dag[{word}]")));
+                }
+            }
+        }
+
+        let annotated = describe_program(
+            0,
+            &DescribeConfig {
+                selectors: true,
+                line_numbers: true,
+            },
+        );
+        let first = annotated.lines().nth(2).unwrap();
+        assert!(first.starts_with(" 0: r["));
+        assert!(first.contains("  // merge 0x"));
+        assert_ne!(
+            annotated,
+            describe_program(
+                1,
+                &DescribeConfig {
+                    selectors: true,
+                    line_numbers: true
+                }
+            )
+        );
+    }
+}
@@ -0,0 +1,454 @@
+//! Every step of one ProgPoW hash, for formal verification and ZK circuits.
+//!
+//! Arithmetizing ProgPoW needs more than the per-loop state of
+//! [`crate::progpow::trace`]: a constraint system checks each operation on
+//! its own, so it needs each operation's inputs, output and memory access.
+//! [`execution_trace`] runs the mix loop one operation at a time and keeps,
+//! for every lane of every loop, each cache load, math operation and DAG
+//! merge as a [`Step`] with the lane's register file before and after it.
+//! [`Chain::execution_trace`](crate::chain::Chain::execution_trace) does the
+//! same for any chain.
+//!
+//! Steps come in execution order: loop by loop, lane by lane within a loop,
+//! and within a lane in the order of the period's
+//! [`Program`]. A step writes one register,
+//! `dst`, which becomes `merge(before[dst], data, merge_sel)`; every other
+//! register is unchanged, and a step's `after` is the next step's `before`
+//! within the lane. A hash has `PROGPOW_CNT_DAG * PROGPOW_LANES *
+//! (PROGPOW_CNT_CACHE + PROGPOW_CNT_MATH + PROGPOW_DAG_LOADS)` steps, 33,792
+//! with the built-in parameters.
+//!
+//! [`ExecutionTrace::write_json`] writes the trace as one JSON object, with
+//! the steps in columns, one array per field, so that each converts directly
+//! to a column of a trace table:
+//!
+//! ```text
+//! {
+//!   "format": "progpow-execution-trace", "version": 1,
+//!   "shape": {"lanes": 16, "regs": 32, "dag_loads": 4, "cnt_cache": 11,
+//!             "cnt_math": 18, "cnt_dag": 64, "cache_words": 4096},
+//!   "seed": "0x…", "period": 0, "dataset_size": 1073739904,
+//!   "c_dag": [4096 words],
+//!   "fill_mix": [16 lanes of 32 words],
+//!   "dag_loads": {"base": [64 word indices], "words": [64 loads of 64 words]},
+//!   "steps": {
+//!     "loop": […], "lane": […], "op": ["cache" | "math" | "dag", …],
+//!     "src1": […], "src2": […], "dst": […], "math_sel": […], "merge_sel": […],
+//!     "address": […], "data": […], "before": [[32 words], …], "after": [[32 words], …]
+//!   },
+//!   "lane_results": [16 words], "result": [8 words],
+//!   "mix_hash": "0x…", "final_hash": "0x…"
+//! }
+//! ```
+//!
+//! Words are decimal numbers. `src1` is the address register of a cache load
+//! and the first operand of a math operation, and `src2` the second operand;
+//! `math_sel` is set for math operations only, and `address` is the `c_dag`
+//! index of a cache load or the dataset word index of a DAG merge. Fields a
+//! step does not have are `null`. A DAG load's `words` start at `base`, and
+//! lane `l` of loop `i` merges words `((l ^ i) % 16) * 4` onwards.
+//!
+//! A trace holds the register file twice per step, about 10 MB in memory
+//! and 25 MB of JSON, so it is for analysis only; verification never
+//! builds one.
+
+use std::io::{self, Write};
+
+use crate::basic_algorithm::{
+    dag_load_base, fill_mix, merge, progpow_math, PROGPOW_CACHE_WORDS, PROGPOW_CNT_CACHE,
+    PROGPOW_CNT_DAG, PROGPOW_CNT_MATH, PROGPOW_DAG_LOADS, PROGPOW_LANES, PROGPOW_MIX_BYTES,
+    PROGPOW_PERIOD_LENGTH, PROGPOW_REGS,
+};
+use crate::hex;
+use crate::keccak::f800long::keccak_f800_long;
+use crate::progpow::progpow::{progpow_seed, reduce_lane_hashes};
+use crate::progpow::program::{Program, ProgramOp};
+use crate::progpow::trace::{digest, Mix};
+
+/// The words of one loop's global DAG load.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DagLoad {
+    /// The dataset word index of the first word.
+    pub base: u32,
+    /// The `PROGPOW_LANES * PROGPOW_DAG_LOADS` words from `base` on.
+    pub words: Vec<u32>,
+}
+
+/// One operation of one lane.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    /// The loop the step belongs to.
+    pub loop_index: u32,
+    /// The lane that runs it.
+    pub lane: u32,
+    /// The operation, with its registers and selectors.
+    pub op: ProgramOp,
+    /// The `c_dag` index of a cache load or the dataset word index of a DAG
+    /// merge; `None` for math operations.
+    pub address: Option<u32>,
+    /// The word merged into the destination register: the cache word, the
+    /// math result or the DAG word.
+    pub data: u32,
+    /// The lane's registers before the step.
+    pub before: [u32; PROGPOW_REGS],
+    /// The lane's registers after the step.
+    pub after: [u32; PROGPOW_REGS],
+}
+
+/// Every step of one hash, with its inputs and results.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExecutionTrace {
+    /// The 64-bit seed from the first Keccak pass.
+    pub seed: u64,
+    /// The period whose program ran.
+    pub period: u64,
+    /// The size of the dataset in bytes.
+    pub dataset_size: u64,
+    /// The cached DAG words that cache loads read.
+    pub c_dag: Vec<u32>,
+    /// Every lane's registers after `fill_mix`.
+    pub fill_mix: Mix,
+    /// Each loop's global DAG load, in loop order.
+    pub dag_loads: Vec<DagLoad>,
+    /// Every step, in execution order.
+    pub steps: Vec<Step>,
+    /// Each lane's FNV-1a digest of its final registers.
+    pub lane_results: [u32; PROGPOW_LANES],
+    /// The lane results reduced to eight words, the mix hash.
+    pub result: [u32; 8],
+    /// `result` as bytes.
+    pub mix_hash: [u8; 32],
+    /// The final hash.
+    pub final_hash: [u8; 32],
+}
+
+/// Computes the ProgPoW hash of the inputs keeping every step.
+///
+/// # Arguments
+///
+/// The arguments of [`progpow`](crate::progpow::progpow::progpow).
+///
+/// # Returns
+///
+/// The [`ExecutionTrace`] of the hash, whose `mix_hash` and `final_hash`
+/// are the pair `progpow` returns.
+///
+/// # Panics
+///
+/// Panics where `progpow` does.
+pub fn execution_trace(
+    hash: &[u8],
+    nonce: u64,
+    size: u64,
+    block_number: u64,
+    c_dag: &[u32],
+    lookup: &dyn Fn(u32) -> Vec<u8>,
+) -> ExecutionTrace {
+    let seed = progpow_seed(hash, nonce);
+    let period = block_number / PROGPOW_PERIOD_LENGTH;
+    execute_at_period(seed, size, period, c_dag, lookup, |result| {
+        keccak_f800_long(hash, seed, result).try_into().unwrap()
+    })
+}
+
+/// Runs the program for `period` from `seed` step by step, with
+/// `final_hash` computing the final hash from the reduced mix, for variants
+/// that derive the seed, period and final hash differently.
+pub(crate) fn execute_at_period(
+    seed: u64,
+    size: u64,
+    period: u64,
+    c_dag: &[u32],
+    lookup: &dyn Fn(u32) -> Vec<u8>,
+    final_hash: impl FnOnce(&[u32; 8]) -> [u8; 32],
+) -> ExecutionTrace {
+    let mut mix = [[0u32; PROGPOW_REGS]; PROGPOW_LANES];
+    for (lane, lane_mix) in mix.iter_mut().enumerate() {
+        *lane_mix = fill_mix(seed, lane as u32);
+    }
+    let initial = mix;
+
+    let program = Program::generate(period);
+    let items = (size / PROGPOW_MIX_BYTES as u64) as u32;
+    let mut dag_loads = Vec::with_capacity(PROGPOW_CNT_DAG);
+    let mut steps = Vec::with_capacity(PROGPOW_CNT_DAG * PROGPOW_LANES * program.ops.len());
+    for loop_index in 0..PROGPOW_CNT_DAG as u32 {
+        let base = dag_load_base(loop_index, &mix, items);
+        let words: Vec<u32> = (0..PROGPOW_DAG_LOADS as u32)
+            .flat_map(|i| lookup(base + 16 * i))
+            .collect::<Vec<u8>>()
+            .chunks_exact(4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+
+        for (lane, lane_mix) in mix.iter_mut().enumerate() {
+            let index =
+                ((lane as u32 ^ loop_index) % PROGPOW_LANES as u32) * PROGPOW_DAG_LOADS as u32;
+            for op in &program.ops {
+                let before = *lane_mix;
+                let (address, data, dst, merge_sel) = match *op {
+                    ProgramOp::Cache {
+                        src,
+                        dst,
+                        merge_sel,
+                    } => {
+                        let offset = lane_mix[src as usize] % PROGPOW_CACHE_WORDS as u32;
+                        (Some(offset), c_dag[offset as usize], dst, merge_sel)
+                    }
+                    ProgramOp::Math {
+                        src1,
+                        src2,
+                        math_sel,
+                        dst,
+                        merge_sel,
+                    } => {
+                        let data = progpow_math(
+                            lane_mix[src1 as usize],
+                            lane_mix[src2 as usize],
+                            math_sel,
+                        );
+                        (None, data, dst, merge_sel)
+                    }
+                    ProgramOp::DagMerge {
+                        word,
+                        dst,
+                        merge_sel,
+                    } => {
+                        let offset = index + word;
+                        (Some(base + offset), words[offset as usize], dst, merge_sel)
+                    }
+                };
+                merge(&mut lane_mix[dst as usize], data, merge_sel);
+                steps.push(Step {
+                    loop_index,
+                    lane: lane as u32,
+                    op: *op,
+                    address,
+                    data,
+                    before,
+                    after: *lane_mix,
+                });
+            }
+        }
+        dag_loads.push(DagLoad { base, words });
+    }
+
+    let lane_results = mix.map(|lane_mix| digest(&lane_mix));
+    let result = reduce_lane_hashes(&lane_results);
+    let mut mix_hash = [0u8; 32];
+    for (bytes, word) in mix_hash.chunks_exact_mut(4).zip(result) {
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+    ExecutionTrace {
+        seed,
+        period,
+        dataset_size: size,
+        c_dag: c_dag.to_vec(),
+        fill_mix: initial,
+        dag_loads,
+        steps,
+        lane_results,
+        result,
+        mix_hash,
+        final_hash: final_hash(&result),
+    }
+}
+
+impl ExecutionTrace {
+    /// Writes the trace to `out` in the columnar JSON format of the module
+    /// docs.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error writing to `out`.
+    pub fn write_json(&self, mut out: impl Write) -> io::Result<()> {
+        write!(
+            out,
+            "{{\"format\":\"progpow-execution-trace\",\"version\":1,\
+             \"shape\":{{\"lanes\":{PROGPOW_LANES},\"regs\":{PROGPOW_REGS},\
+             \"dag_loads\":{PROGPOW_DAG_LOADS},\"cnt_cache\":{PROGPOW_CNT_CACHE},\
+             \"cnt_math\":{PROGPOW_CNT_MATH},\"cnt_dag\":{PROGPOW_CNT_DAG},\
+             \"cache_words\":{PROGPOW_CACHE_WORDS}}},\
+             \"seed\":\"{:#018x}\",\"period\":{},\"dataset_size\":{},\"c_dag\":{},\"fill_mix\":[",
+            self.seed,
+            self.period,
+            self.dataset_size,
+            words(&self.c_dag)
+        )?;
+        rows(&mut out, self.fill_mix.iter().map(|lane| words(lane)))?;
+        write!(out, "],\"dag_loads\":{{\"base\":")?;
+        let bases: Vec<u32> = self.dag_loads.iter().map(|load| load.base).collect();
+        write!(out, "{},\"words\":[", words(&bases))?;
+        rows(
+            &mut out,
+            self.dag_loads.iter().map(|load| words(&load.words)),
+        )?;
+
+        write!(out, "]}},\"steps\":{{")?;
+        let column = |out: &mut dyn Write, name: &str, cell: &dyn Fn(&Step) -> String| {
+            write!(out, "\"{name}\":[")?;
+            rows(out, self.steps.iter().map(cell))?;
+            write!(out, "]")
+        };
+        let number = |value: Option<u32>| value.map_or("null".to_string(), |v| v.to_string());
+        column(&mut out, "loop", &|step| step.loop_index.to_string())?;
+        write!(out, ",")?;
+        column(&mut out, "lane", &|step| step.lane.to_string())?;
+        write!(out, ",")?;
+        column(&mut out, "op", &|step| {
+            match step.op {
+                ProgramOp::Cache { .. } => "\"cache\"",
+                ProgramOp::Math { .. } => "\"math\"",
+                ProgramOp::DagMerge { .. } => "\"dag\"",
+            }
+            .to_string()
+        })?;
+        write!(out, ",")?;
+        column(&mut out, "src1", &|step| {
+            number(match step.op {
+                ProgramOp::Cache { src, .. } => Some(src),
+                ProgramOp::Math { src1, .. } => Some(src1),
+                ProgramOp::DagMerge { .. } => None,
+            })
+        })?;
+        write!(out, ",")?;
+        column(&mut out, "src2", &|step| {
+            number(match step.op {
+                ProgramOp::Math { src2, .. } => Some(src2),
+                _ => None,
+            })
+        })?;
+        write!(out, ",")?;
+        column(&mut out, "dst", &|step| {
+            dst_and_merge(&step.op).0.to_string()
+        })?;
+        write!(out, ",")?;
+        column(&mut out, "math_sel", &|step| {
+            number(match step.op {
+                ProgramOp::Math { math_sel, .. } => Some(math_sel),
+                _ => None,
+            })
+        })?;
+        write!(out, ",")?;
+        column(&mut out, "merge_sel", &|step| {
+            dst_and_merge(&step.op).1.to_string()
+        })?;
+        write!(out, ",")?;
+        column(&mut out, "address", &|step| number(step.address))?;
+        write!(out, ",")?;
+        column(&mut out, "data", &|step| step.data.to_string())?;
+        write!(out, ",")?;
+        column(&mut out, "before", &|step| words(&step.before))?;
+        write!(out, ",")?;
+        column(&mut out, "after", &|step| words(&step.after))?;
+
+        writeln!(
+            out,
+            "}},\"lane_results\":{},\"result\":{},\"mix_hash\":\"0x{}\",\"final_hash\":\"0x{}\"}}",
+            words(&self.lane_results),
+            words(&self.result),
+            hex::encode(&self.mix_hash),
+            hex::encode(&self.final_hash)
+        )
+    }
+}
+
+/// Returns the destination register and merge selector of `op`.
+fn dst_and_merge(op: &ProgramOp) -> (u32, u32) {
+    match *op {
+        ProgramOp::Cache { dst, merge_sel, .. }
+        | ProgramOp::Math { dst, merge_sel, .. }
+        | ProgramOp::DagMerge { dst, merge_sel, .. } => (dst, merge_sel),
+    }
+}
+
+/// Writes `cells` separated by commas.
+fn rows(out: &mut dyn Write, cells: impl Iterator<Item = String>) -> io::Result<()> {
+    for (i, cell) in cells.enumerate() {
+        if i > 0 {
+            out.write_all(b",")?;
+        }
+        out.write_all(cell.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Formats `words` as a JSON array of numbers.
+fn words(words: &[u32]) -> String {
+    let words: Vec<String> = words.iter().map(u32::to_string).collect();
+    format!("[{}]", words.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ethash::buffer::DagBuffer;
+    use crate::progpow::progpow::progpow;
+    use crate::progpow::trace::progpow_with_trace;
+    use crate::testutil::tiny_cache;
+
+    #[test]
+    fn test_steps_replay_the_hash() {
+        let cache = tiny_cache(0);
+        let c_dag = cache.c_dag();
+        let lookup = |index| cache.lookup(index);
+        let exec = execution_trace(&[3; 32], 11, cache.size(), 0, &c_dag, &lookup);
+        let (mix_hash, final_hash) = progpow(&[3; 32], 11, cache.size(), 0, &c_dag, &lookup);
+        assert_eq!(
+            (exec.mix_hash.to_vec(), exec.final_hash.to_vec()),
+            (mix_hash, final_hash)
+        );
+
+        let per_lane = PROGPOW_CNT_CACHE + PROGPOW_CNT_MATH + PROGPOW_DAG_LOADS;
+        assert_eq!(exec.steps.len(), PROGPOW_CNT_DAG * PROGPOW_LANES * per_lane);
+        let trace = progpow_with_trace(&[3; 32], 11, cache.size(), 0, &c_dag, &lookup);
+        for (i, step) in exec.steps.iter().enumerate() {
+            let (dst, merge_sel) = dst_and_merge(&step.op);
+            let mut expected = step.before;
+            merge(&mut expected[dst as usize], step.data, merge_sel);
+            assert_eq!(step.after, expected, "step {i}");
+            match step.op {
+                ProgramOp::Cache { .. } => {
+                    assert_eq!(c_dag[step.address.unwrap() as usize], step.data)
+                }
+                ProgramOp::Math { .. } => assert_eq!(step.address, None),
+                ProgramOp::DagMerge { .. } => {
+                    let load = &exec.dag_loads[step.loop_index as usize];
+                    assert_eq!(
+                        load.words[(step.address.unwrap() - load.base) as usize],
+                        step.data
+                    );
+                }
+            }
+            if i % per_lane == per_lane - 1 {
+                let loop_mix = trace.loops[step.loop_index as usize];
+                assert_eq!(step.after, loop_mix[step.lane as usize], "step {i}");
+            } else {
+                assert_eq!(step.after, exec.steps[i + 1].before, "step {i}");
+            }
+        }
+        assert_eq!(exec.steps[0].before, trace.fill_mix[0]);
+
+        let mut json = Vec::new();
+        exec.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.starts_with("{\"format\":\"progpow-execution-trace\",\"version\":1,"));
+        assert!(json.ends_with(&format!(
+            "\"final_hash\":\"0x{}\"}}\n",
+            hex::encode(&exec.final_hash)
+        )));
+        #[cfg(feature = "vectors")]
+        {
+            let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+            assert_eq!(
+                value["steps"]["op"].as_array().unwrap().len(),
+                exec.steps.len()
+            );
+            assert_eq!(value["steps"]["src2"][0], serde_json::Value::Null);
+            assert_eq!(
+                value["dag_loads"]["words"][63].as_array().unwrap().len(),
+                64
+            );
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use crate::keccak::f800state::Padding;
+use crate::progpow::kawpow::padded_progpow;
+use crate::progpow::verify::{check_seal, Seal, SealError};
+
+/// The number of blocks each FiroPoW program is used for: every block gets
+/// a new one.
+pub const FIROPOW_PERIOD_LENGTH: u64 = 1;
+
+/// The number of blocks in a FiroPoW epoch.
+pub const FIROPOW_EPOCH_LENGTH: u64 = 1300;
+
+/// Implements the FiroPoW hashing algorithm used by Firo.
+///
+/// FiroPoW is ProgPoW 0.9.4: the Keccak-f800 passes of KawPoW with
+/// [`Padding::Progpow094`] in place of the Ravencoin padding, around the
+/// ProgPoW mix loop with a new program every block.
+///
+/// # Arguments
+///
+/// * `header_hash` - A byte slice representing the header hash (32 bytes expected).
+/// * `nonce` - A 64-bit nonce used to vary the output.
+/// * `size` - The size of the dataset.
+/// * `block_number` - The block height, which selects the program.
+/// * `c_dag` - The cached first words of the DAG.
+/// * `lookup` - A function to retrieve memory segments based on an index.
+///
+/// # Returns
+///
+/// A tuple containing the 32-byte mix hash and the 32-byte final hash.
+pub fn firopow(
+    header_hash: &[u8],
+    nonce: u64,
+    size: u64,
+    block_number: u64,
+    c_dag: &[u32],
+    lookup: &dyn Fn(u32) -> Vec<u8>,
+) -> (Vec<u8>, Vec<u8>) {
+    padded_progpow(
+        Padding::Progpow094,
+        FIROPOW_PERIOD_LENGTH,
+        header_hash,
+        nonce,
+        size,
+        block_number,
+        c_dag,
+        lookup,
+    )
+}
+
+/// Verifies a seal by recomputing its FiroPoW hash.
+///
+/// This is [`crate::progpow::verify::verify_seal`] for FiroPoW: the seal's
+/// `block_number` is the block height and its `header_hash` the one
+/// [`crate::firo::header_hash`] computes.
+///
+/// # Returns
+///
+/// The final hash if the mix hash matches and the final hash meets the
+/// boundary, or the [`SealError`] describing the first check that failed.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(
+            block_number = seal.block_number,
+            epoch = seal.block_number / FIROPOW_EPOCH_LENGTH,
+            nonce = seal.nonce,
+        ),
+        err(level = "debug"),
+    )
+)]
+pub fn verify_firopow_seal(
+    seal: &Seal,
+    size: u64,
+    c_dag: &[u32],
+    lookup: &dyn Fn(u32) -> Vec<u8>,
+) -> Result<Vec<u8>, SealError> {
+    let (mix_hash, final_hash) = firopow(
+        &seal.header_hash,
+        seal.nonce,
+        size,
+        seal.block_number,
+        c_dag,
+        lookup,
+    );
+    check_seal(seal, &mix_hash, &final_hash)?;
+    Ok(final_hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keccak::kawpow::{final_pass, seed_pass};
+    use crate::progpow::kawpow::kawpow;
+    use crate::testutil::{header_hash, mock_c_dag, mock_lookup};
+
+    #[test]
+    fn test_firopow_uses_progpow_094_passes_and_per_block_programs() {
+        let header_hash = header_hash(1);
+        let (c_dag, lookup) = (mock_c_dag(1), mock_lookup(1));
+        let (mix_hash, final_hash) = firopow(&header_hash, 7, 1024, 100, &c_dag, &lookup);
+        let seed = seed_pass(&header_hash, 7, Padding::Progpow094);
+        assert_eq!(
+            final_hash,
+            final_pass(&seed, &mix_hash, Padding::Progpow094).to_vec()
+        );
+        assert_ne!(
+            seed,
+            seed_pass(&header_hash, 7, Padding::Zero),
+            "the 0.9.4 padding changes the seed"
+        );
+        assert_ne!(
+            kawpow(&header_hash, 7, 1024, 100, &c_dag, &lookup),
+            (mix_hash.clone(), final_hash.clone())
+        );
+        // KawPoW shares a program between heights 99 and 100; FiroPoW does not.
+        assert_ne!(
+            firopow(&header_hash, 7, 1024, 99, &c_dag, &lookup).0,
+            mix_hash
+        );
+
+        let seal = Seal {
+            header_hash,
+            block_number: 100,
+            nonce: 7,
+            mix_hash: mix_hash.try_into().unwrap(),
+            boundary: [0xff; 32],
+        };
+        assert_eq!(
+            verify_firopow_seal(&seal, 1024, &c_dag, &lookup),
+            Ok(final_hash)
+        );
+    }
+}
@@ -0,0 +1,343 @@
+//! The ProgPoW mix loop for any number of lanes and registers.
+//!
+//! The spec fixes 16 lanes of 32 registers, and the functions of
+//! [`basic_algorithm`](crate::basic_algorithm) and [`progpow`](crate::progpow::progpow)
+//! compute exactly that shape. Research builds studying other geometries
+//! call the same loop through [`progpow_core`] with `LANES` and `REGS` as
+//! const parameters instead, so the mix stays a fixed-size array and the
+//! loop compiles as tightly as the spec's; the spec functions are these
+//! instantiated at [`PROGPOW_LANES`] and [`PROGPOW_REGS`], which the
+//! [`Mix`] and [`Sequence`] aliases default to.
+//!
+//! The DAG loads and the cache, math and DAG access counts stay the spec's.
+//! [`sweep`](crate::progpow::sweep) varies those too, at runtime and more
+//! slowly. As there, each lane's register sequences restart with the lane,
+//! as in go-ethereum's kernel; for the spec shape this is the same as
+//! carrying the counter across lanes.
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::basic_algorithm::{
+    fnv1a, higher32, kiss99, lower32, merge, progpow_math, Kiss99State, PROGPOW_CACHE_WORDS,
+    PROGPOW_CNT_CACHE, PROGPOW_CNT_DAG, PROGPOW_CNT_MATH, PROGPOW_DAG_LOADS, PROGPOW_LANES,
+    PROGPOW_MIX_BYTES, PROGPOW_REGS,
+};
+use crate::keccak::f800long::keccak_f800_long;
+use crate::progpow::progpow::progpow_seed;
+
+/// The registers of every lane, by default in the spec's shape.
+pub type Mix<const LANES: usize = PROGPOW_LANES, const REGS: usize = PROGPOW_REGS> =
+    [[u32; REGS]; LANES];
+
+/// A register sequence of the random program, by default of the spec's
+/// registers.
+pub type Sequence<const REGS: usize = PROGPOW_REGS> = [u32; REGS];
+
+/// Checks at compile time that the loop can run with `LANES` lanes of
+/// `REGS` registers.
+const fn check_geometry<const LANES: usize, const REGS: usize>() {
+    assert!(LANES > 0, "ProgPoW needs at least one lane");
+    assert!(REGS >= 2, "math operations need at least 2 registers");
+    assert!(
+        (LANES * PROGPOW_DAG_LOADS).is_multiple_of(16),
+        "the lanes' DAG loads must fill whole 64-byte DAG items"
+    );
+}
+
+/// Fills the registers of every lane from the seed, as
+/// [`fill_mix_lanes`](crate::basic_algorithm::fill_mix_lanes) does for the
+/// spec's shape.
+///
+/// # Arguments
+///
+/// * `seed` - The seed for random number generation.
+///
+/// # Returns
+///
+/// The `REGS` registers of each of the `LANES` lanes.
+pub fn fill_mix_core<const LANES: usize, const REGS: usize>(seed: u64) -> Mix<LANES, REGS> {
+    let mut fnv_hash = 0x811c9dc5;
+    let mut z = fnv1a(&mut fnv_hash, lower32(seed));
+    let mut w = fnv1a(&mut fnv_hash, higher32(seed));
+    let mut jsr = [0u32; LANES];
+    for (lane, jsr) in jsr.iter_mut().enumerate() {
+        let mut lane_hash = fnv_hash;
+        *jsr = fnv1a(&mut lane_hash, lane as u32);
+    }
+    let mut jcong = [0u32; LANES];
+    for (lane, jcong) in jcong.iter_mut().enumerate() {
+        let mut lane_hash = jsr[lane];
+        *jcong = fnv1a(&mut lane_hash, lane as u32);
+    }
+
+    let mut by_reg = [[0u32; LANES]; REGS];
+    for regs in by_reg.iter_mut() {
+        z = 36969 * (z & 65535) + (z >> 16);
+        w = 18000 * (w & 65535) + (w >> 16);
+        let mwc = (z << 16).wrapping_add(w);
+        for lane in 0..LANES {
+            let mut j = jsr[lane];
+            j ^= j << 17;
+            j ^= j >> 13;
+            j ^= j << 5;
+            jsr[lane] = j;
+            jcong[lane] = jcong[lane].wrapping_mul(69069).wrapping_add(1234567);
+            regs[lane] = (mwc ^ jcong[lane]).wrapping_add(j);
+        }
+    }
+
+    let mut mix = [[0u32; REGS]; LANES];
+    for (reg, regs) in by_reg.iter().enumerate() {
+        for (lane, &value) in regs.iter().enumerate() {
+            mix[lane][reg] = value;
+        }
+    }
+    mix
+}
+
+/// Seeds the random program of `period` and shuffles its register
+/// sequences, as [`progpow_init`](crate::basic_algorithm::progpow_init) does
+/// for the spec's registers.
+///
+/// # Returns
+///
+/// The KISS99 state after the shuffles, the destination sequence and the
+/// source sequence.
+pub fn init_core<const REGS: usize>(period: u64) -> (Kiss99State, Sequence<REGS>, Sequence<REGS>) {
+    let mut fnv_hash = 0x811c9dc5;
+    let mut rand_state = Kiss99State::new(
+        fnv1a(&mut fnv_hash, lower32(period)),
+        fnv1a(&mut fnv_hash, higher32(period)),
+        fnv1a(&mut fnv_hash, lower32(period)),
+        fnv1a(&mut fnv_hash, higher32(period)),
+    );
+
+    let mut dst_seq = [0u32; REGS];
+    let mut src_seq = [0u32; REGS];
+    for (i, (dst, src)) in dst_seq.iter_mut().zip(src_seq.iter_mut()).enumerate() {
+        (*dst, *src) = (i as u32, i as u32);
+    }
+    for i in (1..REGS).rev() {
+        let j = kiss99(&mut rand_state) % (i as u32 + 1);
+        dst_seq.swap(i, j as usize);
+        let j = kiss99(&mut rand_state) % (i as u32 + 1);
+        src_seq.swap(i, j as usize);
+    }
+    (rand_state, dst_seq, src_seq)
+}
+
+/// Returns the dataset word index where the global DAG load of loop
+/// `loop_index` starts, as [`progpow_loop`](crate::basic_algorithm::progpow_loop)
+/// computes it for the spec's lanes; a loop reads `LANES * PROGPOW_DAG_LOADS`
+/// words.
+///
+/// # Arguments
+///
+/// * `loop_index` - The index of the current loop iteration.
+/// * `mix` - The mix at the start of the iteration.
+/// * `dataset_items` - The dataset size in `PROGPOW_MIX_BYTES` items.
+///
+/// # Panics
+///
+/// Panics if `64 * dataset_items` wraps to less than one loop's words in 32
+/// bits, as the division does in go-ethereum.
+pub fn dag_load_base_core<const LANES: usize, const REGS: usize>(
+    loop_index: u32,
+    mix: &Mix<LANES, REGS>,
+    dataset_items: u32,
+) -> u32 {
+    let words = (LANES * PROGPOW_DAG_LOADS) as u32;
+    // go-ethereum computes `64 * datasetSize` in 32 bits, and from epoch 1920
+    // on it overflows; wrap as it does.
+    let g_offset =
+        mix[loop_index as usize % LANES][0] % (64u32.wrapping_mul(dataset_items) / words);
+    g_offset * words
+}
+
+/// Runs loop `loop_index` of the mix on its already loaded DAG words, as
+/// [`progpow_loop`](crate::basic_algorithm::progpow_loop) does for the
+/// spec's shape.
+///
+/// # Arguments
+///
+/// * `period` - The program's period.
+/// * `loop_index` - The index of the current loop iteration.
+/// * `mix` - The mix to update.
+/// * `dag_item` - The `LANES * PROGPOW_DAG_LOADS` little-endian words read
+///   from [`dag_load_base_core`].
+/// * `c_dag` - The cached DAG words.
+pub fn loop_core<const LANES: usize, const REGS: usize>(
+    period: u64,
+    loop_index: u32,
+    mix: &mut Mix<LANES, REGS>,
+    dag_item: &[u8],
+    c_dag: &[u32],
+) {
+    const { check_geometry::<LANES, REGS>() };
+    let (state, dst_seq, src_seq) = init_core::<REGS>(period);
+
+    for (lane, lane_mix) in mix.iter_mut().enumerate() {
+        let mut rand_state = state;
+        let (mut src_counter, mut dst_counter) = (0, 0);
+        let mut next_dst = || {
+            dst_counter += 1;
+            dst_seq[(dst_counter - 1) % REGS] as usize
+        };
+        for i in 0..PROGPOW_CNT_CACHE.max(PROGPOW_CNT_MATH) {
+            if i < PROGPOW_CNT_CACHE {
+                // Cached memory access
+                let src = src_seq[src_counter % REGS] as usize;
+                src_counter += 1;
+                let data = c_dag[lane_mix[src] as usize % PROGPOW_CACHE_WORDS];
+                let dst = next_dst();
+                merge(&mut lane_mix[dst], data, kiss99(&mut rand_state));
+            }
+            if i < PROGPOW_CNT_MATH {
+                // Random Math, with two distinct sources
+                let src_rnd = kiss99(&mut rand_state) % (REGS * (REGS - 1)) as u32;
+                let src1 = (src_rnd % REGS as u32) as usize;
+                let mut src2 = (src_rnd / REGS as u32) as usize;
+                if src2 >= src1 {
+                    src2 += 1;
+                }
+                let data = progpow_math(lane_mix[src1], lane_mix[src2], kiss99(&mut rand_state));
+                let dst = next_dst();
+                merge(&mut lane_mix[dst], data, kiss99(&mut rand_state));
+            }
+        }
+
+        // The global load is consumed last, and always feeds mix[0] first.
+        let index = ((lane ^ loop_index as usize) % LANES) * PROGPOW_DAG_LOADS;
+        let word = |i: usize| LittleEndian::read_u32(&dag_item[4 * (index + i)..]);
+        merge(&mut lane_mix[0], word(0), kiss99(&mut rand_state));
+        for i in 1..PROGPOW_DAG_LOADS {
+            let dst = next_dst();
+            merge(&mut lane_mix[dst], word(i), kiss99(&mut rand_state));
+        }
+    }
+}
+
+/// Reduces each lane's registers to one word with FNV-1a.
+pub fn reduce_core<const LANES: usize, const REGS: usize>(mix: &Mix<LANES, REGS>) -> [u32; LANES] {
+    let mut lane_results = [0x811c9dc5u32; LANES];
+    for (lane_result, lane_mix) in lane_results.iter_mut().zip(mix) {
+        for &reg in lane_mix {
+            fnv1a(lane_result, reg);
+        }
+    }
+    lane_results
+}
+
+/// Computes the ProgPoW mix hash and final hash with `LANES` lanes of `REGS`
+/// registers.
+///
+/// `progpow_core::<PROGPOW_LANES, PROGPOW_REGS>` is
+/// [`progpow`](crate::progpow::progpow::progpow) at `period`; a geometry
+/// the loop cannot run with fails to compile.
+///
+/// # Arguments
+///
+/// * `header_hash` - The 32-byte header hash.
+/// * `nonce` - The 64-bit nonce.
+/// * `size` - The size of the dataset in bytes.
+/// * `period` - The program's period.
+/// * `c_dag` - The cached DAG words.
+/// * `lookup` - A function to retrieve 64-byte DAG items based on a word
+///   index.
+///
+/// # Returns
+///
+/// The `(mix_hash, final_hash)` pair.
+///
+/// # Panics
+///
+/// Panics if `size` leaves no loop's worth of DAG words to load from.
+pub fn progpow_core<const LANES: usize, const REGS: usize>(
+    header_hash: &[u8],
+    nonce: u64,
+    size: u64,
+    period: u64,
+    c_dag: &[u32],
+    lookup: &dyn Fn(u32) -> Vec<u8>,
+) -> ([u8; 32], [u8; 32]) {
+    let seed = progpow_seed(header_hash, nonce);
+    let items = (size / PROGPOW_MIX_BYTES as u64) as u32;
+    let mut mix = fill_mix_core::<LANES, REGS>(seed);
+    let mut dag_item = vec![0u8; LANES * PROGPOW_DAG_LOADS * 4];
+    for loop_index in 0..PROGPOW_CNT_DAG as u32 {
+        let base = dag_load_base_core(loop_index, &mix, items);
+        for (i, item) in dag_item.chunks_exact_mut(64).enumerate() {
+            item.copy_from_slice(&lookup(base + 16 * i as u32));
+        }
+        loop_core(period, loop_index, &mut mix, &dag_item, c_dag);
+    }
+
+    let mut result = [0x811c9dc5u32; 8];
+    for (lane, lane_result) in reduce_core(&mix).into_iter().enumerate() {
+        fnv1a(&mut result[lane % 8], lane_result);
+    }
+    let mut mix_hash = [0u8; 32];
+    for (bytes, word) in mix_hash.chunks_exact_mut(4).zip(result) {
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+    let final_hash = keccak_f800_long(header_hash, seed, &result);
+    (mix_hash, final_hash.try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ethash::buffer::DagBuffer;
+    use crate::progpow::progpow::progpow_from_seed_at_period;
+    use crate::progpow::sweep::{hash, LoopParams};
+    use crate::testutil::tiny_cache;
+
+    #[test]
+    fn test_progpow_core_spans_geometries() {
+        let cache = tiny_cache(0);
+        let c_dag = cache.c_dag();
+        let lookup = |index| cache.lookup(index);
+        for (nonce, period) in [(1, 0), (2, 7), (3, 0xdead_beef)] {
+            let (mix_hash, final_hash) = progpow_core::<PROGPOW_LANES, PROGPOW_REGS>(
+                &[9; 32],
+                nonce,
+                cache.size(),
+                period,
+                &c_dag,
+                &lookup,
+            );
+            let expected = progpow_from_seed_at_period(
+                &[9; 32],
+                progpow_seed(&[9; 32], nonce),
+                cache.size(),
+                period,
+                &c_dag,
+                &lookup,
+            );
+            assert_eq!((mix_hash.to_vec(), final_hash.to_vec()), expected);
+        }
+
+        // Other geometries agree with the runtime-shaped loop.
+        let narrow = progpow_core::<8, 16>(&[9; 32], 1, cache.size(), 0, &c_dag, &lookup);
+        let params = LoopParams {
+            lanes: 8,
+            regs: 16,
+            ..LoopParams::current()
+        };
+        assert_eq!(
+            narrow,
+            hash(&params, &[9; 32], 1, 0, cache.size(), &c_dag, &lookup)
+        );
+        let wide = progpow_core::<32, 64>(&[9; 32], 1, cache.size(), 0, &c_dag, &lookup);
+        let params = LoopParams {
+            lanes: 32,
+            regs: 64,
+            ..LoopParams::current()
+        };
+        assert_eq!(
+            wide,
+            hash(&params, &[9; 32], 1, 0, cache.size(), &c_dag, &lookup)
+        );
+        assert_ne!(narrow, wide);
+    }
+}
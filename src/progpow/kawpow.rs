@@ -0,0 +1,215 @@
+use crate::basic_algorithm::PROGPOW_LANES;
+use crate::keccak::f800state::Padding;
+use crate::keccak::kawpow::{final_pass, seed_pass};
+use crate::progpow::progpow::{period_lane_hashes, reduce_lane_hashes};
+use crate::progpow::verify::{check_seal, Seal, SealError};
+
+use byteorder::{ByteOrder, LittleEndian};
+
+/// The number of blocks each KawPoW program is used for.
+pub const KAWPOW_PERIOD_LENGTH: u64 = 3;
+
+/// The number of blocks in a KawPoW epoch, a quarter of ethash's.
+pub const KAWPOW_EPOCH_LENGTH: u64 = 7500;
+
+/// Implements the KawPoW hashing algorithm used by Ravencoin.
+///
+/// KawPoW runs the same mix loop as ProgPoW over the same DAG, but changes
+/// the program period to [`KAWPOW_PERIOD_LENGTH`] blocks and replaces both
+/// Keccak-f800 passes with the padded ones in
+/// [`crate::keccak::kawpow`]: the program's random state is seeded with the
+/// first two words of the seed pass, and the final pass absorbs all eight.
+///
+/// # Arguments
+///
+/// * `header_hash` - A byte slice representing the header hash (32 bytes expected).
+/// * `nonce` - A 64-bit nonce used to vary the output.
+/// * `size` - The size of the dataset.
+/// * `block_number` - The block height, which selects the program.
+/// * `c_dag` - The cached first words of the DAG.
+/// * `lookup` - A function to retrieve memory segments based on an index.
+///
+/// # Returns
+///
+/// A tuple containing the 32-byte mix hash and the 32-byte final hash.
+pub fn kawpow(
+    header_hash: &[u8],
+    nonce: u64,
+    size: u64,
+    block_number: u64,
+    c_dag: &[u32],
+    lookup: &dyn Fn(u32) -> Vec<u8>,
+) -> (Vec<u8>, Vec<u8>) {
+    padded_progpow(
+        Padding::Kawpow,
+        KAWPOW_PERIOD_LENGTH,
+        header_hash,
+        nonce,
+        size,
+        block_number,
+        c_dag,
+        lookup,
+    )
+}
+
+/// Runs the ProgPoW mix loop between the padded Keccak-f800 passes of
+/// [`seed_pass`] and [`final_pass`], changing the program every
+/// `period_length` blocks.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn padded_progpow(
+    padding: Padding,
+    period_length: u64,
+    header_hash: &[u8],
+    nonce: u64,
+    size: u64,
+    block_number: u64,
+    c_dag: &[u32],
+    lookup: &dyn Fn(u32) -> Vec<u8>,
+) -> (Vec<u8>, Vec<u8>) {
+    #[cfg(feature = "metrics")]
+    let started = std::time::Instant::now();
+    let seed_words = seed_pass(header_hash, nonce, padding);
+    // The first word seeds the low half of the random state.
+    let seed = (seed_words[1] as u64) << 32 | seed_words[0] as u64;
+
+    let lane_results: [u32; PROGPOW_LANES] =
+        period_lane_hashes(seed, size, block_number / period_length, c_dag, lookup);
+    let result = reduce_lane_hashes(&lane_results);
+
+    let mut mix_hash = vec![0u8; 32];
+    LittleEndian::write_u32_into(&result, &mut mix_hash);
+    let final_hash = final_pass(&seed_words, &mix_hash, padding);
+    #[cfg(feature = "metrics")]
+    crate::metrics::global().hashes.observe(started.elapsed());
+    (mix_hash, final_hash.to_vec())
+}
+
+/// Verifies a seal by recomputing its KawPoW hash.
+///
+/// This is [`crate::progpow::verify::verify_seal`] for KawPoW: the seal's
+/// `block_number` is the block height and its `header_hash` the one
+/// [`crate::ravencoin::header_hash`] computes.
+///
+/// # Returns
+///
+/// The final hash if the mix hash matches and the final hash meets the
+/// boundary, or the [`SealError`] describing the first check that failed.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(
+            block_number = seal.block_number,
+            epoch = seal.block_number / KAWPOW_EPOCH_LENGTH,
+            nonce = seal.nonce,
+        ),
+        err(level = "debug"),
+    )
+)]
+pub fn verify_kawpow_seal(
+    seal: &Seal,
+    size: u64,
+    c_dag: &[u32],
+    lookup: &dyn Fn(u32) -> Vec<u8>,
+) -> Result<Vec<u8>, SealError> {
+    let (mix_hash, final_hash) = kawpow(
+        &seal.header_hash,
+        seal.nonce,
+        size,
+        seal.block_number,
+        c_dag,
+        lookup,
+    );
+    check_seal(seal, &mix_hash, &final_hash)?;
+    Ok(final_hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ethash::manager::EpochCache;
+    use crate::hex;
+    use crate::keccak::kawpow::{kawpow_final, kawpow_seed};
+    use crate::progpow::progpow::progpow;
+    use crate::testutil::{header_hash, mock_c_dag, mock_lookup};
+
+    #[test]
+    fn test_kawpow_differs_from_progpow_and_verifies() {
+        let header_hash = header_hash(1);
+        let (c_dag, lookup) = (mock_c_dag(1), mock_lookup(1));
+        let (mix_hash, final_hash) = kawpow(&header_hash, 7, 1024, 100, &c_dag, &lookup);
+        assert_eq!(
+            final_hash,
+            kawpow_final(&kawpow_seed(&header_hash, 7), &mix_hash)
+        );
+        assert_ne!(
+            (mix_hash.clone(), final_hash.clone()),
+            progpow(&header_hash, 7, 1024, 100, &c_dag, &lookup)
+        );
+        // Heights in the same period share a program.
+        assert_eq!(
+            kawpow(&header_hash, 7, 1024, 99, &c_dag, &lookup),
+            (mix_hash.clone(), final_hash.clone())
+        );
+        assert_ne!(
+            kawpow(&header_hash, 7, 1024, 102, &c_dag, &lookup).0,
+            mix_hash
+        );
+
+        let seal = Seal {
+            header_hash,
+            block_number: 100,
+            nonce: 7,
+            mix_hash: mix_hash.try_into().unwrap(),
+            boundary: [0xff; 32],
+        };
+        assert_eq!(
+            verify_kawpow_seal(&seal, 1024, &c_dag, &lookup),
+            Ok(final_hash)
+        );
+        let mut wrong = seal.clone();
+        wrong.nonce += 1;
+        assert!(matches!(
+            verify_kawpow_seal(&wrong, 1024, &c_dag, &lookup),
+            Err(SealError::MixMismatch { .. })
+        ));
+    }
+
+    /// Checks published KawPoW test vectors, such as cpp-kawpow's
+    /// `kawpow_hash_test_cases`, with real epoch caches. Each line of the
+    /// file `PROGPOW_KAWPOW_VECTORS` names holds one case as that table
+    /// does: the block number, then the header hash, nonce, mix hash and
+    /// final hash in hex, separated by spaces.
+    #[test]
+    #[ignore = "needs PROGPOW_KAWPOW_VECTORS and full-size epoch caches"]
+    fn test_kawpow_vectors() {
+        let path = std::env::var("PROGPOW_KAWPOW_VECTORS")
+            .expect("PROGPOW_KAWPOW_VECTORS must name a file of test vectors");
+        let bytes = |digits: &str| hex::decode(digits).unwrap();
+        let fixture = std::fs::read_to_string(path).unwrap();
+        let mut cache: Option<EpochCache> = None;
+        let mut checked = 0;
+        for line in fixture.lines().filter(|line| !line.trim().is_empty()) {
+            let [block_number, header_hash, nonce, mix_hash, final_hash] =
+                line.split_whitespace().collect::<Vec<_>>()[..]
+            else {
+                panic!("malformed test vector {line:?}");
+            };
+            let block_number: u64 = block_number.parse().unwrap();
+            let epoch = block_number / KAWPOW_EPOCH_LENGTH;
+            if cache.as_ref().is_none_or(|cache| cache.epoch() != epoch) {
+                cache = Some(EpochCache::generate(epoch));
+            }
+            let nonce = u64::from_str_radix(nonce, 16).unwrap();
+            let header_hash = bytes(header_hash).try_into().unwrap();
+            let hashes = cache
+                .as_ref()
+                .unwrap()
+                .hash_kawpow(&header_hash, block_number, nonce);
+            assert_eq!(hashes, (bytes(mix_hash), bytes(final_hash)), "{line}");
+            checked += 1;
+        }
+        assert!(checked > 0, "the fixture holds no test vectors");
+    }
+}
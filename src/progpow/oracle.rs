@@ -0,0 +1,278 @@
+//! Differential testing against other ProgPoW implementations.
+//!
+//! An [`Oracle`] is another implementation that hashes the same inputs;
+//! [`cross_check`] runs randomized inputs through it and through this crate
+//! and reports every input where the two disagree. Inputs vary the header,
+//! nonce, block number (so every period and epoch is exercised), and the
+//! cache and dataset sizes, which catches endianness, period and size
+//! handling bugs that fixed test vectors miss.
+//!
+//! [`ProcessOracle`] drives an implementation in another process over a
+//! line-oriented JSON protocol, one request and one response per line:
+//!
+//! ```text
+//! > {"header_hash":"0x…","nonce":"0x…","block_number":1234,"cache_size":4096,
+//!    "dataset_size":65536,"seed_hash":"0x…"}
+//! < {"mix_hash":"0x…","final_hash":"0x…"}
+//! ```
+//!
+//! The oracle builds the light cache of `cache_size` bytes from `seed_hash`
+//! and hashes over a dataset of `dataset_size` bytes computed from it.
+//! `examples/geth-oracle` implements this protocol on top of go-ethereum's
+//! ProgPoW.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use serde::{Deserialize, Serialize};
+
+use crate::ethash::buffer::{DagBuffer, LightDag};
+use crate::ethash::cache::{epoch, make_cache, seed_hash, EPOCH_LENGTH, MAX_EPOCH};
+use crate::progpow::progpow::progpow;
+use crate::progpow::vectors::{hex, hex_u64};
+use crate::testutil::splitmix64;
+
+/// One input to hash.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct OracleInput {
+    #[serde(with = "hex")]
+    pub header_hash: [u8; 32],
+    #[serde(with = "hex_u64")]
+    pub nonce: u64,
+    pub block_number: u64,
+    /// The size of the light cache in bytes, a multiple of 64.
+    pub cache_size: u64,
+    /// The size of the dataset in bytes, a multiple of 256.
+    pub dataset_size: u64,
+    #[serde(with = "hex")]
+    pub seed_hash: [u8; 32],
+}
+
+impl OracleInput {
+    /// Computes the `(mix_hash, final_hash)` of the input with this crate.
+    pub fn hash(&self) -> (Vec<u8>, Vec<u8>) {
+        let dag = self.light_dag();
+        self.hash_over(&dag, &dag.c_dag())
+    }
+
+    /// Builds the dataset the input is hashed over.
+    fn light_dag(&self) -> LightDag {
+        LightDag::new(
+            make_cache(self.cache_size, &self.seed_hash),
+            self.dataset_size,
+        )
+    }
+
+    /// Hashes the input over its already built dataset.
+    fn hash_over(&self, dag: &LightDag, c_dag: &[u32]) -> (Vec<u8>, Vec<u8>) {
+        progpow(
+            &self.header_hash,
+            self.nonce,
+            self.dataset_size,
+            self.block_number,
+            c_dag,
+            &|index| dag.lookup(index),
+        )
+    }
+
+    /// Returns `true` if the input is hashed over the same dataset as `other`.
+    fn shares_dataset(&self, other: &OracleInput) -> bool {
+        (self.cache_size, self.dataset_size, self.seed_hash)
+            == (other.cache_size, other.dataset_size, other.seed_hash)
+    }
+}
+
+/// The hashes an oracle computed for an input.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+struct OracleOutput {
+    #[serde(with = "hex")]
+    mix_hash: Vec<u8>,
+    #[serde(with = "hex")]
+    final_hash: Vec<u8>,
+}
+
+/// Another ProgPoW implementation to compare against.
+pub trait Oracle {
+    /// Computes the `(mix_hash, final_hash)` of `input`.
+    fn hash(&mut self, input: &OracleInput) -> io::Result<(Vec<u8>, Vec<u8>)>;
+}
+
+/// An oracle running in a child process, speaking the protocol described in
+/// the module documentation on its standard input and output.
+pub struct ProcessOracle {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl ProcessOracle {
+    /// Starts `command`, a program followed by its arguments separated by
+    /// whitespace.
+    pub fn spawn(command: &str) -> io::Result<Self> {
+        let mut words = command.split_whitespace();
+        let program = words
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty oracle command"))?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Ok(ProcessOracle {
+            child,
+            stdin,
+            stdout,
+        })
+    }
+}
+
+impl Oracle for ProcessOracle {
+    fn hash(&mut self, input: &OracleInput) -> io::Result<(Vec<u8>, Vec<u8>)> {
+        serde_json::to_writer(&mut self.stdin, input)?;
+        self.stdin.write_all(b"\n")?;
+        self.stdin.flush()?;
+        let mut line = String::new();
+        if self.stdout.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "oracle exited before answering",
+            ));
+        }
+        let output: OracleOutput = serde_json::from_str(&line)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        Ok((output.mix_hash, output.final_hash))
+    }
+}
+
+impl Drop for ProcessOracle {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// An input on which an oracle disagrees with this crate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub input: OracleInput,
+    /// The `(mix_hash, final_hash)` this crate computed.
+    pub expected: (Vec<u8>, Vec<u8>),
+    /// The `(mix_hash, final_hash)` the oracle computed.
+    pub actual: (Vec<u8>, Vec<u8>),
+}
+
+/// Generates `count` pseudo-random inputs from `seed`.
+///
+/// Block numbers span the epochs below [`MAX_EPOCH`], each with its epoch's
+/// seed hash, and a quarter of them sit on an epoch boundary. Caches hold
+/// 16 to 1024 rows and datasets 64 to 4096 mixes, sizes small enough to
+/// generate per input.
+pub fn random_inputs(seed: u64, count: usize) -> Vec<OracleInput> {
+    let mut state = seed;
+    (0..count)
+        .map(|_| {
+            let mut header_hash = [0u8; 32];
+            for chunk in header_hash.chunks_exact_mut(8) {
+                chunk.copy_from_slice(&splitmix64(&mut state).to_le_bytes());
+            }
+            let nonce = splitmix64(&mut state);
+            let mut block_number = splitmix64(&mut state) % (MAX_EPOCH * EPOCH_LENGTH);
+            if block_number.is_multiple_of(4) {
+                block_number -= block_number % EPOCH_LENGTH;
+            }
+            OracleInput {
+                header_hash,
+                nonce,
+                block_number,
+                cache_size: 64 * (16 + splitmix64(&mut state) % 1009),
+                dataset_size: 256 * (64 + splitmix64(&mut state) % 4033),
+                seed_hash: seed_hash(epoch(block_number)),
+            }
+        })
+        .collect()
+}
+
+/// Hashes every input with this crate and with `oracle`, returning the
+/// inputs they disagree on.
+///
+/// Order inputs by dataset when they repeat: each run of inputs sharing one
+/// builds its light cache once.
+pub fn cross_check(
+    oracle: &mut dyn Oracle,
+    inputs: impl IntoIterator<Item = OracleInput>,
+) -> io::Result<Vec<Divergence>> {
+    let mut divergences = Vec::new();
+    let mut dataset: Option<(OracleInput, LightDag, Vec<u32>)> = None;
+    for input in inputs {
+        // Consecutive inputs often share a dataset; build it once for them.
+        if !matches!(&dataset, Some((last, ..)) if last.shares_dataset(&input)) {
+            let dag = input.light_dag();
+            let c_dag = dag.c_dag();
+            dataset = Some((input.clone(), dag, c_dag));
+        }
+        let (_, dag, c_dag) = dataset.as_ref().unwrap();
+        let expected = input.hash_over(dag, c_dag);
+        let actual = oracle.hash(&input)?;
+        if actual != expected {
+            divergences.push(Divergence {
+                input,
+                expected,
+                actual,
+            });
+        }
+    }
+    Ok(divergences)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An oracle hashing with this crate, optionally reversing the mix hash.
+    struct SelfOracle {
+        reverse_mix: bool,
+    }
+
+    impl Oracle for SelfOracle {
+        fn hash(&mut self, input: &OracleInput) -> io::Result<(Vec<u8>, Vec<u8>)> {
+            let (mut mix_hash, final_hash) = input.hash();
+            if self.reverse_mix {
+                mix_hash.reverse();
+            }
+            Ok((mix_hash, final_hash))
+        }
+    }
+
+    #[test]
+    fn test_cross_check_reports_divergence() {
+        let inputs = random_inputs(7, 3);
+        assert!(inputs
+            .iter()
+            .all(|input| input.cache_size.is_multiple_of(64)
+                && input.dataset_size.is_multiple_of(256)
+                && input.seed_hash == seed_hash(epoch(input.block_number))));
+        let json = serde_json::to_string(&inputs[0]).unwrap();
+        assert!(json.starts_with(r#"{"header_hash":"0x"#));
+
+        let mut agreeing = SelfOracle { reverse_mix: false };
+        assert_eq!(cross_check(&mut agreeing, inputs.clone()).unwrap(), vec![]);
+        let mut reversing = SelfOracle { reverse_mix: true };
+        let divergences = cross_check(&mut reversing, inputs).unwrap();
+        assert_eq!(divergences.len(), 3);
+        assert_eq!(divergences[0].actual.1, divergences[0].expected.1);
+    }
+
+    /// Cross-checks go-ethereum when `PROGPOW_GETH_ORACLE` names an oracle
+    /// command, for example one built from `examples/geth-oracle`.
+    #[test]
+    fn test_cross_check_against_geth() {
+        let Ok(command) = std::env::var("PROGPOW_GETH_ORACLE") else {
+            return;
+        };
+        let mut oracle = ProcessOracle::spawn(&command).unwrap();
+        let divergences = cross_check(&mut oracle, random_inputs(0x9e7, 64)).unwrap();
+        assert!(divergences.is_empty(), "{divergences:#?}");
+    }
+}
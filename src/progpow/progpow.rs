@@ -1,12 +1,12 @@
+use crate::endian::le_bytes;
 use crate::keccak::f800long::keccak_f800_long;
 use crate::keccak::f800short::keccak_f800_short;
 
 use crate::basic_algorithm::{
-    fill_mix, fnv1a, higher32, lower32, progpow_loop, PROGPOW_CACHE_BYTES, PROGPOW_CACHE_WORDS,
-    PROGPOW_CNT_CACHE, PROGPOW_CNT_DAG, PROGPOW_CNT_MATH, PROGPOW_DAG_LOADS, PROGPOW_LANES,
-    PROGPOW_MIX_BYTES, PROGPOW_PERIOD_LENGTH, PROGPOW_REGS,
+    fill_mix_lanes, fnv1a, progpow_loop, PROGPOW_CNT_DAG, PROGPOW_LANES, PROGPOW_MIX_BYTES,
+    PROGPOW_PERIOD_LENGTH, PROGPOW_REGS,
 };
-use byteorder::{ByteOrder, LittleEndian};
+use crate::progpow::generic::reduce_core;
 
 /// Implements the ProgPoW hashing algorithm.
 ///
@@ -29,10 +29,17 @@ use byteorder::{ByteOrder, LittleEndian};
 /// 1. `mix_hash` - A vector of 32 bytes representing the mix hash.
 /// 2. `final_hash` - A vector of 32 bytes representing the final hash.
 ///
+/// # Panics
+///
+/// Panics if `size` leaves ProgPoW no DAG items to load from: if it is less
+/// than `PROGPOW_MIX_BYTES`, or a multiple of 2^34 bytes, where
+/// go-ethereum's 32-bit item count wraps to zero. `c_dag` must hold
+/// `PROGPOW_CACHE_WORDS` words.
+///
 /// # Notes
 ///
 /// - This function is a critical part of the Proof of Work (PoW) algorithm for
-/// blockchain mining and is designed to be GPU-friendly.
+///   blockchain mining and is designed to be GPU-friendly.
 pub fn progpow(
     hash: &[u8],
     nonce: u64,
@@ -41,20 +48,118 @@ pub fn progpow(
     c_dag: &[u32],
     lookup: &dyn Fn(u32) -> Vec<u8>,
 ) -> (Vec<u8>, Vec<u8>) {
-    let mut mix = [[0u32; PROGPOW_REGS]; PROGPOW_LANES]; // Initialize mix registers.
-    let mut lane_results = [0u32; PROGPOW_LANES]; // Store results per lane.
-    let mut result = [0u32; 8]; // Final result array.
-
     // Compute the initial seed using Keccak-f800 short hash.
-    let seed = keccak_f800_short(hash, nonce, &mut result);
+    let seed = progpow_seed(hash, nonce);
+
+    progpow_from_seed(hash, seed, size, block_number, c_dag, lookup)
+}
+
+/// Computes the 64-bit ProgPoW seed for a header hash and nonce.
+///
+/// This is the cheap first stage of the hash: a single Keccak-f800 permutation
+/// that does not touch the DAG. Miners use it to pre-filter nonces before
+/// running the expensive mix loop.
+///
+/// # Arguments
+///
+/// * `hash` - A byte slice representing the header hash (32 bytes expected).
+/// * `nonce` - A 64-bit nonce value.
+///
+/// # Returns
+///
+/// The seed that initializes the lane mixes.
+pub fn progpow_seed(hash: &[u8], nonce: u64) -> u64 {
+    keccak_f800_short(hash, nonce, &[0u32; 8])
+}
+
+/// Runs the ProgPoW mix and final hash for an already computed seed.
+///
+/// This is [`progpow`] without the initial seed computation, so callers that
+/// already hold the seed (for example after a pre-check) do not hash twice.
+pub(crate) fn progpow_from_seed(
+    hash: &[u8],
+    seed: u64,
+    size: u64,
+    block_number: u64,
+    c_dag: &[u32],
+    lookup: &dyn Fn(u32) -> Vec<u8>,
+) -> (Vec<u8>, Vec<u8>) {
+    let period = block_number / PROGPOW_PERIOD_LENGTH;
+    progpow_from_seed_at_period(hash, seed, size, period, c_dag, lookup)
+}
+
+/// Runs [`progpow_from_seed`] with the program of `period`, for chains
+/// that change programs at another rate.
+pub(crate) fn progpow_from_seed_at_period(
+    hash: &[u8],
+    seed: u64,
+    size: u64,
+    period: u64,
+    c_dag: &[u32],
+    lookup: &dyn Fn(u32) -> Vec<u8>,
+) -> (Vec<u8>, Vec<u8>) {
+    #[cfg(feature = "metrics")]
+    let started = std::time::Instant::now();
+    let lane_results = period_lane_hashes(seed, size, period, c_dag, lookup);
+    let result = reduce_lane_hashes(&lane_results);
+
+    // Compute the final hash using Keccak-f800 long hash.
+    let final_hash = keccak_f800_long(hash, seed, &result);
 
-    // Initialize the mix for each lane using the seed.
-    for lane in 0..PROGPOW_LANES {
-        mix[lane] = fill_mix(seed, lane as u32);
+    // Convert the `result` array to a mix hash (32 bytes).
+    let mix_hash = le_bytes(&result[..8]);
+
+    #[cfg(feature = "metrics")]
+    crate::metrics::global().hashes.observe(started.elapsed());
+    // Return the mix hash and final hash.
+    (mix_hash, final_hash)
+}
+
+/// Folds the per-lane hashes into the eight words of the mix hash.
+pub(crate) fn reduce_lane_hashes(lane_results: &[u32; PROGPOW_LANES]) -> [u32; 8] {
+    let mut result = [0u32; 8]; // Final result array.
+
+    // Combine lane results into the final result array.
+    result.fill(0x811c9dc5); // Initialize each result element with FNV offset basis.
+    for (lane, &lane_result) in lane_results.iter().enumerate() {
+        fnv1a(&mut result[lane % 8], lane_result); // Apply FNV-1a reduction.
     }
+    result
+}
 
+/// Runs the ProgPoW mix loop for a seed and reduces each lane to one word.
+///
+/// These per-lane hashes are the last state shared by every lane before the
+/// final reduction, which makes them the natural checkpoint when comparing
+/// implementations.
+pub(crate) fn progpow_lane_hashes(
+    seed: u64,
+    size: u64,
+    block_number: u64,
+    c_dag: &[u32],
+    lookup: &dyn Fn(u32) -> Vec<u8>,
+) -> [u32; PROGPOW_LANES] {
     // Compute the period based on the block number and PROGPOW_PERIOD_LENGTH.
     let period = block_number / PROGPOW_PERIOD_LENGTH;
+    period_lane_hashes(seed, size, period, c_dag, lookup)
+}
+
+/// Runs the mix loop of the program for `period` and reduces each lane to
+/// one word, for algorithms that derive the period differently.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(level = "trace", skip(size, c_dag, lookup))
+)]
+pub(crate) fn period_lane_hashes(
+    seed: u64,
+    size: u64,
+    period: u64,
+    c_dag: &[u32],
+    lookup: &dyn Fn(u32) -> Vec<u8>,
+) -> [u32; PROGPOW_LANES] {
+    // Initialize the mix for every lane from the seed.
+    let mut mix = fill_mix_lanes(seed);
+    let mut lane_results = [0u32; PROGPOW_LANES]; // Store results per lane.
 
     // Execute the ProgPoW loop `PROGPOW_CNT_DAG` times.
     for l in 0..PROGPOW_CNT_DAG {
@@ -69,30 +174,14 @@ pub fn progpow(
     }
 
     // Reduce the mix data to a single result per lane.
-    for lane in 0..PROGPOW_LANES {
-        lane_results[lane] = 0x811c9dc5; // Initialize with FNV offset basis.
-        for i in 0..PROGPOW_REGS {
-            fnv1a(&mut lane_results[lane], mix[lane][i]); // Apply FNV-1a hash.
-        }
-    }
-
-    // Combine lane results into the final result array.
-    for i in 0..8 {
-        result[i] = 0x811c9dc5; // Initialize each result element with FNV offset basis.
-    }
-    for lane in 0..PROGPOW_LANES {
-        fnv1a(&mut result[lane % 8], lane_results[lane]); // Apply FNV-1a reduction.
-    }
-
-    // Compute the final hash using Keccak-f800 long hash.
-    let final_hash = keccak_f800_long(hash, seed, &result);
-
-    // Convert the `result` array to a mix hash (32 bytes).
-    let mut mix_hash = vec![0u8; 8 * 4];
-    for i in 0..8 {
-        LittleEndian::write_u32(&mut mix_hash[i * 4..], result[i]);
-    }
+    reduce_mix(&mix, &mut lane_results);
+    lane_results
+}
 
-    // Return the mix hash and final hash.
-    (mix_hash, final_hash)
+/// Reduces each lane's registers to one word of `lane_results`.
+pub(crate) fn reduce_mix(
+    mix: &[[u32; PROGPOW_REGS]; PROGPOW_LANES],
+    lane_results: &mut [u32; PROGPOW_LANES],
+) {
+    *lane_results = reduce_core(mix);
 }
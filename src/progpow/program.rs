@@ -0,0 +1,320 @@
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::basic_algorithm::{
+    kiss99, load_dag_item, merge, progpow_init, progpow_math, PROGPOW_CACHE_WORDS,
+    PROGPOW_CNT_CACHE, PROGPOW_CNT_MATH, PROGPOW_DAG_LOADS, PROGPOW_LANES, PROGPOW_REGS,
+};
+use crate::keccak::keccak256;
+
+/// A single step of the per-period random program.
+///
+/// The `*_sel` fields hold the raw KISS99 outputs that select the math and
+/// merge operations, exactly as drawn by [`progpow_loop`](crate::basic_algorithm::progpow_loop).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ProgramOp {
+    /// Load `c_dag[mix[src] % PROGPOW_CACHE_WORDS]` and merge it into `mix[dst]`.
+    Cache { src: u32, dst: u32, merge_sel: u32 },
+    /// Compute `math(mix[src1], mix[src2])` and merge the result into `mix[dst]`.
+    Math {
+        src1: u32,
+        src2: u32,
+        math_sel: u32,
+        dst: u32,
+        merge_sel: u32,
+    },
+    /// Merge word `word` of the lane's global DAG load into `mix[dst]`.
+    DagMerge { word: u32, dst: u32, merge_sel: u32 },
+}
+
+/// The random program executed by every lane during one period.
+///
+/// ProgPoW derives the sequence of cache loads, math operations, and merges
+/// from the period alone, and GPU miners compile it into a kernel once per
+/// period. This type is the decoded sequence shared by the kernel generators.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Program {
+    /// The period (program seed) the program was generated for.
+    pub period: u64,
+    /// The operations in execution order.
+    pub ops: Vec<ProgramOp>,
+}
+
+impl Program {
+    /// Decodes the random program for a period.
+    ///
+    /// Random values are drawn in the same order as the CPU loop, so the
+    /// program performs the same operations as `progpow_loop(period, ..)`.
+    ///
+    /// # Arguments
+    ///
+    /// * `period` - The program seed, i.e. `block_number / PROGPOW_PERIOD_LENGTH`.
+    ///
+    /// # Returns
+    ///
+    /// The decoded [`Program`].
+    pub fn generate(period: u64) -> Self {
+        let (mut rand_state, dst_seq, src_seq) = progpow_init(period);
+        let mut src_counter = 0usize;
+        let mut dst_counter = 0usize;
+        let mut ops = Vec::with_capacity(PROGPOW_CNT_CACHE + PROGPOW_CNT_MATH + PROGPOW_DAG_LOADS);
+
+        for i in 0..PROGPOW_CNT_CACHE.max(PROGPOW_CNT_MATH) {
+            if i < PROGPOW_CNT_CACHE {
+                // Cached memory access
+                let src = src_seq[src_counter % PROGPOW_REGS];
+                src_counter += 1;
+                let dst = dst_seq[dst_counter % PROGPOW_REGS];
+                dst_counter += 1;
+                let merge_sel = kiss99(&mut rand_state);
+                ops.push(ProgramOp::Cache {
+                    src,
+                    dst,
+                    merge_sel,
+                });
+            }
+            if i < PROGPOW_CNT_MATH {
+                // Random Math, with two distinct sources
+                let src_rnd = kiss99(&mut rand_state) % (PROGPOW_REGS * (PROGPOW_REGS - 1)) as u32;
+                let src1 = src_rnd % PROGPOW_REGS as u32;
+                let mut src2 = src_rnd / PROGPOW_REGS as u32;
+                if src2 >= src1 {
+                    src2 += 1;
+                }
+                let math_sel = kiss99(&mut rand_state);
+                let dst = dst_seq[dst_counter % PROGPOW_REGS];
+                dst_counter += 1;
+                let merge_sel = kiss99(&mut rand_state);
+                ops.push(ProgramOp::Math {
+                    src1,
+                    src2,
+                    math_sel,
+                    dst,
+                    merge_sel,
+                });
+            }
+        }
+
+        // The global load is consumed last, and always feeds mix[0] first.
+        ops.push(ProgramOp::DagMerge {
+            word: 0,
+            dst: 0,
+            merge_sel: kiss99(&mut rand_state),
+        });
+        for word in 1..PROGPOW_DAG_LOADS as u32 {
+            let dst = dst_seq[dst_counter % PROGPOW_REGS];
+            dst_counter += 1;
+            ops.push(ProgramOp::DagMerge {
+                word,
+                dst,
+                merge_sel: kiss99(&mut rand_state),
+            });
+        }
+
+        Program { period, ops }
+    }
+
+    /// Returns a digest identifying the program's operations.
+    ///
+    /// Miners, kernels and verifiers compare digests to agree cheaply that
+    /// they run the same program, and kernel caches can be keyed by it. It is
+    /// the Keccak-256 of the loop shape (`PROGPOW_LANES`, `PROGPOW_REGS`,
+    /// `PROGPOW_DAG_LOADS`, `PROGPOW_CNT_CACHE` and `PROGPOW_CNT_MATH`)
+    /// followed by each operation as a tag byte (0 cache, 1 math, 2 DAG
+    /// merge) and its fields in declaration order, all words little-endian.
+    /// The period is not part of it: two periods drawing the same
+    /// operations have the same digest.
+    ///
+    /// # Returns
+    ///
+    /// The 32-byte digest.
+    pub fn digest(&self) -> [u8; 32] {
+        let mut bytes = Vec::with_capacity(20 + self.ops.len() * 21);
+        for word in [
+            PROGPOW_LANES,
+            PROGPOW_REGS,
+            PROGPOW_DAG_LOADS,
+            PROGPOW_CNT_CACHE,
+            PROGPOW_CNT_MATH,
+        ] {
+            bytes.extend_from_slice(&(word as u32).to_le_bytes());
+        }
+        for op in &self.ops {
+            let (tag, fields): (u8, &[u32]) = match op {
+                ProgramOp::Cache {
+                    src,
+                    dst,
+                    merge_sel,
+                } => (0, &[*src, *dst, *merge_sel]),
+                ProgramOp::Math {
+                    src1,
+                    src2,
+                    math_sel,
+                    dst,
+                    merge_sel,
+                } => (1, &[*src1, *src2, *math_sel, *dst, *merge_sel]),
+                ProgramOp::DagMerge {
+                    word,
+                    dst,
+                    merge_sel,
+                } => (2, &[*word, *dst, *merge_sel]),
+            };
+            bytes.push(tag);
+            for field in fields {
+                bytes.extend_from_slice(&field.to_le_bytes());
+            }
+        }
+        keccak256(&bytes)
+    }
+
+    /// Executes one loop iteration of the program on the CPU.
+    ///
+    /// Unlike [`progpow_loop`](crate::basic_algorithm::progpow_loop), this
+    /// interprets the decoded operations rather than drawing them from KISS99,
+    /// so it runs exactly what the kernel generators emit. Comparing the two is
+    /// how generated programs are tested against the reference loop.
+    ///
+    /// # Arguments
+    ///
+    /// * `loop_index` - The index of the current loop iteration.
+    /// * `mix` - A mutable reference to the mix data.
+    /// * `lookup` - A function to retrieve DAG items based on an index.
+    /// * `c_dag` - The compressed DAG data.
+    /// * `dataset_size` - The size of the dataset.
+    pub fn execute_loop(
+        &self,
+        loop_index: u32,
+        mix: &mut [[u32; PROGPOW_REGS]; PROGPOW_LANES],
+        lookup: &dyn Fn(u32) -> Vec<u8>,
+        c_dag: &[u32],
+        dataset_size: u32,
+    ) {
+        let dag_item = load_dag_item(loop_index, mix, lookup, dataset_size);
+
+        for (l, lane_mix) in mix.iter_mut().enumerate() {
+            let index = ((l as u32 ^ loop_index) % PROGPOW_LANES as u32) * PROGPOW_DAG_LOADS as u32;
+            for op in &self.ops {
+                match *op {
+                    ProgramOp::Cache {
+                        src,
+                        dst,
+                        merge_sel,
+                    } => {
+                        let offset = lane_mix[src as usize] % PROGPOW_CACHE_WORDS as u32;
+                        merge(
+                            &mut lane_mix[dst as usize],
+                            c_dag[offset as usize],
+                            merge_sel,
+                        );
+                    }
+                    ProgramOp::Math {
+                        src1,
+                        src2,
+                        math_sel,
+                        dst,
+                        merge_sel,
+                    } => {
+                        let data = progpow_math(
+                            lane_mix[src1 as usize],
+                            lane_mix[src2 as usize],
+                            math_sel,
+                        );
+                        merge(&mut lane_mix[dst as usize], data, merge_sel);
+                    }
+                    ProgramOp::DagMerge {
+                        word,
+                        dst,
+                        merge_sel,
+                    } => {
+                        let data =
+                            LittleEndian::read_u32(&dag_item[(4 * (index + word)) as usize..]);
+                        merge(&mut lane_mix[dst as usize], data, merge_sel);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns the digest of the program of `period`; see [`Program::digest`].
+pub fn program_digest(period: u64) -> [u8; 32] {
+    Program::generate(period).digest()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hex;
+    use crate::testutil::{mock_c_dag, mock_lookup};
+
+    #[test]
+    fn test_program_shape() {
+        let program = Program::generate(0);
+        let count = |f: fn(&ProgramOp) -> bool| program.ops.iter().filter(|op| f(op)).count();
+
+        assert_eq!(
+            count(|op| matches!(op, ProgramOp::Cache { .. })),
+            PROGPOW_CNT_CACHE
+        );
+        assert_eq!(
+            count(|op| matches!(op, ProgramOp::Math { .. })),
+            PROGPOW_CNT_MATH
+        );
+        assert_eq!(
+            count(|op| matches!(op, ProgramOp::DagMerge { .. })),
+            PROGPOW_DAG_LOADS
+        );
+        for op in &program.ops {
+            if let ProgramOp::Math { src1, src2, .. } = op {
+                assert_ne!(src1, src2);
+            }
+        }
+        assert_eq!(program, Program::generate(0));
+        assert_ne!(program, Program::generate(1));
+    }
+
+    #[test]
+    fn test_program_digest() {
+        let program = Program::generate(0);
+        assert_eq!(program.digest(), program_digest(0));
+        assert_ne!(program_digest(0), program_digest(1));
+
+        // The digest follows the operations, not the period.
+        let mut renamed = Program::generate(1);
+        renamed.period = 0;
+        assert_eq!(renamed.digest(), program_digest(1));
+        let mut changed = program.clone();
+        if let ProgramOp::Cache { merge_sel, .. } = &mut changed.ops[0] {
+            *merge_sel ^= 1;
+        }
+        assert_ne!(changed.digest(), program.digest());
+
+        assert_eq!(
+            hex::encode(&program.digest()),
+            "deb4401926aa4aad30d922de76ecf539f0c549425e020fc63646aa4991fe35bd"
+        );
+    }
+
+    #[test]
+    fn test_program_matches_cpu_loop() {
+        use crate::basic_algorithm::{fill_mix, progpow_loop, PROGPOW_CNT_DAG};
+
+        let (c_dag, lookup) = (mock_c_dag(1), mock_lookup(1));
+
+        for period in [0u64, 1, 0xdead_beef_0042] {
+            let program = Program::generate(period);
+            let mut expected = [[0u32; PROGPOW_REGS]; PROGPOW_LANES];
+            for (lane, lane_mix) in expected.iter_mut().enumerate() {
+                *lane_mix = fill_mix(0x1234_5678_9abc_def0 ^ period, lane as u32);
+            }
+            let mut actual = expected;
+
+            for loop_index in 0..PROGPOW_CNT_DAG as u32 {
+                progpow_loop(period, loop_index, &mut expected, &lookup, &c_dag, 64);
+                program.execute_loop(loop_index, &mut actual, &lookup, &c_dag, 64);
+                assert_eq!(actual, expected, "period {period}, loop {loop_index}");
+            }
+        }
+    }
+}
@@ -0,0 +1,365 @@
+//! Proofs of the DAG items one hash reads, checked against a dataset root.
+//!
+//! A ProgPoW hash reads the cached DAG words, the dataset's first 256
+//! items, and then four consecutive items in each of its 64 loops. An
+//! [`AccessProof`] carries exactly those, each with its branch in the
+//! dataset's [Merkle tree](crate::ethash::merkle), so [`verify_with_proof`]
+//! checks a seal knowing only the epoch's dataset root and size, without
+//! the dataset or its cache: what stateless and on-chain verifiers need.
+//! [`prove_access`] builds the proof for a hash from the tree and the
+//! dataset, on the machine that holds them.
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+
+use crate::basic_algorithm::{PROGPOW_CACHE_WORDS, PROGPOW_CNT_DAG, PROGPOW_MIX_BYTES};
+use crate::ethash::buffer::DagBuffer;
+use crate::ethash::dataset::HASH_WORDS;
+use crate::ethash::merkle::{leaf_hash, verify_branch, DagMerkleTree};
+use crate::progpow::progpow::progpow;
+use crate::progpow::verify::{check_seal_with, Seal, SealBoundary, SealError, TargetRule};
+
+/// Items the cached DAG words span.
+const CACHE_ITEMS: usize = PROGPOW_CACHE_WORDS / HASH_WORDS;
+
+/// The level of the tree whose first node covers the cached DAG words.
+const CACHE_LEVEL: usize = CACHE_ITEMS.trailing_zeros() as usize;
+
+/// Items loaded in each loop.
+const LOOP_ITEMS: usize = PROGPOW_MIX_BYTES / (HASH_WORDS * 4);
+
+/// One dataset item with its branch to the dataset root.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ItemProof {
+    /// The item's index in the dataset.
+    pub index: u32,
+    /// The item as little-endian words.
+    pub item: [u32; HASH_WORDS],
+    /// The item's branch; see [`DagMerkleTree::branch`].
+    pub branch: Vec<[u8; 32]>,
+}
+
+impl ItemProof {
+    /// Returns the item's 64 bytes.
+    pub fn bytes(&self) -> [u8; 64] {
+        let mut bytes = [0u8; 64];
+        for (chunk, word) in bytes.chunks_exact_mut(4).zip(self.item) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+/// The DAG items one hash reads, each proven against the dataset root.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AccessProof {
+    /// The cached DAG words, the dataset's first `PROGPOW_CACHE_WORDS`.
+    pub c_dag: Vec<u32>,
+    /// The branch of the tree node over the cached words' items.
+    pub c_dag_branch: Vec<[u8; 32]>,
+    /// The loaded items in the order the hash reads them, four per loop.
+    pub loads: Vec<ItemProof>,
+}
+
+/// The reason [`verify_with_proof`] rejected a seal.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProofError {
+    /// The cached DAG words are not the dataset's.
+    CacheNotProven,
+    /// Load `load` is not the item the hash reads there, or its branch does
+    /// not lead to the root.
+    LoadNotProven {
+        /// The position of the load in [`AccessProof::loads`].
+        load: usize,
+    },
+    /// The proof holds fewer or more loads than the hash reads.
+    LoadCount {
+        /// The loads in the proof.
+        found: usize,
+    },
+    /// Every access is proven, and the seal fails.
+    Seal(SealError),
+}
+
+impl fmt::Display for ProofError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProofError::CacheNotProven => write!(f, "the cached DAG words are not proven"),
+            ProofError::LoadNotProven { load } => write!(f, "DAG load {load} is not proven"),
+            ProofError::LoadCount { found } => write!(
+                f,
+                "the proof holds {found} DAG loads, expected {}",
+                PROGPOW_CNT_DAG * LOOP_ITEMS
+            ),
+            ProofError::Seal(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for ProofError {}
+
+impl From<SealError> for ProofError {
+    fn from(error: SealError) -> Self {
+        ProofError::Seal(error)
+    }
+}
+
+/// Proves the DAG accesses of the hash of a header hash and nonce.
+///
+/// # Arguments
+///
+/// * `tree` - The Merkle tree of `dag`.
+/// * `dag` - The dataset.
+/// * `header_hash` - The 32-byte header hash.
+/// * `nonce` - The nonce.
+/// * `block_number` - The block number, which selects the program.
+///
+/// # Returns
+///
+/// The proof of every item the hash reads.
+///
+/// # Panics
+///
+/// Panics if `tree` has fewer items than `dag`, or where `progpow` does.
+pub fn prove_access(
+    tree: &DagMerkleTree,
+    dag: &dyn DagBuffer,
+    header_hash: &[u8; 32],
+    nonce: u64,
+    block_number: u64,
+) -> AccessProof {
+    prove_with(tree, dag, &|size, c_dag, lookup| {
+        progpow(header_hash, nonce, size, block_number, c_dag, lookup)
+    })
+}
+
+/// A hash over a dataset given as its size, cached words and item lookup.
+pub(crate) type DagHash<'a> =
+    dyn Fn(u64, &[u32], &dyn Fn(u32) -> Vec<u8>) -> (Vec<u8>, Vec<u8>) + 'a;
+
+/// Proves the DAG accesses of `hash`, for [`prove_access`] and chains
+/// hashing otherwise.
+pub(crate) fn prove_with(tree: &DagMerkleTree, dag: &dyn DagBuffer, hash: &DagHash) -> AccessProof {
+    let c_dag = dag.c_dag();
+    let loads = RefCell::new(Vec::with_capacity(PROGPOW_CNT_DAG * LOOP_ITEMS));
+    let lookup = |word: u32| {
+        let index = word / HASH_WORDS as u32;
+        let mut bytes = [0u8; 64];
+        dag.read_item(index, &mut bytes);
+        loads.borrow_mut().push(ItemProof {
+            index,
+            item: std::array::from_fn(|i| {
+                u32::from_le_bytes(bytes[4 * i..4 * i + 4].try_into().unwrap())
+            }),
+            branch: tree.branch(0, index),
+        });
+        bytes.to_vec()
+    };
+    hash(dag.size(), &c_dag, &lookup);
+    AccessProof {
+        c_dag,
+        c_dag_branch: tree.branch(CACHE_LEVEL, 0),
+        loads: loads.into_inner(),
+    }
+}
+
+/// Verifies a seal with the DAG items of `proof` in place of the dataset.
+///
+/// # Arguments
+///
+/// * `seal` - The seal to verify.
+/// * `size` - The size of the dataset in bytes, from the chain's rules
+///   rather than from the prover.
+/// * `root` - The trusted Merkle root of the dataset.
+/// * `proof` - The proof of the hash's accesses.
+///
+/// # Returns
+///
+/// The final hash if every access is proven and the seal holds, or the
+/// [`ProofError`] describing the first check that failed.
+pub fn verify_with_proof(
+    seal: &Seal,
+    size: u64,
+    root: &[u8; 32],
+    proof: &AccessProof,
+) -> Result<Vec<u8>, ProofError> {
+    verify_proof_with(seal, size, root, proof, &|size, c_dag, lookup| {
+        progpow(
+            &seal.header_hash,
+            seal.nonce,
+            size,
+            seal.block_number,
+            c_dag,
+            lookup,
+        )
+    })
+}
+
+/// Verifies a seal hashed by `hash` with the DAG items of `proof`, for
+/// [`verify_with_proof`] and chains hashing otherwise.
+pub(crate) fn verify_proof_with(
+    seal: &Seal,
+    size: u64,
+    root: &[u8; 32],
+    proof: &AccessProof,
+    hash: &DagHash,
+) -> Result<Vec<u8>, ProofError> {
+    verify_cached_words(size, root, proof)?;
+    verify_loads_with(seal, size, root, proof, hash, &SealBoundary)
+}
+
+/// Returns the number of items in a dataset of `size` bytes.
+fn item_count(size: u64) -> Result<u32, ProofError> {
+    u32::try_from(size / 64).map_err(|_| ProofError::CacheNotProven)
+}
+
+/// Checks the cached DAG words of `proof` against the root of a dataset of
+/// `size` bytes; the first half of [`verify_proof_with`], which one batch
+/// of proofs of an epoch needs only once.
+pub(crate) fn verify_cached_words(
+    size: u64,
+    root: &[u8; 32],
+    proof: &AccessProof,
+) -> Result<(), ProofError> {
+    let items = item_count(size)?;
+    if proof.c_dag.len() != PROGPOW_CACHE_WORDS || (items as usize) < CACHE_ITEMS {
+        return Err(ProofError::CacheNotProven);
+    }
+    let cache_leaves = proof
+        .c_dag
+        .chunks_exact(HASH_WORDS)
+        .map(|words| {
+            let mut bytes = [0u8; 64];
+            for (chunk, word) in bytes.chunks_exact_mut(4).zip(words) {
+                chunk.copy_from_slice(&word.to_le_bytes());
+            }
+            leaf_hash(&bytes)
+        })
+        .collect();
+    let cache_node = DagMerkleTree::from_leaves(cache_leaves).root();
+    if !verify_branch(
+        root,
+        items,
+        CACHE_LEVEL,
+        0,
+        &cache_node,
+        &proof.c_dag_branch,
+    ) {
+        return Err(ProofError::CacheNotProven);
+    }
+    Ok(())
+}
+
+/// Checks the loads of `proof`, whose cached words are proven, as `hash`
+/// reads them, and the final hash against `rule`; the second half of
+/// [`verify_proof_with`].
+pub(crate) fn verify_loads_with(
+    seal: &Seal,
+    size: u64,
+    root: &[u8; 32],
+    proof: &AccessProof,
+    hash: &DagHash,
+    rule: &dyn TargetRule,
+) -> Result<Vec<u8>, ProofError> {
+    let items = item_count(size)?;
+    if proof.loads.len() != PROGPOW_CNT_DAG * LOOP_ITEMS {
+        return Err(ProofError::LoadCount {
+            found: proof.loads.len(),
+        });
+    }
+
+    // Serve the loads in order, noting the first the hash does not ask for;
+    // the lookup cannot fail, so the hash runs to the end regardless.
+    let next = Cell::new(0);
+    let unproven = Cell::new(None);
+    let lookup = |word: u32| {
+        let load = next.get();
+        next.set(load + 1);
+        let proven = proof.loads.get(load).filter(|proven| {
+            proven.index == word / HASH_WORDS as u32
+                && verify_branch(
+                    root,
+                    items,
+                    0,
+                    proven.index,
+                    &leaf_hash(&proven.bytes()),
+                    &proven.branch,
+                )
+        });
+        match proven {
+            Some(proven) => proven.bytes().to_vec(),
+            None => {
+                unproven.set(unproven.get().or(Some(load)));
+                vec![0; 64]
+            }
+        }
+    };
+    let (mix_hash, final_hash) = hash(size, &proof.c_dag, &lookup);
+    if let Some(load) = unproven.get() {
+        return Err(ProofError::LoadNotProven { load });
+    }
+    check_seal_with(seal, &mix_hash, &final_hash, rule)?;
+    Ok(final_hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ethash::dataset::generate_dataset;
+    use crate::testutil::{header_hash, tiny_cache, valid_seal};
+
+    #[test]
+    fn test_access_proof_verifies_against_the_root() {
+        let cache = tiny_cache(0);
+        let dataset = generate_dataset(cache.cache_words(), cache.size());
+        let tree = DagMerkleTree::build(&dataset);
+        let root = cache.dataset_root();
+        let seal = valid_seal(&cache, header_hash(3), 5, 11);
+
+        let proof = prove_access(&tree, &dataset, &seal.header_hash, seal.nonce, 5);
+        assert_eq!(proof.loads.len(), PROGPOW_CNT_DAG * LOOP_ITEMS);
+        assert_eq!(
+            verify_with_proof(&seal, cache.size(), &root, &proof),
+            cache.verify_seal(&seal).map_err(ProofError::Seal)
+        );
+
+        let mut wrong_item = proof.clone();
+        wrong_item.loads[9].item[0] ^= 1;
+        assert_eq!(
+            verify_with_proof(&seal, cache.size(), &root, &wrong_item),
+            Err(ProofError::LoadNotProven { load: 9 })
+        );
+        let mut wrong_index = proof.clone();
+        wrong_index.loads.swap(4, 8);
+        assert!(matches!(
+            verify_with_proof(&seal, cache.size(), &root, &wrong_index),
+            Err(ProofError::LoadNotProven { load: 4 })
+        ));
+        let mut wrong_cache = proof.clone();
+        wrong_cache.c_dag[17] ^= 1;
+        assert_eq!(
+            verify_with_proof(&seal, cache.size(), &root, &wrong_cache),
+            Err(ProofError::CacheNotProven)
+        );
+        let mut short = proof.clone();
+        short.loads.pop();
+        assert_eq!(
+            verify_with_proof(&seal, cache.size(), &root, &short),
+            Err(ProofError::LoadCount { found: 255 })
+        );
+        assert_eq!(
+            verify_with_proof(&seal, cache.size(), &[0; 32], &proof),
+            Err(ProofError::CacheNotProven)
+        );
+
+        // The proof holds, and the seal still has to.
+        let mut wrong_mix = seal.clone();
+        wrong_mix.mix_hash[0] ^= 1;
+        assert!(matches!(
+            verify_with_proof(&wrong_mix, cache.size(), &root, &proof),
+            Err(ProofError::Seal(SealError::MixMismatch { .. }))
+        ));
+    }
+}
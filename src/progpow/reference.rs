@@ -0,0 +1,128 @@
+//! Differential testing against the reference C++ ProgPoW implementation.
+//!
+//! With the `reference-cpp` feature and `PROGPOW_REFERENCE_DIR` naming a
+//! checkout of the reference, the build script compiles it together with
+//! `reference/shim.cpp`, and [`ReferenceOracle`] hashes through it. The
+//! reference derives its caches from the epoch, so inputs cover full epoch
+//! datasets; [`epoch_inputs`] generates them for [`cross_check`].
+//!
+//! [`cross_check`]: crate::progpow::oracle::cross_check
+
+use std::io;
+use std::ops::Range;
+
+use crate::ethash::cache::{cache_size, dataset_size, epoch, seed_hash, EPOCH_LENGTH};
+use crate::progpow::oracle::{Oracle, OracleInput};
+use crate::testutil::splitmix64;
+
+#[cfg(progpow_reference)]
+extern "C" {
+    fn progpow_reference_hash(
+        epoch: std::ffi::c_int,
+        block_number: u64,
+        header_hash: *const u8,
+        nonce: u64,
+        mix_hash: *mut u8,
+        final_hash: *mut u8,
+    );
+}
+
+/// The reference C++ implementation, when it was built in.
+pub struct ReferenceOracle {
+    _built: (),
+}
+
+impl ReferenceOracle {
+    /// Returns the oracle, or `None` if the reference was not built in.
+    pub fn new() -> Option<Self> {
+        cfg!(progpow_reference).then_some(ReferenceOracle { _built: () })
+    }
+}
+
+impl Oracle for ReferenceOracle {
+    /// Hashes `input`, which must use its epoch's full cache and dataset.
+    fn hash(&mut self, input: &OracleInput) -> io::Result<(Vec<u8>, Vec<u8>)> {
+        let epoch = epoch(input.block_number);
+        if (input.cache_size, input.dataset_size, input.seed_hash)
+            != (cache_size(epoch), dataset_size(epoch), seed_hash(epoch))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the reference only hashes over full epoch datasets",
+            ));
+        }
+        #[cfg(progpow_reference)]
+        {
+            let (mut mix_hash, mut final_hash) = ([0u8; 32], [0u8; 32]);
+            // SAFETY: the header is 32 bytes and both outputs have room for
+            // the 32 bytes the shim writes.
+            unsafe {
+                progpow_reference_hash(
+                    epoch as std::ffi::c_int,
+                    input.block_number,
+                    input.header_hash.as_ptr(),
+                    input.nonce,
+                    mix_hash.as_mut_ptr(),
+                    final_hash.as_mut_ptr(),
+                );
+            }
+            Ok((mix_hash.to_vec(), final_hash.to_vec()))
+        }
+        #[cfg(not(progpow_reference))]
+        unreachable!("ReferenceOracle::new returns None without the reference")
+    }
+}
+
+/// Generates `count` pseudo-random inputs over the full datasets of
+/// `epochs`, ordered by block number so each epoch's cache is built once.
+///
+/// A quarter of the block numbers sit on an epoch boundary.
+pub fn epoch_inputs(seed: u64, count: usize, epochs: Range<u64>) -> Vec<OracleInput> {
+    let mut state = seed;
+    let mut inputs: Vec<OracleInput> = (0..count)
+        .map(|_| {
+            let mut header_hash = [0u8; 32];
+            for chunk in header_hash.chunks_exact_mut(8) {
+                chunk.copy_from_slice(&splitmix64(&mut state).to_le_bytes());
+            }
+            let nonce = splitmix64(&mut state);
+            let epoch = epochs.start + splitmix64(&mut state) % (epochs.end - epochs.start);
+            let offset = splitmix64(&mut state) % EPOCH_LENGTH;
+            let block_number =
+                epoch * EPOCH_LENGTH + if offset.is_multiple_of(4) { 0 } else { offset };
+            OracleInput {
+                header_hash,
+                nonce,
+                block_number,
+                cache_size: cache_size(epoch),
+                dataset_size: dataset_size(epoch),
+                seed_hash: seed_hash(epoch),
+            }
+        })
+        .collect();
+    inputs.sort_by_key(|input| input.block_number);
+    inputs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progpow::oracle::cross_check;
+
+    /// Hashes over full epochs; run with `--release` when the reference is
+    /// built in.
+    #[test]
+    fn test_reference_matches_random_inputs() {
+        let inputs = epoch_inputs(0xc0ffee, 32, 0..2);
+        assert!(inputs
+            .windows(2)
+            .all(|pair| pair[0].block_number <= pair[1].block_number));
+        assert!(inputs.iter().all(|input| epoch(input.block_number) < 2));
+
+        let Some(mut oracle) = ReferenceOracle::new() else {
+            return;
+        };
+        let divergences = cross_check(&mut oracle, inputs).unwrap();
+        assert!(divergences.is_empty(), "{divergences:#?}");
+    }
+}
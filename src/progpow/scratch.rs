@@ -0,0 +1,214 @@
+//! Reusable working sets for hashing on many threads.
+//!
+//! [`progpow`](crate::progpow::progpow::progpow) allocates as it goes: a
+//! buffer for each loop's DAG item, one for every item `lookup` returns and
+//! the two result vectors, a few hundred allocations per hash. With many
+//! threads verifying at once they contend in the allocator. A [`Scratch`]
+//! holds everything one hash needs, reads items straight into its own
+//! buffer with [`DagBuffer::read_item`] and keeps the results, so hashing
+//! with it allocates nothing; a [`ScratchPool`] hands scratches out to
+//! workers and takes them back.
+
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+use crate::basic_algorithm::{
+    dag_load_base, fill_mix_lanes, progpow_loop_with_item, PROGPOW_CNT_DAG, PROGPOW_LANES,
+    PROGPOW_MIX_BYTES, PROGPOW_PERIOD_LENGTH, PROGPOW_REGS,
+};
+use crate::ethash::buffer::DagBuffer;
+use crate::ethash::dataset::HASH_WORDS;
+use crate::keccak::f800long::keccak_f800_long_into;
+use crate::progpow::progpow::{progpow_seed, reduce_lane_hashes, reduce_mix};
+
+/// The working set of one hash.
+pub struct Scratch {
+    mix: [[u32; PROGPOW_REGS]; PROGPOW_LANES],
+    lane_results: [u32; PROGPOW_LANES],
+    dag_item: [u8; PROGPOW_MIX_BYTES],
+    mix_hash: [u8; 32],
+    final_hash: [u8; 32],
+}
+
+impl Scratch {
+    /// Creates a zeroed working set.
+    pub fn new() -> Self {
+        Scratch {
+            mix: [[0; PROGPOW_REGS]; PROGPOW_LANES],
+            lane_results: [0; PROGPOW_LANES],
+            dag_item: [0; PROGPOW_MIX_BYTES],
+            mix_hash: [0; 32],
+            final_hash: [0; 32],
+        }
+    }
+
+    /// Computes the ProgPoW mix hash and final hash of a header hash and
+    /// nonce, as [`progpow`](crate::progpow::progpow::progpow) does, in this
+    /// working set.
+    ///
+    /// # Arguments
+    ///
+    /// * `header_hash` - The 32-byte header hash.
+    /// * `nonce` - The nonce.
+    /// * `block_number` - The block number, which selects the program.
+    /// * `dag` - The dataset, read an item at a time.
+    /// * `c_dag` - The cached DAG words, `dag.c_dag()`.
+    ///
+    /// # Returns
+    ///
+    /// The mix hash and final hash, borrowed from the working set until its
+    /// next hash.
+    ///
+    /// # Panics
+    ///
+    /// Panics as `progpow` does if `dag` is too small to load from.
+    pub fn hash(
+        &mut self,
+        header_hash: &[u8],
+        nonce: u64,
+        block_number: u64,
+        dag: &dyn DagBuffer,
+        c_dag: &[u32],
+    ) -> (&[u8; 32], &[u8; 32]) {
+        #[cfg(feature = "metrics")]
+        let started = std::time::Instant::now();
+        let seed = progpow_seed(header_hash, nonce);
+        let period = block_number / PROGPOW_PERIOD_LENGTH;
+        let items = (dag.size() / PROGPOW_MIX_BYTES as u64) as u32;
+
+        self.mix = fill_mix_lanes(seed);
+        for l in 0..PROGPOW_CNT_DAG as u32 {
+            let base = dag_load_base(l, &self.mix, items);
+            for (i, item) in self.dag_item.chunks_exact_mut(64).enumerate() {
+                let index = base / HASH_WORDS as u32 + i as u32;
+                dag.read_item(index, item.try_into().unwrap());
+            }
+            progpow_loop_with_item(period, l, &mut self.mix, &self.dag_item, c_dag);
+        }
+        reduce_mix(&self.mix, &mut self.lane_results);
+        let result = reduce_lane_hashes(&self.lane_results);
+
+        keccak_f800_long_into(header_hash, seed, &result, &mut self.final_hash);
+        for (bytes, word) in self.mix_hash.chunks_exact_mut(4).zip(result) {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        #[cfg(feature = "metrics")]
+        crate::metrics::global().hashes.observe(started.elapsed());
+        (&self.mix_hash, &self.final_hash)
+    }
+}
+
+impl Default for Scratch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A pool of [`Scratch`]es shared by worker threads.
+///
+/// [`get`](Self::get) hands out a free scratch, or a new one when all are in
+/// use, and the guard returns it on drop; up to `capacity` free scratches
+/// are kept, so a steady set of workers stops allocating once each has
+/// hashed once.
+pub struct ScratchPool {
+    free: Mutex<Vec<Scratch>>,
+    capacity: usize,
+}
+
+impl ScratchPool {
+    /// Creates an empty pool keeping up to `capacity` free scratches,
+    /// usually the number of workers.
+    pub fn new(capacity: usize) -> Self {
+        ScratchPool {
+            free: Mutex::new(Vec::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    /// Takes a scratch from the pool, or creates one if none is free.
+    pub fn get(&self) -> PooledScratch<'_> {
+        let scratch = self.free.lock().unwrap().pop().unwrap_or_default();
+        PooledScratch {
+            pool: self,
+            scratch: Some(scratch),
+        }
+    }
+
+    /// Returns the number of free scratches held.
+    pub fn available(&self) -> usize {
+        self.free.lock().unwrap().len()
+    }
+}
+
+/// A [`Scratch`] taken from a [`ScratchPool`], returned to it on drop.
+pub struct PooledScratch<'a> {
+    pool: &'a ScratchPool,
+    scratch: Option<Scratch>,
+}
+
+impl Deref for PooledScratch<'_> {
+    type Target = Scratch;
+
+    fn deref(&self) -> &Scratch {
+        self.scratch.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledScratch<'_> {
+    fn deref_mut(&mut self) -> &mut Scratch {
+        self.scratch.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledScratch<'_> {
+    fn drop(&mut self) {
+        let mut free = self.pool.free.lock().unwrap();
+        if free.len() < self.pool.capacity {
+            free.extend(self.scratch.take());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ethash::buffer::LightDag;
+    use crate::progpow::progpow::progpow;
+    use crate::testutil::header_hash;
+
+    #[test]
+    fn test_scratch_hashes_like_progpow() {
+        let cache: Vec<u32> = (0..64 * 16u32)
+            .map(|i| i.wrapping_mul(0x9e3779b9))
+            .collect();
+        let dag = LightDag::new(cache, 1024 * 64);
+        let c_dag = dag.c_dag();
+        let lookup = |word| dag.lookup(word);
+        let pool = ScratchPool::new(2);
+
+        std::thread::scope(|scope| {
+            for t in 0..4u64 {
+                let (pool, dag, c_dag, lookup) = (&pool, &dag, &c_dag, &lookup);
+                scope.spawn(move || {
+                    for nonce in t * 5..t * 5 + 5 {
+                        let header = header_hash(nonce);
+                        let (mix_hash, final_hash) =
+                            progpow(&header, nonce, dag.size(), 30_000, c_dag, lookup);
+                        let mut scratch = pool.get();
+                        let (scratch_mix, scratch_final) =
+                            scratch.hash(&header, nonce, 30_000, dag, c_dag);
+                        assert_eq!(scratch_mix[..], mix_hash[..]);
+                        assert_eq!(scratch_final[..], final_hash[..]);
+                    }
+                });
+            }
+        });
+        assert_eq!(pool.available(), 2);
+
+        // Returned scratches are kept up to the capacity, and no further.
+        let held = (pool.get(), pool.get(), pool.get());
+        assert_eq!(pool.available(), 0);
+        drop(held);
+        assert_eq!(pool.available(), 2);
+    }
+}
@@ -0,0 +1,209 @@
+use std::ops::Range;
+
+use crate::progpow::progpow::{progpow_from_seed, progpow_seed};
+use crate::target::hash_meets_target;
+
+/// Strategy used by [`search`] to decide which nonces get a full evaluation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SearchStrategy {
+    /// Run the full ProgPoW hash for every nonce in the range.
+    #[default]
+    Full,
+    /// Compute the 64-bit seed first and only run the mix loop when the seed
+    /// is less than or equal to `threshold`.
+    ///
+    /// This mirrors the two-stage structure of GPU miners. Nonces rejected by
+    /// the pre-check are never evaluated, so a pre-checked search may skip
+    /// solutions that a full search would find; `u64::MAX` disables the filter.
+    SeedPreCheck { threshold: u64 },
+}
+
+/// A nonce whose final hash meets the search boundary.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Solution {
+    /// The winning nonce.
+    pub nonce: u64,
+    /// The 32-byte mix hash for `nonce`.
+    pub mix_hash: Vec<u8>,
+    /// The 32-byte final hash for `nonce`.
+    pub final_hash: Vec<u8>,
+}
+
+/// Checks whether a final hash meets a boundary.
+///
+/// Both values are interpreted as 256-bit big-endian integers and compared
+/// in constant time with [`hash_meets_target`].
+///
+/// # Arguments
+///
+/// * `final_hash` - The 32-byte final hash.
+/// * `boundary` - The 32-byte target the hash must not exceed.
+///
+/// # Returns
+///
+/// `true` if `final_hash <= boundary`; a hash of any other length never
+/// meets it.
+pub fn meets_boundary(final_hash: &[u8], boundary: &[u8; 32]) -> bool {
+    <&[u8; 32]>::try_from(final_hash).is_ok_and(|hash| hash_meets_target(hash, boundary))
+}
+
+/// Searches a nonce range for the first nonce meeting a boundary.
+///
+/// # Arguments
+///
+/// * `hash` - The header hash (32 bytes expected).
+/// * `size` - The size of the dataset.
+/// * `block_number` - The block number associated with this computation.
+/// * `c_dag` - The cached first words of the DAG.
+/// * `lookup` - A function to retrieve memory segments based on an index.
+/// * `nonces` - The range of nonces to try, in order.
+/// * `boundary` - The 32-byte big-endian target.
+/// * `strategy` - Which nonces receive a full evaluation.
+///
+/// # Returns
+///
+/// The first [`Solution`] found, or `None` if the range is exhausted.
+#[allow(clippy::too_many_arguments)]
+pub fn search(
+    hash: &[u8],
+    size: u64,
+    block_number: u64,
+    c_dag: &[u32],
+    lookup: &dyn Fn(u32) -> Vec<u8>,
+    nonces: Range<u64>,
+    boundary: &[u8; 32],
+    strategy: SearchStrategy,
+) -> Option<Solution> {
+    for nonce in nonces {
+        let seed = progpow_seed(hash, nonce);
+
+        // Skip the expensive mix when the quick seed fails the coarse threshold.
+        if let SearchStrategy::SeedPreCheck { threshold } = strategy {
+            if seed > threshold {
+                continue;
+            }
+        }
+
+        let (mix_hash, final_hash) =
+            progpow_from_seed(hash, seed, size, block_number, c_dag, lookup);
+        if meets_boundary(&final_hash, boundary) {
+            return Some(Solution {
+                nonce,
+                mix_hash,
+                final_hash,
+            });
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progpow::progpow::progpow;
+    use crate::testutil::{header_hash, mock_c_dag, mock_lookup};
+
+    const SIZE: u64 = 1024;
+    const BLOCK_NUMBER: u64 = 100;
+
+    #[test]
+    fn test_solutions_match_progpow() {
+        let (hash, c_dag, lookup) = (header_hash(1), mock_c_dag(1), mock_lookup(1));
+        let mut boundary = [0xffu8; 32];
+        boundary[0] = 0x0f;
+
+        let found = search(
+            &hash,
+            SIZE,
+            BLOCK_NUMBER,
+            &c_dag,
+            &lookup,
+            0..256,
+            &boundary,
+            SearchStrategy::Full,
+        )
+        .expect("a solution within 256 nonces");
+
+        let (mix_hash, final_hash) =
+            progpow(&hash, found.nonce, SIZE, BLOCK_NUMBER, &c_dag, &lookup);
+        assert_eq!(found.mix_hash, mix_hash);
+        assert_eq!(found.final_hash, final_hash);
+        assert!(meets_boundary(&final_hash, &boundary));
+        assert!(!meets_boundary(&final_hash[..31], &[0xff; 32]));
+
+        // No earlier nonce may meet the boundary.
+        for nonce in 0..found.nonce {
+            let (_, final_hash) = progpow(&hash, nonce, SIZE, BLOCK_NUMBER, &c_dag, &lookup);
+            assert!(!meets_boundary(&final_hash, &boundary));
+        }
+    }
+
+    #[test]
+    fn test_open_pre_check_matches_full_search() {
+        let (hash, c_dag, lookup) = (header_hash(1), mock_c_dag(1), mock_lookup(1));
+        let mut boundary = [0xffu8; 32];
+        boundary[0] = 0x1f;
+
+        let run = |strategy| {
+            search(
+                &hash,
+                SIZE,
+                BLOCK_NUMBER,
+                &c_dag,
+                &lookup,
+                0..128,
+                &boundary,
+                strategy,
+            )
+        };
+        assert_eq!(
+            run(SearchStrategy::Full),
+            run(SearchStrategy::SeedPreCheck {
+                threshold: u64::MAX
+            })
+        );
+    }
+
+    #[test]
+    fn test_pre_check_only_evaluates_passing_seeds() {
+        let (hash, c_dag, lookup) = (header_hash(1), mock_c_dag(1), mock_lookup(1));
+        let boundary = [0xffu8; 32];
+        let threshold = u64::MAX / 8;
+
+        let found = search(
+            &hash,
+            SIZE,
+            BLOCK_NUMBER,
+            &c_dag,
+            &lookup,
+            0..256,
+            &boundary,
+            SearchStrategy::SeedPreCheck { threshold },
+        )
+        .expect("a seed below the threshold within 256 nonces");
+
+        // An open boundary accepts the first nonce whose seed passes the pre-check.
+        let expected = (0..256)
+            .find(|&nonce| progpow_seed(&hash, nonce) <= threshold)
+            .unwrap();
+        assert_eq!(found.nonce, expected);
+        assert!(progpow_seed(&hash, found.nonce) <= threshold);
+    }
+
+    #[test]
+    fn test_empty_range_finds_nothing() {
+        let (hash, c_dag, lookup) = (header_hash(1), mock_c_dag(1), mock_lookup(1));
+        let found = search(
+            &hash,
+            SIZE,
+            BLOCK_NUMBER,
+            &c_dag,
+            &lookup,
+            5..5,
+            &[0xffu8; 32],
+            SearchStrategy::Full,
+        );
+        assert_eq!(found, None);
+    }
+}
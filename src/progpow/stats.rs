@@ -0,0 +1,260 @@
+//! Histograms of the choices random programs make, for evaluating
+//! parameters.
+//!
+//! Each period's [`Program`] draws its math operations, merge operations and
+//! registers from KISS99. [`ProgramStats`] counts those draws over a range of
+//! periods: how often each of the 11 math and 4 merge operations is chosen,
+//! separately for merges of cache loads, math results and DAG words, which
+//! registers are read and written, and by how much rotating merges rotate.
+//! A derivative that changes `PROGPOW_REGS`, `PROGPOW_CNT_MATH` or the
+//! operation tables can check that the programs stay balanced.
+
+use std::fmt::Write as _;
+use std::ops::Range;
+
+use crate::basic_algorithm::PROGPOW_REGS;
+use crate::progpow::program::{Program, ProgramOp};
+
+/// The math operations, in the order `math_sel % 11` selects them.
+pub const MATH_OPS: [&str; 11] = [
+    "add", "mul", "mul_hi", "min", "rotl", "rotr", "and", "or", "xor", "clz", "popcount",
+];
+
+/// The merge operations, in the order `merge_sel % 4` selects them.
+pub const MERGE_OPS: [&str; 4] = ["mul_add", "xor_mul", "rotl_xor", "rotr_xor"];
+
+/// Counts of the choices made by the programs of some periods.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProgramStats {
+    /// The number of programs counted.
+    pub programs: u64,
+    /// Math operations, indexed like [`MATH_OPS`].
+    pub math_ops: [u64; 11],
+    /// Merges of cache loads, indexed like [`MERGE_OPS`].
+    pub cache_merges: [u64; 4],
+    /// Merges of math results, indexed like [`MERGE_OPS`].
+    pub math_merges: [u64; 4],
+    /// Merges of DAG words, indexed like [`MERGE_OPS`].
+    pub dag_merges: [u64; 4],
+    /// Registers read as a cache load's address.
+    pub cache_sources: [u64; PROGPOW_REGS],
+    /// Registers read as either operand of a math operation.
+    pub math_sources: [u64; PROGPOW_REGS],
+    /// Registers written by any merge.
+    pub destinations: [u64; PROGPOW_REGS],
+    /// Rotation amounts of `rotl_xor` and `rotr_xor` merges, indexed by
+    /// the amount, 1 to 31.
+    pub rotations: [u64; 32],
+}
+
+impl Default for ProgramStats {
+    fn default() -> Self {
+        ProgramStats {
+            programs: 0,
+            math_ops: [0; 11],
+            cache_merges: [0; 4],
+            math_merges: [0; 4],
+            dag_merges: [0; 4],
+            cache_sources: [0; PROGPOW_REGS],
+            math_sources: [0; PROGPOW_REGS],
+            destinations: [0; PROGPOW_REGS],
+            rotations: [0; 32],
+        }
+    }
+}
+
+impl ProgramStats {
+    /// Counts the programs of `periods`.
+    pub fn for_periods(periods: Range<u64>) -> Self {
+        let mut stats = Self::default();
+        for period in periods {
+            stats.record(&Program::generate(period));
+        }
+        stats
+    }
+
+    /// Adds the choices of `program` to the counts.
+    pub fn record(&mut self, program: &Program) {
+        self.programs += 1;
+        for op in &program.ops {
+            let (merges, dst, merge_sel) = match *op {
+                ProgramOp::Cache {
+                    src,
+                    dst,
+                    merge_sel,
+                } => {
+                    self.cache_sources[src as usize] += 1;
+                    (&mut self.cache_merges, dst, merge_sel)
+                }
+                ProgramOp::Math {
+                    src1,
+                    src2,
+                    math_sel,
+                    dst,
+                    merge_sel,
+                } => {
+                    self.math_ops[(math_sel % 11) as usize] += 1;
+                    self.math_sources[src1 as usize] += 1;
+                    self.math_sources[src2 as usize] += 1;
+                    (&mut self.math_merges, dst, merge_sel)
+                }
+                ProgramOp::DagMerge { dst, merge_sel, .. } => {
+                    (&mut self.dag_merges, dst, merge_sel)
+                }
+            };
+            merges[(merge_sel % 4) as usize] += 1;
+            self.destinations[dst as usize] += 1;
+            // The amount `merge` rotates by.
+            if merge_sel % 4 >= 2 {
+                self.rotations[((merge_sel >> 16) % 31 + 1) as usize] += 1;
+            }
+        }
+    }
+
+    /// Adds the counts of `other`, for statistics gathered in parts.
+    pub fn add(&mut self, other: &ProgramStats) {
+        fn add<const N: usize>(into: &mut [u64; N], from: &[u64; N]) {
+            into.iter_mut()
+                .zip(from)
+                .for_each(|(into, from)| *into += from);
+        }
+        self.programs += other.programs;
+        add(&mut self.math_ops, &other.math_ops);
+        add(&mut self.cache_merges, &other.cache_merges);
+        add(&mut self.math_merges, &other.math_merges);
+        add(&mut self.dag_merges, &other.dag_merges);
+        add(&mut self.cache_sources, &other.cache_sources);
+        add(&mut self.math_sources, &other.math_sources);
+        add(&mut self.destinations, &other.destinations);
+        add(&mut self.rotations, &other.rotations);
+    }
+
+    /// Returns the counts as text histograms, each row with its share of
+    /// the histogram's total.
+    pub fn render(&self) -> String {
+        let mut out = format!("{} programs\n", self.programs);
+        let named = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        let numbered = |range: Range<usize>| range.map(|n| n.to_string()).collect();
+        histogram(
+            &mut out,
+            "math operations",
+            named(&MATH_OPS),
+            &self.math_ops,
+        );
+        histogram(
+            &mut out,
+            "cache merges",
+            named(&MERGE_OPS),
+            &self.cache_merges,
+        );
+        histogram(
+            &mut out,
+            "math merges",
+            named(&MERGE_OPS),
+            &self.math_merges,
+        );
+        histogram(&mut out, "DAG merges", named(&MERGE_OPS), &self.dag_merges);
+        let registers = || numbered(0..PROGPOW_REGS);
+        histogram(&mut out, "cache sources", registers(), &self.cache_sources);
+        histogram(&mut out, "math sources", registers(), &self.math_sources);
+        histogram(&mut out, "destinations", registers(), &self.destinations);
+        histogram(&mut out, "rotations", numbered(1..32), &self.rotations[1..]);
+        out
+    }
+
+    /// Returns the counts as a JSON object with one field per histogram:
+    /// operation counts keyed by name, register counts as arrays, and
+    /// rotations as an array from an amount of 1.
+    pub fn to_json(&self) -> String {
+        let named = |names: &[&str], counts: &[u64]| {
+            let fields: Vec<String> = names
+                .iter()
+                .zip(counts)
+                .map(|(name, count)| format!("\"{name}\":{count}"))
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        };
+        let array = |counts: &[u64]| {
+            let counts: Vec<String> = counts.iter().map(u64::to_string).collect();
+            format!("[{}]", counts.join(","))
+        };
+        format!(
+            "{{\"programs\":{},\"math_ops\":{},\"cache_merges\":{},\"math_merges\":{},\
+             \"dag_merges\":{},\"cache_sources\":{},\"math_sources\":{},\"destinations\":{},\
+             \"rotations\":{}}}",
+            self.programs,
+            named(&MATH_OPS, &self.math_ops),
+            named(&MERGE_OPS, &self.cache_merges),
+            named(&MERGE_OPS, &self.math_merges),
+            named(&MERGE_OPS, &self.dag_merges),
+            array(&self.cache_sources),
+            array(&self.math_sources),
+            array(&self.destinations),
+            array(&self.rotations[1..]),
+        )
+    }
+}
+
+/// Writes one histogram with a bar per row.
+fn histogram(out: &mut String, title: &str, labels: Vec<String>, counts: &[u64]) {
+    let total: u64 = counts.iter().sum();
+    let max = counts.iter().copied().max().unwrap_or(0).max(1);
+    let _ = writeln!(out, "\n{title} ({total})");
+    for (label, &count) in labels.iter().zip(counts) {
+        let share = if total == 0 {
+            0.0
+        } else {
+            100.0 * count as f64 / total as f64
+        };
+        let bar = "#".repeat((40 * count / max) as usize);
+        let _ = writeln!(out, "  {label:>8} {count:>10} {share:>6.2}% {bar}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basic_algorithm::{PROGPOW_CNT_CACHE, PROGPOW_CNT_MATH, PROGPOW_DAG_LOADS};
+
+    #[test]
+    fn test_stats_count_every_choice() {
+        let stats = ProgramStats::for_periods(0..50);
+        assert_eq!(stats.programs, 50);
+        assert_eq!(
+            stats.math_ops.iter().sum::<u64>(),
+            50 * PROGPOW_CNT_MATH as u64
+        );
+        assert_eq!(
+            stats.math_merges.iter().sum::<u64>(),
+            50 * PROGPOW_CNT_MATH as u64
+        );
+        assert_eq!(
+            stats.cache_merges.iter().sum::<u64>(),
+            50 * PROGPOW_CNT_CACHE as u64
+        );
+        assert_eq!(
+            stats.dag_merges.iter().sum::<u64>(),
+            50 * PROGPOW_DAG_LOADS as u64
+        );
+        assert_eq!(
+            stats.math_sources.iter().sum::<u64>(),
+            2 * 50 * PROGPOW_CNT_MATH as u64
+        );
+        assert_eq!(
+            stats.destinations.iter().sum::<u64>(),
+            50 * (PROGPOW_CNT_CACHE + PROGPOW_CNT_MATH + PROGPOW_DAG_LOADS) as u64
+        );
+        assert_eq!(stats.rotations[0], 0);
+        // Every operation comes up over 50 programs of 18 math steps.
+        assert!(stats.math_ops.iter().all(|&count| count > 0));
+
+        let mut parts = ProgramStats::for_periods(0..20);
+        parts.add(&ProgramStats::for_periods(20..50));
+        assert_eq!(parts, stats);
+
+        assert!(stats.render().contains("\nmath operations (900)\n"));
+        let json = stats.to_json();
+        assert!(json.starts_with("{\"programs\":50,\"math_ops\":{\"add\":"));
+        assert_eq!(json.matches('[').count(), 4);
+    }
+}
@@ -0,0 +1,378 @@
+//! Verification cost of ProgPoW under other loop parameters.
+//!
+//! The shape of the mix loop (lanes, registers, DAG loads and the cache,
+//! math and DAG access counts) is fixed at compile time by the `PROGPOW_*`
+//! constants; a [`Chain`](crate::chain::Chain) changes the period, epochs,
+//! sizes and Keccak passes but not these. A derivative proposing other
+//! values needs to know what they cost a verifier, so this module runs the
+//! loop with its shape as runtime [`LoopParams`]: [`hash`] is ProgPoW for any
+//! shape, equal to `progpow()` under [`LoopParams::current`], and [`sweep`]
+//! times light verification for each shape of a set.
+//!
+//! A light verifier computes every DAG item it reads from the light cache,
+//! so the DAG items a hash reads weigh most in its cost; each [`Cost`]
+//! counts them alongside the cache loads and math operations.
+//!
+//! Each lane's register sequences restart with the lane, as in
+//! go-ethereum's kernel and [`generic`](crate::progpow::generic), which
+//! runs other lane and register counts at compile time and much faster.
+
+use std::time::{Duration, Instant};
+
+use crate::basic_algorithm::{
+    fnv1a, higher32, kiss99, lower32, merge, progpow_math, Kiss99State, PROGPOW_CACHE_WORDS,
+    PROGPOW_CNT_CACHE, PROGPOW_CNT_DAG, PROGPOW_CNT_MATH, PROGPOW_DAG_LOADS, PROGPOW_LANES,
+    PROGPOW_MIX_BYTES, PROGPOW_REGS,
+};
+use crate::ethash::buffer::DagBuffer;
+use crate::keccak::f800long::keccak_f800_long;
+use crate::progpow::progpow::progpow_seed;
+use crate::progpow::trace::digest;
+
+/// Words in one 64-byte DAG item.
+const ITEM_WORDS: usize = 16;
+
+/// The shape of the mix loop.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LoopParams {
+    /// Parallel lanes per hash.
+    pub lanes: usize,
+    /// Registers per lane.
+    pub regs: usize,
+    /// DAG words each lane merges per loop.
+    pub dag_loads: usize,
+    /// Cache loads per lane and loop.
+    pub cnt_cache: usize,
+    /// Math operations per lane and loop.
+    pub cnt_math: usize,
+    /// Loops, each reading one DAG chunk.
+    pub cnt_dag: usize,
+}
+
+impl LoopParams {
+    /// Returns the shape this crate was built with.
+    pub fn current() -> Self {
+        LoopParams {
+            lanes: PROGPOW_LANES,
+            regs: PROGPOW_REGS,
+            dag_loads: PROGPOW_DAG_LOADS,
+            cnt_cache: PROGPOW_CNT_CACHE,
+            cnt_math: PROGPOW_CNT_MATH,
+            cnt_dag: PROGPOW_CNT_DAG,
+        }
+    }
+
+    /// Checks that the loop can run with this shape.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())`, or why the shape cannot run.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.lanes == 0 || self.dag_loads == 0 || self.cnt_dag == 0 {
+            return Err("lanes, DAG loads and DAG accesses must be nonzero");
+        }
+        if self.regs < 2 {
+            return Err("math operations need at least 2 registers");
+        }
+        if !self.dag_words().is_multiple_of(ITEM_WORDS) {
+            return Err("lanes times DAG loads must be a multiple of 16, whole DAG items");
+        }
+        Ok(())
+    }
+
+    /// Returns the DAG words all lanes read in one loop.
+    pub fn dag_words(&self) -> usize {
+        self.lanes * self.dag_loads
+    }
+
+    /// Returns the 64-byte DAG items one hash reads.
+    pub fn dag_items_per_hash(&self) -> usize {
+        self.cnt_dag * self.dag_words() / ITEM_WORDS
+    }
+}
+
+/// Computes the ProgPoW hash of a header hash and nonce with the loop shaped
+/// by `params`.
+///
+/// # Arguments
+///
+/// * `params` - The shape of the mix loop.
+/// * `header_hash` - The 32-byte header hash.
+/// * `nonce` - The 64-bit nonce.
+/// * `period` - The program's period.
+/// * `size` - The size of the dataset in bytes.
+/// * `c_dag` - The cached DAG words.
+/// * `lookup` - A function to retrieve DAG items based on a word index.
+///
+/// # Returns
+///
+/// The `(mix_hash, final_hash)` pair.
+///
+/// # Panics
+///
+/// Panics if `params` fails [`LoopParams::validate`], or if `size` holds no
+/// whole chunk of a loop's DAG words.
+pub fn hash(
+    params: &LoopParams,
+    header_hash: &[u8; 32],
+    nonce: u64,
+    period: u64,
+    size: u64,
+    c_dag: &[u32],
+    lookup: &dyn Fn(u32) -> Vec<u8>,
+) -> ([u8; 32], [u8; 32]) {
+    if let Err(reason) = params.validate() {
+        panic!("invalid loop parameters: {reason}");
+    }
+    let seed = progpow_seed(header_hash, nonce);
+    let mut mix: Vec<Vec<u32>> = (0..params.lanes)
+        .map(|lane| fill_mix(seed, lane as u32, params.regs))
+        .collect();
+    for loop_index in 0..params.cnt_dag {
+        mix_loop(params, period, loop_index, &mut mix, lookup, c_dag, size);
+    }
+
+    let mut result = [0x811c9dc5u32; 8];
+    for (lane, lane_mix) in mix.iter().enumerate() {
+        fnv1a(&mut result[lane % 8], digest(lane_mix));
+    }
+    let mut mix_hash = [0u8; 32];
+    for (bytes, word) in mix_hash.chunks_exact_mut(4).zip(result) {
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+    let final_hash = keccak_f800_long(header_hash, seed, &result);
+    (mix_hash, final_hash.try_into().unwrap())
+}
+
+/// `fill_mix` for `regs` registers.
+fn fill_mix(seed: u64, lane_id: u32, regs: usize) -> Vec<u32> {
+    let mut fnv_hash = 0x811c9dc5;
+    let mut state = Kiss99State::new(
+        fnv1a(&mut fnv_hash, lower32(seed)),
+        fnv1a(&mut fnv_hash, higher32(seed)),
+        fnv1a(&mut fnv_hash, lane_id),
+        fnv1a(&mut fnv_hash, lane_id),
+    );
+    (0..regs).map(|_| kiss99(&mut state)).collect()
+}
+
+/// `progpow_init` for `regs` registers.
+fn program_init(period: u64, regs: usize) -> (Kiss99State, Vec<u32>, Vec<u32>) {
+    let mut fnv_hash = 0x811c9dc5;
+    let mut state = Kiss99State::new(
+        fnv1a(&mut fnv_hash, lower32(period)),
+        fnv1a(&mut fnv_hash, higher32(period)),
+        fnv1a(&mut fnv_hash, lower32(period)),
+        fnv1a(&mut fnv_hash, higher32(period)),
+    );
+    let mut dst_seq: Vec<u32> = (0..regs as u32).collect();
+    let mut src_seq = dst_seq.clone();
+    for i in (1..regs).rev() {
+        let j = kiss99(&mut state) % (i as u32 + 1);
+        dst_seq.swap(i, j as usize);
+        let j = kiss99(&mut state) % (i as u32 + 1);
+        src_seq.swap(i, j as usize);
+    }
+    (state, dst_seq, src_seq)
+}
+
+/// `progpow_loop` for the shape `params`.
+fn mix_loop(
+    params: &LoopParams,
+    period: u64,
+    loop_index: usize,
+    mix: &mut [Vec<u32>],
+    lookup: &dyn Fn(u32) -> Vec<u8>,
+    c_dag: &[u32],
+    size: u64,
+) {
+    let LoopParams {
+        lanes,
+        regs,
+        dag_loads,
+        cnt_cache,
+        cnt_math,
+        ..
+    } = *params;
+
+    // The loop's DAG words, a chunk chosen by the previous loop's mix.
+    let words = params.dag_words();
+    let chunks = (size / PROGPOW_MIX_BYTES as u64) * 64 / words as u64;
+    assert!(chunks > 0, "the dataset holds no chunk of {words} words");
+    let base = (mix[loop_index % lanes][0] as u64 % chunks * words as u64) as u32;
+    let dag: Vec<u32> = (0..words / ITEM_WORDS)
+        .flat_map(|item| lookup(base + (item * ITEM_WORDS) as u32))
+        .collect::<Vec<u8>>()
+        .chunks_exact(4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .collect();
+
+    for (lane, lane_mix) in mix.iter_mut().enumerate() {
+        let (mut state, dst_seq, src_seq) = program_init(period, regs);
+        let (mut src_counter, mut dst_counter) = (0, 0);
+        let mut next_dst = || {
+            dst_counter += 1;
+            dst_seq[(dst_counter - 1) % regs] as usize
+        };
+        for i in 0..cnt_cache.max(cnt_math) {
+            if i < cnt_cache {
+                let src = src_seq[src_counter % regs] as usize;
+                src_counter += 1;
+                let data = c_dag[lane_mix[src] as usize % PROGPOW_CACHE_WORDS];
+                let dst = next_dst();
+                merge(&mut lane_mix[dst], data, kiss99(&mut state));
+            }
+            if i < cnt_math {
+                let src_rnd = kiss99(&mut state) % (regs * (regs - 1)) as u32;
+                let src1 = (src_rnd % regs as u32) as usize;
+                let mut src2 = (src_rnd / regs as u32) as usize;
+                if src2 >= src1 {
+                    src2 += 1;
+                }
+                let data = progpow_math(lane_mix[src1], lane_mix[src2], kiss99(&mut state));
+                let dst = next_dst();
+                merge(&mut lane_mix[dst], data, kiss99(&mut state));
+            }
+        }
+        let index = ((lane ^ loop_index) % lanes) * dag_loads;
+        merge(&mut lane_mix[0], dag[index], kiss99(&mut state));
+        for word in 1..dag_loads {
+            let dst = next_dst();
+            merge(&mut lane_mix[dst], dag[index + word], kiss99(&mut state));
+        }
+    }
+}
+
+/// What light verification costs with one loop shape.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cost {
+    pub params: LoopParams,
+    /// The mean time of one hash.
+    pub time_per_hash: Duration,
+    /// The 64-byte DAG items one hash reads.
+    pub dag_items_per_hash: usize,
+    /// The cache loads of one hash, over all lanes.
+    pub cache_loads_per_hash: usize,
+    /// The math operations of one hash, over all lanes.
+    pub math_ops_per_hash: usize,
+}
+
+/// Times `hashes` hashes over `dag` with the loop shaped by `params`, each
+/// with another nonce and period.
+///
+/// # Panics
+///
+/// Panics where [`hash`] does.
+pub fn measure(params: &LoopParams, dag: &dyn DagBuffer, hashes: u32) -> Cost {
+    let c_dag = dag.c_dag();
+    let lookup = |index| dag.lookup(index);
+    let started = Instant::now();
+    for i in 0..hashes as u64 {
+        hash(params, &[7; 32], i, i, dag.size(), &c_dag, &lookup);
+    }
+    let per_lane = params.cnt_dag * params.lanes;
+    Cost {
+        params: *params,
+        time_per_hash: started.elapsed() / hashes.max(1),
+        dag_items_per_hash: params.dag_items_per_hash(),
+        cache_loads_per_hash: per_lane * params.cnt_cache,
+        math_ops_per_hash: per_lane * params.cnt_math,
+    }
+}
+
+/// Measures every shape in `configs` over `dag`, `hashes` hashes each.
+///
+/// # Returns
+///
+/// The [`Cost`] of each shape, in order, or the first shape that fails
+/// [`LoopParams::validate`] with the reason.
+pub fn sweep(
+    configs: &[LoopParams],
+    dag: &dyn DagBuffer,
+    hashes: u32,
+) -> Result<Vec<Cost>, (LoopParams, &'static str)> {
+    for params in configs {
+        params.validate().map_err(|reason| (*params, reason))?;
+    }
+    Ok(configs
+        .iter()
+        .map(|params| measure(params, dag, hashes))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progpow::progpow::progpow_from_seed_at_period;
+    use crate::testutil::tiny_cache;
+
+    #[test]
+    fn test_current_shape_hashes_like_progpow() {
+        let cache = tiny_cache(0);
+        let c_dag = cache.c_dag();
+        let lookup = |index| cache.lookup(index);
+        for (nonce, period) in [(1, 0), (2, 7), (3, 0xdead_beef)] {
+            let (mix_hash, final_hash) = hash(
+                &LoopParams::current(),
+                &[9; 32],
+                nonce,
+                period,
+                cache.size(),
+                &c_dag,
+                &lookup,
+            );
+            let expected = progpow_from_seed_at_period(
+                &[9; 32],
+                progpow_seed(&[9; 32], nonce),
+                cache.size(),
+                period,
+                &c_dag,
+                &lookup,
+            );
+            assert_eq!((mix_hash.to_vec(), final_hash.to_vec()), expected);
+        }
+
+        let wide = LoopParams {
+            lanes: 32,
+            regs: 64,
+            cnt_math: 36,
+            ..LoopParams::current()
+        };
+        let (mix_hash, _) = hash(&wide, &[9; 32], 1, 0, cache.size(), &c_dag, &lookup);
+        assert_ne!(
+            mix_hash,
+            hash(
+                &LoopParams::current(),
+                &[9; 32],
+                1,
+                0,
+                cache.size(),
+                &c_dag,
+                &lookup
+            )
+            .0
+        );
+
+        let cheap = LoopParams {
+            cnt_dag: 2,
+            ..LoopParams::current()
+        };
+        let costs = sweep(&[cheap, wide], &cache, 2).unwrap();
+        assert_eq!(costs[0].dag_items_per_hash, 8);
+        assert_eq!(costs[1].dag_items_per_hash, 64 * 8);
+        assert_eq!(costs[1].math_ops_per_hash, 64 * 32 * 36);
+
+        let odd = LoopParams {
+            lanes: 3,
+            ..LoopParams::current()
+        };
+        assert_eq!(
+            sweep(&[cheap, odd], &cache, 1),
+            Err((
+                odd,
+                "lanes times DAG loads must be a multiple of 16, whole DAG items"
+            ))
+        );
+        assert!(LoopParams { regs: 1, ..cheap }.validate().is_err());
+    }
+}
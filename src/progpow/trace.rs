@@ -0,0 +1,535 @@
+//! The intermediate state of one ProgPoW hash, for porting it.
+//!
+//! [`progpow_with_trace`] computes the same hash as
+//! [`progpow`](crate::progpow::progpow::progpow) but keeps every stage an
+//! implementation in another language is likely to get wrong: the seed, each
+//! lane's registers after `fill_mix` and after every loop, the lane results
+//! and the reduced mix. Comparing a port's state with a [`Trace`] stage by
+//! stage shows the first loop, lane and register where it diverges.
+//! [`Chain::trace`](crate::chain::Chain::trace) does the same for any chain.
+//!
+//! Tracing copies the whole mix after every loop, 128 KiB per hash, so it is
+//! only for debugging; verification never calls it.
+//!
+//! [`parse_log`] reads another implementation's trace from its debug output,
+//! and [`Trace::first_divergence`] reports the first stage, lane and register
+//! where it differs from this crate's. The log holds lines like those of
+//! `progpow hash --trace`, which go-ethereum prints with:
+//!
+//! ```go
+//! fmt.Printf("seed %016x\n", seed)
+//! fmt.Printf("fill_mix lane %2d: %08x\n", lane, mix[lane])
+//! fmt.Printf("loop %2d lane %2d: %08x\n", l, lane, mix[lane])
+//! fmt.Printf("lane_results: %08x\n", laneResults)
+//! fmt.Printf("result: %08x\n", result)
+//! ```
+//!
+//! A loop line holds a lane's registers, or only their FNV-1a [`digest`].
+//! Lines may carry a logger's prefix, other lines are skipped, and stages a
+//! log leaves out are not compared. With the `vectors` feature a [`Trace`]
+//! also reads and writes JSON, whose fields are the struct's with the seed
+//! and hashes as `0x`-prefixed hex.
+
+use crate::basic_algorithm::{
+    fill_mix, fnv1a, progpow_loop, PROGPOW_CNT_DAG, PROGPOW_LANES, PROGPOW_MIX_BYTES,
+    PROGPOW_PERIOD_LENGTH, PROGPOW_REGS,
+};
+use std::fmt;
+
+#[cfg(feature = "vectors")]
+use serde::{Deserialize, Serialize};
+
+use crate::keccak::f800long::keccak_f800_long;
+use crate::progpow::progpow::{progpow_seed, reduce_lane_hashes};
+
+/// The registers of every lane.
+pub type Mix = [[u32; PROGPOW_REGS]; PROGPOW_LANES];
+
+/// Every intermediate value of one ProgPoW hash, in computation order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "vectors", derive(Serialize, Deserialize))]
+pub struct Trace {
+    /// The 64-bit seed of the header hash and nonce.
+    #[cfg_attr(feature = "vectors", serde(with = "crate::progpow::vectors::hex_u64"))]
+    pub seed: u64,
+    /// Each lane's registers after `fill_mix`.
+    pub fill_mix: Mix,
+    /// Each lane's registers after each of the `PROGPOW_CNT_DAG` loops.
+    pub loops: Vec<Mix>,
+    /// Each lane's registers after the last loop reduced with FNV-1a.
+    pub lane_results: [u32; PROGPOW_LANES],
+    /// The lane results folded into eight words, the words of the mix hash.
+    pub result: [u32; 8],
+    #[cfg_attr(feature = "vectors", serde(with = "hex_32"))]
+    pub mix_hash: [u8; 32],
+    #[cfg_attr(feature = "vectors", serde(with = "hex_32"))]
+    pub final_hash: [u8; 32],
+}
+
+/// A stage of the hash, in computation order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    Seed,
+    FillMix,
+    /// The mix after the loop with this index.
+    Loop(usize),
+    LaneResults,
+    Result,
+    FinalHash,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::Seed => write!(f, "seed"),
+            Stage::FillMix => write!(f, "fill_mix"),
+            Stage::Loop(l) => write!(f, "loop {l}"),
+            Stage::LaneResults => write!(f, "lane_results"),
+            Stage::Result => write!(f, "result"),
+            Stage::FinalHash => write!(f, "final_hash"),
+        }
+    }
+}
+
+/// The words of one stage, or of one lane of it, as a trace records them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub stage: Stage,
+    /// The lane of a `fill_mix` or loop entry.
+    pub lane: Option<usize>,
+    /// The registers of a lane, a loop lane's single digest, the lane
+    /// results, the result or the little-endian words of the final hash.
+    /// The seed is two words, high first.
+    pub words: Vec<u32>,
+}
+
+/// The first value where two traces disagree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub stage: Stage,
+    /// The lane the value belongs to, if the stage has lanes.
+    pub lane: Option<usize>,
+    /// The register of a lane, or the word of the result or final hash;
+    /// `None` for a loop lane's digest.
+    pub word: Option<usize>,
+    /// The value the other trace holds.
+    pub expected: u64,
+    /// The value this crate computed.
+    pub actual: u64,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.stage)?;
+        if let Some(lane) = self.lane {
+            write!(f, " lane {lane}")?;
+        }
+        match (self.stage, self.word) {
+            (Stage::FillMix | Stage::Loop(_), Some(word)) => write!(f, " register {word}")?,
+            (_, Some(word)) => write!(f, " word {word}")?,
+            (Stage::Loop(_), None) => write!(f, " digest")?,
+            (_, None) => {}
+        }
+        let width = if self.stage == Stage::Seed { 18 } else { 10 };
+        write!(
+            f,
+            ": expected {:#0width$x}, computed {:#0width$x}",
+            self.expected, self.actual
+        )
+    }
+}
+
+impl Trace {
+    /// Returns every value of the trace as [`Entry`]s, loops with their
+    /// registers.
+    pub fn entries(&self) -> Vec<Entry> {
+        let entry = |stage, lane, words: &[u32]| Entry {
+            stage,
+            lane,
+            words: words.to_vec(),
+        };
+        let mut entries = vec![entry(
+            Stage::Seed,
+            None,
+            &[(self.seed >> 32) as u32, self.seed as u32],
+        )];
+        for (lane, lane_mix) in self.fill_mix.iter().enumerate() {
+            entries.push(entry(Stage::FillMix, Some(lane), lane_mix));
+        }
+        for (l, mix) in self.loops.iter().enumerate() {
+            for (lane, lane_mix) in mix.iter().enumerate() {
+                entries.push(entry(Stage::Loop(l), Some(lane), lane_mix));
+            }
+        }
+        entries.push(entry(Stage::LaneResults, None, &self.lane_results));
+        entries.push(entry(Stage::Result, None, &self.result));
+        entries.push(entry(Stage::FinalHash, None, &self.final_hash_words()));
+        entries
+    }
+
+    /// Compares `expected` with this trace in computation order.
+    ///
+    /// # Returns
+    ///
+    /// The first value that differs, or `None` if every entry agrees.
+    /// Entries naming a lane, loop or word past this build's parameters are
+    /// not compared.
+    pub fn first_divergence(&self, expected: &[Entry]) -> Option<Divergence> {
+        let mut entries: Vec<&Entry> = expected.iter().collect();
+        entries.sort_by_key(|entry| (entry.stage, entry.lane));
+        entries.into_iter().find_map(|entry| self.compare(entry))
+    }
+
+    /// Returns the first value of `entry` that differs from this trace.
+    fn compare(&self, entry: &Entry) -> Option<Divergence> {
+        let diverge = |lane, word, expected: u32, actual: u32| {
+            (expected != actual).then_some(Divergence {
+                stage: entry.stage,
+                lane,
+                word,
+                expected: expected as u64,
+                actual: actual as u64,
+            })
+        };
+        let registers =
+            |actual: &[u32]| {
+                entry.words.iter().zip(actual).enumerate().find_map(
+                    |(word, (&expected, &actual))| {
+                        diverge(entry.lane, Some(word), expected, actual)
+                    },
+                )
+            };
+        let lane = entry.lane.unwrap_or(usize::MAX);
+        match entry.stage {
+            Stage::Seed => {
+                let [high, low] = entry.words[..] else {
+                    return None;
+                };
+                let expected = (high as u64) << 32 | low as u64;
+                (expected != self.seed).then_some(Divergence {
+                    stage: Stage::Seed,
+                    lane: None,
+                    word: None,
+                    expected,
+                    actual: self.seed,
+                })
+            }
+            Stage::FillMix => registers(self.fill_mix.get(lane)?),
+            Stage::Loop(l) => {
+                let lane_mix = self.loops.get(l)?.get(lane)?;
+                match entry.words[..] {
+                    [expected] => diverge(entry.lane, None, expected, digest(lane_mix)),
+                    _ => registers(lane_mix),
+                }
+            }
+            Stage::LaneResults => entry
+                .words
+                .iter()
+                .zip(&self.lane_results)
+                .enumerate()
+                .find_map(|(lane, (&expected, &actual))| {
+                    diverge(Some(lane), None, expected, actual)
+                }),
+            Stage::Result => registers(&self.result),
+            Stage::FinalHash => registers(&self.final_hash_words()),
+        }
+    }
+
+    /// Returns the final hash as little-endian words.
+    fn final_hash_words(&self) -> [u32; 8] {
+        let mut words = [0u32; 8];
+        for (word, bytes) in words.iter_mut().zip(self.final_hash.chunks_exact(4)) {
+            *word = u32::from_le_bytes(bytes.try_into().unwrap());
+        }
+        words
+    }
+}
+
+/// FNV-1a over `words`, starting from the offset basis, the way a lane's
+/// registers are reduced to its result.
+pub fn digest(words: &[u32]) -> u32 {
+    words
+        .iter()
+        .fold(0x811c9dc5, |mut hash, &word| fnv1a(&mut hash, word))
+}
+
+/// Reads the trace lines of a debug log; see the [module](self) docs for
+/// their format.
+///
+/// # Returns
+///
+/// The entries in the order the log holds them, or an error naming the
+/// first trace line that does not parse.
+pub fn parse_log(text: &str) -> Result<Vec<Entry>, String> {
+    let mut entries = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        // Go prints slices in brackets, which are not part of the words.
+        let line = line.replace(['[', ']'], " ");
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let Some(start) = tokens.iter().position(|token| {
+            matches!(
+                *token,
+                "seed" | "fill_mix" | "loop" | "lane_results:" | "result:"
+            )
+        }) else {
+            continue;
+        };
+        let entry = parse_line(&tokens[start..])
+            .ok_or_else(|| format!("line {}: malformed trace line: {line}", number + 1))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Parses the tokens of one trace line, from its keyword on.
+fn parse_line(tokens: &[&str]) -> Option<Entry> {
+    let index = |token: &str, limit: usize| {
+        token
+            .trim_end_matches(':')
+            .parse::<usize>()
+            .ok()
+            .filter(|&index| index < limit)
+    };
+    let words = |tokens: &[&str], counts: &[usize]| {
+        let words = tokens
+            .iter()
+            .map(|token| u32::from_str_radix(token.trim_start_matches("0x"), 16).ok())
+            .collect::<Option<Vec<u32>>>()?;
+        counts.contains(&words.len()).then_some(words)
+    };
+    match tokens {
+        ["seed", seed] => {
+            let seed = u64::from_str_radix(seed.trim_start_matches("0x"), 16).ok()?;
+            Some(Entry {
+                stage: Stage::Seed,
+                lane: None,
+                words: vec![(seed >> 32) as u32, seed as u32],
+            })
+        }
+        ["fill_mix", "lane", lane, rest @ ..] if lane.ends_with(':') => Some(Entry {
+            stage: Stage::FillMix,
+            lane: Some(index(lane, PROGPOW_LANES)?),
+            words: words(rest, &[PROGPOW_REGS])?,
+        }),
+        ["loop", l, "lane", lane, rest @ ..] if lane.ends_with(':') => Some(Entry {
+            stage: Stage::Loop(index(l, PROGPOW_CNT_DAG)?),
+            lane: Some(index(lane, PROGPOW_LANES)?),
+            words: words(rest, &[1, PROGPOW_REGS])?,
+        }),
+        ["lane_results:", rest @ ..] => Some(Entry {
+            stage: Stage::LaneResults,
+            lane: None,
+            words: words(rest, &[PROGPOW_LANES])?,
+        }),
+        ["result:", rest @ ..] => Some(Entry {
+            stage: Stage::Result,
+            lane: None,
+            words: words(rest, &[8])?,
+        }),
+        _ => None,
+    }
+}
+
+/// Computes the ProgPoW hash of the inputs keeping its intermediate state.
+///
+/// # Arguments
+///
+/// The arguments of [`progpow`](crate::progpow::progpow::progpow).
+///
+/// # Returns
+///
+/// The [`Trace`] of the hash, whose `mix_hash` and `final_hash` are the pair
+/// `progpow` returns.
+///
+/// # Panics
+///
+/// Panics where `progpow` does.
+pub fn progpow_with_trace(
+    hash: &[u8],
+    nonce: u64,
+    size: u64,
+    block_number: u64,
+    c_dag: &[u32],
+    lookup: &dyn Fn(u32) -> Vec<u8>,
+) -> Trace {
+    let seed = progpow_seed(hash, nonce);
+    let period = block_number / PROGPOW_PERIOD_LENGTH;
+    trace_at_period(seed, size, period, c_dag, lookup, |result| {
+        keccak_f800_long(hash, seed, result).try_into().unwrap()
+    })
+}
+
+/// Traces the mix loop of the program for `period` from `seed`, with
+/// `final_hash` computing the final hash from the reduced mix, for variants
+/// that derive the seed, period and final hash differently.
+pub(crate) fn trace_at_period(
+    seed: u64,
+    size: u64,
+    period: u64,
+    c_dag: &[u32],
+    lookup: &dyn Fn(u32) -> Vec<u8>,
+    final_hash: impl FnOnce(&[u32; 8]) -> [u8; 32],
+) -> Trace {
+    let mut mix = [[0u32; PROGPOW_REGS]; PROGPOW_LANES];
+    for (lane, lane_mix) in mix.iter_mut().enumerate() {
+        *lane_mix = fill_mix(seed, lane as u32);
+    }
+    let fill_mix = mix;
+
+    let items = (size / PROGPOW_MIX_BYTES as u64) as u32;
+    let loops = (0..PROGPOW_CNT_DAG as u32)
+        .map(|l| {
+            progpow_loop(period, l, &mut mix, lookup, c_dag, items);
+            mix
+        })
+        .collect();
+
+    let lane_results = mix.map(|lane_mix| digest(&lane_mix));
+    let result = reduce_lane_hashes(&lane_results);
+    let mut mix_hash = [0u8; 32];
+    for (bytes, word) in mix_hash.chunks_exact_mut(4).zip(result) {
+        bytes.copy_from_slice(&word.to_le_bytes());
+    }
+    Trace {
+        seed,
+        fill_mix,
+        loops,
+        lane_results,
+        result,
+        mix_hash,
+        final_hash: final_hash(&result),
+    }
+}
+
+/// Serializes 32-byte hashes as `0x`-prefixed hex.
+#[cfg(feature = "vectors")]
+mod hex_32 {
+    use serde::de::Error;
+    use serde::{Deserializer, Serializer};
+
+    use crate::progpow::vectors::hex;
+
+    pub fn serialize<S: Serializer>(bytes: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error> {
+        hex::serialize(bytes, serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 32], D::Error> {
+        hex::deserialize(deserializer)?
+            .try_into()
+            .map_err(|_| D::Error::custom("expected 32 bytes"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::Chain;
+    use crate::ethash::buffer::DagBuffer;
+    use crate::progpow::progpow::{period_lane_hashes, progpow};
+    use crate::testutil::tiny_cache;
+
+    #[test]
+    fn test_trace_ends_in_the_hash() {
+        let cache = tiny_cache(0);
+        let c_dag = cache.c_dag();
+        let lookup = |index| cache.lookup(index);
+        let trace = progpow_with_trace(&[3; 32], 17, cache.size(), 0, &c_dag, &lookup);
+        let (mix_hash, final_hash) = progpow(&[3; 32], 17, cache.size(), 0, &c_dag, &lookup);
+        assert_eq!(trace.mix_hash.to_vec(), mix_hash);
+        assert_eq!(trace.final_hash.to_vec(), final_hash);
+        assert_eq!(trace.seed, progpow_seed(&[3; 32], 17));
+        assert_eq!(trace.fill_mix[5], fill_mix(trace.seed, 5));
+        assert_eq!(trace.loops.len(), PROGPOW_CNT_DAG);
+        assert_ne!(trace.loops[0], trace.fill_mix);
+        assert_eq!(
+            trace.lane_results,
+            period_lane_hashes(trace.seed, cache.size(), 0, &c_dag, &lookup)
+        );
+
+        for chain in [Chain::ethereum(), Chain::ravencoin()] {
+            let (mix_hash, final_hash) = chain.hash(&cache, &[3; 32], 9, 17);
+            let trace = chain.trace(&cache, &[3; 32], 9, 17);
+            assert_eq!(trace.mix_hash.to_vec(), mix_hash, "{}", chain.name);
+            assert_eq!(trace.final_hash.to_vec(), final_hash, "{}", chain.name);
+        }
+    }
+
+    /// Formats `words` the way Go's `%08x` prints a slice.
+    fn go_words(words: &[u32]) -> String {
+        let words: Vec<String> = words.iter().map(|word| format!("{word:08x}")).collect();
+        format!("[{}]", words.join(" "))
+    }
+
+    #[test]
+    fn test_first_divergence_in_a_geth_log() {
+        let cache = tiny_cache(0);
+        let trace = Chain::ethereum().trace(&cache, &[5; 32], 0, 1);
+        let mut log = format!(
+            "INFO [10-14|12:00:00.000] Starting\nseed {:016x}\n",
+            trace.seed
+        );
+        for (lane, lane_mix) in trace.fill_mix.iter().enumerate() {
+            log += &format!("fill_mix lane {lane:2}: {}\n", go_words(lane_mix));
+        }
+        for (l, mix) in trace.loops.iter().enumerate() {
+            for (lane, lane_mix) in mix.iter().enumerate() {
+                // A mix of full and digest-only loop lines.
+                if lane % 2 == 0 {
+                    log += &format!("DEBUG loop {l:2} lane {lane:2}: {}\n", go_words(lane_mix));
+                } else {
+                    log += &format!("DEBUG loop {l:2} lane {lane:2}: {:08x}\n", digest(lane_mix));
+                }
+            }
+        }
+        log += &format!("lane_results: {}\n", go_words(&trace.lane_results));
+        log += &format!("result: {}\n", go_words(&trace.result));
+
+        let entries = parse_log(&log).unwrap();
+        assert_eq!(entries.len(), 2 + PROGPOW_LANES * (1 + PROGPOW_CNT_DAG) + 1);
+        assert_eq!(trace.first_divergence(&entries), None);
+        assert_eq!(trace.first_divergence(&trace.entries()), None);
+
+        let mut broken = entries.clone();
+        let late = broken
+            .iter()
+            .position(|entry| entry.stage == Stage::Loop(40) && entry.lane == Some(2))
+            .unwrap();
+        broken[late].words[7] ^= 1;
+        let early = broken
+            .iter()
+            .position(|entry| entry.stage == Stage::Loop(12) && entry.lane == Some(3))
+            .unwrap();
+        broken[early].words[0] ^= 1;
+        let divergence = trace.first_divergence(&broken).unwrap();
+        assert_eq!(
+            (divergence.stage, divergence.lane, divergence.word),
+            (Stage::Loop(12), Some(3), None)
+        );
+        assert_eq!(divergence.expected ^ divergence.actual, 1);
+        assert!(divergence
+            .to_string()
+            .starts_with("loop 12 lane 3 digest: expected 0x"));
+
+        broken.remove(early);
+        let divergence = trace.first_divergence(&broken).unwrap();
+        assert_eq!(
+            divergence.to_string().split(':').next().unwrap(),
+            "loop 40 lane 2 register 7"
+        );
+
+        assert!(parse_log("loop 3 lane 99: 00000000").is_err());
+        assert!(parse_log("result: 1 2 3")
+            .unwrap_err()
+            .starts_with("line 1:"));
+    }
+
+    #[cfg(feature = "vectors")]
+    #[test]
+    fn test_trace_json_round_trips() {
+        let cache = tiny_cache(0);
+        let trace = Chain::ravencoin().trace(&cache, &[5; 32], 0, 1);
+        let json = serde_json::to_string(&trace).unwrap();
+        assert!(json.contains(&format!("\"seed\":\"{:#018x}\"", trace.seed)));
+        assert_eq!(serde_json::from_str::<Trace>(&json).unwrap(), trace);
+    }
+}
@@ -0,0 +1,336 @@
+//! Machine-readable test vectors for other ProgPoW implementations.
+//!
+//! A [`Corpus`] records this build's ProgPoW parameters, the dataset the
+//! vectors were computed over, and for each input every intermediate value
+//! an implementation is likely to get wrong: the seed, the lanes' initial
+//! mixes, a digest of the mix after each loop, the lane hashes, and the mix
+//! and final hashes. It serializes to JSON, which YAML readers accept too.
+//!
+//! Two datasets are supported. [`Dataset::Synthetic`] needs no ethash code
+//! at all: byte `b` of 64-byte item `k` is `(16 * k + b) mod 256`.
+//! [`Dataset::Ethash`] uses an epoch's real dataset, computed item by item
+//! from its light cache.
+//!
+//! Unsigned 64-bit values that JavaScript cannot represent (nonces and seeds)
+//! and byte strings are written as `0x`-prefixed hex.
+
+use serde::{Deserialize, Serialize};
+
+use crate::basic_algorithm::{
+    fill_mix, fnv1a, progpow_loop, PROGPOW_CACHE_BYTES, PROGPOW_CNT_CACHE, PROGPOW_CNT_DAG,
+    PROGPOW_CNT_MATH, PROGPOW_DAG_LOADS, PROGPOW_LANES, PROGPOW_MIX_BYTES, PROGPOW_PERIOD_LENGTH,
+    PROGPOW_REGS,
+};
+use crate::ethash::buffer::{DagBuffer, LightDag};
+use crate::ethash::cache::{
+    cache_size, dataset_size, make_cache, seed_hash, EPOCH_LENGTH, MAX_EPOCH,
+};
+use crate::keccak::f800long::keccak_f800_long;
+use crate::progpow::progpow::progpow_seed;
+use crate::testutil::{splitmix64, SyntheticDag};
+
+/// The ProgPoW parameters a corpus was computed with.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Params {
+    pub period_length: u64,
+    pub lanes: usize,
+    pub regs: usize,
+    pub dag_loads: usize,
+    pub cache_bytes: usize,
+    pub cnt_dag: usize,
+    pub cnt_cache: usize,
+    pub cnt_math: usize,
+}
+
+impl Params {
+    /// Returns the parameters this crate was built with.
+    pub fn current() -> Self {
+        Params {
+            period_length: PROGPOW_PERIOD_LENGTH,
+            lanes: PROGPOW_LANES,
+            regs: PROGPOW_REGS,
+            dag_loads: PROGPOW_DAG_LOADS,
+            cache_bytes: PROGPOW_CACHE_BYTES,
+            cnt_dag: PROGPOW_CNT_DAG,
+            cnt_cache: PROGPOW_CNT_CACHE,
+            cnt_math: PROGPOW_CNT_MATH,
+        }
+    }
+}
+
+/// The dataset a corpus was computed over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Dataset {
+    /// `size` bytes where byte `b` of item `k` is `(16 * k + b) mod 256`.
+    Synthetic { size: u64 },
+    /// The full dataset of an ethash epoch.
+    Ethash { epoch: u64 },
+}
+
+impl Dataset {
+    /// Builds the dataset, generating the light cache for an ethash epoch.
+    pub fn open(&self) -> Box<dyn DagBuffer> {
+        match *self {
+            Dataset::Synthetic { size } => Box::new(SyntheticDag::new(size)),
+            Dataset::Ethash { epoch } => Box::new(LightDag::new(
+                make_cache(cache_size(epoch), &seed_hash(epoch)),
+                dataset_size(epoch),
+            )),
+        }
+    }
+}
+
+/// One input and the values ProgPoW computes from it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestVector {
+    #[serde(with = "hex")]
+    pub header_hash: Vec<u8>,
+    #[serde(with = "hex_u64")]
+    pub nonce: u64,
+    pub block_number: u64,
+    /// The Keccak-f800 seed of the header hash and nonce.
+    #[serde(with = "hex_u64")]
+    pub seed: u64,
+    /// Each lane's registers after `fill_mix`.
+    pub fill_mix: Vec<Vec<u32>>,
+    /// After each loop, FNV-1a over every register, lane by lane, starting
+    /// from the offset basis `0x811c9dc5`.
+    pub loop_digests: Vec<u32>,
+    /// Each lane's registers reduced with FNV-1a.
+    pub lane_hashes: Vec<u32>,
+    #[serde(with = "hex")]
+    pub mix_hash: Vec<u8>,
+    #[serde(with = "hex")]
+    pub final_hash: Vec<u8>,
+}
+
+impl TestVector {
+    /// Computes the vector for an input over `dag`.
+    pub fn compute(
+        header_hash: [u8; 32],
+        nonce: u64,
+        block_number: u64,
+        dag: &dyn DagBuffer,
+    ) -> Self {
+        let c_dag = dag.c_dag();
+        let lookup = |index| dag.lookup(index);
+        let seed = progpow_seed(&header_hash, nonce);
+
+        let mut mix = [[0u32; PROGPOW_REGS]; PROGPOW_LANES];
+        for (lane, lane_mix) in mix.iter_mut().enumerate() {
+            *lane_mix = fill_mix(seed, lane as u32);
+        }
+        let filled = mix.iter().map(|lane| lane.to_vec()).collect();
+
+        let period = block_number / PROGPOW_PERIOD_LENGTH;
+        let items = (dag.size() / PROGPOW_MIX_BYTES as u64) as u32;
+        let loop_digests = (0..PROGPOW_CNT_DAG as u32)
+            .map(|l| {
+                progpow_loop(period, l, &mut mix, &lookup, &c_dag, items);
+                reduce(mix.iter().flatten())
+            })
+            .collect();
+
+        let lane_hashes: Vec<u32> = mix.iter().map(reduce).collect();
+        let mut result = [0x811c9dc5u32; 8];
+        for (lane, &hash) in lane_hashes.iter().enumerate() {
+            fnv1a(&mut result[lane % 8], hash);
+        }
+        TestVector {
+            header_hash: header_hash.to_vec(),
+            nonce,
+            block_number,
+            seed,
+            fill_mix: filled,
+            loop_digests,
+            lane_hashes,
+            mix_hash: result.iter().flat_map(|word| word.to_le_bytes()).collect(),
+            final_hash: keccak_f800_long(&header_hash, seed, &result),
+        }
+    }
+
+    /// Returns the name of the first field that differs from `expected`.
+    pub fn first_difference(&self, expected: &TestVector) -> Option<&'static str> {
+        [
+            ("seed", self.seed == expected.seed),
+            ("fill_mix", self.fill_mix == expected.fill_mix),
+            ("loop_digests", self.loop_digests == expected.loop_digests),
+            ("lane_hashes", self.lane_hashes == expected.lane_hashes),
+            ("mix_hash", self.mix_hash == expected.mix_hash),
+            ("final_hash", self.final_hash == expected.final_hash),
+        ]
+        .into_iter()
+        .find(|(_, equal)| !equal)
+        .map(|(name, _)| name)
+    }
+}
+
+/// FNV-1a over `words`, starting from the offset basis.
+fn reduce<'a>(words: impl IntoIterator<Item = &'a u32>) -> u32 {
+    words
+        .into_iter()
+        .fold(0x811c9dc5, |mut hash, &word| fnv1a(&mut hash, word))
+}
+
+/// A set of test vectors with the parameters and dataset they assume.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Corpus {
+    pub params: Params,
+    pub dataset: Dataset,
+    pub vectors: Vec<TestVector>,
+}
+
+/// A vector that this crate computes differently from the corpus.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    /// The position of the vector in the corpus.
+    pub index: usize,
+    /// The first field that differs, in computation order.
+    pub field: &'static str,
+}
+
+impl Corpus {
+    /// Generates `count` vectors with pseudo-random inputs derived from `seed`.
+    ///
+    /// Block numbers fall in the epochs below [`MAX_EPOCH`], and for an ethash
+    /// dataset in its epoch.
+    pub fn generate(dataset: Dataset, count: usize, seed: u64) -> Self {
+        let dag = dataset.open();
+        let mut state = seed;
+        let vectors = (0..count)
+            .map(|_| {
+                let mut header_hash = [0u8; 32];
+                for chunk in header_hash.chunks_exact_mut(8) {
+                    chunk.copy_from_slice(&splitmix64(&mut state).to_le_bytes());
+                }
+                let nonce = splitmix64(&mut state);
+                let block_number = match dataset {
+                    Dataset::Synthetic { .. } => {
+                        splitmix64(&mut state) % (MAX_EPOCH * EPOCH_LENGTH)
+                    }
+                    Dataset::Ethash { epoch } => {
+                        epoch * EPOCH_LENGTH + splitmix64(&mut state) % EPOCH_LENGTH
+                    }
+                };
+                TestVector::compute(header_hash, nonce, block_number, &*dag)
+            })
+            .collect();
+        Corpus {
+            params: Params::current(),
+            dataset,
+            vectors,
+        }
+    }
+
+    /// Recomputes every vector, returning those this crate disagrees with.
+    ///
+    /// # Returns
+    ///
+    /// The mismatches, or an error if the corpus was made with other
+    /// parameters or holds a malformed header hash.
+    pub fn check(&self) -> Result<Vec<Mismatch>, String> {
+        if self.params != Params::current() {
+            return Err(format!(
+                "corpus parameters {:?} differ from this build's {:?}",
+                self.params,
+                Params::current()
+            ));
+        }
+        let dag = self.dataset.open();
+        let mut mismatches = Vec::new();
+        for (index, expected) in self.vectors.iter().enumerate() {
+            let header_hash = expected
+                .header_hash
+                .as_slice()
+                .try_into()
+                .map_err(|_| format!("vector {index}: header_hash must be 32 bytes"))?;
+            let actual =
+                TestVector::compute(header_hash, expected.nonce, expected.block_number, &*dag);
+            if let Some(field) = actual.first_difference(expected) {
+                mismatches.push(Mismatch { index, field });
+            }
+        }
+        Ok(mismatches)
+    }
+}
+
+/// Serializes byte strings as `0x`-prefixed hex.
+pub(crate) mod hex {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&crate::hex::encode_prefixed(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.strip_prefix("0x")
+            .and_then(crate::hex::decode)
+            .ok_or_else(|| D::Error::custom("expected 0x-prefixed hex bytes"))
+    }
+}
+
+/// Serializes 64-bit values as `0x`-prefixed hex.
+pub(crate) mod hex_u64 {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{value:#018x}"))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        let digits = text
+            .strip_prefix("0x")
+            .ok_or_else(|| D::Error::custom("expected 0x-prefixed hex"))?;
+        u64::from_str_radix(digits, 16).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progpow::progpow::progpow;
+
+    #[test]
+    fn test_corpus_round_trips_and_detects_divergence() {
+        let dataset = Dataset::Synthetic { size: 1 << 20 };
+        let corpus = Corpus::generate(dataset, 2, 42);
+        let dag = dataset.open();
+        for vector in &corpus.vectors {
+            let (mix_hash, final_hash) = progpow(
+                &vector.header_hash,
+                vector.nonce,
+                dag.size(),
+                vector.block_number,
+                &dag.c_dag(),
+                &|index| dag.lookup(index),
+            );
+            assert_eq!(
+                (&vector.mix_hash, &vector.final_hash),
+                (&mix_hash, &final_hash)
+            );
+            assert_eq!(vector.loop_digests.len(), PROGPOW_CNT_DAG);
+        }
+
+        let json = serde_json::to_string(&corpus).unwrap();
+        assert!(json.contains(r#""dataset":{"kind":"synthetic","size":1048576}"#));
+        let mut parsed: Corpus = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, corpus);
+        assert_eq!(parsed.check(), Ok(vec![]));
+
+        parsed.vectors[1].loop_digests[10] ^= 1;
+        assert_eq!(
+            parsed.check(),
+            Ok(vec![Mismatch {
+                index: 1,
+                field: "loop_digests"
+            }])
+        );
+        parsed.params.cnt_math += 1;
+        assert!(parsed.check().is_err());
+    }
+}
@@ -0,0 +1,345 @@
+use std::fmt;
+
+use crate::hex;
+use crate::progpow::progpow::progpow;
+use crate::target::{boundary_from_difficulty, hash_meets_target, U256};
+
+/// A sealed header to check: the header hash, its nonce, and the claimed mix hash.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Seal {
+    /// The 32-byte header hash that was sealed.
+    pub header_hash: [u8; 32],
+    /// The block number, which selects the ProgPoW period.
+    pub block_number: u64,
+    /// The nonce found by the miner.
+    pub nonce: u64,
+    /// The 32-byte mix hash claimed by the miner.
+    pub mix_hash: [u8; 32],
+    /// The 32-byte big-endian target the final hash must not exceed.
+    pub boundary: [u8; 32],
+}
+
+/// The reason a [`Seal`] failed verification.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SealError {
+    /// The recomputed mix hash differs from the one in the seal.
+    MixMismatch {
+        /// The mix hash ProgPoW produces for the header and nonce.
+        computed: Vec<u8>,
+    },
+    /// The mix hash matches, but the final hash exceeds the boundary.
+    BoundaryNotMet {
+        /// The final hash ProgPoW produces for the header and nonce.
+        final_hash: Vec<u8>,
+    },
+}
+
+impl fmt::Display for SealError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SealError::MixMismatch { computed } => {
+                write!(f, "mix hash mismatch: computed {}", hex::encode(computed))
+            }
+            SealError::BoundaryNotMet { final_hash } => {
+                write!(
+                    f,
+                    "final hash {} exceeds the boundary",
+                    hex::encode(final_hash)
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for SealError {}
+
+/// Decides whether the final hash of a seal whose mix hash matches is
+/// accepted.
+///
+/// Most chains accept a final hash at most the seal's boundary, which is
+/// [`SealBoundary`]. Pools check shares against their own boundary with
+/// [`ShareBoundary`], and chains with other acceptance rules, such as a
+/// difficulty floor, implement this trait, or pass a closure, to reuse the
+/// rest of the seal machinery; see
+/// [`Verifier::with_target_rule`](crate::verifier::Verifier::with_target_rule).
+/// A rejected hash fails with [`SealError::BoundaryNotMet`].
+pub trait TargetRule: Send + Sync {
+    /// Returns `true` if `final_hash`, computed for `seal`, is accepted.
+    fn accepts(&self, seal: &Seal, final_hash: &[u8; 32]) -> bool;
+}
+
+impl<F: Fn(&Seal, &[u8; 32]) -> bool + Send + Sync> TargetRule for F {
+    fn accepts(&self, seal: &Seal, final_hash: &[u8; 32]) -> bool {
+        self(seal, final_hash)
+    }
+}
+
+/// Accepts a final hash at most the seal's own boundary, the rule of
+/// Ethereum and the default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SealBoundary;
+
+impl TargetRule for SealBoundary {
+    fn accepts(&self, seal: &Seal, final_hash: &[u8; 32]) -> bool {
+        hash_meets_target(final_hash, &seal.boundary)
+    }
+}
+
+/// Accepts a final hash at most a fixed boundary whatever the seal's, as a
+/// pool checks shares of a miner's share difficulty.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShareBoundary(pub [u8; 32]);
+
+impl ShareBoundary {
+    /// Creates the rule for shares of `difficulty`.
+    pub fn from_difficulty(difficulty: U256) -> Self {
+        ShareBoundary(boundary_from_difficulty(difficulty))
+    }
+}
+
+impl TargetRule for ShareBoundary {
+    fn accepts(&self, _: &Seal, final_hash: &[u8; 32]) -> bool {
+        hash_meets_target(final_hash, &self.0)
+    }
+}
+
+/// Accepts a final hash that meets the seal's boundary and the boundary of
+/// a minimum difficulty, for chains that refuse blocks below a floor
+/// whatever their header claims.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MinimumDifficulty {
+    floor: [u8; 32],
+}
+
+impl MinimumDifficulty {
+    /// Creates the rule for the difficulty floor `difficulty`.
+    pub fn new(difficulty: U256) -> Self {
+        MinimumDifficulty {
+            floor: boundary_from_difficulty(difficulty),
+        }
+    }
+}
+
+impl TargetRule for MinimumDifficulty {
+    fn accepts(&self, seal: &Seal, final_hash: &[u8; 32]) -> bool {
+        // Both comparisons run whatever the first finds, keeping the time
+        // independent of the hash.
+        hash_meets_target(final_hash, &seal.boundary) & hash_meets_target(final_hash, &self.floor)
+    }
+}
+
+/// Checks a computed `(mix_hash, final_hash)` pair against a seal.
+pub(crate) fn check_seal(seal: &Seal, mix_hash: &[u8], final_hash: &[u8]) -> Result<(), SealError> {
+    check_seal_with(seal, mix_hash, final_hash, &SealBoundary)
+}
+
+/// Checks a computed `(mix_hash, final_hash)` pair against a seal, deciding
+/// the final hash by `rule`.
+pub(crate) fn check_seal_with(
+    seal: &Seal,
+    mix_hash: &[u8],
+    final_hash: &[u8],
+    rule: &dyn TargetRule,
+) -> Result<(), SealError> {
+    let result = compare_seal(seal, mix_hash, final_hash, rule);
+    #[cfg(feature = "metrics")]
+    crate::metrics::global().record_seal(&result);
+    result
+}
+
+/// Does the comparisons of [`check_seal_with`].
+fn compare_seal(
+    seal: &Seal,
+    mix_hash: &[u8],
+    final_hash: &[u8],
+    rule: &dyn TargetRule,
+) -> Result<(), SealError> {
+    if mix_hash != seal.mix_hash {
+        return Err(SealError::MixMismatch {
+            computed: mix_hash.to_vec(),
+        });
+    }
+    // A final hash of any other length is not a ProgPoW hash.
+    let accepted = <&[u8; 32]>::try_from(final_hash).is_ok_and(|hash| rule.accepts(seal, hash));
+    if !accepted {
+        return Err(SealError::BoundaryNotMet {
+            final_hash: final_hash.to_vec(),
+        });
+    }
+    Ok(())
+}
+
+/// Verifies a seal by recomputing its ProgPoW hash.
+///
+/// # Arguments
+///
+/// * `seal` - The seal to verify.
+/// * `size` - The size of the dataset.
+/// * `c_dag` - The cached first words of the DAG.
+/// * `lookup` - A function to retrieve memory segments based on an index.
+///
+/// # Returns
+///
+/// The final hash if the mix hash matches and the final hash meets the
+/// boundary, or the [`SealError`] describing the first check that failed.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(
+            block_number = seal.block_number,
+            epoch = crate::ethash::cache::epoch(seal.block_number),
+            nonce = seal.nonce,
+        ),
+        err(level = "debug"),
+    )
+)]
+pub fn verify_seal(
+    seal: &Seal,
+    size: u64,
+    c_dag: &[u32],
+    lookup: &dyn Fn(u32) -> Vec<u8>,
+) -> Result<Vec<u8>, SealError> {
+    let (mix_hash, final_hash) = progpow(
+        &seal.header_hash,
+        seal.nonce,
+        size,
+        seal.block_number,
+        c_dag,
+        lookup,
+    );
+    check_seal(seal, &mix_hash, &final_hash)?;
+    Ok(final_hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{header_hash, mock_c_dag, mock_lookup};
+
+    const SIZE: u64 = 1024;
+
+    fn sealed(nonce: u64, c_dag: &[u32], lookup: &dyn Fn(u32) -> Vec<u8>) -> (Seal, Vec<u8>) {
+        let header_hash = header_hash(1);
+        let (mix_hash, final_hash) = progpow(&header_hash, nonce, SIZE, 100, c_dag, lookup);
+        let seal = Seal {
+            header_hash,
+            block_number: 100,
+            nonce,
+            mix_hash: mix_hash.try_into().unwrap(),
+            boundary: [0xff; 32],
+        };
+        (seal, final_hash)
+    }
+
+    #[test]
+    fn test_verify_seal_reports_each_failure() {
+        let (c_dag, lookup) = (mock_c_dag(1), mock_lookup(1));
+        let (seal, final_hash) = sealed(7, &c_dag, &lookup);
+        assert_eq!(
+            verify_seal(&seal, SIZE, &c_dag, &lookup),
+            Ok(final_hash.clone())
+        );
+
+        let mut wrong_mix = seal.clone();
+        wrong_mix.mix_hash[31] ^= 1;
+        assert_eq!(
+            verify_seal(&wrong_mix, SIZE, &c_dag, &lookup),
+            Err(SealError::MixMismatch {
+                computed: seal.mix_hash.to_vec()
+            })
+        );
+
+        let mut wrong_nonce = seal.clone();
+        wrong_nonce.nonce += 1;
+        assert!(matches!(
+            verify_seal(&wrong_nonce, SIZE, &c_dag, &lookup),
+            Err(SealError::MixMismatch { .. })
+        ));
+
+        let mut too_hard = seal;
+        too_hard.boundary = [0; 32];
+        assert_eq!(
+            verify_seal(&too_hard, SIZE, &c_dag, &lookup),
+            Err(SealError::BoundaryNotMet { final_hash })
+        );
+    }
+
+    /// Records the names and `nonce` fields of the spans opened, and the
+    /// number of events emitted.
+    #[cfg(feature = "tracing")]
+    #[derive(Default)]
+    struct Recorder {
+        spans: std::sync::Mutex<Vec<(&'static str, Option<u64>)>>,
+        events: std::sync::atomic::AtomicUsize,
+    }
+
+    #[cfg(feature = "tracing")]
+    impl tracing::Subscriber for Recorder {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            struct Nonce(Option<u64>);
+            impl tracing::field::Visit for Nonce {
+                fn record_u64(&mut self, field: &tracing::field::Field, value: u64) {
+                    if field.name() == "nonce" {
+                        self.0 = Some(value);
+                    }
+                }
+                fn record_debug(&mut self, _: &tracing::field::Field, _: &dyn std::fmt::Debug) {}
+            }
+            let mut nonce = Nonce(None);
+            span.record(&mut nonce);
+            let mut spans = self.spans.lock().unwrap();
+            spans.push((span.metadata().name(), nonce.0));
+            tracing::span::Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+        fn event(&self, _: &tracing::Event<'_>) {
+            self.events
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+
+        fn enter(&self, _: &tracing::span::Id) {}
+
+        fn exit(&self, _: &tracing::span::Id) {}
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_verify_seal_opens_spans_for_each_stage() {
+        let (c_dag, lookup) = (mock_c_dag(1), mock_lookup(1));
+        let (seal, _) = sealed(7, &c_dag, &lookup);
+        let recorder = std::sync::Arc::new(Recorder::default());
+        tracing::subscriber::with_default(recorder.clone(), || {
+            verify_seal(&seal, SIZE, &c_dag, &lookup).unwrap();
+        });
+        let spans = recorder.spans.lock().unwrap().clone();
+        assert_eq!(spans[0], ("verify_seal", Some(7)));
+        let names: Vec<_> = spans.iter().map(|(name, _)| *name).collect();
+        assert!(names.contains(&"keccak_f800_short"));
+        assert!(names.contains(&"period_lane_hashes"));
+        assert!(names.contains(&"keccak_f800_long"));
+        // One event per DAG load.
+        let loads = recorder.events.load(std::sync::atomic::Ordering::SeqCst);
+        assert_eq!(loads, crate::basic_algorithm::PROGPOW_CNT_DAG);
+
+        // A failed seal also emits its error.
+        let mut too_hard = seal;
+        too_hard.boundary = [0; 32];
+        tracing::subscriber::with_default(recorder.clone(), || {
+            verify_seal(&too_hard, SIZE, &c_dag, &lookup).unwrap_err();
+        });
+        let events = recorder.events.load(std::sync::atomic::Ordering::SeqCst);
+        assert_eq!(events, 2 * loads + 1);
+    }
+}
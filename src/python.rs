@@ -0,0 +1,212 @@
+//! Python bindings, published as the `progpow` extension module of the
+//! `progpow-py` package.
+//!
+//! The module exposes the epoch parameters, light cache generation, hashing
+//! and seal verification:
+//!
+//! ```python
+//! import progpow
+//!
+//! cache = progpow.Cache(progpow.epoch(block_number))
+//! mix_hash, final_hash = cache.hash(header_hash, block_number, nonce)
+//! cache.verify_seal(header_hash, block_number, nonce, mix_hash, boundary)
+//! ```
+//!
+//! Hashes are `bytes`. Long computations release the GIL so other Python
+//! threads keep running.
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use crate::endian::le_bytes;
+use crate::ethash::cache::{self, make_cache};
+use crate::ethash::dataset::{dataset_lookup, generate_c_dag};
+use crate::progpow::progpow::progpow;
+use crate::progpow::verify::{verify_seal, Seal};
+
+create_exception!(
+    progpow,
+    InvalidSeal,
+    PyException,
+    "Raised when a seal's mix hash or final hash fails verification."
+);
+
+/// Returns the epoch `block_number` belongs to.
+#[pyfunction]
+fn epoch(block_number: u64) -> u64 {
+    cache::epoch(block_number)
+}
+
+/// Returns the size in bytes of the light cache for `epoch`.
+#[pyfunction]
+fn cache_size(epoch: u64) -> u64 {
+    cache::cache_size(epoch)
+}
+
+/// Returns the size in bytes of the full dataset for `epoch`.
+#[pyfunction]
+fn dataset_size(epoch: u64) -> u64 {
+    cache::dataset_size(epoch)
+}
+
+/// Returns the 32-byte seed hash of `epoch`.
+#[pyfunction]
+fn seed_hash(py: Python<'_>, epoch: u64) -> Bound<'_, PyBytes> {
+    PyBytes::new(py, &cache::seed_hash(epoch))
+}
+
+/// An epoch's light cache, for hashing and verification without the DAG.
+#[pyclass(module = "progpow", frozen)]
+struct Cache {
+    epoch: u64,
+    size: u64,
+    words: Vec<u32>,
+    c_dag: Vec<u32>,
+}
+
+#[pymethods]
+impl Cache {
+    /// Generates the light cache of `epoch`.
+    #[new]
+    fn new(py: Python<'_>, epoch: u64) -> Self {
+        py.detach(|| {
+            let words = make_cache(cache::cache_size(epoch), &cache::seed_hash(epoch));
+            let c_dag = generate_c_dag(&words);
+            Cache {
+                epoch,
+                size: cache::dataset_size(epoch),
+                words,
+                c_dag,
+            }
+        })
+    }
+
+    /// The epoch this cache belongs to.
+    #[getter]
+    fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Returns the cache as little-endian bytes.
+    fn to_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &le_bytes(&self.words))
+    }
+
+    /// Computes the ProgPoW hash of a header.
+    ///
+    /// Returns `(mix_hash, final_hash)`.
+    fn hash<'py>(
+        &self,
+        py: Python<'py>,
+        header_hash: [u8; 32],
+        block_number: u64,
+        nonce: u64,
+    ) -> (Bound<'py, PyBytes>, Bound<'py, PyBytes>) {
+        let (mix_hash, final_hash) = py.detach(|| {
+            let lookup = dataset_lookup(&self.words);
+            progpow(
+                &header_hash,
+                nonce,
+                self.size,
+                block_number,
+                &self.c_dag,
+                &lookup,
+            )
+        });
+        (PyBytes::new(py, &mix_hash), PyBytes::new(py, &final_hash))
+    }
+
+    /// Verifies a seal, returning its final hash.
+    ///
+    /// Raises `InvalidSeal` if the mix hash differs from the recomputed one
+    /// or the final hash exceeds `boundary`.
+    fn verify_seal<'py>(
+        &self,
+        py: Python<'py>,
+        header_hash: [u8; 32],
+        block_number: u64,
+        nonce: u64,
+        mix_hash: [u8; 32],
+        boundary: [u8; 32],
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let seal = Seal {
+            header_hash,
+            block_number,
+            nonce,
+            mix_hash,
+            boundary,
+        };
+        let final_hash = py
+            .detach(|| {
+                let lookup = dataset_lookup(&self.words);
+                verify_seal(&seal, self.size, &self.c_dag, &lookup)
+            })
+            .map_err(|error| InvalidSeal::new_err(error.to_string()))?;
+        Ok(PyBytes::new(py, &final_hash))
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Cache(epoch={})", self.epoch)
+    }
+}
+
+/// The `progpow` Python module.
+#[pymodule]
+#[pyo3(name = "progpow")]
+fn progpow_module(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(epoch, module)?)?;
+    module.add_function(wrap_pyfunction!(cache_size, module)?)?;
+    module.add_function(wrap_pyfunction!(dataset_size, module)?)?;
+    module.add_function(wrap_pyfunction!(seed_hash, module)?)?;
+    module.add_class::<Cache>()?;
+    module.add("InvalidSeal", module.py().get_type::<InvalidSeal>())?;
+    module.add("EPOCH_LENGTH", cache::EPOCH_LENGTH)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::PyDict;
+
+    #[test]
+    fn test_python_cache_verifies_seals() {
+        Python::initialize();
+        Python::attach(|py| {
+            let module = PyModule::new(py, "progpow").unwrap();
+            progpow_module(&module).unwrap();
+            let words = make_cache(1024, &cache::seed_hash(1));
+            let cache = Cache {
+                epoch: 1,
+                size: 1 << 16,
+                c_dag: generate_c_dag(&words),
+                words,
+            };
+            let locals = PyDict::new(py);
+            locals.set_item("progpow", module).unwrap();
+            locals
+                .set_item("cache", Bound::new(py, cache).unwrap())
+                .unwrap();
+            py.run(
+                cr#"
+header = bytes(range(32))
+mix, final = cache.hash(header, 100, 7)
+assert cache.verify_seal(header, 100, 7, mix, b"\xff" * 32) == final
+for bad_mix, boundary in [(bytes(32), b"\xff" * 32), (mix, bytes(32))]:
+    try:
+        cache.verify_seal(header, 100, 7, bad_mix, boundary)
+        raise AssertionError("invalid seal accepted")
+    except progpow.InvalidSeal:
+        pass
+assert progpow.seed_hash(0) == bytes(32)
+assert progpow.epoch(progpow.EPOCH_LENGTH) == 1
+"#,
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+        });
+    }
+}
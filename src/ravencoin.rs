@@ -0,0 +1,526 @@
+//! Ravencoin headers and their KawPoW seals.
+//!
+//! Ravencoin does not seal an RLP keccak hash. Its headers are Bitcoin-style
+//! little-endian structures, and KawPoW seals the double SHA-256 of the
+//! first 80 bytes of one: version, previous block hash, merkle root, time,
+//! compact target bits and height. The full 120-byte header appends the
+//! 64-bit nonce and the 32-byte mix hash the seal consists of.
+//!
+//! Ravencoin keeps both hashes as `uint256`s, whose bytes are stored in the
+//! reverse of the order they are displayed in, and crosses to KawPoW through
+//! their hex: it seals `to_hash256(GetKAWPOWHeaderHash().GetHex())` and
+//! stores the mix hash as `uint256S(to_hex(mix_hash))`. So the header hash
+//! KawPoW sees is the double SHA-256 reversed, and the serialized mix hash
+//! is KawPoW's reversed; [`RavencoinHeader`] reverses both at that boundary.
+
+use std::fmt;
+
+use crate::difficulty::{check_difficulty, BlockInfo, DifficultyError, DifficultyRule};
+use crate::ethash::cache::MAX_EPOCH;
+use crate::ethash::manager::CacheManager;
+use crate::progpow::kawpow::KAWPOW_EPOCH_LENGTH;
+use crate::progpow::verify::{Seal, SealError};
+
+/// The length of the part of a header KawPoW seals.
+pub const HEADER_HASH_INPUT_LENGTH: usize = 80;
+
+/// The length of a full KawPoW header, seal included.
+pub const HEADER_LENGTH: usize = 120;
+
+/// The reason a Ravencoin header was rejected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RavencoinError {
+    /// The header is not [`HEADER_LENGTH`] bytes long.
+    Length {
+        /// The length of the input.
+        found: usize,
+    },
+    /// The compact target bits are negative or overflow 256 bits.
+    InvalidBits {
+        /// The bits of the header.
+        bits: u32,
+    },
+    /// The header's KawPoW epoch is past [`MAX_EPOCH`].
+    UnsupportedEpoch {
+        /// The epoch of the header.
+        epoch: u64,
+    },
+    /// The seal does not verify.
+    InvalidSeal(SealError),
+    /// The target bits are not the ones the header's parents call for.
+    InvalidDifficulty(DifficultyError),
+}
+
+impl fmt::Display for RavencoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RavencoinError::Length { found } => {
+                write!(f, "header is {found} bytes, expected {HEADER_LENGTH}")
+            }
+            RavencoinError::InvalidBits { bits } => {
+                write!(f, "invalid compact target bits {bits:#010x}")
+            }
+            RavencoinError::UnsupportedEpoch { epoch } => write!(
+                f,
+                "epoch {epoch} is past the last supported epoch {}",
+                MAX_EPOCH - 1
+            ),
+            RavencoinError::InvalidSeal(error) => write!(f, "invalid seal: {error}"),
+            RavencoinError::InvalidDifficulty(error) => write!(f, "invalid difficulty: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for RavencoinError {}
+
+impl From<SealError> for RavencoinError {
+    fn from(error: SealError) -> Self {
+        RavencoinError::InvalidSeal(error)
+    }
+}
+
+impl From<DifficultyError> for RavencoinError {
+    fn from(error: DifficultyError) -> Self {
+        RavencoinError::InvalidDifficulty(error)
+    }
+}
+
+/// A decoded KawPoW-era Ravencoin header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RavencoinHeader {
+    /// The block version.
+    pub version: u32,
+    /// The previous block hash, in serialized byte order.
+    pub prev_block: [u8; 32],
+    /// The merkle root, in serialized byte order.
+    pub merkle_root: [u8; 32],
+    /// The block time in seconds since the Unix epoch.
+    pub time: u32,
+    /// The target in compact form; see [`target_from_bits`].
+    pub bits: u32,
+    /// The block height, which selects the epoch and the program.
+    pub height: u32,
+    /// The nonce in the seal.
+    pub nonce: u64,
+    /// The mix hash in the seal, in the byte order KawPoW produces it, the
+    /// reverse of its serialized order.
+    pub mix_hash: [u8; 32],
+}
+
+impl RavencoinHeader {
+    /// Decodes a [`HEADER_LENGTH`]-byte serialized header.
+    pub fn decode(bytes: &[u8]) -> Result<Self, RavencoinError> {
+        if bytes.len() != HEADER_LENGTH {
+            return Err(RavencoinError::Length { found: bytes.len() });
+        }
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        Ok(RavencoinHeader {
+            version: u32_at(0),
+            prev_block: bytes[4..36].try_into().unwrap(),
+            merkle_root: bytes[36..68].try_into().unwrap(),
+            time: u32_at(68),
+            bits: u32_at(72),
+            height: u32_at(76),
+            nonce: u64::from_le_bytes(bytes[80..88].try_into().unwrap()),
+            mix_hash: reversed(bytes[88..120].try_into().unwrap()),
+        })
+    }
+
+    /// Serializes the header, seal included.
+    pub fn encode(&self) -> [u8; HEADER_LENGTH] {
+        let mut out = [0; HEADER_LENGTH];
+        out[..80].copy_from_slice(&self.hash_input());
+        out[80..88].copy_from_slice(&self.nonce.to_le_bytes());
+        out[88..].copy_from_slice(&reversed(self.mix_hash));
+        out
+    }
+
+    /// Returns the 80 bytes KawPoW seals.
+    pub fn hash_input(&self) -> [u8; HEADER_HASH_INPUT_LENGTH] {
+        let mut out = [0; HEADER_HASH_INPUT_LENGTH];
+        out[..4].copy_from_slice(&self.version.to_le_bytes());
+        out[4..36].copy_from_slice(&self.prev_block);
+        out[36..68].copy_from_slice(&self.merkle_root);
+        out[68..72].copy_from_slice(&self.time.to_le_bytes());
+        out[72..76].copy_from_slice(&self.bits.to_le_bytes());
+        out[76..].copy_from_slice(&self.height.to_le_bytes());
+        out
+    }
+
+    /// Returns the hash KawPoW seals; see [`header_hash`].
+    pub fn header_hash(&self) -> [u8; 32] {
+        header_hash(&self.hash_input())
+    }
+
+    /// Returns the [`Seal`] to check, or an error if the bits are invalid.
+    pub fn seal(&self) -> Result<Seal, RavencoinError> {
+        Ok(Seal {
+            header_hash: self.header_hash(),
+            block_number: self.height as u64,
+            nonce: self.nonce,
+            mix_hash: self.mix_hash,
+            boundary: target_from_bits(self.bits)
+                .ok_or(RavencoinError::InvalidBits { bits: self.bits })?,
+        })
+    }
+}
+
+/// Returns the hash KawPoW seals for the first 80 bytes of a header: their
+/// double SHA-256, byte-reversed into the order Ravencoin displays it in.
+pub fn header_hash(header_bytes: &[u8; HEADER_HASH_INPUT_LENGTH]) -> [u8; 32] {
+    reversed(sha256(&sha256(header_bytes)))
+}
+
+/// Returns a `uint256`'s bytes in the other of its stored and displayed
+/// orders.
+fn reversed(mut hash: [u8; 32]) -> [u8; 32] {
+    hash.reverse();
+    hash
+}
+
+/// Expands Bitcoin's compact target encoding into a 32-byte big-endian
+/// boundary.
+///
+/// The top byte of `bits` is a base-256 exponent and the low 23 bits the
+/// mantissa. Returns `None` for negative targets and ones that overflow 256
+/// bits; a zero mantissa gives a zero target, which no hash meets.
+pub fn target_from_bits(bits: u32) -> Option<[u8; 32]> {
+    let exponent = (bits >> 24) as usize;
+    let mantissa = bits & 0x007f_ffff;
+    let mut target = [0; 32];
+    if mantissa == 0 {
+        return Some(target);
+    }
+    if bits & 0x0080_0000 != 0 {
+        return None;
+    }
+    for (i, &byte) in mantissa.to_be_bytes()[1..].iter().enumerate() {
+        // Byte i of the mantissa is worth 256^(exponent - 1 - i); bytes
+        // below 256^0 are shifted out.
+        let Some(power) = exponent.checked_sub(1 + i) else {
+            continue;
+        };
+        if byte != 0 {
+            if power >= 32 {
+                return None;
+            }
+            target[31 - power] = byte;
+        }
+    }
+    Some(target)
+}
+
+/// Encodes a 32-byte big-endian target in Bitcoin's compact form, the
+/// inverse of [`target_from_bits`] up to the precision of its 23-bit
+/// mantissa, which this truncates to as Bitcoin's `GetCompact` does.
+pub fn bits_from_target(target: &[u8; 32]) -> u32 {
+    let Some(first) = target.iter().position(|&byte| byte != 0) else {
+        return 0;
+    };
+    let mut size = 32 - first;
+    let byte = |i: usize| target.get(first + i).copied().unwrap_or(0) as u32;
+    let mut mantissa = byte(0) << 16 | byte(1) << 8 | byte(2);
+    // A set top bit would read as a sign.
+    if mantissa & 0x0080_0000 != 0 {
+        mantissa >>= 8;
+        size += 1;
+    }
+    (size as u32) << 24 | mantissa
+}
+
+/// Verifies the KawPoW seal of a serialized Ravencoin header.
+///
+/// This decodes the header, expands its target bits, and checks the seal
+/// against [`header_hash`] of its first 80 bytes with the cache of its
+/// KawPoW epoch in `caches`, generating the cache if it is not held. The
+/// manager's epochs are KawPoW epochs of [`KAWPOW_EPOCH_LENGTH`] blocks, so
+/// it must not also serve ethash headers.
+pub fn verify_header(bytes: &[u8], caches: &CacheManager) -> Result<(), RavencoinError> {
+    let header = RavencoinHeader::decode(bytes)?;
+    let epoch = supported_epoch(header.height, KAWPOW_EPOCH_LENGTH)?;
+    caches.get(epoch).verify_kawpow_seal(&header.seal()?)?;
+    Ok(())
+}
+
+/// Verifies the KawPoW seal of a serialized Ravencoin header as
+/// [`verify_header`] does, after checking its target bits against its
+/// serialized parents.
+///
+/// # Arguments
+///
+/// * `bytes` - The header.
+/// * `parents` - Consecutive parents of the header, oldest first, ending
+///   with its parent; [`DifficultyRule::parents_needed`] of them suffice.
+/// * `caches` - The KawPoW caches to verify the seal with.
+/// * `rule` - The chain's difficulty rule, such as
+///   [`DarkGravityWave::ravencoin`](crate::difficulty::DarkGravityWave::ravencoin).
+pub fn verify_header_with_parents(
+    bytes: &[u8],
+    parents: &[&[u8]],
+    caches: &CacheManager,
+    rule: &dyn DifficultyRule,
+) -> Result<(), RavencoinError> {
+    check_parents(bytes, parents, rule)?;
+    verify_header(bytes, caches)
+}
+
+/// Checks the target bits of a serialized header against its serialized
+/// parents under `rule`.
+pub(crate) fn check_parents(
+    bytes: &[u8],
+    parents: &[&[u8]],
+    rule: &dyn DifficultyRule,
+) -> Result<(), RavencoinError> {
+    let header = RavencoinHeader::decode(bytes)?;
+    let parents = parents
+        .iter()
+        .map(|parent| Ok(BlockInfo::from(&RavencoinHeader::decode(parent)?)))
+        .collect::<Result<Vec<_>, RavencoinError>>()?;
+    check_difficulty(rule, &BlockInfo::from(&header), &parents)?;
+    Ok(())
+}
+
+/// Returns the epoch of `height` for epochs of `epoch_length` blocks,
+/// rejecting unsupported ones.
+pub(crate) fn supported_epoch(height: u32, epoch_length: u64) -> Result<u64, RavencoinError> {
+    let epoch = height as u64 / epoch_length;
+    if epoch >= MAX_EPOCH {
+        return Err(RavencoinError::UnsupportedEpoch { epoch });
+    }
+    Ok(epoch)
+}
+
+/// The SHA-256 round constants.
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Computes the SHA-256 hash of `data`.
+fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    let mut padded = data.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in padded.chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes(bytes.try_into().unwrap());
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (word, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *word = word.wrapping_add(value);
+        }
+    }
+
+    let mut out = [0; 32];
+    for (bytes, word) in out.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::difficulty::DarkGravityWave;
+    use crate::hex;
+    use crate::testutil::tiny_cache_manager;
+
+    #[test]
+    fn test_sha256_vectors() {
+        assert_eq!(
+            hex::encode(&sha256(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex::encode(&sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        // A full block of data pushes the padding into a second block.
+        assert_eq!(
+            hex::encode(&sha256(&[b'a'; 64])),
+            "ffe054fe7ae0cb6dc65c3af9b61d5209f439851db43d0ba5997337df154668eb"
+        );
+    }
+
+    #[test]
+    fn test_header_hash_is_double_sha256() {
+        // Bitcoin's genesis header has the same 80-byte shape; its block
+        // hash is the double SHA-256 displayed as `GetHex` does, the order
+        // Ravencoin hands KawPoW its header hash in.
+        let genesis = hex::decode(
+            "0100000000000000000000000000000000000000000000000000000000000000\
+             000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa\
+             4b1e5e4a29ab5f49ffff001d1dac2b7c",
+        )
+        .unwrap();
+        assert_eq!(
+            hex::encode(&header_hash(&genesis.try_into().unwrap())),
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
+        );
+    }
+
+    #[test]
+    fn test_target_from_bits() {
+        let mut expected = [0; 32];
+        expected[4..6].copy_from_slice(&[0xff, 0xff]);
+        assert_eq!(target_from_bits(0x1d00ffff), Some(expected));
+        let mut expected = [0; 32];
+        expected[31] = 0x12;
+        assert_eq!(target_from_bits(0x01123456), Some(expected));
+        assert_eq!(
+            target_from_bits(0x20123456).unwrap()[..3],
+            [0x12, 0x34, 0x56]
+        );
+        assert_eq!(target_from_bits(0x21123456), None);
+        assert_eq!(target_from_bits(0x1d800000), Some([0; 32]));
+        assert_eq!(target_from_bits(0x1d812345), None);
+
+        // Compact encoding is canonical and round-trips.
+        for bits in [0x1d00ffff, 0x01120000, 0x20123456, 0x1b0404cb] {
+            assert_eq!(bits_from_target(&target_from_bits(bits).unwrap()), bits);
+        }
+        assert_eq!(bits_from_target(&[0; 32]), 0);
+        let mut high = [0; 32];
+        high[31] = 0x80;
+        assert_eq!(bits_from_target(&high), 0x02008000);
+        high[12..16].copy_from_slice(&[0x12, 0x34, 0x56, 0x78]);
+        assert_eq!(bits_from_target(&high), 0x14123456);
+    }
+
+    #[test]
+    fn test_verify_header_checks_the_kawpow_seal() {
+        let caches = tiny_cache_manager(2);
+        let mut header = RavencoinHeader {
+            version: 0x3000_0000,
+            prev_block: [7; 32],
+            merkle_root: [9; 32],
+            time: 1_700_000_000,
+            bits: 0x2100ffff,
+            height: 7600,
+            nonce: 42,
+            mix_hash: [0; 32],
+        };
+        assert_eq!(supported_epoch(header.height, KAWPOW_EPOCH_LENGTH), Ok(1));
+        let (mix_hash, _) = caches.get(1).hash_kawpow(&header.header_hash(), 7600, 42);
+        header.mix_hash = mix_hash.try_into().unwrap();
+
+        let bytes = header.encode();
+        assert_eq!(bytes[88..], reversed(header.mix_hash));
+        assert_eq!(RavencoinHeader::decode(&bytes), Ok(header.clone()));
+        assert_eq!(verify_header(&bytes, &caches), Ok(()));
+        assert_eq!(
+            verify_header(&bytes[..80], &caches),
+            Err(RavencoinError::Length { found: 80 })
+        );
+
+        let mut tampered = bytes;
+        tampered[68] ^= 1;
+        assert!(matches!(
+            verify_header(&tampered, &caches),
+            Err(RavencoinError::InvalidSeal(SealError::MixMismatch { .. }))
+        ));
+
+        // Below the averaging window, the header declares the limit.
+        let mut rule = DarkGravityWave {
+            past_blocks: 8000,
+            target_spacing: 60,
+            pow_limit: target_from_bits(0x2100ffff).unwrap(),
+        };
+        let parent = RavencoinHeader {
+            height: 7599,
+            ..header.clone()
+        }
+        .encode();
+        assert_eq!(
+            verify_header_with_parents(&bytes, &[&parent], &caches, &rule),
+            Ok(())
+        );
+        rule.pow_limit = target_from_bits(0x1d00ffff).unwrap();
+        assert!(matches!(
+            verify_header_with_parents(&bytes, &[&parent], &caches, &rule),
+            Err(RavencoinError::InvalidDifficulty(
+                DifficultyError::Mismatch { .. }
+            ))
+        ));
+
+        // The bits are sealed too, so reseal before checking the target.
+        header.bits = 0x0100_0001;
+        let (mix_hash, _) = caches.get(1).hash_kawpow(&header.header_hash(), 7600, 42);
+        header.mix_hash = mix_hash.try_into().unwrap();
+        assert!(matches!(
+            verify_header(&header.encode(), &caches),
+            Err(RavencoinError::InvalidSeal(
+                SealError::BoundaryNotMet { .. }
+            ))
+        ));
+    }
+
+    /// Verifies mainnet headers with real epoch caches. The file
+    /// `PROGPOW_RAVENCOIN_HEADERS` names holds one serialized 120-byte
+    /// header in hex per line, as `getblockheader <hash> false` prints it.
+    #[test]
+    #[ignore = "needs PROGPOW_RAVENCOIN_HEADERS and full-size epoch caches"]
+    fn test_mainnet_headers() {
+        let path = std::env::var("PROGPOW_RAVENCOIN_HEADERS")
+            .expect("PROGPOW_RAVENCOIN_HEADERS must name a file of mainnet headers");
+        let caches = CacheManager::new(1);
+        let fixture = std::fs::read_to_string(path).unwrap();
+        let headers: Vec<&str> = fixture
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .collect();
+        assert!(!headers.is_empty(), "the fixture holds no headers");
+        for line in headers {
+            assert_eq!(
+                verify_header(&hex::decode(line).unwrap(), &caches),
+                Ok(()),
+                "{line}"
+            );
+        }
+    }
+}
@@ -0,0 +1,334 @@
+//! Header validation in the shape of reth's consensus hooks.
+//!
+//! reth is not published on crates.io, so this crate cannot implement its
+//! `HeaderValidator` trait. [`ProgpowConsensus`] has the same hooks instead,
+//! over `alloy_consensus::Header`, which is reth's header type:
+//! [`validate_header`](ProgpowConsensus::validate_header),
+//! [`validate_header_against_parent`](ProgpowConsensus::validate_header_against_parent)
+//! and
+//! [`validate_header_with_total_difficulty`](ProgpowConsensus::validate_header_with_total_difficulty).
+//! A node prototype's own `HeaderValidator` impl forwards each call to the
+//! matching hook and maps the [`ConsensusError`] into reth's.
+//!
+//! Seals are checked by a [`Verifier`], so the chain may be ProgPoW or
+//! KawPoW, and difficulties by the chain's [`DifficultyRule`].
+
+use std::fmt;
+use std::sync::Arc;
+
+use alloy_consensus::Header;
+use alloy_primitives::U256;
+
+use crate::difficulty::{check_difficulty, BlockInfo, DifficultyError, DifficultyRule, Work};
+use crate::engine::{seal_of, EngineError};
+use crate::hex;
+use crate::verifier::Verifier;
+
+/// The reason [`ProgpowConsensus`] rejected a header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConsensusError {
+    /// The seal or the difficulty is wrong, or an ommer was rejected.
+    Engine(EngineError),
+    /// The header's parent hash is not the hash of the parent given.
+    ParentHashMismatch {
+        /// The hash of the parent given.
+        expected: [u8; 32],
+        /// The parent hash the header declares.
+        got: [u8; 32],
+    },
+    /// The header is not newer than its parent.
+    TimestampNotAfterParent {
+        /// The parent's timestamp.
+        parent: u64,
+        /// The header's timestamp.
+        timestamp: u64,
+    },
+    /// The header declares no difficulty, so it has no proof of work.
+    ZeroDifficulty,
+    /// The chain's total difficulty before the header reached the terminal
+    /// total difficulty, so no proof-of-work block may follow.
+    PastTerminalDifficulty {
+        /// The total difficulty before the header.
+        total_difficulty: U256,
+    },
+}
+
+impl fmt::Display for ConsensusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConsensusError::Engine(error) => write!(f, "{error}"),
+            ConsensusError::ParentHashMismatch { expected, got } => write!(
+                f,
+                "parent hash mismatch: expected {}, got {}",
+                hex::encode(expected),
+                hex::encode(got)
+            ),
+            ConsensusError::TimestampNotAfterParent { parent, timestamp } => write!(
+                f,
+                "timestamp {timestamp} is not after the parent's {parent}"
+            ),
+            ConsensusError::ZeroDifficulty => write!(f, "proof-of-work header has no difficulty"),
+            ConsensusError::PastTerminalDifficulty { total_difficulty } => write!(
+                f,
+                "total difficulty {total_difficulty} is past the terminal total difficulty"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConsensusError {}
+
+impl From<EngineError> for ConsensusError {
+    fn from(error: EngineError) -> Self {
+        ConsensusError::Engine(error)
+    }
+}
+
+impl From<DifficultyError> for ConsensusError {
+    fn from(error: DifficultyError) -> Self {
+        ConsensusError::Engine(EngineError::InvalidDifficulty(error))
+    }
+}
+
+/// ProgPoW header validation for a reth-based node.
+#[derive(Clone)]
+pub struct ProgpowConsensus {
+    verifier: Verifier,
+    rule: Arc<dyn DifficultyRule>,
+    terminal_total_difficulty: Option<U256>,
+}
+
+impl ProgpowConsensus {
+    /// Creates hooks checking seals with `verifier` and difficulties by
+    /// `rule`, which must read at most one parent, as Ethereum's rules do.
+    pub fn new(verifier: Verifier, rule: impl DifficultyRule + 'static) -> Self {
+        ProgpowConsensus {
+            verifier,
+            rule: Arc::new(rule),
+            terminal_total_difficulty: None,
+        }
+    }
+
+    /// Ends proof of work once the total difficulty reaches
+    /// `terminal_total_difficulty`, as a merge does.
+    pub fn with_terminal_total_difficulty(mut self, terminal_total_difficulty: U256) -> Self {
+        self.terminal_total_difficulty = Some(terminal_total_difficulty);
+        self
+    }
+
+    /// Returns the verifier seals are checked with.
+    pub fn verifier(&self) -> &Verifier {
+        &self.verifier
+    }
+
+    /// Checks what a header shows on its own: a difficulty and a seal that
+    /// meets it.
+    pub fn validate_header(&self, header: &Header) -> Result<(), ConsensusError> {
+        if header.difficulty.is_zero() {
+            return Err(ConsensusError::ZeroDifficulty);
+        }
+        self.verifier
+            .verify_seal(&seal_of(header))
+            .map_err(EngineError::from)?;
+        Ok(())
+    }
+
+    /// Checks a header against its parent: the parent hash, the number, the
+    /// timestamp and the difficulty the parent calls for. The seal is left
+    /// to [`validate_header`](Self::validate_header).
+    pub fn validate_header_against_parent(
+        &self,
+        header: &Header,
+        parent: &Header,
+    ) -> Result<(), ConsensusError> {
+        let expected = parent.hash_slow().0;
+        if header.parent_hash.0 != expected {
+            return Err(ConsensusError::ParentHashMismatch {
+                expected,
+                got: header.parent_hash.0,
+            });
+        }
+        if header.timestamp <= parent.timestamp {
+            return Err(ConsensusError::TimestampNotAfterParent {
+                parent: parent.timestamp,
+                timestamp: header.timestamp,
+            });
+        }
+        check_difficulty(
+            &*self.rule,
+            &BlockInfo::from(header),
+            &[BlockInfo::from(parent)],
+        )?;
+        Ok(())
+    }
+
+    /// Checks a header against the chain's total difficulty before it: no
+    /// proof-of-work block follows the terminal total difficulty.
+    pub fn validate_header_with_total_difficulty(
+        &self,
+        header: &Header,
+        total_difficulty: U256,
+    ) -> Result<(), ConsensusError> {
+        if header.difficulty.is_zero() {
+            return Err(ConsensusError::ZeroDifficulty);
+        }
+        match self.terminal_total_difficulty {
+            Some(terminal) if total_difficulty >= terminal => {
+                Err(ConsensusError::PastTerminalDifficulty { total_difficulty })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the seals of a block's ommers, each at its own number;
+    /// see [`PowEngine::verify_with_ommers`](crate::engine::PowEngine::verify_with_ommers).
+    pub fn validate_ommers(&self, ommers: &[Header]) -> Result<(), ConsensusError> {
+        ommers.iter().enumerate().try_for_each(|(index, ommer)| {
+            self.validate_header(ommer).map_err(|error| match error {
+                ConsensusError::Engine(error) => EngineError::InvalidOmmer {
+                    index,
+                    error: Box::new(error),
+                }
+                .into(),
+                error => error,
+            })
+        })
+    }
+
+    /// Returns the difficulty the next block after `parent` must declare at
+    /// `timestamp`, for a miner assembling it.
+    pub fn next_difficulty(&self, parent: &Header, timestamp: u64) -> Result<U256, ConsensusError> {
+        match self.rule.next_work(&[BlockInfo::from(parent)], timestamp)? {
+            Work::Difficulty(next) => Ok(U256::from_be_bytes(next.to_be_bytes())),
+            Work::Bits(_) => Err(DifficultyError::WrongKind {
+                number: parent.number + 1,
+            }
+            .into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::Chain;
+    use crate::difficulty::EthashRule;
+    use crate::engine::{PowEngine, ProgpowEngine};
+    use crate::progpow::verify::SealError;
+    use crate::testutil::tiny_cache_manager;
+
+    fn consensus() -> ProgpowConsensus {
+        let verifier = Verifier::with_caches(Chain::ethereum(), tiny_cache_manager(1));
+        ProgpowConsensus::new(verifier, EthashRule::progpow_testnet())
+    }
+
+    fn sealed(mut header: Header) -> Header {
+        let engine = ProgpowEngine::new(Arc::new(tiny_cache_manager(1)));
+        assert!(engine.seal(&mut header, 0..256).unwrap());
+        header
+    }
+
+    #[test]
+    fn test_header_hooks() {
+        let consensus = consensus();
+        let parent = Header {
+            number: 99,
+            timestamp: 1_000,
+            difficulty: U256::from(131_072),
+            ..Header::default()
+        };
+        let timestamp = parent.timestamp + 12;
+        let header = sealed(Header {
+            number: 100,
+            timestamp,
+            parent_hash: parent.hash_slow(),
+            difficulty: U256::from(4),
+            ..Header::default()
+        });
+        assert_eq!(consensus.validate_header(&header), Ok(()));
+        let mut forged = header.clone();
+        forged.nonce = (u64::from_be_bytes(forged.nonce.0) + 1)
+            .to_be_bytes()
+            .into();
+        assert!(matches!(
+            consensus.validate_header(&forged),
+            Err(ConsensusError::Engine(EngineError::InvalidSeal(
+                SealError::MixMismatch { .. }
+            )))
+        ));
+        assert!(matches!(
+            consensus.validate_ommers(&[header.clone(), forged]),
+            Err(ConsensusError::Engine(EngineError::InvalidOmmer {
+                index: 1,
+                ..
+            }))
+        ));
+
+        // The difficulty the parent calls for passes; the sealed one does not.
+        let child = Header {
+            difficulty: consensus.next_difficulty(&parent, timestamp).unwrap(),
+            ..header.clone()
+        };
+        assert_eq!(
+            consensus.validate_header_against_parent(&child, &parent),
+            Ok(())
+        );
+        assert!(matches!(
+            consensus.validate_header_against_parent(&header, &parent),
+            Err(ConsensusError::Engine(EngineError::InvalidDifficulty(
+                DifficultyError::Mismatch { .. }
+            )))
+        ));
+        let orphan = Header {
+            parent_hash: Default::default(),
+            ..child.clone()
+        };
+        assert!(matches!(
+            consensus.validate_header_against_parent(&orphan, &parent),
+            Err(ConsensusError::ParentHashMismatch { .. })
+        ));
+        let early = Header {
+            timestamp: parent.timestamp,
+            ..child
+        };
+        assert_eq!(
+            consensus.validate_header_against_parent(&early, &parent),
+            Err(ConsensusError::TimestampNotAfterParent {
+                parent: 1_000,
+                timestamp: 1_000
+            })
+        );
+    }
+
+    #[test]
+    fn test_terminal_total_difficulty() {
+        let consensus = consensus().with_terminal_total_difficulty(U256::from(1_000));
+        let header = Header {
+            number: 1,
+            difficulty: U256::from(10),
+            ..Header::default()
+        };
+        assert_eq!(
+            consensus.validate_header_with_total_difficulty(&header, U256::from(999)),
+            Ok(())
+        );
+        assert_eq!(
+            consensus.validate_header_with_total_difficulty(&header, U256::from(1_000)),
+            Err(ConsensusError::PastTerminalDifficulty {
+                total_difficulty: U256::from(1_000)
+            })
+        );
+        let empty = Header {
+            difficulty: U256::ZERO,
+            ..header
+        };
+        assert_eq!(
+            consensus.validate_header_with_total_difficulty(&empty, U256::ZERO),
+            Err(ConsensusError::ZeroDifficulty)
+        );
+        assert_eq!(
+            consensus.validate_header(&empty),
+            Err(ConsensusError::ZeroDifficulty)
+        );
+    }
+}
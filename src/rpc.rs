@@ -0,0 +1,484 @@
+//! Fetches headers from an Ethereum JSON-RPC node and verifies their seals.
+//!
+//! `eth_getBlockByNumber` returns a header as JSON fields, not RLP. The
+//! fields are re-encoded in consensus order, with the optional fields of
+//! later forks appended while the node reports them, and the encoding is
+//! checked against the block hash the node returned before its seal is
+//! verified, so a spot check cannot pass on a mis-encoded header.
+//!
+//! [`get_work`] and [`submit_work`] speak the `eth_getWork` protocol of
+//! external miners, for mining against a development node.
+
+use std::fmt;
+
+use serde_json::{json, Value};
+
+use crate::engine::{verify_header, EngineError, Work};
+use crate::ethash::cache::{EPOCH_LENGTH, MAX_EPOCH};
+use crate::ethash::manager::CacheManager;
+use crate::ethash::seed::SeedHashChain;
+use crate::header::rlp;
+use crate::hex;
+use crate::keccak::keccak256;
+
+/// How a header field is encoded.
+#[derive(Clone, Copy)]
+enum Kind {
+    /// A byte string, encoded as is.
+    Data,
+    /// An integer, encoded as big-endian bytes without leading zeros.
+    Quantity,
+}
+
+/// The fields every header has, in encoding order.
+const BASE_FIELDS: [(&str, Kind); 15] = [
+    ("parentHash", Kind::Data),
+    ("sha3Uncles", Kind::Data),
+    ("miner", Kind::Data),
+    ("stateRoot", Kind::Data),
+    ("transactionsRoot", Kind::Data),
+    ("receiptsRoot", Kind::Data),
+    ("logsBloom", Kind::Data),
+    ("difficulty", Kind::Quantity),
+    ("number", Kind::Quantity),
+    ("gasLimit", Kind::Quantity),
+    ("gasUsed", Kind::Quantity),
+    ("timestamp", Kind::Quantity),
+    ("extraData", Kind::Data),
+    ("mixHash", Kind::Data),
+    ("nonce", Kind::Data),
+];
+
+/// The fields later forks append, in encoding order. A header has a prefix
+/// of them.
+const FORK_FIELDS: [(&str, Kind); 6] = [
+    ("baseFeePerGas", Kind::Quantity),
+    ("withdrawalsRoot", Kind::Data),
+    ("blobGasUsed", Kind::Quantity),
+    ("excessBlobGas", Kind::Quantity),
+    ("parentBeaconBlockRoot", Kind::Data),
+    ("requestsHash", Kind::Data),
+];
+
+/// The reason a block could not be fetched or verified.
+#[derive(Debug)]
+pub enum RpcError {
+    /// The request did not complete, or the reply is not JSON.
+    Transport(String),
+    /// The node answered with a JSON-RPC error.
+    Rpc {
+        /// The JSON-RPC error code.
+        code: i64,
+        /// The error message.
+        message: String,
+    },
+    /// The node does not know the block.
+    NotFound {
+        /// The block number requested.
+        number: u64,
+    },
+    /// A header field is missing or not valid hex.
+    InvalidField {
+        /// The JSON name of the field.
+        name: &'static str,
+    },
+    /// The reply to a work request is not a list of hashes.
+    InvalidWork,
+    /// The re-encoded header does not hash to the block hash.
+    HashMismatch {
+        /// The block hash the node returned.
+        expected: [u8; 32],
+        /// The Keccak-256 of the re-encoded header.
+        computed: [u8; 32],
+    },
+    /// The header does not verify.
+    Engine(EngineError),
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RpcError::Transport(error) => write!(f, "request failed: {error}"),
+            RpcError::Rpc { code, message } => write!(f, "node error {code}: {message}"),
+            RpcError::NotFound { number } => write!(f, "block {number} not found"),
+            RpcError::InvalidField { name } => write!(f, "invalid header field {name}"),
+            RpcError::InvalidWork => write!(f, "invalid work package"),
+            RpcError::HashMismatch { expected, computed } => write!(
+                f,
+                "re-encoded header hashes to {}, the node reported {}",
+                hex::encode(computed),
+                hex::encode(expected)
+            ),
+            RpcError::Engine(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+impl From<EngineError> for RpcError {
+    fn from(error: EngineError) -> Self {
+        RpcError::Engine(error)
+    }
+}
+
+impl From<ureq::Error> for RpcError {
+    fn from(error: ureq::Error) -> Self {
+        RpcError::Transport(error.to_string())
+    }
+}
+
+/// Fetches block `block_number` from the node at `rpc_url` and verifies its
+/// seal, generating the cache of its epoch.
+///
+/// Generating a cache takes a while; checking several blocks is faster with
+/// [`verify_block_with`] and one shared [`CacheManager`].
+pub fn verify_block(rpc_url: &str, block_number: u64) -> Result<(), RpcError> {
+    verify_block_with(rpc_url, block_number, &CacheManager::new(1))
+}
+
+/// Fetches block `block_number` from the node at `rpc_url` and verifies its
+/// seal with the caches in `caches`.
+pub fn verify_block_with(
+    rpc_url: &str,
+    block_number: u64,
+    caches: &CacheManager,
+) -> Result<(), RpcError> {
+    let rlp = fetch_header(rpc_url, block_number)?;
+    verify_header(&rlp, caches)?;
+    Ok(())
+}
+
+/// Fetches the header of block `block_number` with `eth_getBlockByNumber`
+/// and returns its RLP encoding, checked against its block hash.
+pub fn fetch_header(rpc_url: &str, block_number: u64) -> Result<Vec<u8>, RpcError> {
+    let params = json!([format!("{block_number:#x}"), false]);
+    match call(rpc_url, "eth_getBlockByNumber", params)? {
+        block if block.is_object() => header_rlp(&block),
+        _ => Err(RpcError::NotFound {
+            number: block_number,
+        }),
+    }
+}
+
+/// Asks the node at `rpc_url` for a block to mine with `eth_getWork`.
+///
+/// Nodes that leave out the block number, the optional fourth entry, are
+/// given the first block of the epoch their seed hash belongs to, which
+/// selects the same cache.
+pub fn get_work(rpc_url: &str) -> Result<Work, RpcError> {
+    let result = call(rpc_url, "eth_getWork", json!([]))?;
+    parse_work(&result, &SeedHashChain::new()).ok_or(RpcError::InvalidWork)
+}
+
+/// Submits a seal of `header_hash` with `eth_submitWork`.
+///
+/// # Returns
+///
+/// `true` if the node accepted the seal.
+pub fn submit_work(
+    rpc_url: &str,
+    nonce: u64,
+    header_hash: &[u8; 32],
+    mix_hash: &[u8; 32],
+) -> Result<bool, RpcError> {
+    let params = json!([
+        format!("{:#018x}", nonce),
+        hex::encode_prefixed(header_hash),
+        hex::encode_prefixed(mix_hash)
+    ]);
+    Ok(call(rpc_url, "eth_submitWork", params)?.as_bool() == Some(true))
+}
+
+/// Calls `method` on the node at `rpc_url` and returns its result.
+fn call(rpc_url: &str, method: &str, params: Value) -> Result<Value, RpcError> {
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": method,
+        "params": params,
+    });
+    let mut reply: Value = ureq::post(rpc_url)
+        .send_json(&request)?
+        .body_mut()
+        .read_json()?;
+    if let Some(error) = reply.get("error") {
+        return Err(RpcError::Rpc {
+            code: error["code"].as_i64().unwrap_or(0),
+            message: error["message"].as_str().unwrap_or_default().to_string(),
+        });
+    }
+    Ok(reply["result"].take())
+}
+
+/// Reads the header hash, seed hash, boundary and optional block number of
+/// an `eth_getWork` reply, finding the epoch of the seed hash in `seeds`
+/// when the number is missing.
+pub(crate) fn parse_work(result: &Value, seeds: &SeedHashChain) -> Option<Work> {
+    let entries = result
+        .as_array()
+        .filter(|entries| (3..=4).contains(&entries.len()))?;
+    let hash = |index: usize| -> Option<[u8; 32]> {
+        let bytes = decode_field(entries[index].as_str()?, Kind::Data)?;
+        bytes.try_into().ok()
+    };
+    let (header_hash, seed_hash, boundary) = (hash(0)?, hash(1)?, hash(2)?);
+    let block_number = match entries.get(3) {
+        Some(number) => {
+            let digits = number.as_str()?.strip_prefix("0x")?;
+            u64::from_str_radix(digits, 16).ok()?
+        }
+        None => seeds.epoch_of(&seed_hash, MAX_EPOCH)? * EPOCH_LENGTH,
+    };
+    Some(Work {
+        header_hash,
+        seed_hash,
+        boundary,
+        block_number,
+    })
+}
+
+/// Encodes the header fields of a JSON block and checks the encoding
+/// against the block's `hash`.
+pub fn header_rlp(block: &Value) -> Result<Vec<u8>, RpcError> {
+    let mut payload = Vec::new();
+    for (index, &(name, kind)) in BASE_FIELDS.iter().chain(&FORK_FIELDS).enumerate() {
+        let Some(value) = block.get(name).filter(|value| !value.is_null()) else {
+            if index < BASE_FIELDS.len() {
+                return Err(RpcError::InvalidField { name });
+            }
+            break;
+        };
+        let bytes = value
+            .as_str()
+            .and_then(|hex| decode_field(hex, kind))
+            .ok_or(RpcError::InvalidField { name })?;
+        payload.extend(rlp::encode_string(&bytes));
+    }
+    let mut rlp = rlp::list_header(payload.len());
+    rlp.extend(payload);
+
+    let expected = block
+        .get("hash")
+        .and_then(Value::as_str)
+        .and_then(|hex| decode_field(hex, Kind::Data))
+        .and_then(|hash| <[u8; 32]>::try_from(hash).ok())
+        .ok_or(RpcError::InvalidField { name: "hash" })?;
+    let computed = keccak256(&rlp);
+    if computed != expected {
+        return Err(RpcError::HashMismatch { expected, computed });
+    }
+    Ok(rlp)
+}
+
+/// Decodes a `0x`-prefixed hex field into the bytes it encodes.
+fn decode_field(hex: &str, kind: Kind) -> Option<Vec<u8>> {
+    let digits = hex.strip_prefix("0x")?;
+    let digits = match kind {
+        Kind::Data => digits.to_string(),
+        // Quantities have no leading zeros and may have an odd length.
+        Kind::Quantity => {
+            let trimmed = digits.trim_start_matches('0');
+            format!("{}{trimmed}", if trimmed.len() % 2 == 1 { "0" } else { "" })
+        }
+    };
+    hex::decode(&digits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ethash::cache::seed_hash;
+    use crate::header::Header;
+    use crate::testutil::tiny_cache_manager;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    /// A London block at height 100 with a difficulty of 1, sealed with
+    /// `mix_hash` and nonce 0.
+    fn block(mix_hash: &[u8]) -> Value {
+        let mut block = json!({
+            "parentHash": hex::encode_prefixed(&[1; 32]),
+            "sha3Uncles": hex::encode_prefixed(&[2; 32]),
+            "miner": hex::encode_prefixed(&[3; 20]),
+            "stateRoot": hex::encode_prefixed(&[4; 32]),
+            "transactionsRoot": hex::encode_prefixed(&[5; 32]),
+            "receiptsRoot": hex::encode_prefixed(&[6; 32]),
+            "logsBloom": hex::encode_prefixed(&[0; 256]),
+            "difficulty": "0x1",
+            "number": "0x64",
+            "gasLimit": "0x7a1200",
+            "gasUsed": "0x0",
+            "timestamp": "0x6553f100",
+            "extraData": "0x",
+            "mixHash": hex::encode_prefixed(mix_hash),
+            "nonce": "0x0000000000000000",
+            "baseFeePerGas": "0x7",
+            "withdrawalsRoot": null,
+            "transactions": [],
+        });
+        let rlp = encode_unchecked(&block);
+        block["hash"] = hex::encode_prefixed(&keccak256(&rlp)).into();
+        block
+    }
+
+    /// Encodes a block without checking its hash.
+    fn encode_unchecked(block: &Value) -> Vec<u8> {
+        let mut block = block.clone();
+        block["hash"] = hex::encode_prefixed(&[0; 32]).into();
+        match header_rlp(&block) {
+            Err(RpcError::HashMismatch { computed, .. }) => {
+                block["hash"] = hex::encode_prefixed(&computed).into();
+                header_rlp(&block).unwrap()
+            }
+            other => panic!("unexpected {other:?}"),
+        }
+    }
+
+    /// Serves one reply to `eth_getBlockByNumber` for block 100, returning
+    /// the server's URL.
+    fn serve(reply: Value) -> String {
+        serve_call(reply, |request| {
+            assert_eq!(request["method"], "eth_getBlockByNumber");
+            assert_eq!(request["params"][0], "0x64");
+        })
+    }
+
+    /// Serves one JSON-RPC reply after passing the request to `check`,
+    /// returning the server's URL.
+    fn serve_call(reply: Value, check: impl FnOnce(&Value) + Send + 'static) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut request = vec![0; length];
+            reader.read_exact(&mut request).unwrap();
+            check(&serde_json::from_slice(&request).unwrap());
+
+            let mut body = reply;
+            body["jsonrpc"] = "2.0".into();
+            body["id"] = 1.into();
+            let body = body.to_string();
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                body.len()
+            )
+            .unwrap();
+        });
+        url
+    }
+
+    #[test]
+    fn test_header_rlp_matches_the_header_encoding() {
+        let block = block(&[9; 32]);
+        let rlp = header_rlp(&block).unwrap();
+        let header = Header::decode(&rlp).unwrap();
+        assert_eq!(header.number, 100);
+        assert_eq!(header.timestamp, 0x6553f100);
+        assert_eq!(header.mix_hash, [9; 32]);
+        assert_eq!(header.field_count(), 16);
+
+        let mut wrong = block.clone();
+        wrong["gasUsed"] = "0x1".into();
+        assert!(matches!(
+            header_rlp(&wrong),
+            Err(RpcError::HashMismatch { .. })
+        ));
+        let mut missing = block;
+        missing.as_object_mut().unwrap().remove("nonce");
+        assert!(matches!(
+            header_rlp(&missing),
+            Err(RpcError::InvalidField { name: "nonce" })
+        ));
+        assert_eq!(decode_field("0x007", Kind::Quantity), Some(vec![7]));
+        assert_eq!(decode_field("0x0", Kind::Quantity), Some(vec![]));
+        assert_eq!(decode_field("0x007", Kind::Data), None);
+    }
+
+    #[test]
+    fn test_verify_block_fetches_over_json_rpc() {
+        let caches = tiny_cache_manager(1);
+        let pre_hash = Header::decode(&encode_unchecked(&block(&[0; 32])))
+            .unwrap()
+            .pre_hash();
+        let (mix_hash, _) = caches.get(0).hash(&pre_hash, 100, 0);
+
+        let url = serve(json!({ "result": block(&mix_hash) }));
+        verify_block_with(&url, 100, &caches).unwrap();
+
+        let url = serve(json!({ "result": block(&[0; 32]) }));
+        assert!(matches!(
+            verify_block_with(&url, 100, &caches),
+            Err(RpcError::Engine(EngineError::InvalidSeal(_)))
+        ));
+        let url = serve(json!({ "result": null }));
+        assert!(matches!(
+            verify_block_with(&url, 100, &caches),
+            Err(RpcError::NotFound { number: 100 })
+        ));
+        let url = serve(json!({ "error": { "code": -32000, "message": "pruned" } }));
+        assert!(matches!(
+            verify_block_with(&url, 100, &caches),
+            Err(RpcError::Rpc { code: -32000, .. })
+        ));
+    }
+
+    #[test]
+    fn test_get_and_submit_work() {
+        let work = json!([
+            hex::encode_prefixed(&[1; 32]),
+            hex::encode_prefixed(&seed_hash(2)),
+            hex::encode_prefixed(&[0xff; 32]),
+            "0xea61"
+        ]);
+        let url = serve_call(json!({ "result": work }), |request| {
+            assert_eq!(request["method"], "eth_getWork");
+        });
+        let work = get_work(&url).unwrap();
+        assert_eq!(work.header_hash, [1; 32]);
+        assert_eq!(work.boundary, [0xff; 32]);
+        assert_eq!(work.block_number, 60_001);
+
+        // Without a block number the seed hash gives the epoch.
+        let seeds = SeedHashChain::new();
+        let short = json!([
+            hex::encode_prefixed(&[1; 32]),
+            hex::encode_prefixed(&seed_hash(3)),
+            hex::encode_prefixed(&[0xff; 32])
+        ]);
+        assert_eq!(parse_work(&short, &seeds).unwrap().block_number, 90_000);
+        let unknown = json!([
+            hex::encode_prefixed(&[1; 32]),
+            hex::encode_prefixed(&[7; 32]),
+            hex::encode_prefixed(&[0xff; 32])
+        ]);
+        assert_eq!(parse_work(&unknown, &seeds), None);
+        let url = serve_call(
+            json!({ "result": [hex::encode_prefixed(&[1; 32])] }),
+            |_| {},
+        );
+        assert!(matches!(get_work(&url), Err(RpcError::InvalidWork)));
+
+        let url = serve_call(json!({ "result": true }), |request| {
+            assert_eq!(request["method"], "eth_submitWork");
+            assert_eq!(request["params"][0], "0x000000000000002a");
+            assert_eq!(request["params"][2], hex::encode_prefixed(&[9; 32]));
+        });
+        assert!(submit_work(&url, 42, &[1; 32], &[9; 32]).unwrap());
+        let url = serve_call(json!({ "result": false }), |_| {});
+        assert!(!submit_work(&url, 42, &[1; 32], &[9; 32]).unwrap());
+    }
+}
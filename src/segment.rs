@@ -0,0 +1,378 @@
+//! Verifies the seals of a chain segment read from export files.
+//!
+//! Two formats are read. Raw RLP exports, as `geth export` writes them, are
+//! a concatenation of RLP blocks, each a list whose first item is the
+//! header; a concatenation of bare headers is accepted too. Era1 archives
+//! are e2store files whose `CompressedHeader` entries hold snappy-framed
+//! header RLP; reading them needs the `era1` feature.
+//!
+//! [`verify_segment`] checks every header in a block range with one shared
+//! [`CacheManager`], so each epoch's cache is generated once, and stops at
+//! the first failure.
+
+use std::fmt;
+use std::io::{self, Read};
+use std::ops::Range;
+
+use crate::engine::{verify_with, EngineError};
+use crate::ethash::manager::CacheManager;
+use crate::header::{rlp, Header, HeaderError};
+
+/// The reason a segment failed verification.
+#[derive(Debug)]
+pub enum SegmentError {
+    /// The export file could not be read or is malformed.
+    Read(io::Error),
+    /// A header does not decode.
+    InvalidHeader {
+        /// The position of the header in the file, counting from 0.
+        index: u64,
+        /// Why it does not decode.
+        error: HeaderError,
+    },
+    /// A header's seal does not verify.
+    InvalidSeal {
+        /// The block number of the header.
+        number: u64,
+        /// Why it does not verify.
+        error: EngineError,
+    },
+}
+
+impl fmt::Display for SegmentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SegmentError::Read(error) => write!(f, "reading the export failed: {error}"),
+            SegmentError::InvalidHeader { index, error } => {
+                write!(f, "header {index} in the export: {error}")
+            }
+            SegmentError::InvalidSeal { number, error } => write!(f, "block {number}: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for SegmentError {}
+
+impl From<io::Error> for SegmentError {
+    fn from(error: io::Error) -> Self {
+        SegmentError::Read(error)
+    }
+}
+
+/// What [`verify_segment`] checked.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SegmentReport {
+    /// The number of seals verified.
+    pub verified: u64,
+    /// The first and last block numbers verified, if any were.
+    pub blocks: Option<(u64, u64)>,
+}
+
+/// Verifies the seal of every header in `headers` whose number is in
+/// `range`, with the caches of their epochs in `caches`.
+///
+/// Headers outside the range are skipped, and reading stops at the first
+/// header past its end, so exports are expected in ascending order.
+///
+/// # Returns
+///
+/// A report of the headers verified, or the first failure.
+pub fn verify_segment(
+    headers: impl IntoIterator<Item = io::Result<Vec<u8>>>,
+    range: Range<u64>,
+    caches: &CacheManager,
+) -> Result<SegmentReport, SegmentError> {
+    let mut report = SegmentReport::default();
+    for (index, rlp) in (0..).zip(headers) {
+        let header =
+            Header::decode(&rlp?).map_err(|error| SegmentError::InvalidHeader { index, error })?;
+        if header.number >= range.end {
+            break;
+        }
+        if header.number < range.start {
+            continue;
+        }
+        verify_with(caches, &header).map_err(|error| SegmentError::InvalidSeal {
+            number: header.number,
+            error,
+        })?;
+        report.verified += 1;
+        let first = report.blocks.map_or(header.number, |(first, _)| first);
+        report.blocks = Some((first, header.number));
+    }
+    Ok(report)
+}
+
+/// Reads the headers of a raw RLP export, one block or header at a time.
+pub struct RlpExportReader<R> {
+    reader: R,
+}
+
+impl<R: Read> RlpExportReader<R> {
+    /// Creates a reader over an RLP export.
+    pub fn new(reader: R) -> Self {
+        RlpExportReader { reader }
+    }
+
+    /// Reads the next top-level RLP item, or `None` at the end of input.
+    fn read_item(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut prefix = [0];
+        if self.reader.read(&mut prefix)? == 0 {
+            return Ok(None);
+        }
+        let mut item = prefix.to_vec();
+        let len = match prefix[0] {
+            0x00..=0x7f => 0,
+            0x80..=0xb7 => (prefix[0] - 0x80) as usize,
+            0xc0..=0xf7 => (prefix[0] - 0xc0) as usize,
+            _ => {
+                let len_len = (prefix[0] - if prefix[0] >= 0xf8 { 0xf7 } else { 0xb7 }) as usize;
+                if len_len > 8 {
+                    return Err(invalid_data("RLP length does not fit in 64 bits"));
+                }
+                let mut len_bytes = vec![0; len_len];
+                self.reader.read_exact(&mut len_bytes)?;
+                item.extend_from_slice(&len_bytes);
+                len_bytes.iter().fold(0usize, |n, &b| n << 8 | b as usize)
+            }
+        };
+        read_payload(&mut self.reader, len, &mut item)?;
+        Ok(Some(item))
+    }
+}
+
+impl<R: Read> Iterator for RlpExportReader<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = match self.read_item() {
+            Ok(item) => item?,
+            Err(error) => return Some(Err(error)),
+        };
+        // A block is a list whose first item, the header, is a list too; a
+        // bare header starts with a string.
+        let header = match rlp::item(&item) {
+            Ok((rlp::Item::List(payload), _)) => match rlp::item(payload) {
+                Ok((rlp::Item::List(_), rest)) => payload[..payload.len() - rest.len()].to_vec(),
+                Ok((rlp::Item::String(_), _)) => item,
+                Err(error) => return Some(Err(invalid_data(error))),
+            },
+            Ok((rlp::Item::String(_), _)) => {
+                return Some(Err(invalid_data("export items are lists")))
+            }
+            Err(error) => return Some(Err(invalid_data(error))),
+        };
+        Some(Ok(header))
+    }
+}
+
+/// Reads the headers of an era1 archive.
+#[cfg(feature = "era1")]
+pub struct Era1Reader<R> {
+    reader: R,
+    started: bool,
+}
+
+/// The e2store entry type of the version record every file starts with.
+#[cfg(feature = "era1")]
+const ERA1_VERSION: u16 = 0x3265;
+
+/// The e2store entry type of a snappy-framed header.
+#[cfg(feature = "era1")]
+const ERA1_COMPRESSED_HEADER: u16 = 0x03;
+
+#[cfg(feature = "era1")]
+impl<R: Read> Era1Reader<R> {
+    /// Creates a reader over an era1 archive.
+    pub fn new(reader: R) -> Self {
+        Era1Reader {
+            reader,
+            started: false,
+        }
+    }
+
+    /// Reads the next e2store entry's type and data, or `None` at the end
+    /// of input.
+    fn read_entry(&mut self) -> io::Result<Option<(u16, Vec<u8>)>> {
+        let mut header = [0; 8];
+        let read = self.reader.read(&mut header)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        self.reader.read_exact(&mut header[read..])?;
+        let kind = u16::from_le_bytes([header[0], header[1]]);
+        let len = u32::from_le_bytes(header[2..6].try_into().unwrap()) as usize;
+        if header[6..] != [0, 0] {
+            return Err(invalid_data("e2store reserved bytes are not zero"));
+        }
+        let mut data = Vec::new();
+        read_payload(&mut self.reader, len, &mut data)?;
+        Ok(Some((kind, data)))
+    }
+}
+
+#[cfg(feature = "era1")]
+impl<R: Read> Iterator for Era1Reader<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (kind, data) = match self.read_entry() {
+                Ok(entry) => entry?,
+                Err(error) => return Some(Err(error)),
+            };
+            if !self.started {
+                if kind != ERA1_VERSION {
+                    return Some(Err(invalid_data(
+                        "an era1 file starts with a version entry",
+                    )));
+                }
+                self.started = true;
+                continue;
+            }
+            if kind == ERA1_COMPRESSED_HEADER {
+                let mut header = Vec::new();
+                return Some(
+                    snap::read::FrameDecoder::new(&data[..])
+                        .read_to_end(&mut header)
+                        .map(|_| header),
+                );
+            }
+        }
+    }
+}
+
+/// Appends `len` bytes from `reader` to `out`, growing `out` only as data
+/// arrives, so a corrupt length cannot allocate more than the input holds.
+fn read_payload(reader: &mut impl Read, len: usize, out: &mut Vec<u8>) -> io::Result<()> {
+    let start = out.len();
+    reader.take(len as u64).read_to_end(out)?;
+    if out.len() - start < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+fn invalid_data(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::tests::encode_header;
+    use crate::header::{seal_header, split_seal};
+    use crate::keccak::keccak256;
+    use crate::testutil::tiny_cache_manager;
+
+    fn caches() -> CacheManager {
+        tiny_cache_manager(2)
+    }
+
+    /// Returns sealed headers for blocks `numbers`, with a difficulty of 1.
+    fn sealed(caches: &CacheManager, numbers: Range<u64>) -> Vec<Vec<u8>> {
+        numbers
+            .map(|number| {
+                let (unsealed, _, _) = split_seal(&encode_header(number, 1, 0, [0; 32])).unwrap();
+                let pre_hash = keccak256(&unsealed);
+                let (mix_hash, _) = caches.for_block(number).hash(&pre_hash, number, 0);
+                seal_header(&unsealed, 0, mix_hash.try_into().unwrap()).unwrap()
+            })
+            .collect()
+    }
+
+    /// Wraps a header in a block with empty transaction and ommer lists.
+    fn block(header: &[u8]) -> Vec<u8> {
+        let mut payload = header.to_vec();
+        payload.extend([0xc0, 0xc0]);
+        let mut out = rlp::list_header(payload.len());
+        out.extend(payload);
+        out
+    }
+
+    #[test]
+    fn test_verify_segment_reads_rlp_exports() {
+        let caches = caches();
+        let headers = sealed(&caches, 10..15);
+        let mut export: Vec<u8> = headers.iter().flat_map(|h| block(h)).collect();
+        // Bare headers are accepted as well.
+        export.extend(sealed(&caches, 15..16).concat());
+
+        let read: Vec<Vec<u8>> = RlpExportReader::new(&export[..])
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(read[..5], headers[..]);
+        assert_eq!(read.len(), 6);
+
+        let report = verify_segment(RlpExportReader::new(&export[..]), 11..14, &caches).unwrap();
+        assert_eq!(
+            report,
+            SegmentReport {
+                verified: 3,
+                blocks: Some((11, 13)),
+            }
+        );
+        let report = verify_segment(RlpExportReader::new(&export[..]), 0..100, &caches).unwrap();
+        assert_eq!(report.verified, 6);
+
+        // Break the seal of block 12 by changing its nonce.
+        let (unsealed, _, mix_hash) = split_seal(&headers[2]).unwrap();
+        let broken = seal_header(&unsealed, 1, mix_hash).unwrap();
+        let mut export: Vec<u8> = headers.iter().flat_map(|h| block(h)).collect();
+        let at = block(&headers[0]).len() * 2;
+        export.splice(at..at + block(&headers[2]).len(), block(&broken));
+        assert!(matches!(
+            verify_segment(RlpExportReader::new(&export[..]), 0..100, &caches),
+            Err(SegmentError::InvalidSeal { number: 12, .. })
+        ));
+
+        // A truncated export is a read error once the range reaches it.
+        assert!(matches!(
+            verify_segment(
+                RlpExportReader::new(&export[..export.len() - 1]),
+                13..100,
+                &caches
+            ),
+            Err(SegmentError::Read(_))
+        ));
+    }
+
+    #[cfg(feature = "era1")]
+    #[test]
+    fn test_era1_reader_decompresses_headers() {
+        use std::io::Write;
+
+        let entry = |kind: u16, data: &[u8]| {
+            let mut out = kind.to_le_bytes().to_vec();
+            out.extend((data.len() as u32).to_le_bytes());
+            out.extend([0, 0]);
+            out.extend_from_slice(data);
+            out
+        };
+        let compress = |data: &[u8]| {
+            let mut encoder = snap::write::FrameEncoder::new(Vec::new());
+            encoder.write_all(data).unwrap();
+            encoder.into_inner().unwrap()
+        };
+
+        let caches = caches();
+        let headers = sealed(&caches, 20..23);
+        let mut archive = entry(ERA1_VERSION, &[]);
+        for header in &headers {
+            archive.extend(entry(ERA1_COMPRESSED_HEADER, &compress(header)));
+            // Bodies, receipts and total difficulties are skipped.
+            archive.extend(entry(0x04, &compress(&[0xc2, 0xc0, 0xc0])));
+            archive.extend(entry(0x06, &[0; 32]));
+        }
+        archive.extend(entry(0x07, &[0; 32]));
+
+        let read: Vec<Vec<u8>> = Era1Reader::new(&archive[..])
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(read, headers);
+        let report = verify_segment(Era1Reader::new(&archive[..]), 0..100, &caches).unwrap();
+        assert_eq!(report.blocks, Some((20, 22)));
+
+        assert!(Era1Reader::new(&archive[8..]).next().unwrap().is_err());
+    }
+}
@@ -0,0 +1,207 @@
+//! Pool share classification and variable share difficulty.
+//!
+//! A pool checks every submitted hash against two targets: the miner's own
+//! share boundary, which is easy to meet, and the network's block boundary.
+//! [`classify_share`] compares one final hash against both, so a pool
+//! hashes each submission once whatever it turns out to be. [`Vardiff`]
+//! keeps each miner's share boundary at a rate of a few shares a minute as
+//! its hashrate changes.
+//!
+//! A pool that holds no DAG takes shares as [`ShareBundle`]s instead: the
+//! seal with the proof of its DAG accesses, which a
+//! [`StatelessVerifier`](crate::stateless::StatelessVerifier) trusting the
+//! epoch's dataset root classifies one at a time or in batches.
+
+use crate::chain::Chain;
+use crate::ethash::buffer::DagBuffer;
+use crate::ethash::merkle::DagMerkleTree;
+use crate::hashrate::share_boundary;
+use crate::progpow::proof::AccessProof;
+use crate::progpow::verify::Seal;
+use crate::target::hash_meets_target;
+
+/// What a submitted hash is worth.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ShareKind {
+    /// The hash meets neither boundary.
+    Invalid,
+    /// The hash meets the share boundary only.
+    Share,
+    /// The hash meets the block boundary, so it seals a block. It counts as
+    /// a share too.
+    Block,
+}
+
+/// Classifies a final hash against a miner's share boundary and the
+/// network's block boundary.
+///
+/// # Arguments
+///
+/// * `hash_output` - The final hash of the submitted nonce.
+/// * `share_boundary` - The 32-byte big-endian boundary of the miner's shares.
+/// * `block_boundary` - The 32-byte big-endian boundary of the block.
+///
+/// # Returns
+///
+/// [`ShareKind::Block`] if the hash meets the block boundary, whatever the
+/// share boundary, [`ShareKind::Share`] if it meets only the share boundary,
+/// and [`ShareKind::Invalid`] otherwise.
+pub fn classify_share(
+    hash_output: &[u8; 32],
+    share_boundary: &[u8; 32],
+    block_boundary: &[u8; 32],
+) -> ShareKind {
+    if hash_meets_target(hash_output, block_boundary) {
+        ShareKind::Block
+    } else if hash_meets_target(hash_output, share_boundary) {
+        ShareKind::Share
+    } else {
+        ShareKind::Invalid
+    }
+}
+
+/// A share packaged with the proof of its DAG accesses, for a pool that
+/// verifies shares against the epoch's dataset root.
+///
+/// The seal's boundary is the block boundary; the share boundary is the
+/// pool's, passed when the bundle is verified.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ShareBundle {
+    /// The submitted seal.
+    pub seal: Seal,
+    /// The proof of the DAG items the seal's hash reads.
+    pub proof: AccessProof,
+}
+
+impl ShareBundle {
+    /// Packages a seal found on `chain` with the proof of its accesses, on
+    /// the miner that holds the dataset.
+    ///
+    /// # Arguments
+    ///
+    /// * `chain` - The chain the seal is for.
+    /// * `tree` - The Merkle tree of `dag`.
+    /// * `dag` - The dataset of the seal's epoch.
+    /// * `seal` - The seal to submit.
+    pub fn package(chain: &Chain, tree: &DagMerkleTree, dag: &dyn DagBuffer, seal: Seal) -> Self {
+        ShareBundle {
+            proof: chain.prove_access(tree, dag, &seal),
+            seal,
+        }
+    }
+}
+
+/// Returns the share difficulty a miner of `hashrate` hashes per second
+/// meets once every `share_interval` seconds on average, at least 1.
+pub fn share_difficulty_for_rate(hashrate: f64, share_interval: f64) -> u64 {
+    (hashrate * share_interval).clamp(1.0, u64::MAX as f64) as u64
+}
+
+/// A variable share difficulty policy.
+///
+/// After each retarget window the difficulty is scaled by how far the
+/// observed share rate was from the target, by at most
+/// [`max_step`](Self::max_step) either way so a short burst of luck does not
+/// swing it, and kept within the configured bounds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vardiff {
+    /// The seconds between shares to aim for.
+    pub share_interval: f64,
+    /// The lowest share difficulty handed out.
+    pub min_difficulty: u64,
+    /// The highest share difficulty handed out.
+    pub max_difficulty: u64,
+    /// The largest factor one retarget changes the difficulty by.
+    pub max_step: f64,
+}
+
+impl Default for Vardiff {
+    /// One share every 15 seconds, changing by at most 4x per retarget.
+    fn default() -> Self {
+        Vardiff {
+            share_interval: 15.0,
+            min_difficulty: 1,
+            max_difficulty: u64::MAX,
+            max_step: 4.0,
+        }
+    }
+}
+
+impl Vardiff {
+    /// Returns the share difficulty to use after a miner submitted `shares`
+    /// shares of `difficulty` in `elapsed` seconds.
+    ///
+    /// A window without shares lowers the difficulty by the largest step.
+    pub fn retarget(&self, difficulty: u64, shares: u64, elapsed: f64) -> u64 {
+        let expected = elapsed / self.share_interval;
+        let factor = if shares == 0 {
+            1.0 / self.max_step
+        } else {
+            (shares as f64 / expected).clamp(1.0 / self.max_step, self.max_step)
+        };
+        let retargeted = (difficulty as f64 * factor).clamp(1.0, u64::MAX as f64) as u64;
+        retargeted.clamp(self.min_difficulty, self.max_difficulty)
+    }
+
+    /// Returns the share boundary after a retarget; see
+    /// [`retarget`](Self::retarget).
+    pub fn retarget_boundary(&self, difficulty: u64, shares: u64, elapsed: f64) -> [u8; 32] {
+        share_boundary(self.retarget(difficulty, shares, elapsed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_share() {
+        let share = share_boundary(16);
+        let block = share_boundary(1 << 20);
+        let hash = |first: u8| {
+            let mut hash = [0xff; 32];
+            hash[0] = first;
+            hash
+        };
+        let mut lucky = [0xff; 32];
+        lucky[..3].fill(0);
+        assert_eq!(classify_share(&lucky, &share, &block), ShareKind::Block);
+        assert_eq!(
+            classify_share(&hash(0x0f), &share, &block),
+            ShareKind::Share
+        );
+        assert_eq!(
+            classify_share(&hash(0x10), &share, &block),
+            ShareKind::Invalid
+        );
+        // A hash meeting the block boundary is a block even if the share
+        // boundary is the harder one.
+        assert_eq!(classify_share(&lucky, &block, &share), ShareKind::Block);
+        assert_eq!(classify_share(&share, &share, &block), ShareKind::Share);
+    }
+
+    #[test]
+    fn test_vardiff_retargets_towards_the_share_interval() {
+        assert_eq!(share_difficulty_for_rate(1e9, 15.0), 15_000_000_000);
+        assert_eq!(share_difficulty_for_rate(0.0, 15.0), 1);
+
+        let vardiff = Vardiff {
+            min_difficulty: 100,
+            max_difficulty: 1_000_000,
+            ..Vardiff::default()
+        };
+        // Twice the target rate doubles the difficulty.
+        assert_eq!(vardiff.retarget(1000, 8, 60.0), 2000);
+        assert_eq!(vardiff.retarget(1000, 4, 60.0), 1000);
+        // Steps are limited to 4x, and the result to the bounds.
+        assert_eq!(vardiff.retarget(1000, 400, 60.0), 4000);
+        assert_eq!(vardiff.retarget(1000, 0, 60.0), 250);
+        assert_eq!(vardiff.retarget(200, 0, 60.0), 100);
+        assert_eq!(vardiff.retarget(900_000, 40, 60.0), 1_000_000);
+        assert_eq!(
+            vardiff.retarget_boundary(1000, 8, 60.0),
+            share_boundary(2000)
+        );
+    }
+}
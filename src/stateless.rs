@@ -0,0 +1,492 @@
+//! Seal verification for clients that hold no caches.
+//!
+//! A bridge contract or a mobile client may follow many epochs but cannot
+//! generate and keep a light cache for each. A [`StatelessVerifier`] keeps
+//! one [`Anchor`] per epoch instead: the epoch's trusted dataset root, 32
+//! bytes, against which seals are checked with an
+//! [`AccessProof`](crate::progpow::proof::AccessProof) from the prover, or,
+//! for the few epochs it can afford one, a light cache that needs no proof.
+//! Hashing follows the [`Chain`]'s period and variant either way.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use std::thread;
+
+use crate::chain::Chain;
+use crate::ethash::buffer::DagBuffer;
+use crate::ethash::manager::EpochCache;
+use crate::ethash::merkle::DagMerkleTree;
+use crate::progpow::proof::{
+    prove_with, verify_cached_words, verify_loads_with, verify_proof_with, AccessProof, ProofError,
+};
+use crate::progpow::verify::{check_seal, check_seal_with, Seal, SealError};
+use crate::share::{classify_share, ShareBundle, ShareKind};
+
+/// What a [`StatelessVerifier`] trusts for one epoch.
+#[derive(Clone)]
+pub enum Anchor {
+    /// The Merkle root of the epoch's dataset; seals need a proof.
+    Root([u8; 32]),
+    /// The epoch's light cache; seals need no proof.
+    Cache(Arc<EpochCache>),
+}
+
+/// The reason a [`StatelessVerifier`] rejected a seal.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StatelessError {
+    /// No anchor is trusted for the seal's epoch.
+    UnknownEpoch {
+        /// The seal's epoch.
+        epoch: u64,
+    },
+    /// The epoch is anchored by its root, and the seal came without a proof.
+    MissingProof {
+        /// The seal's epoch.
+        epoch: u64,
+    },
+    /// The proof does not prove the hash's DAG accesses.
+    NotProven(ProofError),
+    /// The hash is proven, or computed from the cache, and the seal fails.
+    Seal(SealError),
+}
+
+impl fmt::Display for StatelessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatelessError::UnknownEpoch { epoch } => {
+                write!(f, "no root or cache is trusted for epoch {epoch}")
+            }
+            StatelessError::MissingProof { epoch } => {
+                write!(f, "epoch {epoch} needs a DAG access proof")
+            }
+            StatelessError::NotProven(error) => write!(f, "{error}"),
+            StatelessError::Seal(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for StatelessError {}
+
+impl From<ProofError> for StatelessError {
+    fn from(error: ProofError) -> Self {
+        match error {
+            ProofError::Seal(error) => StatelessError::Seal(error),
+            error => StatelessError::NotProven(error),
+        }
+    }
+}
+
+/// A verifier of one chain's seals holding a root or a cache per epoch.
+///
+/// Anchors are added and removed through `&self`, so one verifier serves
+/// every thread while roots arrive, for example from a bridge's checkpoint
+/// contract.
+pub struct StatelessVerifier {
+    chain: Chain,
+    anchors: RwLock<HashMap<u64, Anchor>>,
+}
+
+impl StatelessVerifier {
+    /// Creates a verifier for `chain` trusting no epoch yet.
+    pub fn new(chain: Chain) -> Self {
+        StatelessVerifier {
+            chain,
+            anchors: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Creates a verifier for `chain` trusting the dataset roots `roots`,
+    /// by epoch.
+    pub fn with_roots(chain: Chain, roots: impl IntoIterator<Item = (u64, [u8; 32])>) -> Self {
+        let verifier = Self::new(chain);
+        for (epoch, root) in roots {
+            verifier.trust_root(epoch, root);
+        }
+        verifier
+    }
+
+    /// Returns the chain seals are verified for.
+    pub fn chain(&self) -> &Chain {
+        &self.chain
+    }
+
+    /// Trusts `root` as the dataset root of `epoch`, replacing its anchor.
+    pub fn trust_root(&self, epoch: u64, root: [u8; 32]) {
+        self.anchors
+            .write()
+            .unwrap()
+            .insert(epoch, Anchor::Root(root));
+    }
+
+    /// Trusts `cache`, one of this chain's caches, for its epoch, replacing
+    /// the epoch's anchor.
+    pub fn trust_cache(&self, cache: Arc<EpochCache>) {
+        self.anchors
+            .write()
+            .unwrap()
+            .insert(cache.epoch(), Anchor::Cache(cache));
+    }
+
+    /// Stops trusting anything for `epoch`.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the epoch had an anchor.
+    pub fn forget(&self, epoch: u64) -> bool {
+        self.anchors.write().unwrap().remove(&epoch).is_some()
+    }
+
+    /// Returns the anchor trusted for `epoch`, if any.
+    pub fn anchor(&self, epoch: u64) -> Option<Anchor> {
+        self.anchors.read().unwrap().get(&epoch).cloned()
+    }
+
+    /// Verifies a seal against the anchor of its epoch.
+    ///
+    /// # Arguments
+    ///
+    /// * `seal` - The seal to verify.
+    /// * `proof` - The proof of the seal's DAG accesses, which an epoch
+    ///   anchored by its root requires and one anchored by a cache ignores.
+    ///
+    /// # Returns
+    ///
+    /// The final hash, or the [`StatelessError`] describing the first check
+    /// that failed.
+    pub fn verify_seal(
+        &self,
+        seal: &Seal,
+        proof: Option<&AccessProof>,
+    ) -> Result<Vec<u8>, StatelessError> {
+        let epoch = self.chain.epoch(seal.block_number);
+        match self.anchor(epoch) {
+            None => Err(StatelessError::UnknownEpoch { epoch }),
+            Some(Anchor::Cache(cache)) => {
+                let (mix_hash, final_hash) =
+                    self.chain
+                        .hash(&cache, &seal.header_hash, seal.block_number, seal.nonce);
+                check_seal(seal, &mix_hash, &final_hash).map_err(StatelessError::Seal)?;
+                Ok(final_hash)
+            }
+            Some(Anchor::Root(root)) => {
+                let proof = proof.ok_or(StatelessError::MissingProof { epoch })?;
+                let size = self.chain.dataset_size(epoch);
+                let hash = |size, c_dag: &[u32], lookup: &dyn Fn(u32) -> Vec<u8>| {
+                    self.chain.hash_with_dag(
+                        size,
+                        c_dag,
+                        lookup,
+                        &seal.header_hash,
+                        seal.block_number,
+                        seal.nonce,
+                    )
+                };
+                Ok(verify_proof_with(seal, size, &root, proof, &hash)?)
+            }
+        }
+    }
+
+    /// Classifies a pool share against its epoch's anchor; see
+    /// [`verify_shares`](Self::verify_shares).
+    pub fn verify_share(
+        &self,
+        bundle: &ShareBundle,
+        share_boundary: &[u8; 32],
+    ) -> Result<ShareKind, StatelessError> {
+        self.verify_shares(std::slice::from_ref(bundle), share_boundary)
+            .pop()
+            .unwrap()
+    }
+
+    /// Classifies a batch of pool shares against their epochs' anchors,
+    /// hashing on every available core.
+    ///
+    /// The cached DAG words are the same in every proof of an epoch, so
+    /// their branch is checked once per epoch rather than once per share.
+    ///
+    /// # Arguments
+    ///
+    /// * `bundles` - The shares with their proofs.
+    /// * `share_boundary` - The 32-byte big-endian boundary of the shares.
+    ///
+    /// # Returns
+    ///
+    /// For each bundle, in order, what its proven hash is worth, as
+    /// [`classify_share`] tells against `share_boundary` and the seal's own
+    /// boundary, or the [`StatelessError`] describing why it is not proven.
+    pub fn verify_shares(
+        &self,
+        bundles: &[ShareBundle],
+        share_boundary: &[u8; 32],
+    ) -> Vec<Result<ShareKind, StatelessError>> {
+        let anchors = self.anchors.read().unwrap().clone();
+        let mut proven: HashMap<u64, &[u32]> = HashMap::new();
+        let checked: Vec<Result<&Anchor, StatelessError>> = bundles
+            .iter()
+            .map(|bundle| {
+                let epoch = self.chain.epoch(bundle.seal.block_number);
+                let anchor = anchors
+                    .get(&epoch)
+                    .ok_or(StatelessError::UnknownEpoch { epoch })?;
+                if let Anchor::Root(root) = anchor {
+                    if proven.get(&epoch) != Some(&&bundle.proof.c_dag[..]) {
+                        verify_cached_words(self.chain.dataset_size(epoch), root, &bundle.proof)?;
+                        proven.insert(epoch, &bundle.proof.c_dag);
+                    }
+                }
+                Ok(anchor)
+            })
+            .collect();
+
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let per_thread = bundles.len().div_ceil(threads).max(1);
+        thread::scope(|scope| {
+            let handles: Vec<_> = checked
+                .chunks(per_thread)
+                .zip(bundles.chunks(per_thread))
+                .map(|(checked, bundles)| {
+                    scope.spawn(move || {
+                        checked
+                            .iter()
+                            .zip(bundles)
+                            .map(|(anchor, bundle)| {
+                                self.classify(anchor.clone()?, bundle, share_boundary)
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        })
+    }
+
+    /// Hashes a share whose cached words, if it needs them, are proven, and
+    /// classifies the hash.
+    fn classify(
+        &self,
+        anchor: &Anchor,
+        bundle: &ShareBundle,
+        share_boundary: &[u8; 32],
+    ) -> Result<ShareKind, StatelessError> {
+        let seal = &bundle.seal;
+        // Every hash is worth something or nothing; only the mix must match.
+        let any_hash = |_: &Seal, _: &[u8; 32]| true;
+        let final_hash = match anchor {
+            Anchor::Cache(cache) => {
+                let (mix_hash, final_hash) =
+                    self.chain
+                        .hash(cache, &seal.header_hash, seal.block_number, seal.nonce);
+                check_seal_with(seal, &mix_hash, &final_hash, &any_hash)
+                    .map_err(StatelessError::Seal)?;
+                final_hash
+            }
+            Anchor::Root(root) => {
+                let size = self.chain.dataset_size(self.chain.epoch(seal.block_number));
+                let hash = |size, c_dag: &[u32], lookup: &dyn Fn(u32) -> Vec<u8>| {
+                    self.chain.hash_with_dag(
+                        size,
+                        c_dag,
+                        lookup,
+                        &seal.header_hash,
+                        seal.block_number,
+                        seal.nonce,
+                    )
+                };
+                verify_loads_with(seal, size, root, &bundle.proof, &hash, &any_hash)?
+            }
+        };
+        let final_hash: [u8; 32] = final_hash.try_into().unwrap();
+        Ok(classify_share(&final_hash, share_boundary, &seal.boundary))
+    }
+}
+
+impl Chain {
+    /// Proves the DAG accesses of a seal's hash on this chain, for a
+    /// [`StatelessVerifier`] trusting the dataset's root; see
+    /// [`prove_access`](crate::progpow::proof::prove_access).
+    ///
+    /// # Arguments
+    ///
+    /// * `tree` - The Merkle tree of `dag`.
+    /// * `dag` - The dataset of the seal's epoch.
+    /// * `seal` - The seal to prove.
+    pub fn prove_access(
+        &self,
+        tree: &DagMerkleTree,
+        dag: &dyn DagBuffer,
+        seal: &Seal,
+    ) -> AccessProof {
+        prove_with(tree, dag, &|size, c_dag, lookup| {
+            self.hash_with_dag(
+                size,
+                c_dag,
+                lookup,
+                &seal.header_hash,
+                seal.block_number,
+                seal.nonce,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ethash::dataset::generate_dataset;
+    use crate::testutil::header_hash;
+
+    /// A chain with tiny caches and datasets, epochs of 100 blocks and
+    /// periods of 10.
+    fn small_chain() -> Chain {
+        Chain {
+            name: "bridgecoin".to_string(),
+            period_length: 10,
+            epoch_length: 100,
+            cache_bytes_init: 1024,
+            cache_bytes_growth: 128,
+            dataset_bytes_init: 1 << 15,
+            dataset_bytes_growth: 1 << 10,
+            ..Chain::ethereum()
+        }
+    }
+
+    /// Returns the seal of a header on `chain`, whatever its final hash.
+    fn seal_on(
+        chain: &Chain,
+        cache: &EpochCache,
+        header_hash: [u8; 32],
+        block_number: u64,
+        nonce: u64,
+        boundary: [u8; 32],
+    ) -> Seal {
+        let (mix_hash, _) = chain.hash(cache, &header_hash, block_number, nonce);
+        Seal {
+            header_hash,
+            block_number,
+            nonce,
+            mix_hash: mix_hash.try_into().unwrap(),
+            boundary,
+        }
+    }
+
+    #[test]
+    fn test_stateless_verifier_checks_roots_and_caches() {
+        let chain = small_chain();
+        let caches = chain.cache_manager(2);
+        let (old, head) = (caches.get(1), caches.get(2));
+        let dataset = generate_dataset(head.cache_words(), head.size());
+        let tree = DagMerkleTree::build(&dataset);
+
+        let verifier = StatelessVerifier::with_roots(chain.clone(), [(2, tree.root())]);
+        verifier.trust_cache(old.clone());
+        let seal = seal_on(&chain, &head, header_hash(2), 215, 3, [0xff; 32]);
+        let proof = chain.prove_access(&tree, &dataset, &seal);
+        let expected = chain.hash(&head, &seal.header_hash, 215, 3).1;
+        assert_eq!(verifier.verify_seal(&seal, Some(&proof)), Ok(expected));
+        assert_eq!(
+            verifier.verify_seal(&seal, None),
+            Err(StatelessError::MissingProof { epoch: 2 })
+        );
+        let mut tampered = proof.clone();
+        tampered.loads[0].branch[0][0] ^= 1;
+        assert_eq!(
+            verifier.verify_seal(&seal, Some(&tampered)),
+            Err(StatelessError::NotProven(ProofError::LoadNotProven {
+                load: 0
+            }))
+        );
+        let mut wrong_mix = seal.clone();
+        wrong_mix.mix_hash[0] ^= 1;
+        assert!(matches!(
+            verifier.verify_seal(&wrong_mix, Some(&proof)),
+            Err(StatelessError::Seal(SealError::MixMismatch { .. }))
+        ));
+
+        // An epoch anchored by its cache needs no proof.
+        let old_seal = seal_on(&chain, &old, [4; 32], 150, 8, [0xff; 32]);
+        assert!(verifier.verify_seal(&old_seal, None).is_ok());
+
+        assert!(verifier.forget(1));
+        assert!(!verifier.forget(1));
+        assert_eq!(
+            verifier.verify_seal(&old_seal, None),
+            Err(StatelessError::UnknownEpoch { epoch: 1 })
+        );
+    }
+
+    #[test]
+    fn test_verify_shares_classifies_bundles() {
+        let chain = small_chain();
+        let caches = chain.cache_manager(2);
+        let (old, head) = (caches.get(1), caches.get(2));
+        let dataset = generate_dataset(head.cache_words(), head.size());
+        let tree = DagMerkleTree::build(&dataset);
+        let verifier = StatelessVerifier::with_roots(chain.clone(), [(2, tree.root())]);
+        verifier.trust_cache(old.clone());
+
+        // Half the hashes meet the share boundary; the block boundary is
+        // the final hash of the first nonce, so that share seals a block.
+        let mut share_boundary = [0xff; 32];
+        share_boundary[0] = 0x7f;
+        let block_boundary: [u8; 32] = chain
+            .hash(&head, &header_hash(5), 210, 0)
+            .1
+            .try_into()
+            .unwrap();
+        let mut bundles: Vec<ShareBundle> = (0..8)
+            .map(|nonce| {
+                let seal = seal_on(&chain, &head, header_hash(5), 210, nonce, block_boundary);
+                ShareBundle::package(&chain, &tree, &dataset, seal)
+            })
+            .collect();
+        let expected: Vec<_> = bundles
+            .iter()
+            .map(|bundle| {
+                let seal = &bundle.seal;
+                let (_, final_hash) = chain.hash(&head, &seal.header_hash, 210, seal.nonce);
+                Ok(classify_share(
+                    &final_hash.try_into().unwrap(),
+                    &share_boundary,
+                    &block_boundary,
+                ))
+            })
+            .collect();
+        assert_eq!(expected[0], Ok(ShareKind::Block));
+        assert!(expected.contains(&Ok(ShareKind::Invalid)));
+        assert!(expected.contains(&Ok(ShareKind::Share)));
+        assert_eq!(verifier.verify_shares(&bundles, &share_boundary), expected);
+        assert_eq!(
+            verifier.verify_share(&bundles[3], &share_boundary),
+            expected[3]
+        );
+
+        // Each bundle is judged on its own, and words other than the ones
+        // proven first are checked again.
+        bundles[1].proof.loads[7].item[2] ^= 1;
+        bundles[2].proof.c_dag[0] ^= 1;
+        bundles[3].seal.block_number = 310;
+        bundles[4] = ShareBundle {
+            seal: seal_on(&chain, &old, header_hash(6), 150, 1, [0xff; 32]),
+            proof: bundles[4].proof.clone(),
+        };
+        let results = verifier.verify_shares(&bundles, &share_boundary);
+        assert_eq!(results[0], expected[0]);
+        assert_eq!(
+            results[1],
+            Err(StatelessError::NotProven(ProofError::LoadNotProven {
+                load: 7
+            }))
+        );
+        assert_eq!(
+            results[2],
+            Err(StatelessError::NotProven(ProofError::CacheNotProven))
+        );
+        assert_eq!(results[3], Err(StatelessError::UnknownEpoch { epoch: 3 }));
+        assert_eq!(results[4], Ok(ShareKind::Block));
+        assert_eq!(results[5..], expected[5..]);
+    }
+}
@@ -0,0 +1,262 @@
+//! A client for pools speaking the eth-proxy stratum protocol.
+//!
+//! Eth-proxy is `eth_getWork` over a TCP connection: a miner logs in with
+//! `eth_submitLogin`, asks for work and submits seals as line-delimited
+//! JSON-RPC requests, and the pool pushes new work unasked, as replies with
+//! an `id` of 0. [`StratumClient`] keeps the newest pushed work until the
+//! miner polls for it.
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::engine::Work;
+use crate::ethash::seed::SeedHashChain;
+use crate::hex;
+use crate::rpc::parse_work;
+
+/// How long to wait for the reply to a request.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long [`StratumClient::poll_work`] waits for pushed work.
+const POLL_TIMEOUT: Duration = Duration::from_millis(1);
+
+/// The reason a stratum exchange failed.
+#[derive(Debug)]
+pub enum StratumError {
+    /// The connection failed or timed out.
+    Io(io::Error),
+    /// The pool refused the login or answered a request with an error.
+    Rejected(String),
+    /// The pool sent a line that is not a JSON-RPC reply or work package.
+    InvalidReply(String),
+}
+
+impl fmt::Display for StratumError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StratumError::Io(error) => write!(f, "stratum connection failed: {error}"),
+            StratumError::Rejected(message) => write!(f, "pool rejected the request: {message}"),
+            StratumError::InvalidReply(line) => write!(f, "invalid stratum reply {line:?}"),
+        }
+    }
+}
+
+impl std::error::Error for StratumError {}
+
+impl From<io::Error> for StratumError {
+    fn from(error: io::Error) -> Self {
+        StratumError::Io(error)
+    }
+}
+
+/// A logged-in connection to an eth-proxy pool.
+pub struct StratumClient {
+    reader: BufReader<TcpStream>,
+    next_id: u64,
+    /// The bytes of a line not yet fully received.
+    partial: Vec<u8>,
+    /// The newest work pushed and not yet polled.
+    pushed: Option<Work>,
+    seeds: SeedHashChain,
+}
+
+impl StratumClient {
+    /// Connects to the pool at `address` and logs in as `login`, usually a
+    /// wallet address with a worker name after a dot.
+    ///
+    /// `address` is `host:port`, optionally after `stratum+tcp://`.
+    pub fn connect(address: &str, login: &str) -> Result<Self, StratumError> {
+        let address = address.strip_prefix("stratum+tcp://").unwrap_or(address);
+        let mut client = StratumClient {
+            reader: BufReader::new(TcpStream::connect(address)?),
+            next_id: 1,
+            partial: Vec::new(),
+            pushed: None,
+            seeds: SeedHashChain::new(),
+        };
+        match client.call("eth_submitLogin", json!([login]))? {
+            Value::Bool(true) => Ok(client),
+            reply => Err(StratumError::Rejected(format!("login refused: {reply}"))),
+        }
+    }
+
+    /// Asks the pool for work and waits for it.
+    pub fn get_work(&mut self) -> Result<Work, StratumError> {
+        let reply = self.call("eth_getWork", json!([]))?;
+        self.pushed = None;
+        parse_work(&reply, &self.seeds).ok_or_else(|| StratumError::InvalidReply(reply.to_string()))
+    }
+
+    /// Returns the newest work the pool pushed since the last call, without
+    /// waiting for more.
+    pub fn poll_work(&mut self) -> Result<Option<Work>, StratumError> {
+        while let Some(message) = self.read_message(POLL_TIMEOUT)? {
+            self.unexpected(message)?;
+        }
+        Ok(self.pushed.take())
+    }
+
+    /// Submits a seal of `header_hash`.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the pool accepted the share.
+    pub fn submit_work(
+        &mut self,
+        nonce: u64,
+        header_hash: &[u8; 32],
+        mix_hash: &[u8; 32],
+    ) -> Result<bool, StratumError> {
+        let params = json!([
+            format!("{:#018x}", nonce),
+            hex::encode_prefixed(header_hash),
+            hex::encode_prefixed(mix_hash)
+        ]);
+        Ok(self.call("eth_submitWork", params)? == Value::Bool(true))
+    }
+
+    /// Sends a request and waits for its result, keeping work pushed in the
+    /// meantime.
+    fn call(&mut self, method: &str, params: Value) -> Result<Value, StratumError> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = json!({ "id": id, "jsonrpc": "2.0", "method": method, "params": params });
+        let stream = self.reader.get_mut();
+        stream.write_all(format!("{request}\n").as_bytes())?;
+        stream.flush()?;
+        loop {
+            let Some(mut message) = self.read_message(REPLY_TIMEOUT)? else {
+                return Err(StratumError::Io(ErrorKind::TimedOut.into()));
+            };
+            if message["id"] != id {
+                self.unexpected(message)?;
+                continue;
+            }
+            if let Some(error) = message.get("error").filter(|error| !error.is_null()) {
+                return Err(StratumError::Rejected(error.to_string()));
+            }
+            return Ok(message["result"].take());
+        }
+    }
+
+    /// Keeps `message` if it is pushed work, and fails on anything else.
+    fn unexpected(&mut self, message: Value) -> Result<(), StratumError> {
+        let pushed = message["id"].as_u64().unwrap_or(0) == 0;
+        match parse_work(&message["result"], &self.seeds) {
+            Some(work) if pushed => {
+                self.pushed = Some(work);
+                Ok(())
+            }
+            _ => Err(StratumError::InvalidReply(message.to_string())),
+        }
+    }
+
+    /// Reads the next line, waiting at most `timeout` for it.
+    ///
+    /// # Returns
+    ///
+    /// The line as JSON, or `None` if it did not arrive in time. Part of a
+    /// line received before the timeout is kept for the next read.
+    fn read_message(&mut self, timeout: Duration) -> Result<Option<Value>, StratumError> {
+        self.reader.get_ref().set_read_timeout(Some(timeout))?;
+        match self.reader.read_until(b'\n', &mut self.partial) {
+            Ok(0) => return Err(StratumError::Io(ErrorKind::UnexpectedEof.into())),
+            Ok(_) => {}
+            Err(error) if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Ok(None)
+            }
+            Err(error) => return Err(error.into()),
+        }
+        if self.partial.last() != Some(&b'\n') {
+            return Err(StratumError::Io(ErrorKind::UnexpectedEof.into()));
+        }
+        let line = std::mem::take(&mut self.partial);
+        serde_json::from_slice(&line)
+            .map(Some)
+            .map_err(|_| StratumError::InvalidReply(String::from_utf8_lossy(&line).into_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ethash::cache::seed_hash;
+    use std::net::TcpListener;
+
+    fn work(header: u8, epoch: u64) -> Value {
+        json!([
+            hex::encode_prefixed(&[header; 32]),
+            hex::encode_prefixed(&seed_hash(epoch)),
+            hex::encode_prefixed(&[0xff; 32])
+        ])
+    }
+
+    #[test]
+    fn test_stratum_client_logs_in_polls_and_submits() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("stratum+tcp://{}", listener.local_addr().unwrap());
+        let pool = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut request = || {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                serde_json::from_str::<Value>(&line).unwrap()
+            };
+            let login = request();
+            assert_eq!(login["method"], "eth_submitLogin");
+            assert_eq!(login["params"][0], "0xabc.rig1");
+            writeln!(writer, "{}", json!({ "id": login["id"], "result": true })).unwrap();
+
+            let get_work = request();
+            assert_eq!(get_work["method"], "eth_getWork");
+            // Work pushed before the reply is superseded by it.
+            writeln!(writer, "{}", json!({ "id": 0, "result": work(2, 1) })).unwrap();
+            writeln!(
+                writer,
+                "{}",
+                json!({ "id": get_work["id"], "result": work(1, 1) })
+            )
+            .unwrap();
+            writeln!(writer, "{}", json!({ "id": 0, "result": work(3, 2) })).unwrap();
+
+            let submit = request();
+            assert_eq!(submit["method"], "eth_submitWork");
+            assert_eq!(submit["params"][0], "0x0000000000000007");
+            writeln!(writer, "{}", json!({ "id": submit["id"], "result": false })).unwrap();
+            let submit = request();
+            writeln!(
+                writer,
+                "{}",
+                json!({ "id": submit["id"], "error": { "code": 23, "message": "stale" } })
+            )
+            .unwrap();
+        });
+
+        let mut client = StratumClient::connect(&address, "0xabc.rig1").unwrap();
+        let first = client.get_work().unwrap();
+        assert_eq!(first.header_hash, [1; 32]);
+        assert_eq!(first.block_number, 30_000);
+        // The work pushed after the reply replaces the one before it.
+        let pushed = loop {
+            if let Some(work) = client.poll_work().unwrap() {
+                break work;
+            }
+        };
+        assert_eq!(pushed.header_hash, [3; 32]);
+        assert_eq!(pushed.block_number, 60_000);
+        assert!(client.poll_work().unwrap().is_none());
+
+        assert!(!client.submit_work(7, &[3; 32], &[0; 32]).unwrap());
+        assert!(matches!(
+            client.submit_work(8, &[3; 32], &[0; 32]),
+            Err(StratumError::Rejected(_))
+        ));
+        pool.join().unwrap();
+    }
+}
@@ -0,0 +1,142 @@
+//! Seal verification as a stream, for async sync pipelines.
+//!
+//! An async node syncs by pulling headers from peers as a stream and
+//! importing them in order. [`verify_headers`] turns a stream of headers
+//! into a stream of the same headers with their results, in input order,
+//! verifying up to `in_flight` at once on tokio's blocking pool with a
+//! shared [`Verifier`]. It pulls a header only when one of those slots is
+//! free, so a fast peer is held back by verification rather than buffered
+//! without bound. [`verifier_channel`] does the same behind a bounded
+//! [`Sink`](futures::Sink), for producers that push headers instead.
+
+use futures::channel::mpsc;
+use futures::{Stream, StreamExt};
+
+use crate::engine::{seal_of, EngineError, SealableHeader};
+use crate::verifier::Verifier;
+
+/// A verified header and its result.
+pub type Verified<H> = (H, Result<(), EngineError>);
+
+/// Verifies the seal of every header of `headers` with `verifier`, at most
+/// `in_flight` at a time.
+///
+/// # Returns
+///
+/// A stream of each header with its result, in the order of `headers`.
+///
+/// # Panics
+///
+/// Panics if `in_flight` is 0.
+pub fn verify_headers<H, S>(
+    verifier: Verifier,
+    headers: S,
+    in_flight: usize,
+) -> impl Stream<Item = Verified<H>> + Send
+where
+    H: SealableHeader + Send + 'static,
+    S: Stream<Item = H> + Send,
+{
+    assert!(in_flight > 0, "verification needs room for one header");
+    headers
+        .map(move |header| {
+            let verifier = verifier.clone();
+            async move {
+                let result = verifier.verify_seal_async(seal_of(&header)).await;
+                (header, result.map(|_| ()).map_err(EngineError::from))
+            }
+        })
+        .buffered(in_flight)
+}
+
+/// Returns a bounded sink of headers and the stream of their results, as
+/// [`verify_headers`] produces them.
+///
+/// Sending waits while `in_flight` headers are queued and `in_flight` more
+/// are being verified. Headers are verified only while the result stream is
+/// polled; it ends once every sender is dropped and the last result is
+/// taken.
+///
+/// # Panics
+///
+/// Panics if `in_flight` is 0.
+pub fn verifier_channel<H>(
+    verifier: Verifier,
+    in_flight: usize,
+) -> (mpsc::Sender<H>, impl Stream<Item = Verified<H>> + Send)
+where
+    H: SealableHeader + Send + 'static,
+{
+    assert!(in_flight > 0, "verification needs room for one header");
+    let (headers, queued) = mpsc::channel(in_flight - 1);
+    (headers, verify_headers(verifier, queued, in_flight))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::SinkExt;
+
+    use super::*;
+    use crate::chain::Chain;
+    use crate::header::tests::encode_header;
+    use crate::header::Header;
+    use crate::progpow::verify::SealError;
+    use crate::testutil::tiny_cache_manager;
+
+    /// Headers from two epochs, every third one with a broken seal.
+    fn headers(verifier: &Verifier) -> Vec<Header> {
+        (0..9u64)
+            .map(|i| {
+                let number = 29_996 + i;
+                let mut header = Header::decode(&encode_header(number, 1, i, [0; 32])).unwrap();
+                let (mix_hash, _) = verifier.hash(&header.pre_hash(), number, i);
+                let mut mix_hash: [u8; 32] = mix_hash.try_into().unwrap();
+                if i % 3 == 0 {
+                    mix_hash[0] ^= 1;
+                }
+                header.set_seal(i, mix_hash);
+                header
+            })
+            .collect()
+    }
+
+    fn check(results: &[Verified<Header>], headers: &[Header]) {
+        assert_eq!(results.len(), headers.len());
+        for (i, ((header, result), expected)) in results.iter().zip(headers).enumerate() {
+            assert_eq!(header, expected);
+            if i % 3 == 0 {
+                assert!(matches!(
+                    result,
+                    Err(EngineError::InvalidSeal(SealError::MixMismatch { .. }))
+                ));
+            } else {
+                assert_eq!(result, &Ok(()));
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_streams_keep_input_order() {
+        let verifier = Verifier::with_caches(Chain::ethereum(), tiny_cache_manager(2));
+        let headers = headers(&verifier);
+
+        let results: Vec<_> =
+            verify_headers(verifier.clone(), futures::stream::iter(headers.clone()), 3)
+                .collect()
+                .await;
+        check(&results, &headers);
+
+        let (mut sink, results) = verifier_channel(verifier, 2);
+        let producer = {
+            let headers = headers.clone();
+            tokio::spawn(async move {
+                for header in headers {
+                    sink.send(header).await.unwrap();
+                }
+            })
+        };
+        let results: Vec<_> = results.collect().await;
+        producer.await.unwrap();
+        check(&results, &headers);
+    }
+}
@@ -0,0 +1,254 @@
+//! A Substrate PoW algorithm: `sc_consensus_pow::PowAlgorithm` for ProgPoW.
+//!
+//! [`ProgpowAlgorithm`] plugs into `sc_consensus_pow::PowBlockImport` and the
+//! mining worker. The next block's difficulty comes from the runtime's
+//! `DifficultyApi<Block, U256>`; a seal is the SCALE encoding of
+//! [`ProgpowSeal`]; and the pre-hash, the header hash without the seal, is the
+//! ProgPoW header hash. As in ethash, a seal meets a difficulty when its
+//! final hash, read as a big-endian integer, is at most `2^256 / difficulty`.
+//!
+//! The light caches come from a shared [`CacheManager`], keyed by the epoch
+//! of the sealed block's number (its parent's plus one).
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use parity_scale_codec::{Decode, DecodeAll, Encode};
+use sc_consensus_pow::{Error, PowAlgorithm};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_consensus_pow::{DifficultyApi, Seal as RawSeal};
+use sp_core::{H256, U256};
+use sp_runtime::generic::BlockId;
+use sp_runtime::traits::{Block as BlockT, UniqueSaturatedInto};
+
+use crate::ethash::buffer::DagBuffer;
+use crate::ethash::cache::{epoch, MAX_EPOCH};
+use crate::ethash::manager::CacheManager;
+use crate::progpow::search::{search, SearchStrategy};
+use crate::progpow::verify::Seal;
+use crate::target::{self, boundary_from_difficulty};
+
+/// The seal a ProgPoW block carries, SCALE-encoded in its seal digest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct ProgpowSeal {
+    /// The nonce found by the miner.
+    pub nonce: u64,
+    /// The mix hash for the pre-hash and nonce.
+    pub mix_hash: [u8; 32],
+}
+
+/// Returns the boundary a final hash must not exceed to meet `difficulty`;
+/// see [`boundary_from_difficulty`].
+pub fn boundary(difficulty: U256) -> [u8; 32] {
+    boundary_from_difficulty(target::U256::from_be_bytes(difficulty.to_big_endian()))
+}
+
+/// ProgPoW for `sc_consensus_pow`, reading difficulties and block numbers
+/// through `client`.
+pub struct ProgpowAlgorithm<C> {
+    client: Arc<C>,
+    caches: Arc<CacheManager>,
+}
+
+impl<C> Clone for ProgpowAlgorithm<C> {
+    fn clone(&self) -> Self {
+        ProgpowAlgorithm {
+            client: self.client.clone(),
+            caches: self.caches.clone(),
+        }
+    }
+}
+
+impl<C> ProgpowAlgorithm<C> {
+    /// Creates the algorithm, hashing with the light caches in `caches`.
+    pub fn new(client: Arc<C>, caches: Arc<CacheManager>) -> Self {
+        ProgpowAlgorithm { client, caches }
+    }
+
+    /// Searches `nonces` for a seal of block `block_number` meeting
+    /// `difficulty`, for mining workers.
+    ///
+    /// # Returns
+    ///
+    /// The seal of the first nonce that meets it, or `None` if the range is
+    /// exhausted or the block is past the supported epochs.
+    pub fn mine(
+        &self,
+        pre_hash: &H256,
+        block_number: u64,
+        difficulty: U256,
+        nonces: Range<u64>,
+    ) -> Option<ProgpowSeal> {
+        if epoch(block_number) >= MAX_EPOCH {
+            return None;
+        }
+        let cache = self.caches.for_block(block_number);
+        let solution = search(
+            pre_hash.as_bytes(),
+            cache.size(),
+            block_number,
+            &cache.c_dag(),
+            &|index| cache.lookup(index),
+            nonces,
+            &boundary(difficulty),
+            SearchStrategy::Full,
+        )?;
+        Some(ProgpowSeal {
+            nonce: solution.nonce,
+            mix_hash: solution.mix_hash.try_into().unwrap(),
+        })
+    }
+}
+
+impl<B, C> PowAlgorithm<B> for ProgpowAlgorithm<C>
+where
+    B: BlockT<Hash = H256>,
+    C: ProvideRuntimeApi<B> + HeaderBackend<B>,
+    C::Api: DifficultyApi<B, U256>,
+{
+    type Difficulty = U256;
+
+    fn difficulty(&self, parent: H256) -> Result<U256, Error<B>> {
+        self.client
+            .runtime_api()
+            .difficulty(parent)
+            .map_err(|error| Error::Environment(format!("fetching the difficulty failed: {error}")))
+    }
+
+    fn verify(
+        &self,
+        parent: &BlockId<B>,
+        pre_hash: &H256,
+        _pre_digest: Option<&[u8]>,
+        seal: &RawSeal,
+        difficulty: U256,
+    ) -> Result<bool, Error<B>> {
+        let Ok(seal) = ProgpowSeal::decode_all(&mut &seal[..]) else {
+            return Ok(false);
+        };
+        let parent_number = match *parent {
+            BlockId::Number(number) => number,
+            BlockId::Hash(hash) => self
+                .client
+                .number(hash)
+                .map_err(Error::Client)?
+                .ok_or_else(|| Error::Other(format!("unknown parent block {hash}")))?,
+        };
+        let block_number = UniqueSaturatedInto::<u64>::unique_saturated_into(parent_number) + 1;
+        if epoch(block_number) >= MAX_EPOCH {
+            return Ok(false);
+        }
+        let seal = Seal {
+            header_hash: pre_hash.0,
+            block_number,
+            nonce: seal.nonce,
+            mix_hash: seal.mix_hash,
+            boundary: boundary(difficulty),
+        };
+        Ok(self.caches.verify_seal(&seal).is_ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::tiny_cache_manager;
+    use sp_api::ApiRef;
+    use sp_blockchain::{BlockStatus, Info};
+    use sp_runtime::generic;
+    use sp_runtime::traits::BlakeTwo256;
+    use sp_runtime::OpaqueExtrinsic;
+
+    type TestHeader = generic::Header<u64, BlakeTwo256>;
+    type Block = generic::Block<TestHeader, OpaqueExtrinsic>;
+
+    /// A chain whose only known block is 99, with a fixed difficulty.
+    struct TestClient;
+
+    struct TestApi;
+
+    sp_api::mock_impl_runtime_apis! {
+        impl DifficultyApi<Block, U256> for TestApi {
+            fn difficulty() -> U256 {
+                U256::from(4)
+            }
+        }
+    }
+
+    impl ProvideRuntimeApi<Block> for TestClient {
+        type Api = TestApi;
+
+        fn runtime_api(&self) -> ApiRef<'_, TestApi> {
+            TestApi.into()
+        }
+    }
+
+    impl HeaderBackend<Block> for TestClient {
+        fn header(&self, _hash: H256) -> sp_blockchain::Result<Option<TestHeader>> {
+            Ok(None)
+        }
+
+        fn info(&self) -> Info<Block> {
+            Info {
+                best_hash: H256::repeat_byte(99),
+                best_number: 99,
+                genesis_hash: H256::zero(),
+                finalized_hash: H256::zero(),
+                finalized_number: 0,
+                finalized_state: None,
+                number_leaves: 1,
+                block_gap: None,
+            }
+        }
+
+        fn status(&self, _hash: H256) -> sp_blockchain::Result<BlockStatus> {
+            Ok(BlockStatus::Unknown)
+        }
+
+        fn number(&self, hash: H256) -> sp_blockchain::Result<Option<u64>> {
+            Ok((hash == H256::repeat_byte(99)).then_some(99))
+        }
+
+        fn hash(&self, _number: u64) -> sp_blockchain::Result<Option<H256>> {
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn test_progpow_algorithm_mines_and_verifies() {
+        let caches = tiny_cache_manager(1);
+        let algorithm = ProgpowAlgorithm::new(Arc::new(TestClient), Arc::new(caches));
+        let parent = H256::repeat_byte(99);
+        let difficulty = PowAlgorithm::<Block>::difficulty(&algorithm, parent).unwrap();
+        assert_eq!(difficulty, U256::from(4));
+        assert_eq!(boundary(difficulty)[0], 0x40);
+
+        let pre_hash = H256::repeat_byte(5);
+        let seal = algorithm.mine(&pre_hash, 100, difficulty, 0..64).unwrap();
+        let verify = |parent: &BlockId<Block>, raw: &RawSeal| {
+            algorithm.verify(parent, &pre_hash, None, raw, difficulty)
+        };
+        assert!(matches!(
+            verify(&BlockId::Hash(parent), &seal.encode()),
+            Ok(true)
+        ));
+        assert!(matches!(
+            verify(&BlockId::Number(99), &seal.encode()),
+            Ok(true)
+        ));
+        let forged = ProgpowSeal {
+            nonce: seal.nonce + 1,
+            ..seal
+        };
+        assert!(matches!(
+            verify(&BlockId::Number(99), &forged.encode()),
+            Ok(false)
+        ));
+        assert!(matches!(
+            verify(&BlockId::Number(99), &vec![1, 2, 3]),
+            Ok(false)
+        ));
+        assert!(verify(&BlockId::Hash(H256::zero()), &seal.encode()).is_err());
+    }
+}
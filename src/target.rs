@@ -0,0 +1,303 @@
+//! Difficulties, boundaries and the 256-bit integers between them.
+//!
+//! A seal meets a difficulty when its final hash, read as a big-endian
+//! integer, is at most the boundary `2^256 / difficulty`, as go-ethereum
+//! computes it. Neither 2^256 nor the quotient for a difficulty of 1 fits in
+//! 256 bits, and `U256::MAX / difficulty` is one short whenever the
+//! difficulty is a power of two, so [`boundary_from_difficulty`] is the one
+//! place the division is done.
+
+use std::fmt;
+
+use crate::hex;
+
+/// An unsigned 256-bit integer.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct U256 {
+    /// The 64-bit limbs, most significant first, so the derived ordering is
+    /// numeric.
+    limbs: [u64; 4],
+}
+
+impl U256 {
+    /// Zero.
+    pub const ZERO: U256 = U256 { limbs: [0; 4] };
+
+    /// One.
+    pub const ONE: U256 = U256 {
+        limbs: [0, 0, 0, 1],
+    };
+
+    /// `2^256 - 1`.
+    pub const MAX: U256 = U256 {
+        limbs: [u64::MAX; 4],
+    };
+
+    /// Reads 32 big-endian bytes.
+    pub fn from_be_bytes(bytes: [u8; 32]) -> U256 {
+        U256 {
+            limbs: std::array::from_fn(|i| {
+                u64::from_be_bytes(bytes[8 * i..8 * i + 8].try_into().unwrap())
+            }),
+        }
+    }
+
+    /// Returns the 32 big-endian bytes.
+    pub fn to_be_bytes(self) -> [u8; 32] {
+        let mut out = [0; 32];
+        for (chunk, limb) in out.chunks_exact_mut(8).zip(self.limbs) {
+            chunk.copy_from_slice(&limb.to_be_bytes());
+        }
+        out
+    }
+
+    /// Returns `true` if the value is zero.
+    pub fn is_zero(self) -> bool {
+        self == U256::ZERO
+    }
+
+    /// Returns the value as a `u64`, or `None` if it does not fit.
+    pub fn to_u64(self) -> Option<u64> {
+        (self.limbs[..3] == [0; 3]).then_some(self.limbs[3])
+    }
+
+    /// Returns the nearest `f64`, for estimates where the exact value does
+    /// not matter.
+    pub fn to_f64(self) -> f64 {
+        self.limbs
+            .iter()
+            .fold(0.0, |acc, &limb| acc * 18446744073709551616.0 + limb as f64)
+    }
+
+    /// Returns the quotient and remainder of `self / divisor`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `divisor` is zero.
+    pub fn div_rem(self, divisor: U256) -> (U256, U256) {
+        assert!(!divisor.is_zero(), "division by zero");
+        let mut quotient = U256::ZERO;
+        let mut remainder = U256::ZERO;
+        for bit in (0..256).rev() {
+            // The remainder stays below the divisor, so doubling it carries
+            // out at most one bit, and then it exceeds the divisor.
+            let carry = remainder.shl1(self.bit(bit));
+            if carry || remainder >= divisor {
+                remainder = remainder.wrapping_sub(divisor);
+                quotient.limbs[3 - bit / 64] |= 1 << (bit % 64);
+            }
+        }
+        (quotient, remainder)
+    }
+
+    /// Returns `self + rhs`, or `None` on overflow.
+    pub fn checked_add(self, rhs: U256) -> Option<U256> {
+        let mut out = U256::ZERO;
+        let mut carry = false;
+        for i in (0..4).rev() {
+            let (sum, c1) = self.limbs[i].overflowing_add(rhs.limbs[i]);
+            let (sum, c2) = sum.overflowing_add(carry as u64);
+            out.limbs[i] = sum;
+            carry = c1 || c2;
+        }
+        (!carry).then_some(out)
+    }
+
+    /// Returns `self - rhs`, or `None` if `rhs` is greater.
+    pub fn checked_sub(self, rhs: U256) -> Option<U256> {
+        (self >= rhs).then(|| self.wrapping_sub(rhs))
+    }
+
+    /// Returns `self * rhs`, or `None` on overflow.
+    pub fn checked_mul_u64(self, rhs: u64) -> Option<U256> {
+        let mut out = U256::ZERO;
+        let mut carry = 0u128;
+        for i in (0..4).rev() {
+            let product = self.limbs[i] as u128 * rhs as u128 + carry;
+            out.limbs[i] = product as u64;
+            carry = product >> 64;
+        }
+        (carry == 0).then_some(out)
+    }
+
+    /// Returns `self - rhs`, wrapping around at zero.
+    fn wrapping_sub(self, rhs: U256) -> U256 {
+        let mut out = U256::ZERO;
+        let mut borrow = false;
+        for i in (0..4).rev() {
+            let (diff, b1) = self.limbs[i].overflowing_sub(rhs.limbs[i]);
+            let (diff, b2) = diff.overflowing_sub(borrow as u64);
+            out.limbs[i] = diff;
+            borrow = b1 || b2;
+        }
+        out
+    }
+
+    /// Returns bit `bit`, counting from the least significant.
+    fn bit(self, bit: usize) -> bool {
+        self.limbs[3 - bit / 64] >> (bit % 64) & 1 == 1
+    }
+
+    /// Shifts left by one bit, shifting `low` in, and returns the bit
+    /// shifted out.
+    fn shl1(&mut self, low: bool) -> bool {
+        let carry = self.limbs[0] >> 63 == 1;
+        for i in 0..4 {
+            let next = self.limbs.get(i + 1).map_or(low as u64, |next| next >> 63);
+            self.limbs[i] = self.limbs[i] << 1 | next;
+        }
+        carry
+    }
+}
+
+impl From<u64> for U256 {
+    fn from(value: u64) -> Self {
+        U256 {
+            limbs: [0, 0, 0, value],
+        }
+    }
+}
+
+impl fmt::Debug for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{}", hex::encode(&self.to_be_bytes()))
+    }
+}
+
+/// Returns `2^256 / n`, saturating to `U256::MAX` for 0 and 1.
+fn two_pow_256_over(n: U256) -> U256 {
+    if n <= U256::ONE {
+        return U256::MAX;
+    }
+    // 2^256 = MAX + 1, so the quotient gains one exactly when the
+    // remainder of MAX reaches the divisor.
+    let (quotient, remainder) = U256::MAX.div_rem(n);
+    if remainder.checked_add(U256::ONE) == Some(n) {
+        quotient.checked_add(U256::ONE).unwrap()
+    } else {
+        quotient
+    }
+}
+
+/// Returns the boundary a final hash must not exceed to meet `difficulty`:
+/// `2^256 / difficulty` as 32 big-endian bytes.
+///
+/// Difficulties of 0 and 1 accept every hash.
+pub fn boundary_from_difficulty(difficulty: U256) -> [u8; 32] {
+    two_pow_256_over(difficulty).to_be_bytes()
+}
+
+/// Returns the difficulty a boundary corresponds to, `2^256 / boundary`,
+/// the inverse of [`boundary_from_difficulty`].
+///
+/// A boundary of all ones gives 1; a zero boundary, which no hash meets,
+/// saturates to `U256::MAX`.
+pub fn difficulty_from_boundary(boundary: &[u8; 32]) -> U256 {
+    two_pow_256_over(U256::from_be_bytes(*boundary))
+}
+
+/// Returns `true` if `hash`, read as a big-endian integer, is at most
+/// `target`.
+///
+/// The comparison looks at every byte whatever the inputs, so its timing does
+/// not depend on where they first differ.
+pub fn hash_meets_target(hash: &[u8; 32], target: &[u8; 32]) -> bool {
+    // Going from the least to the most significant byte, each differing
+    // byte overrides the verdict of the ones after it.
+    let mut greater = 0u8;
+    for (&h, &t) in hash.iter().zip(target).rev() {
+        let gt = ((t as u16).wrapping_sub(h as u16) >> 8) as u8 & 1;
+        let lt = ((h as u16).wrapping_sub(t as u16) >> 8) as u8 & 1;
+        greater = gt | (greater & !lt & 1);
+    }
+    greater == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn u256(bytes: [u8; 32]) -> U256 {
+        U256::from_be_bytes(bytes)
+    }
+
+    #[test]
+    fn test_boundary_from_difficulty() {
+        assert_eq!(boundary_from_difficulty(U256::ZERO), [0xff; 32]);
+        assert_eq!(boundary_from_difficulty(U256::ONE), [0xff; 32]);
+        // Powers of two divide 2^256 exactly.
+        let mut eighth = [0; 32];
+        eighth[0] = 0x20;
+        assert_eq!(boundary_from_difficulty(U256::from(8)), eighth);
+        assert_eq!(boundary_from_difficulty(U256::from(3)), [0x55; 32]);
+        assert_eq!(boundary_from_difficulty(U256::MAX), U256::ONE.to_be_bytes());
+
+        assert_eq!(difficulty_from_boundary(&eighth), U256::from(8));
+        assert_eq!(difficulty_from_boundary(&[0xff; 32]), U256::ONE);
+        assert_eq!(difficulty_from_boundary(&[0; 32]), U256::MAX);
+    }
+
+    #[test]
+    fn test_u256_arithmetic() {
+        let (q, r) = U256::MAX.div_rem(U256::from(10));
+        assert_eq!(r, U256::from(5));
+        assert_eq!(q.to_be_bytes()[0], 0x19);
+        assert_eq!(
+            U256::from(7).div_rem(U256::from(7)),
+            (U256::ONE, U256::ZERO)
+        );
+        assert_eq!(U256::MAX.checked_add(U256::ONE), None);
+        assert_eq!(U256::from(3).checked_sub(U256::from(5)), None);
+        assert_eq!(
+            U256::from(5).checked_sub(U256::from(3)),
+            Some(U256::from(2))
+        );
+        assert_eq!(U256::MAX.checked_mul_u64(2), None);
+        assert_eq!(
+            U256::from(u64::MAX).checked_mul_u64(u64::MAX),
+            U256::from(u64::MAX)
+                .checked_mul_u64(u64::MAX - 1)
+                .unwrap()
+                .checked_add(U256::from(u64::MAX))
+        );
+        assert_eq!(U256::from(2).to_u64(), Some(2));
+        assert_eq!(U256::MAX.to_u64(), None);
+        assert_eq!(U256::from(1 << 53).to_f64(), (1u64 << 53) as f64);
+        assert_eq!(U256::MAX.to_f64(), 2f64.powi(256));
+        assert!(U256::from(1 << 40) > U256::from(1 << 39));
+        assert_eq!(
+            format!("{:?}", U256::from(255)),
+            format!("0x{}ff", "0".repeat(62))
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn prop_division_matches_u128(a: u128, b in 1u128..) {
+            let to_u256 = |n: u128| {
+                let mut bytes = [0; 32];
+                bytes[16..].copy_from_slice(&n.to_be_bytes());
+                u256(bytes)
+            };
+            prop_assert_eq!(to_u256(a).div_rem(to_u256(b)), (to_u256(a / b), to_u256(a % b)));
+        }
+
+        #[test]
+        fn prop_hash_meets_target_is_big_endian_order(hash: [u8; 32], target: [u8; 32]) {
+            prop_assert_eq!(hash_meets_target(&hash, &target), hash <= target);
+            prop_assert!(hash_meets_target(&hash, &hash));
+        }
+
+        #[test]
+        fn prop_boundary_is_floor_of_two_pow_256(bytes: [u8; 32]) {
+            let difficulty = u256(bytes);
+            prop_assume!(difficulty > U256::ONE);
+            // boundary * difficulty <= 2^256 < (boundary + 1) * difficulty,
+            // checked as MAX / difficulty being boundary or boundary - 1.
+            let boundary = u256(boundary_from_difficulty(difficulty));
+            let (quotient, _) = U256::MAX.div_rem(difficulty);
+            prop_assert!(boundary == quotient || boundary == quotient.checked_add(U256::ONE).unwrap());
+        }
+    }
+}
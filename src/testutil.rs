@@ -0,0 +1,193 @@
+//! Deterministic fixtures for testing code built on ProgPoW.
+//!
+//! A real light cache takes seconds to generate and a real DAG gigabytes to
+//! hold, too much for a unit test. The fixtures here build in microseconds
+//! and are the same on every run and machine:
+//!
+//! - [`tiny_cache`] and [`tiny_cache_manager`]: epoch caches of
+//!   [`TINY_CACHE_BYTES`] over a [`TINY_DATASET_BYTES`] dataset, generated
+//!   from the epoch's real seed hash, or another seed with
+//!   [`tiny_cache_with_seed`].
+//! - [`SyntheticDag`]: a dataset whose bytes follow a formula, which other
+//!   implementations reproduce without ethash code.
+//! - [`SeededDag`], [`mock_lookup`] and [`mock_c_dag`]: pseudo-random
+//!   datasets keyed by a seed, for code that takes a lookup function.
+//! - [`header_hash`] and [`valid_seal`]: inputs and seals that verify
+//!   against a tiny cache.
+//!
+//! Hashes over these fixtures are not those of any chain. The module is
+//! built with the `testutil` feature, for downstream tests:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! progpow_verifier = { version = "*", features = ["testutil"] }
+//! ```
+
+use crate::ethash::buffer::DagBuffer;
+use crate::ethash::cache::{make_cache, seed_hash};
+use crate::ethash::manager::{CacheManager, EpochCache};
+use crate::progpow::verify::Seal;
+
+/// Bytes in a tiny light cache: 16 64-byte rows.
+pub const TINY_CACHE_BYTES: u64 = 1024;
+
+/// Bytes in the dataset of a tiny cache: 1024 64-byte items.
+pub const TINY_DATASET_BYTES: u64 = 1 << 16;
+
+/// Returns a tiny cache for `epoch`, generated from its seed hash.
+pub fn tiny_cache(epoch: u64) -> EpochCache {
+    tiny_cache_with_seed(epoch, &seed_hash(epoch))
+}
+
+/// Returns a tiny cache for `epoch` generated from `seed`, for tests that
+/// need caches which differ within one epoch.
+pub fn tiny_cache_with_seed(epoch: u64, seed: &[u8; 32]) -> EpochCache {
+    EpochCache::new(
+        epoch,
+        make_cache(TINY_CACHE_BYTES, seed),
+        TINY_DATASET_BYTES,
+    )
+}
+
+/// Returns a [`CacheManager`] holding up to `capacity` [`tiny_cache`]s.
+pub fn tiny_cache_manager(capacity: usize) -> CacheManager {
+    CacheManager::with_generator(capacity, tiny_cache)
+}
+
+/// A dataset of `size` bytes where byte `b` of 64-byte item `k` is
+/// `(16 * k + b) mod 256`.
+pub struct SyntheticDag {
+    size: u64,
+}
+
+impl SyntheticDag {
+    /// Creates a synthetic dataset of `size` bytes.
+    pub fn new(size: u64) -> Self {
+        SyntheticDag { size }
+    }
+}
+
+impl DagBuffer for SyntheticDag {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn read_item(&self, index: u32, out: &mut [u8; 64]) {
+        for (b, byte) in out.iter_mut().enumerate() {
+            *byte = (index as usize * 16 + b) as u8;
+        }
+    }
+}
+
+/// A dataset of `size` bytes of pseudo-random items keyed by a seed.
+pub struct SeededDag {
+    size: u64,
+    seed: u64,
+}
+
+impl SeededDag {
+    /// Creates a dataset of `size` bytes whose items are derived from `seed`.
+    pub fn new(size: u64, seed: u64) -> Self {
+        SeededDag { size, seed }
+    }
+}
+
+impl DagBuffer for SeededDag {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn read_item(&self, index: u32, out: &mut [u8; 64]) {
+        let mut state = self.seed ^ (index as u64).wrapping_mul(0xd6e8feb86659fd93);
+        for chunk in out.chunks_exact_mut(8) {
+            chunk.copy_from_slice(&splitmix64(&mut state).to_le_bytes());
+        }
+    }
+}
+
+/// Returns a lookup over a [`SeededDag`] of `seed`, as `progpow()`'s
+/// `lookup` argument expects. The lookup answers any index, so it suits
+/// any dataset size.
+pub fn mock_lookup(seed: u64) -> impl Fn(u32) -> Vec<u8> + Send + Sync {
+    let dag = SeededDag::new(u64::MAX, seed);
+    move |index| dag.lookup(index)
+}
+
+/// Returns the cached DAG words of the dataset [`mock_lookup`] of `seed`
+/// reads, as `progpow()`'s `c_dag` argument expects.
+pub fn mock_c_dag(seed: u64) -> Vec<u32> {
+    SeededDag::new(u64::MAX, seed).c_dag()
+}
+
+/// Returns a pseudo-random header hash derived from `seed`.
+pub fn header_hash(seed: u64) -> [u8; 32] {
+    let mut state = seed;
+    let mut hash = [0u8; 32];
+    for chunk in hash.chunks_exact_mut(8) {
+        chunk.copy_from_slice(&splitmix64(&mut state).to_le_bytes());
+    }
+    hash
+}
+
+/// Returns a seal of `header_hash` and `nonce` at `block_number` that
+/// `cache` verifies: its mix hash is the one `cache` computes and its
+/// boundary admits any final hash.
+pub fn valid_seal(
+    cache: &EpochCache,
+    header_hash: [u8; 32],
+    block_number: u64,
+    nonce: u64,
+) -> Seal {
+    let (mix_hash, _) = cache.hash(&header_hash, block_number, nonce);
+    Seal {
+        header_hash,
+        block_number,
+        nonce,
+        mix_hash: mix_hash.try_into().unwrap(),
+        boundary: [0xff; 32],
+    }
+}
+
+/// The SplitMix64 generator, used for reproducible inputs.
+pub(crate) fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progpow::progpow::progpow;
+
+    #[test]
+    fn test_fixtures_are_deterministic() {
+        let cache = tiny_cache(3);
+        assert_eq!(cache.size(), TINY_DATASET_BYTES);
+        assert_eq!(cache.c_dag(), tiny_cache(3).c_dag());
+        assert_ne!(cache.c_dag(), tiny_cache_with_seed(3, &[1; 32]).c_dag());
+
+        let seal = valid_seal(&cache, header_hash(1), 3 * 30000, 7);
+        assert_eq!(cache.verify_seal(&seal).map(|_| ()), Ok(()));
+        let manager = tiny_cache_manager(1);
+        assert_eq!(manager.for_block(seal.block_number).epoch(), 3);
+        assert!(manager.verify_seal(&seal).is_ok());
+        assert_ne!(header_hash(1), header_hash(2));
+
+        let dag = SeededDag::new(1 << 20, 9);
+        let lookup = mock_lookup(9);
+        assert_eq!(lookup(12345), dag.lookup(12345));
+        assert_ne!(lookup(12345), mock_lookup(10)(12345));
+        assert_eq!(mock_c_dag(9), dag.c_dag());
+        let hash = |lookup: &dyn Fn(u32) -> Vec<u8>| {
+            progpow(&header_hash(4), 5, dag.size(), 0, &dag.c_dag(), lookup)
+        };
+        assert_eq!(hash(&lookup), hash(&|index| dag.lookup(index)));
+
+        let mut item = [0u8; 64];
+        SyntheticDag::new(1 << 16).read_item(2, &mut item);
+        assert_eq!(item[..3], [32, 33, 34]);
+    }
+}
@@ -0,0 +1,389 @@
+//! A seal verifier to share between threads.
+//!
+//! A node verifies headers on many threads at once, nearly all of them from
+//! the epoch at the chain head. [`Verifier`] is a cheap handle to clone into
+//! each thread: clones share one [`Chain`] and one [`CacheManager`], caches
+//! are shared as `Arc<EpochCache>`, and `verify_seal` takes `&self`, so
+//! callers need no mutex of their own.
+//!
+//! The manager locks briefly on every request to track which epochs were
+//! used. A verifier instead keeps the caches of the last [`HOT_EPOCHS`]
+//! epochs it switched to behind a read-write lock that hashing threads only read,
+//! and asks the manager only for another epoch: the write lock is taken at
+//! epoch switches, never while a cache generates, and concurrent readers
+//! never wait for one another.
+//!
+//! Final hashes are accepted by a [`TargetRule`], by default the seal's
+//! boundary; [`Verifier::with_target_rule`] gives a clone sharing the same
+//! caches another rule, such as a pool's share boundary.
+//! [`Verifier::with_memo`] gives one remembering the hashes of recent seals
+//! in a [`SealMemo`], for gossip layers handed the same seal by many peers.
+//!
+//! With the `tokio` feature, [`Verifier::verify_seal_async`] and its
+//! siblings run the same work on tokio's blocking pool, so async node stacks
+//! await a verification, including the generation of a cache that is not
+//! ready yet, without blocking their executor threads.
+
+use std::sync::{Arc, RwLock};
+
+use crate::chain::Chain;
+use crate::ethash::manager::{CacheManager, EpochCache};
+use crate::memo::SealMemo;
+use crate::progpow::verify::{check_seal_with, Seal, SealBoundary, SealError, TargetRule};
+
+/// Epochs a [`Verifier`] holds without asking its [`CacheManager`]: the
+/// head's and, around an epoch boundary, the one before it.
+pub const HOT_EPOCHS: usize = 2;
+
+/// A cloneable, `Send + Sync` handle for verifying seals of one chain.
+#[derive(Clone)]
+pub struct Verifier {
+    shared: Arc<Shared>,
+    rule: Arc<dyn TargetRule>,
+    memo: Option<Arc<SealMemo>>,
+}
+
+/// What the clones of a [`Verifier`] share.
+struct Shared {
+    chain: Chain,
+    caches: Arc<CacheManager>,
+    /// The caches of the epochs last switched to, newest last.
+    hot: RwLock<Vec<Arc<EpochCache>>>,
+}
+
+impl Verifier {
+    /// Creates a verifier for `chain` holding up to `capacity` epochs'
+    /// caches, generated on first use.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn new(chain: Chain, capacity: usize) -> Self {
+        let caches = chain.cache_manager(capacity);
+        Self::with_caches(chain, caches)
+    }
+
+    /// Creates a verifier for `chain` taking caches from `caches`, which must
+    /// hold this chain's caches; see [`Chain::verify_seal`].
+    pub fn with_caches(chain: Chain, caches: CacheManager) -> Self {
+        Self::with_shared_caches(chain, Arc::new(caches))
+    }
+
+    /// Creates a verifier for `chain` taking caches from `caches`, a manager
+    /// other code fills too, such as a
+    /// [`BackgroundGenerator`](crate::ethash::background::BackgroundGenerator).
+    pub fn with_shared_caches(chain: Chain, caches: Arc<CacheManager>) -> Self {
+        Verifier {
+            shared: Arc::new(Shared {
+                chain,
+                caches,
+                hot: RwLock::new(Vec::with_capacity(HOT_EPOCHS)),
+            }),
+            rule: Arc::new(SealBoundary),
+            memo: None,
+        }
+    }
+
+    /// Returns a verifier sharing this one's caches and memo that accepts
+    /// final hashes by `rule` instead.
+    pub fn with_target_rule(&self, rule: impl TargetRule + 'static) -> Self {
+        Verifier {
+            shared: self.shared.clone(),
+            rule: Arc::new(rule),
+            memo: self.memo.clone(),
+        }
+    }
+
+    /// Returns a verifier sharing this one's caches and rule that remembers
+    /// the hashes of the last `capacity` header pre-hashes and nonces it
+    /// computed, so seals seen again are checked without rehashing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn with_memo(&self, capacity: usize) -> Self {
+        Verifier {
+            shared: self.shared.clone(),
+            rule: self.rule.clone(),
+            memo: Some(Arc::new(SealMemo::new(capacity))),
+        }
+    }
+
+    /// Returns the memo hashes are remembered in, if any.
+    pub fn memo(&self) -> Option<&SealMemo> {
+        self.memo.as_deref()
+    }
+
+    /// Returns the chain seals are verified for.
+    pub fn chain(&self) -> &Chain {
+        &self.shared.chain
+    }
+
+    /// Returns the manager caches come from.
+    pub fn caches(&self) -> &CacheManager {
+        &self.shared.caches
+    }
+
+    /// Returns the cache of the epoch `block_number` belongs to, generating
+    /// it if it is not held.
+    pub fn cache_for_block(&self, block_number: u64) -> Arc<EpochCache> {
+        let epoch = self.shared.chain.epoch(block_number);
+        let hot =
+            |held: &[Arc<EpochCache>]| held.iter().find(|cache| cache.epoch() == epoch).cloned();
+        if let Some(cache) = hot(&self.shared.hot.read().unwrap()) {
+            return cache;
+        }
+
+        // Another epoch: ask the manager without holding the lock, so
+        // verification in the hot epochs goes on while this one generates.
+        let cache = self.shared.caches.get(epoch);
+        let mut held = self.shared.hot.write().unwrap();
+        if hot(&held).is_none() {
+            if held.len() == HOT_EPOCHS {
+                held.remove(0);
+            }
+            held.push(cache.clone());
+        }
+        cache
+    }
+
+    /// Computes the `(mix_hash, final_hash)` pair of a header hash and nonce
+    /// at `block_number`, or takes it from the memo; see [`Chain::hash`].
+    pub fn hash(
+        &self,
+        header_hash: &[u8; 32],
+        block_number: u64,
+        nonce: u64,
+    ) -> (Vec<u8>, Vec<u8>) {
+        let Some(memo) = &self.memo else {
+            return self.compute(header_hash, block_number, nonce);
+        };
+        if let Some((mix_hash, final_hash)) = memo.get(header_hash, nonce, block_number) {
+            return (mix_hash.to_vec(), final_hash.to_vec());
+        }
+        let (mix_hash, final_hash) = self.compute(header_hash, block_number, nonce);
+        memo.insert(
+            header_hash,
+            nonce,
+            block_number,
+            mix_hash[..].try_into().unwrap(),
+            final_hash[..].try_into().unwrap(),
+        );
+        (mix_hash, final_hash)
+    }
+
+    /// Computes a pair with the cache of `block_number`'s epoch.
+    fn compute(&self, header_hash: &[u8; 32], block_number: u64, nonce: u64) -> (Vec<u8>, Vec<u8>) {
+        let cache = self.cache_for_block(block_number);
+        self.shared
+            .chain
+            .hash(&cache, header_hash, block_number, nonce)
+    }
+
+    /// Verifies a seal with the cache of its block's epoch.
+    ///
+    /// # Returns
+    ///
+    /// The final hash if the mix hash matches and the verifier's
+    /// [`TargetRule`] accepts the final hash, or the [`SealError`] describing
+    /// the first check that failed.
+    pub fn verify_seal(&self, seal: &Seal) -> Result<Vec<u8>, SealError> {
+        let (mix_hash, final_hash) = self.hash(&seal.header_hash, seal.block_number, seal.nonce);
+        check_seal_with(seal, &mix_hash, &final_hash, &*self.rule)?;
+        Ok(final_hash)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Verifier {
+    /// Returns the cache of the epoch `block_number` belongs to, awaiting
+    /// its generation on the blocking pool if it is not held; see
+    /// [`cache_for_block`](Self::cache_for_block).
+    pub async fn cache_for_block_async(&self, block_number: u64) -> Arc<EpochCache> {
+        let verifier = self.clone();
+        blocking(move || verifier.cache_for_block(block_number)).await
+    }
+
+    /// Computes [`hash`](Self::hash) on the blocking pool.
+    pub async fn hash_async(
+        &self,
+        header_hash: [u8; 32],
+        block_number: u64,
+        nonce: u64,
+    ) -> (Vec<u8>, Vec<u8>) {
+        let verifier = self.clone();
+        blocking(move || verifier.hash(&header_hash, block_number, nonce)).await
+    }
+
+    /// Verifies a seal on the blocking pool, first generating its epoch's
+    /// cache if it is not held; see [`verify_seal`](Self::verify_seal).
+    pub async fn verify_seal_async(&self, seal: Seal) -> Result<Vec<u8>, SealError> {
+        let verifier = self.clone();
+        blocking(move || verifier.verify_seal(&seal)).await
+    }
+}
+
+/// Runs `f` on tokio's blocking pool.
+///
+/// # Panics
+///
+/// Resumes a panic of `f`, and panics if the runtime shuts down first.
+#[cfg(feature = "tokio")]
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(value) => value,
+        Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+        Err(error) => panic!("verification task did not run: {error}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::ethash::cache::EPOCH_LENGTH;
+    use crate::testutil::{header_hash, tiny_cache, tiny_cache_manager, valid_seal};
+
+    #[test]
+    fn test_verifier_is_shared_between_threads() {
+        fn assert_send_sync<T: Send + Sync + Clone>() {}
+        assert_send_sync::<Verifier>();
+
+        let verifier = Verifier::with_caches(Chain::ethereum(), tiny_cache_manager(4));
+        let seals: Vec<Seal> = (0..3)
+            .map(|epoch| {
+                let block_number = epoch * EPOCH_LENGTH + 5;
+                valid_seal(&tiny_cache(epoch), header_hash(epoch), block_number, 9)
+            })
+            .collect();
+        thread::scope(|scope| {
+            for i in 0..4 {
+                let verifier = verifier.clone();
+                let seals = &seals;
+                scope.spawn(move || {
+                    for seal in seals.iter().cycle().skip(i).take(6) {
+                        assert!(verifier.verify_seal(seal).is_ok());
+                        let mut bad = seal.clone();
+                        bad.nonce += 1;
+                        assert!(matches!(
+                            verifier.verify_seal(&bad),
+                            Err(SealError::MixMismatch { .. })
+                        ));
+                    }
+                });
+            }
+        });
+
+        // The last two epochs switched to are hot, and clones share them.
+        for epoch in [3, 4, 0, 1] {
+            verifier.cache_for_block(epoch * EPOCH_LENGTH);
+        }
+        let hot: Vec<u64> = verifier
+            .shared
+            .hot
+            .read()
+            .unwrap()
+            .iter()
+            .map(|cache| cache.epoch())
+            .collect();
+        assert_eq!(hot, [0, 1]);
+        assert!(Arc::ptr_eq(
+            &verifier.clone().cache_for_block(EPOCH_LENGTH),
+            &verifier.caches().get(1)
+        ));
+        assert_eq!(verifier.shared.hot.read().unwrap().len(), HOT_EPOCHS);
+    }
+
+    #[test]
+    fn test_target_rules() {
+        use crate::progpow::verify::{MinimumDifficulty, ShareBoundary};
+        use crate::target::{difficulty_from_boundary, U256};
+
+        let verifier = Verifier::with_caches(Chain::ethereum(), tiny_cache_manager(1));
+        let seal = valid_seal(&tiny_cache(0), header_hash(0), 5, 9);
+        let final_hash: [u8; 32] = verifier.verify_seal(&seal).unwrap().try_into().unwrap();
+        let rejected = Err(SealError::BoundaryNotMet {
+            final_hash: final_hash.to_vec(),
+        });
+        // The difficulty the hash just meets, and the next one up.
+        let met = difficulty_from_boundary(&final_hash);
+        let missed = met.checked_add(U256::ONE).unwrap();
+
+        let shares = verifier.with_target_rule(ShareBoundary::from_difficulty(met));
+        assert!(Arc::ptr_eq(&shares.shared, &verifier.shared));
+        assert!(shares.verify_seal(&seal).is_ok());
+        let shares = verifier.with_target_rule(ShareBoundary::from_difficulty(missed));
+        assert_eq!(shares.verify_seal(&seal), rejected);
+
+        // A floor applies on top of the seal's own boundary.
+        let floored = verifier.with_target_rule(MinimumDifficulty::new(missed));
+        assert_eq!(floored.verify_seal(&seal), rejected);
+        let floored = verifier.with_target_rule(MinimumDifficulty::new(U256::ONE));
+        assert!(floored.verify_seal(&seal).is_ok());
+        let mut hard = seal.clone();
+        hard.boundary = [0; 32];
+        assert_eq!(floored.verify_seal(&hard), rejected);
+
+        let custom =
+            verifier.with_target_rule(|seal: &Seal, _: &[u8; 32]| seal.nonce.is_multiple_of(2));
+        assert_eq!(custom.verify_seal(&seal), rejected);
+        let mut bad = seal.clone();
+        bad.mix_hash[0] ^= 1;
+        assert!(matches!(
+            custom.verify_seal(&bad),
+            Err(SealError::MixMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_memo_skips_rehashing() {
+        let verifier = Verifier::with_caches(Chain::ethereum(), tiny_cache_manager(1));
+        assert!(verifier.memo().is_none());
+        let memoized = verifier.with_memo(16);
+        let seal = valid_seal(&tiny_cache(0), header_hash(0), 5, 9);
+        for _ in 0..3 {
+            assert_eq!(memoized.verify_seal(&seal), verifier.verify_seal(&seal));
+        }
+        let memo = memoized.memo().unwrap();
+        assert_eq!((memo.hits(), memo.misses(), memo.len()), (2, 1, 1));
+
+        // A remembered hash is still checked against each seal.
+        let mut forged = seal.clone();
+        forged.mix_hash[0] ^= 1;
+        assert!(matches!(
+            memoized.verify_seal(&forged),
+            Err(SealError::MixMismatch { .. })
+        ));
+        let strict = memoized.with_target_rule(|_: &Seal, _: &[u8; 32]| false);
+        assert!(matches!(
+            strict.verify_seal(&seal),
+            Err(SealError::BoundaryNotMet { .. })
+        ));
+        assert_eq!(memo.hits(), 4);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_verify_seal_async() {
+        let verifier = Verifier::with_caches(Chain::ethereum(), tiny_cache_manager(2));
+        let seal = valid_seal(&tiny_cache(1), header_hash(1), EPOCH_LENGTH, 4);
+        let tasks: Vec<_> = (0..4)
+            .map(|_| {
+                let verifier = verifier.clone();
+                let seal = seal.clone();
+                tokio::spawn(async move { verifier.verify_seal_async(seal).await })
+            })
+            .collect();
+        for task in tasks {
+            assert!(task.await.unwrap().is_ok());
+        }
+        assert!(verifier.caches().is_cached(1));
+        let (mix_hash, _) = verifier.hash_async([1; 32], 0, 2).await;
+        assert_eq!(mix_hash, verifier.hash(&[1; 32], 0, 2).0);
+        assert_eq!(
+            verifier.cache_for_block_async(EPOCH_LENGTH).await.epoch(),
+            1
+        );
+    }
+}
@@ -0,0 +1,248 @@
+//! Preparing caches before a service starts accepting requests.
+//!
+//! A verifier that starts cold generates the current epoch's cache on its
+//! first request, so the requests of the first seconds after a restart wait
+//! on it or time out. [`warm_up`] starts that work up front on a background
+//! thread: it extends the seed hash chain to the head, then builds the
+//! current epoch's cache and, as [`WarmUpOptions`] asks, the next epoch's
+//! cache and the current epoch's full dataset. A service polls the
+//! [`ReadinessProbe`] it returns, for example from a health endpoint, and
+//! starts accepting verification once it reports ready.
+
+use std::sync::Arc;
+
+use crate::chain::Chain;
+use crate::ethash::background::{BackgroundGenerator, GenerationFailed, Ready, Throttle};
+use crate::ethash::cache::MAX_EPOCH;
+use crate::ethash::manager::{CacheManager, EpochCache, Retention};
+use crate::verifier::Verifier;
+
+/// What [`warm_up`] prepares, and how.
+#[derive(Clone, Debug)]
+pub struct WarmUpOptions {
+    /// The number of epochs' caches the manager holds.
+    pub capacity: usize,
+    /// Also build the next epoch's cache, for a head close to a boundary.
+    pub next_epoch: bool,
+    /// Also build the current epoch's full dataset, for mining or fast
+    /// hashing.
+    pub dataset: bool,
+    /// The retention policy of the manager, if any.
+    pub retention: Option<Retention>,
+    /// How gently to generate.
+    pub throttle: Throttle,
+}
+
+impl Default for WarmUpOptions {
+    fn default() -> Self {
+        WarmUpOptions {
+            capacity: 3,
+            next_epoch: true,
+            dataset: false,
+            retention: None,
+            throttle: Throttle::default(),
+        }
+    }
+}
+
+/// The progress of a warm-up, as a [`ReadinessProbe`] reports it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WarmUpStatus {
+    /// The steps finished so far.
+    pub done: usize,
+    /// The steps in all.
+    pub total: usize,
+    /// Whether a step failed, so the warm-up never becomes ready.
+    pub failed: bool,
+}
+
+impl WarmUpStatus {
+    /// Returns `true` if every step finished.
+    pub fn is_ready(&self) -> bool {
+        self.done == self.total
+    }
+}
+
+/// A cloneable view of a warm-up's progress, for readiness checks.
+#[derive(Clone)]
+pub struct ReadinessProbe {
+    caches: Vec<Ready<Arc<EpochCache>>>,
+    dataset: Option<Ready<Arc<Vec<u32>>>>,
+}
+
+impl ReadinessProbe {
+    /// Returns how far the warm-up has got.
+    pub fn status(&self) -> WarmUpStatus {
+        let caches = self
+            .caches
+            .iter()
+            .map(|ready| (ready.is_ready(), ready.has_failed()));
+        let dataset = self
+            .dataset
+            .iter()
+            .map(|ready| (ready.is_ready(), ready.has_failed()));
+        let steps: Vec<(bool, bool)> = caches.chain(dataset).collect();
+        WarmUpStatus {
+            done: steps
+                .iter()
+                .filter(|&&(done, failed)| done && !failed)
+                .count(),
+            total: steps.len(),
+            failed: steps.iter().any(|&(_, failed)| failed),
+        }
+    }
+
+    /// Returns `true` once every step has finished.
+    pub fn is_ready(&self) -> bool {
+        self.status().is_ready()
+    }
+
+    /// Blocks until every step has finished.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())`, or [`GenerationFailed`] if a step failed.
+    pub fn wait(&self) -> Result<(), GenerationFailed> {
+        for ready in &self.caches {
+            ready.wait()?;
+        }
+        if let Some(dataset) = &self.dataset {
+            dataset.wait()?;
+        }
+        Ok(())
+    }
+}
+
+/// A warm-up under way: the caches it fills and the thread filling them.
+///
+/// Dropping it waits for the steps still running.
+pub struct WarmUp {
+    chain: Chain,
+    generator: BackgroundGenerator,
+    probe: ReadinessProbe,
+}
+
+impl WarmUp {
+    /// Returns a probe reporting the warm-up's progress.
+    pub fn probe(&self) -> ReadinessProbe {
+        self.probe.clone()
+    }
+
+    /// Returns `true` once every step has finished.
+    pub fn is_ready(&self) -> bool {
+        self.probe.is_ready()
+    }
+
+    /// Blocks until every step has finished; see [`ReadinessProbe::wait`].
+    pub fn wait(&self) -> Result<(), GenerationFailed> {
+        self.probe.wait()
+    }
+
+    /// Returns the cache manager being filled.
+    pub fn caches(&self) -> &Arc<CacheManager> {
+        self.generator.caches()
+    }
+
+    /// Returns the generator, to queue later generations on its thread.
+    pub fn generator(&self) -> &BackgroundGenerator {
+        &self.generator
+    }
+
+    /// Returns the current epoch's dataset if it was asked for and is built.
+    pub fn dataset(&self) -> Option<Arc<Vec<u32>>> {
+        self.probe.dataset.as_ref().and_then(Ready::try_get)
+    }
+
+    /// Returns a verifier for the chain sharing the warmed caches.
+    pub fn verifier(&self) -> Verifier {
+        Verifier::with_shared_caches(self.chain.clone(), self.caches().clone())
+    }
+}
+
+/// Starts preparing `chain`'s caches for a head at `head_block`.
+///
+/// The seed hash chain is extended to the head before this returns; the
+/// caches and dataset are built on a background thread, the current epoch's
+/// cache first.
+///
+/// # Arguments
+///
+/// * `chain` - The chain to prepare.
+/// * `head_block` - The block number of the chain head.
+/// * `options` - What to prepare besides the current epoch's cache.
+///
+/// # Panics
+///
+/// Panics if `options.capacity` is 0.
+pub fn warm_up(chain: &Chain, head_block: u64, options: WarmUpOptions) -> WarmUp {
+    let mut caches = chain.cache_manager(options.capacity);
+    if let Some(retention) = options.retention {
+        caches = caches.with_retention(retention);
+    }
+    let epoch = chain.epoch(head_block);
+    let last = if options.next_epoch && epoch + 1 < MAX_EPOCH {
+        epoch + 1
+    } else {
+        epoch
+    };
+    caches.seed_hash(last);
+
+    let generator = BackgroundGenerator::with_throttle(Arc::new(caches), options.throttle);
+    let probe = ReadinessProbe {
+        caches: (epoch..=last).map(|epoch| generator.cache(epoch)).collect(),
+        dataset: options.dataset.then(|| generator.dataset(epoch)),
+    };
+    WarmUp {
+        chain: chain.clone(),
+        generator,
+        probe,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ethash::buffer::DagBuffer;
+    use crate::ethash::dataset::generate_dataset;
+
+    #[test]
+    fn test_warm_up_prepares_the_head_epoch() {
+        let chain = Chain {
+            name: "warmcoin".to_string(),
+            epoch_length: 100,
+            cache_bytes_init: 1024,
+            cache_bytes_growth: 128,
+            dataset_bytes_init: 1 << 14,
+            dataset_bytes_growth: 1 << 10,
+            ..Chain::ethereum()
+        };
+        let options = WarmUpOptions {
+            dataset: true,
+            ..WarmUpOptions::default()
+        };
+        let warm = warm_up(&chain, 250, options);
+        let probe = warm.probe();
+        assert_eq!(probe.status().total, 3);
+        probe.wait().unwrap();
+        assert_eq!(
+            probe.status(),
+            WarmUpStatus {
+                done: 3,
+                total: 3,
+                failed: false
+            }
+        );
+
+        assert!(warm.is_ready());
+        assert!(warm.caches().is_cached(2) && warm.caches().is_cached(3));
+        let cache = warm.caches().get(2);
+        assert_eq!(cache.size(), chain.dataset_size(2));
+        assert_eq!(
+            *warm.dataset().unwrap(),
+            generate_dataset(cache.cache_words(), cache.size())
+        );
+        let verifier = warm.verifier();
+        assert!(Arc::ptr_eq(&verifier.cache_for_block(250), &cache));
+    }
+}
@@ -0,0 +1,376 @@
+//! JavaScript bindings for building and reusing light caches in a browser.
+//!
+//! Generating an epoch's light cache takes a few seconds of hashing, which
+//! would freeze a page if done in one call. [`WasmCache::build`] instead
+//! returns a promise and runs [`CacheBuilder`] in chunks, handing control
+//! back to the event loop between chunks. The finished cache can be turned
+//! into bytes with [`WasmCache::to_bytes`], stored by the page (for example
+//! in IndexedDB), and restored on a later visit with
+//! [`WasmCache::from_bytes`] instead of being rebuilt.
+//!
+//! [`WasmMiner`] searches nonces with a cache, for demonstrations of mining
+//! in a page. It is meant to run in a Web Worker: a search hashes a batch of
+//! nonces at a time, pausing between batches as its throttle says, so the
+//! worker still receives messages, such as one asking it to
+//! [`cancel`](WasmMiner::cancel), and the machine stays usable.
+
+use std::cell::Cell;
+use std::ops::Range;
+use std::rc::Rc;
+
+use js_sys::{Function, Object, Promise, Reflect};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{future_to_promise, JsFuture};
+
+use crate::endian::{le_bytes, le_words};
+use crate::ethash::cache::{cache_size, dataset_size, CacheBuilder};
+use crate::ethash::dataset::{dataset_lookup, generate_c_dag};
+use crate::hex;
+use crate::progpow::progpow::progpow;
+use crate::progpow::search::{search, SearchStrategy, Solution};
+
+/// The first bytes of a serialized cache.
+const CACHE_MAGIC: [u8; 4] = *b"PPWC";
+
+/// Bytes before the cache words: the magic number and the epoch.
+const HEADER_BYTES: usize = 8;
+
+/// An epoch's light cache, usable from JavaScript.
+#[wasm_bindgen]
+pub struct WasmCache {
+    epoch: u32,
+    words: Vec<u32>,
+    /// The cached DAG words, derived once from `words`.
+    c_dag: Vec<u32>,
+}
+
+impl WasmCache {
+    /// Wraps the light cache `words` of `epoch`.
+    fn new(epoch: u32, words: Vec<u32>) -> Self {
+        let c_dag = generate_c_dag(&words);
+        WasmCache {
+            epoch,
+            words,
+            c_dag,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl WasmCache {
+    /// Builds the light cache of `epoch` without blocking the page.
+    ///
+    /// Control returns to the event loop after every `rows_per_yield`
+    /// 64-byte rows, and `on_progress`, if given, is called with the
+    /// fraction done, from 0 to 1.
+    pub async fn build(
+        epoch: u32,
+        rows_per_yield: u32,
+        on_progress: Option<Function>,
+    ) -> Result<WasmCache, JsValue> {
+        let mut builder = CacheBuilder::new(epoch.into());
+        while !builder.step(rows_per_yield.max(1) as usize) {
+            if let Some(callback) = &on_progress {
+                callback.call1(&JsValue::NULL, &builder.progress().into())?;
+            }
+            sleep(0).await?;
+        }
+        Ok(WasmCache::new(epoch, builder.finish()))
+    }
+
+    /// Restores a cache saved with [`WasmCache::to_bytes`].
+    ///
+    /// # Returns
+    ///
+    /// The cache, or an error if `bytes` is not a whole serialized cache.
+    #[wasm_bindgen(js_name = fromBytes)]
+    pub fn from_bytes(bytes: &[u8]) -> Result<WasmCache, JsValue> {
+        decode(bytes)
+            .filter(|(epoch, words)| words.len() as u64 * 4 == cache_size((*epoch).into()))
+            .map(|(epoch, words)| WasmCache::new(epoch, words))
+            .ok_or_else(|| JsValue::from_str("not a serialized light cache"))
+    }
+
+    /// Serializes the cache for storage across page loads.
+    #[wasm_bindgen(js_name = toBytes)]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encode(self.epoch, &self.words)
+    }
+
+    /// Returns the epoch this cache belongs to.
+    #[wasm_bindgen(getter)]
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
+    /// Computes the ProgPoW hash of a header with the light cache.
+    ///
+    /// # Returns
+    ///
+    /// The 32-byte mix hash followed by the 32-byte final hash.
+    pub fn hash(&self, header_hash: &[u8], block_number: u64, nonce: u64) -> Vec<u8> {
+        let lookup = dataset_lookup(&self.words);
+        let (mut mix_hash, final_hash) = progpow(
+            header_hash,
+            nonce,
+            dataset_size(self.epoch.into()),
+            block_number,
+            &self.c_dag,
+            &lookup,
+        );
+        mix_hash.extend(final_hash);
+        mix_hash
+    }
+}
+
+/// What a [`WasmMiner`] searches with: a cache and the block being mined.
+struct MinerState {
+    block_number: u64,
+    size: u64,
+    words: Vec<u32>,
+    c_dag: Vec<u32>,
+    /// Bumped by [`WasmMiner::cancel`]; a search stops once it changes.
+    cancellations: Cell<u64>,
+}
+
+impl MinerState {
+    /// Searches `nonces` for the first one meeting `boundary`.
+    fn search(
+        &self,
+        header_hash: &[u8; 32],
+        boundary: &[u8; 32],
+        nonces: Range<u64>,
+    ) -> Option<Solution> {
+        let lookup = dataset_lookup(&self.words);
+        search(
+            header_hash,
+            self.size,
+            self.block_number,
+            &self.c_dag,
+            &lookup,
+            nonces,
+            boundary,
+            SearchStrategy::Full,
+        )
+    }
+}
+
+/// A throttled nonce search over a light cache, for mining demonstrations.
+///
+/// Hashing from the light cache computes every DAG item it reads, so a
+/// search runs at a few hashes a second; it is for showing how mining
+/// works, not for mining.
+#[wasm_bindgen]
+pub struct WasmMiner {
+    state: Rc<MinerState>,
+    hashes_per_yield: u32,
+    pause_ms: u32,
+}
+
+#[wasm_bindgen]
+impl WasmMiner {
+    /// Creates a miner for block `block_number` with `cache`, which must be
+    /// the cache of the block's epoch.
+    #[wasm_bindgen(constructor)]
+    pub fn new(cache: &WasmCache, block_number: u64) -> Result<WasmMiner, JsValue> {
+        let epoch = cache.epoch.into();
+        if crate::ethash::cache::epoch(block_number) != epoch {
+            return Err(JsValue::from_str("the cache is not of the block's epoch"));
+        }
+        Ok(WasmMiner {
+            state: Rc::new(MinerState {
+                block_number,
+                size: dataset_size(epoch),
+                words: cache.words.clone(),
+                c_dag: cache.c_dag.clone(),
+                cancellations: Cell::new(0),
+            }),
+            hashes_per_yield: 16,
+            pause_ms: 0,
+        })
+    }
+
+    /// Sets how many nonces a search hashes between pauses, and how many
+    /// milliseconds each pause lasts. The default is 16 nonces and no pause
+    /// beyond letting pending events run.
+    #[wasm_bindgen(js_name = setThrottle)]
+    pub fn set_throttle(&mut self, hashes_per_yield: u32, pause_ms: u32) {
+        self.hashes_per_yield = hashes_per_yield.max(1);
+        self.pause_ms = pause_ms;
+    }
+
+    /// Stops the searches in progress at their next pause; they resolve to
+    /// `null`.
+    pub fn cancel(&self) {
+        let state = &self.state;
+        state.cancellations.set(state.cancellations.get() + 1);
+    }
+
+    /// Searches the `count` nonces from `start_nonce` for one whose final
+    /// hash meets the boundary. The range stops short of `u64::MAX` rather
+    /// than wrapping, so that nonce is never tried.
+    ///
+    /// # Arguments
+    ///
+    /// * `header_hex` - The 32-byte header pre-hash as hex, with or without `0x`.
+    /// * `boundary_hex` - The 32-byte big-endian boundary as hex.
+    /// * `start_nonce` - The first nonce tried.
+    /// * `count` - The number of nonces tried.
+    ///
+    /// # Returns
+    ///
+    /// A promise of `{ nonce, mixHash, finalHash }`, with the nonce a
+    /// `bigint` and the hashes hex, or of `null` if no nonce in the range
+    /// meets the boundary or the search was cancelled. It rejects if either
+    /// hex argument is not 32 bytes.
+    #[wasm_bindgen(js_name = searchRange)]
+    pub fn search_range(
+        &self,
+        header_hex: &str,
+        boundary_hex: &str,
+        start_nonce: u64,
+        count: u32,
+    ) -> Promise {
+        let parsed = parse_hash(header_hex).zip(parse_hash(boundary_hex));
+        let state = self.state.clone();
+        let (hashes_per_yield, pause_ms) = (self.hashes_per_yield, self.pause_ms);
+        let cancellations = state.cancellations.get();
+        future_to_promise(async move {
+            let (header_hash, boundary) = parsed.ok_or_else(|| {
+                JsValue::from_str("the header and boundary must be 32 bytes of hex")
+            })?;
+            let end = start_nonce.saturating_add(count.into());
+            let mut start = start_nonce;
+            while start < end {
+                let batch = start..end.min(start.saturating_add(hashes_per_yield.into()));
+                start = batch.end;
+                if let Some(solution) = state.search(&header_hash, &boundary, batch) {
+                    return solution_object(&solution);
+                }
+                sleep(pause_ms).await?;
+                if state.cancellations.get() != cancellations {
+                    break;
+                }
+            }
+            Ok(JsValue::NULL)
+        })
+    }
+}
+
+/// Returns `solution` as `{ nonce, mixHash, finalHash }`.
+fn solution_object(solution: &Solution) -> Result<JsValue, JsValue> {
+    let object = Object::new();
+    Reflect::set(&object, &"nonce".into(), &solution.nonce.into())?;
+    Reflect::set(
+        &object,
+        &"mixHash".into(),
+        &hex::encode_prefixed(&solution.mix_hash).into(),
+    )?;
+    Reflect::set(
+        &object,
+        &"finalHash".into(),
+        &hex::encode_prefixed(&solution.final_hash).into(),
+    )?;
+    Ok(object.into())
+}
+
+/// Decodes 32 bytes of hex, with or without a `0x` prefix.
+fn parse_hash(hex: &str) -> Option<[u8; 32]> {
+    hex::decode_hash(hex.strip_prefix("0x").unwrap_or(hex))
+}
+
+/// Resolves after `ms` milliseconds, letting the page or worker handle
+/// pending events in the meantime.
+///
+/// `setTimeout` is looked up on the global object, so this works both in
+/// windows and in workers.
+async fn sleep(ms: u32) -> Result<(), JsValue> {
+    let set_timeout: Function =
+        Reflect::get(&js_sys::global(), &"setTimeout".into())?.dyn_into()?;
+    let mut result = Ok(JsValue::UNDEFINED);
+    let promise = Promise::new(&mut |resolve, _reject| {
+        result = set_timeout.call2(&JsValue::NULL, &resolve, &ms.into());
+    });
+    result?;
+    JsFuture::from(promise).await.map(|_| ())
+}
+
+/// Serializes a cache as its magic number, epoch and little-endian words.
+fn encode(epoch: u32, words: &[u32]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_BYTES + words.len() * 4);
+    bytes.extend(CACHE_MAGIC);
+    bytes.extend(epoch.to_le_bytes());
+    bytes.extend(le_bytes(words));
+    bytes
+}
+
+/// Parses the output of [`encode`].
+fn decode(bytes: &[u8]) -> Option<(u32, Vec<u32>)> {
+    let body = bytes.strip_prefix(&CACHE_MAGIC)?;
+    let (epoch, words) = body.split_first_chunk::<4>()?;
+    if words.len() % 4 != 0 {
+        return None;
+    }
+    Some((u32::from_le_bytes(*epoch), le_words(words)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_bytes_round_trip() {
+        let words: Vec<u32> = (0..64).map(|i| i * 0x01010101).collect();
+        let bytes = encode(7, &words);
+        assert_eq!(bytes.len(), HEADER_BYTES + 64 * 4);
+        assert_eq!(decode(&bytes), Some((7, words)));
+
+        assert_eq!(decode(&bytes[..bytes.len() - 1]), None);
+        assert_eq!(decode(&bytes[1..]), None);
+    }
+
+    #[test]
+    fn test_cache_hash_uses_the_kept_c_dag() {
+        let words = crate::ethash::cache::make_cache(1024, &crate::ethash::cache::seed_hash(0));
+        let cache = WasmCache::new(0, words.clone());
+        assert_eq!(cache.c_dag, generate_c_dag(&words));
+
+        let header_hash = crate::testutil::header_hash(5);
+        let (mut expected, final_hash) = progpow(
+            &header_hash,
+            9,
+            dataset_size(0),
+            100,
+            &cache.c_dag,
+            &dataset_lookup(&words),
+        );
+        expected.extend(final_hash);
+        assert_eq!(cache.hash(&header_hash, 100, 9), expected);
+    }
+
+    #[test]
+    fn test_miner_search() {
+        let words = crate::ethash::cache::make_cache(1024, &crate::ethash::cache::seed_hash(0));
+        let state = MinerState {
+            block_number: 100,
+            size: 1 << 16,
+            c_dag: generate_c_dag(&words),
+            words,
+            cancellations: Cell::new(0),
+        };
+        let header_hash = crate::testutil::header_hash(5);
+        let solution = state.search(&header_hash, &[0xff; 32], 40..50).unwrap();
+        assert_eq!(solution.nonce, 40);
+        let lookup = dataset_lookup(&state.words);
+        let (_, final_hash) = progpow(&header_hash, 40, 1 << 16, 100, &state.c_dag, &lookup);
+        assert_eq!(solution.final_hash, final_hash);
+        assert_eq!(state.search(&header_hash, &[0; 32], 40..42), None);
+
+        let hex = hex::encode_prefixed(&header_hash);
+        assert_eq!(parse_hash(&hex), Some(header_hash));
+        assert_eq!(parse_hash(&hex[2..]), Some(header_hash));
+        assert_eq!(parse_hash(&hex[..64]), None);
+        assert_eq!(parse_hash(&format!("{}zz", &hex[..64])), None);
+    }
+}